@@ -0,0 +1,550 @@
+use crate::app::{ActivePane, App, AppState, CollectionRow, ItemTab, UPLOAD_FIELDS};
+use crate::exclusions;
+use crate::forecast;
+use crate::keymap::Action;
+use crate::log_buffer;
+use crate::palette;
+use crate::progress;
+use crate::offline;
+use crate::retry;
+use crate::segments;
+use crate::library::ItemStatusFilter;
+use crate::sync;
+use crate::throttle;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::io::{self, Write};
+
+/// Labels for the settings list, in display order (matches `selected_setting_index`).
+const SETTING_LABELS: [&str; 28] = [
+    "Download directory",
+    "Download mode",
+    "Max concurrent file downloads",
+    "Max concurrent collection downloads",
+    "Theme",
+    "Check for updates on startup",
+    "Metadata sidecar",
+    "Confirm collection downloads above",
+    "Default file preset",
+    "Always include subtitles for movies",
+    "Download filter",
+    "Torrent backend",
+    "Download speed limit",
+    "Collection sync",
+    "File sources",
+    "Write playlists for audio items",
+    "Upload keys",
+    "archive.org login",
+    "Add items to IPFS",
+    "Write checksum manifests",
+    "Deduplicate files",
+    "Tag audio files",
+    "MusicBrainz lookup",
+    "Media player",
+    "Parallel segments for large files",
+    "Retry failed downloads",
+    "Item details cache",
+    "Cover art",
+];
+
+/// Turns UI state changes into plain-text announcements for screen readers.
+///
+/// Each time the textual description of the screen changes it is stored in
+/// [`App::last_announcement`] (shown on the event line) and, if mirroring is enabled,
+/// written as one line to stdout. The TUI itself draws on stderr, so stdout can be
+/// redirected to a file, another terminal or a speech pipeline.
+pub struct Announcer {
+    mirror: Option<Box<dyn Write + Send>>,
+}
+
+impl Announcer {
+    /// Creates an announcer. With `mirror_stdout`, announcements and key presses are also
+    /// printed to stdout.
+    pub fn new(mirror_stdout: bool) -> Self {
+        let mirror: Option<Box<dyn Write + Send>> = if mirror_stdout { Some(Box::new(io::stdout())) } else { None };
+        Self { mirror }
+    }
+
+    /// Creates an announcer that mirrors into an arbitrary writer.
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self { mirror: Some(writer) }
+    }
+
+    /// Mirrors a key press (e.g. `key: Ctrl+c`).
+    pub fn key(&mut self, key_event: &KeyEvent) {
+        let line = format!("key: {}", describe_key(key_event));
+        self.write_line(&line);
+    }
+
+    /// Re-describes the screen and announces it if it changed since the last call.
+    pub fn observe(&mut self, app: &mut App) {
+        let description = describe(app);
+        if app.last_announcement.as_deref() == Some(description.as_str()) {
+            return;
+        }
+        self.write_line(&description);
+        app.last_announcement = Some(description);
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if let Some(writer) = self.mirror.as_mut() {
+            if writeln!(writer, "{}", line).and_then(|_| writer.flush()).is_err() {
+                // stdout went away (e.g. closed pipe); stop mirroring rather than erroring every frame
+                self.mirror = None;
+            }
+        }
+    }
+}
+
+/// Human-readable name of a key press.
+pub fn describe_key(key_event: &KeyEvent) -> String {
+    let key = match key_event.code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        other => format!("{:?}", other),
+    };
+    if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+        format!("Ctrl+{}", key)
+    } else if key_event.modifiers.contains(KeyModifiers::ALT) {
+        format!("Alt+{}", key)
+    } else {
+        key
+    }
+}
+
+/// Describes position in a list as "n of total".
+fn position(selected: Option<usize>, total: usize) -> String {
+    match selected {
+        Some(i) if total > 0 => format!("{} of {}", i + 1, total),
+        _ => format!("none of {}", total),
+    }
+}
+
+/// Describes the current screen as one line of plain text.
+pub fn describe(app: &App) -> String {
+    let mut text = match app.current_state {
+        AppState::Browsing | AppState::Downloading => match app.active_pane {
+            ActivePane::Collections => {
+                let rows = app.collection_rows();
+                let selected = app.collection_list_state.selected();
+                let heading = match &app.collection_group_filter {
+                    Some(group) => format!("Collections in {}", group),
+                    None => "Collections".to_string(),
+                };
+                let mut text = format!("{}, {}: ", heading, position(selected, rows.len()));
+                match selected.and_then(|i| rows.get(i)) {
+                    Some(CollectionRow::Group { name, count, collapsed }) => {
+                        text.push_str(&format!("group {}, {} collections, {}", name, count, if *collapsed { "collapsed" } else { "expanded" }));
+                    }
+                    Some(CollectionRow::Collection(name)) => {
+                        text.push_str(&app.collection_display_name(name));
+                        let new_items = app.collection_status.get(*name).map_or(0, |status| status.new_items);
+                        if new_items > 0 {
+                            text.push_str(&format!(", {} new items", new_items));
+                        }
+                    }
+                    None => {}
+                }
+                text
+            }
+            ActivePane::Items => {
+                let selected = app.item_list_state.selected();
+                let name = app.selected_item().map(|d| d.identifier.as_str()).unwrap_or("");
+                let collection = match (&app.search_query, &app.current_collection_name) {
+                    (Some(query), _) => format!("search results for {}", query),
+                    (None, Some(_)) => app.breadcrumb_path(),
+                    (None, None) => "no collection".to_string(),
+                };
+                let excluded = if app.exclusions.is_excluded(name) { ", excluded" } else { "" };
+                let marked = match app.marked_items.len() {
+                    0 => String::new(),
+                    count if app.marked_items.contains(name) => format!(", marked, {} marked in all", count),
+                    count => format!(", {} marked", count),
+                };
+                let mut filters = Vec::new();
+                if app.item_status_filter != ItemStatusFilter::All {
+                    filters.push(app.item_status_filter.to_string().to_lowercase());
+                }
+                if app.item_filter.is_active() {
+                    filters.push(app.item_filter.to_string());
+                }
+                let filter = if filters.is_empty() { String::new() } else { format!(" ({})", filters.join(", ")) };
+                let rows = app.visible_rows();
+                let group = match selected.and_then(|i| rows.get(i)) {
+                    Some(row) if row.is_variant => ", variant".to_string(),
+                    Some(row) if row.group_size > 1 => {
+                        format!(", group of {} {}", row.group_size, if row.expanded { "expanded" } else { "collapsed" })
+                    }
+                    _ => String::new(),
+                };
+                let kind = if app.selected_item().and_then(|d| d.mediatype.as_deref()) == Some("collection") { ", collection" } else { "" };
+                format!("Items in {}{}, {}: {}{}{}{}{}", collection, filter, position(selected, rows.len()), name, kind, excluded, group, marked)
+            }
+        },
+        AppState::ViewingItem => {
+            let item = app.viewing_item_id.as_deref().unwrap_or("unknown item");
+            match &app.current_item_details {
+                Some(details) if app.item_tab == ItemTab::Reviews => {
+                    let review = details.reviews.get(app.review_index).map(|r| {
+                        let stars = r.stars.map_or(String::new(), |n| format!(", {} of 5 stars", n));
+                        format!("{}{}: {}", r.title.as_deref().unwrap_or("untitled"), stars, r.body)
+                    });
+                    let selected = (!details.reviews.is_empty()).then_some(app.review_index);
+                    format!("Item {}, review {}: {}", item, position(selected, details.reviews.len()), review.unwrap_or_default())
+                }
+                Some(details) if app.item_tab == ItemTab::Related => {
+                    let selected = app.related_list_state.selected();
+                    format!(
+                        "Item {}, related item {}: {}",
+                        item,
+                        position(selected, details.related_identifiers.len()),
+                        app.selected_related().map_or("", String::as_str)
+                    )
+                }
+                Some(details) => {
+                    let selected = app.file_list_state.selected();
+                    let file = selected.and_then(|i| details.files.get(i)).map(|f| {
+                        format!(
+                            "{}, {}, {} bytes",
+                            f.name,
+                            f.format.as_deref().unwrap_or("unknown format"),
+                            f.size.as_deref().unwrap_or("unknown")
+                        )
+                    });
+                    format!(
+                        "Item {}, {}. File {}: {}",
+                        item,
+                        details.title.as_deref().unwrap_or("untitled"),
+                        position(selected, details.files.len()),
+                        file.unwrap_or_default()
+                    )
+                }
+                None => format!("Item {}", item),
+            }
+        }
+        AppState::SettingsView => {
+            let label = SETTING_LABELS.get(app.selected_setting_index).copied().unwrap_or("");
+            format!(
+                "Settings, {}: {}: {}",
+                position(Some(app.selected_setting_index), SETTING_LABELS.len()),
+                label,
+                setting_value(app, app.selected_setting_index)
+            )
+        }
+        AppState::EditingSetting => {
+            let label = SETTING_LABELS.get(app.selected_setting_index).copied().unwrap_or("setting");
+            format!("Editing {}: {}", label.to_lowercase(), app.editing_setting_input)
+        }
+        AppState::AddingCollection => format!("Add collection, type identifier: {}", app.add_collection_input),
+        AppState::AskingDownloadDir => format!("Enter download directory: {}", app.editing_setting_input),
+        AppState::UpdateAvailable => match &app.available_update {
+            Some(release) => format!(
+                "Update {} available. Enter to install, Escape to close. Release notes: {}",
+                release.version,
+                release.notes.replace('\n', " ")
+            ),
+            None => "No update available".to_string(),
+        },
+        AppState::ManagingExclusions => {
+            let rules = exclusions::rules(&app.settings);
+            let selected = app.exclusion_list_state.selected();
+            let rule = selected.and_then(|i| rules.get(i)).map(|r| format!("{} {}", r.kind, r.value)).unwrap_or_default();
+            format!("Exclusions, {}: {}. a to add identifier, r to add regex, Delete to remove", position(selected, rules.len()), rule)
+        }
+        AppState::EnteringSearch => format!("Search archive.org, type query: {}", app.editing_setting_input),
+        AppState::EditingUpload => {
+            let form = &app.upload_form;
+            format!(
+                "Upload to archive.org, field {}, {}: {}",
+                position(Some(form.field), UPLOAD_FIELDS.len()),
+                UPLOAD_FIELDS[form.field],
+                form.values[form.field]
+            )
+        }
+        AppState::EnteringFavoritesUser => {
+            format!("Import archive.org favorites, type screen name: {}", app.editing_setting_input)
+        }
+        AppState::EnteringFetchFilter => {
+            format!("Fetch filter, type an optional date range and query: {}", app.editing_setting_input)
+        }
+        AppState::EnteringCollectionGroup => {
+            format!("Collection group, type a name or nothing for none: {}", app.editing_setting_input)
+        }
+        AppState::EnteringCollectionAlias => {
+            format!("Collection name, type a name or nothing for its title: {}", app.editing_setting_input)
+        }
+        AppState::EnteringWaybackUrl => {
+            format!("Wayback Machine lookup, type URL and optional start and end dates: {}", app.editing_setting_input)
+        }
+        AppState::ViewingWayback => match &app.wayback {
+            Some(view) if view.loading => format!("Wayback Machine, looking up captures of {}", view.query.url),
+            Some(view) => {
+                let selected = view.list_state.selected();
+                let capture = match selected.and_then(|i| view.snapshots.get(i)) {
+                    Some(snapshot) => format!(": {}, status {}, {}", snapshot.date(), snapshot.status, snapshot.mimetype),
+                    None => String::new(),
+                };
+                format!("Wayback Machine captures of {}, {}{}", view.query.url, position(selected, view.snapshots.len()), capture)
+            }
+            None => "Wayback Machine".to_string(),
+        },
+        AppState::EnteringExportPath => {
+            let what = if app.exporting_item { "item" } else { "listed items" };
+            format!("Export {}, type file path (.json for JSON, otherwise CSV): {}", what, app.editing_setting_input)
+        }
+        AppState::FilteringItems => {
+            let shown = app.visible_items().len();
+            format!("Find in items, type filter: {}. {} of {} items match", app.item_query, shown, app.items.len())
+        }
+        AppState::ViewingDownloads => {
+            let rows = app.active_transfers.rows(std::time::Instant::now());
+            let files: Vec<String> = rows
+                .iter()
+                .map(|row| match row.ratio() {
+                    Some(ratio) => format!("{} {} percent", row.name, (ratio * 100.0) as u64),
+                    None => format!("{} {}", row.name, forecast::format_bytes(row.done)),
+                })
+                .collect();
+            let paused = if app.download_control.is_paused_all() { ", all paused" } else { "" };
+            format!(
+                "Downloads, {} active{}. {}. {} to pause or resume the item, {} for all, {} to cancel the item, {} for all, Escape to go back",
+                rows.len(),
+                paused,
+                files.join("; "),
+                app.keymap.hint(Action::Pause),
+                app.keymap.hint(Action::PauseAll),
+                app.keymap.hint(Action::Cancel),
+                app.keymap.hint(Action::CancelAll)
+            )
+        }
+        AppState::EnteringCommand => {
+            let commands = palette::matching(&app.editing_setting_input, &palette::contexts(&app.command_return_state, &app.active_pane));
+            let selected = app.command_list_state.selected().filter(|_| !commands.is_empty());
+            let command = selected.and_then(|i| commands.get(i)).map_or("", |action| action.description());
+            format!("Command palette, type command: {}. {}: {}", app.editing_setting_input, position(selected, commands.len()), command)
+        }
+        AppState::ViewingHelp => {
+            let rows: Vec<String> = app
+                .keymap
+                .help()
+                .into_iter()
+                .flat_map(|(context, actions)| {
+                    std::iter::once(context.title().to_string()).chain(actions.into_iter().map(|(keys, description)| format!("{}: {}", description, keys)))
+                })
+                .collect();
+            let selected = app.help_table_state.selected();
+            format!("Keys, {}: {}. Escape to go back", position(selected, rows.len()), selected.and_then(|i| rows.get(i)).map_or("", String::as_str))
+        }
+        AppState::ViewingFailures => {
+            let failures = &app.failures.failures;
+            let selected = app.failures_table_state.selected();
+            let failure = selected.and_then(|i| failures.get(i)).map_or(String::new(), |failure| {
+                let what = failure.file.as_ref().map_or(failure.identifier.clone(), |file| format!("{}/{}", failure.identifier, file));
+                format!("{}, {}: {}", what, failure.kind.label(), failure.reason)
+            });
+            format!(
+                "Failures of the last download run, {}: {}. {} to download the failed items again, Escape to go back",
+                position(selected, failures.len()),
+                failure,
+                app.keymap.hint(Action::RetryFailed)
+            )
+        }
+        AppState::ViewingLog => {
+            let lines = app.log_buffer.lines(app.log_level);
+            let selected = app.log_list_state.selected().or(lines.len().checked_sub(1));
+            let line = selected.and_then(|i| lines.get(i)).map_or(String::new(), |line| format!("{} {}", line.level, line.message));
+            format!(
+                "Log, showing {}, {}: {}. {} to change the levels, Escape to go back",
+                log_buffer::level_label(app.log_level),
+                position(selected, lines.len()),
+                line,
+                app.keymap.hint(Action::LogLevel)
+            )
+        }
+        AppState::AddingExclusion => format!("Add exclusion, type {}: {}", app.new_exclusion_kind.to_string().to_lowercase(), app.editing_setting_input),
+        AppState::ResumePrompt => {
+            let mut text = format!("{}. Enter to resume, d to discard, i to inspect, Escape to decide later", app.unfinished_session_summary());
+            if app.show_session_details {
+                let interrupted = app.session.entries().iter().map(|e| e.job.describe());
+                let jobs: Vec<String> = interrupted.chain(app.queue.held().iter().map(|job| job.describe())).collect();
+                text.push_str(&format!(". Jobs: {}", jobs.join("; ")));
+            }
+            text
+        }
+        AppState::ViewingCollectionReport => match &app.collection_report {
+            Some(report) => {
+                let mut text = format!("Downloaded {}. {}", report.collection, report.summary_lines().join(". "));
+                if !report.failed_items.is_empty() {
+                    text.push_str(&format!(". Failed: {}", report.failed_items.join(", ")));
+                }
+                text.push_str(". Escape to close");
+                text
+            }
+            None => "No collection report".to_string(),
+        },
+        AppState::ViewingEstimate => match &app.size_estimate {
+            Some(estimate) => {
+                let progress = match estimate.items_total {
+                    Some(total) if estimate.is_complete() => format!("{} items checked", total),
+                    Some(total) => format!("{} of {} items checked", estimate.items_checked, total),
+                    None => "listing items".to_string(),
+                };
+                let formats: Vec<String> =
+                    estimate.formats_by_size().iter().map(|(format, total)| format!("{} {}", format, forecast::format_bytes(total.bytes))).collect();
+                format!(
+                    "Estimate for {}, {}: {} files, {}. {}. Enter to download, Escape to close",
+                    estimate.collection,
+                    progress,
+                    estimate.files,
+                    forecast::format_bytes(estimate.bytes),
+                    formats.join(", ")
+                )
+            }
+            None => "No estimate".to_string(),
+        },
+        AppState::ConfirmingDownload => match &app.collection_forecast {
+            Some(forecast) => {
+                let size = match forecast.item_count {
+                    Some(count) => format!("{} items, {}", count, forecast::format_bytes(forecast.total_bytes)),
+                    None => "size unknown".to_string(),
+                };
+                let fit = if forecast.exceeds_free_space() { ", not enough free space" } else { "" };
+                format!("Download collection {}? {}{}. Enter to start, Escape to cancel", forecast.collection, size, fit)
+            }
+            None => "No download to confirm".to_string(),
+        },
+    };
+
+    if app.is_loading || app.is_loading_details {
+        text.push_str(". Loading");
+    }
+    if let Some(label) = app.network.label() {
+        text.push_str(&format!(". {}, showing cached data", label));
+    }
+    if app.is_downloading {
+        if let Some(label) = progress::gauge_label(&app.run_progress()) {
+            text.push_str(&format!(". Downloading, {}", label.replace(" · ", ", ")));
+        }
+    }
+    if let Some(err) = &app.error_message {
+        text.push_str(&format!(". Error: {}", err));
+    } else if let Some(status) = &app.download_status {
+        text.push_str(&format!(". Status: {}", status));
+    }
+    text
+}
+
+fn setting_value(app: &App, index: usize) -> String {
+    let settings = &app.settings;
+    match index {
+        0 => settings.download_directory.clone().unwrap_or_else(|| "not set".to_string()),
+        1 => settings.download_mode.to_string(),
+        2 => settings.max_concurrent_downloads.map_or("unlimited".to_string(), |n| n.to_string()),
+        3 => settings.max_concurrent_collections.map_or("unlimited".to_string(), |n| n.to_string()),
+        4 => settings.theme.to_string(),
+        5 => if settings.check_for_updates { "on" } else { "off" }.to_string(),
+        6 => settings.metadata_sidecar.to_string(),
+        7 => match settings.confirm_collection_above_gb {
+            0 => "always".to_string(),
+            gb => format!("{} GB", gb),
+        },
+        8 => settings.default_file_preset.to_string(),
+        9 => subtitles_value(settings),
+        10 if settings.download_file_filter.is_empty() => "all files".to_string(),
+        10 => settings.download_file_filter.join(", "),
+        11 => settings.torrent_backend.to_string(),
+        12 => throttle::format_limit(settings.download_speed_limit_kb).to_lowercase(),
+        13 => sync::format_interval(settings.sync_interval_minutes).to_lowercase(),
+        14 => settings.file_source_policy.to_string().to_lowercase(),
+        15 => if settings.write_playlists { "on" } else { "off" }.to_string(),
+        16 => app.s3_keys.as_ref().map_or("not set".to_string(), |keys| format!("access key {}", keys.access)),
+        17 => app.credentials.as_ref().map_or("not logged in".to_string(), |creds| format!("logged in as {}", creds.display_user())),
+        18 if settings.ipfs_export => format!("on, node {}", settings.ipfs_api_url),
+        18 => "off".to_string(),
+        19 => if settings.write_checksums { "on" } else { "off" }.to_string(),
+        20 => settings.dedupe_mode.to_string(),
+        21 if settings.tag_audio => format!("on, {}", settings.tag_policy),
+        21 => "off".to_string(),
+        22 => if settings.musicbrainz_lookup { "on" } else { "off" }.to_string(),
+        23 => settings.player_command.clone(),
+        24 => segments::describe(settings).to_lowercase(),
+        25 => retry::describe(settings).to_lowercase(),
+        26 => offline::format_ttl(settings.details_cache_hours).to_lowercase(),
+        27 => settings.cover_art.to_string().to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+/// "off", "on (any language)" or "on (en, eng)".
+fn subtitles_value(settings: &crate::settings::Settings) -> String {
+    match (settings.include_subtitles, settings.subtitle_languages.is_empty()) {
+        (false, _) => "off".to_string(),
+        (true, true) => "on (any language)".to_string(),
+        (true, false) => format!("on ({})", settings.subtitle_languages.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection_status::CollectionStatus;
+    use crate::rate_limit::SharedRateLimiter;
+    use governor::Quota;
+    use std::{
+        num::NonZeroU32,
+        sync::{Arc, Mutex},
+    };
+
+    /// Writer that appends into a shared buffer so tests can inspect mirrored output.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_app() -> App {
+        let quota = Quota::per_minute(NonZeroU32::new(60).unwrap());
+        App::new(Arc::new(SharedRateLimiter::new(quota)))
+    }
+
+    #[test]
+    fn test_describe_collections_pane() {
+        let mut app = test_app();
+        app.settings.favorite_collections = vec!["etree".to_string(), "audio".to_string()];
+        app.collection_list_state.select(Some(1));
+        assert_eq!(describe(&app), "Collections, 2 of 2: audio");
+        app.collection_status.insert("audio".to_string(), CollectionStatus { items: 9, synced: None, new_items: 3, opened: None, title: None });
+        assert_eq!(describe(&app), "Collections, 2 of 2: audio, 3 new items");
+        app.collection_status.clear();
+
+        app.error_message = Some("offline".to_string());
+        assert_eq!(describe(&app), "Collections, 2 of 2: audio. Error: offline");
+    }
+
+    #[test]
+    fn test_observe_announces_only_changes() {
+        let buf = SharedBuf::default();
+        let mut announcer = Announcer::with_writer(Box::new(buf.clone()));
+        let mut app = test_app();
+
+        announcer.observe(&mut app);
+        announcer.observe(&mut app); // unchanged, no second line
+        app.current_state = AppState::SettingsView;
+        announcer.observe(&mut app);
+        announcer.key(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("Settings, 1 of 28: Download directory"));
+        assert_eq!(lines[2], "key: Ctrl+c");
+        assert_eq!(app.last_announcement.as_deref(), Some(lines[1]));
+    }
+}
@@ -1,17 +1,61 @@
-use crate::archive_api::{ArchiveDoc, FileDetails, ItemDetails};
-use crate::settings::Settings;
-// Use SystemClock and align middleware Instant type
+use crate::archive_api::{self, ArchiveDoc, FileDetails, ItemDetails};
+use crate::auth::{self, Credentials, LoginRequest};
+use crate::collection_status::{self, CollectionStatus};
+use crate::control::DownloadControl;
+use crate::dns::CachingResolver;
+use crate::download;
+use crate::estimate::{FilePlan, SizeEstimate};
+use crate::exclusions::{self, ExclusionKind, ExclusionList, ExclusionRule};
+use crate::failures::RunFailures;
+use crate::filters::{self, ItemFilter};
+use crate::forecast::{self, CollectionForecast};
+use crate::fs_util;
+use crate::grouping;
+use crate::history::{History, Place};
+use crate::ia_upload::{self, S3Keys, UploadProgress, UploadRequest};
+use crate::keymap::{Action, Keymap};
+use crate::library::{ItemStatus, ItemStatusFilter, LibraryIndex};
+use crate::list_nav::{ListNav, Motion, PageRows};
+use crate::log_buffer::LogBuffer;
+use crate::offline::{self, NetworkMode};
+use crate::paths::{self, PathPolicy};
+use crate::perf::{self, Phase};
+use crate::presets::FilePreset;
+use crate::preview::Preview;
+use crate::progress::RunProgress;
+use crate::report::{CollectionReport, CollectionRun, FileOutcome, FileResult, ItemOutcome, RunReport};
+use crate::retry::{Retry, RetryPolicy, RetryQueue};
+use crate::saved_search;
+use crate::scheduler::Priority;
+use crate::rate_limit::SharedRateLimiter;
+use crate::queue::DownloadQueue;
+use crate::session::{DownloadJob, ProgressSnapshot, SessionJournal};
+use crate::settings::{CollectionOrder, Settings};
+use crate::sync::{self, SyncSchedule};
+use crate::theme::Theme;
+use crate::throttle::Throttle;
+use crate::thumbnail::Thumbnail;
+use crate::transfers::{self, ActiveTransfers};
+use crate::updater::ReleaseInfo;
+use crate::verify::FileCheck;
+use crate::wayback::{Snapshot, SnapshotQuery, WaybackProgress};
 use anyhow::{anyhow, Context, Result}; // Add anyhow macro import, Add Result
-use governor::{RateLimiter, clock::SystemClock, state::{InMemoryState, direct::NotKeyed}, middleware::NoOpMiddleware}; // Corrected InMemoryState path
-use ratatui::widgets::ListState;
+use chrono::Utc;
+use log::Level;
+use ratatui::layout::Rect;
+use ratatui::widgets::{ListState, TableState};
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use serde_json; // Add serde_json
-// Import SystemTime to match SystemClock
-use std::{fs, path::{Path, PathBuf}, sync::Arc, time::{Instant, Duration, SystemTime}}; // Add fs, Path
+use std::{borrow::Cow, cmp::Reverse, collections::{BTreeMap, HashSet}, fmt, fs, path::{Path, PathBuf}, sync::Arc, time::{Instant, Duration, SystemTime}}; // Add fs, Path
+use tokio::sync::mpsc;
 
-/// Type alias for the specific RateLimiter used in the app
-// Use SystemClock, the public InMemoryState path, and SystemTime for middleware
-pub type AppRateLimiter = Arc<RateLimiter<NotKeyed, InMemoryState, SystemClock, NoOpMiddleware<SystemTime>>>;
+/// Upper bound on progress messages applied per tick, so a flood of
+/// `BytesDownloaded` chunks can never stall input handling or rendering.
+const MAX_PROGRESS_MESSAGES_PER_TICK: usize = 20_000;
+
+/// Type alias for the rate limiter shared by all API requests in the app
+pub type AppRateLimiter = Arc<SharedRateLimiter>;
 
 /// Represents the different states or modes the application can be in.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -30,6 +74,50 @@ pub enum AppState {
     EditingSetting,
     /// Adding a new collection to favorites.
     AddingCollection,
+    /// Showing release notes for an available update.
+    UpdateAvailable,
+    /// Asking the user to confirm a collection download after showing its size forecast.
+    ConfirmingDownload,
+    /// Offering to resume downloads interrupted in a previous session.
+    ResumePrompt,
+    /// Viewing/editing the identifier exclusion rules.
+    ManagingExclusions,
+    /// Typing a new exclusion rule (kind in [`App::new_exclusion_kind`]).
+    AddingExclusion,
+    /// Typing an advancedsearch query ('/' while browsing).
+    EnteringSearch,
+    /// Watching the files being downloaded ('D' while browsing).
+    ViewingDownloads,
+    /// Showing the dry-run size estimate of a collection download ('B').
+    ViewingEstimate,
+    /// Typing the live filter of the items pane ('f'; text in [`App::item_query`]).
+    FilteringItems,
+    /// Typing the file an export is written to ('e'; see [`App::exporting_item`]).
+    EnteringExportPath,
+    /// Filling in the upload form ('u'; see [`App::upload_form`]).
+    EditingUpload,
+    /// Typing the archive.org screen name whose favorites 'F' imports.
+    EnteringFavoritesUser,
+    /// Typing a URL (and optional date range) to look up in the Wayback Machine ('W').
+    EnteringWaybackUrl,
+    /// Typing the fetch filter of the selected collection ('Q'; see [`crate::fetch_filter`]).
+    EnteringFetchFilter,
+    /// Typing the group of the selected collection ('t').
+    EnteringCollectionGroup,
+    /// Typing the alias the collections pane shows for the selected collection ('n').
+    EnteringCollectionAlias,
+    /// Browsing the Wayback Machine captures of a URL (see [`App::wayback`]).
+    ViewingWayback,
+    /// Listing the active key bindings ('?'; closes back to [`App::help_return_state`]).
+    ViewingHelp,
+    /// Typing a command name into the palette (':'; closes back to [`App::command_return_state`]).
+    EnteringCommand,
+    /// Reading recent log lines ('!'; closes back to [`App::log_return_state`]).
+    ViewingLog,
+    /// Listing the failures of the last download run ('E'; see [`App::failures`]).
+    ViewingFailures,
+    /// Showing the totals of a collection download that just ended (see [`App::collection_report`]).
+    ViewingCollectionReport,
 }
 
 /// Indicates which pane is currently active/focused.
@@ -39,6 +127,25 @@ pub enum ActivePane {
     Items,
 }
 
+/// Tab shown in the left pane of the item view (cycled with Tab).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ItemTab {
+    #[default]
+    Metadata,
+    Reviews,
+    Related,
+}
+
+impl ItemTab {
+    pub fn next(self) -> Self {
+        match self {
+            ItemTab::Metadata => ItemTab::Reviews,
+            ItemTab::Reviews => ItemTab::Related,
+            ItemTab::Related => ItemTab::Metadata,
+        }
+    }
+}
+
 
 /// Application state
 pub struct App {
@@ -72,10 +179,23 @@ pub struct App {
     pub current_item_details: Option<ItemDetails>,
     /// State for the file list widget when viewing an item
     pub file_list_state: ListState,
+    /// Tab of the item view's left pane.
+    pub item_tab: ItemTab,
+    /// Review shown in the Reviews tab.
+    pub review_index: usize,
+    /// Selection in the Related tab.
+    pub related_list_state: ListState,
     /// Flag indicating if item details are being loaded
     pub is_loading_details: bool,
     /// Name of the collection currently selected and being browsed
     pub current_collection_name: Option<String>,
+    /// Query whose results the items pane shows instead of a collection's items.
+    pub search_query: Option<String>,
+    /// Listings the items pane drilled down from into sub-collections, outermost first
+    /// (Backspace/Esc goes back up).
+    pub collection_trail: Vec<Breadcrumb>,
+    /// Back/forward history of visited collections, searches and items ('['/']').
+    pub history: History,
     /// Flag indicating if a download is in progress
     pub is_downloading: bool,
     /// Status message for the current or last download
@@ -94,8 +214,14 @@ pub struct App {
     pub files_downloaded_count: usize,
     /// Total bytes downloaded in the current operation
     pub total_bytes_downloaded: u64,
+    /// Bytes the items started so far still needed when they started
+    pub bytes_to_download: u64,
+    /// Number of items whose size is counted in `bytes_to_download`
+    pub items_sized: usize,
     /// Start time of the current download operation
     pub download_start_time: Option<Instant>,
+    /// Receiver for progress messages from download tasks, drained in batches on each tick
+    pub download_progress_rx: Option<mpsc::Receiver<DownloadProgress>>,
 
 
     // --- Settings State ---
@@ -115,33 +241,351 @@ pub struct App {
     pub add_collection_cursor_pos: usize,
     /// Shared global rate limiter for API calls and downloads
     pub rate_limiter: AppRateLimiter,
+    /// Download speed cap shared with running downloads, so settings changes apply live
+    pub throttle: Arc<Throttle>,
+    /// Files being downloaded right now, shown in the Downloads view.
+    pub active_transfers: ActiveTransfers,
+    /// Pause/cancel switches handed to every download started from now on.
+    pub download_control: Arc<DownloadControl>,
+    /// Selected row of the Downloads view.
+    pub transfers_table_state: TableState,
+    /// Resolved UI styles (from the theme setting and NO_COLOR).
+    pub theme: Theme,
+    /// Screen-reader mode: single-pane layout plus an event line describing the screen.
+    pub screen_reader: bool,
+    /// Last screen description announced in screen-reader mode.
+    pub last_announcement: Option<String>,
+    /// Newer release found by the update check, if any.
+    pub available_update: Option<ReleaseInfo>,
+    /// archive.org login the client sends, if any (see [`auth::credentials`]).
+    pub credentials: Option<Credentials>,
+    /// Files skipped by the current download because the account can't access them.
+    pub restricted_files_count: usize,
+    /// Files skipped by the current download because a complete copy was already on disk.
+    pub skipped_files_count: usize,
+    /// Download files again even when a complete copy is on disk (`--force`).
+    pub force_downloads: bool,
+    /// Forecast shown while in [`AppState::ConfirmingDownload`].
+    pub collection_forecast: Option<CollectionForecast>,
+    /// Dry-run size estimate shown while in [`AppState::ViewingEstimate`], filled in as items are checked.
+    pub size_estimate: Option<SizeEstimate>,
+    /// Average speed of the most recent download in this session (bytes/s).
+    pub recent_download_speed: Option<f64>,
+    /// Journal of download jobs in flight (in memory only until `main` loads the real one).
+    pub session: SessionJournal,
+    /// Downloads waiting for the running one to finish (in memory only until `main` loads it).
+    pub queue: DownloadQueue,
+    /// Failed downloads waiting to be tried again (in memory only until `main` loads it).
+    pub retries: RetryQueue,
+    /// Whether listings and item details come from the network or the caches (see [`crate::offline`]).
+    pub network: NetworkMode,
+    /// When the shown item list was cached, if it came from the cache.
+    pub items_cached_at: Option<SystemTime>,
+    /// Item counts and sync state of the listed collections, for the collections pane.
+    pub collection_status: BTreeMap<String, CollectionStatus>,
+    /// When the shown item details were cached, if they came from the cache.
+    pub details_cached_at: Option<SystemTime>,
+    /// Cover art of the viewed item, once fetched (see [`crate::thumbnail`]).
+    pub thumbnail: Option<Thumbnail>,
+    /// Where the last frame left room for the cover art; the terminal draws it there.
+    pub thumbnail_area: Option<Rect>,
+    /// When favorite collections are next re-listed for new items.
+    pub sync: SyncSchedule,
+    /// Whether the resume prompt lists the interrupted jobs.
+    pub show_session_details: bool,
+    /// Compiled exclusion rules from the settings (see [`App::refresh_exclusions`]).
+    pub exclusions: Arc<ExclusionList>,
+    /// Selection in the exclusion rules screen.
+    pub exclusion_list_state: ListState,
+    /// Kind of rule being typed in [`AppState::AddingExclusion`].
+    pub new_exclusion_kind: ExclusionKind,
+    /// Download status of items in the download directory (see [`App::sync_library_dir`]).
+    pub library: LibraryIndex,
+    /// Which items the items pane shows; `item_list_state` indexes the filtered list.
+    pub item_status_filter: ItemStatusFilter,
+    /// Mediatype/format filter of the items pane ('m' and 'F' cycle it).
+    pub item_filter: ItemFilter,
+    /// Live fuzzy filter of the items pane over identifiers and titles ('f').
+    pub item_query: String,
+    /// Items marked in the items pane for a batch download (Space, '*', '-').
+    pub marked_items: HashSet<String>,
+    /// Whether the export being set up writes the viewed item rather than the listed items.
+    pub exporting_item: bool,
+    /// File preset applied to the next item/collection download ('p' cycles it).
+    pub download_preset: FilePreset,
+    /// Whether the items pane clusters items with near-identical titles ('g').
+    pub group_duplicates: bool,
+    /// Identifiers of the first item of each group expanded to show its variants.
+    pub expanded_groups: HashSet<String>,
+    /// Groups of favorite collections folded under their header in the collections pane.
+    pub collapsed_collection_groups: HashSet<String>,
+    /// Group the collections pane is limited to, if any.
+    pub collection_group_filter: Option<String>,
+    /// Favorites whose archive.org title was asked for this session (see [`App::take_untitled_collections`]).
+    collection_titles_requested: HashSet<String>,
+    /// Report of the current download run, written when its last job ends.
+    pub run_report: Option<RunReport>,
+    /// HTML page of the most recent run report ('R' opens it).
+    pub last_report_path: Option<PathBuf>,
+    /// archive.org S3 keys used for uploads (settings, or the `ia` tool's configuration).
+    pub s3_keys: Option<S3Keys>,
+    /// Upload form being filled in ('u').
+    pub upload_form: UploadForm,
+    /// Progress of the running upload.
+    pub upload: Option<UploadStatus>,
+    /// Wayback Machine captures being browsed ('W').
+    pub wayback: Option<WaybackView>,
+    /// Active key bindings (see [`App::refresh_keymap`]).
+    pub keymap: Keymap,
+    /// State the help screen returns to.
+    pub help_return_state: AppState,
+    /// Selected row of the help screen.
+    pub help_table_state: TableState,
+    /// State the command palette runs its command in.
+    pub command_return_state: AppState,
+    /// Selected command among those matching the palette's input.
+    pub command_list_state: ListState,
+    /// Count or `g` typed before a list motion.
+    pub list_nav: ListNav,
+    /// Rows the lists showed when last drawn (see [`Motion::PageDown`]).
+    pub page_rows: PageRows,
+    /// Audio file playing in the preview player, if any.
+    pub preview: Option<Preview>,
+    /// Recent log lines, for the log view.
+    pub log_buffer: LogBuffer,
+    /// Least severe level the log view shows.
+    pub log_level: Level,
+    /// Selected log line; none follows the newest.
+    pub log_list_state: ListState,
+    /// State the log view returns to.
+    pub log_return_state: AppState,
+    /// What failed in the last download run (loaded from its download directory at start).
+    pub failures: RunFailures,
+    /// Selected row of the failures view.
+    pub failures_table_state: TableState,
+    /// Report of the last collection download, shown when it ends.
+    pub collection_report: Option<CollectionReport>,
+}
+
+/// Labels of the upload form's fields: the local directory and identifier, then the
+/// item metadata sent with the upload.
+pub const UPLOAD_FIELDS: [&str; 7] = ["Local directory", "Identifier", "Title", "Mediatype", "Collection", "Creator", "Description"];
+/// Metadata keys of `UPLOAD_FIELDS[2..]`.
+const UPLOAD_METADATA_KEYS: [&str; 5] = ["title", "mediatype", "collection", "creator", "description"];
+
+/// Values typed into the upload form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadForm {
+    pub values: [String; 7],
+    /// Index of the field being edited.
+    pub field: usize,
+}
+
+impl Default for UploadForm {
+    fn default() -> Self {
+        let mut values: [String; 7] = Default::default();
+        values[3] = "data".to_string(); // archive.org's mediatype for anything else
+        values[4] = "opensource".to_string(); // The community collection anyone can upload to
+        UploadForm { values, field: 0 }
+    }
+}
+
+impl UploadForm {
+    /// Builds the upload request, checking that the directory exists and the identifier is valid.
+    pub fn request(&self) -> Result<UploadRequest> {
+        let directory = PathBuf::from(self.values[0].trim());
+        if !directory.is_dir() {
+            return Err(anyhow!("'{}' is not a directory", directory.display()));
+        }
+        let identifier = self.values[1].trim().to_string();
+        ia_upload::validate_identifier(&identifier)?;
+        let metadata = UPLOAD_METADATA_KEYS
+            .iter()
+            .zip(&self.values[2..])
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(key, value)| (key.to_string(), value.trim().to_string()))
+            .collect();
+        Ok(UploadRequest { directory, identifier, metadata })
+    }
+}
+
+/// Progress of an upload, updated from [`UploadProgress`] messages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UploadStatus {
+    pub identifier: String,
+    pub files_total: usize,
+    pub files_done: usize,
+    pub bytes_total: u64,
+    pub bytes_sent: u64,
+    /// File being uploaded.
+    pub current_file: Option<String>,
+}
+
+/// The captures of a URL listed in [`AppState::ViewingWayback`].
+#[derive(Debug, Clone)]
+pub struct WaybackView {
+    pub query: SnapshotQuery,
+    /// Captures, oldest first.
+    pub snapshots: Vec<Snapshot>,
+    /// Whether the lookup is still running.
+    pub loading: bool,
+    /// Whether captures are being saved.
+    pub downloading: bool,
+    pub list_state: ListState,
+}
+
+/// Text the items pane's live filter matches: the identifier, then the title.
+pub fn item_search_text(doc: &ArchiveDoc) -> String {
+    match doc.title.as_deref() {
+        Some(title) => format!("{} - {}", doc.identifier, title),
+        None => doc.identifier.clone(),
+    }
+}
+
+/// A row of the items pane.
+#[derive(Debug, Clone, Copy)]
+pub struct ItemRow<'a> {
+    pub doc: &'a ArchiveDoc,
+    /// Items in the row's group (1 when ungrouped or unique).
+    pub group_size: usize,
+    /// Whether the row is a variant listed under an expanded group.
+    pub is_variant: bool,
+    /// Whether the row heads an expanded group.
+    pub expanded: bool,
+}
+
+/// A row of the collections pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectionRow<'a> {
+    /// Header of a group of favorites, with the number of favorites in it.
+    Group { name: &'a str, count: usize, collapsed: bool },
+    /// A favorite collection or saved search.
+    Collection(&'a str),
 }
 
 /// Actions that the main loop should perform based on user input or events.
 #[derive(Clone, Debug)]
 pub enum UpdateAction {
-    /// Start fetching all items for a collection identifier in bulk.
-    StartBulkItemFetch(String),
+    /// Start listing a collection's items page by page (shown as they arrive).
+    StartIncrementalItemFetch(String),
+    /// Run an advancedsearch query and list the results in the items pane.
+    StartSearch(String),
     /// Fetch details for the currently selected item.
     FetchItemDetails,
     /// Start a download operation.
     StartDownload(DownloadAction),
     /// Save the current settings (e.g., after adding/removing a collection or exiting settings).
     SaveSettings,
+    /// Download and install `App::available_update`.
+    InstallUpdate,
+    /// Restart the download jobs left in the session journal.
+    ResumeSession,
+    /// Open a downloaded file or item directory with the system's default application.
+    OpenPath(PathBuf),
+    /// Fetch the metadata of every item of a collection for `App::size_estimate`.
+    EstimateCollection(String),
+    /// Upload a local directory to archive.org with `App::s3_keys`.
+    StartUpload(UploadRequest),
+    /// Save `App::s3_keys` to the keys file.
+    SaveUploadKeys,
+    /// Log in to archive.org and save the session cookies.
+    Login(LoginRequest),
+    /// Forget the saved archive.org login.
+    Logout,
+    /// Add the collections among an archive.org user's favorites to the favorite collections.
+    ImportFavorites(String),
+    /// List the Wayback Machine captures matching a query into `App::wayback`.
+    ListWayback(SnapshotQuery),
+    /// Save Wayback Machine captures below the download directory.
+    DownloadWayback(Vec<Snapshot>),
+    /// Re-list the favorite collections now and queue their new items (see [`crate::sync`]).
+    SyncNow,
+    /// Check the downloaded files of an item (identifier, folder) against its checksum
+    /// manifest, or archive.org's listing without one.
+    VerifyItem(String, PathBuf),
+    /// Open a web page in the default browser.
+    OpenUrl(String),
+    /// Stream a file URL in `Settings::player_command`.
+    PlayUrl(String),
+    /// Play an item's (identifier) MP3 or FLAC file in the built-in preview player.
+    PreviewFile(String, FileDetails),
+    /// Copy text (an identifier or URL) to the system clipboard.
+    CopyToClipboard(String),
+    /// Paste the system clipboard into the current text prompt.
+    PasteClipboard,
+}
+
+impl UpdateAction {
+    /// Whether the action talks to archive.org (or another server), so offline mode refuses it.
+    pub fn needs_network(&self) -> bool {
+        matches!(
+            self,
+            UpdateAction::StartIncrementalItemFetch(_)
+                | UpdateAction::StartSearch(_)
+                | UpdateAction::FetchItemDetails
+                | UpdateAction::StartDownload(_)
+                | UpdateAction::InstallUpdate
+                | UpdateAction::EstimateCollection(_)
+                | UpdateAction::StartUpload(_)
+                | UpdateAction::Login(_)
+                | UpdateAction::ImportFavorites(_)
+                | UpdateAction::ListWayback(_)
+                | UpdateAction::DownloadWayback(_)
+                | UpdateAction::SyncNow
+                | UpdateAction::PlayUrl(_)
+                | UpdateAction::PreviewFile(..)
+        )
+    }
 }
 
 /// Specifies what to download.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DownloadAction {
     /// Download all files for a specific item.
     ItemAllFiles(String), // item_identifier
     /// Download a single specific file.
-    File(String, FileDetails), // item_identifier, file details
+    File(String, Box<FileDetails>), // item_identifier, file details (boxed: much larger than the other variants)
     /// Download all items for a specific collection identifier.
     Collection(String), // collection_identifier
+    /// Download a batch of items picked in the Items pane, as one job.
+    Items(Vec<String>), // item_identifiers
     // Maybe add CollectionAllFavorites later
 }
 
+/// A listing the items pane was showing before entering a sub-collection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breadcrumb {
+    pub listing: Listing,
+    /// The sub-collection that was entered, selected again when going back up.
+    pub selected: Option<String>,
+}
+
+/// What the items pane lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listing {
+    Collection(String),
+    Search(String),
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listing::Collection(name) => write!(f, "{}", saved_search::label(name)),
+            Listing::Search(query) => write!(f, "Search '{}'", query),
+        }
+    }
+}
+
+/// A page of a collection listing, sent by the incremental item fetch task.
+#[derive(Debug, Clone)]
+pub struct ItemPage {
+    pub docs: Vec<ArchiveDoc>,
+    /// Total items in the collection, as reported by the API.
+    pub total: Option<usize>,
+    /// No pages follow; the listing is complete.
+    pub last: bool,
+}
+
 /// Represents progress updates sent from download tasks.
 #[derive(Debug, Clone)]
 pub enum DownloadProgress {
@@ -151,18 +595,42 @@ pub enum DownloadProgress {
     ItemStarted(String),
     /// Determined the number of files for an item.
     ItemFileCount(usize),
-    /// A chunk of bytes was downloaded for a file.
+    /// Determined how many bytes an item still needs (its missing files and parts).
+    ItemBytes(u64),
+    /// A chunk of bytes was downloaded (torrent pieces; direct files use `FileBytes`).
     BytesDownloaded(u64),
+    /// A file transfer (or a retry of one) began; `resumed` bytes were already on disk.
+    FileStarted { key: Arc<str>, total: Option<u64>, resumed: u64 },
+    /// A chunk of bytes was downloaded for the file with this key (see [`transfers::transfer_key`]).
+    FileBytes(Arc<str>, u64),
     /// A single file download completed successfully.
     FileCompleted(String), // filename
     /// An item download finished (successfully or with partial failure).
     ItemCompleted(String, bool), // identifier, success (true if all files OK)
     /// An item download was skipped because the identifier was actually a collection.
     ItemSkippedWasCollection(String), // identifier
+    /// An item was skipped because another instance is downloading it into the same directory.
+    ItemSkippedClaimed(String), // identifier
+    /// A collection's items are done; sent just before `CollectionCompleted` for its report.
+    CollectionFinished(CollectionRun),
     /// The entire collection download attempt finished.
     CollectionCompleted(usize, usize), // total items attempted, total items failed
     /// An error occurred during download.
     Error(String),
+    /// A file was skipped because it is access-restricted (lending/stream-only) and the
+    /// session isn't entitled to it.
+    FileRestricted(String, String), // identifier, filename
+    /// A file download ended (downloaded, skipped or failed); feeds the run report.
+    FileFinished(FileResult),
+    /// A completed item directory was added to IPFS under this CID.
+    ItemAddedToIpfs(String, String), // identifier, CID
+    /// A library layout put the item's files in this folder.
+    ItemPlaced(String, PathBuf), // identifier, folder
+    /// The item matched this MusicBrainz release.
+    ItemMatched(String, String), // identifier, release MBID
+    /// A job failed for a reason that may pass and asks to be tried again later (see
+    /// [`crate::retry`]); the job carries its earlier attempts.
+    RetryLater(DownloadJob, String), // job, reason
     /// A general status message.
     Status(String),
 }
@@ -171,12 +639,8 @@ pub enum DownloadProgress {
 impl App {
     /// Constructs a new instance of [`App`].
     pub fn new(rate_limiter: AppRateLimiter) -> Self { // Accept rate limiter
-        // Configure Reqwest client with significantly increased timeouts for large collections
-        let client = Client::builder()
-            .timeout(Duration::from_secs(1800)) // General request timeout (increased to 30 minutes)
-            .connect_timeout(Duration::from_secs(60)) // Connection timeout (remains 60s)
-            .build()
-            .unwrap_or_else(|_| Client::new()); // Fallback to default if builder fails
+        let credentials = auth::credentials(&Settings::default());
+        let client = Self::build_client(&Settings::default(), credentials.as_ref());
 
         Self {
             running: true,
@@ -195,8 +659,14 @@ impl App {
             viewing_item_id: None,
             current_item_details: None,
             file_list_state: ListState::default(),
+            item_tab: ItemTab::Metadata,
+            review_index: 0,
+            related_list_state: ListState::default(),
             is_loading_details: false,
             current_collection_name: None,
+            search_query: None,
+            collection_trail: Vec::new(),
+            history: History::default(),
             is_downloading: false,
             download_status: None,
             pending_action: None,
@@ -205,7 +675,10 @@ impl App {
             total_files_to_download: None,
             files_downloaded_count: 0,
             total_bytes_downloaded: 0,
+            bytes_to_download: 0,
+            items_sized: 0,
             download_start_time: None,
+            download_progress_rx: None,
             settings_list_state: ListState::default(),
             selected_setting_index: 0, // Start with the first setting selected
             editing_setting_input: String::new(),
@@ -213,12 +686,124 @@ impl App {
             add_collection_input: String::new(), // Initialize add collection input
             add_collection_cursor_pos: 0, // Initialize add collection cursor
             rate_limiter, // Store the passed rate limiter
+            throttle: Arc::new(Throttle::new(0)),
+            active_transfers: ActiveTransfers::default(),
+            download_control: Arc::new(DownloadControl::default()),
+            transfers_table_state: TableState::default(),
+            theme: Theme::from_env(Settings::default().theme),
+            screen_reader: false,
+            last_announcement: None,
+            available_update: None,
+            credentials,
+            restricted_files_count: 0,
+            skipped_files_count: 0,
+            force_downloads: false,
+            collection_forecast: None,
+            size_estimate: None,
+            recent_download_speed: None,
+            session: SessionJournal::default(),
+            queue: DownloadQueue::default(),
+            retries: RetryQueue::default(),
+            network: NetworkMode::Online,
+            items_cached_at: None,
+            collection_status: BTreeMap::new(),
+            details_cached_at: None,
+            thumbnail: None,
+            thumbnail_area: None,
+            sync: SyncSchedule::default(),
+            show_session_details: false,
+            exclusions: Arc::new(ExclusionList::default()),
+            exclusion_list_state: ListState::default(),
+            new_exclusion_kind: ExclusionKind::Identifier,
+            library: LibraryIndex::default(),
+            item_status_filter: ItemStatusFilter::All,
+            item_filter: ItemFilter::default(),
+            item_query: String::new(),
+            marked_items: HashSet::new(),
+            exporting_item: false,
+            s3_keys: None,
+            upload_form: UploadForm::default(),
+            upload: None,
+            wayback: None,
+            keymap: Keymap::default(),
+            help_return_state: AppState::Browsing,
+            help_table_state: TableState::default(),
+            command_return_state: AppState::Browsing,
+            command_list_state: ListState::default(),
+            list_nav: ListNav::default(),
+            page_rows: PageRows::default(),
+            preview: None,
+            log_buffer: LogBuffer::default(),
+            log_level: Level::Info,
+            log_list_state: ListState::default(),
+            log_return_state: AppState::Browsing,
+            failures: RunFailures::default(),
+            failures_table_state: TableState::default(),
+            collection_report: None,
+            download_preset: FilePreset::AllFiles,
+            group_duplicates: false,
+            expanded_groups: HashSet::new(),
+            collapsed_collection_groups: HashSet::new(),
+            collection_group_filter: None,
+            collection_titles_requested: HashSet::new(),
+            run_report: None,
+            last_report_path: None,
+        }
+    }
+
+    /// Builds the HTTP client shared by API requests and downloads.
+    ///
+    /// With `credentials`, archive.org login cookies are attached to requests to the API host
+    /// and its data nodes.
+    pub fn build_client(settings: &Settings, credentials: Option<&Credentials>) -> Client {
+        // Configure Reqwest client with significantly increased timeouts for large collections
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(1800)) // General request timeout (increased to 30 minutes)
+            .connect_timeout(Duration::from_secs(60)); // Connection timeout (remains 60s)
+        match CachingResolver::new(&settings.dns_config()) {
+            Ok(resolver) => builder = builder.dns_resolver(Arc::new(resolver)),
+            Err(e) => log::error!("Invalid DNS settings, using the system resolver: {:#}", e),
+        }
+        if let Some(creds) = credentials {
+            builder = builder.cookie_provider(Arc::new(auth::cookie_jar(creds, &archive_api::base_url())));
+        }
+        let transport = settings.transport_config();
+        builder = match transport.load() {
+            Ok(loaded) => loaded.apply(builder),
+            Err(e) => {
+                log::error!("Invalid proxy or TLS settings: {:#}", e);
+                transport.fail_closed(builder)
+            }
+        };
+        builder.build().unwrap_or_else(|_| Client::new()) // Fallback to default if builder fails
+    }
+
+    /// Switches to another archive.org login (or none), rebuilding the client so metadata
+    /// and download requests carry the new cookies.
+    pub fn set_credentials(&mut self, credentials: Option<Credentials>) {
+        if credentials != self.credentials {
+            self.client = Self::build_client(&self.settings, credentials.as_ref());
+            self.credentials = credentials;
         }
     }
 
     /// Load settings into the App state.
     pub fn load_settings(&mut self, settings: Settings) {
+        // Rebuild the client only when resolver, proxy/TLS or login settings change, keeping pooled connections otherwise
+        let credentials = auth::credentials(&settings);
+        if settings.dns_config() != self.settings.dns_config() || settings.transport_config() != self.settings.transport_config() || credentials != self.credentials {
+            self.client = Self::build_client(&settings, credentials.as_ref());
+            self.credentials = credentials;
+        }
+        self.screen_reader = settings.screen_reader;
+        self.download_preset = settings.default_file_preset;
+        self.throttle.set_limit(settings.download_speed_limit_kb);
+        self.network = self.network.with_setting(settings.offline_mode);
         self.settings = settings;
+        self.refresh_theme();
+        self.refresh_keymap();
+        self.refresh_exclusions();
+        self.sync_library_dir();
         // Select the first collection if the list is not empty after loading
         if !self.settings.favorite_collections.is_empty() {
             self.collection_list_state.select(Some(0));
@@ -228,9 +813,511 @@ impl App {
         }
     }
 
+    /// Re-resolves [`App::theme`] after the theme setting changed.
+    pub fn refresh_theme(&mut self) {
+        self.theme = Theme::from_env(self.settings.theme);
+    }
+
+    /// Rebuilds [`App::keymap`] from the `[keys]` section of the settings.
+    pub fn refresh_keymap(&mut self) {
+        self.keymap = Keymap::from_settings(&self.settings);
+    }
+
+    /// Recompiles [`App::exclusions`] after the exclusion rules changed.
+    pub fn refresh_exclusions(&mut self) {
+        self.exclusions = Arc::new(ExclusionList::from_settings(&self.settings));
+    }
+
+    /// Loads the library index of the current download directory if it isn't loaded yet
+    /// (the directory can change in the settings or when asked before a download).
+    pub fn sync_library_dir(&mut self) {
+        let Some(dir) = self.settings.download_directory.as_deref().map(PathBuf::from) else { return };
+        if self.library.base_dir() != Some(dir.as_path()) {
+            self.library.flush();
+            self.library = LibraryIndex::load(&dir);
+            self.failures = RunFailures::load_latest(&dir).unwrap_or_default();
+            self.collection_status = collection_status::load(&dir.to_string_lossy());
+            self.collection_titles_requested.clear(); // Another directory has other titles cached
+        }
+    }
+
+    /// Attaches the receiving end of the download progress channel.
+    /// Messages are only consumed on [`App::tick`], decoupling download throughput from redraws.
+    pub fn attach_progress_receiver(&mut self, rx: mpsc::Receiver<DownloadProgress>) {
+        self.download_progress_rx = Some(rx);
+    }
+
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {
-        // Placeholder for tick logic
+    ///
+    /// Drains all queued download progress messages (up to `MAX_PROGRESS_MESSAGES_PER_TICK`)
+    /// into the app counters, so the UI is updated once per tick instead of once per chunk.
+    pub fn tick(&mut self) {
+        self.drain_progress(MAX_PROGRESS_MESSAGES_PER_TICK);
+
+        if let Some(start) = self.download_start_time {
+            let elapsed = start.elapsed().as_secs_f64();
+            if elapsed >= 1.0 && self.total_bytes_downloaded > 0 {
+                self.recent_download_speed = Some(self.total_bytes_downloaded as f64 / elapsed);
+            }
+        }
+        if self.is_downloading {
+            let snapshot = self.progress_snapshot();
+            self.session.update_progress(snapshot);
+        }
+        self.library.save_if_due();
+        if self.preview.as_mut().is_some_and(|preview| preview.has_finished()) {
+            self.preview = None;
+        }
+    }
+
+    /// Applies up to `limit` queued download progress messages.
+    fn drain_progress(&mut self, limit: usize) {
+        let Some(mut rx) = self.download_progress_rx.take() else {
+            return;
+        };
+        for _ in 0..limit {
+            match rx.try_recv() {
+                Ok(progress) => self.apply_download_progress(progress),
+                Err(_) => break, // Empty or all senders gone
+            }
+        }
+        self.download_progress_rx = Some(rx);
+    }
+
+    /// Whether the previous run left interrupted or queued downloads to decide on.
+    pub fn has_unfinished_session(&self) -> bool {
+        !self.session.entries().is_empty() || !self.queue.held().is_empty()
+    }
+
+    /// Short description of the interrupted and queued downloads, for the resume prompt.
+    pub fn unfinished_session_summary(&self) -> String {
+        let held = self.queue.held().len();
+        match (self.session.entries().is_empty(), held) {
+            (false, 0) => self.session.summary(),
+            (false, held) => format!("{}; {} more queued", self.session.summary(), held),
+            (true, held) => format!("{} download(s) queued", held),
+        }
+    }
+
+    /// Whether a collection download is running. Collections download one at a time;
+    /// file and item downloads start alongside them (see [`crate::scheduler`]).
+    pub fn is_bulk_download_running(&self) -> bool {
+        self.session.entries().iter().any(|entry| Priority::of(&entry.job.action) == Priority::Bulk)
+    }
+
+    /// The next queued job, if no collection is downloading.
+    pub fn next_queued_job(&mut self) -> Option<DownloadJob> {
+        if self.is_bulk_download_running() {
+            None
+        } else {
+            self.queue.pop_ready()
+        }
+    }
+
+    /// Adds a job to the run report, starting a new report if no run is in progress.
+    pub fn begin_report_job(&mut self, description: String, base_dir: &str) {
+        let report = self.run_report.get_or_insert_with(|| RunReport::new(PathBuf::from(base_dir)));
+        report.jobs.push(description);
+    }
+
+    /// Writes the report of the run that just ended (called once no job is in flight).
+    pub fn finish_run_report(&mut self) {
+        // Tasks send all their progress before they end; apply it first
+        self.drain_progress(usize::MAX);
+        let Some(mut report) = self.run_report.take() else { return };
+        report.finish();
+        self.failures = RunFailures::from_report(&report);
+        if let Err(e) = self.failures.write(&report.download_dir) {
+            log::error!("{:#}", e);
+        }
+        match report.write() {
+            Ok(path) => {
+                log::info!("Download report written to {}", path.display());
+                let mut status = format!("{}. Report saved ('R' to open).", report.summary());
+                if !self.failures.is_empty() {
+                    status.push_str(&format!(" '{}' lists the failures.", self.keymap.hint(Action::ToggleFailures)));
+                }
+                if !self.retries.is_empty() {
+                    status.push_str(&format!(" {} to retry later.", self.retries.len()));
+                }
+                self.download_status = Some(status);
+                self.last_report_path = Some(path);
+            }
+            Err(e) => log::error!("{:#}", e),
+        }
+    }
+
+    /// Current download counters, as saved in the session journal.
+    pub fn progress_snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            items_done: self.items_downloaded_count,
+            items_total: self.total_items_to_download,
+            files_done: self.files_downloaded_count,
+            files_total: self.total_files_to_download,
+            bytes_done: self.total_bytes_downloaded,
+        }
+    }
+
+    /// Progress of the running downloads, for the gauge and its time estimate.
+    pub fn run_progress(&self) -> RunProgress {
+        RunProgress {
+            items_total: self.total_items_to_download,
+            items_done: self.items_downloaded_count,
+            items_sized: self.items_sized,
+            bytes_planned: self.bytes_to_download,
+            bytes_done: self.total_bytes_downloaded,
+            files_total: self.total_files_to_download,
+            files_done: self.files_downloaded_count,
+            elapsed: self.download_start_time.map(|start| start.elapsed()).unwrap_or_default(),
+        }
+    }
+
+    /// Applies a fresh listing of favorite `collection_name` from a sync round: items
+    /// missing from the item cache are queued for download and the cache is updated.
+    /// A collection without a cache yet only gets its cache written, so enabling sync
+    /// doesn't queue whole collections. Returns the number of queued items.
+    pub fn apply_sync_listing(&mut self, collection_name: &str, fresh: Vec<ArchiveDoc>) -> Result<usize> {
+        let base_dir = self.settings.download_directory.clone().context("Download directory is not set in settings")?;
+        let new_ids = match self.load_items_from_cache(collection_name) {
+            Ok(cached) => sync::new_identifiers(&cached, &fresh),
+            Err(_) => Vec::new(), // First listing becomes the baseline
+        };
+        let mut queued = 0;
+        for id in new_ids {
+            if self.exclusions.is_excluded(&id) {
+                log::info!("Sync: skipping excluded new item '{}' in '{}'", id, collection_name);
+                continue;
+            }
+            self.queue.push(DownloadJob {
+                action: DownloadAction::ItemAllFiles(id),
+                collection: Some(collection_name.to_string()),
+                mode: self.settings.download_mode,
+                base_dir: base_dir.clone(),
+                preset: self.settings.default_file_preset,
+                retry: None,
+            });
+            queued += 1;
+        }
+        if queued > 0 {
+            log::info!("Sync: queued {} new item(s) from '{}'", queued, collection_name);
+        }
+        // Keep the open collection's list in step with its cache
+        let is_open = self.search_query.is_none() && self.current_collection_name.as_deref() == Some(collection_name);
+        if is_open {
+            self.items = fresh.clone();
+        }
+        self.save_items_to_cache(collection_name, &fresh)?;
+        self.record_listing(collection_name, fresh.len(), if is_open { 0 } else { queued });
+        Ok(queued)
+    }
+
+    /// Shows the outcome of verifying an item's downloaded files ([`UpdateAction::VerifyItem`]).
+    pub fn apply_verify_result(&mut self, identifier: &str, result: Result<Vec<(String, FileCheck)>>) {
+        let checks = match result {
+            Ok(checks) => checks,
+            Err(e) => {
+                log::error!("Verifying '{}' failed: {:#}", identifier, e);
+                self.error_message = Some(format!("Verifying '{}' failed: {:#}", identifier, e));
+                return;
+            }
+        };
+        let failed: Vec<&str> =
+            checks.iter().filter(|(_, check)| matches!(check, FileCheck::Mismatch { .. })).map(|(name, _)| name.as_str()).collect();
+        let ok = checks.iter().filter(|(_, check)| *check == FileCheck::Ok).count();
+        let missing = checks.len() - ok - failed.len();
+        if failed.is_empty() {
+            self.download_status = Some(format!("Verified {}: {} file(s) OK, {} not downloaded", identifier, ok, missing));
+        } else {
+            self.error_message = Some(format!("{}: {} file(s) failed verification: {}", identifier, failed.len(), failed.join(", ")));
+        }
+    }
+
+    /// Appends a page of `collection_name`'s listing. Pages of a collection that is no
+    /// longer open are ignored; the last page ends loading and writes the item cache.
+    pub fn apply_item_page(&mut self, collection_name: &str, page: ItemPage) -> Result<()> {
+        if self.search_query.is_some() || self.current_collection_name.as_deref() != Some(collection_name) {
+            return Ok(()); // The user moved on meanwhile
+        }
+        let was_empty = self.items.is_empty();
+        self.items.extend(page.docs);
+        self.total_items_found = Some(page.total.unwrap_or(self.items.len()));
+        if was_empty && !self.items.is_empty() {
+            self.item_list_state.select(Some(0));
+        }
+        if page.last {
+            self.is_loading = false;
+            log::info!("Listed {} items of collection '{}'", self.items.len(), collection_name);
+            self.save_items_to_cache(collection_name, &self.items)?;
+            self.record_listing(collection_name, self.items.len(), 0);
+        }
+        Ok(())
+    }
+
+    /// Builds the size forecast for downloading `collection_name` from its listing (the
+    /// loaded items if that collection is open, otherwise the item cache).
+    pub fn forecast_collection(&self, collection_name: &str) -> CollectionForecast {
+        let cached;
+        let items = if self.current_collection_name.as_deref() == Some(collection_name) && !self.items.is_empty() {
+            Some(self.items.as_slice())
+        } else {
+            cached = self.load_items_from_cache(collection_name).ok();
+            cached.as_deref()
+        };
+        let free_bytes = self.settings.download_directory.as_deref().and_then(|dir| forecast::available_space(Path::new(dir)));
+        CollectionForecast::new(collection_name, items, free_bytes, self.recent_download_speed)
+    }
+
+    /// Identifiers of `collection_name` from the loaded listing or the item cache, if
+    /// it has been listed; and the file selection a download would use now.
+    pub fn estimate_inputs(&self, collection_name: &str) -> (Option<Vec<String>>, FilePlan) {
+        let identifiers = if self.current_collection_name.as_deref() == Some(collection_name) && !self.items.is_empty() {
+            Some(self.items.iter().map(|doc| doc.identifier.clone()).collect())
+        } else {
+            self.load_items_from_cache(collection_name).ok().map(|docs| docs.into_iter().map(|doc| doc.identifier).collect())
+        };
+        let plan = FilePlan {
+            preset: self.download_preset,
+            sources: self.settings.file_source_policy,
+            filter: self.settings.download_file_filter.clone(),
+            subtitle_languages: self.settings.include_subtitles.then(|| self.settings.subtitle_languages.clone()),
+        };
+        (identifiers, plan)
+    }
+
+    /// Why downloading `bytes` more for `what` is refused, if they won't fit in the free
+    /// space of the download directory's filesystem.
+    pub fn space_shortfall(&self, what: &str, bytes: u64) -> Option<String> {
+        let free = forecast::available_space(Path::new(self.settings.download_directory.as_deref()?))?;
+        (bytes > free).then(|| {
+            format!("Not enough free space for {}: needs {}, {} free", what, forecast::format_bytes(bytes), forecast::format_bytes(free))
+        })
+    }
+
+    /// Applies an upload progress message; the upload ends with a status or error message.
+    pub fn apply_upload_progress(&mut self, progress: UploadProgress) {
+        let Some(upload) = &mut self.upload else { return };
+        match progress {
+            UploadProgress::Started { files, bytes } => {
+                upload.files_total = files;
+                upload.bytes_total = bytes;
+            }
+            UploadProgress::File(name) => {
+                if upload.current_file.is_some() {
+                    upload.files_done += 1;
+                }
+                upload.current_file = Some(name);
+            }
+            UploadProgress::Bytes(sent) => upload.bytes_sent = sent,
+            UploadProgress::Finished => {
+                let url = format!("{}/details/{}", archive_api::DEFAULT_BASE_URL, upload.identifier);
+                self.download_status = Some(format!("Uploaded {} files to {}", upload.files_total, url));
+                self.upload = None;
+            }
+            UploadProgress::Failed(error) => {
+                self.error_message = Some(format!("Upload to '{}' failed: {}", upload.identifier, error));
+                self.upload = None;
+            }
+        }
+    }
+
+    /// Applies a Wayback Machine lookup or download update. Lookups of a query that is
+    /// no longer shown are ignored.
+    pub fn apply_wayback_progress(&mut self, progress: WaybackProgress) {
+        let Some(view) = &mut self.wayback else { return };
+        match progress {
+            WaybackProgress::Listed(query, result) => {
+                if view.query != query {
+                    return;
+                }
+                view.loading = false;
+                match result {
+                    Ok(snapshots) => {
+                        // Start on the latest capture, which is what a download fetches by default
+                        view.list_state.select(snapshots.len().checked_sub(1));
+                        self.download_status = Some(match snapshots.len() {
+                            0 => format!("The Wayback Machine has no captures of {}", query.url),
+                            n => format!("{} captures of {}", n, query.url),
+                        });
+                        view.snapshots = snapshots;
+                    }
+                    Err(e) => self.error_message = Some(e),
+                }
+            }
+            WaybackProgress::Saved(path) => self.download_status = Some(format!("Saved {}", path.display())),
+            WaybackProgress::Failed(e) => self.error_message = Some(e),
+            WaybackProgress::Finished { saved, failed } => {
+                view.downloading = false;
+                let dir = self.settings.download_directory.as_deref().unwrap_or_default();
+                let mut status = format!("Saved {} capture(s) into {}", saved, Path::new(dir).join(crate::wayback::WAYBACK_DIR).display());
+                if failed > 0 {
+                    status.push_str(&format!(", {} failed (see the log)", failed));
+                }
+                self.download_status = Some(status);
+            }
+        }
+    }
+
+    /// Applies a single download progress message to the app state.
+    pub fn apply_download_progress(&mut self, progress: DownloadProgress) {
+        match progress {
+            DownloadProgress::CollectionInfo(total) => {
+                self.total_items_to_download = Some(total);
+            }
+            DownloadProgress::ItemStarted(id) => {
+                if let Some(report) = &mut self.run_report {
+                    report.item_started(&id);
+                }
+                self.download_status = Some(format!("Starting: {}", id));
+            }
+            DownloadProgress::ItemFileCount(count) => {
+                self.total_files_to_download = Some(self.total_files_to_download.unwrap_or(0) + count);
+                self.download_status = Some(format!("Found {} files...", count));
+            }
+            DownloadProgress::ItemBytes(bytes) => {
+                self.bytes_to_download += bytes;
+                self.items_sized += 1;
+            }
+            DownloadProgress::BytesDownloaded(bytes) => {
+                self.total_bytes_downloaded += bytes;
+                // Don't update status string for every chunk, too noisy
+            }
+            DownloadProgress::FileStarted { key, total, resumed } => {
+                self.active_transfers.start(key, total, resumed, Instant::now());
+            }
+            DownloadProgress::FileBytes(key, bytes) => {
+                self.total_bytes_downloaded += bytes;
+                self.active_transfers.add(&key, bytes);
+            }
+            DownloadProgress::FileCompleted(filename) => {
+                self.files_downloaded_count += 1;
+                self.download_status = Some(format!("Done: {}", filename));
+            }
+            DownloadProgress::ItemCompleted(id, success) => {
+                self.items_downloaded_count += 1;
+                self.sync_library_dir();
+                self.library.record(&id, if success { ItemStatus::Downloaded } else { ItemStatus::Failed });
+                if let Some(report) = &mut self.run_report {
+                    report.item_finished(&id, if success { ItemOutcome::Downloaded } else { ItemOutcome::Failed });
+                }
+                let status_prefix = if success {
+                    "Completed item"
+                } else if self.download_control.is_cancelled(&id) {
+                    "Cancelled item"
+                } else {
+                    "Finished item (with errors)"
+                };
+                self.download_control.forget_item(&id);
+                self.download_status = Some(format!("{}: {}", status_prefix, id));
+            }
+            DownloadProgress::ItemPlaced(id, dir) => {
+                self.sync_library_dir();
+                self.library.record_dir(&id, &dir);
+            }
+            DownloadProgress::ItemMatched(id, release_id) => {
+                self.sync_library_dir();
+                self.library.record_musicbrainz_release(&id, &release_id);
+            }
+            DownloadProgress::ItemAddedToIpfs(id, cid) => {
+                self.sync_library_dir();
+                self.library.record_cid(&id, &cid);
+                self.download_status = Some(format!("Added {} to IPFS: {}", id, cid));
+            }
+            DownloadProgress::ItemSkippedWasCollection(id) => {
+                // Increment downloaded count as we 'processed' it by skipping
+                self.items_downloaded_count += 1;
+                if let Some(report) = &mut self.run_report {
+                    report.item_finished(&id, ItemOutcome::SkippedCollection);
+                }
+                self.download_status = Some(format!("Skipped (is collection): {}", id));
+                log::info!("Identified '{}' as a collection during TorrentOnly download attempt.", id);
+            }
+            DownloadProgress::ItemSkippedClaimed(id) => {
+                self.items_downloaded_count += 1;
+                if let Some(report) = &mut self.run_report {
+                    report.item_finished(&id, ItemOutcome::SkippedClaimed);
+                }
+                self.download_status = Some(format!("Skipped (downloading in another instance): {}", id));
+            }
+            DownloadProgress::CollectionFinished(run) => {
+                if let Some(report) = &self.run_report {
+                    let collection_report = CollectionReport::new(&run, report);
+                    match collection_report.write(&report.download_dir) {
+                        Ok(path) => log::info!("Collection report written to {}", path.display()),
+                        Err(e) => log::error!("{:#}", e),
+                    }
+                    // Pops up over the panes; elsewhere (typing, another view) it would get in the way
+                    if self.current_state == AppState::Browsing {
+                        self.current_state = AppState::ViewingCollectionReport;
+                    }
+                    self.collection_report = Some(collection_report);
+                }
+            }
+            DownloadProgress::CollectionCompleted(total, failed) => {
+                self.is_downloading = false; // Collection finished
+                self.library.flush();
+                self.download_start_time = None; // Clear start time
+                let mut status = format!("Collection download finished. Items: {} attempted, {} failed.", total, failed);
+                if self.skipped_files_count > 0 {
+                    status.push_str(&format!(" {} files already on disk skipped.", self.skipped_files_count));
+                }
+                if self.restricted_files_count > 0 {
+                    status.push_str(&format!(" {} restricted files skipped.", self.restricted_files_count));
+                }
+                self.download_status = Some(status);
+            }
+            DownloadProgress::Error(msg) => {
+                log::error!("Download Progress Error: {}", msg); // Log the error
+                if let Some(report) = &mut self.run_report {
+                    report.error(msg.clone());
+                }
+                self.is_downloading = false; // Stop on major error
+                self.download_start_time = None; // Clear start time
+                self.error_message = Some(msg.clone()); // Show as main error
+                self.download_status = Some(format!("Error: {}", msg));
+            }
+            DownloadProgress::FileRestricted(item_id, filename) => {
+                // Counted as handled so progress totals still add up
+                self.files_downloaded_count += 1;
+                self.restricted_files_count += 1;
+                self.download_status = Some(format!("Restricted (not downloadable with this account): {}/{}", item_id, filename));
+            }
+            DownloadProgress::FileFinished(result) => {
+                self.active_transfers.finish(&transfers::transfer_key(&result.item_id, &result.name));
+                if result.outcome == FileOutcome::AlreadyPresent {
+                    self.skipped_files_count += 1;
+                }
+                if let Some(report) = &mut self.run_report {
+                    report.file_finished(result);
+                }
+            }
+            DownloadProgress::RetryLater(job, reason) => {
+                let what = job.describe();
+                let policy = RetryPolicy::from_settings(&self.settings);
+                match self.retries.schedule(job, reason, &policy, Utc::now()) {
+                    Retry::After { delay, attempt } => {
+                        log::info!("Retrying {} in {}s (attempt {})", what, delay.as_secs(), attempt);
+                        self.download_status = Some(format!("Will retry {} in {} (attempt {}/{})", what, forecast::format_duration(delay), attempt, policy.max_attempts));
+                    }
+                    Retry::GaveUp { attempts } if attempts > 1 => {
+                        log::warn!("Giving up on {} after {} attempts", what, attempts);
+                        self.download_status = Some(format!("Gave up on {} after {} attempts", what, attempts));
+                    }
+                    Retry::GaveUp { .. } => {} // Retries are off
+                }
+            }
+            DownloadProgress::Status(msg) => {
+                // General status update
+                self.download_status = Some(msg);
+            }
+        }
+    }
+
+    /// Cancels every running download. Their control stays cancelled until they have
+    /// all stopped, so downloads started afterwards get a fresh one.
+    pub fn cancel_all_downloads(&mut self) {
+        self.download_control.cancel_all();
+        self.download_control = Arc::new(DownloadControl::default());
+        self.download_status = Some("Cancelling all downloads; partial files are kept for resuming.".to_string());
     }
 
     /// Set running to false to quit the application.
@@ -307,65 +1394,161 @@ impl App {
 
     // --- Collection List Navigation & Management ---
 
-    /// Selects the next collection in the favorite collections list.
-    pub fn select_next_collection(&mut self) {
-        let count = self.settings.favorite_collections.len();
-        if count == 0 {
-            return;
+    /// Rows of the collections pane; `collection_list_state` indexes this list. Favorites
+    /// without a group come first, then each group (by name) under its header, unless
+    /// the pane is limited to one group, which is listed without a header.
+    pub fn collection_rows(&self) -> Vec<CollectionRow<'_>> {
+        let group_of = |collection: &String| self.settings.collection_groups.get(collection).map(String::as_str);
+        let favorites = self.ordered_favorites();
+        if let Some(filter) = self.collection_group_filter.as_deref() {
+            return favorites.iter().filter(|c| group_of(c) == Some(filter)).map(|c| CollectionRow::Collection(c)).collect();
         }
-        let i = match self.collection_list_state.selected() {
-            Some(i) => {
-                if i >= count - 1 { 0 } else { i + 1 }
+        let mut rows: Vec<CollectionRow> = favorites.iter().filter(|c| group_of(c).is_none()).map(|c| CollectionRow::Collection(c)).collect();
+        let mut groups: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for collection in favorites {
+            if let Some(group) = group_of(collection) {
+                groups.entry(group).or_default().push(collection);
             }
-            None => 0,
-        };
-        self.collection_list_state.select(Some(i));
+        }
+        for (name, members) in groups {
+            let collapsed = self.collapsed_collection_groups.contains(name);
+            rows.push(CollectionRow::Group { name, count: members.len(), collapsed });
+            if !collapsed {
+                rows.extend(members.into_iter().map(CollectionRow::Collection));
+            }
+        }
+        rows
+    }
+
+    /// The favorite collections in the order the collections pane lists them.
+    fn ordered_favorites(&self) -> Vec<&String> {
+        let mut favorites: Vec<&String> = self.settings.favorite_collections.iter().collect();
+        match self.settings.collection_order {
+            CollectionOrder::Manual => {}
+            CollectionOrder::Alphabetical => favorites.sort_by_cached_key(|c| self.collection_display_name(c).to_lowercase()),
+            CollectionOrder::Recent => {
+                favorites.sort_by_key(|c| Reverse(self.collection_status.get(*c).and_then(|status| status.opened.as_deref())));
+            }
+        }
+        favorites
+    }
+
+    /// Name the collections pane shows for `collection`: its alias, else its archive.org
+    /// title once fetched, else the identifier (or the query of a saved search).
+    pub fn collection_display_name<'a>(&'a self, collection: &'a str) -> Cow<'a, str> {
+        let title = || self.collection_status.get(collection).and_then(|status| status.title.as_deref());
+        match self.settings.collection_aliases.get(collection).map(String::as_str).or_else(title) {
+            Some(name) => Cow::Borrowed(name),
+            None => saved_search::label(collection),
+        }
+    }
+
+    /// Favorite collections whose archive.org title isn't known yet and hasn't been asked
+    /// for this session; they count as asked for from now on. Saved searches have none.
+    pub fn take_untitled_collections(&mut self) -> Vec<String> {
+        let untitled: Vec<String> = self
+            .settings
+            .favorite_collections
+            .iter()
+            .filter(|c| saved_search::query(c).is_none() && !self.collection_titles_requested.contains(*c))
+            .filter(|c| self.collection_status.get(*c).is_none_or(|status| status.title.is_none()))
+            .cloned()
+            .collect();
+        self.collection_titles_requested.extend(untitled.iter().cloned());
+        untitled
     }
 
-    /// Selects the previous collection in the favorite collections list.
-    pub fn select_previous_collection(&mut self) {
-        let count = self.settings.favorite_collections.len();
-        if count == 0 {
+    /// Records the archive.org titles (identifier, title) of collections, keeping the selection.
+    pub fn apply_collection_titles(&mut self, titles: Vec<(String, String)>) {
+        if titles.is_empty() {
             return;
         }
-        let i = match self.collection_list_state.selected() {
-            Some(i) => {
-                if i == 0 { count - 1 } else { i - 1 }
-            }
-            None => 0,
-        };
-        self.collection_list_state.select(Some(i));
+        let selected = self.get_selected_collection().cloned();
+        for (collection, title) in titles {
+            self.collection_status.entry(collection).or_default().title = Some(title);
+        }
+        self.save_collection_status();
+        if let Some(collection) = selected {
+            self.select_collection_row(&collection);
+        }
+    }
+
+    /// Shows `collection` as `alias` in the collections pane, or by its title again for an
+    /// empty alias (or the title itself), keeping it selected.
+    pub fn set_collection_alias(&mut self, collection: &str, alias: &str) {
+        let alias = alias.trim();
+        let title = self.collection_status.get(collection).and_then(|status| status.title.clone());
+        if alias.is_empty() || alias == title.unwrap_or_else(|| saved_search::label(collection).into_owned()) {
+            self.settings.collection_aliases.remove(collection);
+        } else {
+            self.settings.collection_aliases.insert(collection.to_string(), alias.to_string());
+        }
+        self.select_collection_row(collection);
+    }
+
+    /// Groups the favorites are in, by name.
+    pub fn collection_group_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .settings
+            .favorite_collections
+            .iter()
+            .filter_map(|c| self.settings.collection_groups.get(c).map(String::as_str))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Moves the selection in the favorite collections list.
+    pub fn move_collection_selection(&mut self, motion: Motion) {
+        if let Some(i) = motion.apply(self.collection_list_state.selected(), self.collection_rows().len(), self.page_rows.collections) {
+            self.collection_list_state.select(Some(i));
+        }
     }
 
-    /// Gets the identifier of the currently selected collection, if any.
+    /// Gets the identifier of the currently selected collection, if any (none while a
+    /// group header is selected).
     pub fn get_selected_collection(&self) -> Option<&String> {
-        match self.collection_list_state.selected() {
-            Some(index) => self.settings.favorite_collections.get(index),
-            None => None,
+        let index = self.collection_list_state.selected()?;
+        let CollectionRow::Collection(name) = *self.collection_rows().get(index)? else { return None };
+        self.settings.favorite_collections.iter().find(|c| c.as_str() == name)
+    }
+
+    /// Name of the group whose header is selected, if one is.
+    pub fn get_selected_collection_group(&self) -> Option<String> {
+        let index = self.collection_list_state.selected()?;
+        match self.collection_rows().get(index)? {
+            CollectionRow::Group { name, .. } => Some(name.to_string()),
+            CollectionRow::Collection(_) => None,
         }
     }
 
+    /// Selects the row of `collection`, or nothing if it isn't shown.
+    fn select_collection_row(&mut self, collection: &str) {
+        let index = self.collection_rows().iter().position(|row| *row == CollectionRow::Collection(collection));
+        self.collection_list_state.select(index);
+    }
+
+    /// Keeps the selection at `index`, or on the last row if fewer are left.
+    fn clamp_collection_selection(&mut self, index: usize) {
+        let rows = self.collection_rows().len();
+        self.collection_list_state.select(if rows == 0 { None } else { Some(index.min(rows - 1)) });
+    }
+
     /// Removes the currently selected collection from the favorites list.
     /// Returns true if a collection was removed, false otherwise.
     pub fn remove_selected_collection(&mut self) -> bool {
-        if let Some(index) = self.collection_list_state.selected() {
-            if index < self.settings.favorite_collections.len() {
-                self.settings.favorite_collections.remove(index);
-                // Adjust selection if the removed item was the last one
-                let new_selection = if self.settings.favorite_collections.is_empty() {
-                    None
-                } else if index >= self.settings.favorite_collections.len() {
-                    // If removed last item, select the new last item
-                    Some(self.settings.favorite_collections.len() - 1)
-                } else {
-                    // Otherwise, keep selection at the same index
-                    Some(index)
-                };
-                self.collection_list_state.select(new_selection);
-                return true; // Indicate removal occurred
-            }
+        let (Some(index), Some(collection)) = (self.collection_list_state.selected(), self.get_selected_collection().cloned()) else {
+            return false; // Nothing, or a group header, is selected
+        };
+        self.settings.favorite_collections.retain(|c| c != &collection);
+        self.settings.collection_groups.remove(&collection);
+        self.settings.collection_aliases.remove(&collection);
+        if self.collection_group_filter.as_ref().is_some_and(|group| !self.collection_group_names().contains(&group.as_str())) {
+            self.collection_group_filter = None; // Its last collection is gone
         }
-        false // Indicate nothing was removed
+        self.clamp_collection_selection(index);
+        true
     }
 
     /// Adds a new collection identifier to the favorites list if it doesn't exist.
@@ -373,81 +1556,372 @@ impl App {
         let trimmed_id = identifier.trim().to_string();
         if !trimmed_id.is_empty() && !self.settings.favorite_collections.contains(&trimmed_id) {
             self.settings.favorite_collections.push(trimmed_id.clone());
-            self.settings.favorite_collections.sort(); // Keep the list sorted
+            self.collection_group_filter = None; // New favorites have no group yet
             // Select the newly added item
-            if let Some(index) = self.settings.favorite_collections.iter().position(|c| c == &trimmed_id) {
-                 self.collection_list_state.select(Some(index));
-            }
+            self.select_collection_row(&trimmed_id);
         }
     }
 
+    /// Swaps the selected collection with the one listed above (`up`) or below it in its
+    /// group, switching the pane to manual order. Returns whether it moved.
+    pub fn move_selected_collection(&mut self, up: bool) -> bool {
+        let Some(collection) = self.get_selected_collection().cloned() else { return false };
+        let group = self.settings.collection_groups.get(&collection);
+        let mut order: Vec<String> = self.ordered_favorites().into_iter().cloned().collect();
+        let Some(index) = order.iter().position(|c| *c == collection) else { return false };
+        let same_group = |c: &String| self.settings.collection_groups.get(c) == group;
+        let neighbour = if up {
+            order[..index].iter().rposition(same_group)
+        } else {
+            order[index + 1..].iter().position(same_group).map(|i| index + 1 + i)
+        };
+        let Some(neighbour) = neighbour else { return false };
+        order.swap(index, neighbour);
+        self.settings.favorite_collections = order;
+        self.settings.collection_order = CollectionOrder::Manual;
+        self.select_collection_row(&collection);
+        true
+    }
+
+    /// Switches the collections pane to the next order, keeping the selection.
+    pub fn cycle_collection_order(&mut self) {
+        let selected = self.get_selected_collection().cloned();
+        self.settings.collection_order = self.settings.collection_order.cycle();
+        if let Some(collection) = selected {
+            self.select_collection_row(&collection);
+        }
+    }
 
-    // --- Item List Navigation (Uses item_list_state) ---
+    /// Puts `collection` into `group`, or takes it out of its group for an empty name,
+    /// keeping it selected.
+    pub fn set_collection_group(&mut self, collection: &str, group: &str) {
+        let group = group.trim();
+        if group.is_empty() {
+            self.settings.collection_groups.remove(collection);
+        } else {
+            self.settings.collection_groups.insert(collection.to_string(), group.to_string());
+            self.collapsed_collection_groups.remove(group);
+        }
+        if self.collection_group_filter.as_deref().is_some_and(|filter| filter != group) {
+            self.collection_group_filter = None;
+        }
+        self.select_collection_row(collection);
+    }
 
-    pub fn select_next_item(&mut self) {
-        if self.items.is_empty() {
-            return;
+    /// Folds or unfolds the group whose header is selected. Returns whether one was.
+    pub fn toggle_selected_collection_group(&mut self) -> bool {
+        let Some(group) = self.get_selected_collection_group() else { return false };
+        if !self.collapsed_collection_groups.remove(&group) {
+            self.collapsed_collection_groups.insert(group);
         }
-        let i = match self.item_list_state.selected() {
-            Some(i) => {
-                if i >= self.items.len() - 1 {
-                    0
-                } else {
-                    i + 1
-                }
-            }
-            None => 0,
+        true
+    }
+
+    /// Limits the collections pane to the next group (all groups in turn, then none).
+    pub fn cycle_collection_group_filter(&mut self) {
+        let groups = self.collection_group_names();
+        let next = match self.collection_group_filter.as_deref() {
+            None => groups.first().copied(),
+            Some(current) => groups.iter().skip_while(|g| **g != current).nth(1).copied(),
         };
-        self.item_list_state.select(Some(i));
+        self.collection_group_filter = next.map(str::to_string);
+        self.clamp_collection_selection(0);
     }
 
-    pub fn select_previous_item(&mut self) {
-        if self.items.is_empty() {
-            return;
+
+    // --- Exclusion Rules ---
+
+    /// Adds an exclusion rule. Returns false if it already exists; errors on an invalid pattern.
+    pub fn add_exclusion(&mut self, kind: ExclusionKind, value: &str) -> Result<bool> {
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return Err(anyhow!("{} cannot be empty", kind));
         }
-        let i = match self.item_list_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.items.len() - 1
-                } else {
-                    i - 1
-                }
+        let list = match kind {
+            ExclusionKind::Identifier => &mut self.settings.excluded_identifiers,
+            ExclusionKind::Pattern => {
+                exclusions::validate_pattern(&value)?;
+                &mut self.settings.excluded_patterns
             }
-            None => 0,
         };
-        self.item_list_state.select(Some(i));
+        if list.contains(&value) {
+            return Ok(false);
+        }
+        list.push(value.clone());
+        list.sort();
+        self.refresh_exclusions();
+        let rules = exclusions::rules(&self.settings);
+        let index = rules.iter().position(|rule| rule.kind == kind && rule.value == value);
+        self.exclusion_list_state.select(index);
+        Ok(true)
     }
 
-    // --- File List Navigation ---
+    /// Removes the rule selected in the exclusion rules screen.
+    pub fn remove_selected_exclusion(&mut self) -> Option<ExclusionRule> {
+        let index = self.exclusion_list_state.selected()?;
+        let rule = exclusions::rules(&self.settings).into_iter().nth(index)?;
+        match rule.kind {
+            ExclusionKind::Identifier => self.settings.excluded_identifiers.retain(|v| v != &rule.value),
+            ExclusionKind::Pattern => self.settings.excluded_patterns.retain(|v| v != &rule.value),
+        }
+        self.refresh_exclusions();
+        let remaining = exclusions::rules(&self.settings).len();
+        self.exclusion_list_state.select(if remaining == 0 { None } else { Some(index.min(remaining - 1)) });
+        Some(rule)
+    }
 
-    /// Selects the next file in the file list view.
-    pub fn select_next_file(&mut self) {
-        let file_count = self.current_item_details.as_ref().map_or(0, |d| d.files.len());
-        if file_count == 0 {
-            return;
+    /// Adds `identifier` as an exact exclusion, or removes it if it is already one.
+    /// Returns whether the identifier is excluded afterwards.
+    pub fn toggle_identifier_exclusion(&mut self, identifier: &str) -> bool {
+        if self.settings.excluded_identifiers.iter().any(|id| id == identifier) {
+            self.settings.excluded_identifiers.retain(|id| id != identifier);
+            self.refresh_exclusions();
+            false
+        } else {
+            self.add_exclusion(ExclusionKind::Identifier, identifier).is_ok()
         }
-        let i = match self.file_list_state.selected() {
-            Some(i) => {
-                if i >= file_count - 1 { 0 } else { i + 1 }
+    }
+
+    // --- Item List Navigation (Uses item_list_state) ---
+
+    /// Items shown in the items pane, i.e. those passing [`App::item_status_filter`]
+    /// and [`App::item_filter`], with duplicate groups collapsed to their first item unless expanded.
+    pub fn visible_items(&self) -> Vec<&ArchiveDoc> {
+        self.visible_rows().into_iter().map(|row| row.doc).collect()
+    }
+
+    /// Rows of the items pane; `item_list_state` indexes this list.
+    pub fn visible_rows(&self) -> Vec<ItemRow<'_>> {
+        let filtered: Vec<&ArchiveDoc> = self
+            .items
+            .iter()
+            .filter(|doc| self.item_filter.matches(doc))
+            .filter(|doc| self.item_query.trim().is_empty() || filters::fuzzy_positions(&self.item_query, &item_search_text(doc)).is_some())
+            .filter(|doc| match self.item_status_filter {
+                ItemStatusFilter::All => true,
+                filter => filter.matches(self.library.status(&doc.identifier)),
+            })
+            .collect();
+        let single = |doc| ItemRow { doc, group_size: 1, is_variant: false, expanded: false };
+        if !self.group_duplicates {
+            return filtered.into_iter().map(single).collect();
+        }
+        let mut rows = Vec::with_capacity(filtered.len());
+        for group in grouping::group_indices(&filtered) {
+            let head = filtered[group[0]];
+            let expanded = group.len() > 1 && self.expanded_groups.contains(&head.identifier);
+            rows.push(ItemRow { doc: head, group_size: group.len(), is_variant: false, expanded });
+            if expanded {
+                rows.extend(group[1..].iter().map(|&i| ItemRow { doc: filtered[i], group_size: group.len(), is_variant: true, expanded: false }));
             }
-            None => 0,
-        };
-        self.file_list_state.select(Some(i));
+        }
+        rows
     }
 
-    /// Selects the previous file in the file list view.
-    pub fn select_previous_file(&mut self) {
-        let file_count = self.current_item_details.as_ref().map_or(0, |d| d.files.len());
-        if file_count == 0 {
-            return;
+    /// Turns duplicate grouping on or off, keeping the selected item selected if it is
+    /// still shown.
+    pub fn toggle_duplicate_grouping(&mut self) {
+        let selected = self.selected_item().map(|doc| doc.identifier.clone());
+        self.group_duplicates = !self.group_duplicates;
+        self.select_item_by_id(selected.as_deref());
+    }
+
+    /// Expands (`true`) or collapses the duplicate group of the selected row. Collapsing
+    /// from a variant selects the group's first item. Returns whether anything changed.
+    pub fn set_selected_group_expanded(&mut self, expand: bool) -> bool {
+        let Some(index) = self.item_list_state.selected() else { return false };
+        let rows = self.visible_rows();
+        let Some(row) = rows.get(index) else { return false };
+        if row.group_size < 2 {
+            return false;
+        }
+        // The group's first item is the nearest non-variant row at or above the selection
+        let Some(head) = rows[..=index].iter().rev().find(|r| !r.is_variant).map(|r| r.doc.identifier.clone()) else { return false };
+        let changed = if expand { self.expanded_groups.insert(head.clone()) } else { self.expanded_groups.remove(&head) };
+        if !expand {
+            self.select_item_by_id(Some(&head));
+        }
+        changed
+    }
+
+    /// Selects the row showing `identifier`, or the first row if it isn't shown.
+    fn select_item_by_id(&mut self, identifier: Option<&str>) {
+        let rows = self.visible_rows();
+        let index = identifier.and_then(|id| rows.iter().position(|row| row.doc.identifier == id));
+        let index = index.or((!rows.is_empty()).then_some(0));
+        self.item_list_state.select(index);
+    }
+
+    /// What the items pane currently lists, if anything.
+    pub fn current_listing(&self) -> Option<Listing> {
+        match (&self.search_query, &self.current_collection_name) {
+            (Some(query), _) => Some(Listing::Search(query.clone())),
+            (None, Some(collection)) => Some(Listing::Collection(collection.clone())),
+            (None, None) => None,
+        }
+    }
+
+    /// Where the user is, for the back/forward history: the item being viewed or the
+    /// listing in the items pane.
+    pub fn current_place(&self) -> Option<Place> {
+        match (&self.current_state, &self.viewing_item_id) {
+            (AppState::ViewingItem, Some(identifier)) => Some(Place::Item(identifier.clone())),
+            _ => self.current_listing().map(|listing| Place::Listing { listing, trail: self.collection_trail.clone() }),
+        }
+    }
+
+    /// Breadcrumb trail of the items pane, e.g. `fav-me > prelinger > ephemera`.
+    pub fn breadcrumb_path(&self) -> String {
+        self.collection_trail
+            .iter()
+            .map(|crumb| crumb.listing.to_string())
+            .chain(self.current_collection_name.as_deref().map(|name| saved_search::label(name).into_owned()))
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
+
+    /// Selects `identifier` in the items pane (or the first row if it isn't listed).
+    pub fn select_item(&mut self, identifier: &str) {
+        self.select_item_by_id(Some(identifier));
+    }
+
+    /// The item selected in the items pane.
+    pub fn selected_item(&self) -> Option<&ArchiveDoc> {
+        let index = self.item_list_state.selected()?;
+        self.visible_items().get(index).copied()
+    }
+
+    /// Replaces the live filter text, keeping the selected item selected if it still matches.
+    pub fn set_item_query(&mut self, query: String) {
+        let selected = self.selected_item().map(|doc| doc.identifier.clone());
+        self.item_query = query;
+        self.select_item_by_id(selected.as_deref());
+    }
+
+    /// Marks the selected item for a batch download, or unmarks it. Returns whether it is now marked.
+    pub fn toggle_marked_item(&mut self) -> bool {
+        let Some(id) = self.selected_item().map(|doc| doc.identifier.clone()) else { return false };
+        if self.marked_items.remove(&id) {
+            return false;
+        }
+        self.marked_items.insert(id)
+    }
+
+    /// Marks every item the items pane currently shows.
+    pub fn mark_visible_items(&mut self) {
+        let ids: Vec<String> = self.visible_items().iter().map(|doc| doc.identifier.clone()).collect();
+        self.marked_items.extend(ids);
+    }
+
+    /// Marked items in list order.
+    pub fn marked_item_docs(&self) -> Vec<&ArchiveDoc> {
+        self.items.iter().filter(|doc| self.marked_items.contains(&doc.identifier)).collect()
+    }
+
+    /// Switches the items pane to the next status filter and selects its first item.
+    pub fn cycle_item_status_filter(&mut self) {
+        self.sync_library_dir();
+        self.item_status_filter = self.item_status_filter.cycle();
+        let has_items = !self.visible_items().is_empty();
+        self.item_list_state.select(has_items.then_some(0));
+    }
+
+    /// Switches the mediatype filter to the next mediatype among the loaded items.
+    pub fn cycle_mediatype_filter(&mut self) {
+        let values = filters::mediatypes(&self.items);
+        self.item_filter.mediatype = filters::next_value(&values, self.item_filter.mediatype.as_deref());
+        self.select_item_by_id(None);
+    }
+
+    /// Switches the format filter to the next file format among the loaded items.
+    pub fn cycle_format_filter(&mut self) {
+        let values = filters::formats(&self.items);
+        self.item_filter.format = filters::next_value(&values, self.item_filter.format.as_deref());
+        self.select_item_by_id(None);
+    }
+
+    /// Moves the selection in the items pane.
+    pub fn move_item_selection(&mut self, motion: Motion) {
+        if let Some(i) = motion.apply(self.item_list_state.selected(), self.visible_items().len(), self.page_rows.items) {
+            self.item_list_state.select(Some(i));
         }
-        let i = match self.file_list_state.selected() {
-            Some(i) => {
-                if i == 0 { file_count - 1 } else { i - 1 }
+    }
+
+    /// Switches to the item view for `identifier`, showing its cached details if they are
+    /// recent enough (or whatever is cached while offline) and requesting them otherwise.
+    pub fn open_item(&mut self, identifier: String) {
+        self.viewing_item_id = Some(identifier.clone());
+        self.current_state = AppState::ViewingItem;
+        self.current_item_details = None; // Clear previous details
+        self.file_list_state = ListState::default(); // Reset file list selection
+        self.item_tab = ItemTab::Metadata;
+        self.review_index = 0;
+        self.related_list_state = ListState::default();
+        self.details_cached_at = None;
+        let cached = self.settings.download_directory.as_deref().and_then(|base_dir| offline::load_details(base_dir, &identifier).ok());
+        if let Some((details, cached_at)) = cached {
+            if self.network.is_offline() || offline::is_fresh(cached_at, self.settings.details_cache_hours, SystemTime::now()) {
+                self.details_cached_at = Some(cached_at);
+                self.show_item_details(details);
+                return;
             }
-            None => 0, // Select the first item if nothing was selected
-        };
-        self.file_list_state.select(Some(i));
+        }
+        if !self.network.allows_requests() {
+            self.error_message = Some(format!("Offline: the details of '{}' aren't cached. Press 'N' to go online.", identifier));
+            return;
+        }
+        self.is_loading_details = true; // Set flag
+        self.pending_action = Some(UpdateAction::FetchItemDetails);
+    }
+
+    /// Fetches the details of the item being viewed again, bypassing the cache.
+    pub fn refresh_item_details(&mut self) {
+        if self.viewing_item_id.is_some() {
+            self.is_loading_details = true;
+            self.pending_action = Some(UpdateAction::FetchItemDetails);
+        }
+    }
+
+    /// Shows the details of the item being viewed, selecting its first file and related item.
+    pub fn show_item_details(&mut self, details: ItemDetails) {
+        if self.thumbnail.as_ref().is_some_and(|t| t.identifier != details.identifier) {
+            self.thumbnail = None;
+        }
+        self.file_list_state.select((!details.files.is_empty()).then_some(0));
+        self.related_list_state.select((!details.related_identifiers.is_empty()).then_some(0));
+        self.current_item_details = Some(details);
+        self.error_message = None;
+    }
+
+    /// Moves to another review of the item being viewed.
+    pub fn move_review(&mut self, motion: Motion) {
+        let count = self.current_item_details.as_ref().map_or(0, |d| d.reviews.len());
+        if let Some(i) = motion.apply(Some(self.review_index), count, 1) {
+            self.review_index = i;
+        }
+    }
+
+    /// Moves the selection in the Related tab.
+    pub fn move_related_selection(&mut self, motion: Motion) {
+        let count = self.current_item_details.as_ref().map_or(0, |d| d.related_identifiers.len());
+        if let Some(i) = motion.apply(self.related_list_state.selected(), count, self.page_rows.item_view) {
+            self.related_list_state.select(Some(i));
+        }
+    }
+
+    /// Identifier selected in the Related tab, if any.
+    pub fn selected_related(&self) -> Option<&String> {
+        self.related_list_state.selected().and_then(|i| self.current_item_details.as_ref()?.related_identifiers.get(i))
+    }
+
+    // --- File List Navigation ---
+
+    /// Moves the selection in the file list view.
+    pub fn move_file_selection(&mut self, motion: Motion) {
+        let file_count = self.current_item_details.as_ref().map_or(0, |d| d.files.len());
+        if let Some(i) = motion.apply(self.file_list_state.selected(), file_count, self.page_rows.item_view) {
+            self.file_list_state.select(Some(i));
+        }
     }
 
     /// Gets the details of the currently selected file, if any.
@@ -458,42 +1932,31 @@ impl App {
         }
     }
 
-    /// Constructs the full download path for a given file.
-    /// Path structure: base_dir / item_id / filename
+    /// Constructs the full download path for a file of the item being viewed, the same
+    /// way downloads do (base_dir / [collection] / item_id / filename, or the folder a
+    /// library layout put the item in).
     /// Returns None if download directory is not set or item ID is missing.
     pub fn get_download_path_for_file(&self, file: &FileDetails) -> Option<PathBuf> {
-        match (
-            self.settings.download_directory.as_ref(),
-            self.viewing_item_id.as_ref(), // Item ID is sufficient
-        ) {
-            (Some(base_dir), Some(item_id)) => {
-                let mut path = PathBuf::from(base_dir);
-                // path.push(collection); // Removed collection from path
-                path.push(item_id);
-                path.push(&file.name);
-                Some(path)
-            }
-            _ => None, // Missing necessary info
-        }
-    }
-
-     /// Constructs the directory path for a given item.
-     /// Path structure: base_dir / item_id
-     /// Returns None if download directory is not set or item ID is missing.
-     pub fn get_download_path_for_item(&self) -> Option<PathBuf> {
-         match (
-             self.settings.download_directory.as_ref(),
-             self.viewing_item_id.as_ref(), // Item ID is sufficient
-         ) {
-             (Some(base_dir), Some(item_id)) => {
-                 let mut path = PathBuf::from(base_dir);
-                 // path.push(collection); // Removed collection from path
-                 path.push(item_id);
-                 Some(path)
-             }
-             _ => None, // Missing necessary info
-         }
-     }
+        let base_dir = self.settings.download_directory.as_deref()?;
+        let item_id = self.viewing_item_id.as_deref()?;
+        let policy = PathPolicy::from_settings(&self.settings);
+        if let Some(dir) = self.library.dir(item_id) {
+            return Some(paths::item_file_path(dir.to_path_buf(), &file.name, &policy));
+        }
+        Some(paths::download_path(base_dir, self.current_collection_name.as_deref(), item_id, &file.name, &policy))
+    }
+
+    /// Constructs the directory path for an item of the current collection
+    /// (base_dir / [collection] / item_id, or the folder a library layout put it in).
+    /// Returns None if download directory is not set.
+    pub fn get_download_path_for_item(&self, item_id: &str) -> Option<PathBuf> {
+        let base_dir = self.settings.download_directory.as_deref()?;
+        if let Some(dir) = self.library.dir(item_id) {
+            return Some(dir.to_path_buf());
+        }
+        let policy = PathPolicy::from_settings(&self.settings);
+        Some(paths::item_dir(base_dir, self.current_collection_name.as_deref(), item_id, &policy))
+    }
 
     /// Helper function to construct the path to the item cache file for a collection.
     /// Returns None if download directory or collection name is missing.
@@ -503,11 +1966,32 @@ impl App {
             .as_ref()
             .map(|base_dir| {
                 Path::new(base_dir)
-                    .join(".item_cache")
-                    .join(format!("{}.json", collection_name))
+                    .join(offline::CACHE_DIR)
+                    .join(format!("{}.json", saved_search::local_name(collection_name)))
             })
     }
 
+    /// Deletes the item cache of `collection_name` and the identifiers cached by collection
+    /// downloads, so its next listing is fetched anew.
+    pub fn forget_item_cache(&self, collection_name: &str) -> Result<()> {
+        let Some(base_dir) = self.settings.download_directory.as_deref() else { return Ok(()) };
+        let caches = self.get_item_cache_path(collection_name).into_iter().chain([download::identifier_cache_path(base_dir, collection_name)]);
+        for path in caches {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).context(format!("Failed to remove item cache file: {}", path.display()));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// When the item cache of `collection_name` was last written, if there is one.
+    pub fn item_cache_modified(&self, collection_name: &str) -> Option<SystemTime> {
+        offline::modified(&self.get_item_cache_path(collection_name)?)
+    }
+
     /// Attempts to load the item list for a collection from its cache file.
     /// Returns `Ok(Vec<ArchiveDoc>)` on success, or an `anyhow::Error` if the cache
     /// file doesn't exist, cannot be read, or contains invalid JSON.
@@ -529,8 +2013,17 @@ impl App {
         log::debug!("Read {} bytes from cache file.", json_data.len());
 
         log::debug!("Parsing JSON data from cache file...");
-        let items: Vec<ArchiveDoc> = serde_json::from_str(&json_data)
-            .context(format!("Failed to parse JSON from cache file: {}", cache_file_path.display()))?;
+        let items: Vec<ArchiveDoc> = match serde_json::from_str(&json_data) {
+            Ok(items) => items,
+            Err(e) => {
+                // Move the corrupt cache aside; the caller falls back to a network fetch,
+                // which rewrites the cache.
+                if let Err(backup_err) = fs_util::backup_corrupt_file(&cache_file_path) {
+                    log::error!("{:#}", backup_err);
+                }
+                return Err(anyhow!(e).context(format!("Failed to parse JSON from cache file: {}", cache_file_path.display())));
+            }
+        };
         log::debug!("Successfully parsed {} items from cache.", items.len());
 
         log::info!("Successfully loaded {} items from cache file: {}", items.len(), cache_file_path.display());
@@ -548,7 +2041,64 @@ impl App {
         self.items = new_items;
         log::debug!("Items list replaced. Total items now: {}", self.items.len());
 
-        // 2. Get necessary components for the path
+        // 2. Save under the current collection's name
+        let collection_name = self
+            .current_collection_name
+            .as_ref()
+            .context("Current collection name is not set in app state")?;
+        log::debug!("Collection name found: {}", collection_name);
+        self.save_items_to_cache(collection_name, &self.items)?;
+        let collection_name = collection_name.clone();
+        self.record_listing(&collection_name, self.items.len(), 0);
+        Ok(())
+    }
+
+    /// Records a fresh listing of `collection_name` with `items` items, `new_items` of
+    /// which nobody has seen yet, in the collection statuses.
+    fn record_listing(&mut self, collection_name: &str, items: usize, new_items: usize) {
+        let status = self.collection_status.entry(collection_name.to_string()).or_default();
+        status.items = items;
+        status.synced = Some(Utc::now().to_rfc3339());
+        status.new_items += new_items;
+        self.save_collection_status();
+    }
+
+    /// Records that `collection_name` was opened now, for the recently used order,
+    /// keeping the selection on the collection it was on.
+    pub fn mark_collection_opened(&mut self, collection_name: &str) {
+        let selected = self.get_selected_collection().cloned();
+        self.collection_status.entry(collection_name.to_string()).or_default().opened = Some(Utc::now().to_rfc3339());
+        self.save_collection_status();
+        if let Some(collection) = selected {
+            self.select_collection_row(&collection);
+        }
+    }
+
+    /// Shows `collection_name` as opened with `items` cached items: its new items are seen.
+    /// A collection listed before the statuses were recorded gets one from its cache.
+    pub fn mark_collection_seen(&mut self, collection_name: &str, items: usize) {
+        let cached_at = self.item_cache_modified(collection_name);
+        let status = self.collection_status.entry(collection_name.to_string()).or_default();
+        if status.synced.is_none() {
+            status.synced = cached_at.map(|at| chrono::DateTime::<Utc>::from(at).to_rfc3339());
+        } else if status.new_items == 0 && status.items == items {
+            return;
+        }
+        status.items = items;
+        status.new_items = 0;
+        self.save_collection_status();
+    }
+
+    fn save_collection_status(&self) {
+        let Some(base_dir) = self.settings.download_directory.as_deref() else { return };
+        if let Err(e) = collection_status::save(base_dir, &self.collection_status) {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    /// Writes `items` to the item cache of `collection_name` (used as the baseline by
+    /// later listings and collection sync).
+    pub fn save_items_to_cache(&self, collection_name: &str, items: &[ArchiveDoc]) -> Result<()> {
         log::debug!("Checking prerequisites for saving item cache...");
         let download_dir = self
             .settings
@@ -556,11 +2106,6 @@ impl App {
             .as_ref()
             .context("Download directory is not set in settings")?;
         log::debug!("Download directory found: {}", download_dir);
-        let collection_name = self
-            .current_collection_name
-            .as_ref()
-            .context("Current collection name is not set in app state")?;
-        log::debug!("Collection name found: {}", collection_name);
 
         // 3. Construct the cache file path using the helper
         let cache_file_path = self.get_item_cache_path(collection_name)
@@ -580,26 +2125,223 @@ impl App {
              return Err(anyhow!("Could not determine parent directory for cache file"));
         }
 
-        // 4. Serialize the *entire current* items list to JSON
-        log::debug!("Serializing {} items to JSON...", self.items.len());
-        let json_data = serde_json::to_string_pretty(&self.items)
+        let write_started = std::time::Instant::now();
+        // 4. Serialize the items list to JSON
+        log::debug!("Serializing {} items to JSON...", items.len());
+        let json_data = serde_json::to_string_pretty(items)
             .context("Failed to serialize item list to JSON")?;
         log::debug!("Serialization successful. JSON size: {} bytes", json_data.len());
 
         // 6. Write the JSON data to the file (overwrite)
         log::debug!("Writing JSON data to cache file: {}", cache_file_path.display());
-        fs::write(&cache_file_path, json_data).context(format!(
+        fs_util::atomic_write(&cache_file_path, json_data).context(format!(
             "Failed to write item cache file: {}",
             cache_file_path.display()
         ))?;
+        perf::record(Phase::CacheWrite, write_started.elapsed());
         log::debug!("Successfully wrote cache file.");
 
         log::info!( // Changed to info for successful save confirmation
             "Successfully saved {} items to cache file: {}",
-            self.items.len(),
+            items.len(),
             cache_file_path.display()
         );
 
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::SharedRateLimiter;
+    use governor::Quota;
+    use std::num::NonZeroU32;
+
+    fn test_app() -> App {
+        let quota = Quota::per_hour(NonZeroU32::new(u32::MAX).unwrap());
+        App::new(Arc::new(SharedRateLimiter::new(quota)))
+    }
+
+    #[test]
+    fn test_tick_drains_progress_in_one_batch() {
+        let mut app = test_app();
+        let (tx, rx) = mpsc::channel(1024);
+        app.attach_progress_receiver(rx);
+
+        for _ in 0..500 {
+            tx.try_send(DownloadProgress::BytesDownloaded(100)).unwrap();
+        }
+        tx.try_send(DownloadProgress::FileCompleted("a.flac".to_string())).unwrap();
+
+        // Nothing is applied until the tick
+        assert_eq!(app.total_bytes_downloaded, 0);
+        app.tick();
+        assert_eq!(app.total_bytes_downloaded, 50_000);
+        assert_eq!(app.files_downloaded_count, 1);
+        assert_eq!(app.download_status.as_deref(), Some("Done: a.flac"));
+
+        // Receiver is retained for subsequent ticks
+        tx.try_send(DownloadProgress::BytesDownloaded(1)).unwrap();
+        app.tick();
+        assert_eq!(app.total_bytes_downloaded, 50_001);
+    }
+
+    #[test]
+    fn test_file_progress_tracks_active_transfers() {
+        let mut app = test_app();
+        let key = transfers::transfer_key("item", "a.flac");
+        app.apply_download_progress(DownloadProgress::FileStarted { key: Arc::clone(&key), total: Some(1000), resumed: 100 });
+        app.apply_download_progress(DownloadProgress::FileBytes(Arc::clone(&key), 400));
+        assert_eq!(app.total_bytes_downloaded, 400, "Resumed bytes were counted by an earlier run");
+        let rows = app.active_transfers.rows(Instant::now());
+        assert_eq!((rows[0].name.as_str(), rows[0].done, rows[0].total), ("item/a.flac", 500, Some(1000)));
+
+        app.apply_download_progress(DownloadProgress::FileFinished(FileResult {
+            item_id: "item".to_string(),
+            name: "a.flac".to_string(),
+            outcome: crate::report::FileOutcome::Downloaded,
+            bytes: 900,
+            size_verified: Some(true),
+        }));
+        assert!(app.active_transfers.is_empty());
+    }
+
+    #[test]
+    fn test_tick_without_receiver_is_noop() {
+        let mut app = test_app();
+        app.tick();
+        assert_eq!(app.total_bytes_downloaded, 0);
+    }
+
+    #[test]
+    fn test_collections_queue_but_items_do_not_wait_for_them() {
+        use crate::session::DownloadJob;
+        use crate::settings::DownloadMode;
+        let job = |action| DownloadJob { action, collection: None, mode: DownloadMode::Direct, base_dir: "/fake".to_string(), preset: FilePreset::AllFiles, retry: None };
+        let mut app = test_app();
+        let running = app.session.record_start(job(DownloadAction::Collection("etree".to_string())));
+        assert!(app.is_bulk_download_running());
+        app.queue.push(job(DownloadAction::Collection("gd".to_string())));
+        assert!(app.next_queued_job().is_none(), "One collection at a time");
+
+        app.session.record_start(job(DownloadAction::ItemAllFiles("gd1977".to_string())));
+        app.session.record_finish(running);
+        assert!(!app.is_bulk_download_running(), "An item download doesn't hold up the queue");
+        assert_eq!(app.next_queued_job().map(|job| job.describe()).as_deref(), Some("collection gd"));
+    }
+
+    #[test]
+    fn test_transient_failures_are_scheduled_for_retry() {
+        let mut app = test_app();
+        app.settings.retry_max_attempts = 2;
+        let job = DownloadJob { action: DownloadAction::ItemAllFiles("gd1977".to_string()), collection: None, mode: crate::settings::DownloadMode::Direct, base_dir: "/fake".to_string(), preset: FilePreset::AllFiles, retry: None };
+        app.apply_download_progress(DownloadProgress::RetryLater(job, "status 503".to_string()));
+        assert_eq!(app.retries.len(), 1);
+        assert_eq!(app.download_status.as_deref(), Some("Will retry item gd1977 in 1m (attempt 2/2)"));
+
+        // The retry fails too; that was the last attempt
+        let retried = app.retries.take_due(Utc::now() + chrono::Duration::minutes(1)).remove(0);
+        app.apply_download_progress(DownloadProgress::RetryLater(retried, "status 503".to_string()));
+        assert!(app.retries.is_empty());
+        assert_eq!(app.download_status.as_deref(), Some("Gave up on item gd1977 after 2 attempts"));
+    }
+
+    #[test]
+    fn test_restricted_files_are_counted_and_summarised() {
+        let mut app = test_app();
+        app.apply_download_progress(DownloadProgress::FileRestricted("lending_item".to_string(), "book.epub".to_string()));
+        assert_eq!(app.restricted_files_count, 1);
+        assert_eq!(app.files_downloaded_count, 1);
+        assert!(app.download_status.as_deref().unwrap().contains("lending_item/book.epub"));
+
+        app.apply_download_progress(DownloadProgress::CollectionCompleted(3, 0));
+        assert!(app.download_status.as_deref().unwrap().ends_with("1 restricted files skipped."));
+    }
+
+    #[test]
+    fn test_corrupt_item_cache_is_backed_up_and_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.current_collection_name = Some("coll".to_string());
+
+        app.set_and_save_items(Vec::new()).unwrap();
+        assert!(app.load_items_from_cache("coll").unwrap().is_empty());
+
+        let cache_path = dir.path().join(".item_cache").join("coll.json");
+        fs::write(&cache_path, "[{\"identifier\": ").unwrap();
+        assert!(app.load_items_from_cache("coll").is_err());
+        assert!(!cache_path.exists(), "Corrupt cache should be moved aside");
+        // A later save regenerates the cache in place
+        app.set_and_save_items(Vec::new()).unwrap();
+        assert!(app.load_items_from_cache("coll").is_ok());
+    }
+    #[test]
+    fn test_sync_listing_queues_new_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.settings.excluded_identifiers = vec!["unwanted".to_string()];
+        app.refresh_exclusions();
+        let docs = |ids: &[&str]| -> Vec<ArchiveDoc> { ids.iter().map(|id| ArchiveDoc { identifier: id.to_string(), ..Default::default() }).collect() };
+
+        // The first listing only becomes the baseline
+        assert_eq!(app.apply_sync_listing("coll", docs(&["a", "b"])).unwrap(), 0);
+        assert!(app.queue.is_empty());
+
+        assert_eq!(app.apply_sync_listing("coll", docs(&["a", "b", "c", "unwanted"])).unwrap(), 1);
+        let job = app.queue.pop_ready().unwrap();
+        assert_eq!(job.describe(), "item c");
+        assert_eq!(job.collection.as_deref(), Some("coll"));
+        assert_eq!(app.load_items_from_cache("coll").unwrap().len(), 4);
+        assert_eq!(app.apply_sync_listing("coll", docs(&["a", "b", "c", "unwanted"])).unwrap(), 0);
+
+        // The collections pane counts the new item until the collection is opened
+        let status = &app.collection_status["coll"];
+        assert_eq!((status.items, status.new_items), (4, 1));
+        assert!(status.synced.is_some());
+        assert_eq!(collection_status::load(dir.path().to_str().unwrap())["coll"], *status);
+        app.mark_collection_seen("coll", 4);
+        assert_eq!(app.collection_status["coll"].new_items, 0);
+    }
+
+    #[test]
+    fn test_verify_result_is_summarised() {
+        let mut app = test_app();
+        let checks = vec![("a.flac".to_string(), FileCheck::Ok), ("b.flac".to_string(), FileCheck::Missing)];
+        app.apply_verify_result("item", Ok(checks.clone()));
+        assert_eq!(app.download_status.as_deref(), Some("Verified item: 1 file(s) OK, 1 not downloaded"));
+        assert_eq!(app.error_message, None);
+
+        let mismatch = FileCheck::Mismatch { expected: "md5 1".to_string(), actual: "md5 2".to_string() };
+        app.apply_verify_result("item", Ok([checks, vec![("c.flac".to_string(), mismatch)]].concat()));
+        assert_eq!(app.error_message.as_deref(), Some("item: 1 file(s) failed verification: c.flac"));
+    }
+    #[test]
+    fn test_item_pages_fill_the_list_and_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.current_collection_name = Some("coll".to_string());
+        app.is_loading = true;
+        let page = |ids: &[&str], last: bool| ItemPage {
+            docs: ids.iter().map(|id| ArchiveDoc { identifier: id.to_string(), ..Default::default() }).collect(),
+            total: Some(3),
+            last,
+        };
+
+        app.apply_item_page("coll", page(&["a", "b"], false)).unwrap();
+        assert_eq!(app.items.len(), 2);
+        assert_eq!(app.item_list_state.selected(), Some(0));
+        assert!(app.is_loading);
+        assert!(app.load_items_from_cache("coll").is_err(), "Partial listings aren't cached");
+
+        app.apply_item_page("other", page(&["x"], true)).unwrap(); // Stale page of another collection
+        app.apply_item_page("coll", page(&["c"], true)).unwrap();
+        assert!(!app.is_loading);
+        assert_eq!(app.total_items_found, Some(3));
+        assert_eq!(app.load_items_from_cache("coll").unwrap().len(), 3);
+    }
+}
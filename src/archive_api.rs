@@ -1,14 +1,62 @@
-use anyhow::{anyhow, Result}; // Removed unused Context
+use anyhow::{anyhow, bail, Context, Result};
 use log::{debug, error, info, warn}; // Import log macros
 use reqwest::{Client, StatusCode}; // Import StatusCode
 use serde::{Deserialize, Serialize}; // Added Serialize
-use std::collections::HashMap; // Removed unused sync::Arc
+use regex::Regex;
+use std::{collections::HashMap, sync::{OnceLock, RwLock}}; // Removed unused sync::Arc
 // Removed mpsc import as FetchAllResult is removed
 use tokio::time::{sleep, Duration as TokioDuration}; // Import sleep and Tokio Duration for retries
 use crate::app::AppRateLimiter; // Use the type alias from app.rs
+use crate::perf::{self, Phase};
+use crate::rate_control;
+use crate::saved_search;
+
+/// Root of the public archive.org API. Can be overridden (e.g. by the mock server) via [`set_base_url`].
+pub const DEFAULT_BASE_URL: &str = "https://archive.org";
+const ADVANCED_SEARCH_PATH: &str = "/advancedsearch.php";
+const SCRAPE_PATH: &str = "/services/search/v1/scrape";
+const METADATA_PATH: &str = "/metadata/";
+const DOWNLOAD_PATH: &str = "/download/";
+const DETAILS_PATH: &str = "/details/";
+const THUMBNAIL_PATH: &str = "/services/img/";
+
+/// Process-wide override of [`DEFAULT_BASE_URL`].
+static BASE_URL_OVERRIDE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Points all API and download requests at a different host (e.g. `http://127.0.0.1:8080`).
+/// Used by the `--mock` mode and tests; a trailing slash is ignored.
+pub fn set_base_url(url: &str) {
+    let trimmed = url.trim_end_matches('/').to_string();
+    info!("Using archive.org base URL override: {}", trimmed);
+    if let Ok(mut guard) = BASE_URL_OVERRIDE.write() {
+        *guard = Some(trimmed);
+    }
+}
+
+/// Returns the base URL currently used for archive.org requests.
+pub fn base_url() -> String {
+    BASE_URL_OVERRIDE
+        .read()
+        .ok()
+        .and_then(|guard| guard.clone())
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+}
+
+/// Builds the URL of a file inside an item (`{base}/download/{item}/{file}`).
+pub fn download_url(item_id: &str, file_name: &str) -> String {
+    format!("{}{}{}/{}", base_url(), DOWNLOAD_PATH, item_id, file_name)
+}
+
+/// Builds the URL of an item's page (`{base}/details/{item}`).
+pub fn details_url(item_id: &str) -> String {
+    format!("{}{}{}", base_url(), DETAILS_PATH, item_id)
+}
 
-const ADVANCED_SEARCH_URL: &str = "https://archive.org/advancedsearch.php";
-const METADATA_URL_BASE: &str = "https://archive.org/metadata/";
+/// Builds the URL of an item's thumbnail (`{base}/services/img/{item}`): its cover art, or
+/// the image archive.org picked for it.
+pub fn thumbnail_url(item_id: &str) -> String {
+    format!("{}{}{}", base_url(), THUMBNAIL_PATH, item_id)
+}
 
 // --- Structs for Bulk Search API (JSONP response) ---
 
@@ -32,14 +80,73 @@ struct JsonpResponseContent {
 }
 
 
+/// One page of the scrape API (`/services/search/v1/scrape`).
+#[derive(Deserialize, Debug)]
+struct ScrapeResponse {
+    #[serde(default)]
+    items: Vec<ArchiveDoc>,
+    /// Total number of matches (sent with every page).
+    total: Option<usize>,
+    /// Cursor for the next page; absent on the last page.
+    cursor: Option<String>,
+    /// Set instead of `items` when the query is rejected.
+    error: Option<String>,
+}
+
+
 // --- Structs for Item List and Details ---
 
-#[derive(Deserialize, Serialize, Debug, Clone)] // Added Serialize
+#[derive(Deserialize, Serialize, Debug, Clone, Default)] // Added Serialize
 pub struct ArchiveDoc {
     pub identifier: String,
-    // Add other fields you might need, e.g., title, description
-    // pub title: Option<String>,
-    // Consider adding other useful fields like 'title' if needed for the list view
+    /// Total size of the item's files in bytes, as reported by the search index.
+    #[serde(default, deserialize_with = "deserialize_optional_size")]
+    pub item_size: Option<u64>,
+    /// Item title, used to group duplicate uploads in the items pane.
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub title: Option<String>,
+    /// Item mediatype (`audio`, `movies`, `texts`, ...), for the items pane filter.
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub mediatype: Option<String>,
+    /// Formats of the item's files, as listed by the search index.
+    #[serde(default, deserialize_with = "deserialize_text_list")]
+    pub format: Vec<String>,
+}
+
+/// Accepts sizes sent as numbers or numeric strings (the search index uses both).
+fn deserialize_optional_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::Number(n)) => n.as_u64(),
+        Some(serde_json::Value::String(s)) => s.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+/// Accepts a string or a list of strings (first entry), as multi-valued fields are sent.
+fn deserialize_optional_text<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(s)) => Some(s),
+        Some(serde_json::Value::Array(values)) => values.into_iter().find_map(|v| v.as_str().map(str::to_string)),
+        _ => None,
+    })
+}
+
+/// Accepts a list of strings or a single string.
+fn deserialize_text_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(s)) => vec![s],
+        Some(serde_json::Value::Array(values)) => values.into_iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+        _ => Vec::new(),
+    })
 }
 
 // --- Structs for Item Metadata Endpoint (metadata/{identifier}) ---
@@ -52,6 +159,12 @@ pub struct ItemMetadataResponse {
     pub files: Option<serde_json::Value>,
     pub server: Option<String>, // Server hosting the files
     pub dir: Option<String>,    // Directory path on the server
+    pub d1: Option<String>,     // Primary datanode holding a copy
+    pub d2: Option<String>,     // Secondary datanode holding a copy
+    #[serde(default)]
+    pub workable_servers: Vec<String>, // Datanodes currently able to serve the item
+    #[serde(default)]
+    pub reviews: Vec<serde_json::Value>, // User reviews; malformed entries are skipped
     // Add other top-level fields if needed (e.g., reviews, related)
 }
 
@@ -83,25 +196,140 @@ pub struct FileDetailsInternal {
     pub format: Option<String>, // e.g., "JPEG", "MP3", "JSON"
     pub size: Option<String>,   // Size is often a string, parse later if needed
     pub md5: Option<String>,
-    // Add other file fields if needed (e.g., length, height, width)
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub track: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub length: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub album: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub artist: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub creator: Option<String>,
+    // Add other file fields if needed (e.g., height, width)
     #[serde(flatten)]
     pub _extra: HashMap<String, serde_json::Value>, // Prefixed with _
 }
 
+/// archive.org marks files of lending/stream-only items with `"private": "true"`.
+fn is_private_file(extra: &HashMap<String, serde_json::Value>) -> bool {
+    extra.get("private").is_some_and(is_true)
+}
+
+/// Interprets metadata flags, which archive.org sends as `"true"` strings or booleans.
+fn is_true(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Bool(b) => *b,
+        serde_json::Value::String(s) => s.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+/// A user review of an item.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Review {
+    pub title: Option<String>,
+    pub body: String,
+    pub reviewer: Option<String>,
+    pub date: Option<String>,
+    /// Rating out of 5.
+    pub stars: Option<u8>,
+}
+
+/// Reads one entry of the metadata `reviews` array (stars come as strings or numbers).
+fn parse_review(value: &serde_json::Value) -> Option<Review> {
+    let text = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty()).map(String::from);
+    let stars = value.get("stars").and_then(|v| v.as_u64().or_else(|| v.as_str()?.trim().parse().ok()));
+    Some(Review {
+        title: text("reviewtitle"),
+        body: text("reviewbody")?,
+        reviewer: text("reviewer"),
+        date: text("reviewdate"),
+        stars: stars.map(|s| s.min(5) as u8),
+    })
+}
+
+/// Other items `identifier` points to: `archive.org/details/...` links in its description
+/// and the identifiers or links in its `relation` field, in order, without duplicates.
+fn related_identifiers(identifier: &str, description: Option<&str>, relation: Option<&serde_json::Value>) -> Vec<String> {
+    static DETAILS_LINK: OnceLock<Regex> = OnceLock::new();
+    let link = DETAILS_LINK.get_or_init(|| Regex::new(r"archive\.org/details/([A-Za-z0-9][A-Za-z0-9._-]*)").expect("valid regex"));
+    let relations: Vec<&str> = match relation {
+        Some(serde_json::Value::String(s)) => vec![s.as_str()],
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let mut related: Vec<String> = Vec::new();
+    let mut add = |candidate: &str| {
+        if candidate != identifier && !related.iter().any(|r| r == candidate) {
+            related.push(candidate.to_string());
+        }
+    };
+    let links = |text: &str| -> Vec<String> {
+        link.captures_iter(text).filter_map(|c| c.get(1)).map(|m| m.as_str().trim_end_matches('.').to_string()).collect()
+    };
+    for found in links(description.unwrap_or_default()) {
+        add(&found);
+    }
+    for value in relations {
+        let found = links(value);
+        let bare = value.trim();
+        if found.is_empty() && !bare.is_empty() && bare.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)) {
+            add(bare);
+        }
+        found.iter().for_each(|f| add(f));
+    }
+    related
+}
+
+/// Item directory URLs on each datanode listed in the metadata, without duplicates.
+fn mirror_base_urls(raw: &ItemMetadataResponse) -> Vec<String> {
+    let Some(dir) = raw.dir.as_deref() else { return Vec::new() };
+    let mut servers: Vec<&str> = Vec::new();
+    for server in raw.server.iter().chain(&raw.d1).chain(&raw.d2).chain(&raw.workable_servers) {
+        if !server.is_empty() && !servers.contains(&server.as_str()) {
+            servers.push(server);
+        }
+    }
+    servers.into_iter().map(|server| format!("https://{}/{}", server, dir.trim_start_matches('/'))).collect()
+}
+
 /// Final structure representing a file, used within ItemDetails.
-/// This is constructed manually, not directly deserialized.
-#[derive(Debug, Clone, Default)]
+/// This is constructed manually, not directly deserialized (serde is for the session journal).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct FileDetails {
     pub name: String, // The actual filename
     pub source: Option<String>,
     pub format: Option<String>,
     pub size: Option<String>,
     pub md5: Option<String>,
+    /// Access-restricted (lending or stream-only); downloadable only by entitled accounts.
+    #[serde(default)]
+    pub private: bool,
+    /// Track number of audio files, e.g. `3` or `03/12`.
+    #[serde(default)]
+    pub track: Option<String>,
+    /// Track title of audio files.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Playing time, in seconds (`215.43`) or as `mm:ss`.
+    #[serde(default)]
+    pub length: Option<String>,
+    /// Album of audio files.
+    #[serde(default)]
+    pub album: Option<String>,
+    /// Performer of audio files (`artist`, or else `creator`, of the file entry).
+    #[serde(default)]
+    pub artist: Option<String>,
 }
 
 
 /// A processed structure holding the relevant details for display.
-#[derive(Debug, Clone, Default)]
+/// Serialized for the offline details cache (see [`crate::offline`]).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ItemDetails {
     pub identifier: String,
     pub title: Option<String>,
@@ -113,6 +341,14 @@ pub struct ItemDetails {
     pub mediatype: Option<String>, // Added mediatype field
     pub files: Vec<FileDetails>, // Store the list of files
     pub download_base_url: Option<String>, // Constructed base URL for downloads
+    /// The item's directory on every datanode holding a copy (`server` first, then `d1`,
+    /// `d2` and the other workable servers); failed file downloads are retried there.
+    pub mirror_base_urls: Vec<String>,
+    pub reviews: Vec<Review>,
+    /// Other items this one links to (see [`related_identifiers`]).
+    pub related_identifiers: Vec<String>,
+    /// The item is lending/stream-only; some or all files need an entitled login.
+    pub access_restricted: bool,
 }
 
 /// Specific errors that can occur during `fetch_item_details`.
@@ -156,39 +392,184 @@ impl std::error::Error for FetchDetailsError {
 
 // --- Constants ---
 // Removed ROWS_PER_PAGE
-const BULK_ROWS: usize = 1_000_000; // Fetch up to 1 million rows in one go
+const SCRAPE_PAGE_SIZE: usize = 10_000; // Largest page the scrape API serves
+const SCRAPE_FIELDS: &str = "identifier,item_size,title,mediatype,format"; // What the list and forecast need
+const SEARCH_ROWS: usize = 500; // Free-text searches list the most relevant results only
 const MAX_FETCH_RETRIES: u32 = 3; // Max retries for network/server errors
 
 // --- API Fetch Functions ---
 
-/// Fetches ALL item identifiers for a given collection name from Archive.org in a single bulk request.
-///
-/// Uses the advanced search API with JSONP output format and trims the wrapper.
+/// Fetches ALL items of a collection, following the scrape API's cursor page by page.
+/// Returns the items and the total number reported by the API.
 pub async fn fetch_collection_items_bulk(
     client: &Client,
     collection_name: &str,
+    filter: Option<&str>,
     rate_limiter: AppRateLimiter, // Added rate limiter parameter
 ) -> Result<(Vec<ArchiveDoc>, usize)> {
-    info!("Fetching collection items BULK for '{}', rows {}", collection_name, BULK_ROWS);
-    let query = format!("collection:\"{}\"", collection_name); // Ensure collection name is quoted
+    let mut scrape = CollectionScrape::new(collection_name, filter);
+    let mut docs = Vec::new();
+    while let Some(page) = scrape.next_page(client, &rate_limiter).await? {
+        docs.extend(page);
+    }
+    let total_found = scrape.total().unwrap_or(docs.len());
+    info!("Fetched {} items (total reported: {}) for collection '{}'", docs.len(), total_found, collection_name);
+    Ok((docs, total_found))
+}
+
+/// Cursor-paginated listing of a collection through the scrape API, which (unlike
+/// advancedsearch) has no cap on how deep it pages. Call [`CollectionScrape::next_page`]
+/// until it returns `None`.
+#[derive(Debug)]
+pub struct CollectionScrape {
+    collection_name: String,
+    query: String,
+    cursor: Option<String>,
+    total: Option<usize>,
+    finished: bool,
+}
+
+impl CollectionScrape {
+    /// Lists `collection_name` (or a saved search's results, see [`crate::saved_search`]), narrowed by the extra search clauses of `filter` (see
+    /// [`crate::fetch_filter`]) if given.
+    pub fn new(collection_name: &str, filter: Option<&str>) -> Self {
+        let mut query = saved_search::listing_query(collection_name);
+        if let Some(filter) = filter {
+            query.push_str(&format!(" AND {}", filter));
+        }
+        Self { collection_name: collection_name.to_string(), query, cursor: None, total: None, finished: false }
+    }
+
+    /// Total number of items in the collection, known after the first page.
+    pub fn total(&self) -> Option<usize> {
+        self.total
+    }
+
+    /// Whether the last page has been fetched.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Fetches the next page of items, or returns `None` once the listing is complete.
+    pub async fn next_page(&mut self, client: &Client, rate_limiter: &AppRateLimiter) -> Result<Option<Vec<ArchiveDoc>>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let label = format!("collection '{}'", self.collection_name);
+        let page = fetch_scrape_page(client, &self.query, self.cursor.as_deref(), &label, rate_limiter).await?;
+        if let Some(error) = page.error {
+            bail!("Scrape API rejected the listing of {}: {}", label, error);
+        }
+        debug!("Scrape page of {}: {} items (total {:?})", label, page.items.len(), page.total);
+        self.total = page.total.or(self.total);
+        self.cursor = page.cursor;
+        self.finished = self.cursor.is_none();
+        Ok(Some(page.items))
+    }
+}
+
+/// Fetches one scrape page of `query`, continuing from `cursor`. Network and server
+/// errors are retried with backoff.
+async fn fetch_scrape_page(
+    client: &Client,
+    query: &str,
+    cursor: Option<&str>,
+    label: &str,
+    rate_limiter: &AppRateLimiter,
+) -> Result<ScrapeResponse> {
+    let mut params = vec![("q", query.to_string()), ("fields", SCRAPE_FIELDS.to_string()), ("count", SCRAPE_PAGE_SIZE.to_string())];
+    if let Some(cursor) = cursor {
+        params.push(("cursor", cursor.to_string()));
+    }
+    let mut attempt = 1;
+    loop {
+        rate_limiter.until_ready().await;
+        let fetch_started = std::time::Instant::now();
+        let (error, throttled) = match client.get(format!("{}{}", base_url(), SCRAPE_PATH)).query(&params).send().await {
+            Ok(response) if response.status().is_success() => {
+                let body = response.text().await.context(format!("Failed to read scrape page for {}", label))?;
+                perf::record(Phase::SearchFetch, fetch_started.elapsed());
+                return perf::time(Phase::SearchParse, || serde_json::from_str(&body)).context(format!("Failed to parse scrape page for {}", label));
+            }
+            Ok(response) if rate_control::is_throttling(response.status()) => {
+                rate_limiter.throttled(rate_control::retry_after(&response));
+                (anyhow!("Scrape request for {} throttled with status: {}", label, response.status()), true)
+            }
+            Ok(response) if !response.status().is_server_error() => {
+                bail!("Scrape request for {} failed with status: {}", label, response.status());
+            }
+            Ok(response) => (anyhow!("Scrape request for {} failed with status: {}", label, response.status()), false),
+            Err(e) => (anyhow!(e).context(format!("Failed to send scrape request for {}", label)), false),
+        };
+        if attempt >= MAX_FETCH_RETRIES {
+            error!("{:#}", error);
+            return Err(error);
+        }
+        if throttled {
+            warn!("{:#} (attempt {}/{}); retrying once the rate limiter allows", error, attempt, MAX_FETCH_RETRIES);
+        } else {
+            let delay_secs = 1 << (attempt - 1); // Exponential backoff: 1s, 2s
+            warn!("{:#} (attempt {}/{}); retrying in {} seconds...", error, attempt, MAX_FETCH_RETRIES, delay_secs);
+            sleep(TokioDuration::from_secs(delay_secs)).await;
+        }
+        attempt += 1;
+    }
+}
+
+/// Runs an arbitrary advancedsearch query (e.g. `title:(dead) AND mediatype:audio`),
+/// returning the first [`SEARCH_ROWS`] matches and the total number found.
+pub async fn search_items(client: &Client, query: &str, rate_limiter: AppRateLimiter) -> Result<(Vec<ArchiveDoc>, usize)> {
+    fetch_advanced_search(client, query, &format!("search '{}'", query), SEARCH_ROWS, rate_limiter).await
+}
+
+/// Identifiers per request of [`fetch_titles`], keeping the query URL short.
+const TITLE_BATCH: usize = 50;
+
+/// Fetches the titles of `identifiers` (collections or items) from the search index, a
+/// request per [`TITLE_BATCH`] identifiers. Identifiers without a title are left out.
+pub async fn fetch_titles(client: &Client, identifiers: &[String], rate_limiter: AppRateLimiter) -> Result<Vec<(String, String)>> {
+    let mut titles = Vec::new();
+    for batch in identifiers.chunks(TITLE_BATCH) {
+        let quoted: Vec<String> = batch.iter().map(|identifier| format!("\"{}\"", identifier)).collect();
+        let query = format!("identifier:({})", quoted.join(" OR "));
+        let (docs, _) = fetch_advanced_search(client, &query, "collection titles", batch.len(), rate_limiter.clone()).await?;
+        titles.extend(docs.into_iter().filter_map(|doc| Some((doc.identifier, doc.title?))));
+    }
+    Ok(titles)
+}
+
+/// Fetches up to `rows` results of `query` in one request; `label` names the request in
+/// logs and errors.
+async fn fetch_advanced_search(
+    client: &Client,
+    query: &str,
+    label: &str,
+    rows: usize,
+    rate_limiter: AppRateLimiter,
+) -> Result<(Vec<ArchiveDoc>, usize)> {
+    info!("Fetching items BULK for {}, rows {}", label, rows);
     let mut last_error: Option<anyhow::Error> = None;
 
     for attempt in 1..=MAX_FETCH_RETRIES {
-        debug!("Attempting bulk fetch for '{}', attempt {}/{}", collection_name, attempt, MAX_FETCH_RETRIES);
+        debug!("Attempting bulk fetch for {}, attempt {}/{}", label, attempt, MAX_FETCH_RETRIES);
 
         // --- Wait for Rate Limiter (inside retry loop) ---
-        debug!("Waiting for rate limit permit for bulk collection items: {}", collection_name);
+        debug!("Waiting for rate limit permit for {}", label);
         rate_limiter.until_ready().await;
-        debug!("Acquired rate limit permit for bulk collection items: {}", collection_name);
+        debug!("Acquired rate limit permit for {}", label);
         // --- Rate Limit Permit Acquired ---
 
         // Construct request builder inside the loop for retries
         let request_builder = client
-            .get(ADVANCED_SEARCH_URL)
+            .get(format!("{}{}", base_url(), ADVANCED_SEARCH_PATH))
             .query(&[
-                ("q", query.as_str()),
-                ("fl[]", "identifier"), // Request only the identifier field
-                ("rows", &BULK_ROWS.to_string()),
+                ("q", query),
+                ("fl[]", "identifier"), // Request only the fields the list and forecast need
+                ("fl[]", "item_size"),
+                ("fl[]", "title"),
+                ("fl[]", "mediatype"),
+                ("fl[]", "format"),
+                ("rows", &rows.to_string()),
                 ("output", "json"),
                 ("callback", "callback"), // Use the JSONP callback parameter
                 // ("page", "1"), // Page/start usually not needed with huge rows, but API might require it? Test without first.
@@ -198,6 +579,7 @@ pub async fn fetch_collection_items_bulk(
 
         match request_builder.try_clone() {
             Some(cloned_builder) => {
+                let fetch_started = std::time::Instant::now();
                 match cloned_builder.send().await {
                     Ok(response) => {
                         let status = response.status();
@@ -205,6 +587,7 @@ pub async fn fetch_collection_items_bulk(
                             // Read the body as text first to handle JSONP wrapper
                             match response.text().await {
                                 Ok(body_text) => {
+                                    perf::record(Phase::SearchFetch, fetch_started.elapsed());
                                     // Trim the "callback(" prefix and ")" suffix
                                     let trimmed_body = body_text
                                         .strip_prefix("callback(")
@@ -212,15 +595,16 @@ pub async fn fetch_collection_items_bulk(
                                         .unwrap_or(&body_text); // Fallback to original text if trimming fails
 
                                     // Parse the trimmed JSON
-                                    match serde_json::from_str::<JsonpResponseWrapper>(trimmed_body) {
+                                    let parsed = perf::time(Phase::SearchParse, || serde_json::from_str::<JsonpResponseWrapper>(trimmed_body));
+                                    match parsed {
                                         Ok(parsed_jsonp) => {
                                             let docs = parsed_jsonp.response.docs;
                                             let total_found = parsed_jsonp.response.num_found;
-                                            info!("Successfully fetched BULK {} items (total reported: {}) for collection '{}'",
-                                                  docs.len(), total_found, collection_name);
+                                            info!("Successfully fetched BULK {} items (total reported: {}) for {}",
+                                                  docs.len(), total_found, label);
                                             // Basic sanity check
                                             if docs.len() > total_found {
-                                                warn!("Fetched more items ({}) than reported total ({}) for collection '{}'. Using fetched count.", docs.len(), total_found, collection_name);
+                                                warn!("Fetched more items ({}) than reported total ({}) for {}. Using fetched count.", docs.len(), total_found, label);
                                                 // Optionally return docs.len() as the total? Or stick with reported total?
                                                 // Let's return the actual docs and the reported total for now.
                                             }
@@ -228,8 +612,8 @@ pub async fn fetch_collection_items_bulk(
                                         }
                                         Err(e) => {
                                             let parse_err = anyhow!(e).context(format!(
-                                                "Failed to parse trimmed JSONP response for bulk collection items '{}' (Attempt {}/{})",
-                                                collection_name, attempt, MAX_FETCH_RETRIES
+                                                "Failed to parse trimmed JSONP response for {} (Attempt {}/{})",
+                                                label, attempt, MAX_FETCH_RETRIES
                                             ));
                                             error!("Trimmed Body: '{}'", trimmed_body); // Log the body that failed parsing
                                             error!("{}", parse_err);
@@ -241,8 +625,8 @@ pub async fn fetch_collection_items_bulk(
                                 }
                                 Err(e) => {
                                     let body_err = anyhow!(e).context(format!(
-                                        "Failed to read response body for bulk collection items '{}' (Attempt {}/{})",
-                                        collection_name, attempt, MAX_FETCH_RETRIES
+                                        "Failed to read response body for {} (Attempt {}/{})",
+                                        label, attempt, MAX_FETCH_RETRIES
                                     ));
                                     error!("{}", body_err);
                                     last_error = Some(body_err);
@@ -254,11 +638,16 @@ pub async fn fetch_collection_items_bulk(
                             // Handle non-success HTTP status
                             let err_msg = format!(
                                 "Bulk collection items API request failed for '{}' with status: {} (Attempt {}/{})",
-                                collection_name, status, attempt, MAX_FETCH_RETRIES
+                                label, status, attempt, MAX_FETCH_RETRIES
                             );
                             error!("{}", err_msg);
                             last_error = Some(anyhow!(err_msg));
 
+                            // 429/503: the rate limiter pauses every request as long as the server asks
+                            if rate_control::is_throttling(status) && attempt < MAX_FETCH_RETRIES {
+                                rate_limiter.throttled(rate_control::retry_after(&response));
+                                continue;
+                            }
                             // Retry only on server errors (5xx) or specific transient errors if needed
                             if status.is_server_error() && attempt < MAX_FETCH_RETRIES {
                                 let delay_secs = 1 << (attempt - 1); // Exponential backoff: 1s, 2s
@@ -275,7 +664,7 @@ pub async fn fetch_collection_items_bulk(
                         // Handle request sending errors (network, timeout, etc.)
                         let current_err = anyhow!(e).context(format!(
                             "Failed to send bulk collection items request for '{}' (Attempt {}/{})",
-                            collection_name, attempt, MAX_FETCH_RETRIES
+                            label, attempt, MAX_FETCH_RETRIES
                         ));
                         error!("{}", current_err);
                         last_error = Some(current_err);
@@ -293,7 +682,7 @@ pub async fn fetch_collection_items_bulk(
             }
             None => {
                 // Should not happen with standard reqwest builders
-                let build_err = anyhow!("Failed to clone request builder for bulk collection items '{}'", collection_name);
+                let build_err = anyhow!("Failed to clone request builder for {}", label);
                 error!("{}", build_err);
                 last_error = Some(build_err);
                 break; // Cannot retry if builder cannot be cloned
@@ -302,7 +691,7 @@ pub async fn fetch_collection_items_bulk(
     } // End retry loop
 
     // If loop finished without returning Ok, return the last error
-    Err(last_error.unwrap_or_else(|| anyhow!("Bulk collection items request failed after {} attempts for '{}'", MAX_FETCH_RETRIES, collection_name)))
+    Err(last_error.unwrap_or_else(|| anyhow!("Search request failed after {} attempts for {}", MAX_FETCH_RETRIES, label)))
 }
 
 
@@ -315,7 +704,7 @@ pub async fn fetch_item_details(
     rate_limiter: AppRateLimiter, // Added rate limiter parameter
 ) -> Result<ItemDetails, FetchDetailsError> { // Changed return type
     info!("Fetching item details for identifier: {}", identifier);
-    let url = format!("{}{}", METADATA_URL_BASE, identifier);
+    let url = format!("{}{}{}", base_url(), METADATA_PATH, identifier);
 
     // --- Wait for Rate Limiter ---
     debug!("Waiting for rate limit permit for item details: {}", identifier);
@@ -330,6 +719,9 @@ pub async fn fetch_item_details(
         Ok(response) => {
             let status = response.status();
             if !status.is_success() {
+                if rate_control::is_throttling(status) {
+                    rate_limiter.throttled(rate_control::retry_after(&response)); // Retries wait in `until_ready`
+                }
                 // Classify HTTP errors
                 let kind = match status {
                     StatusCode::NOT_FOUND => FetchDetailsErrorKind::NotFound,
@@ -368,7 +760,7 @@ pub async fn fetch_item_details(
                     match v {
                         Some(serde_json::Value::String(s)) => Some(s.clone()),
                         Some(serde_json::Value::Array(arr)) => arr
-                            .get(0)
+                            .first()
                             .and_then(|first| first.as_str())
                             .map(String::from),
                         _ => None,
@@ -406,6 +798,13 @@ pub async fn fetch_item_details(
                         (None, None, None, None, None, Vec::new(), None) // Added None for mediatype
                     };
 
+                let mirror_base_urls = mirror_base_urls(&raw_details);
+                let reviews = raw_details.reviews.iter().filter_map(parse_review).collect();
+                let related_identifiers = related_identifiers(
+                    identifier,
+                    description.as_deref(),
+                    raw_details.metadata.as_ref().and_then(|m| m.extra.get("relation")),
+                );
                 let download_base_url = match (raw_details.server, raw_details.dir) {
                     (Some(server), Some(dir)) => Some(format!("https://{}/{}", server, dir)),
                     _ => None, // Add default case
@@ -443,7 +842,13 @@ pub async fn fetch_item_details(
                                             source: file_with_name.details.source,
                                             format: file_with_name.details.format,
                                             size: file_with_name.details.size,
+                                            private: is_private_file(&file_with_name.details._extra),
                                             md5: file_with_name.details.md5,
+                                            track: file_with_name.details.track,
+                                            title: file_with_name.details.title,
+                                            length: file_with_name.details.length,
+                                            album: file_with_name.details.album,
+                                            artist: file_with_name.details.artist.or(file_with_name.details.creator),
                                         }),
                                         Err(_) => None, // Skip files that don't match the expected structure
                                     }
@@ -463,7 +868,13 @@ pub async fn fetch_item_details(
                                             source: internal_details.source,
                                             format: internal_details.format,
                                             size: internal_details.size,
+                                            private: is_private_file(&internal_details._extra),
                                             md5: internal_details.md5,
+                                            track: internal_details.track,
+                                            title: internal_details.title,
+                                            length: internal_details.length,
+                                            album: internal_details.album,
+                                            artist: internal_details.artist.or(internal_details.creator),
                                         }),
                                         Err(_) => None, // Skip files that don't match the expected structure
                                     }
@@ -474,6 +885,12 @@ pub async fn fetch_item_details(
                         _ => Vec::new(),
                     },
                     download_base_url,
+                    mirror_base_urls,
+                    reviews,
+                    related_identifiers,
+                    access_restricted: raw_details.metadata.as_ref().is_some_and(|m| {
+                        m.extra.get("access-restricted-item").is_some_and(is_true)
+                    }),
                 };
 
                 info!("Successfully processed item details for identifier: {}", identifier);
@@ -504,10 +921,8 @@ pub async fn fetch_item_details(
             error!("{} for identifier '{}'", err, identifier); // Log the error created from 'e'
 
             // Classify network errors using the extracted info
-            let kind = if is_timeout {
-                FetchDetailsErrorKind::NetworkError // Specifically timeout
-            } else if is_connect_or_request {
-                 FetchDetailsErrorKind::NetworkError // Other connection/request errors
+            let kind = if is_timeout || is_connect_or_request {
+                FetchDetailsErrorKind::NetworkError // Timeouts and other connection/request errors
             } else {
                  FetchDetailsErrorKind::Other // Other reqwest errors
             };
@@ -530,7 +945,8 @@ mod tests {
     // need to be removed or adapted for fetch_collection_items_bulk.
     use super::*;
     use crate::app::AppRateLimiter; // Use the type alias
-    use governor::{Quota, RateLimiter, clock::SystemClock};
+    use crate::rate_limit::SharedRateLimiter;
+    use governor::Quota;
     use reqwest::Client;
     use std::{error::Error, sync::Arc, time::Duration, num::NonZeroU32}; // Import std::error::Error
     use tokio;
@@ -548,8 +964,7 @@ mod tests {
     fn test_limiter() -> AppRateLimiter {
         // Use the actual 15 requests per minute quota for integration tests
         let quota = Quota::per_minute(NonZeroU32::new(15).unwrap());
-        // Use direct_with_clock and SystemClock to match the AppRateLimiter type alias
-        Arc::new(RateLimiter::direct_with_clock(quota, &SystemClock::default()))
+        Arc::new(SharedRateLimiter::new(quota))
     }
 
     // --- Integration Tests (require network access to archive.org) ---
@@ -564,7 +979,7 @@ mod tests {
         let limiter = test_limiter();
 
         // Act
-        let result = fetch_collection_items_bulk(&client, collection_name, Arc::clone(&limiter)).await;
+        let result = fetch_collection_items_bulk(&client, collection_name, None, Arc::clone(&limiter)).await;
 
         // Assert
         assert!(result.is_ok(), "Bulk API call should succeed. Error: {:?}", result.err());
@@ -573,12 +988,9 @@ mod tests {
         assert!(total_found > 100, "Total found should be > 100 for 'enough_records' (found {})", total_found);
         assert!(!items.is_empty(), "Should return items for 'enough_records'");
         // Check if the number of items fetched is close to the total reported
-        // Allow some difference as the total might fluctuate slightly or BULK_ROWS might be smaller
+        // Allow some difference as the total might fluctuate slightly while paging
         let diff = (total_found as isize - items.len() as isize).abs();
-        // Allow a slightly larger difference percentage for smaller collections if needed, or keep absolute diff
-        assert!(diff < 100 || items.len() >= BULK_ROWS,
-                "Fetched items ({}) should be close to total ({}) or limited by BULK_ROWS ({}) for '{}'",
-                items.len(), total_found, BULK_ROWS, collection_name);
+        assert!(diff < 100, "Fetched items ({}) should be close to total ({}) for '{}'", items.len(), total_found, collection_name);
         assert!(items.iter().all(|doc| !doc.identifier.is_empty()), "All items should have an identifier");
     }
 
@@ -591,7 +1003,7 @@ mod tests {
         let limiter = test_limiter();
 
         // Act
-        let result = fetch_collection_items_bulk(&client, collection_name, limiter).await;
+        let result = fetch_collection_items_bulk(&client, collection_name, None, limiter).await;
 
         // Assert
         // The API call itself might succeed but return 0 results.
@@ -627,7 +1039,7 @@ mod tests {
         let details = result.unwrap();
 
         assert_eq!(details.identifier, identifier);
-        assert_eq!(details.title.is_some(), true, "Title should be present for item '{}'. Details: {:?}", identifier, details);
+        assert!(details.title.is_some(), "Title should be present for item '{}'. Details: {:?}", identifier, details);
         assert_eq!(details.title.as_deref(), Some("Litmus - Perception Of Light [enrmp270]"), "Title mismatch");
         assert!(details.creator.is_some(), "Should have a creator: {:?}", details.creator);
         assert_eq!(details.creator.as_deref(), Some("Litmus"), "Creator mismatch");
@@ -664,4 +1076,140 @@ mod tests {
     // Removed test_fetch_item_details_integration_minimal_metadata as it used an invalid identifier
 
     // Removed leftover tests calling the old fetch_collection_items function
+
+    #[test]
+    fn test_mirror_base_urls_lists_each_datanode_once() {
+        let raw: ItemMetadataResponse = serde_json::from_value(serde_json::json!({
+            "server": "ia800101.us.archive.org",
+            "dir": "/12/items/some_item",
+            "d1": "ia800101.us.archive.org",
+            "d2": "ia600101.us.archive.org",
+            "workable_servers": ["ia800101.us.archive.org", "ia600101.us.archive.org", "ia900101.us.archive.org"]
+        }))
+        .unwrap();
+        assert_eq!(
+            mirror_base_urls(&raw),
+            vec![
+                "https://ia800101.us.archive.org/12/items/some_item",
+                "https://ia600101.us.archive.org/12/items/some_item",
+                "https://ia900101.us.archive.org/12/items/some_item",
+            ]
+        );
+
+        let no_dir: ItemMetadataResponse = serde_json::from_value(serde_json::json!({ "d1": "ia800101.us.archive.org" })).unwrap();
+        assert!(mirror_base_urls(&no_dir).is_empty());
+    }
+
+    #[test]
+    fn test_reviews_and_related_items_parsed_from_metadata() {
+        let review = parse_review(&serde_json::json!({
+            "reviewtitle": "Great show", "reviewbody": "Best version of the song.", "reviewer": "taper", "reviewdate": "2011-02-03 04:05:06", "stars": "4"
+        }))
+        .unwrap();
+        assert_eq!((review.title.as_deref(), review.stars), (Some("Great show"), Some(4)));
+        assert_eq!(parse_review(&serde_json::json!({ "stars": 5 })), None, "A review needs a body");
+
+        let description = "Part two: <a href=\"https://archive.org/details/show_part2\">here</a>. See also archive.org/details/show_part2 and https://archive.org/details/this_item.";
+        let relation = serde_json::json!(["show_part3", "https://archive.org/details/show_part1.", "not an identifier"]);
+        assert_eq!(related_identifiers("this_item", Some(description), Some(&relation)), vec!["show_part2", "show_part3", "show_part1"]);
+    }
+
+    // --- Mock server tests (deterministic, run with `cargo test --features mock`) ---
+    #[cfg(feature = "mock")]
+    fn use_mock_server() {
+        set_base_url(crate::mock_server::shared_base_url());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_collection_scrape_follows_cursor_mock() {
+        use_mock_server();
+        let client = test_client();
+        let limiter = test_limiter();
+        let mut scrape = CollectionScrape::new(crate::mock_server::MOCK_COLLECTION, None);
+
+        // The mock serves one item per page
+        let first = scrape.next_page(&client, &limiter).await.unwrap().expect("First page");
+        assert_eq!(first.len(), crate::mock_server::MOCK_SCRAPE_PAGE_SIZE);
+        assert_eq!(scrape.total(), Some(crate::mock_server::MOCK_ITEMS.len()));
+        assert!(!scrape.is_finished());
+        let second = scrape.next_page(&client, &limiter).await.unwrap().expect("Second page");
+        assert_eq!(second[0].identifier, crate::mock_server::MOCK_ITEMS[1]);
+        assert!(scrape.is_finished());
+        assert!(scrape.next_page(&client, &limiter).await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_fetch_titles_mock() {
+        use_mock_server();
+        let identifiers = vec![crate::mock_server::MOCK_COLLECTION.to_string(), "no_such_collection".to_string()];
+        let titles = fetch_titles(&test_client(), &identifiers, test_limiter()).await.unwrap();
+        assert_eq!(titles, vec![(crate::mock_server::MOCK_COLLECTION.to_string(), "Mock Collection".to_string())]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_fetch_collection_items_bulk_mock() {
+        use_mock_server();
+        let result = fetch_collection_items_bulk(&test_client(), crate::mock_server::MOCK_COLLECTION, None, test_limiter()).await;
+
+        let (items, total_found) = result.expect("Mock bulk fetch should succeed");
+        assert_eq!(total_found, crate::mock_server::MOCK_ITEMS.len());
+        let identifiers: Vec<&str> = items.iter().map(|doc| doc.identifier.as_str()).collect();
+        assert_eq!(identifiers, crate::mock_server::MOCK_ITEMS);
+        assert_eq!(items[0].mediatype.as_deref(), Some("audio"));
+        assert_eq!(items[0].format, vec!["VBR MP3", "JPEG"]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_search_items_mock() {
+        use_mock_server();
+        let (items, total_found) = search_items(&test_client(), "Item_Two", test_limiter())
+            .await
+            .expect("Mock search should succeed");
+
+        assert_eq!(total_found, 1);
+        assert_eq!(items[0].identifier, "mock_item_two");
+        assert_eq!(items[0].title.as_deref(), Some("Mock Item mock_item_two"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_fetch_item_details_mock() {
+        use_mock_server();
+        let details = fetch_item_details(&test_client(), "mock_item_one", test_limiter())
+            .await
+            .expect("Mock item details should succeed");
+
+        assert_eq!(details.creator.as_deref(), Some("Mock Artist"));
+        assert_eq!(details.collections, vec![crate::mock_server::MOCK_COLLECTION.to_string()]);
+        assert_eq!(details.files.len(), crate::mock_server::MOCK_FILES.len());
+        assert_eq!(details.reviews.len(), 1);
+        assert_eq!(details.related_identifiers, vec!["mock_item_two".to_string()]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_fetch_item_details_mock_marks_restricted_files() {
+        use_mock_server();
+        let details = fetch_item_details(&test_client(), crate::mock_server::MOCK_RESTRICTED_ITEM, test_limiter())
+            .await
+            .expect("Mock lending item details should succeed");
+
+        assert!(details.access_restricted);
+        let private: Vec<&str> = details.files.iter().filter(|f| f.private).map(|f| f.name.as_str()).collect();
+        assert_eq!(private, vec!["book.epub"]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_fetch_item_details_mock_not_found() {
+        use_mock_server();
+        let err = fetch_item_details(&test_client(), "mock_missing_item", test_limiter())
+            .await
+            .expect_err("Unknown mock item should fail");
+        assert!(matches!(err.kind, FetchDetailsErrorKind::NotFound));
+    }
 }
@@ -0,0 +1,329 @@
+use crate::ia_upload::S3Keys;
+use crate::secrets::SecretStore;
+use crate::settings::{self, Settings};
+use anyhow::{bail, Context, Result};
+use directories::BaseDirs;
+use log::{debug, info, warn};
+use reqwest::{cookie::Jar, Client, Url};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}};
+
+/// Cookie domain used for real archive.org hosts (covers the `ia*.us.archive.org` data nodes).
+const ARCHIVE_COOKIE_DOMAIN: &str = "archive.org";
+/// File in the config directory that held the login saved from the settings before it
+/// moved to the secret store; moved there on the next load.
+pub const LOGIN_FILE: &str = "ia_login.toml";
+/// Key of the login saved from the settings in the secret store (see [`crate::secrets`]).
+const LOGIN_SECRET: &str = "ia_login";
+
+/// archive.org session cookies (`logged-in-user` / `logged-in-sig`).
+///
+/// These are what the website and the `ia` command line tool use; with them the
+/// download endpoints serve files the account is entitled to (borrowed books,
+/// stream-only media the account has access to).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub logged_in_user: String,
+    /// Kept in the keyring rather than `settings.toml` (see [`crate::secrets`]).
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub logged_in_sig: String,
+}
+
+impl Credentials {
+    /// Account name for display (the cookie value is URL-encoded, e.g. `me%40example.com`).
+    pub fn display_user(&self) -> String {
+        self.logged_in_user.replace("%40", "@")
+    }
+}
+
+/// Returns the credentials to use: `archive_login` from the settings, otherwise the login
+/// saved from the settings view (in the secret store), otherwise the cookies saved by the
+/// `ia` tool (`ia configure`), if any.
+pub fn credentials(settings: &Settings) -> Option<Credentials> {
+    if let Some(login) = &settings.archive_login {
+        return Some(login.clone());
+    }
+    if let (Ok(store), Ok(dir)) = (settings::secret_store(), settings::config_dir()) {
+        if let Some(creds) = load_login(store, &dir) {
+            return Some(creds);
+        }
+    }
+    let (path, creds) = ia_config_paths().into_iter().find_map(|path| Some((path.clone(), credentials_from_file(&path)?)))?;
+    info!("Using archive.org login for '{}' from {}", creds.display_user(), path.display());
+    Some(creds)
+}
+
+/// Reads the login saved by [`save_login`], if any, first moving a [`LOGIN_FILE`] left in
+/// `config_dir` by an earlier version into `store`.
+pub fn load_login(store: &SecretStore, config_dir: &Path) -> Option<Credentials> {
+    store.adopt_file::<Credentials>(LOGIN_SECRET, &config_dir.join(LOGIN_FILE));
+    store.get_value(LOGIN_SECRET)
+}
+
+/// Saves the login cookies in `store`: the keyring, or a file readable by the current
+/// user only.
+pub fn save_login(store: &SecretStore, creds: &Credentials) -> Result<()> {
+    store.set_value(LOGIN_SECRET, Some(creds))?;
+    info!("Saved archive.org login for '{}'", creds.display_user());
+    Ok(())
+}
+
+/// Forgets the saved login. Logins from `settings.toml` or `ia configure` are not touched.
+pub fn remove_login(store: &SecretStore, config_dir: &Path) -> Result<()> {
+    let path = config_dir.join(LOGIN_FILE);
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to remove '{}'", path.display())),
+    }
+    store.set_value::<Credentials>(LOGIN_SECRET, None)
+}
+
+/// Email and password typed into the settings, on their way to [`login`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+// The password never ends up in logs
+impl fmt::Debug for LoginRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginRequest").field("email", &self.email).field("password", &"***").finish()
+    }
+}
+
+impl LoginRequest {
+    /// Parses `EMAIL PASSWORD` as typed in the settings (the password may contain spaces).
+    pub fn parse(input: &str) -> Option<Self> {
+        let (email, password) = input.trim().split_once(char::is_whitespace)?;
+        let password = password.trim_start();
+        (email.contains('@') && !password.is_empty())
+            .then(|| LoginRequest { email: email.to_string(), password: password.to_string() })
+    }
+}
+
+/// What a successful [`login`] returns: the session cookies and the account's S3 keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginResult {
+    pub credentials: Credentials,
+    pub s3_keys: Option<S3Keys>,
+    /// The account's screen name (used for its public favorites collection).
+    pub screenname: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct XauthnResponse {
+    #[serde(default)]
+    success: bool,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    values: XauthnValues,
+}
+
+#[derive(Deserialize, Default)]
+struct XauthnValues {
+    #[serde(default)]
+    cookies: HashMap<String, String>,
+    #[serde(default)]
+    s3: Option<S3Keys>,
+    #[serde(default)]
+    screenname: Option<String>,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Logs in to archive.org with an email address and password, the way `ia configure` does.
+///
+/// The password is only sent to archive.org; what comes back (and gets saved) is the
+/// `logged-in-user`/`logged-in-sig` cookie pair plus the account's S3 keys.
+pub async fn login(client: &Client, base_url: &str, email: &str, password: &str) -> Result<LoginResult> {
+    let url = format!("{}/services/xauthn/?op=login", base_url.trim_end_matches('/'));
+    let response = client
+        .post(&url)
+        .form(&[("email", email), ("password", password)])
+        .send()
+        .await
+        .context("Failed to reach the archive.org login service")?;
+    let status = response.status();
+    let body = response.text().await.context("Failed to read the login response")?;
+    let parsed: XauthnResponse = serde_json::from_str(&body)
+        .with_context(|| format!("Unexpected login response (HTTP {})", status))?;
+    if !parsed.success {
+        let reason = parsed.values.reason.or(parsed.error).unwrap_or_else(|| format!("HTTP {}", status));
+        bail!("Login failed: {}", login_failure_message(&reason));
+    }
+    let cookie = |name: &str| parsed.values.cookies.get(name).map(|v| v.split(';').next().unwrap_or("").trim().to_string());
+    let (Some(logged_in_user), Some(logged_in_sig)) = (cookie("logged-in-user"), cookie("logged-in-sig")) else {
+        bail!("Login succeeded but archive.org sent no session cookies");
+    };
+    let credentials = Credentials { logged_in_user, logged_in_sig };
+    info!("Logged in to archive.org as '{}'", credentials.display_user());
+    Ok(LoginResult { credentials, s3_keys: parsed.values.s3, screenname: parsed.values.screenname })
+}
+
+/// Readable text for the `reason` codes of the login service.
+fn login_failure_message(reason: &str) -> String {
+    match reason {
+        "account_not_found" => "no account with that email address".to_string(),
+        "account_bad_password" => "wrong password".to_string(),
+        "account_locked" => "the account is locked".to_string(),
+        "account_not_verified" => "the account's email address is not verified yet".to_string(),
+        other => other.replace('_', " "),
+    }
+}
+
+/// Reads the login cookies from an `ia.ini` file, if it exists and contains them.
+fn credentials_from_file(path: &Path) -> Option<Credentials> {
+    let contents = fs::read_to_string(path).ok()?;
+    let creds = parse_ia_config(&contents);
+    if creds.is_none() {
+        debug!("No login cookies in {}", path.display());
+    }
+    creds
+}
+
+/// Candidate locations of the `ia` configuration file, in the order `ia` itself checks them.
+pub(crate) fn ia_config_paths() -> Vec<PathBuf> {
+    let Some(base) = BaseDirs::new() else { return Vec::new() };
+    let home = base.home_dir();
+    vec![
+        base.config_dir().join("internetarchive").join("ia.ini"),
+        home.join(".config").join("ia.ini"),
+        home.join(".ia"),
+    ]
+}
+
+/// Extracts the login cookies from the `[cookies]` section of an `ia.ini` file.
+pub fn parse_ia_config(contents: &str) -> Option<Credentials> {
+    let mut in_cookies = false;
+    let (mut user, mut sig) = (None, None);
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_cookies = line == "[cookies]";
+            continue;
+        }
+        if !in_cookies {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        // ia stores the full Set-Cookie string; only the value before ';' matters
+        let value = value.split(';').next().unwrap_or("").trim().to_string();
+        match key.trim() {
+            "logged-in-user" => user = Some(value),
+            "logged-in-sig" => sig = Some(value),
+            _ => {}
+        }
+    }
+    match (user, sig) {
+        (Some(logged_in_user), Some(logged_in_sig)) if !logged_in_user.is_empty() && !logged_in_sig.is_empty() => {
+            Some(Credentials { logged_in_user, logged_in_sig })
+        }
+        _ => None,
+    }
+}
+
+/// Builds a cookie jar that sends `creds` to `base_url`.
+///
+/// For archive.org the cookies are scoped to the whole domain so they follow the
+/// redirects to the data nodes; any other host (the mock server) gets host-only cookies.
+pub fn cookie_jar(creds: &Credentials, base_url: &str) -> Jar {
+    let jar = Jar::default();
+    let Ok(url) = Url::parse(base_url) else {
+        warn!("Invalid base URL '{}'; not sending login cookies", base_url);
+        return jar;
+    };
+    let host = url.host_str().unwrap_or_default();
+    let domain = if host == ARCHIVE_COOKIE_DOMAIN || host.ends_with(".archive.org") {
+        format!("; Domain={}", ARCHIVE_COOKIE_DOMAIN)
+    } else {
+        String::new()
+    };
+    for (name, value) in [("logged-in-user", &creds.logged_in_user), ("logged-in-sig", &creds.logged_in_sig)] {
+        jar.add_cookie_str(&format!("{}={}; Path=/{}", name, value, domain), &url);
+    }
+    jar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore;
+
+    fn creds() -> Credentials {
+        Credentials { logged_in_user: "me%40example.com".to_string(), logged_in_sig: "abc123".to_string() }
+    }
+
+    #[test]
+    fn test_parse_ia_config_reads_cookie_section() {
+        let ini = "[s3]\naccess = KEY\nsecret = SECRET\n\n[cookies]\nlogged-in-user = me%40example.com; path=/; domain=.archive.org\nlogged-in-sig = abc123; path=/\n";
+        assert_eq!(parse_ia_config(ini), Some(creds()));
+        assert_eq!(creds().display_user(), "me@example.com");
+    }
+
+    #[test]
+    fn test_parse_ia_config_requires_both_cookies() {
+        assert_eq!(parse_ia_config("[cookies]\nlogged-in-user = me\n"), None);
+        // Keys outside [cookies] are ignored
+        assert_eq!(parse_ia_config("[general]\nlogged-in-user = me\nlogged-in-sig = sig\n"), None);
+    }
+
+    #[test]
+    fn test_cookie_jar_covers_archive_data_nodes() {
+        let jar = cookie_jar(&creds(), "https://archive.org");
+        let node = Url::parse("https://ia800100.us.archive.org/1/items/x/x.epub").unwrap();
+        let header = jar.cookies(&node).expect("Cookies should be sent to data nodes");
+        let header = header.to_str().unwrap();
+        assert!(header.contains("logged-in-user=me%40example.com"));
+        assert!(header.contains("logged-in-sig=abc123"));
+        assert!(jar.cookies(&Url::parse("https://api.github.com/").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_login_request_parse() {
+        let request = LoginRequest::parse(" me@example.com  pass word ").unwrap();
+        assert_eq!(request.email, "me@example.com");
+        assert_eq!(request.password, "pass word");
+        assert!(!format!("{:?}", request).contains("pass word"));
+        assert_eq!(LoginRequest::parse("me@example.com"), None);
+        assert_eq!(LoginRequest::parse("me password"), None, "Not an email address");
+    }
+
+    #[test]
+    fn test_saved_login_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::new(None, dir.path().join(crate::secrets::SECRETS_FILE));
+        assert_eq!(load_login(&store, dir.path()), None);
+        save_login(&store, &creds()).unwrap();
+        assert_eq!(load_login(&store, dir.path()), Some(creds()));
+        remove_login(&store, dir.path()).unwrap();
+        assert_eq!(load_login(&store, dir.path()), None);
+        remove_login(&store, dir.path()).unwrap(); // Logging out twice is fine
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_login_against_mock_service() {
+        use crate::mock_server::{self, MOCK_LOGIN_EMAIL, MOCK_LOGIN_PASSWORD, MOCK_LOGIN_SIG};
+        let base = mock_server::shared_base_url();
+        let client = Client::new();
+
+        let result = login(&client, base, MOCK_LOGIN_EMAIL, MOCK_LOGIN_PASSWORD).await.unwrap();
+        assert_eq!(result.credentials.logged_in_sig, MOCK_LOGIN_SIG);
+        assert_eq!(result.credentials.display_user(), MOCK_LOGIN_EMAIL);
+        assert_eq!(result.s3_keys.map(|k| k.access), Some(mock_server::MOCK_S3_ACCESS.to_string()));
+        assert_eq!(result.screenname.as_deref(), Some(mock_server::MOCK_FAVORITES_USER));
+
+        let err = login(&client, base, MOCK_LOGIN_EMAIL, "wrong").await.unwrap_err();
+        assert_eq!(err.to_string(), "Login failed: wrong password");
+    }
+
+    #[test]
+    fn test_credentials_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ia.ini");
+        fs::write(&path, "[cookies]\nlogged-in-user = me%40example.com\nlogged-in-sig = abc123\n").unwrap();
+        assert_eq!(credentials_from_file(&path), Some(creds()));
+    }
+}
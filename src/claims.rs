@@ -0,0 +1,88 @@
+use crate::paths::{self, PathPolicy};
+use anyhow::{Context, Result};
+use log::debug;
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Directory (inside the download directory) holding one claim file per item.
+pub const CLAIMS_DIR: &str = ".archiver-claims";
+
+/// Exclusive claim on an item for the lifetime of the value.
+///
+/// Backed by an OS file lock, so a claim disappears with its process even if it
+/// crashes; there are no stale claims to clean up. The claim file itself is left in
+/// place (deleting it would let a third process lock a fresh file while the second
+/// still waits on the old one).
+#[derive(Debug)]
+pub struct ItemClaim {
+    _file: File, // Lock is released when the file is closed
+    path: PathBuf,
+}
+
+impl ItemClaim {
+    /// Path of the claim file (for logging).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Path of the claim file for `item_id` under `base_dir`.
+pub fn claim_path(base_dir: &str, item_id: &str, policy: &PathPolicy) -> PathBuf {
+    let name = format!("{}.lock", paths::sanitize_component(item_id, policy));
+    Path::new(base_dir).join(CLAIMS_DIR).join(name)
+}
+
+/// Tries to claim `item_id` for this process.
+///
+/// Returns `Ok(None)` when another process (or another download task in this one)
+/// currently holds the claim.
+pub fn try_claim(base_dir: &str, item_id: &str, policy: &PathPolicy) -> Result<Option<ItemClaim>> {
+    let path = claim_path(base_dir, item_id, policy);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("Failed to create claims directory '{}'", dir.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .context(format!("Failed to open claim file '{}'", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            debug!("Item '{}' is claimed by another download", item_id);
+            return Ok(None);
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).context(format!("Failed to lock claim file '{}'", path.display()));
+        }
+    }
+    // Owner details only help whoever inspects the directory; the lock is what counts
+    let _ = file.set_len(0);
+    let _ = writeln!(file, "pid {} since {}", std::process::id(), chrono::Local::now().to_rfc3339());
+    Ok(Some(ItemClaim { _file: file, path }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_is_exclusive_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_str().unwrap();
+        let policy = PathPolicy::default();
+
+        let claim = try_claim(base, "item_one", &policy).unwrap().expect("First claim should succeed");
+        assert!(claim.path().starts_with(dir.path().join(CLAIMS_DIR)));
+        assert!(try_claim(base, "item_one", &policy).unwrap().is_none());
+        // Other items are unaffected
+        assert!(try_claim(base, "item_two", &policy).unwrap().is_some());
+
+        drop(claim);
+        assert!(try_claim(base, "item_one", &policy).unwrap().is_some());
+    }
+}
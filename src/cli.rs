@@ -0,0 +1,251 @@
+use crate::seed::SeedOptions;
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+/// Usage text printed for `--help` and on argument errors.
+pub const USAGE: &str = "Usage: archiver [OPTIONS] [COMMAND]
+
+Commands (run without the TUI, e.g. from cron or CI):
+  download <collection>  Download every item of a collection
+  download-item <item>   Download all files of one item
+  fetch-metadata <item>  Print an item's metadata and file list
+  verify <dir>           Check the files in an item or collection folder against
+                         their checksum manifest, or archive.org's MD5 checksums
+                         for items without one
+  wayback <url>          List the Wayback Machine captures of a URL
+  wayback-download <url> Save the latest capture of a URL, or every capture
+                         between --from and --to, into <dir>/wayback
+  ipfs-manifest          Write ipfs-manifest.json (identifiers and the CIDs of
+                         the items added to IPFS) into the download directory
+  feeds                  Write an OPDS catalog of the downloaded texts and a
+                         podcast feed of the downloaded audio into the download
+                         directory
+  site                   Write a static HTML site of the downloaded collections and
+                         items into the download directory
+  make-torrent <dir>     Write a BitTorrent v1/v2 <dir>.torrent of a downloaded
+                         collection or item folder, for seeding it
+  export-state <file>    Write the settings, favorites, saved searches and download
+                         history (without credentials) into one JSON file
+  import-state <file>    Take over the state in a file written by export-state,
+                         keeping this machine's credentials and download directory
+
+Options:
+  --dir <path>    Download directory for the download commands, ipfs-manifest,
+                  feeds, site and the state commands (default: the one in
+                  settings)
+  --force         Download files again even when a copy with the listed size
+                  and MD5 is already on disk
+  --from <date>   Earliest capture for the wayback commands (YYYY, YYYY-MM,
+                  YYYY-MM-DD or a 14-digit timestamp)
+  --to <date>     Latest capture for the wayback commands
+  --tracker <url> Tracker announced by make-torrent (repeatable)
+  --web-seed <url> Web seed listed by make-torrent (repeatable)
+  --archive-seed  List archive.org as a web seed (only resolves for a single
+                  item folder, which is named after its identifier)
+  --mock          Run against a local mock archive.org serving canned fixtures
+                  (requires a build with the `mock` feature)
+  --profile-perf  Record per-phase timings and write a report on exit
+  --screen-reader Simplified layout with a textual event line
+  --mirror-stdout Also print announcements and key presses to stdout as plain
+                  text (implies --screen-reader; the TUI draws on stderr)
+  -h, --help      Print this help and exit
+
+Environment:
+  NO_COLOR        Disable colors (state is still shown with text markers)";
+
+/// Options parsed from the command line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CliOptions {
+    /// Serve canned fixtures from a local mock archive.org instead of the real API.
+    pub mock: bool,
+    /// Record performance timings and write a report on exit.
+    pub profile_perf: bool,
+    /// Screen-reader friendly layout and announcements.
+    pub screen_reader: bool,
+    /// Mirror announcements and key presses to stdout.
+    pub mirror_stdout: bool,
+    /// Print usage and exit.
+    pub help: bool,
+    /// Headless command to run instead of the TUI.
+    pub command: Option<Command>,
+    /// Download directory overriding the settings (headless downloads).
+    pub dir: Option<String>,
+    /// Download files again even when a complete copy is on disk.
+    pub force: bool,
+}
+
+/// Headless commands, run to completion without starting the TUI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Download every item of a collection.
+    Download(String),
+    /// Download all files of one item.
+    DownloadItem(String),
+    /// Print an item's metadata and file list.
+    FetchMetadata(String),
+    /// Check downloaded files against archive.org's checksums.
+    Verify(PathBuf),
+    /// List (or with `download`, save) the Wayback Machine captures of a URL.
+    Wayback { url: String, from: Option<String>, to: Option<String>, download: bool },
+    /// Write the identifier-to-CID manifest of the items added to IPFS.
+    IpfsManifest,
+    /// Write the OPDS catalog and podcast feed of the downloaded items.
+    Feeds,
+    /// Write the static HTML site of the downloaded items.
+    Site,
+    /// Write a torrent of a local directory tree for seeding.
+    MakeTorrent { dir: PathBuf, options: SeedOptions },
+    /// Write the settings and download history into a file for another machine.
+    ExportState(PathBuf),
+    /// Take over the settings and download history in an exported file.
+    ImportState(PathBuf),
+}
+
+/// Parses command-line arguments (excluding the program name).
+///
+/// Returns an error naming the offending argument if it is not recognised.
+pub fn parse_args<I, S>(args: I) -> Result<CliOptions>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut options = CliOptions::default();
+    let (mut from, mut to) = (None, None);
+    let mut seed_options = SeedOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next().map(|v| v.as_ref().to_string()).ok_or_else(|| anyhow!("'{}' needs an argument\n\n{}", name, USAGE))
+        };
+        match arg.as_ref() {
+            "download" | "download-item" | "fetch-metadata" | "verify" | "wayback" | "wayback-download" | "make-torrent" | "export-state" | "import-state"
+                if options.command.is_none() =>
+            {
+                let name = arg.as_ref();
+                let target = value(name)?;
+                options.command = Some(match name {
+                    "download" => Command::Download(target),
+                    "download-item" => Command::DownloadItem(target),
+                    "fetch-metadata" => Command::FetchMetadata(target),
+                    "wayback" | "wayback-download" => Command::Wayback { url: target, from: None, to: None, download: name == "wayback-download" },
+                    "make-torrent" => Command::MakeTorrent { dir: PathBuf::from(target), options: SeedOptions::default() },
+                    "export-state" => Command::ExportState(PathBuf::from(target)),
+                    "import-state" => Command::ImportState(PathBuf::from(target)),
+                    _ => Command::Verify(PathBuf::from(target)),
+                });
+            }
+            "ipfs-manifest" if options.command.is_none() => options.command = Some(Command::IpfsManifest),
+            "feeds" if options.command.is_none() => options.command = Some(Command::Feeds),
+            "site" if options.command.is_none() => options.command = Some(Command::Site),
+            "--dir" => options.dir = Some(value("--dir")?),
+            "--force" => options.force = true,
+            "--from" => from = Some(value("--from")?),
+            "--to" => to = Some(value("--to")?),
+            "--tracker" => seed_options.trackers.push(value("--tracker")?),
+            "--web-seed" => seed_options.web_seeds.push(value("--web-seed")?),
+            "--archive-seed" => seed_options.archive_web_seed = true,
+            "--mock" => options.mock = true,
+            "--profile-perf" => options.profile_perf = true,
+            "--screen-reader" => options.screen_reader = true,
+            "--mirror-stdout" => {
+                options.mirror_stdout = true;
+                options.screen_reader = true;
+            }
+            "-h" | "--help" => options.help = true,
+            other => return Err(anyhow!("Unrecognised argument: '{}'\n\n{}", other, USAGE)),
+        }
+    }
+    match &mut options.command {
+        Some(Command::Wayback { from: range_from, to: range_to, .. }) => (*range_from, *range_to) = (from, to),
+        _ if from.is_some() || to.is_some() => return Err(anyhow!("--from and --to only apply to the wayback commands\n\n{}", USAGE)),
+        _ => {}
+    }
+    match &mut options.command {
+        Some(Command::MakeTorrent { options, .. }) => *options = seed_options,
+        _ if seed_options != SeedOptions::default() => {
+            return Err(anyhow!("--tracker, --web-seed and --archive-seed only apply to make-torrent\n\n{}", USAGE))
+        }
+        _ => {}
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_no_args_gives_defaults() {
+        let options = parse_args(Vec::<String>::new()).unwrap();
+        assert_eq!(options, CliOptions::default());
+    }
+
+    #[test]
+    fn test_parse_mock_and_help() {
+        let options = parse_args(["--mock", "-h"]).unwrap();
+        assert!(options.mock);
+        assert!(options.help);
+        assert!(!options.profile_perf);
+    }
+
+    #[test]
+    fn test_mirror_stdout_implies_screen_reader() {
+        let options = parse_args(["--mirror-stdout"]).unwrap();
+        assert!(options.mirror_stdout);
+        assert!(options.screen_reader);
+    }
+
+    #[test]
+    fn test_parse_unknown_argument_fails() {
+        let err = parse_args(["--bogus"]).unwrap_err();
+        assert!(err.to_string().contains("--bogus"));
+    }
+
+    #[test]
+    fn test_parse_headless_commands() {
+        let options = parse_args(["--mock", "download", "etree", "--dir", "/srv/archive"]).unwrap();
+        assert_eq!(options.command, Some(Command::Download("etree".to_string())));
+        assert_eq!(options.dir.as_deref(), Some("/srv/archive"));
+        assert!(options.mock);
+        assert!(!options.force);
+        assert!(parse_args(["download-item", "gd1977", "--force"]).unwrap().force);
+
+        assert_eq!(parse_args(["ipfs-manifest", "--dir", "/srv"]).unwrap().command, Some(Command::IpfsManifest));
+        assert_eq!(parse_args(["feeds"]).unwrap().command, Some(Command::Feeds));
+        assert_eq!(parse_args(["site", "--dir", "/srv"]).unwrap().command, Some(Command::Site));
+        assert_eq!(parse_args(["export-state", "state.json"]).unwrap().command, Some(Command::ExportState(PathBuf::from("state.json"))));
+        assert_eq!(parse_args(["import-state", "state.json"]).unwrap().command, Some(Command::ImportState(PathBuf::from("state.json"))));
+
+        let options = parse_args(["verify", "downloads/etree"]).unwrap();
+        assert_eq!(options.command, Some(Command::Verify(PathBuf::from("downloads/etree"))));
+
+        assert!(parse_args(["fetch-metadata"]).unwrap_err().to_string().contains("needs an argument"));
+        assert!(parse_args(["download", "a", "download", "b"]).is_err(), "Only one command per run");
+    }
+
+    #[test]
+    fn test_parse_wayback_commands() {
+        let options = parse_args(["--from", "2005", "wayback-download", "example.com", "--to", "2010-06"]).unwrap();
+        assert_eq!(
+            options.command,
+            Some(Command::Wayback {
+                url: "example.com".to_string(),
+                from: Some("2005".to_string()),
+                to: Some("2010-06".to_string()),
+                download: true
+            })
+        );
+        assert!(matches!(parse_args(["wayback", "example.com"]).unwrap().command, Some(Command::Wayback { download: false, .. })));
+        assert!(parse_args(["download", "etree", "--from", "2005"]).is_err(), "--from needs a wayback command");
+    }
+
+    #[test]
+    fn test_parse_make_torrent() {
+        let options = parse_args(["make-torrent", "/srv/archive/etree", "--tracker", "http://t1/announce", "--tracker", "http://t2/announce", "--archive-seed"]).unwrap();
+        let Some(Command::MakeTorrent { dir, options }) = options.command else { panic!("Expected make-torrent") };
+        assert_eq!(dir, PathBuf::from("/srv/archive/etree"));
+        assert_eq!(options.trackers.len(), 2);
+        assert!(options.archive_web_seed);
+        assert!(parse_args(["verify", "etree", "--web-seed", "http://mirror/"]).is_err(), "--web-seed needs make-torrent");
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::debug;
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// A clipboard tool and its arguments.
+type ClipboardCommand = (&'static str, &'static [&'static str]);
+
+/// Copies `text` to the system clipboard with the platform's clipboard tool (`pbcopy`,
+/// `clip`, `wl-copy`, `xclip` or `xsel`). Without one, the terminal is asked to do it with
+/// an OSC 52 escape, which most terminals (and tmux) honour, also over SSH.
+pub fn copy(text: &str) -> Result<()> {
+    for (program, args) in copy_commands() {
+        match run_with_input(program, args, text) {
+            Ok(()) => return Ok(()),
+            Err(e) => debug!("{:#}", e),
+        }
+    }
+    // The TUI draws on stderr, so the escape goes there too
+    let mut stderr = io::stderr();
+    stderr.write_all(osc52(text).as_bytes()).and_then(|()| stderr.flush()).context("Failed to write to the terminal")
+}
+
+/// Text on the system clipboard.
+pub fn paste() -> Result<String> {
+    for (program, args) in paste_commands() {
+        match Command::new(program).args(args).stdin(Stdio::null()).stderr(Stdio::null()).output() {
+            Ok(output) if output.status.success() => return Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            Ok(output) => debug!("{} exited with {}", program, output.status),
+            Err(e) => debug!("Failed to run {}: {}", program, e),
+        }
+    }
+    bail!("Can't read the clipboard: install wl-clipboard, xclip or xsel, or paste with the terminal's own shortcut")
+}
+
+fn run_with_input(program: &str, args: &[&str], text: &str) -> Result<()> {
+    // Output is discarded: wl-copy and xclip keep serving the selection in the background
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context(format!("Failed to run {}", program))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).context(format!("Failed to write to {}", program))?;
+    }
+    let status = child.wait().context(format!("Failed to wait for {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+fn copy_commands() -> Vec<ClipboardCommand> {
+    if cfg!(target_os = "macos") {
+        vec![("pbcopy", &[])]
+    } else if cfg!(windows) {
+        vec![("clip", &[])]
+    } else {
+        unix_commands(("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"]))
+    }
+}
+
+fn paste_commands() -> Vec<ClipboardCommand> {
+    if cfg!(target_os = "macos") {
+        vec![("pbpaste", &[])]
+    } else if cfg!(windows) {
+        vec![("powershell", &["-NoProfile", "-Command", "Get-Clipboard"])]
+    } else {
+        unix_commands(("wl-paste", &["--no-newline"]), ("xclip", &["-selection", "clipboard", "-o"]), ("xsel", &["--clipboard", "--output"]))
+    }
+}
+
+/// The Wayland tool in a Wayland session, the X11 ones with a display.
+fn unix_commands(wayland: ClipboardCommand, xclip: ClipboardCommand, xsel: ClipboardCommand) -> Vec<ClipboardCommand> {
+    let mut commands = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        commands.push(wayland);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        commands.extend([xclip, xsel]);
+    }
+    commands
+}
+
+/// OSC 52 escape setting the clipboard (`c`) to `text`.
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_encodes_text() {
+        assert_eq!(osc52("gd1977-05-08"), "\x1b]52;c;Z2QxOTc3LTA1LTA4\x07");
+        assert_eq!(osc52(""), "\x1b]52;c;\x07");
+    }
+}
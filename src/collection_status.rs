@@ -0,0 +1,92 @@
+use crate::forecast;
+use crate::fs_util;
+use crate::offline;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File (in the item cache directory) with the [`CollectionStatus`] of every listed collection.
+pub const STATUS_FILE: &str = "collections.json";
+
+/// What the collections pane shows about a favorite besides its name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectionStatus {
+    /// Items in its item cache.
+    pub items: usize,
+    /// RFC 3339 time its listing was last fetched from archive.org.
+    #[serde(default)]
+    pub synced: Option<String>,
+    /// Items a sync found that weren't listed before, until the collection is opened.
+    #[serde(default)]
+    pub new_items: usize,
+    /// RFC 3339 time it was last opened, for the recently used order.
+    #[serde(default)]
+    pub opened: Option<String>,
+    /// Its title on archive.org, shown instead of the identifier.
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl CollectionStatus {
+    /// ` · 1234 items · synced 3h 12m ago`, for the collections pane.
+    pub fn summary(&self, now: DateTime<Utc>) -> String {
+        let mut summary = format!(" · {} item{}", self.items, if self.items == 1 { "" } else { "s" });
+        if let Some(synced) = self.synced.as_deref().and_then(|at| DateTime::parse_from_rfc3339(at).ok()) {
+            let age = (now - synced.with_timezone(&Utc)).to_std().unwrap_or_default();
+            summary.push_str(&format!(" · synced {} ago", forecast::format_duration(age)));
+        }
+        summary
+    }
+}
+
+/// Path of the status file of download directory `base_dir`.
+pub fn status_path(base_dir: &str) -> PathBuf {
+    Path::new(base_dir).join(offline::CACHE_DIR).join(STATUS_FILE)
+}
+
+/// The statuses recorded in `base_dir`; none if there are none or they can't be read.
+pub fn load(base_dir: &str) -> BTreeMap<String, CollectionStatus> {
+    let path = status_path(base_dir);
+    let Ok(json) = fs::read_to_string(&path) else { return BTreeMap::new() };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable collection statuses {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+/// Records the statuses of `base_dir`.
+pub fn save(base_dir: &str, statuses: &BTreeMap<String, CollectionStatus>) -> Result<()> {
+    let path = status_path(base_dir);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("Failed to create cache directory: {}", dir.display()))?;
+    }
+    let json = serde_json::to_string_pretty(statuses).context("Failed to serialize collection statuses")?;
+    fs_util::atomic_write(&path, json).context(format!("Failed to write collection statuses {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statuses_round_trip_and_summarize() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_str().unwrap();
+        assert!(load(base).is_empty());
+        let now = Utc::now();
+        let status = CollectionStatus { items: 1234, synced: Some((now - chrono::Duration::minutes(90)).to_rfc3339()), new_items: 5, opened: None, title: None };
+        save(base, &BTreeMap::from([("etree".to_string(), status.clone())])).unwrap();
+        assert_eq!(load(base)["etree"], status);
+
+        assert_eq!(status.summary(now), " · 1234 items · synced 1h 30m ago");
+        assert_eq!(CollectionStatus { items: 1, ..Default::default() }.summary(now), " · 1 item");
+        fs::write(status_path(base), "[").unwrap();
+        assert!(load(base).is_empty());
+    }
+}
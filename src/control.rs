@@ -0,0 +1,154 @@
+use anyhow::Result;
+use std::{collections::HashSet, fmt, sync::Mutex};
+use tokio::sync::Notify;
+
+/// Pause/resume/cancel switches for running downloads, shared by the UI and every
+/// download task (like [`crate::throttle::Throttle`], changes apply to running transfers).
+///
+/// Tasks call [`DownloadControl::checkpoint`] between chunks, files and items: it waits
+/// while the download is paused and fails with [`Cancelled`] once it is cancelled.
+/// Cancelling only stops the transfers; `.part` files stay so a later download resumes.
+#[derive(Debug, Default)]
+pub struct DownloadControl {
+    state: Mutex<ControlState>,
+    changed: Notify,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused_all: bool,
+    cancelled_all: bool,
+    paused_items: HashSet<String>,
+    cancelled_items: HashSet<String>,
+}
+
+/// The error a download fails with after being cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl DownloadControl {
+    /// Pauses every download, or resumes them if they are paused. Returns whether they are now paused.
+    pub fn toggle_pause_all(&self) -> bool {
+        self.update(|state| {
+            state.paused_all = !state.paused_all;
+            state.paused_all
+        })
+    }
+
+    /// Pauses every download (e.g. when the disk is full) until they are resumed.
+    pub fn pause_all(&self) {
+        self.update(|state| state.paused_all = true);
+    }
+
+    /// Pauses the downloads of `item_id`, or resumes them. Returns whether it is now paused.
+    pub fn toggle_pause_item(&self, item_id: &str) -> bool {
+        self.update(|state| {
+            if !state.paused_items.remove(item_id) {
+                state.paused_items.insert(item_id.to_string());
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    pub fn cancel_item(&self, item_id: &str) {
+        self.update(|state| state.cancelled_items.insert(item_id.to_string()));
+    }
+
+    /// Cancels every download using this control. It stays cancelled, so new downloads
+    /// need a fresh control.
+    pub fn cancel_all(&self) {
+        self.update(|state| state.cancelled_all = true);
+    }
+
+    /// Drops the switches for an item that finished, so downloading it again starts afresh.
+    pub fn forget_item(&self, item_id: &str) {
+        self.update(|state| {
+            state.paused_items.remove(item_id);
+            state.cancelled_items.remove(item_id);
+        });
+    }
+
+    pub fn is_paused_all(&self) -> bool {
+        self.state.lock().unwrap().paused_all
+    }
+
+    /// Whether `item_id` is paused, on its own or with everything else.
+    pub fn is_paused(&self, item_id: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.paused_all || state.paused_items.contains(item_id)
+    }
+
+    pub fn is_cancelled(&self, item_id: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.cancelled_all || state.cancelled_items.contains(item_id)
+    }
+
+    /// Waits while `item_id` is paused; fails with [`Cancelled`] if it is (or gets) cancelled.
+    pub async fn checkpoint(&self, item_id: &str) -> Result<()> {
+        loop {
+            // Registered before checking, so a change between the check and the wait isn't missed
+            let changed = self.changed.notified();
+            if self.is_cancelled(item_id) {
+                return Err(Cancelled.into());
+            }
+            if !self.is_paused(item_id) {
+                return Ok(());
+            }
+            changed.await;
+        }
+    }
+
+    fn update<T>(&self, change: impl FnOnce(&mut ControlState) -> T) -> T {
+        let result = change(&mut self.state.lock().unwrap());
+        self.changed.notify_waiters();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_checkpoint_waits_while_paused_and_fails_when_cancelled() {
+        let control = Arc::new(DownloadControl::default());
+        control.checkpoint("a").await.expect("Nothing paused");
+
+        assert!(control.toggle_pause_item("a"));
+        control.checkpoint("b").await.expect("Only 'a' is paused");
+        let waiting = tokio::spawn({
+            let control = Arc::clone(&control);
+            async move { control.checkpoint("a").await }
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!waiting.is_finished(), "Paused item waits");
+        assert!(!control.toggle_pause_item("a"));
+        waiting.await.unwrap().expect("Resumed item continues");
+
+        control.toggle_pause_all();
+        let waiting = tokio::spawn({
+            let control = Arc::clone(&control);
+            async move { control.checkpoint("b").await }
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        control.cancel_item("b");
+        let err = waiting.await.unwrap().unwrap_err();
+        assert!(err.is::<Cancelled>());
+
+        control.forget_item("b");
+        assert!(!control.is_cancelled("b"));
+        control.cancel_all();
+        assert!(control.is_cancelled("c"));
+    }
+}
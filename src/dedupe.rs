@@ -0,0 +1,176 @@
+use crate::verify;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::sync::OnceCell;
+
+/// How a file whose content is already on disk (same MD5 and size) is put in place
+/// instead of being downloaded again.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupeMode {
+    /// Always download.
+    #[default]
+    Off,
+    /// Hard link to the existing copy (same filesystem only; falls back to downloading).
+    Hardlink,
+    /// Symbolic link to the existing copy.
+    Symlink,
+}
+
+impl fmt::Display for DedupeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DedupeMode::Off => write!(f, "Off"),
+            DedupeMode::Hardlink => write!(f, "Hard links"),
+            DedupeMode::Symlink => write!(f, "Symlinks"),
+        }
+    }
+}
+
+impl DedupeMode {
+    /// Next mode in settings order (Off -> Hardlink -> Symlink -> Off).
+    pub fn cycle(self) -> Self {
+        match self {
+            DedupeMode::Off => DedupeMode::Hardlink,
+            DedupeMode::Hardlink => DedupeMode::Symlink,
+            DedupeMode::Symlink => DedupeMode::Off,
+        }
+    }
+}
+
+/// Content already in the download directory, by MD5.
+///
+/// Seeded on first use from the checksum manifests of earlier downloads (see
+/// [`verify::MANIFEST_SUFFIX`]) and extended with every file completed during the run,
+/// so items downloaded with checksum manifests turned off are only known within a run.
+pub struct DedupeIndex {
+    mode: DedupeMode,
+    base_dir: PathBuf,
+    scanned: OnceCell<()>,
+    by_md5: Mutex<HashMap<String, PathBuf>>,
+}
+
+impl DedupeIndex {
+    pub fn new(mode: DedupeMode, base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode,
+            base_dir: base_dir.into(),
+            scanned: OnceCell::new(),
+            by_md5: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Remembers that `path` holds the content with `md5`. The first copy wins.
+    pub fn record(&self, md5: &str, path: &Path) {
+        let mut by_md5 = self.by_md5.lock().unwrap_or_else(|e| e.into_inner());
+        by_md5.entry(md5.to_ascii_lowercase()).or_insert_with(|| path.to_path_buf());
+    }
+
+    /// Puts the known copy of `md5` at `target` if one exists with `size` bytes.
+    /// Returns the copy linked to; `Ok(None)` if there is none, so the file should be
+    /// downloaded. The target's parent directory must exist.
+    pub async fn link_existing(&self, md5: &str, size: u64, target: &Path) -> io::Result<Option<PathBuf>> {
+        self.scanned
+            .get_or_init(|| async {
+                let base_dir = self.base_dir.clone();
+                let found = tokio::task::spawn_blocking(move || scan_manifests(&base_dir)).await.unwrap_or_default();
+                info!("Deduplication index: {} file(s) from checksum manifests", found.len());
+                let mut by_md5 = self.by_md5.lock().unwrap_or_else(|e| e.into_inner());
+                for (md5, path) in found {
+                    by_md5.entry(md5).or_insert(path);
+                }
+            })
+            .await;
+        let source = self.by_md5.lock().unwrap_or_else(|e| e.into_inner()).get(&md5.to_ascii_lowercase()).cloned();
+        let Some(source) = source.filter(|source| source != target) else { return Ok(None) };
+        if !fs::metadata(&source).is_ok_and(|m| m.is_file() && m.len() == size) {
+            debug!("Known copy '{}' is gone or changed; downloading instead", source.display());
+            return Ok(None);
+        }
+        let (mode, link_source, link_target) = (self.mode, source.clone(), target.to_path_buf());
+        tokio::task::spawn_blocking(move || link(mode, &link_source, &link_target)).await.map_err(io::Error::other)??;
+        Ok(Some(source))
+    }
+}
+
+fn link(mode: DedupeMode, source: &Path, target: &Path) -> io::Result<()> {
+    match mode {
+        DedupeMode::Off => Err(io::Error::other("deduplication is off")),
+        DedupeMode::Hardlink => fs::hard_link(source, target),
+        DedupeMode::Symlink => {
+            // Absolute, so the link survives wherever the download directory is opened from
+            let source = fs::canonicalize(source)?;
+            #[cfg(unix)]
+            return std::os::unix::fs::symlink(source, target);
+            #[cfg(windows)]
+            return std::os::windows::fs::symlink_file(source, target);
+        }
+    }
+}
+
+/// MD5s listed in the checksum manifests of items in `base_dir` (`<base>/<item>` and
+/// `<base>/<collection>/<item>`), mapped to files that still exist.
+fn scan_manifests(base_dir: &Path) -> HashMap<String, PathBuf> {
+    let mut found = HashMap::new();
+    let subdirs = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.is_dir() && !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let item_dirs = subdirs(base_dir).into_iter().flat_map(|dir| {
+        let nested = subdirs(&dir);
+        std::iter::once(dir).chain(nested)
+    });
+    for item_dir in item_dirs {
+        let Some(manifest) = verify::find_manifest(&item_dir) else { continue };
+        let Ok(text) = fs::read_to_string(&manifest) else { continue };
+        for entry in verify::parse_manifest(&text).unwrap_or_default() {
+            let Some(md5) = entry.md5 else { continue };
+            let path = entry.name.split('/').fold(item_dir.clone(), |path, segment| path.join(segment));
+            if path.is_file() {
+                found.entry(md5.to_ascii_lowercase()).or_insert(path);
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_links_content_from_manifests_and_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_item = dir.path().join("coll/old_item");
+        fs::create_dir_all(&old_item).unwrap();
+        fs::write(old_item.join("a.flac"), b"abc").unwrap();
+        let listed = vec![(old_item.join("a.flac"), "a.flac".to_string(), Some("900150983CD24FB0D6963F7D28E17F72".to_string()))];
+        verify::write_manifest(old_item.clone(), "old_item".to_string(), listed).await.unwrap();
+
+        let new_item = dir.path().join("new_item");
+        fs::create_dir_all(&new_item).unwrap();
+        let index = DedupeIndex::new(DedupeMode::Hardlink, dir.path());
+        let linked = index.link_existing("900150983cd24fb0d6963f7d28e17f72", 3, &new_item.join("a.flac")).await.unwrap();
+        assert_eq!(linked, Some(old_item.join("a.flac")));
+        assert_eq!(fs::read(new_item.join("a.flac")).unwrap(), b"abc");
+        assert_eq!(index.link_existing("900150983cd24fb0d6963f7d28e17f72", 4, &new_item.join("b.flac")).await.unwrap(), None, "Size differs");
+        assert_eq!(index.link_existing("unknown", 3, &new_item.join("c.flac")).await.unwrap(), None);
+
+        fs::write(new_item.join("d.txt"), b"def").unwrap();
+        let symlinks = DedupeIndex::new(DedupeMode::Symlink, dir.path());
+        symlinks.record("4ed9407630eb1000c0f6b63842defa7d", &new_item.join("d.txt"));
+        assert!(symlinks.link_existing("4ed9407630eb1000c0f6b63842defa7d", 3, &old_item.join("d.txt")).await.unwrap().is_some());
+        assert!(fs::symlink_metadata(old_item.join("d.txt")).unwrap().file_type().is_symlink());
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use hickory_resolver::{
+    config::{LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    system_conf, TokioAsyncResolver,
+};
+use log::{info, warn};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, OnceLock},
+};
+
+/// Number of DNS answers kept in the resolver's LRU cache. Bulk metadata fetches hit the
+/// same handful of archive.org hosts thousands of times, so this mostly saves repeats.
+const DNS_CACHE_SIZE: usize = 1024;
+/// Port used for nameservers given without one.
+const DEFAULT_DNS_PORT: u16 = 53;
+
+/// Resolver options taken from the user's settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DnsConfig {
+    /// Nameservers (`ip` or `ip:port`). Empty means use the system configuration.
+    pub nameservers: Vec<String>,
+    /// Only look up A records (for hosts with broken IPv6 connectivity).
+    pub ipv4_only: bool,
+}
+
+/// Caching resolver plugged into reqwest in place of the blocking system resolver.
+///
+/// The underlying hickory resolver is built on first lookup so that the client can be
+/// constructed outside a Tokio runtime.
+#[derive(Clone)]
+pub struct CachingResolver {
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    resolver: Arc<OnceLock<TokioAsyncResolver>>,
+}
+
+impl CachingResolver {
+    /// Builds a resolver from `config`. Fails if a nameserver entry can't be parsed.
+    pub fn new(config: &DnsConfig) -> Result<Self> {
+        let (resolver_config, mut opts) = if config.nameservers.is_empty() {
+            system_conf::read_system_conf().unwrap_or_else(|e| {
+                warn!("Failed to read system DNS configuration ({}), falling back to defaults", e);
+                (ResolverConfig::default(), ResolverOpts::default())
+            })
+        } else {
+            let mut resolver_config = ResolverConfig::new();
+            for entry in &config.nameservers {
+                let socket_addr = parse_nameserver(entry)?;
+                resolver_config.add_name_server(NameServerConfig::new(socket_addr, Protocol::Udp));
+                resolver_config.add_name_server(NameServerConfig::new(socket_addr, Protocol::Tcp));
+            }
+            (resolver_config, ResolverOpts::default())
+        };
+        opts.cache_size = DNS_CACHE_SIZE;
+        if config.ipv4_only {
+            opts.ip_strategy = LookupIpStrategy::Ipv4Only;
+        }
+        info!(
+            "DNS resolver configured: nameservers={}, ipv4_only={}",
+            if config.nameservers.is_empty() { "system".to_string() } else { config.nameservers.join(",") },
+            config.ipv4_only
+        );
+        Ok(Self { config: resolver_config, opts, resolver: Arc::new(OnceLock::new()) })
+    }
+
+    fn resolver(&self) -> &TokioAsyncResolver {
+        self.resolver
+            .get_or_init(|| TokioAsyncResolver::tokio(self.config.clone(), self.opts.clone()))
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let this = self.clone();
+        Box::pin(async move {
+            let lookup = this.resolver().lookup_ip(name.as_str()).await?;
+            // Port 0 is replaced by reqwest with the URL's port
+            let addrs: Addrs = Box::new(
+                lookup.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect::<Vec<_>>().into_iter(),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// Parses `ip` or `ip:port` (IPv6 with a port must use `[ip]:port`).
+fn parse_nameserver(entry: &str) -> Result<SocketAddr> {
+    let entry = entry.trim();
+    if let Ok(addr) = entry.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    entry
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, DEFAULT_DNS_PORT))
+        .map_err(|_| anyhow!("Invalid DNS nameserver '{}': expected an IP address or ip:port", entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nameserver_variants() {
+        assert_eq!(parse_nameserver("1.1.1.1").unwrap(), "1.1.1.1:53".parse().unwrap());
+        assert_eq!(parse_nameserver(" 9.9.9.9:5353 ").unwrap(), "9.9.9.9:5353".parse().unwrap());
+        assert_eq!(parse_nameserver("2606:4700::1111").unwrap(), "[2606:4700::1111]:53".parse().unwrap());
+        assert!(parse_nameserver("dns.example").is_err());
+    }
+
+    #[test]
+    fn test_custom_nameservers_and_ipv4_only() {
+        let config = DnsConfig { nameservers: vec!["8.8.8.8".to_string()], ipv4_only: true };
+        let resolver = CachingResolver::new(&config).unwrap();
+        assert_eq!(resolver.config.name_servers().len(), 2); // UDP + TCP
+        assert_eq!(resolver.opts.ip_strategy, LookupIpStrategy::Ipv4Only);
+        assert_eq!(resolver.opts.cache_size, DNS_CACHE_SIZE);
+    }
+
+    #[test]
+    fn test_invalid_nameserver_is_rejected() {
+        let config = DnsConfig { nameservers: vec!["not-an-ip".to_string()], ipv4_only: false };
+        assert!(CachingResolver::new(&config).is_err());
+    }
+}
@@ -0,0 +1,2511 @@
+use crate::{
+    app::{AppRateLimiter, DownloadAction, DownloadProgress},
+    archive_api::{self, ItemDetails},
+    claims,
+    control::DownloadControl,
+    dedupe::DedupeIndex,
+    exclusions::ExclusionList,
+    failures::ErrorKind,
+    fetch_filter,
+    file_history::{FileHistory, FileRecord},
+    forecast,
+    ipfs,
+    paths::{self, PathPolicy},
+    perf,
+    playlist,
+    politeness::HostPacer,
+    rate_control,
+    presets::{self, FilePreset, SourcePolicy},
+    replicate::{self, Replication},
+    report::{CollectionRun, FileOutcome, FileResult},
+    retry::RetryState,
+    saved_search,
+    scheduler::{DownloadScheduler, Ticket},
+    segments::{self, SegmentPolicy},
+    session::DownloadJob,
+    settings::DownloadMode,
+    storage::{self, ObjectUpload, S3Bucket, Storage},
+    sidecar::{self, SidecarFormat},
+    tagging::{self, TagPolicy, Tags},
+    throttle::Throttle,
+    torrent,
+    torrent_backend::TorrentBackend,
+    transfers,
+    layout,
+    musicbrainz::{MusicBrainz, ReleaseMatch},
+    offline,
+    verify,
+};
+use anyhow::{anyhow, Context, Result};
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use reqwest::{header::RANGE, Client, StatusCode};
+use std::{collections::BTreeMap, io, ops::Range, path::{Path, PathBuf}, sync::Arc};
+use tokio::fs::{self, File as TokioFile}; // Alias tokio::fs::File to avoid clash with std::fs::File
+use tokio::io::{AsyncReadExt, AsyncWriteExt}; // Add AsyncReadExt for reading cache file
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::Duration;
+
+/// Metadata requests made for one item before leaving it to the retry queue.
+const MAX_DETAILS_ATTEMPTS: u32 = 5;
+/// Requests made for one file before giving up (each resumes where the last stopped).
+const MAX_FILE_ATTEMPTS: u32 = 3;
+/// Wait before resuming an interrupted file, multiplied by the attempt number.
+const FILE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Shared handles every download task needs.
+///
+/// Cheap to clone: all fields are reference-counted or channel handles.
+#[derive(Clone)]
+pub struct DownloadContext {
+    /// HTTP client used for metadata and file requests.
+    pub client: Client,
+    /// Root of the download directory.
+    pub base_dir: String,
+    /// Channel for reporting progress back to the UI (or CLI).
+    pub progress_tx: mpsc::Sender<DownloadProgress>,
+    /// Hands out the concurrent file download slots shared by all running jobs.
+    pub scheduler: Arc<DownloadScheduler>,
+    /// This job's turn in the scheduler (its priority class).
+    pub ticket: Ticket,
+    /// Limits concurrent *item processing* tasks within a collection download.
+    pub collection_item_semaphore: Arc<Semaphore>,
+    /// Shared API rate limiter.
+    pub rate_limiter: AppRateLimiter,
+    /// How identifiers and file names are mapped onto local paths.
+    pub path_policy: PathPolicy,
+    /// Human-readable metadata file written next to each item's files.
+    pub sidecar: SidecarFormat,
+    /// Write an `.m3u8` playlist into downloaded audio items.
+    pub write_playlist: bool,
+    /// Write a `<item>_checksums.tsv` manifest (size, MD5, SHA-256) into downloaded items.
+    pub write_checksums: bool,
+    /// Library layout template by collection (see [`crate::layout`]); items of the other
+    /// collections use the default `[collection] / item` folders.
+    pub library_layouts: Arc<BTreeMap<String, String>>,
+    /// Fetch filter by collection (see [`crate::fetch_filter`]) narrowing collection downloads.
+    pub fetch_filters: Arc<BTreeMap<String, String>>,
+    /// Links files whose content is already on disk instead of downloading them, if enabled.
+    pub dedupe: Option<Arc<DedupeIndex>>,
+    /// Files downloaded into `base_dir` so far (shared by all jobs downloading into it).
+    pub history: Arc<FileHistory>,
+    /// Download files again even when a complete copy is already on disk.
+    pub force: bool,
+    /// Writes tags from archive.org metadata into downloaded MP3/FLAC files, if enabled.
+    pub tag_audio: Option<TagPolicy>,
+    /// Matches downloaded audio items with MusicBrainz releases, if enabled.
+    pub musicbrainz: Option<Arc<MusicBrainz>>,
+    /// IPFS API that completed item directories are added to, if enabled.
+    pub ipfs_api: Option<String>,
+    /// Remote storage completed item directories are pushed to, if any is configured.
+    pub replication: Option<Arc<Replication>>,
+    /// Where downloaded files are written: below `base_dir`, or into a bucket.
+    pub storage: Storage,
+    /// Whether the client carries archive.org login cookies.
+    pub authenticated: bool,
+    /// Spaces out requests to the same datanode (shared by all download tasks).
+    pub host_pacer: Arc<HostPacer>,
+    /// Identifiers skipped by collection downloads.
+    pub exclusions: Arc<ExclusionList>,
+    /// Which files of each item are downloaded (Direct mode).
+    pub file_preset: FilePreset,
+    /// Whether original uploads, derivatives or both are downloaded (Direct mode).
+    pub source_policy: SourcePolicy,
+    /// Subtitle languages always downloaded for movie items, or `None` to follow the preset.
+    pub subtitle_languages: Option<Arc<Vec<String>>>,
+    /// Download filter entries each file must match (see [`presets::passes_file_filter`]).
+    pub file_filter: Arc<Vec<String>>,
+    /// Torrent client that TorrentOnly downloads are added to, if any.
+    pub torrent_backend: Option<Arc<dyn TorrentBackend>>,
+    /// Which files are fetched over several connections at once.
+    pub segments: SegmentPolicy,
+    /// Global download speed cap (shared by all download tasks, adjustable while they run).
+    pub throttle: Arc<Throttle>,
+    /// Pause/cancel switches set from the Downloads view.
+    pub control: Arc<DownloadControl>,
+    /// Earlier failed attempts, when this job is a retry; failing again counts on from them.
+    pub retry: Option<RetryState>,
+}
+
+/// Runs a single [`DownloadAction`] to completion.
+///
+/// `current_collection` provides the collection context used to build item paths for
+/// item and file downloads. Top-level failures (e.g. the identifier list could not be
+/// fetched) are returned as errors; per-file failures are reported via progress messages.
+pub async fn run_download_action(
+    ctx: &DownloadContext,
+    action: DownloadAction,
+    mode: DownloadMode,
+    current_collection: Option<&str>,
+) -> Result<()> {
+    match action {
+        DownloadAction::ItemAllFiles(item_id) => {
+            download_item(ctx, current_collection, &item_id, mode).await
+        }
+        DownloadAction::File(item_id, file) => {
+            let _claim = match claims::try_claim(&ctx.base_dir, &item_id, &ctx.path_policy) {
+                Ok(Some(claim)) => Some(claim),
+                Ok(None) => return Err(anyhow!("Item '{}' is being downloaded by another instance", item_id)),
+                Err(e) => {
+                    warn!("Could not claim item '{}', downloading without a claim: {:#}", item_id, e);
+                    None
+                }
+            };
+            // Mode doesn't apply here, always download the specific file
+            download_single_file(ctx, current_collection, &item_id, &file, &[]).await
+        }
+        DownloadAction::Collection(collection_id) => {
+            download_collection(ctx, &collection_id, mode).await
+        }
+        DownloadAction::Items(item_ids) => {
+            download_items(ctx, current_collection, item_ids, mode).await
+        }
+    }
+}
+
+/// Downloads a single file.
+/// Path: base_dir / [collection_id] / item_id / filename
+///
+/// If archive.org can't deliver the file, it is fetched from the item's `mirrors`
+/// (datanode directory URLs, see [`archive_api::ItemDetails::mirror_base_urls`]) in turn.
+///
+/// The outcome is also reported as [`DownloadProgress::FileFinished`] for the run report.
+pub async fn download_single_file(
+    ctx: &DownloadContext,
+    collection_id: Option<&str>, // Added: Optional collection context
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    mirrors: &[String],
+) -> Result<()> {
+    // Torrent files go next to the item directory, other files into it
+    if file_details.name.ends_with("_archive.torrent") && collection_id.is_none() {
+        warn!("Attempting to download torrent file '{}' for item '{}' without collection context. Placing in base directory.", file_details.name, item_id);
+    }
+    let file_path = paths::download_path(&ctx.base_dir, collection_id, item_id, &file_details.name, &ctx.path_policy);
+    download_file_to(ctx, item_id, file_details, mirrors, file_path).await
+}
+
+/// Downloads a single file to `file_path` and reports the outcome like
+/// [`download_single_file`].
+async fn download_file_to(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    mirrors: &[String],
+    file_path: PathBuf,
+) -> Result<()> {
+    let result = transfer_file(ctx, item_id, file_details, mirrors, file_path).await;
+    let file_result = match &result {
+        Ok(file_result) => file_result.clone(),
+        Err(e) => FileResult {
+            item_id: item_id.to_string(),
+            name: file_details.name.clone(),
+            outcome: FileOutcome::Failed(format!("{:#}", e)),
+            bytes: 0,
+            size_verified: None,
+        },
+    };
+    let _ = ctx.progress_tx.send(DownloadProgress::FileFinished(file_result)).await;
+    result.map(|_| ())
+}
+
+async fn transfer_file(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    mirrors: &[String],
+    file_path: PathBuf,
+) -> Result<FileResult> {
+    let file_result = |outcome, bytes, size_verified| FileResult {
+        item_id: item_id.to_string(),
+        name: file_details.name.clone(),
+        outcome,
+        bytes,
+        size_verified,
+    };
+    let progress_tx = &ctx.progress_tx;
+    info!("Starting download_single_file: item='{}', file='{}', path='{}'", item_id, file_details.name, file_path.display());
+    if let Storage::S3(bucket) = &ctx.storage {
+        return transfer_to_bucket(ctx, item_id, file_details, mirrors, &file_path, bucket).await;
+    }
+
+    // --- Idempotency Check ---
+
+    let expected_size_str = file_details.size.as_deref();
+    let expected_size: Option<u64> = expected_size_str.and_then(|s| s.parse().ok());
+
+    if let (Some(expected), false) = (expected_size, ctx.force) {
+        // Use tokio::fs::metadata here
+        match fs::metadata(&file_path).await {
+            Ok(metadata) => {
+                if metadata.is_file() && metadata.len() == expected && is_complete(ctx, item_id, file_details, &file_path, expected).await {
+                    info!("Skipping existing file with matching size and MD5: '{}'", file_path.display());
+                    if let (Some(dedupe), Some(md5)) = (&ctx.dedupe, &file_details.md5) {
+                        dedupe.record(md5, &file_path);
+                    }
+                    // Send FileCompleted immediately if skipped
+                    let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
+                    // Also send a status message for clarity
+                    let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping (exists): {}", file_details.name))).await;
+                    return Ok(file_result(FileOutcome::AlreadyPresent, expected, Some(true))); // File exists and size matches, skip download - NO PERMIT USED
+                } else {
+                     debug!("Existing file found but size mismatch or not a file: '{}'. Proceeding with download.", file_path.display());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                 debug!("File not found: '{}'. Proceeding with download.", file_path.display());
+            }
+            Err(e) => {
+                 warn!("Failed to get metadata for '{}': {}. Proceeding with download.", file_path.display(), e);
+            }
+        }
+        // If metadata check fails or size mismatch, continue to acquire permit and download
+    } else {
+         // If expected size is unknown, we still need to acquire permit before checking/downloading
+         // Log warning later if needed after acquiring permit
+         debug!("File size unknown for '{}'. Will acquire permit and download.", file_details.name);
+    }
+    // --- End Idempotency Check ---
+
+    // The same content under another identifier is linked instead of downloaded again
+    if let (Some(dedupe), Some(md5), Some(expected)) = (&ctx.dedupe, &file_details.md5, expected_size) {
+        if let Some(parent_dir) = file_path.parent() {
+            fs::create_dir_all(parent_dir).await.context(format!("Failed to create download directory '{}'", parent_dir.display()))?;
+        }
+        match dedupe.link_existing(md5, expected, &file_path).await {
+            Ok(Some(source)) => {
+                info!("Linked '{}' to identical '{}'", file_path.display(), source.display());
+                let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Linked (duplicate): {}", file_details.name))).await;
+                return Ok(file_result(FileOutcome::Linked, expected, Some(true)));
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to link '{}' to an identical file: {}. Downloading it instead.", file_path.display(), e),
+        }
+    }
+
+    // Lending/stream-only files can't be fetched anonymously; don't waste a request on them
+    if file_details.private && !ctx.authenticated {
+        info!("Skipping restricted file '{}' of item '{}': not logged in", file_details.name, item_id);
+        let _ = progress_tx.send(DownloadProgress::FileRestricted(item_id.to_string(), file_details.name.clone())).await;
+        return Ok(file_result(FileOutcome::Restricted, 0, None));
+    }
+
+    ctx.control.checkpoint(item_id).await?; // Paused downloads don't take a slot
+    // --- Acquire Download Slot ---
+    // Acquire a slot *before* making network request or creating file.
+    // The slot is stored in `_slot` and handed on automatically
+    // when this function returns (success or error).
+    debug!("Waiting for a {} download slot for file: {}", ctx.ticket.priority.label(), file_details.name);
+    let mut slot = Some(ctx.scheduler.acquire(ctx.ticket).await);
+    debug!("Acquired download slot for file: {}", file_details.name);
+    // --- Download Slot Acquired ---
+
+
+    // --- Wait for Rate Limiter ---
+    debug!("Waiting for rate limit permit for file: {}", file_details.name);
+    ctx.rate_limiter.until_ready().await;
+    debug!("Acquired rate limit permit for file: {}", file_details.name);
+    // --- Rate Limit Permit Acquired ---
+
+
+    // Log unknown size warning if necessary
+    if expected_size.is_none() {
+        warn!("File size is unknown for '{}'. Downloading anyway.", file_details.name);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Warning: Unknown size for {}, downloading anyway", file_details.name))).await;
+    }
+
+
+    // URL encode the filename part? Archive.org seems tolerant but might be safer.
+    // Using raw name for now.
+    let mut download_url = archive_api::download_url(item_id, &file_details.name);
+    let mut mirror_urls = mirrors.iter().map(|base| format!("{}/{}", base, file_details.name));
+
+    // Send status via progress channel
+    // let _ = progress_tx.send(DownloadProgress::Status(format!("Checking: {}", file_details.name))).await;
+
+    // Ensure target directory exists
+    if let Some(parent_dir) = file_path.parent() {
+        debug!("Ensuring download directory exists: {}", parent_dir.display());
+        fs::create_dir_all(parent_dir).await.context(format!("Failed to create download directory '{}'", parent_dir.display()))?;
+    } else {
+        error!("Could not determine parent directory for path: {}", file_path.display());
+        return Err(anyhow!("Invalid download file path: {}", file_path.display()));
+    }
+
+    info!("Downloading '{}' from {}", file_details.name, download_url);
+    let _ = progress_tx.send(DownloadProgress::Status(format!("Downloading: {}", file_details.name))).await;
+
+    // Data goes to `<file>.part` until it is complete and verified, so an interrupted
+    // transfer never looks finished and can be resumed with a Range request.
+    let part_path = paths::part_path(&file_path);
+    let mut attempt = 1;
+    loop {
+        ctx.control.checkpoint(item_id).await?;
+        if slot.is_none() {
+            slot = Some(ctx.scheduler.acquire(ctx.ticket).await);
+        }
+        let transfer = match ctx.segments.plan(expected_size) {
+            Some(ranges) => fetch_segmented(ctx, item_id, file_details, &download_url, &part_path, ranges).await?,
+            None => fetch_part(ctx, item_id, file_details, &download_url, &part_path, expected_size).await?,
+        };
+        let err_msg = match transfer {
+            PartTransfer::Complete => break,
+            PartTransfer::Restricted => {
+                let _ = progress_tx.send(DownloadProgress::FileRestricted(item_id.to_string(), file_details.name.clone())).await;
+                return Ok(file_result(FileOutcome::Restricted, 0, None));
+            }
+            PartTransfer::DiskFull => {
+                // Waits at the checkpoint until resumed; not a failed attempt. The slot is given
+                // back meanwhile so a paused file doesn't hold up the rest of the queue.
+                slot = None;
+                continue;
+            }
+            PartTransfer::Interrupted(e) if attempt < MAX_FILE_ATTEMPTS => {
+                let delay = FILE_RETRY_DELAY * attempt;
+                warn!("Download of '{}' interrupted (attempt {}): {:#}. Resuming in {}s...", file_details.name, attempt, e, delay.as_secs());
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Resuming {} (attempt {}/{})", file_details.name, attempt + 1, MAX_FILE_ATTEMPTS))).await;
+                tokio::time::sleep(delay).await;
+                ctx.rate_limiter.until_ready().await; // Also waits out a slow-down archive.org asked for
+                attempt += 1;
+                continue;
+            }
+            PartTransfer::Interrupted(e) => format!("Download of '{}' failed after {} attempts: {:#}", file_details.name, attempt, e),
+            PartTransfer::Refused(e) => format!("{:#}", e),
+        };
+        // Another datanode holding the item may still serve the file; the `.part` file carries over
+        match mirror_urls.next() {
+            Some(mirror_url) => {
+                warn!("{}. Trying mirror {}", err_msg, mirror_url);
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Trying another server for {}", file_details.name))).await;
+                download_url = mirror_url;
+                attempt = 1;
+            }
+            None => {
+                error!("{}", err_msg);
+                let _ = progress_tx.send(DownloadProgress::Error(err_msg.clone())).await;
+                return Err(anyhow!(err_msg));
+            }
+        }
+    }
+
+    let bytes_on_disk = fs::metadata(&part_path).await.context(format!("Failed to read size of '{}'", part_path.display()))?.len();
+    if let Some(expected) = expected_size {
+        if bytes_on_disk != expected {
+            // Too long can't be fixed by resuming; start from scratch next time
+            let _ = fs::remove_file(&part_path).await;
+            let err_msg = format!("Size mismatch for '{}': expected {} bytes, got {}", file_details.name, expected, bytes_on_disk);
+            error!("{}", err_msg);
+            let _ = progress_tx.send(DownloadProgress::Error(err_msg.clone())).await;
+            return Err(anyhow!(err_msg));
+        }
+    }
+    fs::rename(&part_path, &file_path)
+        .await
+        .context(format!("Failed to move '{}' to '{}'", part_path.display(), file_path.display()))?;
+    info!("Successfully downloaded file '{}' ({} bytes)", file_details.name, bytes_on_disk);
+    record_file(ctx, item_id, file_details, &file_path, bytes_on_disk);
+    if let (Some(dedupe), Some(md5)) = (&ctx.dedupe, &file_details.md5) {
+        dedupe.record(md5, &file_path);
+    }
+    // Send completion via progress channel
+    let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
+
+    debug!("Releasing download permit for file: {}", file_details.name); // Log before permit is dropped
+    Ok(file_result(FileOutcome::Downloaded, bytes_on_disk, expected_size.map(|_| true)))
+}
+
+/// [`transfer_file`] for [`Storage::S3`]: streams the file into the bucket, as the object
+/// named by its path below the download directory. An object of the listed size counts
+/// as present. Files are fetched over a single connection and never linked to duplicates.
+async fn transfer_to_bucket(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    mirrors: &[String],
+    file_path: &Path,
+    bucket: &S3Bucket,
+) -> Result<FileResult> {
+    let file_result = |outcome, bytes, size_verified| FileResult {
+        item_id: item_id.to_string(),
+        name: file_details.name.clone(),
+        outcome,
+        bytes,
+        size_verified,
+    };
+    let progress_tx = &ctx.progress_tx;
+    let object_key = storage::object_key(Path::new(&ctx.base_dir), file_path)?;
+    let expected_size: Option<u64> = file_details.size.as_deref().and_then(|s| s.parse().ok());
+
+    if let (Some(expected), false) = (expected_size, ctx.force) {
+        match bucket.size(&ctx.client, &object_key).await {
+            Ok(Some(size)) if size == expected => {
+                info!("Skipping existing object with matching size: '{}'", object_key);
+                let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping (exists): {}", file_details.name))).await;
+                return Ok(file_result(FileOutcome::AlreadyPresent, expected, Some(true)));
+            }
+            Ok(_) => debug!("Object '{}' missing or of another size. Proceeding with download.", object_key),
+            Err(e) => warn!("Failed to check '{}' in the bucket: {:#}. Proceeding with download.", object_key, e),
+        }
+    }
+
+    if file_details.private && !ctx.authenticated {
+        info!("Skipping restricted file '{}' of item '{}': not logged in", file_details.name, item_id);
+        let _ = progress_tx.send(DownloadProgress::FileRestricted(item_id.to_string(), file_details.name.clone())).await;
+        return Ok(file_result(FileOutcome::Restricted, 0, None));
+    }
+
+    ctx.control.checkpoint(item_id).await?;
+    let mut slot = Some(ctx.scheduler.acquire(ctx.ticket).await);
+    ctx.rate_limiter.until_ready().await;
+    if expected_size.is_none() {
+        warn!("File size is unknown for '{}'. Downloading anyway.", file_details.name);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Warning: Unknown size for {}, downloading anyway", file_details.name))).await;
+    }
+
+    let mut download_url = archive_api::download_url(item_id, &file_details.name);
+    let mut mirror_urls = mirrors.iter().map(|base| format!("{}/{}", base, file_details.name));
+    info!("Downloading '{}' from {} into the bucket as '{}'", file_details.name, download_url, object_key);
+    let _ = progress_tx.send(DownloadProgress::Status(format!("Downloading: {}", file_details.name))).await;
+
+    // What arrived so far stays in the upload, so retries and mirrors resume like `.part` files
+    let mut upload = ObjectUpload::new(object_key, expected_size);
+    let mut attempt = 1;
+    loop {
+        let transfer = match ctx.control.checkpoint(item_id).await {
+            Ok(()) => {
+                if slot.is_none() {
+                    slot = Some(ctx.scheduler.acquire(ctx.ticket).await);
+                }
+                fetch_into_bucket(ctx, item_id, file_details, &download_url, bucket, &mut upload, expected_size).await
+            }
+            Err(e) => Err(e),
+        };
+        let transfer = match transfer {
+            Ok(transfer) => transfer,
+            Err(e) => {
+                upload.abort(&ctx.client, bucket).await; // Unlike a `.part` file, nothing outlives this call
+                return Err(e);
+            }
+        };
+        let err_msg = match transfer {
+            PartTransfer::Complete => break,
+            PartTransfer::Restricted => {
+                upload.abort(&ctx.client, bucket).await;
+                let _ = progress_tx.send(DownloadProgress::FileRestricted(item_id.to_string(), file_details.name.clone())).await;
+                return Ok(file_result(FileOutcome::Restricted, 0, None));
+            }
+            PartTransfer::DiskFull => {
+                slot = None; // Given back until the downloads are resumed
+                continue;
+            }
+            PartTransfer::Interrupted(e) if attempt < MAX_FILE_ATTEMPTS => {
+                let delay = FILE_RETRY_DELAY * attempt;
+                warn!("Download of '{}' interrupted (attempt {}): {:#}. Resuming in {}s...", file_details.name, attempt, e, delay.as_secs());
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Resuming {} (attempt {}/{})", file_details.name, attempt + 1, MAX_FILE_ATTEMPTS))).await;
+                tokio::time::sleep(delay).await;
+                ctx.rate_limiter.until_ready().await;
+                attempt += 1;
+                continue;
+            }
+            PartTransfer::Interrupted(e) => format!("Download of '{}' failed after {} attempts: {:#}", file_details.name, attempt, e),
+            PartTransfer::Refused(e) => format!("{:#}", e),
+        };
+        match mirror_urls.next() {
+            Some(mirror_url) => {
+                warn!("{}. Trying mirror {}", err_msg, mirror_url);
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Trying another server for {}", file_details.name))).await;
+                download_url = mirror_url;
+                attempt = 1;
+            }
+            None => {
+                upload.abort(&ctx.client, bucket).await;
+                error!("{}", err_msg);
+                let _ = progress_tx.send(DownloadProgress::Error(err_msg.clone())).await;
+                return Err(anyhow!(err_msg));
+            }
+        }
+    }
+
+    let received = upload.received();
+    let finished = match expected_size {
+        Some(expected) if received != expected => {
+            upload.abort(&ctx.client, bucket).await;
+            Err(anyhow!("Size mismatch for '{}': expected {} bytes, got {}", file_details.name, expected, received))
+        }
+        _ => upload.finish(&ctx.client, bucket).await.context(format!("Failed to store '{}' in the bucket", file_details.name)),
+    };
+    let size = match finished {
+        Ok(size) => size,
+        Err(e) => {
+            let err_msg = format!("{:#}", e);
+            error!("{}", err_msg);
+            let _ = progress_tx.send(DownloadProgress::Error(err_msg)).await;
+            return Err(e);
+        }
+    };
+    info!("Successfully stored file '{}' in the bucket ({} bytes)", file_details.name, size);
+    let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
+    Ok(file_result(FileOutcome::Downloaded, size, expected_size.map(|_| true)))
+}
+
+/// Whether the file already at `file_path`, which has archive.org's size, is the listed
+/// one: recorded in the download history with the same MD5, or hashing to it. Files
+/// archive.org lists no MD5 for are taken on their size alone.
+async fn is_complete(ctx: &DownloadContext, item_id: &str, file_details: &archive_api::FileDetails, file_path: &Path, size: u64) -> bool {
+    let md5 = file_details.md5.as_deref();
+    if ctx.history.is_recorded(file_path, size, md5) {
+        return true;
+    }
+    if let Some(md5) = md5 {
+        let path = file_path.to_path_buf();
+        match tokio::task::spawn_blocking(move || verify::md5_file(&path)).await {
+            Ok(Ok(local)) if local.eq_ignore_ascii_case(md5) => {}
+            Ok(Ok(local)) => {
+                warn!("'{}' has the listed size but MD5 {} instead of {}. Downloading it again.", file_path.display(), local, md5);
+                return false;
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to hash '{}': {:#}. Downloading it again.", file_path.display(), e);
+                return false;
+            }
+            Err(e) => {
+                warn!("Hashing task for '{}' failed: {}. Downloading it again.", file_path.display(), e);
+                return false;
+            }
+        }
+    }
+    // Checked once; later runs trust the history
+    record_file(ctx, item_id, file_details, file_path, size);
+    true
+}
+
+/// Adds a complete file to the download history.
+fn record_file(ctx: &DownloadContext, item_id: &str, file_details: &archive_api::FileDetails, file_path: &Path, size: u64) {
+    ctx.history.record(FileRecord {
+        identifier: item_id.to_string(),
+        name: file_details.name.clone(),
+        md5: file_details.md5.clone(),
+        size,
+        path: file_path.to_path_buf(),
+        downloaded: chrono::Local::now().to_rfc3339(),
+        local_sha256: None,
+    });
+}
+
+/// How one request for a file ended.
+enum PartTransfer {
+    /// The `.part` file holds the whole file (or everything the server sent, if the
+    /// size is unknown).
+    Complete,
+    /// The account can't access the file.
+    Restricted,
+    /// The transfer stopped early; the `.part` file is kept so the next attempt resumes.
+    Interrupted(anyhow::Error),
+    /// The server answered with an error status (e.g. 404); retrying it won't help, but
+    /// another server might have the file.
+    Refused(anyhow::Error),
+    /// The disk filled up; all downloads were paused and the transfer resumes with them.
+    DiskFull,
+}
+
+/// Pauses every download after a write to `part_path` failed for lack of space, and
+/// tells the user. What was written stays in the `.part` file.
+async fn disk_full(ctx: &DownloadContext, part_path: &Path) -> PartTransfer {
+    ctx.control.pause_all();
+    let msg = format!(
+        "Disk full while writing '{}'. All downloads are paused; free up space, then resume them with 'P' in the Downloads view.",
+        part_path.display()
+    );
+    error!("{}", msg);
+    let _ = ctx.progress_tx.send(DownloadProgress::Error(msg)).await;
+    PartTransfer::DiskFull
+}
+
+/// Bytes the listed files (with their destinations) still need on disk: files already
+/// complete count for nothing, partial ones for what is missing. Files without a size are
+/// ignored.
+async fn bytes_still_needed(files: &[(archive_api::FileDetails, PathBuf)]) -> u64 {
+    let mut needed = 0;
+    for (file, path) in files {
+        let Some(size) = file.size.as_deref().and_then(|s| s.parse::<u64>().ok()) else {
+            continue;
+        };
+        if fs::metadata(path).await.is_ok_and(|m| m.len() == size) {
+            continue;
+        }
+        let partial = fs::metadata(paths::part_path(path)).await.map_or(0, |m| m.len());
+        needed += size.saturating_sub(partial);
+    }
+    needed
+}
+
+/// Requests the part of the file not yet in `part_path` and appends it. Errors are
+/// permanent failures (e.g. disk errors); network trouble is `Interrupted`.
+async fn fetch_part(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    download_url: &str,
+    part_path: &Path,
+    expected_size: Option<u64>,
+) -> Result<PartTransfer> {
+    let mut resume_from = match fs::metadata(part_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    if let Some(expected) = expected_size {
+        if resume_from == expected {
+            return Ok(PartTransfer::Complete); // Interrupted between the last byte and the rename
+        }
+        if resume_from > expected {
+            warn!("Discarding '{}': larger than the listed {} bytes", part_path.display(), expected);
+            resume_from = 0;
+        }
+    }
+
+    let mut request = ctx.client.get(download_url);
+    if resume_from > 0 {
+        debug!("Resuming '{}' from byte {}", file_details.name, resume_from);
+        request = request.header(RANGE, format!("bytes={}-", resume_from));
+    }
+    ctx.host_pacer.wait_turn(item_id, download_url).await;
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return Ok(PartTransfer::Interrupted(anyhow!(e).context(format!("Failed to send download request for {}", file_details.name)))),
+    };
+    ctx.host_pacer.learn_item_host(item_id, response.url().as_str()); // Where archive.org redirected us
+    if let Some(outcome) = unusable_response(ctx, item_id, file_details, &response, Some(part_path), resume_from).await {
+        return Ok(outcome);
+    }
+
+    // 206 continues the partial file; a plain 200 (range ignored) starts it over
+    let mut dest = if response.status() == StatusCode::PARTIAL_CONTENT && resume_from > 0 {
+        fs::OpenOptions::new().append(true).open(part_path).await
+    } else {
+        resume_from = 0;
+        TokioFile::create(part_path).await
+    }
+    .context(format!("Failed to open partial file '{}'", part_path.display()))?;
+    let key = transfers::transfer_key(item_id, &file_details.name);
+    let _ = ctx.progress_tx.send(DownloadProgress::FileStarted { key: Arc::clone(&key), total: expected_size, resumed: resume_from }).await;
+    let (bytes_written, stopped) = write_body(ctx, item_id, file_details, response, &mut dest, part_path, &key).await?;
+    if let Some(outcome) = stopped {
+        return Ok(outcome);
+    }
+    let total = resume_from + bytes_written;
+    match expected_size {
+        Some(expected) if total < expected => {
+            Ok(PartTransfer::Interrupted(anyhow!("Connection closed after {} of {} bytes of '{}'", total, expected, file_details.name)))
+        }
+        _ => Ok(PartTransfer::Complete),
+    }
+}
+
+/// Requests the part of the file not yet in `upload` and streams it in, like
+/// [`fetch_part`] does into the `.part` file. A failed part upload interrupts the
+/// transfer; the data stays in `upload` for the next attempt.
+async fn fetch_into_bucket(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    download_url: &str,
+    bucket: &S3Bucket,
+    upload: &mut ObjectUpload,
+    expected_size: Option<u64>,
+) -> Result<PartTransfer> {
+    let resume_from = upload.received();
+    if expected_size == Some(resume_from) {
+        return Ok(PartTransfer::Complete);
+    }
+    let mut request = ctx.client.get(download_url);
+    if resume_from > 0 {
+        debug!("Resuming '{}' from byte {}", file_details.name, resume_from);
+        request = request.header(RANGE, format!("bytes={}-", resume_from));
+    }
+    ctx.host_pacer.wait_turn(item_id, download_url).await;
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return Ok(PartTransfer::Interrupted(anyhow!(e).context(format!("Failed to send download request for {}", file_details.name)))),
+    };
+    ctx.host_pacer.learn_item_host(item_id, response.url().as_str());
+    if let Some(outcome) = unusable_response(ctx, item_id, file_details, &response, None, resume_from).await {
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            upload.abort(&ctx.client, bucket).await;
+        }
+        return Ok(outcome);
+    }
+    // 206 continues the upload; a plain 200 (range ignored) starts it over
+    if resume_from > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        upload.abort(&ctx.client, bucket).await;
+    }
+
+    let key = transfers::transfer_key(item_id, &file_details.name);
+    let _ = ctx.progress_tx.send(DownloadProgress::FileStarted { key: Arc::clone(&key), total: expected_size, resumed: upload.received() }).await;
+    let transfer_started = std::time::Instant::now();
+    let mut stream = response.bytes_stream();
+    let mut bytes_received: u64 = 0;
+    let mut stopped = None;
+    while let Some(chunk_result) = stream.next().await {
+        let chunk = match chunk_result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                stopped = Some(Ok(PartTransfer::Interrupted(anyhow!(e).context(format!("Failed to read download chunk for '{}'", file_details.name)))));
+                break;
+            }
+        };
+        if let Err(e) = upload.write(&ctx.client, bucket, &chunk).await {
+            stopped = Some(Ok(PartTransfer::Interrupted(e)));
+            break;
+        }
+        bytes_received += chunk.len() as u64;
+        ctx.throttle.consume(chunk.len() as u64).await;
+        let _ = ctx.progress_tx.send(DownloadProgress::FileBytes(Arc::clone(&key), chunk.len() as u64)).await;
+        if let Err(e) = ctx.control.checkpoint(item_id).await {
+            stopped = Some(Err(e));
+            break;
+        }
+    }
+    perf::record_transfer(bytes_received, transfer_started.elapsed());
+    if let Some(outcome) = stopped {
+        return outcome;
+    }
+    match expected_size {
+        Some(expected) if upload.received() < expected => {
+            Ok(PartTransfer::Interrupted(anyhow!("Connection closed after {} of {} bytes of '{}'", upload.received(), expected, file_details.name)))
+        }
+        _ => Ok(PartTransfer::Complete),
+    }
+}
+
+/// How a response whose status rules out reading the body ends the transfer, or `None`
+/// if the body should be read. A 416 means the partial data (`resumed_from` bytes, at
+/// `path` if it is on disk) doesn't fit the file on the server any more, so it is
+/// discarded.
+async fn unusable_response(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    response: &reqwest::Response,
+    path: Option<&Path>,
+    resumed_from: u64,
+) -> Option<PartTransfer> {
+    let status = response.status();
+    if file_details.private && (status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN) {
+        // Logged in, but this account hasn't borrowed the item (or can't stream it)
+        info!("Access to restricted file '{}' of item '{}' denied: {}", file_details.name, item_id, status);
+        return Some(PartTransfer::Restricted);
+    }
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        if let Some(path) = path {
+            let _ = fs::remove_file(path).await;
+        }
+        return Some(PartTransfer::Interrupted(anyhow!("Server rejected resuming '{}' at byte {}", file_details.name, resumed_from)));
+    }
+    if rate_control::is_throttling(status) {
+        ctx.rate_limiter.throttled(rate_control::retry_after(response));
+    }
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        return Some(PartTransfer::Interrupted(anyhow!("Download request for '{}' failed: Status {}", file_details.name, status)));
+    }
+    if !status.is_success() {
+        return Some(PartTransfer::Refused(anyhow!("Download request failed for '{}': Status {}", file_details.name, status)));
+    }
+    None
+}
+
+/// Streams the response body into `dest` (the file at `path`), paced by the speed cap and
+/// reported as progress of the transfer `key`. Returns the bytes written and, if the body
+/// didn't arrive whole, how the transfer ended. Errors are permanent failures.
+async fn write_body(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    response: reqwest::Response,
+    dest: &mut TokioFile,
+    path: &Path,
+    key: &Arc<str>,
+) -> Result<(u64, Option<PartTransfer>)> {
+    let transfer_started = std::time::Instant::now();
+    let mut stream = response.bytes_stream();
+    let mut bytes_written: u64 = 0;
+    let mut interrupted = None;
+    let mut cancelled = None;
+
+    while let Some(chunk_result) = stream.next().await {
+        match chunk_result {
+            Ok(chunk) => {
+                let chunk_len = chunk.len() as u64;
+                if let Err(e) = dest.write_all(&chunk).await {
+                    if e.kind() == io::ErrorKind::StorageFull {
+                        return Ok((bytes_written, Some(disk_full(ctx, path).await)));
+                    }
+                    error!("Failed to write chunk to file '{}': {}", path.display(), e);
+                    return Err(e).context(format!("Failed to write chunk to file '{}'", path.display()));
+                }
+                bytes_written += chunk_len;
+                ctx.throttle.consume(chunk_len).await;
+                // Send byte count update
+                let _ = ctx.progress_tx.send(DownloadProgress::FileBytes(Arc::clone(key), chunk_len)).await;
+                // Pausing holds the connection open; if the server drops it, resuming retries
+                if let Err(e) = ctx.control.checkpoint(item_id).await {
+                    cancelled = Some(e);
+                    break;
+                }
+            }
+            Err(e) => {
+                interrupted = Some(anyhow!(e).context(format!("Failed to read download chunk for '{}'", file_details.name)));
+                break;
+            }
+        }
+    }
+
+    // tokio's File completes writes in the background; flush so the data is on disk
+    // (and any write error surfaces) before the file is resumed or verified.
+    if let Err(e) = dest.flush().await {
+        if e.kind() == io::ErrorKind::StorageFull {
+            return Ok((bytes_written, Some(disk_full(ctx, path).await)));
+        }
+        return Err(e).context(format!("Failed to flush partial file '{}'", path.display()));
+    }
+    perf::record_transfer(bytes_written, transfer_started.elapsed());
+    if let Some(e) = cancelled {
+        return Err(e); // The `.part` file is kept for a later download
+    }
+    Ok((bytes_written, interrupted.map(PartTransfer::Interrupted)))
+}
+
+/// Fetches the file as the byte `ranges` (see [`SegmentPolicy::plan`]) over parallel
+/// connections, then stitches them into `part_path`. Each connection waits for the rate
+/// limiter and the host pacer like any other request, and all of them share the speed cap
+/// and the file's download slot. Falls back to [`fetch_part`] when the server ignores
+/// ranges, or when a single-connection download of the file is already under way.
+async fn fetch_segmented(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    download_url: &str,
+    part_path: &Path,
+    ranges: Vec<Range<u64>>,
+) -> Result<PartTransfer> {
+    let size = ranges.last().map_or(0, |range| range.end);
+    let started = fs::metadata(part_path).await.map_or(0, |m| m.len());
+    if started == size {
+        return Ok(PartTransfer::Complete); // Stitched, but interrupted before the rename
+    }
+    if started > ranges[0].end {
+        debug!("'{}' is partly downloaded over one connection; resuming it that way", file_details.name);
+        remove_segments(part_path, &ranges).await;
+        return fetch_part(ctx, item_id, file_details, download_url, part_path, Some(size)).await;
+    }
+
+    let mut resumed = 0;
+    for range in &ranges {
+        let have = fs::metadata(segments::segment_path(part_path, range.start)).await.map_or(0, |m| m.len());
+        resumed += have.min(range.end - range.start);
+    }
+    let key = transfers::transfer_key(item_id, &file_details.name);
+    let _ = ctx.progress_tx.send(DownloadProgress::FileStarted { key: Arc::clone(&key), total: Some(size), resumed }).await;
+    debug!("Fetching '{}' over {} connections", file_details.name, ranges.len());
+    let results = futures_util::future::join_all(ranges.iter().map(|range| fetch_segment(ctx, item_id, file_details, download_url, part_path, range.clone(), &key))).await;
+
+    let mut ranges_ignored = false;
+    for result in results {
+        match result? {
+            Some(PartTransfer::Complete) => {}
+            Some(outcome) => return Ok(outcome),
+            None => ranges_ignored = true,
+        }
+    }
+    if ranges_ignored {
+        warn!("Server ignored range requests for '{}'; downloading it over one connection", file_details.name);
+        remove_segments(part_path, &ranges).await;
+        return fetch_part(ctx, item_id, file_details, download_url, part_path, Some(size)).await;
+    }
+    stitch(part_path, &ranges).await?;
+    Ok(PartTransfer::Complete)
+}
+
+/// Fetches what is missing of one byte range into its `.part` file. `None` if the server
+/// answered with the whole file instead of the range (nothing is written then).
+async fn fetch_segment(
+    ctx: &DownloadContext,
+    item_id: &str,
+    file_details: &archive_api::FileDetails,
+    download_url: &str,
+    part_path: &Path,
+    range: Range<u64>,
+    key: &Arc<str>,
+) -> Result<Option<PartTransfer>> {
+    let path = segments::segment_path(part_path, range.start);
+    let length = range.end - range.start;
+    let mut have = fs::metadata(&path).await.map_or(0, |m| m.len());
+    if have == length {
+        return Ok(Some(PartTransfer::Complete));
+    }
+    if have > length {
+        warn!("Discarding '{}': larger than its {} byte range", path.display(), length);
+        have = 0;
+    }
+
+    ctx.rate_limiter.until_ready().await; // Every connection is a request of its own
+    let request = ctx.client.get(download_url).header(RANGE, format!("bytes={}-{}", range.start + have, range.end - 1));
+    ctx.host_pacer.wait_turn(item_id, download_url).await;
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return Ok(Some(PartTransfer::Interrupted(anyhow!(e).context(format!("Failed to send download request for {}", file_details.name))))),
+    };
+    ctx.host_pacer.learn_item_host(item_id, response.url().as_str());
+    if let Some(outcome) = unusable_response(ctx, item_id, file_details, &response, Some(&path), range.start + have).await {
+        return Ok(Some(outcome));
+    }
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Ok(None);
+    }
+
+    let mut dest = if have > 0 { fs::OpenOptions::new().append(true).open(&path).await } else { TokioFile::create(&path).await }
+        .context(format!("Failed to open partial file '{}'", path.display()))?;
+    let (bytes_written, stopped) = write_body(ctx, item_id, file_details, response, &mut dest, &path, key).await?;
+    if let Some(outcome) = stopped {
+        return Ok(Some(outcome));
+    }
+    let total = have + bytes_written;
+    if total < length {
+        return Ok(Some(PartTransfer::Interrupted(anyhow!(
+            "Connection closed after {} of {} bytes of '{}' (from byte {})",
+            total,
+            length,
+            file_details.name,
+            range.start
+        ))));
+    }
+    Ok(Some(PartTransfer::Complete))
+}
+
+/// Appends the later ranges' `.part` files to the first one's, deleting each once copied.
+/// If this is interrupted, the first `.part` file is longer than its range and the next
+/// attempt resumes it over one connection.
+async fn stitch(part_path: &Path, ranges: &[Range<u64>]) -> Result<()> {
+    let mut dest = fs::OpenOptions::new().append(true).open(part_path).await.context(format!("Failed to open partial file '{}'", part_path.display()))?;
+    for range in &ranges[1..] {
+        let path = segments::segment_path(part_path, range.start);
+        let mut source = TokioFile::open(&path).await.context(format!("Failed to open partial file '{}'", path.display()))?;
+        tokio::io::copy(&mut source, &mut dest).await.context(format!("Failed to stitch '{}' into '{}'", path.display(), part_path.display()))?;
+        dest.flush().await.context(format!("Failed to flush partial file '{}'", part_path.display()))?;
+        fs::remove_file(&path).await.context(format!("Failed to remove '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Deletes the `.part` files of every range but the first.
+async fn remove_segments(part_path: &Path, ranges: &[Range<u64>]) {
+    for range in &ranges[1..] {
+        let _ = fs::remove_file(segments::segment_path(part_path, range.start)).await;
+    }
+}
+
+/// Completes an item whose `.torrent` is at `torrent_path`: TorrentEmbedded downloads its
+/// content with the built-in client, TorrentOnly hands it to the configured torrent client.
+/// Returns whether the item succeeded.
+async fn finish_torrent(
+    ctx: &DownloadContext,
+    mode: DownloadMode,
+    collection_id: Option<&str>,
+    item_id: &str,
+    torrent_path: &Path,
+    collection_dir: &Path,
+) -> bool {
+    if mode != DownloadMode::TorrentEmbedded {
+        return hand_off_torrent(ctx, item_id, torrent_path, collection_dir).await;
+    }
+    match torrent::download_item_content(ctx, collection_id, item_id, torrent_path).await {
+        Ok(()) => true,
+        Err(e) => {
+            error!("BitTorrent download of '{}' failed: {:#}", item_id, e);
+            let _ = ctx.progress_tx.send(DownloadProgress::Error(format!("BitTorrent download of {} failed: {:#}", item_id, e))).await;
+            false
+        }
+    }
+}
+
+/// Adds a downloaded `.torrent` to the configured torrent client, with its content going
+/// into `download_dir`. Returns whether the item counts as done: always when no client is
+/// configured, otherwise only if the client accepted the torrent.
+async fn hand_off_torrent(ctx: &DownloadContext, item_id: &str, torrent_path: &Path, download_dir: &Path) -> bool {
+    let Some(backend) = &ctx.torrent_backend else { return true };
+    // The daemon has its own working directory, so relative download directories won't do
+    let download_dir = std::path::absolute(download_dir).unwrap_or_else(|_| download_dir.to_path_buf());
+    match backend.add_torrent(torrent_path, &download_dir).await {
+        Ok(()) => {
+            let _ = ctx.progress_tx.send(DownloadProgress::Status(format!("Added {} to {}", item_id, backend.name()))).await;
+            true
+        }
+        Err(e) => {
+            error!("Failed to add torrent of '{}' to {}: {:#}", item_id, backend.name(), e);
+            let _ = ctx.progress_tx.send(DownloadProgress::Error(format!("{}: {:#}", backend.name(), e))).await;
+            false
+        }
+    }
+}
+
+/// Downloads all files for a given item.
+/// Path: base_dir / [collection_id] / item_id / ...
+pub async fn download_item(
+    ctx: &DownloadContext,
+    collection_id: Option<&str>, // Added: Optional collection context
+    item_id: &str,
+    mode: DownloadMode, // Added: Download mode
+) -> Result<()> {
+    let progress_tx = &ctx.progress_tx;
+    let base_dir = ctx.base_dir.as_str();
+    let collection_str = collection_id.unwrap_or("<none>");
+    info!("Starting download_item: collection='{}', item='{}', mode='{:?}'", collection_str, item_id, mode);
+    let _ = progress_tx.send(DownloadProgress::ItemStarted(item_id.to_string())).await;
+    if ctx.control.checkpoint(item_id).await.is_err() {
+        info!("Item '{}' was cancelled before it started.", item_id);
+        let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await;
+        return Ok(());
+    }
+
+    // Held until this function returns, so other instances sharing the directory skip the item
+    let _claim = match claims::try_claim(base_dir, item_id, &ctx.path_policy) {
+        Ok(Some(claim)) => {
+            debug!("Claimed item '{}' via {}", item_id, claim.path().display());
+            Some(claim)
+        }
+        Ok(None) => {
+            info!("Item '{}' is being downloaded by another instance; skipping.", item_id);
+            let _ = progress_tx.send(DownloadProgress::ItemSkippedClaimed(item_id.to_string())).await;
+            return Ok(());
+        }
+        Err(e) => {
+            warn!("Could not claim item '{}', downloading without a claim: {:#}", item_id, e);
+            None
+        }
+    };
+
+    // --- Mode-Specific Logic ---
+    // Both torrent modes start by fetching the item's .torrent
+    if mode != DownloadMode::Direct {
+        info!("{:?} mode: Attempting direct download of {}.torrent", mode, item_id);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Queueing torrent file for item: {}", item_id))).await;
+        let _ = progress_tx.send(DownloadProgress::ItemFileCount(1)).await; // The .torrent; TorrentEmbedded adds its files later
+
+        // Construct the expected torrent file details using the new format
+        let torrent_file_details = archive_api::FileDetails {
+            name: format!("{}_archive.torrent", item_id), // Use _archive.torrent format
+            source: None,
+            format: Some("Torrent".to_string()), // Indicate format if known
+            size: None, // Size is unknown without fetching metadata
+            md5: None,
+            private: false,
+            ..Default::default()
+        };
+
+        // Ensure the parent directory for the torrent file exists
+        // Path: base_dir / collection_id / item_id.torrent -> Parent: base_dir / collection_id
+        let torrent_parent_dir = paths::collection_dir(base_dir, collection_id, &ctx.path_policy);
+        debug!("Ensuring torrent parent directory exists: {}", torrent_parent_dir.display());
+        fs::create_dir_all(&torrent_parent_dir).await.context(format!("Failed to create torrent parent directory '{}'", torrent_parent_dir.display()))?;
+
+        // --- Idempotency Check for Torrent File ---
+        let torrent_file_path = paths::torrent_path(base_dir, collection_id, item_id, &ctx.path_policy);
+        match fs::metadata(&torrent_file_path).await {
+            Ok(metadata) if metadata.is_file() => {
+                info!("Skipping existing torrent file: '{}'", torrent_file_path.display());
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping (exists): {}", torrent_file_details.name))).await;
+                // The daemon may not have it yet (e.g. the backend was enabled since); it ignores duplicates
+                let handled = finish_torrent(ctx, mode, collection_id, item_id, &torrent_file_path, &torrent_parent_dir).await;
+                // Send ItemCompleted as we successfully handled this item (by skipping)
+                let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), handled)).await;
+                return Ok(()); // File exists, skip download attempt
+            }
+            Ok(_) => {
+                // Path exists but is not a file (e.g., a directory) - proceed to download/overwrite?
+                // Or log a warning? For now, proceed.
+                warn!("Path exists but is not a file: '{}'. Proceeding with download.", torrent_file_path.display());
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // File does not exist, proceed with download
+                debug!("Torrent file not found: '{}'. Proceeding with download.", torrent_file_path.display());
+            }
+            Err(e) => {
+                // Other error checking metadata, log warning and proceed
+                warn!("Failed to get metadata for torrent file '{}': {}. Proceeding with download.", torrent_file_path.display(), e);
+            }
+        }
+        // --- End Idempotency Check ---
+
+        // Spawn a single task to download the assumed torrent file
+        let ctx_clone = ctx.clone();
+        let item_id_clone = item_id.to_string();
+        let collection_id_task_clone = collection_id.map(|s| s.to_string());
+
+        let handle = tokio::spawn(async move {
+            download_single_file(
+                &ctx_clone,
+                collection_id_task_clone.as_deref(),
+                &item_id_clone,
+                &torrent_file_details, // Pass the constructed details
+                &[],
+            )
+            .await
+        });
+
+        // Wait for the single torrent download task
+        let torrent_result = handle.await;
+        let item_success = match torrent_result {
+            Ok(Ok(_)) => {
+                debug!("Assumed torrent download task completed successfully for item '{}'.", item_id);
+                finish_torrent(ctx, mode, collection_id, item_id, &torrent_file_path, &torrent_parent_dir).await
+            }
+            Ok(Err(e)) => { // Torrent download task completed but reported an error (e.g., 404)
+                warn!("Assumed torrent download failed for item '{}': {}. Fetching metadata to check if it's a collection.", item_id, e);
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Torrent download failed for {}, checking metadata...", item_id))).await;
+
+                // Fetch metadata to check if it's a collection
+                let limiter_clone_details = Arc::clone(&ctx.rate_limiter);
+                match archive_api::fetch_item_details(&ctx.client, item_id, limiter_clone_details).await {
+                    Ok(details) => {
+                        // Check mediatype directly on the processed ItemDetails struct
+                        if details.mediatype == Some("collection".to_string()) {
+                             info!("Item '{}' is actually a collection. Skipping torrent download.", item_id);
+                             let _ = progress_tx.send(DownloadProgress::ItemSkippedWasCollection(item_id.to_string())).await;
+                             // Mark item processing as 'successful' in the sense that we handled it (by skipping)
+                                // The CollectionCompleted count won't increment 'failed' for this.
+                                return Ok(()); // Exit download_item successfully after skipping
+                        } else {
+                            // If mediatype is None or not "collection", proceed to mark as failed download
+                            warn!("Item '{}' is not a collection (mediatype: {:?}). Torrent download failed.", item_id, details.mediatype);
+                        }
+                    }
+                    Err(fetch_err) => {
+                        // Metadata fetch failed after torrent download failed
+                        error!("Failed to fetch metadata for item '{}' after torrent download failed: {}", item_id, fetch_err);
+                        // Proceed to mark item as failed below
+                    }
+                }
+                // If we reach here, it means torrent download failed AND (metadata fetch failed OR it wasn't a collection)
+                // Mark the item as failed.
+                let _ = progress_tx.send(DownloadProgress::Error(format!("Torrent download failed for {}: {}", item_id, e))).await;
+                false // item_success = false
+            }
+            Err(e) => { // Torrent download task panicked
+                error!("Assumed torrent download task panicked for item {}: {}", item_id, e);
+                let _ = progress_tx.send(DownloadProgress::Error(format!("Torrent download task panicked for item {}: {}", item_id, e))).await;
+                false
+            }
+        };
+
+        if item_success && mode == DownloadMode::TorrentEmbedded {
+            add_to_ipfs(ctx, item_id, &paths::item_dir(base_dir, collection_id, item_id, &ctx.path_policy)).await;
+        }
+
+        // Only send ItemCompleted if we didn't already send ItemSkippedWasCollection
+        if item_success {
+            info!("Finished processing item '{}' (TorrentOnly mode - direct attempt). Success: {}", item_id, item_success);
+            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), item_success)).await;
+        } else {
+             // Failure case (torrent download failed and it wasn't identified as a collection, or task panicked)
+             info!("Finished processing item '{}' (TorrentOnly mode - direct attempt). Success: false", item_id);
+             let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await;
+        }
+        Ok(()) // Finished processing this item in TorrentOnly mode
+
+    } else { // Direct Mode
+        // --- Fetch item details with retry logic (Only for Direct mode) ---
+        let details: ItemDetails; // Declare details, assign on success
+        let mut attempt = 0;
+        let mut backoff_secs = 1; // Initial backoff delay
+
+        loop {
+            attempt += 1;
+            let limiter_clone_details = Arc::clone(&ctx.rate_limiter);
+            let details_result = archive_api::fetch_item_details(&ctx.client, item_id, limiter_clone_details).await;
+
+            match details_result {
+                Ok(fetched_details) => {
+                    info!("Successfully fetched details for item '{}' on attempt {}", item_id, attempt);
+                    details = fetched_details; // Assign directly on success
+                    break; // Exit loop on success
+                }
+                Err(e) => {
+                    // Check if the error is permanent
+                    match e.kind {
+                        archive_api::FetchDetailsErrorKind::NotFound |
+                        archive_api::FetchDetailsErrorKind::ParseError |
+                        archive_api::FetchDetailsErrorKind::ClientError(_) => {
+                            error!("Permanent error fetching details for item '{}': {}. Skipping item.", item_id, e);
+                            let _ = progress_tx.send(DownloadProgress::Error(format!("Permanent error for {}: {:?}", item_id, e.kind))).await;
+                            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await; // Mark as failed
+                            return Ok(()); // Handled permanent item error
+                        }
+                        // archive.org asked to slow down: the rate limiter already holds every request back
+                        archive_api::FetchDetailsErrorKind::RateLimitExceeded |
+                        archive_api::FetchDetailsErrorKind::ServerError(StatusCode::SERVICE_UNAVAILABLE) => {
+                            warn!("Throttled fetching details for item '{}' (Attempt {}): {}. Retrying when the rate limiter allows.", item_id, attempt, e);
+                            let _ = progress_tx.send(DownloadProgress::Status(format!("archive.org is busy, retrying {} (Attempt {})", item_id, attempt))).await;
+                        }
+                        // Transient, but it didn't pass: try the item again later
+                        _ if attempt >= MAX_DETAILS_ATTEMPTS => {
+                            let cause = match e.kind {
+                                archive_api::FetchDetailsErrorKind::ServerError(status) => format!("server answered status {}", status.as_u16()),
+                                _ => "connection failed".to_string(),
+                            };
+                            let msg = format!("Could not fetch metadata for {} after {} attempts ({}): {}", item_id, attempt, cause, e);
+                            error!("{}", msg);
+                            offer_retry(ctx, DownloadAction::ItemAllFiles(item_id.to_string()), collection_id, mode, &msg).await;
+                            let _ = progress_tx.send(DownloadProgress::Error(msg)).await;
+                            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await;
+                            return Ok(());
+                        }
+                        // Otherwise, it's a transient error, proceed with retry logic
+                        _ => {
+                            warn!("Transient error fetching details for item '{}' (Attempt {}): {}. Retrying in {}s...", item_id, attempt, e, backoff_secs);
+                            let _ = progress_tx.send(DownloadProgress::Status(format!("Retrying {} (Attempt {}, Wait {}s): {:?}", item_id, attempt, backoff_secs, e.kind))).await;
+                            tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+                            backoff_secs *= 2;
+                        }
+                    }
+                }
+            }
+        } // --- End fetch details retry loop ---
+
+        if let Some(node_url) = &details.download_base_url {
+            ctx.host_pacer.learn_item_host(item_id, node_url);
+        }
+        let files = presets::plan_files(&details, ctx.file_preset, ctx.source_policy, &ctx.file_filter, ctx.subtitle_languages.as_deref().map(Vec::as_slice));
+        let total_files = files.len();
+        if total_files < details.files.len() {
+            info!("Direct mode: Preset '{}' and download filter select {} of {} files for item '{}'", ctx.file_preset, total_files, details.files.len(), item_id);
+        } else {
+            info!("Direct mode: Found {} files for item '{}'", total_files, item_id);
+        }
+        let _ = progress_tx.send(DownloadProgress::ItemFileCount(total_files)).await;
+
+        if files.is_empty() {
+            let reason = if details.files.is_empty() { "No files found".to_string() } else { format!("No files match preset '{}' and the download filter", ctx.file_preset) };
+            info!("{} for item: {}. Marking as complete.", reason, item_id);
+            let _ = progress_tx.send(DownloadProgress::Status(format!("{} for item: {}", reason, item_id))).await;
+            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), true)).await;
+            return Ok(());
+        }
+
+        // Items of collections with a library layout get a folder named from their metadata
+        let layout_dir = collection_id.and_then(|c| ctx.library_layouts.get(c)).and_then(|template| {
+            layout::item_dir(template, base_dir, &details, collection_id, &ctx.path_policy)
+                .map_err(|e| warn!("Ignoring library layout for item '{}': {:#}", item_id, e))
+                .ok()
+        });
+        // Path: base_dir / [collection_id] / item_id, unless a layout applies
+        let item_dir = layout_dir.clone().unwrap_or_else(|| paths::item_dir(base_dir, collection_id, item_id, &ctx.path_policy));
+        match replicate::replicated_to(&item_dir) {
+            Some(targets) if !ctx.force => {
+                info!("Item '{}' was replicated to {} and deleted locally; skipping it.", item_id, targets.join(", "));
+                let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping {}: kept on {} only", item_id, targets.join(", ")))).await;
+                let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), true)).await;
+                return Ok(());
+            }
+            Some(_) => { // Forced: bring the local copy back
+                let _ = fs::remove_file(item_dir.join(replicate::MARKER_FILE)).await;
+            }
+            None => {}
+        }
+        let mut planned: Vec<(archive_api::FileDetails, PathBuf)> = files
+            .into_iter()
+            .map(|file| {
+                let path = match &layout_dir {
+                    Some(dir) => paths::item_file_path(dir.clone(), &file.name, &ctx.path_policy),
+                    None => paths::download_path(base_dir, collection_id, item_id, &file.name, &ctx.path_policy),
+                };
+                (file, path)
+            })
+            .collect();
+        if !ctx.force && ctx.storage.is_local() {
+            accept_tagged_files(ctx, &mut planned).await;
+        }
+
+        // Refuse items that can't fit rather than filling the disk halfway through
+        let needed = bytes_still_needed(&planned).await;
+        if let Some(free) = forecast::available_space(&item_dir).filter(|_| ctx.storage.is_local()) {
+            if needed > free {
+                let msg = format!(
+                    "Not enough free space for {}: needs {}, {} free",
+                    item_id,
+                    forecast::format_bytes(needed),
+                    forecast::format_bytes(free)
+                );
+                error!("{}", msg);
+                let _ = progress_tx.send(DownloadProgress::Error(msg)).await;
+                let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await;
+                return Ok(());
+            }
+        }
+        let _ = progress_tx.send(DownloadProgress::ItemBytes(needed)).await;
+
+        info!("Direct mode: Queueing {} files for item: {}", total_files, item_id);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Queueing {} files for item: {}", total_files, item_id))).await;
+
+        // For Direct mode, ensure the item-specific directory exists, as files (other than torrents) go there.
+        if ctx.storage.is_local() {
+            debug!("Ensuring item directory exists for non-torrent files: {}", item_dir.display());
+            fs::create_dir_all(&item_dir).await.context(format!("Failed to create item directory '{}'", item_dir.display()))?;
+            write_sidecar(ctx.sidecar, &item_dir, &details).await;
+        }
+        // Keeps the item browsable offline and describable in the library feeds
+        if let Err(e) = offline::save_details(base_dir, &details) {
+            warn!("Failed to cache the details of '{}': {:#}", item_id, e);
+        }
+
+        let mut file_join_handles = vec![];
+        let mut item_failed = false; // Track if any file task fails
+        let mut transient_error = None; // A failure worth retrying the item for
+        let mirrors = Arc::new(details.mirror_base_urls.clone());
+
+        // Spawn a download task for each file concurrently
+        for (file, file_path) in planned.iter().cloned() {
+            // Clone necessary data for the file download task
+            let ctx_clone = ctx.clone();
+         let item_id_clone = item_id.to_string();
+         let mirrors = Arc::clone(&mirrors);
+
+         let handle = tokio::spawn(async move {
+             download_file_to(&ctx_clone, &item_id_clone, &file, &mirrors, file_path).await
+         });
+         file_join_handles.push(handle);
+     }
+
+     // Wait for all file download tasks for this item to complete (Direct Mode)
+     for handle in file_join_handles {
+         match handle.await {
+             Ok(Ok(_)) => {
+                 debug!("File download task completed successfully for item '{}'.", item_id);
+             }
+             Ok(Err(e)) => {
+                 item_failed = true;
+                 let reason = format!("{:#}", e);
+                 if transient_error.is_none() && ErrorKind::classify(&reason).is_transient() {
+                     transient_error = Some(reason);
+                 }
+                 // Error already logged and sent by download_single_file, just log context here.
+                 error!("File download task failed within item {}: {}", item_id, e);
+                 // Optionally send another status update if needed, but Error should have been sent.
+                 // let _ = progress_tx.send(DownloadProgress::Status(format!("File download failed within item {}: {}", item_id, e))).await;
+             }
+             Err(e) => { // Task panicked or was cancelled
+                 item_failed = true;
+                 error!("File download task panicked or was cancelled for item {}: {}", item_id, e);
+                 let _ = progress_tx.send(DownloadProgress::Error(format!("File download task panicked for item {}: {}", item_id, e))).await;
+             }
+         }
+     }
+
+     // Files streamed into a bucket have no local copy to tag, list or hash
+     let local = ctx.storage.is_local();
+     let release = match &ctx.musicbrainz {
+         Some(musicbrainz) if local => match_release(ctx, musicbrainz, &details, &planned).await,
+         _ => None,
+     };
+     if let (Some(policy), true) = (ctx.tag_audio, local) {
+         tag_audio(ctx, policy, &details, &planned, release.as_ref()).await;
+     }
+     if ctx.write_playlist && local {
+         write_playlist(&item_dir, &details, &planned).await;
+     }
+     if ctx.write_checksums && local {
+         write_checksums(&item_dir, &details, &planned).await;
+     }
+
+     if layout_dir.is_some() {
+         let _ = progress_tx.send(DownloadProgress::ItemPlaced(item_id.to_string(), item_dir.clone())).await;
+     }
+
+     // Send item completion status based on whether any file task failed (Direct Mode)
+     let success_status = !item_failed;
+     if success_status && local {
+         add_to_ipfs(ctx, item_id, &item_dir).await;
+         replicate_item(ctx, item_id, &item_dir).await;
+     }
+     if let Some(reason) = transient_error {
+         // The files already downloaded are skipped on the next attempt
+         offer_retry(ctx, DownloadAction::ItemAllFiles(item_id.to_string()), collection_id, mode, &reason).await;
+     }
+     info!("Finished processing item '{}' (Direct mode). Success: {}", item_id, success_status);
+     let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), success_status)).await;
+
+     // Return Ok even if some files failed, ItemCompleted indicates success/failure of the item overall
+     Ok(())
+    } // End else block for Direct Mode
+} // End download_item function
+
+/// Asks for `action` to be tried again later (see [`crate::retry`]) if `error` looks like
+/// it will pass. Returns whether it did.
+pub async fn offer_retry(ctx: &DownloadContext, action: DownloadAction, collection: Option<&str>, mode: DownloadMode, error: &str) -> bool {
+    if !ErrorKind::classify(error).is_transient() {
+        return false;
+    }
+    let job = DownloadJob {
+        action,
+        collection: collection.map(str::to_string),
+        mode,
+        base_dir: ctx.base_dir.clone(),
+        preset: ctx.file_preset,
+        retry: ctx.retry,
+    };
+    let _ = ctx.progress_tx.send(DownloadProgress::RetryLater(job, error.to_string())).await;
+    true
+}
+
+/// Writes the item's human-readable metadata file, if enabled.
+///
+/// Failures are only logged: a missing README shouldn't fail the item. An archive file
+/// with the same name always wins over the generated one.
+async fn write_sidecar(format: SidecarFormat, item_dir: &Path, details: &ItemDetails) {
+    let Some(file_name) = format.file_name() else { return };
+    if details.files.iter().any(|f| f.name == file_name) {
+        info!("Item '{}' ships its own {}; not writing a sidecar.", details.identifier, file_name);
+        return;
+    }
+    let downloaded_on = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let Some(contents) = sidecar::render(format, details, &downloaded_on) else { return };
+    let path = item_dir.join(file_name);
+    if let Err(e) = fs::write(&path, contents).await {
+        warn!("Failed to write metadata sidecar '{}': {}", path.display(), e);
+    }
+}
+
+/// Adds a completed item directory to the IPFS node, if enabled. Failures are only
+/// logged, like for the sidecar: the files are downloaded either way.
+async fn add_to_ipfs(ctx: &DownloadContext, item_id: &str, item_dir: &Path) {
+    let Some(api) = &ctx.ipfs_api else { return };
+    match ipfs::add_directory(&ctx.client, api, item_dir).await {
+        Ok(cid) => {
+            let _ = ctx.progress_tx.send(DownloadProgress::ItemAddedToIpfs(item_id.to_string(), cid)).await;
+        }
+        Err(e) => {
+            warn!("Failed to add '{}' to IPFS: {:#}", item_id, e);
+            let _ = ctx.progress_tx.send(DownloadProgress::Status(format!("Couldn't add {} to IPFS: {:#}", item_id, e))).await;
+        }
+    }
+}
+
+/// Pushes a completed item directory to every replication target and, if enabled,
+/// deletes the local copy once all of them hold one with the same content. Failures are only
+/// logged, like for IPFS: the next download of the item pushes it again.
+async fn replicate_item(ctx: &DownloadContext, item_id: &str, item_dir: &Path) {
+    let Some(replication) = &ctx.replication else { return };
+    let mut replicated = Vec::new();
+    for target in &replication.targets {
+        let _ = ctx.progress_tx.send(DownloadProgress::Status(format!("Replicating {} to {}", item_id, target.name))).await;
+        match replicate::push(&ctx.client, target, Path::new(&ctx.base_dir), item_dir).await {
+            Ok(()) => replicated.push(target.name.clone()),
+            Err(e) => {
+                warn!("Failed to replicate '{}' to {}: {:#}", item_id, target.name, e);
+                let _ = ctx.progress_tx.send(DownloadProgress::Status(format!("Couldn't replicate {} to {}: {:#}", item_id, target.name, e))).await;
+            }
+        }
+    }
+    if replicated.len() < replication.targets.len() {
+        return;
+    }
+    let mut unverified = None;
+    if replication.delete_local {
+        for target in &replication.targets {
+            if let Err(e) = replicate::verify_content(&ctx.client, target, Path::new(&ctx.base_dir), item_dir).await {
+                warn!("Keeping the local copy of '{}': {:#}", item_id, e);
+                unverified = Some(e);
+                break;
+            }
+        }
+    }
+    let status = if !replication.delete_local {
+        format!("Replicated {} to {}", item_id, replicated.join(", "))
+    } else if let Some(e) = unverified {
+        format!("Replicated {}, but kept the local copy: {:#}", item_id, e)
+    } else {
+        match replicate::remove_local(item_dir, &replicated) {
+            Ok(()) => format!("Replicated {} to {} and deleted the local copy", item_id, replicated.join(", ")),
+            Err(e) => {
+                warn!("Failed to delete the local copy of '{}': {:#}", item_id, e);
+                format!("Replicated {}, but couldn't delete the local copy: {:#}", item_id, e)
+            }
+        }
+    };
+    let _ = ctx.progress_tx.send(DownloadProgress::Status(status)).await;
+}
+
+/// Looks up the MusicBrainz release the item's audio files are a copy of and reports it.
+/// Lookup failures are only logged, like for the sidecar.
+async fn match_release(
+    ctx: &DownloadContext,
+    musicbrainz: &MusicBrainz,
+    details: &ItemDetails,
+    files: &[(archive_api::FileDetails, PathBuf)],
+) -> Option<ReleaseMatch> {
+    let files: Vec<archive_api::FileDetails> = files.iter().map(|(file, _)| file.clone()).collect();
+    match musicbrainz.match_item(details, &files).await {
+        Ok(Some(release)) => {
+            let _ = ctx.progress_tx.send(DownloadProgress::ItemMatched(details.identifier.clone(), release.release_id.clone())).await;
+            Some(release)
+        }
+        Ok(None) => {
+            debug!("No MusicBrainz release matches item '{}'", details.identifier);
+            None
+        }
+        Err(e) => {
+            warn!("MusicBrainz lookup for '{}' failed: {:#}", details.identifier, e);
+            None
+        }
+    }
+}
+
+/// Tags the item's downloaded MP3 and FLAC files from its metadata (see [`tagging`]),
+/// adding the MBIDs of the matched MusicBrainz release, if any. Tagged files are recorded
+/// in the download history with their local size and SHA-256, so later runs keep them.
+/// Runs before the checksum manifest is written, so the manifest lists the tagged files.
+/// Failures are only logged, like for the sidecar.
+async fn tag_audio(ctx: &DownloadContext, policy: TagPolicy, details: &ItemDetails, files: &[(archive_api::FileDetails, PathBuf)], release: Option<&ReleaseMatch>) {
+    let jobs: Vec<(archive_api::FileDetails, PathBuf, Tags)> = files
+        .iter()
+        .filter(|(file, _)| tagging::is_taggable(&file.name))
+        .map(|(file, path)| {
+            let musicbrainz = release.and_then(|release| release.tracks.get(&file.name).cloned());
+            (file.clone(), path.clone(), Tags { musicbrainz, ..Tags::for_file(details, file) })
+        })
+        .collect();
+    if jobs.is_empty() {
+        return;
+    }
+    let identifier = details.identifier.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let mut written = Vec::new();
+        for (file, path, tags) in jobs {
+            // Linked duplicates are left alone: rewriting would replace the link with a copy
+            if !std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_file()) {
+                continue;
+            }
+            match tagging::tag_file(&path, &tags, policy) {
+                Ok(tagging::TagOutcome::Written) => match (std::fs::metadata(&path), verify::sha256_file(&path)) {
+                    (Ok(metadata), Ok(sha256)) => written.push((file, path, metadata.len(), sha256)),
+                    (Err(e), _) => warn!("Failed to read tagged '{}': {}", path.display(), e),
+                    (_, Err(e)) => warn!("Failed to hash tagged '{}': {:#}", path.display(), e),
+                },
+                Ok(_) => {}
+                Err(e) => warn!("Failed to tag '{}': {:#}", path.display(), e),
+            }
+        }
+        written
+    })
+    .await;
+    match result {
+        Ok(written) => {
+            if !written.is_empty() {
+                info!("Tagged {} audio file(s) of '{}'", written.len(), details.identifier);
+            }
+            for (file, path, size, sha256) in written {
+                ctx.history.record(FileRecord {
+                    identifier: details.identifier.clone(),
+                    name: file.name,
+                    md5: file.md5,
+                    size,
+                    path,
+                    downloaded: chrono::Local::now().to_rfc3339(),
+                    local_sha256: Some(sha256),
+                });
+            }
+        }
+        Err(e) => warn!("Tagging task for '{}' failed: {}", identifier, e),
+    }
+}
+
+/// Files changed on disk since download (tagged by [`tag_audio`]) no longer have
+/// archive.org's size, so they would be downloaded again. The download history records
+/// their local size next to archive.org's MD5: a taggable file whose recorded MD5 is still
+/// archive.org's and whose size matches the record counts as complete.
+async fn accept_tagged_files(ctx: &DownloadContext, planned: &mut [(archive_api::FileDetails, PathBuf)]) {
+    for (file, path) in planned.iter_mut() {
+        if !tagging::is_taggable(&file.name) {
+            continue;
+        }
+        let Some(local_size) = ctx.history.changed_size(path, file.md5.as_deref()) else { continue };
+        let local_size_text = local_size.to_string();
+        if file.size.as_deref() != Some(local_size_text.as_str()) && fs::metadata(&*path).await.is_ok_and(|m| m.len() == local_size) {
+            debug!("'{}' was changed locally since download; keeping it", path.display());
+            file.size = Some(local_size_text);
+        }
+    }
+}
+
+/// Writes `<item>.m3u8` listing the item's downloaded audio tracks, if it has any.
+///
+/// Only files that made it to disk are listed, so a partly failed item gets a
+/// playlist of what it has. Failures are only logged, like for the sidecar.
+async fn write_playlist(item_dir: &Path, details: &ItemDetails, files: &[(archive_api::FileDetails, PathBuf)]) {
+    let file_name = playlist::file_name(&details.identifier);
+    if details.files.iter().any(|f| f.name == file_name) {
+        info!("Item '{}' ships its own {}; not writing a playlist.", details.identifier, file_name);
+        return;
+    }
+    let mut downloaded = Vec::new();
+    for (file, path) in files {
+        if fs::metadata(path).await.is_ok_and(|m| m.is_file()) {
+            if let Ok(relative) = path.strip_prefix(item_dir) {
+                downloaded.push((file.clone(), relative.to_string_lossy().into_owned()));
+            }
+        }
+    }
+    let Some(contents) = playlist::render(details, &downloaded) else { return };
+    let path = item_dir.join(&file_name);
+    match fs::write(&path, contents).await {
+        Ok(()) => info!("Wrote playlist '{}'", path.display()),
+        Err(e) => warn!("Failed to write playlist '{}': {}", path.display(), e),
+    }
+}
+
+/// Writes `<item>_checksums.tsv` with the size, archive.org MD5 and a locally computed
+/// SHA-256 of each downloaded file, so the item can later be verified offline.
+/// Failures are only logged, like for the sidecar.
+async fn write_checksums(item_dir: &Path, details: &ItemDetails, files: &[(archive_api::FileDetails, PathBuf)]) {
+    if details.files.iter().any(|f| f.name == verify::manifest_file_name(&details.identifier)) {
+        info!("Item '{}' ships its own checksum manifest; not writing one.", details.identifier);
+        return;
+    }
+    let listed = files
+        .iter()
+        .filter_map(|(file, path)| {
+            let relative = path.strip_prefix(item_dir).ok()?.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            Some((path.clone(), relative, file.md5.clone()))
+        })
+        .collect();
+    match verify::write_manifest(item_dir.to_path_buf(), details.identifier.clone(), listed).await {
+        Ok(path) => info!("Wrote checksum manifest '{}'", path.display()),
+        Err(e) => warn!("Failed to write checksum manifest for '{}': {:#}", details.identifier, e),
+    }
+}
+
+/// File the identifiers listed by [`download_collection`] are cached in, so that resuming
+/// a collection download doesn't list it again.
+pub fn identifier_cache_path(base_dir: &str, collection_id: &str) -> PathBuf {
+    Path::new(base_dir).join(format!("{}.identifiers.json", saved_search::local_name(collection_id)))
+}
+
+/// Downloads all items for a specific collection identifier.
+pub async fn download_collection(
+    ctx: &DownloadContext,
+    collection_id: &str, // Now takes specific collection ID
+    mode: DownloadMode, // Added: Download mode
+) -> Result<()> {
+    let progress_tx = &ctx.progress_tx;
+    let base_dir = ctx.base_dir.as_str();
+    info!("Starting download_collection for '{}', mode: {:?}", collection_id, mode);
+
+    // --- Identifier Caching Logic ---
+    let cache_path = identifier_cache_path(base_dir, collection_id);
+    let cache_file_name = cache_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut all_identifiers: Vec<String> = Vec::new();
+    let mut use_cache = false;
+
+    // 1. Check if cache file exists
+    if cache_path.exists() {
+        info!("Found identifier cache file: {}", cache_path.display());
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Loading identifiers from cache: {}", cache_file_name))).await;
+        match TokioFile::open(&cache_path).await {
+            Ok(mut file) => {
+                let mut contents = String::new();
+                if file.read_to_string(&mut contents).await.is_ok() {
+                    match serde_json::from_str::<Vec<String>>(&contents) {
+                        Ok(cached_ids) => {
+                            if !cached_ids.is_empty() {
+                                info!("Successfully loaded {} identifiers from cache: {}", cached_ids.len(), cache_path.display());
+                                all_identifiers = cached_ids;
+                                use_cache = true;
+                            } else {
+                                warn!("Cache file is empty or invalid: {}. Re-fetching.", cache_path.display());
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to parse cache file JSON '{}': {}. Re-fetching.", cache_path.display(), e);
+                            // Attempt to delete the invalid cache file? Or just overwrite later.
+                            let _ = fs::remove_file(&cache_path).await; // Try removing invalid cache
+                        }
+                    }
+                } else {
+                    warn!("Failed to read cache file '{}'. Re-fetching.", cache_path.display());
+                }
+            }
+            Err(e) => {
+                warn!("Failed to open cache file '{}': {}. Re-fetching.", cache_path.display(), e);
+            }
+        }
+    }
+
+    // 2. Fetch from API if cache wasn't used
+    if !use_cache {
+        info!("Fetching identifiers from API for collection: {}", collection_id);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Fetching identifiers from API: {}", collection_id))).await;
+
+        // --- Use bulk fetch to get identifiers ---
+        // No temporary channel needed here, call directly
+        let collection_id_clone_ids = collection_id.to_string(); // Keep clone for error messages
+        let limiter_clone_ids = Arc::clone(&ctx.rate_limiter);
+
+        // Call the bulk fetch function directly
+        let listing = match fetch_filter::for_collection(&ctx.fetch_filters, collection_id) {
+            Ok(filter) => archive_api::fetch_collection_items_bulk(&ctx.client, collection_id, filter.as_deref(), limiter_clone_ids).await,
+            Err(e) => Err(e),
+        };
+        match listing {
+            Ok((fetched_items, _total_found)) => {
+                 // Extract identifiers from fetched items
+                 all_identifiers = fetched_items.into_iter().map(|doc| doc.identifier).collect();
+                 info!("Fetched {} identifiers via bulk API for collection '{}'", all_identifiers.len(), collection_id);
+            }
+            Err(e) => {
+                 // Propagate error if fetch failed
+                 let err_msg = format!("Failed to get identifiers for {}: {}", collection_id_clone_ids, e);
+                 error!("{}", err_msg);
+                 let _ = progress_tx.send(DownloadProgress::Error(err_msg.clone())).await;
+                 let _ = progress_tx.send(DownloadProgress::CollectionCompleted(0, 0)).await;
+                 return Err(anyhow!(err_msg)); // Return the error
+            }
+        }
+        // --- End bulk fetch ---
+
+        // 3. Save fetched identifiers to cache (only if fetch was successful)
+        if !all_identifiers.is_empty() {
+            if !all_identifiers.is_empty() {
+                    match serde_json::to_string_pretty(&all_identifiers) {
+                        Ok(json_data) => {
+                            // Ensure parent directory exists (should already from download setup, but good practice)
+                            if let Some(parent) = cache_path.parent() {
+                                if let Err(e) = fs::create_dir_all(parent).await {
+                                     warn!("Failed to ensure cache directory exists '{}': {}", parent.display(), e);
+                                     // Proceed without saving cache if dir creation fails
+                                } else {
+                                    // Write to cache file
+                                    match TokioFile::create(&cache_path).await {
+                                        Ok(mut file) => {
+                                            if let Err(e) = file.write_all(json_data.as_bytes()).await {
+                                                warn!("Failed to write to cache file '{}': {}", cache_path.display(), e);
+                                            } else {
+                                                info!("Successfully saved {} identifiers to cache: {}", all_identifiers.len(), cache_path.display());
+                                            }
+                                        }
+                                        Err(e) => {
+                                            warn!("Failed to create cache file '{}': {}", cache_path.display(), e);
+                                        }
+                                    }
+                                }
+                            } else {
+                                warn!("Could not determine parent directory for cache file: {}", cache_path.display());
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to serialize identifiers to JSON for caching: {}", e);
+                        }
+                    }
+                } // End of if !all_identifiers.is_empty()
+                else { // Add the else block here
+                    info!("No identifiers fetched from API, cache file not created/updated.");
+                }
+            } // End of else block (fetch_error is None)
+        } // End of if !use_cache block
+    // --- End Identifier Caching Logic --- // Removed extra brace before this comment
+
+
+    // Excluded items are dropped before anything is counted or queued; the cache keeps
+    // the full listing so changed rules apply on the next run
+    let listed = all_identifiers.len();
+    all_identifiers.retain(|id| match ctx.exclusions.matching_rule(id) {
+        Some(rule) => {
+            debug!("Skipping excluded item '{}' (rule '{}')", id, rule);
+            false
+        }
+        None => true,
+    });
+    if all_identifiers.len() < listed {
+        let excluded = listed - all_identifiers.len();
+        info!("Skipping {} excluded items in collection '{}'", excluded, collection_id);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping {} excluded items", excluded))).await;
+    }
+
+    if all_identifiers.is_empty() {
+        info!("No items found in collection (or cache): {}. Download complete.", collection_id);
+        let _ = progress_tx.send(DownloadProgress::Status(format!("No items found in collection: {}", collection_id))).await;
+        let _ = progress_tx.send(DownloadProgress::CollectionCompleted(0, 0)).await;
+        return Ok(());
+    }
+
+    info!("Found {} items to download for collection '{}'", all_identifiers.len(), collection_id);
+    download_items(ctx, Some(collection_id), all_identifiers, mode).await
+} // Re-adding the closing brace for download_collection
+
+/// Downloads `item_ids` as one unit, at most `collection_item_semaphore` items at a time.
+///
+/// Progress is reported like a collection download (`CollectionInfo`/`CollectionCompleted`),
+/// so a batch of hand-picked items shows one overall item count. Items are placed under
+/// `collection_id` when given.
+pub async fn download_items(
+    ctx: &DownloadContext,
+    collection_id: Option<&str>,
+    item_ids: Vec<String>,
+    mode: DownloadMode,
+) -> Result<()> {
+    let progress_tx = &ctx.progress_tx;
+    let label = collection_id.unwrap_or("batch");
+    let total_items = item_ids.len();
+    let started = chrono::Local::now().to_rfc3339();
+    let started_at = std::time::Instant::now();
+    let listed_items = collection_id.map(|_| item_ids.clone());
+    // Send total item count for this download
+    let _ = progress_tx.send(DownloadProgress::CollectionInfo(total_items)).await;
+    let _ = progress_tx.send(DownloadProgress::Status(format!("Queueing {} items for: {}", total_items, label))).await;
+
+    let mut join_handles = vec![];
+    let mut total_failed_items = 0; // Count items where download_item itself returned Err or panicked
+
+    // Iterate through identifiers and spawn item download tasks
+    for item_id in item_ids.into_iter() {
+        // Acquire item processing permit *before* spawning
+        debug!("Attempting to acquire item processing permit for item: {}", item_id);
+        let item_permit = match Arc::clone(&ctx.collection_item_semaphore).acquire_owned().await {
+            Ok(permit) => {
+                debug!("Acquired item processing permit for item: {}", item_id);
+                permit
+            },
+            Err(e) => {
+                error!("Failed to acquire item processing permit for item {}: {}", item_id, e);
+                // Skip this item if permit acquisition fails
+                total_failed_items += 1;
+                continue;
+            }
+        };
+        debug!("Acquired item processing permit for item: {}", item_id);
+
+        // Clone data needed for the item download task
+        let ctx_clone = ctx.clone();
+        let item_id_clone = item_id.clone(); // Keep clone for task
+        let collection_id_clone = collection_id.map(str::to_string); // Clone collection ID for task
+
+        let handle = tokio::spawn(async move {
+            // download_item handles fetching details and spawning file downloads based on mode
+            // It uses the scheduler in the context for individual file slots
+            let item_result = download_item(
+                &ctx_clone,
+                collection_id_clone.as_deref(), // Pass collection ID context (now cloned)
+                &item_id_clone,
+                mode, // Pass the download mode down
+            )
+            .await;
+            // Drop the item permit when the task finishes
+            drop(item_permit);
+            debug!("Released item processing permit for item: {}", item_id_clone);
+            item_result // Return result (Ok or Err)
+        });
+        join_handles.push(handle);
+    }
+
+    // Wait for all item download tasks to complete
+    info!("Waiting for {} item download tasks for '{}'...", join_handles.len(), label);
+    for handle in join_handles {
+        match handle.await {
+            Ok(Ok(_)) => {
+                debug!("Item download task completed successfully for '{}'.", label);
+            }
+            Ok(Err(e)) => {
+                // Error should have been logged within download_item (e.g., failed details fetch)
+                error!("Item download task failed for '{}': {}", label, e);
+                total_failed_items += 1;
+            }
+            Err(e) => { // Task panicked or was cancelled
+                error!("Item download task panicked or was cancelled for '{}': {}", label, e);
+                total_failed_items += 1;
+            }
+        }
+        // Note: Individual file errors within an item are handled by download_item
+        // and reflected in the ItemCompleted message's success flag.
+        // total_failed_items here counts items where the top-level download_item task failed.
+    }
+
+    info!("Finished item downloads for '{}'. Total items: {}, Failed items: {}",
+          label, total_items, total_failed_items);
+    // Collections get a report of their own (see `report::CollectionReport`)
+    if let (Some(collection), Some(items)) = (collection_id, listed_items) {
+        let run = CollectionRun { collection: collection.to_string(), items, started, duration: started_at.elapsed() };
+        let _ = progress_tx.send(DownloadProgress::CollectionFinished(run)).await;
+    }
+    // Send final completion status for this set of items
+    let _ = progress_tx.send(DownloadProgress::CollectionCompleted(total_items, total_failed_items)).await;
+
+    Ok(())
+}
+
+// TODO: Implement multi-collection download logic using max_concurrent_collections semaphore.
+// This would likely involve another layer of task spawning in main.rs or a dedicated function.
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::auth::{self, Credentials};
+    use crate::mock_server;
+    use crate::rate_limit::SharedRateLimiter;
+    use governor::Quota;
+    use std::num::NonZeroU32;
+
+    fn mock_context(base_dir: &Path) -> (DownloadContext, mpsc::Receiver<DownloadProgress>) {
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let (progress_tx, progress_rx) = mpsc::channel(1024);
+        let quota = Quota::per_minute(NonZeroU32::new(600).unwrap());
+        let ctx = DownloadContext {
+            client: Client::new(),
+            base_dir: base_dir.to_string_lossy().into_owned(),
+            progress_tx,
+            scheduler: DownloadScheduler::new(2),
+            ticket: Ticket { job: 0, priority: crate::scheduler::Priority::Item },
+            collection_item_semaphore: Arc::new(Semaphore::new(1)),
+            rate_limiter: Arc::new(SharedRateLimiter::new(quota)),
+            path_policy: PathPolicy::default(),
+            sidecar: SidecarFormat::Off,
+            write_playlist: false,
+            write_checksums: false,
+            library_layouts: Arc::new(BTreeMap::new()),
+            fetch_filters: Arc::new(BTreeMap::new()),
+            dedupe: None,
+            history: FileHistory::open(base_dir),
+            force: false,
+            tag_audio: None,
+            musicbrainz: None,
+            ipfs_api: None,
+            replication: None,
+            storage: Storage::LocalFs,
+            authenticated: false,
+            host_pacer: Arc::new(HostPacer::new(Duration::ZERO)),
+            exclusions: Arc::new(ExclusionList::default()),
+            file_preset: FilePreset::AllFiles,
+            source_policy: SourcePolicy::All,
+            subtitle_languages: None,
+            file_filter: Arc::new(Vec::new()),
+            torrent_backend: None,
+            segments: SegmentPolicy::default(),
+            throttle: Arc::new(Throttle::new(0)),
+            control: Arc::new(DownloadControl::default()),
+            retry: None,
+        };
+        (ctx, progress_rx)
+    }
+
+    #[tokio::test]
+    async fn test_download_item_from_mock_writes_all_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, mut progress_rx) = mock_context(dir.path());
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct)
+            .await
+            .expect("Mock item download should succeed");
+
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        for (name, _, _, size) in mock_server::MOCK_FILES {
+            let bytes = std::fs::read(item_dir.join(name)).expect("Downloaded file should exist");
+            assert_eq!(bytes.len(), size);
+            assert_eq!(Some(bytes), mock_server::file_bytes("mock_item_one", name));
+        }
+
+        drop(ctx);
+        let mut completed = false;
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::ItemCompleted(id, success) = progress {
+                assert_eq!(id, "mock_item_one");
+                completed = success;
+            }
+        }
+        assert!(completed, "Item should be reported as completed successfully");
+    }
+
+    #[tokio::test]
+    async fn test_download_item_follows_collection_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.write_checksums = true;
+        ctx.library_layouts = Arc::new(BTreeMap::from([(mock_server::MOCK_COLLECTION.to_string(), layout::PRESETS[0].1.to_string())]));
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+
+        let item_dir = dir.path().join("Mock Artist").join("2020 - Mock Item mock_item_one");
+        for (name, _, _, _) in mock_server::MOCK_FILES {
+            assert!(item_dir.join(name).is_file(), "{} should be in the layout folder", name);
+        }
+        assert!(item_dir.join(verify::manifest_file_name("mock_item_one")).exists());
+        assert!(!dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one").exists());
+        let mut placed = None;
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::ItemPlaced(id, dir) = progress {
+                placed = Some((id, dir));
+            }
+        }
+        assert_eq!(placed, Some(("mock_item_one".to_string(), item_dir)));
+    }
+
+    #[tokio::test]
+    async fn test_download_item_fetches_large_files_in_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.segments = SegmentPolicy { segments: 3, threshold: 2048 };
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        std::fs::create_dir_all(&item_dir).unwrap();
+        // The second of three ranges of the 4096 byte track was partly fetched before
+        let track = mock_server::file_bytes("mock_item_one", "track01.mp3").unwrap();
+        let part = paths::part_path(&item_dir.join("track01.mp3"));
+        std::fs::write(segments::segment_path(&part, 1366), &track[1366..1466]).unwrap();
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+        assert_eq!(std::fs::read(item_dir.join("track01.mp3")).unwrap(), track);
+        let leftovers: Vec<_> = std::fs::read_dir(&item_dir).unwrap().filter_map(|e| e.ok()).filter(|e| e.file_name().to_string_lossy().contains(".part")).collect();
+        assert!(leftovers.is_empty(), "Segments are stitched and removed: {:?}", leftovers);
+
+        let mut resumed = None;
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileStarted { key, resumed: bytes, .. } = progress {
+                if key.ends_with("track01.mp3") {
+                    resumed = Some(bytes);
+                }
+            }
+        }
+        assert_eq!(resumed, Some(100), "The partly fetched range resumes");
+    }
+
+    #[tokio::test]
+    async fn test_download_item_skips_complete_files_unless_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        std::fs::create_dir_all(&item_dir).unwrap();
+        // A complete copy of the cover and a track of the right size but other content
+        std::fs::write(item_dir.join("cover.jpg"), mock_server::file_bytes("mock_item_one", "cover.jpg").unwrap()).unwrap();
+        std::fs::write(item_dir.join("track01.mp3"), vec![0u8; 4096]).unwrap();
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        assert_eq!(std::fs::read(item_dir.join("track01.mp3")).unwrap(), mock_server::file_bytes("mock_item_one", "track01.mp3").unwrap());
+        assert!(ctx.history.is_recorded(&item_dir.join("track01.mp3"), 4096, None));
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        ctx.force = true;
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+
+        let mut outcomes: BTreeMap<String, Vec<FileOutcome>> = BTreeMap::new();
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileFinished(result) = progress {
+                outcomes.entry(result.name).or_default().push(result.outcome);
+            }
+        }
+        use FileOutcome::{AlreadyPresent, Downloaded};
+        assert_eq!(outcomes["cover.jpg"], [AlreadyPresent, AlreadyPresent, Downloaded]);
+        assert_eq!(outcomes["track01.mp3"], [Downloaded, AlreadyPresent, Downloaded], "The MD5 mismatch is downloaded again");
+        assert!(dir.path().join(crate::file_history::HISTORY_FILE).exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_item_streams_files_into_a_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.sidecar = SidecarFormat::Markdown;
+        ctx.storage = Storage::S3(Arc::new(S3Bucket {
+            url: format!("{}/bucket/archive", mock_server::shared_base_url()),
+            region: None,
+            access_key: mock_server::MOCK_S3_ACCESS.to_string(),
+            secret_key: mock_server::MOCK_S3_SECRET.to_string(),
+        }));
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        for (name, _, _, _) in mock_server::MOCK_FILES {
+            let key = format!("archive/{}/mock_item_one/{}", mock_server::MOCK_COLLECTION, name);
+            assert_eq!(mock_server::bucket_object(&key), mock_server::file_bytes("mock_item_one", name), "{} is in the bucket", key);
+        }
+        assert!(!dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one").exists(), "Nothing is written locally");
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+
+        let mut outcomes = Vec::new();
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileFinished(result) = progress {
+                outcomes.push(result.outcome);
+            }
+        }
+        use FileOutcome::{AlreadyPresent, Downloaded};
+        assert_eq!(outcomes, [Downloaded, Downloaded, AlreadyPresent, AlreadyPresent]);
+    }
+
+    #[tokio::test]
+    async fn test_download_item_links_duplicates_instead_of_downloading() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.dedupe = Some(Arc::new(DedupeIndex::new(crate::dedupe::DedupeMode::Hardlink, dir.path())));
+
+        download_item(&ctx, Some("first"), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        download_item(&ctx, Some("second"), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+
+        let mut linked = Vec::new();
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::Status(status) = progress {
+                if let Some(name) = status.strip_prefix("Linked (duplicate): ") {
+                    linked.push(name.to_string());
+                }
+            }
+        }
+        assert_eq!(linked.len(), mock_server::MOCK_FILES.len(), "Every file of the second copy is linked: {:?}", linked);
+        for (name, _, _, _) in mock_server::MOCK_FILES {
+            let second = std::fs::read(dir.path().join("second/mock_item_one").join(name)).unwrap();
+            assert_eq!(Some(second), mock_server::file_bytes("mock_item_one", name));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_item_tags_audio_and_keeps_tagged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.write_checksums = true;
+        ctx.tag_audio = Some(TagPolicy::FillMissing);
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        let track = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one/track01.mp3");
+        let tagged = std::fs::read(&track).unwrap();
+        assert!(tagged.starts_with(b"ID3\x04"));
+        assert!(String::from_utf8_lossy(&tagged).contains("Mock Artist"));
+        assert!(tagged.ends_with(&mock_server::file_bytes("mock_item_one", "track01.mp3").unwrap()));
+
+        // A second run recognises the tagged file from the download history
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+        assert_eq!(std::fs::read(&track).unwrap(), tagged);
+        let mut outcomes = Vec::new();
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileFinished(result) = progress {
+                if result.name == "track01.mp3" {
+                    outcomes.push(result.outcome);
+                }
+            }
+        }
+        assert_eq!(outcomes, vec![FileOutcome::Downloaded, FileOutcome::AlreadyPresent]);
+    }
+
+    #[tokio::test]
+    async fn test_tagged_files_kept_without_checksum_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.write_checksums = false;
+        ctx.tag_audio = Some(TagPolicy::FillMissing);
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        assert!(verify::find_manifest(&item_dir).is_none());
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+
+        let mut transfers = Vec::new();
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileFinished(result) = progress {
+                if tagging::is_taggable(&result.name) && result.outcome == FileOutcome::Downloaded {
+                    transfers.push(result.name);
+                }
+            }
+        }
+        transfers.sort();
+        let mut taggable: Vec<String> = mock_server::MOCK_FILES.iter().map(|(name, _, _, _)| name.to_string()).filter(|name| tagging::is_taggable(name)).collect();
+        taggable.sort();
+        assert!(!taggable.is_empty());
+        assert_eq!(transfers, taggable, "Each tagged file is transferred once");
+    }
+
+    #[tokio::test]
+    async fn test_download_item_adds_musicbrainz_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.tag_audio = Some(TagPolicy::FillMissing);
+        ctx.musicbrainz = Some(Arc::new(MusicBrainz::new(Client::new())));
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        drop(ctx);
+
+        let track = std::fs::read(dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one/track01.mp3")).unwrap();
+        let text = String::from_utf8_lossy(&track);
+        assert!(text.contains(&format!("MusicBrainz Album Id\0{}", mock_server::MOCK_MUSICBRAINZ_RELEASE)));
+        assert!(text.contains("http://musicbrainz.org\0mock-recording-1"));
+        let mut matched = None;
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::ItemMatched(id, release_id) = progress {
+                matched = Some((id, release_id));
+            }
+        }
+        assert_eq!(matched, Some(("mock_item_one".to_string(), mock_server::MOCK_MUSICBRAINZ_RELEASE.to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_download_item_writes_metadata_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, _progress_rx) = mock_context(dir.path());
+        ctx.sidecar = SidecarFormat::Markdown;
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct)
+            .await
+            .expect("Mock item download should succeed");
+
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        let sidecar = std::fs::read_to_string(item_dir.join("metadata.md")).expect("Sidecar should be written");
+        assert!(sidecar.contains(&sidecar::source_url("mock_item_one")));
+        assert!(sidecar.contains(mock_server::MOCK_COLLECTION));
+    }
+
+    #[tokio::test]
+    async fn test_download_item_writes_playlist_of_audio_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, _progress_rx) = mock_context(dir.path());
+        ctx.write_playlist = true;
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct)
+            .await
+            .expect("Mock item download should succeed");
+
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        let playlist = std::fs::read_to_string(item_dir.join("mock_item_one.m3u8")).expect("Playlist should be written");
+        assert!(playlist.starts_with("#EXTM3U\n"));
+        assert!(playlist.lines().any(|line| line == "track01.mp3"));
+        assert!(!playlist.contains("cover.jpg"), "Only audio files are listed");
+    }
+
+    /// Downloads the mock lending item and returns the restricted files reported.
+    async fn download_restricted_item(ctx: DownloadContext, mut progress_rx: mpsc::Receiver<DownloadProgress>) -> Vec<String> {
+        download_item(&ctx, None, mock_server::MOCK_RESTRICTED_ITEM, DownloadMode::Direct)
+            .await
+            .expect("Restricted item download should not fail outright");
+        drop(ctx);
+        let mut restricted = Vec::new();
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileRestricted(_, name) = progress {
+                restricted.push(name);
+            }
+        }
+        restricted
+    }
+
+    fn login_client(sig: &str) -> Client {
+        let creds = Credentials { logged_in_user: "me".to_string(), logged_in_sig: sig.to_string() };
+        let jar = auth::cookie_jar(&creds, mock_server::shared_base_url());
+        Client::builder().cookie_provider(Arc::new(jar)).build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_restricted_files_skipped_without_login() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, progress_rx) = mock_context(dir.path());
+
+        let restricted = download_restricted_item(ctx, progress_rx).await;
+
+        let item_dir = dir.path().join(mock_server::MOCK_RESTRICTED_ITEM);
+        assert_eq!(restricted, vec!["book.epub".to_string()]);
+        assert!(!item_dir.join("book.epub").exists());
+        assert!(item_dir.join("book_meta.xml").exists());
+    }
+
+    #[tokio::test]
+    async fn test_restricted_files_follow_account_entitlement() {
+        // Entitled session: the lending epub is downloaded
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, progress_rx) = mock_context(dir.path());
+        ctx.client = login_client(mock_server::MOCK_LOGIN_SIG);
+        ctx.authenticated = true;
+        assert!(download_restricted_item(ctx, progress_rx).await.is_empty());
+        let epub = std::fs::read(dir.path().join(mock_server::MOCK_RESTRICTED_ITEM).join("book.epub")).unwrap();
+        assert_eq!(Some(epub), mock_server::file_bytes(mock_server::MOCK_RESTRICTED_ITEM, "book.epub"));
+
+        // Logged in but not entitled: the 403 is reported as restricted, not as an error
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, progress_rx) = mock_context(dir.path());
+        ctx.client = login_client("not-borrowed");
+        ctx.authenticated = true;
+        assert_eq!(download_restricted_item(ctx, progress_rx).await, vec!["book.epub".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_download_item_skips_item_claimed_elsewhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, mut progress_rx) = mock_context(dir.path());
+        let _other_instance = claims::try_claim(&ctx.base_dir, "mock_item_one", &ctx.path_policy).unwrap().unwrap();
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct)
+            .await
+            .expect("Skipping a claimed item is not an error");
+
+        assert!(!dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one").exists());
+        drop(ctx);
+        let mut skipped = false;
+        while let Some(progress) = progress_rx.recv().await {
+            skipped |= matches!(progress, DownloadProgress::ItemSkippedClaimed(ref id) if id == "mock_item_one");
+        }
+        assert!(skipped, "Item should be reported as claimed by another instance");
+    }
+
+    #[tokio::test]
+    async fn test_partial_files_resume_with_range_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, mut progress_rx) = mock_context(dir.path());
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        std::fs::create_dir_all(&item_dir).unwrap();
+        let track = mock_server::file_bytes("mock_item_one", "track01.mp3").unwrap();
+        std::fs::write(paths::part_path(&item_dir.join("track01.mp3")), &track[..1000]).unwrap();
+        // Longer than the listed size: can't be resumed, so it is downloaded again
+        std::fs::write(paths::part_path(&item_dir.join("cover.jpg")), vec![0u8; 2000]).unwrap();
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct)
+            .await
+            .expect("Mock item download should succeed");
+
+        for (name, _, _, _) in mock_server::MOCK_FILES {
+            assert_eq!(std::fs::read(item_dir.join(name)).ok(), mock_server::file_bytes("mock_item_one", name));
+            assert!(!paths::part_path(&item_dir.join(name)).exists());
+        }
+        drop(ctx);
+        let mut transferred = 0;
+        while let Some(progress) = progress_rx.recv().await {
+            if let DownloadProgress::FileBytes(_, bytes) = progress {
+                transferred += bytes;
+            }
+        }
+        assert_eq!(transferred, (track.len() - 1000 + 1024) as u64, "Only the missing part of the track is fetched");
+    }
+
+    #[tokio::test]
+    async fn test_download_item_applies_file_preset() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, _progress_rx) = mock_context(dir.path());
+        ctx.file_preset = FilePreset::BestAudio;
+
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct)
+            .await
+            .expect("Mock item download should succeed");
+
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        assert!(item_dir.join("track01.mp3").exists());
+        assert!(!item_dir.join("cover.jpg").exists(), "Preset should skip non-audio files");
+    }
+
+    #[tokio::test]
+    async fn test_bytes_still_needed_counts_missing_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let item_dir = dir.path().join("item");
+        let file = |name: &str, size: Option<&str>| {
+            let details = archive_api::FileDetails { name: name.to_string(), size: size.map(str::to_string), ..Default::default() };
+            (details, item_dir.join(name))
+        };
+        std::fs::create_dir_all(&item_dir).unwrap();
+        std::fs::write(item_dir.join("done.flac"), vec![0u8; 100]).unwrap();
+        std::fs::write(paths::part_path(&item_dir.join("half.flac")), vec![0u8; 40]).unwrap();
+
+        let files = [file("done.flac", Some("100")), file("half.flac", Some("100")), file("new.flac", Some("10")), file("unknown.txt", None)];
+        assert_eq!(bytes_still_needed(&files).await, 70);
+    }
+
+    #[tokio::test]
+    async fn test_paused_item_waits_and_cancelled_item_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, mut progress_rx) = mock_context(dir.path());
+        ctx.control.toggle_pause_item("mock_item_one");
+        let task = tokio::spawn({
+            let ctx = ctx.clone();
+            async move { download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!task.is_finished(), "Paused item waits");
+
+        ctx.control.cancel_item("mock_item_one");
+        task.await.unwrap().expect("Cancelling isn't a task error");
+        let item_dir = dir.path().join(mock_server::MOCK_COLLECTION).join("mock_item_one");
+        assert!(!item_dir.join("track01.mp3").exists());
+        let mut completed = None;
+        while let Ok(progress) = progress_rx.try_recv() {
+            if let DownloadProgress::ItemCompleted(_, success) = progress {
+                completed = Some(success);
+            }
+        }
+        assert_eq!(completed, Some(false));
+
+        // Once the app forgets the cancelled item, it can be downloaded again
+        ctx.control.forget_item("mock_item_one");
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::Direct).await.unwrap();
+        assert!(item_dir.join("track01.mp3").exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_file_is_fetched_from_a_mirror() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, _progress_rx) = mock_context(dir.path());
+        let (name, _, _, size) = mock_server::MOCK_FILES[0];
+        let file = archive_api::FileDetails { name: name.to_string(), size: Some(size.to_string()), ..Default::default() };
+
+        // archive.org doesn't know the item (404), the "mirror" serves mock_item_one's copy
+        let missing = download_single_file(&ctx, None, "mirrored_item", &file, &[]).await;
+        assert!(missing.is_err(), "No mirror to fall back on");
+        let mirror = format!("{}/download/mock_item_one", mock_server::shared_base_url());
+        download_single_file(&ctx, None, "mirrored_item", &file, &[mirror]).await.expect("Mirror should serve the file");
+        let written = std::fs::read(dir.path().join("mirrored_item").join(name)).unwrap();
+        assert_eq!(written, mock_server::file_bytes("mock_item_one", name).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_embedded_torrent_mode_falls_back_to_http() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ctx, mut progress_rx) = mock_context(dir.path());
+
+        // The mock torrent lists no trackers, so every piece comes over HTTP
+        download_item(&ctx, Some(mock_server::MOCK_COLLECTION), "mock_item_one", DownloadMode::TorrentEmbedded)
+            .await
+            .expect("Embedded torrent download should succeed");
+
+        let collection_dir = dir.path().join(mock_server::MOCK_COLLECTION);
+        assert!(collection_dir.join("mock_item_one_archive.torrent").exists());
+        for (name, _, _, _) in mock_server::MOCK_FILES {
+            let written = std::fs::read(collection_dir.join("mock_item_one").join(name)).unwrap();
+            assert_eq!(Some(written), mock_server::file_bytes("mock_item_one", name));
+        }
+        let mut succeeded = false;
+        while let Ok(progress) = progress_rx.try_recv() {
+            if let DownloadProgress::ItemCompleted(_, success) = progress {
+                succeeded = success;
+            }
+        }
+        assert!(succeeded);
+    }
+
+    #[tokio::test]
+    async fn test_download_collection_skips_excluded_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut ctx, mut progress_rx) = mock_context(dir.path());
+        let settings = crate::settings::Settings {
+            excluded_patterns: vec!["_two$".to_string()],
+            ..Default::default()
+        };
+        ctx.exclusions = Arc::new(ExclusionList::from_settings(&settings));
+
+        download_collection(&ctx, mock_server::MOCK_COLLECTION, DownloadMode::Direct)
+            .await
+            .expect("Mock collection download should succeed");
+
+        let collection_dir = dir.path().join(mock_server::MOCK_COLLECTION);
+        assert!(collection_dir.join("mock_item_one").exists());
+        assert!(!collection_dir.join("mock_item_two").exists(), "Excluded item must not be downloaded");
+        drop(ctx);
+        let mut total = None;
+        let mut reported = None;
+        while let Some(progress) = progress_rx.recv().await {
+            match progress {
+                DownloadProgress::CollectionInfo(count) => total = Some(count),
+                DownloadProgress::CollectionFinished(run) => reported = Some((run.collection, run.items)),
+                _ => {}
+            }
+        }
+        assert_eq!(total, Some(1));
+        assert_eq!(reported, Some((mock_server::MOCK_COLLECTION.to_string(), vec!["mock_item_one".to_string()])), "The report lists what was attempted");
+    }
+}
@@ -0,0 +1,197 @@
+use crate::app::AppRateLimiter;
+use crate::archive_api::{self, FileDetails};
+use crate::exclusions::ExclusionList;
+use crate::presets::{self, FilePreset, SourcePolicy};
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::mpsc;
+
+/// Dry run of a collection download ('B'): the metadata of every item is fetched and the
+/// files the current preset and download filter would fetch are added up, without
+/// downloading anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeEstimate {
+    pub collection: String,
+    /// Preset the estimate was made with.
+    pub preset: FilePreset,
+    /// Items to check, once the listing is known.
+    pub items_total: Option<usize>,
+    pub items_checked: usize,
+    /// Items whose metadata couldn't be fetched.
+    pub items_failed: usize,
+    pub files: usize,
+    pub bytes: u64,
+    /// Selected files without a listed size.
+    pub unknown_sizes: usize,
+    /// Files and bytes per archive.org format ("Flac", "VBR MP3"...).
+    pub by_format: BTreeMap<String, FormatTotal>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FormatTotal {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// What the estimate task reports, in order: the item count, then one message per item.
+#[derive(Debug)]
+pub enum EstimateUpdate {
+    Listed(usize),
+    /// The files planned for one item, or why its metadata couldn't be fetched.
+    Item(Result<Vec<FileDetails>>),
+}
+
+/// How the download being estimated would pick files.
+#[derive(Debug, Clone)]
+pub struct FilePlan {
+    pub preset: FilePreset,
+    pub sources: SourcePolicy,
+    pub filter: Vec<String>,
+    pub subtitle_languages: Option<Vec<String>>,
+}
+
+impl SizeEstimate {
+    pub fn new(collection: &str, preset: FilePreset) -> Self {
+        Self {
+            collection: collection.to_string(),
+            preset,
+            items_total: None,
+            items_checked: 0,
+            items_failed: 0,
+            files: 0,
+            bytes: 0,
+            unknown_sizes: 0,
+            by_format: BTreeMap::new(),
+        }
+    }
+
+    pub fn apply(&mut self, update: EstimateUpdate) {
+        match update {
+            EstimateUpdate::Listed(count) => self.items_total = Some(count),
+            EstimateUpdate::Item(Ok(files)) => {
+                self.items_checked += 1;
+                for file in files {
+                    let size = file.size.as_deref().and_then(|s| s.parse::<u64>().ok());
+                    let total = self.by_format.entry(file.format.unwrap_or_else(|| "Unknown".to_string())).or_default();
+                    total.files += 1;
+                    total.bytes += size.unwrap_or(0);
+                    self.files += 1;
+                    self.bytes += size.unwrap_or(0);
+                    self.unknown_sizes += usize::from(size.is_none());
+                }
+            }
+            EstimateUpdate::Item(Err(_)) => {
+                self.items_checked += 1;
+                self.items_failed += 1;
+            }
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.items_total.is_some_and(|total| self.items_checked >= total)
+    }
+
+    /// Formats by total size, largest first.
+    pub fn formats_by_size(&self) -> Vec<(&str, FormatTotal)> {
+        let mut formats: Vec<(&str, FormatTotal)> = self.by_format.iter().map(|(name, total)| (name.as_str(), *total)).collect();
+        formats.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        formats
+    }
+}
+
+/// The items of the collection an estimate covers.
+#[derive(Debug, Clone)]
+pub enum EstimateItems {
+    /// Identifiers already listed (the open collection or its item cache).
+    Listed(Vec<String>),
+    /// Not listed yet: list the collection first, narrowed by these fetch filter clauses
+    /// (see [`crate::fetch_filter`]).
+    Fetch(Option<String>),
+}
+
+/// Fetches the metadata of every item of `collection` (listing it first if `items` aren't
+/// known) and sends what `plan` would download for each. Excluded items are left out, as
+/// collection downloads skip them. Stops when `tx` is closed.
+pub async fn run(
+    client: Client,
+    rate_limiter: AppRateLimiter,
+    collection: String,
+    items: EstimateItems,
+    exclusions: Arc<ExclusionList>,
+    plan: FilePlan,
+    tx: mpsc::Sender<(String, EstimateUpdate)>,
+) {
+    let mut identifiers = match items {
+        EstimateItems::Listed(identifiers) => identifiers,
+        EstimateItems::Fetch(filter) => match archive_api::fetch_collection_items_bulk(&client, &collection, filter.as_deref(), Arc::clone(&rate_limiter)).await {
+            Ok((docs, _)) => docs.into_iter().map(|doc| doc.identifier).collect(),
+            Err(e) => {
+                let _ = tx.send((collection.clone(), EstimateUpdate::Listed(1))).await;
+                let _ = tx.send((collection, EstimateUpdate::Item(Err(e.context("Failed to list the collection"))))).await;
+                return;
+            }
+        },
+    };
+    identifiers.retain(|id| !exclusions.is_excluded(id));
+    if tx.send((collection.clone(), EstimateUpdate::Listed(identifiers.len()))).await.is_err() {
+        return;
+    }
+    for identifier in identifiers {
+        let files = archive_api::fetch_item_details(&client, &identifier, Arc::clone(&rate_limiter))
+            .await
+            .map(|details| presets::plan_files(&details, plan.preset, plan.sources, &plan.filter, plan.subtitle_languages.as_deref()))
+            .map_err(|e| anyhow!("{}: {}", identifier, e));
+        if tx.send((collection.clone(), EstimateUpdate::Item(files))).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(format: &str, size: Option<&str>) -> FileDetails {
+        FileDetails { name: "f".to_string(), format: Some(format.to_string()), size: size.map(str::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn test_estimate_totals_by_format() {
+        let mut estimate = SizeEstimate::new("coll", FilePreset::AllFiles);
+        estimate.apply(EstimateUpdate::Listed(3));
+        estimate.apply(EstimateUpdate::Item(Ok(vec![file("Flac", Some("300")), file("VBR MP3", Some("50"))])));
+        estimate.apply(EstimateUpdate::Item(Ok(vec![file("Flac", Some("200")), file("Flac", None)])));
+        assert!(!estimate.is_complete());
+        estimate.apply(EstimateUpdate::Item(Err(anyhow!("gone"))));
+
+        assert!(estimate.is_complete());
+        assert_eq!((estimate.items_checked, estimate.items_failed), (3, 1));
+        assert_eq!((estimate.files, estimate.bytes, estimate.unknown_sizes), (4, 550, 1));
+        assert_eq!(estimate.formats_by_size(), vec![("Flac", FormatTotal { files: 3, bytes: 500 }), ("VBR MP3", FormatTotal { files: 1, bytes: 50 })]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_run_lists_and_plans_every_item_mock() {
+        use crate::mock_server::{self, MOCK_COLLECTION, MOCK_ITEMS};
+        use governor::Quota;
+        use std::num::NonZeroU32;
+
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let limiter: AppRateLimiter = Arc::new(crate::rate_limit::SharedRateLimiter::new(Quota::per_minute(NonZeroU32::new(600).unwrap())));
+        let (tx, mut rx) = mpsc::channel(16);
+        let plan = FilePlan { preset: FilePreset::AllFiles, sources: SourcePolicy::All, filter: Vec::new(), subtitle_languages: None };
+        run(Client::new(), limiter, MOCK_COLLECTION.to_string(), EstimateItems::Fetch(None), Arc::new(ExclusionList::default()), plan, tx).await;
+
+        let mut estimate = SizeEstimate::new(MOCK_COLLECTION, FilePreset::AllFiles);
+        while let Some((collection, update)) = rx.recv().await {
+            assert_eq!(collection, MOCK_COLLECTION);
+            estimate.apply(update);
+        }
+        assert!(estimate.is_complete());
+        assert_eq!(estimate.items_checked, MOCK_ITEMS.len());
+        assert_eq!(estimate.items_failed, 0);
+        assert!(estimate.files >= MOCK_ITEMS.len() && estimate.bytes > 0);
+    }
+}
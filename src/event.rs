@@ -1,24 +1,86 @@
 use anyhow::Result;
-use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
+use crossterm::event::{self, Event as CrosstermEvent, KeyCode, KeyEvent, MouseEvent};
 use std::{
     sync::mpsc,
     thread,
     time::{Duration, Instant},
 };
 
+/// Max gap between two presses of the same navigation key for them to count as one held key (ms).
+/// Terminals typically auto-repeat every ~30ms once the initial repeat delay has passed.
+const KEY_HOLD_WINDOW_MS: u64 = 120;
+/// How long a key must stay held before its stride doubles again (ms).
+const KEY_ACCEL_INTERVAL_MS: u64 = 500;
+/// Upper bound on how many rows a single held-key repeat may move.
+const KEY_ACCEL_MAX_STRIDE: usize = 32;
+
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
     /// Key press.
     Key(KeyEvent),
+    /// A navigation key that was held down or repeated faster than we render,
+    /// coalesced into a single event carrying the number of steps to apply.
+    KeyRepeat(KeyEvent, usize),
+    /// Text pasted into the terminal (bracketed paste).
+    Paste(String),
     /// Mouse click/scroll.
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
 }
 
+/// Returns true for keys whose repeats can be merged into a single multi-step move.
+/// Only pure navigation keys qualify, since they never trigger side effects.
+pub fn is_repeatable(key: &KeyEvent) -> bool {
+    matches!(
+        key.code,
+        KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown
+    )
+}
+
+/// Tracks how long a navigation key has been held and derives an accelerating stride.
+///
+/// The stride starts at 1 and doubles every `KEY_ACCEL_INTERVAL_MS` while the key keeps
+/// repeating, capped at `KEY_ACCEL_MAX_STRIDE`. Any pause longer than `KEY_HOLD_WINDOW_MS`
+/// or a different key resets the streak.
+#[derive(Debug, Default)]
+pub struct KeyRepeatTracker {
+    /// The key (code + modifiers) of the current streak.
+    last_key: Option<KeyEvent>,
+    /// When the current streak started.
+    streak_start: Option<Instant>,
+    /// When the last press of the streak was seen.
+    last_seen: Option<Instant>,
+}
+
+impl KeyRepeatTracker {
+    /// Registers a key press at `now` and returns how many steps it should move.
+    pub fn register(&mut self, key: &KeyEvent, now: Instant) -> usize {
+        let same_key = self
+            .last_key
+            .is_some_and(|last| last.code == key.code && last.modifiers == key.modifiers);
+        let within_window = self.last_seen.is_some_and(|seen| {
+            now.saturating_duration_since(seen) <= Duration::from_millis(KEY_HOLD_WINDOW_MS)
+        });
+
+        if !(same_key && within_window) {
+            // New key or the previous hold was released: start a fresh streak
+            self.last_key = Some(*key);
+            self.streak_start = Some(now);
+        }
+        self.last_seen = Some(now);
+
+        let held_ms = self
+            .streak_start
+            .map_or(0, |start| now.saturating_duration_since(start).as_millis() as u64);
+        let doublings = (held_ms / KEY_ACCEL_INTERVAL_MS).min(usize::BITS as u64 - 1) as u32;
+        (1usize << doublings).min(KEY_ACCEL_MAX_STRIDE)
+    }
+}
+
 /// Terminal event handler.
 #[derive(Debug)]
 pub struct EventHandler {
@@ -40,19 +102,45 @@ impl EventHandler {
             let sender = sender.clone(); // Keep the clone for the thread
             thread::spawn(move || {
                 let mut last_tick = Instant::now();
+                let mut repeat_tracker = KeyRepeatTracker::default();
                 loop {
                     let timeout = tick_rate
                         .checked_sub(last_tick.elapsed())
                         .unwrap_or(tick_rate);
 
                     if event::poll(timeout).expect("unable to poll for event") {
-                        match event::read().expect("unable to read event") {
-                            CrosstermEvent::Key(e) => sender.send(Event::Key(e)), // Use the cloned sender
-                            CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)), // Use the cloned sender
-                            CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)), // Use the cloned sender
-                            _ => Ok(()), // Ignore other event types
+                        let mut next_event = Some(event::read().expect("unable to read event"));
+                        while let Some(crossterm_event) = next_event.take() {
+                            match crossterm_event {
+                                CrosstermEvent::Key(e) if is_repeatable(&e) => {
+                                    // Drain whatever repeats of this key are already queued so a held
+                                    // arrow key produces one event (and one redraw) per batch.
+                                    let mut steps = repeat_tracker.register(&e, Instant::now());
+                                    while event::poll(Duration::ZERO).expect("unable to poll for event") {
+                                        match event::read().expect("unable to read event") {
+                                            CrosstermEvent::Key(next)
+                                                if next.code == e.code && next.modifiers == e.modifiers =>
+                                            {
+                                                steps += repeat_tracker.register(&next, Instant::now());
+                                            }
+                                            other => {
+                                                // Different event: handle it after flushing this batch
+                                                next_event = Some(other);
+                                                break;
+                                            }
+                                        }
+                                    }
+                                    let coalesced = if steps == 1 { Event::Key(e) } else { Event::KeyRepeat(e, steps) };
+                                    sender.send(coalesced)
+                                }
+                                CrosstermEvent::Key(e) => sender.send(Event::Key(e)), // Use the cloned sender
+                                CrosstermEvent::Paste(text) => sender.send(Event::Paste(text)),
+                                CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)), // Use the cloned sender
+                                CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)), // Use the cloned sender
+                                _ => Ok(()), // Ignore other event types
+                            }
+                            .expect("failed to send terminal event")
                         }
-                        .expect("failed to send terminal event")
                     }
 
                     if last_tick.elapsed() >= tick_rate {
@@ -76,3 +164,61 @@ impl EventHandler {
         Ok(self.receiver.recv()?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_single_press_moves_one_step() {
+        let mut tracker = KeyRepeatTracker::default();
+        assert_eq!(tracker.register(&key(KeyCode::Down), Instant::now()), 1);
+    }
+
+    #[test]
+    fn test_held_key_accelerates_and_caps() {
+        let mut tracker = KeyRepeatTracker::default();
+        let start = Instant::now();
+        let mut stride = 0;
+        // Simulate a key held for 5 seconds with a 30ms auto-repeat rate
+        for i in 0..=(5000 / 30) {
+            stride = tracker.register(&key(KeyCode::Down), start + Duration::from_millis(i * 30));
+        }
+        assert_eq!(stride, KEY_ACCEL_MAX_STRIDE, "Stride should be capped after a long hold");
+
+        // Shortly after starting a hold the stride should still be small
+        let mut tracker = KeyRepeatTracker::default();
+        tracker.register(&key(KeyCode::Down), start);
+        let early = tracker.register(&key(KeyCode::Down), start + Duration::from_millis(30));
+        assert_eq!(early, 1);
+    }
+
+    #[test]
+    fn test_pause_or_other_key_resets_streak() {
+        let mut tracker = KeyRepeatTracker::default();
+        let start = Instant::now();
+        for i in 0..=(2000 / 30) {
+            tracker.register(&key(KeyCode::Down), start + Duration::from_millis(i * 30));
+        }
+        // Releasing the key for longer than the hold window resets acceleration
+        let after_pause = start + Duration::from_millis(2000 + KEY_HOLD_WINDOW_MS + 100);
+        assert_eq!(tracker.register(&key(KeyCode::Down), after_pause), 1);
+
+        // Switching keys also resets acceleration
+        let later = after_pause + Duration::from_millis(10);
+        assert_eq!(tracker.register(&key(KeyCode::Up), later), 1);
+    }
+
+    #[test]
+    fn test_only_navigation_keys_are_repeatable() {
+        assert!(is_repeatable(&key(KeyCode::Down)));
+        assert!(is_repeatable(&key(KeyCode::PageUp)));
+        assert!(!is_repeatable(&key(KeyCode::Enter)));
+        assert!(!is_repeatable(&key(KeyCode::Char('d'))));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use log::warn;
+use regex::Regex;
+use std::{collections::HashSet, fmt};
+
+/// Kind of an exclusion rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusionKind {
+    /// Matches one identifier exactly.
+    Identifier,
+    /// Regular expression matched anywhere in the identifier (anchor with `^...$`).
+    Pattern,
+}
+
+impl fmt::Display for ExclusionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExclusionKind::Identifier => write!(f, "Identifier"),
+            ExclusionKind::Pattern => write!(f, "Pattern"),
+        }
+    }
+}
+
+/// A rule as stored in the settings, in the order shown by the editor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExclusionRule {
+    pub kind: ExclusionKind,
+    pub value: String,
+}
+
+/// Lists the rules from `settings`: exact identifiers first, then patterns.
+pub fn rules(settings: &Settings) -> Vec<ExclusionRule> {
+    let identifiers = settings.excluded_identifiers.iter().map(|value| ExclusionRule { kind: ExclusionKind::Identifier, value: value.clone() });
+    let patterns = settings.excluded_patterns.iter().map(|value| ExclusionRule { kind: ExclusionKind::Pattern, value: value.clone() });
+    identifiers.chain(patterns).collect()
+}
+
+/// Checks that `pattern` is a valid regular expression.
+pub fn validate_pattern(pattern: &str) -> Result<()> {
+    Regex::new(pattern).map(|_| ()).context(format!("Invalid pattern '{}'", pattern))
+}
+
+/// Compiled exclusion rules, consulted before items of a collection are queued.
+#[derive(Debug, Default)]
+pub struct ExclusionList {
+    identifiers: HashSet<String>,
+    patterns: Vec<Regex>,
+}
+
+impl ExclusionList {
+    /// Compiles the rules in `settings`. Invalid patterns (e.g. hand-edited into the
+    /// settings file) are logged and ignored.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let patterns = settings
+            .excluded_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!("Ignoring invalid exclusion pattern '{}': {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Self { identifiers: settings.excluded_identifiers.iter().cloned().collect(), patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identifiers.is_empty() && self.patterns.is_empty()
+    }
+
+    /// The rule excluding `identifier`, if any (the identifier itself or the pattern).
+    pub fn matching_rule(&self, identifier: &str) -> Option<&str> {
+        if let Some(id) = self.identifiers.get(identifier) {
+            return Some(id);
+        }
+        self.patterns.iter().find(|regex| regex.is_match(identifier)).map(Regex::as_str)
+    }
+
+    pub fn is_excluded(&self, identifier: &str) -> bool {
+        self.matching_rule(identifier).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_and_pattern_rules() {
+        let settings = Settings {
+            excluded_identifiers: vec!["bad_item".to_string()],
+            excluded_patterns: vec!["_dupe$".to_string(), "(".to_string()],
+            ..Settings::default()
+        };
+        let list = ExclusionList::from_settings(&settings);
+
+        assert_eq!(list.matching_rule("bad_item"), Some("bad_item"));
+        assert!(!list.is_excluded("bad_item_2"), "Identifiers match exactly");
+        assert_eq!(list.matching_rule("concert_1977_dupe"), Some("_dupe$"));
+        assert!(!list.is_excluded("dupe_concert"));
+        // The invalid pattern is dropped rather than excluding everything or nothing
+        assert_eq!(list.patterns.len(), 1);
+
+        assert_eq!(rules(&settings).len(), 3);
+        assert_eq!(rules(&settings)[1], ExclusionRule { kind: ExclusionKind::Pattern, value: "_dupe$".to_string() });
+        assert!(validate_pattern("(").is_err());
+    }
+}
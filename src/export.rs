@@ -0,0 +1,143 @@
+use crate::archive_api::{ArchiveDoc, ItemDetails};
+use crate::fs_util;
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::Path;
+
+/// File format of an export, picked from the target file's extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// `.json` files get JSON, anything else CSV.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ExportFormat::Json,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+/// Writes the listed items (one row per item) to `path`.
+pub fn write_items(path: &Path, items: &[&ArchiveDoc]) -> Result<()> {
+    let contents = match ExportFormat::for_path(path) {
+        ExportFormat::Csv => items_csv(items),
+        ExportFormat::Json => serde_json::to_string_pretty(items).context("Failed to serialize items")?,
+    };
+    fs_util::atomic_write(path, contents).with_context(|| format!("Failed to write export '{}'", path.display()))
+}
+
+/// Writes an item's metadata and file list to `path`. CSV has one row per file; the
+/// item's metadata is only included in JSON.
+pub fn write_item(path: &Path, details: &ItemDetails) -> Result<()> {
+    let contents = match ExportFormat::for_path(path) {
+        ExportFormat::Csv => files_csv(details),
+        ExportFormat::Json => serde_json::to_string_pretty(&item_json(details)).context("Failed to serialize item")?,
+    };
+    fs_util::atomic_write(path, contents).with_context(|| format!("Failed to write export '{}'", path.display()))
+}
+
+fn items_csv(items: &[&ArchiveDoc]) -> String {
+    let mut out = csv_row(&["identifier", "title", "mediatype", "item_size", "format"]);
+    for doc in items {
+        out.push_str(&csv_row(&[
+            &doc.identifier,
+            doc.title.as_deref().unwrap_or(""),
+            doc.mediatype.as_deref().unwrap_or(""),
+            &doc.item_size.map_or(String::new(), |size| size.to_string()),
+            &doc.format.join(";"),
+        ]));
+    }
+    out
+}
+
+fn files_csv(details: &ItemDetails) -> String {
+    let mut out = csv_row(&["identifier", "name", "source", "format", "size", "md5", "private"]);
+    for file in &details.files {
+        out.push_str(&csv_row(&[
+            &details.identifier,
+            &file.name,
+            file.source.as_deref().unwrap_or(""),
+            file.format.as_deref().unwrap_or(""),
+            file.size.as_deref().unwrap_or(""),
+            file.md5.as_deref().unwrap_or(""),
+            if file.private { "true" } else { "false" },
+        ]));
+    }
+    out
+}
+
+fn item_json(details: &ItemDetails) -> serde_json::Value {
+    json!({
+        "identifier": details.identifier,
+        "title": details.title,
+        "creator": details.creator,
+        "description": details.description,
+        "date": details.date,
+        "uploader": details.uploader,
+        "collections": details.collections,
+        "mediatype": details.mediatype,
+        "access_restricted": details.access_restricted,
+        "related_identifiers": details.related_identifiers,
+        "files": details.files,
+    })
+}
+
+/// One CSV line (RFC 4180): fields with commas, quotes or line breaks are quoted.
+fn csv_row(fields: &[&str]) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    format!("{}\r\n", quoted.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive_api::FileDetails;
+
+    #[test]
+    fn test_export_items_and_item_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = ArchiveDoc {
+            identifier: "gd77".to_string(),
+            title: Some("Live, \"Cornell\"".to_string()),
+            item_size: Some(42),
+            format: vec!["Flac".to_string(), "VBR MP3".to_string()],
+            ..Default::default()
+        };
+        let csv_path = dir.path().join("items.csv");
+        write_items(&csv_path, &[&doc]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&csv_path).unwrap(),
+            "identifier,title,mediatype,item_size,format\r\ngd77,\"Live, \"\"Cornell\"\"\",,42,Flac;VBR MP3\r\n"
+        );
+        let json_path = dir.path().join("items.JSON");
+        write_items(&json_path, &[&doc]).unwrap();
+        let parsed: Vec<ArchiveDoc> = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(parsed[0].identifier, "gd77");
+
+        let details = ItemDetails {
+            identifier: "gd77".to_string(),
+            title: Some("Live".to_string()),
+            files: vec![FileDetails { name: "d1t01.flac".to_string(), size: Some("10".to_string()), ..Default::default() }],
+            ..Default::default()
+        };
+        write_item(&csv_path, &details).unwrap();
+        assert_eq!(std::fs::read_to_string(&csv_path).unwrap().lines().nth(1), Some("gd77,d1t01.flac,,,10,,false"));
+        write_item(&json_path, &details).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(parsed["title"], "Live");
+        assert_eq!(parsed["files"][0]["name"], "d1t01.flac");
+    }
+}
@@ -0,0 +1,213 @@
+use crate::fs_util;
+use crate::report::{FileOutcome, ItemOutcome, RunReport, REPORTS_DIR};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File name prefix of the failure lists in [`REPORTS_DIR`]; the rest is the run's time,
+/// so the newest sorts last.
+const FAILURES_PREFIX: &str = "failures-";
+
+/// Rough cause of a failure, guessed from its error chain.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// 404: the item or file is gone (or was never public).
+    NotFound,
+    /// 401/403: needs a login that can access it.
+    Forbidden,
+    /// 429: archive.org asked to slow down.
+    RateLimited,
+    /// 5xx responses.
+    Server,
+    /// Timeouts, refused and dropped connections.
+    Network,
+    /// Writing the file failed (disk full, permissions).
+    Disk,
+    /// The claimed item was being downloaded by another instance.
+    Claimed,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn classify(reason: &str) -> Self {
+        let reason = reason.to_lowercase();
+        let status = |codes: &[&str]| codes.iter().any(|code| reason.contains(&format!("status {}", code)));
+        if status(&["404", "410"]) || reason.contains("not found") {
+            ErrorKind::NotFound
+        } else if status(&["401", "403"]) || reason.contains("forbidden") {
+            ErrorKind::Forbidden
+        } else if status(&["429"]) || reason.contains("too many requests") {
+            ErrorKind::RateLimited
+        } else if status(&["5"]) {
+            ErrorKind::Server
+        } else if reason.contains("another instance") {
+            ErrorKind::Claimed
+        } else if ["timed out", "timeout", "connection", "send download request", "read download chunk", "dns"].iter().any(|s| reason.contains(s)) {
+            ErrorKind::Network
+        } else if ["failed to write", "failed to create", "failed to move", "failed to flush", "no space", "permission denied"].iter().any(|s| reason.contains(s)) {
+            ErrorKind::Disk
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    /// Whether the cause is likely to pass, so trying again later may succeed.
+    pub fn is_transient(self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::Server | ErrorKind::Network)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "Not found",
+            ErrorKind::Forbidden => "Forbidden",
+            ErrorKind::RateLimited => "Rate limited",
+            ErrorKind::Server => "Server error",
+            ErrorKind::Network => "Network",
+            ErrorKind::Disk => "Disk",
+            ErrorKind::Claimed => "Claimed",
+            ErrorKind::Other => "Other",
+        }
+    }
+}
+
+/// An item (or one of its files) that failed to download.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub identifier: String,
+    /// `None` when the item failed as a whole (e.g. its metadata couldn't be fetched).
+    pub file: Option<String>,
+    pub kind: ErrorKind,
+    /// The error chain.
+    pub reason: String,
+    /// RFC 3339 time the item finished.
+    pub time: String,
+}
+
+/// The failures of one download run, saved next to its report so they survive a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RunFailures {
+    /// RFC 3339 start time of the run (empty before the first run).
+    pub started: String,
+    pub failures: Vec<Failure>,
+}
+
+impl RunFailures {
+    /// Failed files and items of a finished run.
+    pub fn from_report(report: &RunReport) -> Self {
+        let finished = report.finished.clone().unwrap_or_default();
+        let mut failures = Vec::new();
+        for item in &report.items {
+            let time = item.finished.clone().unwrap_or_else(|| finished.clone());
+            let before = failures.len();
+            for file in &item.files {
+                if let FileOutcome::Failed(reason) = &file.outcome {
+                    failures.push(Failure {
+                        identifier: item.identifier.clone(),
+                        file: Some(file.name.clone()),
+                        kind: ErrorKind::classify(reason),
+                        reason: reason.clone(),
+                        time: time.clone(),
+                    });
+                }
+            }
+            if failures.len() == before && item.outcome == Some(ItemOutcome::Failed) {
+                // No file to blame; the run's errors name the item when they say why
+                let reason = report
+                    .errors
+                    .iter()
+                    .find(|error| error.contains(&item.identifier))
+                    .cloned()
+                    .unwrap_or_else(|| "The item download failed; see the log".to_string());
+                failures.push(Failure { identifier: item.identifier.clone(), file: None, kind: ErrorKind::classify(&reason), reason, time });
+            }
+        }
+        Self { started: report.started.clone(), failures }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The failed items, each once, in the order they failed.
+    pub fn identifiers(&self) -> Vec<String> {
+        let mut identifiers: Vec<String> = Vec::new();
+        for failure in &self.failures {
+            if !identifiers.contains(&failure.identifier) {
+                identifiers.push(failure.identifier.clone());
+            }
+        }
+        identifiers
+    }
+
+    /// Writes the list as `failures-<time>.json` into `<download_dir>/.archiver-reports`.
+    pub fn write(&self, download_dir: &Path) -> Result<PathBuf> {
+        let dir = download_dir.join(REPORTS_DIR);
+        fs::create_dir_all(&dir).context(format!("Failed to create report directory {}", dir.display()))?;
+        let path = dir.join(format!("{}{}.json", FAILURES_PREFIX, chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize the failure list")?;
+        fs_util::atomic_write(&path, json).context(format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// The list of the latest run below `download_dir`, if there was one.
+    pub fn load_latest(download_dir: &Path) -> Option<Self> {
+        let entries = fs::read_dir(download_dir.join(REPORTS_DIR)).ok()?;
+        let path = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(FAILURES_PREFIX) && name.ends_with(".json")))
+            .max()?;
+        let loaded = fs::read_to_string(&path).map_err(anyhow::Error::from).and_then(|json| Ok(serde_json::from_str(&json)?));
+        match loaded {
+            Ok(failures) => Some(failures),
+            Err(e) => {
+                warn!("Ignoring failure list {}: {:#}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::FileResult;
+
+    #[test]
+    fn test_failures_of_a_run_are_kept_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut report = RunReport::new(dir.path().to_path_buf());
+        report.item_started("good");
+        report.item_finished("good", ItemOutcome::Downloaded);
+        report.item_started("bad");
+        for (name, reason) in [("a.mp3", "Download request failed for 'a.mp3': Status 404 Not Found"), ("b.mp3", "Failed to send download request for b.mp3: timed out")] {
+            let outcome = FileOutcome::Failed(reason.to_string());
+            report.file_finished(FileResult { item_id: "bad".to_string(), name: name.to_string(), outcome, bytes: 0, size_verified: None });
+        }
+        report.item_finished("bad", ItemOutcome::Failed);
+        report.error("Failed to fetch metadata for 'gone': Status 503 Service Unavailable".to_string());
+        report.item_started("gone");
+        report.item_finished("gone", ItemOutcome::Failed);
+        report.finish();
+
+        let failures = RunFailures::from_report(&report);
+        let kinds: Vec<_> = failures.failures.iter().map(|f| (f.identifier.as_str(), f.file.as_deref(), f.kind)).collect();
+        assert_eq!(
+            kinds,
+            [("bad", Some("a.mp3"), ErrorKind::NotFound), ("bad", Some("b.mp3"), ErrorKind::Network), ("gone", None, ErrorKind::Server)]
+        );
+        assert!(!failures.failures[0].time.is_empty());
+        assert_eq!(failures.identifiers(), ["bad", "gone"]);
+
+        assert!(RunFailures::load_latest(dir.path()).is_none());
+        failures.write(dir.path()).unwrap();
+        report.write().unwrap();
+        let loaded = RunFailures::load_latest(dir.path()).unwrap();
+        assert_eq!(loaded.failures, failures.failures);
+        assert_eq!(ErrorKind::classify("Failed to write chunk to file 'x.part'"), ErrorKind::Disk);
+        assert_eq!(ErrorKind::classify("Item 'x' is being downloaded by another instance"), ErrorKind::Claimed);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::app::AppRateLimiter;
+use crate::archive_api::{self, ArchiveDoc};
+use anyhow::{bail, Context, Result};
+use log::info;
+use reqwest::Client;
+
+/// Identifier of the public collection archive.org keeps a user's favorites in.
+pub fn favorites_collection(user: &str) -> String {
+    format!("fav-{}", user.trim().trim_start_matches('@').to_lowercase())
+}
+
+/// Collections to add for `user`'s favorites: the favorites collection itself (which lists
+/// every favorited item), followed by the collections among the favorites.
+pub async fn fetch_favorite_collections(client: &Client, user: &str, rate_limiter: AppRateLimiter) -> Result<Vec<String>> {
+    let collection = favorites_collection(user);
+    let (docs, _) = archive_api::fetch_collection_items_bulk(client, &collection, None, rate_limiter)
+        .await
+        .with_context(|| format!("Failed to list the favorites of '{}'", user))?;
+    if docs.is_empty() {
+        bail!("No public favorites found for archive.org user '{}' (check the screen name)", user);
+    }
+    let collections = collections_to_import(&collection, &docs);
+    info!("Found {} favorited items of '{}', {} to import as collections", docs.len(), user, collections.len());
+    Ok(collections)
+}
+
+fn collections_to_import(favorites_collection: &str, docs: &[ArchiveDoc]) -> Vec<String> {
+    std::iter::once(favorites_collection.to_string())
+        .chain(docs.iter().filter(|doc| doc.mediatype.as_deref() == Some("collection")).map(|doc| doc.identifier.clone()))
+        .collect()
+}
+
+/// Appends the collections not already in `favorites`, returning how many were added.
+pub fn merge(favorites: &mut Vec<String>, found: &[String]) -> usize {
+    let before = favorites.len();
+    for collection in found {
+        if !favorites.contains(collection) {
+            favorites.push(collection.clone());
+        }
+    }
+    favorites.len() - before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(identifier: &str, mediatype: &str) -> ArchiveDoc {
+        ArchiveDoc { identifier: identifier.to_string(), mediatype: Some(mediatype.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_favorited_collections_are_imported_once() {
+        assert_eq!(favorites_collection(" @JaneDoe "), "fav-janedoe");
+        let docs = [doc("gd77", "audio"), doc("prelinger", "collection"), doc("book", "texts")];
+        let found = collections_to_import("fav-janedoe", &docs);
+        assert_eq!(found, ["fav-janedoe", "prelinger"]);
+
+        let mut favorites = vec!["prelinger".to_string()];
+        assert_eq!(merge(&mut favorites, &found), 1);
+        assert_eq!(favorites, ["prelinger", "fav-janedoe"]);
+        assert_eq!(merge(&mut favorites, &found), 0, "Importing again adds nothing");
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_fetch_favorites_from_mock() {
+        use crate::mock_server::{self, MOCK_COLLECTION, MOCK_FAVORITES_USER};
+        use crate::rate_limit::SharedRateLimiter;
+        use governor::Quota;
+        use std::{num::NonZeroU32, sync::Arc};
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let client = Client::new();
+        let limiter = Arc::new(SharedRateLimiter::new(Quota::per_minute(NonZeroU32::new(600).unwrap())));
+
+        let found = fetch_favorite_collections(&client, MOCK_FAVORITES_USER, Arc::clone(&limiter)).await.unwrap();
+        assert_eq!(found, [favorites_collection(MOCK_FAVORITES_USER), MOCK_COLLECTION.to_string()]);
+        assert!(fetch_favorite_collections(&client, "nobody", limiter).await.is_err(), "No favorites, nothing to import");
+    }
+}
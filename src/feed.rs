@@ -0,0 +1,358 @@
+use crate::archive_api::{FileDetails, ItemDetails};
+use crate::fs_util;
+use crate::library::LibraryIndex;
+use crate::offline;
+use crate::paths::{self, PathPolicy};
+use crate::playlist;
+use crate::sidecar;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// OPDS catalog of the downloaded texts, written into the download directory.
+pub const OPDS_FILE: &str = "catalog.opds.xml";
+/// Podcast feed of the downloaded audio, written into the download directory.
+pub const PODCAST_FILE: &str = "podcast.rss";
+/// E-book formats listed in the OPDS catalog, by extension.
+const BOOK_TYPES: [(&str, &str); 7] = [
+    ("epub", "application/epub+zip"),
+    ("pdf", "application/pdf"),
+    ("mobi", "application/x-mobipocket-ebook"),
+    ("azw3", "application/vnd.amazon.ebook"),
+    ("djvu", "image/vnd.djvu"),
+    ("cbz", "application/vnd.comicbook+zip"),
+    ("txt", "text/plain"),
+];
+const AUDIO_TYPES: [(&str, &str); 6] =
+    [("mp3", "audio/mpeg"), ("flac", "audio/flac"), ("ogg", "audio/ogg"), ("opus", "audio/ogg"), ("m4a", "audio/mp4"), ("wav", "audio/wav")];
+const IMAGE_TYPES: [(&str, &str); 3] = [("jpg", "image/jpeg"), ("jpeg", "image/jpeg"), ("png", "image/png")];
+
+/// A downloaded item with the files of it that are on disk.
+#[derive(Debug)]
+pub struct LocalItem {
+    pub details: ItemDetails,
+    /// The item's folder, `/`-separated and relative to the download directory.
+    pub dir: String,
+    /// Files on disk, with their `/`-separated paths relative to the download directory.
+    pub files: Vec<(FileDetails, String)>,
+    /// When the last of its files was written.
+    pub downloaded: SystemTime,
+}
+
+/// What [`write`] wrote.
+#[derive(Debug, Default)]
+pub struct FeedSummary {
+    /// The OPDS catalog and its number of books, if any texts were downloaded.
+    pub opds: Option<(PathBuf, usize)>,
+    /// The podcast feed and its number of episodes, if any audio was downloaded.
+    pub podcast: Option<(PathBuf, usize)>,
+    /// Downloaded items left out because their metadata isn't cached.
+    pub missing_metadata: usize,
+}
+
+/// Writes [`OPDS_FILE`] (texts) and [`PODCAST_FILE`] (audio) into `base_dir`, listing the
+/// items `library` records as downloaded. Their metadata comes from the item details
+/// cache (see [`crate::offline`]), which downloads fill. Links are relative to `base_dir`
+/// unless `base_url` says where it is served. A feed with nothing to list isn't written.
+pub fn write(base_dir: &Path, library: &LibraryIndex, policy: &PathPolicy, base_url: Option<&str>) -> Result<FeedSummary> {
+    let (items, missing_metadata) = collect(base_dir, library, policy);
+    let base_url = base_url.map(str::trim).filter(|url| !url.is_empty());
+    let now = Local::now();
+    let mut summary = FeedSummary { missing_metadata, ..Default::default() };
+    let texts: Vec<&LocalItem> = items.iter().filter(|item| item.details.mediatype.as_deref() == Some("texts")).collect();
+    if let Some((xml, books)) = render_opds(&texts, base_url, now) {
+        let path = base_dir.join(OPDS_FILE);
+        fs_util::atomic_write(&path, xml).context(format!("Failed to write OPDS catalog {}", path.display()))?;
+        summary.opds = Some((path, books));
+    }
+    let audio: Vec<&LocalItem> = items.iter().filter(|item| matches!(item.details.mediatype.as_deref(), Some("audio" | "etree"))).collect();
+    if let Some((xml, episodes)) = render_podcast(&audio, base_url, now) {
+        let path = base_dir.join(PODCAST_FILE);
+        fs_util::atomic_write(&path, xml).context(format!("Failed to write podcast feed {}", path.display()))?;
+        summary.podcast = Some((path, episodes));
+    }
+    Ok(summary)
+}
+
+/// Finds the downloaded items of `library` and their files on disk. Also returns how
+/// many were left out for lack of cached metadata.
+pub fn collect(base_dir: &Path, library: &LibraryIndex, policy: &PathPolicy) -> (Vec<LocalItem>, usize) {
+    let base = base_dir.to_string_lossy();
+    let mut items = Vec::new();
+    let mut missing = 0;
+    for identifier in library.downloaded() {
+        let Ok((details, _)) = offline::load_details(&base, identifier) else {
+            missing += 1;
+            continue;
+        };
+        let Some(dir) = library.dir(identifier).map(Path::to_path_buf).or_else(|| find_item_dir(base_dir, identifier, policy)) else { continue };
+        let Some(relative_dir) = relative_path(base_dir, &dir) else { continue };
+        let mut files = Vec::new();
+        let mut downloaded = SystemTime::UNIX_EPOCH;
+        for file in &details.files {
+            let path = paths::item_file_path(dir.clone(), &file.name, policy);
+            let Ok(meta) = fs::metadata(&path) else { continue };
+            let Some(relative) = relative_path(base_dir, &path) else { continue };
+            if meta.is_file() {
+                downloaded = downloaded.max(meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+                files.push((FileDetails { size: Some(meta.len().to_string()), ..file.clone() }, relative));
+            }
+        }
+        if !files.is_empty() {
+            items.push(LocalItem { details, dir: relative_dir, files, downloaded });
+        }
+    }
+    (items, missing)
+}
+
+/// `base/item` or `base/<collection>/item`, whichever exists.
+fn find_item_dir(base_dir: &Path, identifier: &str, policy: &PathPolicy) -> Option<PathBuf> {
+    let name = paths::sanitize_component(identifier, policy);
+    let top = base_dir.join(&name);
+    if top.is_dir() {
+        return Some(top);
+    }
+    fs::read_dir(base_dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path().join(&name))
+        .find(|dir| dir.is_dir())
+}
+
+fn relative_path(base_dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(base_dir).ok()?;
+    Some(relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"))
+}
+
+/// Renders the OPDS 1.2 acquisition feed of `items`, with one entry per item that has an
+/// e-book file on disk. Returns the feed and its number of entries, `None` if empty.
+pub fn render_opds(items: &[&LocalItem], base_url: Option<&str>, now: DateTime<Local>) -> Option<(String, usize)> {
+    let mut entries = String::new();
+    let mut count = 0;
+    for item in items {
+        let books: Vec<(&str, &str)> = item.files.iter().filter_map(|(_, path)| Some((path.as_str(), media_type(path, &BOOK_TYPES)?))).collect();
+        if books.is_empty() {
+            continue;
+        }
+        count += 1;
+        let details = &item.details;
+        let _ = writeln!(entries, "  <entry>");
+        let _ = writeln!(entries, "    <title>{}</title>", escape(details.title.as_deref().unwrap_or(&details.identifier)));
+        let _ = writeln!(entries, "    <id>urn:archive-org:{}</id>", escape(&details.identifier));
+        let _ = writeln!(entries, "    <updated>{}</updated>", DateTime::<Local>::from(item.downloaded).to_rfc3339());
+        if let Some(creator) = &details.creator {
+            let _ = writeln!(entries, "    <author><name>{}</name></author>", escape(creator));
+        }
+        if let Some(date) = &details.date {
+            let _ = writeln!(entries, "    <dc:issued>{}</dc:issued>", escape(date));
+        }
+        if let Some(summary) = description(details) {
+            let _ = writeln!(entries, "    <summary>{}</summary>", escape(&summary));
+        }
+        if let Some((path, image_type)) = cover(item) {
+            let _ = writeln!(entries, "    <link rel=\"http://opds-spec.org/image\" href=\"{}\" type=\"{}\"/>", escape(&link(base_url, path)), image_type);
+        }
+        for (path, book_type) in books {
+            let _ = writeln!(entries, "    <link rel=\"http://opds-spec.org/acquisition\" href=\"{}\" type=\"{}\"/>", escape(&link(base_url, path)), book_type);
+        }
+        let _ = writeln!(entries, "    <link rel=\"alternate\" href=\"{}\" type=\"text/html\"/>", escape(&sidecar::source_url(&details.identifier)));
+        let _ = writeln!(entries, "  </entry>");
+    }
+    if count == 0 {
+        return None;
+    }
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n");
+    out.push_str("  <id>urn:archiver:library:texts</id>\n");
+    out.push_str("  <title>archiver library: texts</title>\n");
+    let _ = writeln!(out, "  <updated>{}</updated>", now.to_rfc3339());
+    let _ = writeln!(out, "  <link rel=\"self\" href=\"{}\" type=\"application/atom+xml;profile=opds-catalog;kind=acquisition\"/>", escape(&link(base_url, OPDS_FILE)));
+    out.push_str(&entries);
+    out.push_str("</feed>\n");
+    Some((out, count))
+}
+
+/// Renders the RSS 2.0 podcast feed of `items`, with one episode per track of the best
+/// audio format on disk (see [`playlist::tracks`]). Items are listed newest download
+/// first; an item's tracks are dated a second apart in track order, so podcast apps keep
+/// them in sequence. Returns the feed and its number of episodes, `None` if empty.
+pub fn render_podcast(items: &[&LocalItem], base_url: Option<&str>, now: DateTime<Local>) -> Option<(String, usize)> {
+    let mut items: Vec<&&LocalItem> = items.iter().collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.downloaded));
+    let mut episodes = String::new();
+    let mut count = 0;
+    for item in items {
+        let details = &item.details;
+        let title = details.title.as_deref().unwrap_or(&details.identifier);
+        let tracks: Vec<&(FileDetails, String)> = playlist::tracks(&item.files).into_iter().filter(|(_, path)| media_type(path, &AUDIO_TYPES).is_some()).collect();
+        let image = cover(item);
+        for (number, (file, path)) in tracks.iter().enumerate() {
+            count += 1;
+            let episode_title = match (&file.title, tracks.len()) {
+                (_, 1) => title.to_string(),
+                (Some(track), _) => format!("{}: {}", title, track),
+                (None, _) => format!("{}: {}", title, file.name.rsplit('/').next().unwrap_or(&file.name)),
+            };
+            let published = item.downloaded.checked_sub(Duration::from_secs((tracks.len() - 1 - number) as u64)).unwrap_or(item.downloaded);
+            let _ = writeln!(episodes, "    <item>");
+            let _ = writeln!(episodes, "      <title>{}</title>", escape(&episode_title));
+            let _ = writeln!(episodes, "      <guid isPermaLink=\"false\">{}/{}</guid>", escape(&details.identifier), escape(&file.name));
+            let _ = writeln!(episodes, "      <link>{}</link>", escape(&sidecar::source_url(&details.identifier)));
+            let _ = writeln!(episodes, "      <pubDate>{}</pubDate>", DateTime::<Local>::from(published).to_rfc2822());
+            let _ = writeln!(
+                episodes,
+                "      <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>",
+                escape(&link(base_url, path)),
+                file.size.as_deref().unwrap_or("0"),
+                media_type(path, &AUDIO_TYPES).unwrap_or("audio/mpeg")
+            );
+            if let Some(author) = file.artist.as_ref().or(details.creator.as_ref()) {
+                let _ = writeln!(episodes, "      <itunes:author>{}</itunes:author>", escape(author));
+            }
+            if let Some(seconds) = file.length.as_deref().and_then(playlist::length_seconds) {
+                let _ = writeln!(episodes, "      <itunes:duration>{}</itunes:duration>", seconds.round() as u64);
+            }
+            if tracks.len() > 1 {
+                let _ = writeln!(episodes, "      <itunes:episode>{}</itunes:episode>", number + 1);
+            }
+            if let Some((image, _)) = image {
+                let _ = writeln!(episodes, "      <itunes:image href=\"{}\"/>", escape(&link(base_url, image)));
+            }
+            if let Some(summary) = description(details) {
+                let _ = writeln!(episodes, "      <description>{}</description>", escape(&summary));
+            }
+            let _ = writeln!(episodes, "    </item>");
+        }
+    }
+    if count == 0 {
+        return None;
+    }
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\n  <channel>\n");
+    out.push_str("    <title>archiver library: audio</title>\n");
+    let _ = writeln!(out, "    <link>{}</link>", escape(base_url.unwrap_or(".")));
+    out.push_str("    <description>Audio downloaded from archive.org</description>\n");
+    let _ = writeln!(out, "    <lastBuildDate>{}</lastBuildDate>", now.to_rfc2822());
+    out.push_str(&episodes);
+    out.push_str("  </channel>\n</rss>\n");
+    Some((out, count))
+}
+
+/// The item's description as plain text, if it has one.
+pub fn description(details: &ItemDetails) -> Option<String> {
+    details.description.as_deref().map(sidecar::plain_text).filter(|text| !text.is_empty())
+}
+
+/// The first image on disk, for the cover of books and episodes.
+fn cover(item: &LocalItem) -> Option<(&str, &'static str)> {
+    item.files.iter().find_map(|(_, path)| Some((path.as_str(), media_type(path, &IMAGE_TYPES)?)))
+}
+
+/// Media type of `path` by its extension, if it is one of `types`.
+fn media_type(path: &str, types: &[(&str, &'static str)]) -> Option<&'static str> {
+    let (_, extension) = path.rsplit_once('.')?;
+    types.iter().find(|(ext, _)| extension.eq_ignore_ascii_case(ext)).map(|(_, media_type)| *media_type)
+}
+
+/// Link to the file at `path` (relative to the download directory), below `base_url` if
+/// given.
+pub fn link(base_url: Option<&str>, path: &str) -> String {
+    let encoded = path.split('/').map(percent_encode).collect::<Vec<_>>().join("/");
+    match base_url {
+        Some(base) => format!("{}/{}", base.trim_end_matches('/'), encoded),
+        None => encoded,
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Escapes text for XML (and HTML), dropping the control characters XML 1.0 doesn't allow.
+pub fn escape(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::ItemStatus;
+
+    fn file(name: &str, format: &str, track: Option<&str>) -> FileDetails {
+        FileDetails { name: name.to_string(), format: Some(format.to_string()), track: track.map(str::to_string), title: track.map(|t| format!("Track {}", t)), ..Default::default() }
+    }
+
+    #[test]
+    fn test_feeds_list_downloaded_books_and_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        let policy = PathPolicy::default();
+        let book = ItemDetails {
+            identifier: "moby_dick".to_string(),
+            title: Some("Moby Dick & Other Tales".to_string()),
+            creator: Some("Melville".to_string()),
+            mediatype: Some("texts".to_string()),
+            files: vec![file("moby dick.epub", "EPUB", None), file("moby dick.pdf", "Text PDF", None), file("moby_meta.xml", "Metadata", None)],
+            ..Default::default()
+        };
+        let show = ItemDetails {
+            identifier: "gd1977".to_string(),
+            title: Some("Live 1977".to_string()),
+            mediatype: Some("etree".to_string()),
+            files: vec![file("d1t02.flac", "Flac", Some("2")), file("d1t01.flac", "Flac", Some("1")), file("d1t01.mp3", "VBR MP3", Some("1"))],
+            ..Default::default()
+        };
+        for (details, dir) in [(&book, base.join("books").join("moby_dick")), (&show, base.join("gd1977"))] {
+            fs::create_dir_all(&dir).unwrap();
+            for file in details.files.iter().filter(|f| !f.name.ends_with(".pdf")) {
+                fs::write(dir.join(&file.name), b"data").unwrap();
+            }
+            offline::save_details(base.to_str().unwrap(), details).unwrap();
+        }
+        let mut library = LibraryIndex::load(base);
+        library.record("moby_dick", ItemStatus::Downloaded);
+        library.record("gd1977", ItemStatus::Downloaded);
+        library.record("unviewed", ItemStatus::Downloaded);
+        library.record("broken", ItemStatus::Failed);
+
+        let summary = write(base, &library, &policy, Some("http://nas.local/archive/")).unwrap();
+        assert_eq!(summary.missing_metadata, 1, "Only the downloaded item without cached details");
+        let (opds_path, books) = summary.opds.unwrap();
+        assert_eq!(books, 1);
+        let opds = fs::read_to_string(opds_path).unwrap();
+        assert!(opds.contains("<title>Moby Dick &amp; Other Tales</title>"), "{}", opds);
+        assert!(opds.contains("href=\"http://nas.local/archive/books/moby_dick/moby%20dick.epub\" type=\"application/epub+zip\""), "{}", opds);
+        assert!(!opds.contains(".pdf"), "Files not on disk aren't listed");
+
+        let (podcast_path, episodes) = summary.podcast.unwrap();
+        assert_eq!(episodes, 2, "One episode per track of the best format");
+        let rss = fs::read_to_string(podcast_path).unwrap();
+        let first = rss.find("<title>Live 1977: Track 1</title>").expect("first track");
+        assert!(first < rss.find("<title>Live 1977: Track 2</title>").unwrap(), "In track order");
+        assert!(rss.contains("url=\"http://nas.local/archive/gd1977/d1t01.flac\" length=\"4\" type=\"audio/flac\""), "{}", rss);
+        assert!(!rss.contains(".mp3"));
+
+        let (items, _) = collect(base, &library, &policy);
+        let texts: Vec<&LocalItem> = items.iter().filter(|item| item.details.identifier == "moby_dick").collect();
+        let (relative, _) = render_opds(&texts, None, Local::now()).unwrap();
+        assert!(relative.contains("href=\"books/moby_dick/moby%20dick.epub\""), "Relative links without a base URL");
+        assert!(render_podcast(&texts, None, Local::now()).is_none(), "No audio, no feed");
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Months, NaiveDate};
+use std::collections::BTreeMap;
+
+/// Turns a collection's fetch filter, as typed in the TUI and saved in
+/// `Settings::fetch_filters`, into the search clauses its listing is narrowed by.
+///
+/// A filter is `[FROM..TO] [CLAUSES]`: an optional range of `publicdate` (when items were
+/// added to archive.org), each end `YYYY`, `YYYY-MM` or `YYYY-MM-DD` and either end left
+/// open, followed by archive.org search clauses every listed item must match, e.g.
+/// `2020.. mediatype:audio` or `subject:jazz AND date:[1950-01-01 TO 1959-12-31]`.
+/// Returns `None` for an empty filter.
+pub fn to_query(filter: &str) -> Result<Option<String>> {
+    let filter = filter.trim();
+    let (first, rest) = filter.split_once(char::is_whitespace).unwrap_or((filter, ""));
+    let (range, clauses) = match first.split_once("..") {
+        Some((from, to)) => (Some(date_range(from, to)?), rest.trim()),
+        None => (None, filter),
+    };
+    if !clauses.is_empty() {
+        check_balanced(clauses)?;
+    }
+    let clauses = (!clauses.is_empty()).then(|| format!("({})", clauses));
+    Ok(match (range, clauses) {
+        (Some(range), Some(clauses)) => Some(format!("{} AND {}", range, clauses)),
+        (range, clauses) => range.or(clauses),
+    })
+}
+
+/// The query clauses of `collection`'s filter in `filters`, if it has one.
+pub fn for_collection(filters: &BTreeMap<String, String>, collection: &str) -> Result<Option<String>> {
+    match filters.get(collection) {
+        Some(filter) => to_query(filter).with_context(|| format!("Invalid fetch filter for '{}'", collection)),
+        None => Ok(None),
+    }
+}
+
+/// `publicdate:[2020-01-01 TO *]` for `2020..`.
+fn date_range(from: &str, to: &str) -> Result<String> {
+    let from = (!from.is_empty()).then(|| parse_date(from, false)).transpose()?;
+    let to = (!to.is_empty()).then(|| parse_date(to, true)).transpose()?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            bail!("The date range ends ({}) before it starts ({})", to, from);
+        }
+    }
+    if from.is_none() && to.is_none() {
+        bail!("Give a start or an end date for the range, e.g. 2020.. or ..2019-06");
+    }
+    let bound = |date: Option<NaiveDate>| date.map_or("*".to_string(), |date| date.format("%Y-%m-%d").to_string());
+    Ok(format!("publicdate:[{} TO {}]", bound(from), bound(to)))
+}
+
+/// Parses `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, taking the first day of a year or month as
+/// a range start and the last as a range end.
+fn parse_date(date: &str, end: bool) -> Result<NaiveDate> {
+    let invalid = || format!("'{}' is not a date (use YYYY, YYYY-MM or YYYY-MM-DD)", date);
+    let parts: Vec<&str> = date.split('-').collect();
+    let number = |part: &str| -> Result<u32> {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            bail!(invalid());
+        }
+        part.parse().with_context(invalid)
+    };
+    let parsed = match parts.as_slice() {
+        [year] if year.len() == 4 => {
+            let year = number(year)? as i32;
+            if end { NaiveDate::from_ymd_opt(year, 12, 31) } else { NaiveDate::from_ymd_opt(year, 1, 1) }
+        }
+        [year, month] if year.len() == 4 => {
+            let first = NaiveDate::from_ymd_opt(number(year)? as i32, number(month)?, 1);
+            match (end, first) {
+                (true, Some(first)) => first.checked_add_months(Months::new(1)).and_then(|next| next.pred_opt()),
+                _ => first,
+            }
+        }
+        [year, month, day] if year.len() == 4 => NaiveDate::from_ymd_opt(number(year)? as i32, number(month)?, number(day)?),
+        _ => None,
+    };
+    parsed.with_context(invalid)
+}
+
+/// Catches unclosed quotes, parentheses and ranges, which archive.org would reject.
+fn check_balanced(clauses: &str) -> Result<()> {
+    if !clauses.matches('"').count().is_multiple_of(2) {
+        bail!("Unclosed quote in '{}'", clauses);
+    }
+    let mut parens = 0i32;
+    let mut ranges = 0i32;
+    for c in clauses.chars() {
+        match c {
+            '(' => parens += 1,
+            ')' => parens -= 1,
+            '[' | '{' => ranges += 1,
+            ']' | '}' => ranges -= 1,
+            _ => {}
+        }
+        if parens < 0 || ranges < 0 {
+            break;
+        }
+    }
+    if parens != 0 {
+        bail!("Unbalanced parentheses in '{}'", clauses);
+    }
+    if ranges != 0 {
+        bail!("Unbalanced range brackets in '{}'", clauses);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_become_query_clauses() {
+        assert_eq!(to_query("  ").unwrap(), None);
+        assert_eq!(to_query("mediatype:audio").unwrap().as_deref(), Some("(mediatype:audio)"));
+        assert_eq!(to_query("2020..").unwrap().as_deref(), Some("publicdate:[2020-01-01 TO *]"));
+        assert_eq!(
+            to_query("2019-06..2020-02 mediatype:audio AND subject:\"live music\"").unwrap().as_deref(),
+            Some("publicdate:[2019-06-01 TO 2020-02-29] AND (mediatype:audio AND subject:\"live music\")")
+        );
+        assert_eq!(to_query("..2019-06-15").unwrap().as_deref(), Some("publicdate:[* TO 2019-06-15]"));
+        assert_eq!(
+            to_query("publicdate:[2020-01-01 TO *]").unwrap().as_deref(),
+            Some("(publicdate:[2020-01-01 TO *])"),
+            "Clauses are passed through as typed"
+        );
+
+        assert!(to_query("..").is_err());
+        assert!(to_query("2021..2020").is_err(), "Ends before it starts");
+        assert!(to_query("2020-13..").is_err());
+        assert!(to_query("20..").is_err());
+        assert!(to_query("mediatype:(audio OR movies").is_err());
+        assert!(to_query("publicdate:[2020-01-01 TO *").is_err());
+        assert!(to_query("title:\"moby").is_err());
+
+        let filters = BTreeMap::from([("etree".to_string(), "1990..1999".to_string()), ("bad".to_string(), "x..".to_string())]);
+        assert_eq!(for_collection(&filters, "etree").unwrap().as_deref(), Some("publicdate:[1990-01-01 TO 1999-12-31]"));
+        assert_eq!(for_collection(&filters, "other").unwrap(), None);
+        assert!(format!("{:#}", for_collection(&filters, "bad").unwrap_err()).contains("'bad'"));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::fs_util;
+use anyhow::{Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+/// File (inside the download directory) recording every file downloaded into it.
+pub const HISTORY_FILE: &str = ".archiver-history.json";
+/// Minimum interval between history writes while a download is running.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Histories in use, by download directory, so concurrent jobs share one.
+static OPEN: Mutex<Vec<(PathBuf, Weak<FileHistory>)>> = Mutex::new(Vec::new());
+
+/// A file that was downloaded successfully.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileRecord {
+    pub identifier: String,
+    pub name: String,
+    /// MD5 archive.org lists for the file, if any.
+    pub md5: Option<String>,
+    pub size: u64,
+    pub path: PathBuf,
+    /// RFC 3339 time of the download.
+    pub downloaded: String,
+    /// Hex SHA-256 of the file on disk, if it was changed after download (e.g. tagged);
+    /// `size` is then its local size rather than archive.org's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct HistoryFile {
+    files: Vec<FileRecord>,
+}
+
+struct State {
+    by_path: BTreeMap<PathBuf, FileRecord>,
+    dirty: bool,
+    last_save: Instant,
+}
+
+/// Every file downloaded into a download directory, persisted in [`HISTORY_FILE`], so a
+/// file already on disk with the recorded size and MD5 is skipped without hashing it again.
+pub struct FileHistory {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl FileHistory {
+    /// The history of `base_dir`, shared with the other jobs downloading into it.
+    pub fn open(base_dir: &Path) -> Arc<Self> {
+        let mut open = OPEN.lock().unwrap_or_else(|e| e.into_inner());
+        open.retain(|(_, history)| history.strong_count() > 0);
+        if let Some(history) = open.iter().find(|(dir, _)| dir == base_dir).and_then(|(_, history)| history.upgrade()) {
+            return history;
+        }
+        let history = Arc::new(Self::load(base_dir));
+        open.push((base_dir.to_path_buf(), Arc::downgrade(&history)));
+        history
+    }
+
+    /// Reads the history of `base_dir`; a missing or unreadable file gives an empty one.
+    pub fn load(base_dir: &Path) -> Self {
+        let path = base_dir.join(HISTORY_FILE);
+        let files = match fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str::<HistoryFile>(&json) {
+                Ok(history) => history.files,
+                Err(e) => {
+                    warn!("Ignoring unreadable download history {}: {}", path.display(), e);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        let by_path = files.into_iter().map(|record| (record.path.clone(), record)).collect();
+        Self { path, state: Mutex::new(State { by_path, dirty: false, last_save: Instant::now() }) }
+    }
+
+    /// Adds (or replaces) the record of the file at `record.path`. Saved at most every few
+    /// seconds; the rest is written by [`FileHistory::flush`] or when the history is dropped.
+    pub fn record(&self, record: FileRecord) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.by_path.insert(record.path.clone(), record);
+        state.dirty = true;
+        if state.last_save.elapsed() >= SAVE_INTERVAL {
+            self.save(&mut state);
+        }
+    }
+
+    /// Whether the file at `path` was downloaded with `size` bytes and, when both are
+    /// known, the same MD5.
+    pub fn is_recorded(&self, path: &Path, size: u64, md5: Option<&str>) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.by_path.get(path).is_some_and(|record| {
+            record.size == size
+                && match (record.md5.as_deref(), md5) {
+                    (Some(recorded), Some(md5)) => recorded.eq_ignore_ascii_case(md5),
+                    _ => true,
+                }
+        })
+    }
+
+    /// The local size of the file at `path` if it was changed after being downloaded
+    /// with `md5` (see [`FileRecord::local_sha256`]).
+    pub fn changed_size(&self, path: &Path, md5: Option<&str>) -> Option<u64> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let record = state.by_path.get(path).filter(|record| record.local_sha256.is_some())?;
+        match (record.md5.as_deref(), md5) {
+            (Some(recorded), Some(md5)) if !recorded.eq_ignore_ascii_case(md5) => None,
+            _ => Some(record.size),
+        }
+    }
+
+    /// Every record, by path.
+    pub fn records(&self) -> Vec<FileRecord> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).by_path.values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).by_path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes unsaved records.
+    pub fn flush(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.dirty {
+            self.save(&mut state);
+        }
+    }
+
+    fn save(&self, state: &mut State) {
+        state.last_save = Instant::now();
+        match self.write(state) {
+            Ok(()) => state.dirty = false,
+            Err(e) => error!("{:#}", e),
+        }
+    }
+
+    fn write(&self, state: &State) -> Result<()> {
+        let history = HistoryFile { files: state.by_path.values().cloned().collect() };
+        let json = serde_json::to_string(&history).context("Failed to serialize the download history")?;
+        fs_util::atomic_write(&self.path, json).context(format!("Failed to write download history {}", self.path.display()))
+    }
+}
+
+impl Drop for FileHistory {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(dir: &Path, name: &str, md5: Option<&str>) -> FileRecord {
+        FileRecord {
+            identifier: "gd1977".to_string(),
+            name: name.to_string(),
+            md5: md5.map(str::to_string),
+            size: 3,
+            path: dir.join("gd1977").join(name),
+            downloaded: chrono::Local::now().to_rfc3339(),
+            local_sha256: None,
+        }
+    }
+
+    #[test]
+    fn test_history_matches_size_and_md5_and_is_shared() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = FileHistory::open(dir.path());
+        assert!(Arc::ptr_eq(&history, &FileHistory::open(dir.path())), "Jobs in one directory share it");
+        history.record(record(dir.path(), "a.flac", Some("900150983CD24FB0D6963F7D28E17F72")));
+        history.record(record(dir.path(), "b.txt", None));
+
+        let a = dir.path().join("gd1977/a.flac");
+        assert!(history.is_recorded(&a, 3, Some("900150983cd24fb0d6963f7d28e17f72")));
+        assert!(!history.is_recorded(&a, 4, Some("900150983cd24fb0d6963f7d28e17f72")), "Size differs");
+        assert!(!history.is_recorded(&a, 3, Some("d41d8cd98f00b204e9800998ecf8427e")), "MD5 differs");
+        assert!(history.is_recorded(&dir.path().join("gd1977/b.txt"), 3, Some("d41d8cd98f00b204e9800998ecf8427e")), "No MD5 recorded");
+        assert!(!history.is_recorded(&dir.path().join("gd1977/c.txt"), 3, None));
+        assert_eq!(history.changed_size(&a, None), None, "Not changed since download");
+        history.record(FileRecord { size: 5, local_sha256: Some("ab".repeat(32)), ..record(dir.path(), "d.mp3", Some("900150983cd24fb0d6963f7d28e17f72")) });
+        let d = dir.path().join("gd1977/d.mp3");
+        assert_eq!(history.changed_size(&d, Some("900150983CD24FB0D6963F7D28E17F72")), Some(5));
+        assert_eq!(history.changed_size(&d, Some("d41d8cd98f00b204e9800998ecf8427e")), None, "archive.org has a new version");
+
+        drop(history);
+        let reloaded = FileHistory::open(dir.path());
+        assert_eq!(reloaded.len(), 3, "Saved when the last job let go of it");
+        assert!(reloaded.is_recorded(&a, 3, None));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::archive_api::ArchiveDoc;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Narrows the items pane by the search index's `mediatype` and `format` fields.
+/// `None` means any value; values compare case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemFilter {
+    pub mediatype: Option<String>,
+    pub format: Option<String>,
+}
+
+impl ItemFilter {
+    pub fn is_active(&self) -> bool {
+        self.mediatype.is_some() || self.format.is_some()
+    }
+
+    pub fn matches(&self, doc: &ArchiveDoc) -> bool {
+        let mediatype_ok = self
+            .mediatype
+            .as_deref()
+            .is_none_or(|wanted| doc.mediatype.as_deref().is_some_and(|m| m.eq_ignore_ascii_case(wanted)));
+        let format_ok = self.format.as_deref().is_none_or(|wanted| doc.format.iter().any(|f| f.eq_ignore_ascii_case(wanted)));
+        mediatype_ok && format_ok
+    }
+}
+
+impl fmt::Display for ItemFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.mediatype, &self.format) {
+            (None, None) => write!(f, "Any type"),
+            (Some(m), None) => write!(f, "{}", m),
+            (None, Some(fmt)) => write!(f, "{} files", fmt),
+            (Some(m), Some(fmt)) => write!(f, "{}, {} files", m, fmt),
+        }
+    }
+}
+
+/// Case-insensitive fuzzy match of `query` against `text` for the items pane's live
+/// filter ('f'). Each whitespace-separated term must occur in `text`, as a contiguous run
+/// if possible, otherwise as a subsequence. Returns the matched positions (char indices,
+/// sorted) for highlighting, or `None` if a term doesn't match.
+pub fn fuzzy_positions(query: &str, text: &str) -> Option<Vec<usize>> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let text: Vec<char> = text.chars().map(fold).collect();
+    let mut positions = Vec::new();
+    for term in query.split_whitespace() {
+        let term: Vec<char> = term.chars().map(fold).collect();
+        if let Some(start) = text.windows(term.len()).position(|window| window == term.as_slice()) {
+            positions.extend(start..start + term.len());
+            continue;
+        }
+        let mut from = 0;
+        for c in term {
+            let found = from + text[from..].iter().position(|&t| t == c)?;
+            positions.push(found);
+            from = found + 1;
+        }
+    }
+    positions.sort_unstable();
+    positions.dedup();
+    Some(positions)
+}
+
+/// Distinct mediatypes among `docs`, sorted.
+pub fn mediatypes(docs: &[ArchiveDoc]) -> Vec<String> {
+    let set: BTreeSet<&str> = docs.iter().filter_map(|doc| doc.mediatype.as_deref()).collect();
+    set.into_iter().map(str::to_string).collect()
+}
+
+/// Distinct file formats among `docs`, sorted.
+pub fn formats(docs: &[ArchiveDoc]) -> Vec<String> {
+    let set: BTreeSet<&str> = docs.iter().flat_map(|doc| doc.format.iter().map(String::as_str)).collect();
+    set.into_iter().map(str::to_string).collect()
+}
+
+/// Next value when cycling a filter: no filter, then each of `values` in turn, then no
+/// filter again. A current value missing from `values` restarts the cycle.
+pub fn next_value(values: &[String], current: Option<&str>) -> Option<String> {
+    let next = match current.and_then(|c| values.iter().position(|v| v == c)) {
+        Some(i) => i + 1,
+        None if current.is_some() => return None,
+        None => 0,
+    };
+    values.get(next).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, mediatype: &str, formats: &[&str]) -> ArchiveDoc {
+        ArchiveDoc {
+            identifier: id.to_string(),
+            mediatype: Some(mediatype.to_string()),
+            format: formats.iter().map(|f| f.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_positions_prefer_contiguous_runs() {
+        assert_eq!(fuzzy_positions("", "anything"), Some(Vec::new()));
+        assert_eq!(fuzzy_positions("DEAD", "gd77-05-08 Grateful Dead"), Some(vec![20, 21, 22, 23]));
+        assert_eq!(fuzzy_positions("gd77", "gd1977-05-08"), Some(vec![0, 1, 4, 5]), "Falls back to a subsequence");
+        assert_eq!(fuzzy_positions("dead 77", "gd77 dead"), Some(vec![2, 3, 5, 6, 7, 8]), "Terms match independently");
+        assert_eq!(fuzzy_positions("dead 78", "gd77 dead"), None);
+    }
+
+    #[test]
+    fn test_filter_by_mediatype_and_format() {
+        let docs = [doc("show", "audio", &["Flac", "VBR MP3"]), doc("film", "movies", &["MPEG4"]), doc("tape", "audio", &["VBR MP3"])];
+
+        assert_eq!(mediatypes(&docs), vec!["audio", "movies"]);
+        assert_eq!(formats(&docs), vec!["Flac", "MPEG4", "VBR MP3"]);
+
+        let shown = |filter: &ItemFilter| -> Vec<&str> { docs.iter().filter(|d| filter.matches(d)).map(|d| d.identifier.as_str()).collect() };
+        let mut filter = ItemFilter::default();
+        assert_eq!(shown(&filter), vec!["show", "film", "tape"]);
+        filter.mediatype = Some("Audio".to_string());
+        assert_eq!(shown(&filter), vec!["show", "tape"]);
+        filter.format = Some("flac".to_string());
+        assert_eq!(shown(&filter), vec!["show"]);
+        assert_eq!(filter.to_string(), "Audio, flac files");
+
+        let values = mediatypes(&docs);
+        assert_eq!(next_value(&values, None).as_deref(), Some("audio"));
+        assert_eq!(next_value(&values, Some("audio")).as_deref(), Some("movies"));
+        assert_eq!(next_value(&values, Some("movies")), None);
+        assert_eq!(next_value(&values, Some("texts")), None);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::archive_api::ArchiveDoc;
+use std::{path::Path, time::Duration};
+
+const BYTES_PER_GB: u64 = 1_000_000_000;
+
+/// What downloading a whole collection is expected to cost, shown before it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionForecast {
+    pub collection: String,
+    /// Items in the listing, or `None` if the collection hasn't been listed yet.
+    pub item_count: Option<usize>,
+    /// Sum of the listed `item_size` values.
+    pub total_bytes: u64,
+    /// Listed items without a size (the real total is higher by that much).
+    pub unknown_sizes: usize,
+    /// Free space on the download directory's filesystem, if it could be determined.
+    pub free_bytes: Option<u64>,
+    /// Transfer time at the recently observed download speed.
+    pub estimated_duration: Option<Duration>,
+}
+
+impl CollectionForecast {
+    /// Aggregates the listing of `collection` (`None` when it isn't loaded or cached).
+    pub fn new(collection: &str, items: Option<&[ArchiveDoc]>, free_bytes: Option<u64>, bytes_per_sec: Option<f64>) -> Self {
+        let items = items.unwrap_or_default();
+        let total_bytes = items.iter().filter_map(|doc| doc.item_size).sum();
+        let estimated_duration = bytes_per_sec
+            .filter(|speed| *speed > 0.0 && total_bytes > 0)
+            .map(|speed| Duration::from_secs_f64(total_bytes as f64 / speed));
+        Self {
+            collection: collection.to_string(),
+            item_count: (!items.is_empty()).then_some(items.len()),
+            total_bytes,
+            unknown_sizes: items.iter().filter(|doc| doc.item_size.is_none()).count(),
+            free_bytes,
+            estimated_duration,
+        }
+    }
+
+    /// True if the known total already exceeds the free space.
+    pub fn exceeds_free_space(&self) -> bool {
+        self.free_bytes.is_some_and(|free| self.total_bytes > free)
+    }
+
+    /// Whether the user has to confirm before the download starts: the total is above
+    /// `threshold_gb`, won't fit on disk, or can't be estimated at all.
+    pub fn needs_confirmation(&self, threshold_gb: u64) -> bool {
+        self.item_count.is_none() || self.exceeds_free_space() || self.total_bytes > threshold_gb.saturating_mul(BYTES_PER_GB)
+    }
+}
+
+/// Free space available to this user on the filesystem holding `dir`.
+///
+/// The download directory may not exist yet, so the nearest existing ancestor is queried.
+pub fn available_space(dir: &Path) -> Option<u64> {
+    let existing = dir.ancestors().find(|p| p.exists())?;
+    fs4::available_space(existing).ok()
+}
+
+/// Formats a byte count in decimal units (`12.3 GB`), matching what archive.org shows.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Formats a duration as `2d 4h`, `3h 12m` or `45m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs().div_ceil(60);
+    let (days, hours, mins) = (minutes / 1440, (minutes % 1440) / 60, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, mins)
+    } else {
+        format!("{}m", mins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, size: Option<u64>) -> ArchiveDoc {
+        ArchiveDoc { identifier: id.to_string(), item_size: size, ..Default::default() }
+    }
+
+    #[test]
+    fn test_forecast_aggregates_listing() {
+        let items = vec![doc("a", Some(3 * BYTES_PER_GB)), doc("b", None), doc("c", Some(BYTES_PER_GB))];
+        let forecast = CollectionForecast::new("col", Some(&items), Some(10 * BYTES_PER_GB), Some(1_000_000.0));
+
+        assert_eq!(forecast.item_count, Some(3));
+        assert_eq!(forecast.total_bytes, 4 * BYTES_PER_GB);
+        assert_eq!(forecast.unknown_sizes, 1);
+        assert_eq!(forecast.estimated_duration, Some(Duration::from_secs(4000)));
+        assert!(!forecast.needs_confirmation(5));
+        assert!(forecast.needs_confirmation(3));
+    }
+
+    #[test]
+    fn test_forecast_requires_confirmation_when_it_cannot_fit_or_is_unknown() {
+        let items = vec![doc("a", Some(2 * BYTES_PER_GB))];
+        let forecast = CollectionForecast::new("col", Some(&items), Some(BYTES_PER_GB), None);
+        assert!(forecast.exceeds_free_space());
+        assert!(forecast.needs_confirmation(100));
+        assert_eq!(forecast.estimated_duration, None);
+
+        let unlisted = CollectionForecast::new("col", None, None, None);
+        assert!(unlisted.needs_confirmation(100));
+    }
+
+    #[test]
+    fn test_format_helpers() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(12_345_678_901), "12.3 GB");
+        assert_eq!(format_duration(Duration::from_secs(45 * 60)), "45m");
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 12 * 60)), "3h 12m");
+        assert_eq!(format_duration(Duration::from_secs(52 * 3600)), "2d 4h");
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Writes `contents` to `path` atomically.
+///
+/// The data goes to a temporary file in the same directory, is flushed to disk and then
+/// renamed over the target, so readers (or a crash) only ever see the old or the new file.
+pub fn atomic_write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot write to '{}': path has no file name", path.display()))?;
+    let tmp_path = parent.join(format!(
+        ".{}.tmp-{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let write_result = (|| -> Result<()> {
+        let mut tmp_file = File::create(&tmp_path)
+            .context(format!("Failed to create temporary file '{}'", tmp_path.display()))?;
+        tmp_file.write_all(contents.as_ref())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, path)
+            .context(format!("Failed to move '{}' into place at '{}'", tmp_path.display(), path.display()))?;
+        Ok(())
+    })();
+    if write_result.is_err() {
+        let _ = fs::remove_file(&tmp_path); // Don't leave partial temp files behind
+        return write_result;
+    }
+
+    // Persist the rename itself; not supported on every platform/filesystem, so best effort.
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Writes a file holding secrets (login cookies, API keys), readable and writable by
+/// the current user only on Unix.
+pub fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // An existing file keeps its mode on open; tighten it before writing the secret
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to restrict permissions of '{}'", path.display()))?;
+        }
+    }
+    let mut file = options.open(path).with_context(|| format!("Failed to open '{}'", path.display()))?;
+    file.write_all(contents.as_ref()).with_context(|| format!("Failed to write '{}'", path.display()))
+}
+
+/// Moves a file that failed to parse out of the way (to `<name>.corrupt-<timestamp>`)
+/// so it can be regenerated while keeping the original for inspection.
+pub fn backup_corrupt_file(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot back up '{}': path has no file name", path.display()))?;
+    let backup_path = path.with_file_name(format!(
+        "{}.corrupt-{}",
+        file_name.to_string_lossy(),
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::rename(path, &backup_path)
+        .context(format!("Failed to back up corrupt file '{}'", path.display()))?;
+    log::warn!("Backed up corrupt file '{}' to '{}'", path.display(), backup_path.display());
+    Ok(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_atomic_write_replaces_contents_without_leftovers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.json");
+        atomic_write(&path, "first").unwrap();
+        atomic_write(&path, "second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1, "Temporary file should have been renamed away");
+    }
+
+    #[test]
+    fn test_atomic_write_fails_for_missing_directory() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("data.json");
+        assert!(atomic_write(&path, "x").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private_restricts_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keys.toml");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, "secret").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "secret");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_backup_corrupt_file_moves_original() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(&path, "garbage = [").unwrap();
+
+        let backup = backup_corrupt_file(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "garbage = [");
+        assert!(backup.file_name().unwrap().to_string_lossy().starts_with("settings.toml.corrupt-"));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::archive_api::ArchiveDoc;
+use std::collections::HashMap;
+
+/// Words describing a rip or encoding rather than the work itself; dropped before
+/// comparing titles so `Album (FLAC)` and `Album [320kbps MP3]` end up together.
+const NOISE_WORDS: [&str; 16] = [
+    "flac", "mp3", "ogg", "320", "320kbps", "256kbps", "128kbps", "16bit", "24bit", "lossless", "remaster", "remastered", "web",
+    "cd", "vinyl", "rip",
+];
+
+/// Normalizes a title for duplicate detection: lowercase, bracketed annotations
+/// removed, punctuation collapsed and encoding words dropped.
+pub fn normalize_title(title: &str) -> String {
+    let mut depth = 0usize;
+    let mut cleaned = String::with_capacity(title.len());
+    for c in title.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            c if c.is_alphanumeric() => cleaned.extend(c.to_lowercase()),
+            _ => cleaned.push(' '),
+        }
+    }
+    cleaned.split_whitespace().filter(|word| !NOISE_WORDS.contains(word)).collect::<Vec<_>>().join(" ")
+}
+
+/// Key items are grouped by; items without a usable title only group with themselves.
+fn group_key(doc: &ArchiveDoc) -> String {
+    let normalized = doc.title.as_deref().map(normalize_title).unwrap_or_default();
+    if normalized.is_empty() {
+        format!("\0{}", doc.identifier)
+    } else {
+        normalized
+    }
+}
+
+/// Clusters `docs` by normalized title. Returns groups of indices into `docs`, ordered
+/// by first appearance, with members in their original order.
+pub fn group_indices(docs: &[&ArchiveDoc]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    for (index, doc) in docs.iter().enumerate() {
+        let group = *by_key.entry(group_key(doc)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(index);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, title: Option<&str>) -> ArchiveDoc {
+        ArchiveDoc { identifier: id.to_string(), title: title.map(str::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn test_near_identical_titles_grouped() {
+        assert_eq!(normalize_title("Live at the Fillmore (1970) [FLAC 24bit]"), "live at the fillmore");
+        assert_eq!(normalize_title("LIVE AT THE FILLMORE - 320kbps MP3"), "live at the fillmore");
+
+        let docs = [
+            doc("fillmore_flac", Some("Live at the Fillmore (FLAC)")),
+            doc("other", Some("Something Else")),
+            doc("fillmore_mp3", Some("Live at the Fillmore [MP3]")),
+            doc("untitled_a", None),
+            doc("untitled_b", Some("(1970)")),
+        ];
+        let refs: Vec<&ArchiveDoc> = docs.iter().collect();
+        assert_eq!(group_indices(&refs), vec![vec![0, 2], vec![1], vec![3], vec![4]]);
+    }
+}
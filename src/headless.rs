@@ -0,0 +1,470 @@
+use crate::app::{App, AppRateLimiter, DownloadAction, DownloadProgress};
+use crate::archive_api::{self, ItemDetails};
+use crate::cli::Command;
+use crate::dedupe::{DedupeIndex, DedupeMode};
+use crate::feed;
+use crate::download::{run_download_action, DownloadContext};
+use crate::file_history::FileHistory;
+use crate::forecast;
+use crate::fs_util;
+use crate::ipfs;
+use crate::library::LibraryIndex;
+use crate::musicbrainz::MusicBrainz;
+use crate::paths::PathPolicy;
+use crate::politeness::HostPacer;
+use crate::portable;
+use crate::replicate::Replication;
+use crate::scheduler::{DownloadScheduler, Ticket};
+use crate::seed::{self, SeedOptions};
+use crate::segments::SegmentPolicy;
+use crate::session::DownloadJob;
+use crate::settings::{self, Settings};
+use crate::site;
+use crate::storage::Storage;
+use crate::torrent_backend;
+use crate::verify::{self, FileCheck};
+use crate::wayback::{self, SnapshotQuery};
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{mpsc, Semaphore};
+
+/// Runs a headless `command` to completion, printing progress as plain lines on stdout.
+/// Fails (non-zero exit) if anything failed: a download error, a file that doesn't
+/// match its checksum, or an item that can't be found.
+pub async fn run(command: Command, settings: Settings, dir: Option<String>, force: bool, rate_limiter: AppRateLimiter) -> Result<()> {
+    info!("Running headless command {:?}", command);
+    let mut app = App::new(rate_limiter);
+    app.load_settings(settings);
+    app.force_downloads = force;
+    match command {
+        Command::Download(collection) => download(&mut app, dir, DownloadAction::Collection(collection.clone()), Some(collection)).await,
+        Command::DownloadItem(item) => download(&mut app, dir, DownloadAction::ItemAllFiles(item), None).await,
+        Command::FetchMetadata(item) => {
+            let details = fetch_details(&app, &item).await?;
+            print!("{}", describe_item(&details));
+            Ok(())
+        }
+        Command::Verify(dir) => verify_dir(&app, &dir).await,
+        Command::Wayback { url, from, to, download } => {
+            let query = SnapshotQuery::new(&url, from.as_deref(), to.as_deref())?;
+            if download {
+                download_wayback(&app, dir, &query).await
+            } else {
+                list_wayback(&app, &query).await
+            }
+        }
+        Command::IpfsManifest => {
+            let base_dir = dir
+                .or_else(|| app.settings.download_directory.clone())
+                .context("No download directory: pass --dir or set download_directory in settings.toml")?;
+            let (path, count) = ipfs::write_manifest(Path::new(&base_dir), &LibraryIndex::load(Path::new(&base_dir)))?;
+            println!("Wrote {} ({} item(s))", path.display(), count);
+            Ok(())
+        }
+        Command::Feeds => {
+            let base_dir = dir
+                .or_else(|| app.settings.download_directory.clone())
+                .context("No download directory: pass --dir or set download_directory in settings.toml")?;
+            let base_dir = Path::new(&base_dir);
+            let policy = PathPolicy::from_settings(&app.settings);
+            let summary = feed::write(base_dir, &LibraryIndex::load(base_dir), &policy, app.settings.feed_base_url.as_deref())?;
+            match (&summary.opds, &summary.podcast) {
+                (None, None) => println!("No downloaded texts or audio to list"),
+                (opds, podcast) => {
+                    if let Some((path, books)) = opds {
+                        println!("Wrote {} ({} book(s))", path.display(), books);
+                    }
+                    if let Some((path, episodes)) = podcast {
+                        println!("Wrote {} ({} episode(s))", path.display(), episodes);
+                    }
+                }
+            }
+            if summary.missing_metadata > 0 {
+                println!("Left out {} downloaded item(s) without cached metadata; download them again to add them", summary.missing_metadata);
+            }
+            Ok(())
+        }
+        Command::Site => {
+            let base_dir = dir
+                .or_else(|| app.settings.download_directory.clone())
+                .context("No download directory: pass --dir or set download_directory in settings.toml")?;
+            let base_dir = Path::new(&base_dir);
+            let summary = site::write(base_dir, &LibraryIndex::load(base_dir), &PathPolicy::from_settings(&app.settings))?;
+            println!("Wrote pages of {} item(s) in {} collection(s) into {}", summary.items, summary.collections, base_dir.join(site::SITE_DIR).display());
+            match &summary.index {
+                Some(index) => println!("Front page: {}", index.display()),
+                None => println!("Kept the existing {}, which archiver didn't write; the collections are in {}/collections", site::INDEX_FILE, site::SITE_DIR),
+            }
+            if summary.missing_metadata > 0 {
+                println!("Left out {} downloaded item(s) without cached metadata; download them again to add them", summary.missing_metadata);
+            }
+            Ok(())
+        }
+        Command::MakeTorrent { dir, options } => make_torrent(dir, options).await,
+        Command::ExportState(path) => {
+            let base_dir = dir.or_else(|| app.settings.download_directory.clone());
+            let bundle = portable::export(&app.settings, base_dir.as_deref().map(Path::new));
+            portable::write(&path, &bundle)?;
+            println!(
+                "Wrote {}: settings, {} favorite(s) and {} download history record(s)",
+                path.display(),
+                bundle.settings.favorite_collections.len(),
+                bundle.download_history.len()
+            );
+            Ok(())
+        }
+        Command::ImportState(path) => {
+            let bundle = portable::read(&path)?;
+            let (merged, new_favorites) = portable::merged_settings(&bundle, &app.settings, dir.as_deref());
+            settings::save_settings(&merged)?;
+            println!("Imported the settings from {} ({} new favorite(s))", path.display(), new_favorites);
+            match &merged.download_directory {
+                Some(base_dir) => {
+                    let records = portable::import_history(&bundle, Path::new(base_dir));
+                    println!("Added {} download history record(s) to {}", records, base_dir);
+                }
+                None if !bundle.download_history.is_empty() => {
+                    println!("Skipped the download history: no download directory, pass --dir to import it");
+                }
+                None => {}
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes `<dir>.torrent` next to `dir` and prints its info hashes.
+async fn make_torrent(dir: PathBuf, options: SeedOptions) -> Result<()> {
+    if !dir.is_dir() {
+        bail!("'{}' is not a directory", dir.display());
+    }
+    let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    if options.archive_web_seed && !dir.join(format!("{}_meta.xml", name)).exists() {
+        println!("Warning: '{}' doesn't look like an item folder, so the archive.org web seed won't resolve", dir.display());
+    }
+    println!("Hashing {}...", dir.display());
+    let torrent = {
+        let dir = dir.clone();
+        tokio::task::spawn_blocking(move || seed::create_torrent(&dir, &options)).await.context("Hashing task failed")??
+    };
+    let path = seed::torrent_path(&dir);
+    fs_util::atomic_write(&path, &torrent.data)?;
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    println!(
+        "Wrote {}: {} files, {}, piece size {}",
+        path.display(),
+        torrent.file_count,
+        forecast::format_bytes(torrent.total_size),
+        forecast::format_bytes(torrent.piece_length)
+    );
+    println!("Info hash v1: {}", hex(&torrent.info_hash_v1));
+    println!("Info hash v2: {}", hex(&torrent.info_hash_v2));
+    Ok(())
+}
+
+/// Prints the captures of a URL, one per line: date, status, type and URL.
+async fn list_wayback(app: &App, query: &SnapshotQuery) -> Result<()> {
+    let snapshots = wayback::list_snapshots(&app.client, &wayback::endpoint(), query, &app.rate_limiter).await?;
+    for snapshot in &snapshots {
+        println!("{}\t{}\t{}\t{}", snapshot.date(), snapshot.status, snapshot.mimetype, snapshot.original);
+    }
+    println!("{} capture(s) of {}", snapshots.len(), query.url);
+    Ok(())
+}
+
+/// Saves the latest capture of a URL, or every capture in the requested date range.
+async fn download_wayback(app: &App, dir: Option<String>, query: &SnapshotQuery) -> Result<()> {
+    let base_dir = dir
+        .or_else(|| app.settings.download_directory.clone())
+        .context("No download directory: pass --dir or set download_directory in settings.toml")?;
+    let base = wayback::endpoint();
+    let snapshots = wayback::list_snapshots(&app.client, &base, query, &app.rate_limiter).await?;
+    let wanted = wayback::to_download(&snapshots, query.has_range());
+    if wanted.is_empty() {
+        bail!("No successful captures of {} to download", query.url);
+    }
+    let policy = PathPolicy::from_settings(&app.settings);
+    let mut failures = 0;
+    for snapshot in &wanted {
+        match wayback::download_snapshot(&app.client, &base, snapshot, &base_dir, &policy, &app.rate_limiter).await {
+            Ok(path) => println!("Saved {}", path.display()),
+            Err(e) => {
+                println!("Error: {:#}", e);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        bail!("{} of {} capture(s) failed to download", failures, wanted.len());
+    }
+    Ok(())
+}
+
+/// Downloads `action` into `dir` (or the configured download directory) with the
+/// download settings, like the TUI would, and writes the run report.
+async fn download(app: &mut App, dir: Option<String>, action: DownloadAction, collection: Option<String>) -> Result<()> {
+    let base_dir = dir
+        .or_else(|| app.settings.download_directory.clone())
+        .context("No download directory: pass --dir or set download_directory in settings.toml")?;
+    let settings = &app.settings;
+    let (progress_tx, mut progress_rx) = mpsc::channel(1024);
+    let ctx = DownloadContext {
+        client: app.client.clone(),
+        base_dir: base_dir.clone(),
+        progress_tx,
+        scheduler: DownloadScheduler::new(settings.max_concurrent_downloads.unwrap_or(4)),
+        ticket: Ticket::new(0, &action), // The only job
+        collection_item_semaphore: Arc::new(Semaphore::new(settings.max_concurrent_collections.unwrap_or(2).max(1))),
+        rate_limiter: Arc::clone(&app.rate_limiter),
+        path_policy: PathPolicy::from_settings(settings),
+        sidecar: settings.metadata_sidecar,
+        write_playlist: settings.write_playlists,
+        write_checksums: settings.write_checksums,
+        library_layouts: Arc::new(settings.library_layouts.clone()),
+        fetch_filters: Arc::new(settings.fetch_filters.clone()),
+        dedupe: (settings.dedupe_mode != DedupeMode::Off).then(|| Arc::new(DedupeIndex::new(settings.dedupe_mode, &base_dir))),
+        history: FileHistory::open(Path::new(&base_dir)),
+        force: app.force_downloads,
+        tag_audio: settings.tag_audio.then_some(settings.tag_policy),
+        musicbrainz: settings.musicbrainz_lookup.then(|| Arc::new(MusicBrainz::new(app.client.clone()))),
+        ipfs_api: settings.ipfs_export.then(|| settings.ipfs_api_url.clone()),
+        replication: Replication::from_settings(settings),
+        storage: Storage::from_settings(settings),
+        authenticated: app.credentials.is_some(),
+        host_pacer: Arc::new(HostPacer::new(Duration::from_millis(settings.host_delay_ms))),
+        exclusions: Arc::clone(&app.exclusions),
+        file_preset: settings.default_file_preset,
+        source_policy: settings.file_source_policy,
+        subtitle_languages: settings.include_subtitles.then(|| Arc::new(settings.subtitle_languages.clone())),
+        file_filter: Arc::new(settings.download_file_filter.clone()),
+        torrent_backend: torrent_backend::from_settings(settings, &app.client),
+        segments: SegmentPolicy::from_settings(settings),
+        throttle: Arc::clone(&app.throttle),
+        control: Arc::clone(&app.download_control),
+        retry: None,
+    };
+    let mode = settings.download_mode;
+    let job = DownloadJob { action: action.clone(), collection: collection.clone(), mode, base_dir: base_dir.clone(), preset: settings.default_file_preset, retry: None };
+    let description = job.describe();
+    println!("Downloading {} into {} ({})", description, base_dir, mode);
+    app.begin_report_job(description, &base_dir);
+    app.is_downloading = true;
+
+    let task = tokio::spawn(async move { run_download_action(&ctx, action, mode, collection.as_deref()).await });
+    let mut failed = false;
+    // The channel closes once the task (and every file download it spawned) is done
+    while let Some(progress) = progress_rx.recv().await {
+        if let Some(line) = progress_line(&progress) {
+            println!("{}", line);
+        }
+        failed |= matches!(progress, DownloadProgress::Error(_) | DownloadProgress::ItemCompleted(_, false));
+        app.apply_download_progress(progress);
+    }
+    if let Err(e) = task.await.context("Download task panicked")? {
+        println!("Error: {:#}", e);
+        if let Some(report) = &mut app.run_report {
+            report.error(format!("{:#}", e));
+        }
+        failed = true;
+    }
+    app.finish_run_report();
+    app.library.flush();
+    if let Some(status) = &app.download_status {
+        println!("{}", status);
+    }
+    if failed {
+        bail!("Some downloads failed (see the log and the download report)");
+    }
+    Ok(())
+}
+
+/// The progress worth a line in a log: item and file milestones, not byte counts.
+fn progress_line(progress: &DownloadProgress) -> Option<String> {
+    match progress {
+        DownloadProgress::CollectionInfo(total) => Some(format!("{} item(s) to download", total)),
+        DownloadProgress::ItemStarted(id) => Some(format!("Item {}", id)),
+        DownloadProgress::FileCompleted(name) => Some(format!("  done {}", name)),
+        DownloadProgress::FileRestricted(_, name) => Some(format!("  restricted {}", name)),
+        DownloadProgress::ItemCompleted(id, false) => Some(format!("Item {} finished with errors", id)),
+        DownloadProgress::ItemPlaced(id, dir) => Some(format!("  {} is in {}", id, dir.display())),
+        DownloadProgress::ItemMatched(id, release_id) => Some(format!("  {} matches MusicBrainz release {}", id, release_id)),
+        DownloadProgress::ItemAddedToIpfs(id, cid) => Some(format!("Added {} to IPFS: {}", id, cid)),
+        DownloadProgress::ItemSkippedWasCollection(id) => Some(format!("Skipped {} (is a collection)", id)),
+        DownloadProgress::ItemSkippedClaimed(id) => Some(format!("Skipped {} (downloading in another instance)", id)),
+        DownloadProgress::Error(msg) => Some(format!("Error: {}", msg)),
+        DownloadProgress::Status(msg) => Some(msg.clone()),
+        _ => None,
+    }
+}
+
+async fn fetch_details(app: &App, identifier: &str) -> Result<ItemDetails> {
+    archive_api::fetch_item_details(&app.client, identifier, Arc::clone(&app.rate_limiter)).await.map_err(|e| anyhow!("{}", e))
+}
+
+/// Plain-text metadata and file list of an item.
+fn describe_item(details: &ItemDetails) -> String {
+    let mut out = format!("identifier: {}\n", details.identifier);
+    let fields = [
+        ("title", &details.title),
+        ("creator", &details.creator),
+        ("date", &details.date),
+        ("mediatype", &details.mediatype),
+        ("uploader", &details.uploader),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            out.push_str(&format!("{}: {}\n", name, value));
+        }
+    }
+    if !details.collections.is_empty() {
+        out.push_str(&format!("collections: {}\n", details.collections.join(", ")));
+    }
+    out.push_str(&format!("files ({}):\n", details.files.len()));
+    for file in &details.files {
+        out.push_str(&format!(
+            "  {}\t{}\t{}{}\n",
+            file.name,
+            file.size.as_deref().unwrap_or("-"),
+            file.format.as_deref().unwrap_or("-"),
+            if file.private { "\t(restricted)" } else { "" }
+        ));
+    }
+    out
+}
+
+/// Verifies an item folder, or every item folder of a collection folder (one whose
+/// name is a collection identifier).
+async fn verify_dir(app: &App, dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        bail!("'{}' is not a directory", dir.display());
+    }
+    let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).context("Directory has no name")?;
+    let subdirs = || -> Result<Vec<PathBuf>> {
+        let mut subdirs: Vec<PathBuf> = std::fs::read_dir(dir)
+            .context(format!("Failed to list '{}'", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_dir() && !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')))
+            .collect();
+        subdirs.sort();
+        Ok(subdirs)
+    };
+    // Items with a checksum manifest are checked offline; archive.org is only asked
+    // about directories without one.
+    let items: Vec<(PathBuf, Option<ItemDetails>)> = if verify::find_manifest(dir).is_some() {
+        vec![(dir.to_path_buf(), None)]
+    } else if subdirs()?.iter().any(|sub| verify::find_manifest(sub).is_some()) {
+        subdirs()?.into_iter().map(|path| (path, None)).collect()
+    } else {
+        let details = fetch_details(app, &name).await?;
+        if details.mediatype.as_deref() == Some("collection") {
+            subdirs()?.into_iter().map(|path| (path, None)).collect()
+        } else {
+            vec![(dir.to_path_buf(), Some(details))]
+        }
+    };
+
+    let policy = PathPolicy::from_settings(&app.settings);
+    let (mut ok, mut missing, mut bad) = (0, 0, 0);
+    for (item_dir, details) in items {
+        let id = item_dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let checks = match details {
+            Some(details) => {
+                println!("Item {}", details.identifier);
+                verify::verify_item(item_dir, details, policy).await?
+            }
+            None => match verify::verify_manifest(item_dir.clone()).await? {
+                Some(checks) => {
+                    println!("Item {} (checksum manifest)", id);
+                    checks
+                }
+                None => match fetch_details(app, &id).await {
+                    Ok(details) => {
+                        println!("Item {}", details.identifier);
+                        verify::verify_item(item_dir, details, policy).await?
+                    }
+                    Err(e) => {
+                        println!("{}: {}", id, e);
+                        bad += 1;
+                        continue;
+                    }
+                },
+            },
+        };
+        for (file, check) in checks {
+            match check {
+                FileCheck::Ok => ok += 1,
+                FileCheck::Missing => missing += 1,
+                FileCheck::Mismatch { expected, actual } => {
+                    println!("  MISMATCH {}: expected {}, found {}", file, expected, actual);
+                    bad += 1;
+                }
+            }
+        }
+    }
+    println!("{} file(s) OK, {} not downloaded, {} failed", ok, missing, bad);
+    if bad > 0 {
+        bail!("{} file(s) failed verification", bad);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_item_lists_fields_and_files() {
+        let details = ItemDetails {
+            identifier: "gd1977".to_string(),
+            title: Some("Live at Barton Hall".to_string()),
+            collections: vec!["etree".to_string(), "GratefulDead".to_string()],
+            files: vec![crate::archive_api::FileDetails {
+                name: "track01.flac".to_string(),
+                size: Some("1024".to_string()),
+                format: Some("Flac".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let text = describe_item(&details);
+        assert!(text.starts_with("identifier: gd1977\ntitle: Live at Barton Hall\n"));
+        assert!(text.contains("collections: etree, GratefulDead\n"));
+        assert!(text.ends_with("files (1):\n  track01.flac\t1024\tFlac\n"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_headless_download_then_verify_mock() {
+        use crate::mock_server::{self, MOCK_ITEMS};
+        use governor::Quota;
+        use std::num::NonZeroU32;
+
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let limiter: AppRateLimiter = Arc::new(crate::rate_limit::SharedRateLimiter::new(Quota::per_minute(NonZeroU32::new(600).unwrap())));
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_string_lossy().into_owned();
+
+        run(Command::DownloadItem(MOCK_ITEMS[0].to_string()), Settings::default(), Some(base.clone()), false, Arc::clone(&limiter))
+            .await
+            .expect("Headless download should succeed");
+        let item_dir = dir.path().join(MOCK_ITEMS[0]);
+        assert!(item_dir.join("track01.mp3").exists());
+        let manifest = item_dir.join(verify::manifest_file_name(MOCK_ITEMS[0]));
+        assert!(manifest.exists(), "Checksum manifest is written by default");
+        run(Command::Verify(item_dir.clone()), Settings::default(), None, false, Arc::clone(&limiter)).await.expect("Fresh download verifies");
+
+        std::fs::write(item_dir.join("track01.mp3"), b"corrupted").unwrap();
+        let err = run(Command::Verify(item_dir.clone()), Settings::default(), None, false, Arc::clone(&limiter)).await.unwrap_err();
+        assert!(err.to_string().contains("1 file(s) failed verification"), "{}", err);
+
+        // Without the manifest, the MD5s are fetched from archive.org instead
+        std::fs::remove_file(manifest).unwrap();
+        let err = run(Command::Verify(item_dir), Settings::default(), None, false, limiter).await.unwrap_err();
+        assert!(err.to_string().contains("1 file(s) failed verification"), "{}", err);
+    }
+}
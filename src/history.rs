@@ -0,0 +1,104 @@
+use crate::app::{Breadcrumb, Listing};
+
+/// Places kept in each direction; the oldest are dropped beyond this.
+const MAX_PLACES: usize = 100;
+
+/// Somewhere the user can navigate back or forward to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Place {
+    /// A listing in the items pane, with the sub-collection trail it was reached through.
+    Listing { listing: Listing, trail: Vec<Breadcrumb> },
+    /// An item's detail view.
+    Item(String),
+}
+
+/// Browser-style back/forward history of visited collections, searches and items.
+#[derive(Debug, Default)]
+pub struct History {
+    back: Vec<Place>,
+    forward: Vec<Place>,
+}
+
+impl History {
+    /// Records `current` before navigating somewhere new, which drops the forward history.
+    pub fn visit(&mut self, current: Place) {
+        self.forward.clear();
+        push(&mut self.back, current);
+    }
+
+    /// Returns the place to go back to from `current`, if any.
+    pub fn back(&mut self, current: Place) -> Option<Place> {
+        step(&mut self.back, &mut self.forward, current)
+    }
+
+    /// Returns the place to go forward to from `current`, if any.
+    pub fn forward(&mut self, current: Place) -> Option<Place> {
+        step(&mut self.forward, &mut self.back, current)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+fn push(places: &mut Vec<Place>, place: Place) {
+    if places.last() != Some(&place) {
+        places.push(place);
+        if places.len() > MAX_PLACES {
+            places.remove(0);
+        }
+    }
+}
+
+/// Pops the nearest place in `from` that isn't `current` (returning to a listing with Esc
+/// doesn't record it, so it may still be on the stack) and pushes `current` onto `to`.
+fn step(from: &mut Vec<Place>, to: &mut Vec<Place>, current: Place) -> Option<Place> {
+    while let Some(place) = from.pop() {
+        if place != current {
+            push(to, current);
+            return Some(place);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(name: &str) -> Place {
+        Place::Listing { listing: Listing::Collection(name.to_string()), trail: Vec::new() }
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut history = History::default();
+        history.visit(collection("a"));
+        history.visit(Place::Item("x".to_string()));
+        // Now at collection b, reached from item x
+        assert_eq!(history.back(collection("b")), Some(Place::Item("x".to_string())));
+        assert_eq!(history.back(Place::Item("x".to_string())), Some(collection("a")));
+        assert!(!history.can_go_back());
+        assert_eq!(history.forward(collection("a")), Some(Place::Item("x".to_string())));
+        assert_eq!(history.forward(Place::Item("x".to_string())), Some(collection("b")));
+        assert_eq!(history.forward(collection("b")), None);
+
+        // Going somewhere new drops the forward history
+        history.back(collection("b"));
+        history.visit(Place::Item("x".to_string()));
+        assert!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn test_back_skips_the_current_place() {
+        let mut history = History::default();
+        history.visit(collection("a")); // Opened item y from a, then Esc back to a
+        history.visit(collection("a")); // Not recorded twice
+        assert_eq!(history.back(collection("a")), None, "Already there");
+        assert!(!history.can_go_back());
+    }
+}
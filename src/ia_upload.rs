@@ -0,0 +1,409 @@
+use crate::archive_api;
+use crate::auth;
+use crate::rate_control;
+use crate::secrets::SecretStore;
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::stream;
+use log::{info, warn};
+use reqwest::{header, Body, Client};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+
+/// File in the config directory that held the S3 keys before they moved to the secret
+/// store; moved there on the next load.
+pub const KEYS_FILE: &str = "ia_s3_keys.toml";
+/// Key of the S3 keys in the secret store (see [`crate::secrets`]).
+const KEYS_SECRET: &str = "ia_s3_keys";
+/// archive.org's S3-like upload endpoint.
+const S3_ENDPOINT: &str = "https://s3.us.archive.org";
+/// Where users look up their keys.
+pub const KEYS_URL: &str = "https://archive.org/account/s3.php";
+/// Bytes read from disk per chunk of an upload body (and between progress messages).
+const CHUNK_SIZE: usize = 256 * 1024;
+/// Tries per file when archive.org answers 503 SlowDown / 429.
+const MAX_ATTEMPTS: u32 = 3;
+/// Pause before retrying a throttled file when the server doesn't say how long to wait.
+const THROTTLE_PAUSE: Duration = Duration::from_secs(30);
+
+/// archive.org S3 access/secret key pair (see [`KEYS_URL`]).
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct S3Keys {
+    pub access: String,
+    pub secret: String,
+}
+
+// The secret never ends up in logs
+impl fmt::Debug for S3Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Keys").field("access", &self.access).field("secret", &"***").finish()
+    }
+}
+
+impl S3Keys {
+    /// Parses `ACCESS SECRET` or `ACCESS:SECRET` as typed in the settings.
+    pub fn parse(input: &str) -> Option<Self> {
+        let (access, secret) = input.trim().split_once([' ', ':'])?;
+        let (access, secret) = (access.trim(), secret.trim());
+        (!access.is_empty() && !secret.is_empty() && !secret.contains(char::is_whitespace))
+            .then(|| S3Keys { access: access.to_string(), secret: secret.to_string() })
+    }
+
+    /// Access key followed by a hidden secret, for display.
+    pub fn masked(&self) -> String {
+        format!("{} / ********", self.access)
+    }
+
+    fn authorization(&self) -> String {
+        format!("LOW {}:{}", self.access, self.secret)
+    }
+}
+
+/// Loads the keys saved from the settings, otherwise the `[s3]` keys of the `ia` tool's
+/// configuration (`ia configure`). A [`KEYS_FILE`] left in `config_dir` by an earlier
+/// version is moved into `store` first.
+pub fn load_keys(store: &SecretStore, config_dir: &Path) -> Option<S3Keys> {
+    store.adopt_file::<S3Keys>(KEYS_SECRET, &config_dir.join(KEYS_FILE));
+    if let Some(keys) = store.get_value(KEYS_SECRET) {
+        return Some(keys);
+    }
+    let (path, keys) = auth::ia_config_paths()
+        .into_iter()
+        .find_map(|path| Some((path.clone(), parse_ia_s3_keys(&fs::read_to_string(&path).ok()?)?)))?;
+    info!("Using archive.org S3 keys from {}", path.display());
+    Some(keys)
+}
+
+/// Saves the keys in `store`: the keyring, or a file readable by the current user only.
+pub fn save_keys(store: &SecretStore, keys: &S3Keys) -> Result<()> {
+    store.set_value(KEYS_SECRET, Some(keys))?;
+    info!("Saved archive.org S3 keys");
+    Ok(())
+}
+
+/// Extracts `access`/`secret` from the `[s3]` section of an `ia.ini` file.
+fn parse_ia_s3_keys(contents: &str) -> Option<S3Keys> {
+    let mut in_s3 = false;
+    let (mut access, mut secret) = (None, None);
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_s3 = line == "[s3]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_s3) else { continue };
+        match key.trim() {
+            "access" => access = Some(value.trim().to_string()),
+            "secret" => secret = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    match (access, secret) {
+        (Some(access), Some(secret)) if !access.is_empty() && !secret.is_empty() => Some(S3Keys { access, secret }),
+        _ => None,
+    }
+}
+
+/// A local directory to upload as (or into) an archive.org item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadRequest {
+    pub directory: PathBuf,
+    pub identifier: String,
+    /// Item metadata (`title`, `mediatype`, `collection`, ...); repeated keys become
+    /// multiple values. Only applied when the upload creates the item.
+    pub metadata: Vec<(String, String)>,
+}
+
+/// Progress of an upload, sent to the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadProgress {
+    /// Files and bytes about to be uploaded.
+    Started { files: usize, bytes: u64 },
+    /// Uploading this file (path relative to the directory).
+    File(String),
+    /// Bytes of the whole upload sent so far.
+    Bytes(u64),
+    /// All files are uploaded.
+    Finished,
+    /// The upload stopped; the error chain.
+    Failed(String),
+}
+
+/// Checks an identifier against archive.org's rules: letters, digits, `_`, `-` and `.`,
+/// starting with a letter or digit, at most 100 characters.
+pub fn validate_identifier(identifier: &str) -> Result<()> {
+    let valid_chars = identifier.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    let starts_alnum = identifier.chars().next().is_some_and(|c| c.is_ascii_alphanumeric());
+    if !valid_chars || !starts_alnum || identifier.len() > 100 {
+        bail!("'{}' is not a valid identifier: use letters, digits, '_', '-' and '.', starting with a letter or digit", identifier);
+    }
+    Ok(())
+}
+
+/// The S3 endpoint matching the API base URL: [`S3_ENDPOINT`] for archive.org, `<base>/s3`
+/// for an override such as the mock server.
+pub fn endpoint() -> String {
+    let base = archive_api::base_url();
+    if base == archive_api::DEFAULT_BASE_URL {
+        S3_ENDPOINT.to_string()
+    } else {
+        format!("{}/s3", base)
+    }
+}
+
+/// Files below `dir` as (path, name relative to `dir` with `/` separators, size),
+/// sorted by name. Hidden files and directories (`.git`, `.DS_Store`) are skipped.
+pub fn collect_files(dir: &Path) -> Result<Vec<(PathBuf, String, u64)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current).with_context(|| format!("Failed to read directory '{}'", current.display()))? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if metadata.is_file() {
+                let relative = path.strip_prefix(dir).unwrap_or(&path);
+                let name = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+                files.push((path, name, metadata.len()));
+            }
+        }
+    }
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(files)
+}
+
+/// Uploads every file of `request.directory` to `<endpoint>/<identifier>/<name>`.
+///
+/// The first file creates the item with the request's metadata; the derive (archive.org's
+/// format conversions) is only queued after the last one. Progress goes to `progress_tx`
+/// (except the final `Finished`/`Failed`, which the caller sends). Returns the number of
+/// files uploaded.
+pub async fn upload_directory(
+    client: &Client,
+    endpoint: &str,
+    keys: &S3Keys,
+    request: &UploadRequest,
+    progress_tx: &mpsc::Sender<UploadProgress>,
+) -> Result<usize> {
+    validate_identifier(&request.identifier)?;
+    let directory = request.directory.clone();
+    let files = tokio::task::spawn_blocking(move || collect_files(&directory)).await??;
+    if files.is_empty() {
+        bail!("'{}' has no files to upload", request.directory.display());
+    }
+    let total_bytes: u64 = files.iter().map(|(_, _, size)| size).sum();
+    info!("Uploading {} files ({} bytes) from '{}' to '{}'", files.len(), total_bytes, request.directory.display(), request.identifier);
+    let _ = progress_tx.send(UploadProgress::Started { files: files.len(), bytes: total_bytes }).await;
+
+    let mut sent_before = 0;
+    for (index, (path, name, size)) in files.iter().enumerate() {
+        let _ = progress_tx.send(UploadProgress::File(name.clone())).await;
+        let url = format!("{}/{}/{}", endpoint, request.identifier, encode_path(name));
+        let mut attempt = 1;
+        loop {
+            let mut builder = client
+                .put(&url)
+                .header(header::AUTHORIZATION, keys.authorization())
+                .header(header::CONTENT_LENGTH, *size)
+                .header("x-archive-queue-derive", if index + 1 == files.len() { "1" } else { "0" });
+            if index == 0 {
+                builder = builder.header("x-amz-auto-make-bucket", "1").header("x-archive-size-hint", total_bytes);
+                for (header_name, value) in metadata_headers(&request.metadata) {
+                    builder = builder.header(header_name, value);
+                }
+            }
+            let body = file_body(path, sent_before, progress_tx.clone()).await?;
+            let response = builder.body(body).send().await.with_context(|| format!("Failed to upload '{}'", name))?;
+            let status = response.status();
+            if status.is_success() {
+                break;
+            }
+            if rate_control::is_throttling(status) && attempt < MAX_ATTEMPTS {
+                let pause = rate_control::retry_after(&response).unwrap_or(THROTTLE_PAUSE * attempt);
+                warn!("archive.org is busy ({}); retrying '{}' in {:?}", status, name, pause);
+                tokio::time::sleep(pause).await;
+                attempt += 1;
+                continue;
+            }
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Upload of '{}' failed with {}: {}", name, status, s3_error_message(&message)));
+        }
+        sent_before += size;
+        let _ = progress_tx.send(UploadProgress::Bytes(sent_before)).await;
+    }
+    info!("Uploaded {} files to '{}'", files.len(), request.identifier);
+    Ok(files.len())
+}
+
+/// Streams the file from disk, reporting the bytes sent (counted from `sent_before`).
+async fn file_body(path: &Path, sent_before: u64, progress_tx: mpsc::Sender<UploadProgress>) -> Result<Body> {
+    let file = tokio::fs::File::open(path).await.with_context(|| format!("Failed to open '{}'", path.display()))?;
+    let chunks = stream::unfold((file, sent_before), move |(mut file, sent)| {
+        let progress_tx = progress_tx.clone();
+        async move {
+            let mut buf = vec![0; CHUNK_SIZE];
+            match file.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    let sent = sent + n as u64;
+                    let _ = progress_tx.try_send(UploadProgress::Bytes(sent)); // Dropped when the UI lags
+                    Some((Ok(buf), (file, sent)))
+                }
+                Err(e) => Some((Err(e), (file, sent))),
+            }
+        }
+    });
+    Ok(Body::wrap_stream(chunks))
+}
+
+/// `x-archive-meta-*` headers for the metadata. Repeated keys are numbered
+/// (`x-archive-meta01-collection`), and values that aren't plain ASCII are sent as
+/// `uri(<percent-encoded>)`, which archive.org decodes.
+fn metadata_headers(metadata: &[(String, String)]) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    for (key, value) in metadata.iter().filter(|(_, value)| !value.trim().is_empty()) {
+        let repeated = metadata.iter().filter(|(k, v)| k == key && !v.trim().is_empty()).count() > 1;
+        let key_part = key.trim().to_lowercase().replace('_', "--");
+        let header_name = if repeated {
+            let number = headers.iter().filter(|(name, _): &&(String, String)| name.ends_with(&format!("-{}", key_part))).count() + 1;
+            format!("x-archive-meta{:02}-{}", number, key_part)
+        } else {
+            format!("x-archive-meta-{}", key_part)
+        };
+        let value = value.trim();
+        let plain = value.chars().all(|c| c.is_ascii_graphic() || c == ' ');
+        headers.push((header_name, if plain { value.to_string() } else { format!("uri({})", percent_encode(value)) }));
+    }
+    headers
+}
+
+/// Percent-encodes each `/`-separated segment of a file name for the URL.
+fn encode_path(name: &str) -> String {
+    name.split('/').map(percent_encode).collect::<Vec<_>>().join("/")
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The `<Message>` of an S3 XML error body, or the body itself.
+fn s3_error_message(body: &str) -> String {
+    let message = body.split("<Message>").nth(1).and_then(|rest| rest.split("</Message>").next()).unwrap_or(body);
+    message.trim().chars().take(200).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_parse_load_and_save() {
+        let keys = S3Keys { access: "AK".to_string(), secret: "SK".to_string() };
+        assert_eq!(S3Keys::parse(" AK SK "), Some(keys.clone()));
+        assert_eq!(S3Keys::parse("AK:SK"), Some(keys.clone()));
+        assert_eq!(S3Keys::parse("AK"), None);
+        assert!(!format!("{:?}", keys).contains("SK"), "Debug output hides the secret");
+        assert_eq!(parse_ia_s3_keys("[s3]\naccess = AK\nsecret = SK\n\n[cookies]\nlogged-in-user = me\n"), Some(keys.clone()));
+        assert_eq!(parse_ia_s3_keys("[cookies]\naccess = AK\nsecret = SK\n"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::new(None, dir.path().join(crate::secrets::SECRETS_FILE));
+        save_keys(&store, &keys).unwrap();
+        assert_eq!(load_keys(&store, dir.path()), Some(keys));
+    }
+
+    #[test]
+    fn test_metadata_headers_number_repeats_and_encode_unicode() {
+        let metadata = vec![
+            ("title".to_string(), "Café Sessions".to_string()),
+            ("collection".to_string(), "opensource_audio".to_string()),
+            ("collection".to_string(), "community".to_string()),
+            ("licenseurl".to_string(), "".to_string()),
+            ("subject_tag".to_string(), "live".to_string()),
+        ];
+        assert_eq!(
+            metadata_headers(&metadata),
+            vec![
+                ("x-archive-meta-title".to_string(), "uri(Caf%C3%A9%20Sessions)".to_string()),
+                ("x-archive-meta01-collection".to_string(), "opensource_audio".to_string()),
+                ("x-archive-meta02-collection".to_string(), "community".to_string()),
+                ("x-archive-meta-subject--tag".to_string(), "live".to_string()),
+            ]
+        );
+        assert_eq!(encode_path("disc 1/01 #intro.flac"), "disc%201/01%20%23intro.flac");
+    }
+
+    #[test]
+    fn test_collect_files_skips_hidden_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("disc1")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join("disc1").join("01.flac"), b"abc").unwrap();
+        fs::write(dir.path().join("cover.jpg"), b"x").unwrap();
+        fs::write(dir.path().join(".DS_Store"), b"x").unwrap();
+        fs::write(dir.path().join(".git").join("HEAD"), b"x").unwrap();
+        let names: Vec<(String, u64)> = collect_files(dir.path()).unwrap().into_iter().map(|(_, name, size)| (name, size)).collect();
+        assert_eq!(names, vec![("cover.jpg".to_string(), 1), ("disc1/01.flac".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_validate_identifier() {
+        assert!(validate_identifier("my-band_live.2024").is_ok());
+        assert!(validate_identifier("-leading-dash").is_err());
+        assert!(validate_identifier("has space").is_err());
+        assert!(validate_identifier("").is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_upload_directory_to_mock_s3() {
+        use crate::mock_server;
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+        fs::write(dir.path().join("b.txt"), vec![7u8; CHUNK_SIZE + 10]).unwrap();
+        let request = UploadRequest {
+            directory: dir.path().to_path_buf(),
+            identifier: "mock_upload_test".to_string(),
+            metadata: vec![("title".to_string(), "My upload".to_string())],
+        };
+        let (progress_tx, mut progress_rx) = mpsc::channel(64);
+
+        let wrong = S3Keys { access: "nope".to_string(), secret: "nope".to_string() };
+        let err = upload_directory(&Client::new(), &endpoint(), &wrong, &request, &progress_tx).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("403"), "Bad keys are refused: {:#}", err);
+
+        let keys = S3Keys { access: mock_server::MOCK_S3_ACCESS.to_string(), secret: mock_server::MOCK_S3_SECRET.to_string() };
+        let uploaded = upload_directory(&Client::new(), &endpoint(), &keys, &request, &progress_tx).await.unwrap();
+        assert_eq!(uploaded, 2);
+        let uploads = mock_server::uploads("mock_upload_test");
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(uploads[0].name, "a.txt");
+        assert_eq!(uploads[0].body, b"hello");
+        assert!(uploads[0].headers.contains(&("x-archive-meta-title".to_string(), "My upload".to_string())));
+        assert_eq!(uploads[1].body.len(), CHUNK_SIZE + 10);
+        drop(progress_tx);
+        let mut last_bytes = 0;
+        while let Some(progress) = progress_rx.recv().await {
+            if let UploadProgress::Bytes(bytes) = progress {
+                last_bytes = bytes;
+            }
+        }
+        assert_eq!(last_bytes, 5 + CHUNK_SIZE as u64 + 10);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::fs_util;
+use crate::library::LibraryIndex;
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::stream;
+use log::info;
+use reqwest::{header, Body, Client};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncReadExt;
+
+/// Address of a local IPFS node's HTTP API (kubo's default).
+pub const DEFAULT_API_URL: &str = "http://127.0.0.1:5001";
+/// Manifest mapping identifiers to CIDs, written into the download directory.
+pub const MANIFEST_FILE: &str = "ipfs-manifest.json";
+/// Separates the parts of the multipart upload; never appears in (escaped) file names.
+const BOUNDARY: &str = "archiver-ipfs-7c1e9b42";
+/// Size of the chunks files are streamed in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A directory or file sent to the node, named by its path from the added directory
+/// (which is included, so the node reports a CID for it).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Dir(String),
+    File { name: String, path: PathBuf, size: u64 },
+}
+
+/// Lists `dir` for adding: the directory itself, then each directory before its contents,
+/// sorted by name. Unfinished downloads (`.part`) are left out.
+fn collect_entries(dir: &Path) -> Result<Vec<Entry>> {
+    let root = dir.file_name().and_then(|n| n.to_str()).context(format!("'{}' has no usable directory name", dir.display()))?;
+    let mut entries = vec![Entry::Dir(root.to_string())];
+    collect_below(dir, root, &mut entries)?;
+    Ok(entries)
+}
+
+fn collect_below(dir: &Path, name: &str, entries: &mut Vec<Entry>) -> Result<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory '{}'", dir.display()))?
+        .collect::<std::io::Result<_>>()
+        .with_context(|| format!("Failed to read directory '{}'", dir.display()))?;
+    children.sort_by_key(|entry| entry.file_name());
+    for child in children {
+        let path = child.path();
+        let child_name = format!("{}/{}", name, child.file_name().to_string_lossy());
+        let metadata = child.metadata()?;
+        if metadata.is_dir() {
+            entries.push(Entry::Dir(child_name.clone()));
+            collect_below(&path, &child_name, entries)?;
+        } else if metadata.is_file() && path.extension().is_none_or(|ext| ext != "part") {
+            entries.push(Entry::File { name: child_name, path, size: metadata.len() });
+        }
+    }
+    Ok(())
+}
+
+/// Header of one multipart part. The node expects the path in `filename`, URL-escaped.
+fn part_header(entry: &Entry) -> String {
+    let (name, content_type) = match entry {
+        Entry::Dir(name) => (name, "application/x-directory"),
+        Entry::File { name, .. } => (name, "application/octet-stream"),
+    };
+    format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+        BOUNDARY,
+        escape(name),
+        content_type
+    )
+}
+
+/// Percent-encodes everything but unreserved characters (`/` included).
+fn escape(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// A piece of the request body: literal bytes or a file streamed from disk.
+enum Segment {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+/// The multipart body for `entries` and its exact length (sent as `Content-Length`).
+fn body_segments(entries: &[Entry]) -> (Vec<Segment>, u64) {
+    let mut segments = Vec::new();
+    let mut length = 0;
+    for entry in entries {
+        let header = part_header(entry);
+        length += header.len() as u64 + 2;
+        segments.push(Segment::Bytes(header.into_bytes()));
+        if let Entry::File { path, size, .. } = entry {
+            length += size;
+            segments.push(Segment::File(path.clone()));
+        }
+        segments.push(Segment::Bytes(b"\r\n".to_vec()));
+    }
+    let closing = format!("--{}--\r\n", BOUNDARY);
+    length += closing.len() as u64;
+    segments.push(Segment::Bytes(closing.into_bytes()));
+    (segments, length)
+}
+
+fn stream_body(segments: Vec<Segment>) -> Body {
+    let chunks = stream::unfold((segments.into_iter(), None::<tokio::fs::File>), |(mut segments, mut file)| async move {
+        loop {
+            if let Some(open) = &mut file {
+                let mut buf = vec![0; CHUNK_SIZE];
+                match open.read(&mut buf).await {
+                    Ok(0) => file = None,
+                    Ok(n) => {
+                        buf.truncate(n);
+                        return Some((Ok(buf), (segments, file)));
+                    }
+                    Err(e) => return Some((Err(e), (Vec::new().into_iter(), None))),
+                }
+                continue;
+            }
+            match segments.next()? {
+                Segment::Bytes(bytes) => return Some((Ok(bytes), (segments, None))),
+                Segment::File(path) => match tokio::fs::File::open(&path).await {
+                    Ok(opened) => file = Some(opened),
+                    Err(e) => return Some((Err(e), (Vec::new().into_iter(), None))),
+                },
+            }
+        }
+    });
+    Body::wrap_stream(chunks)
+}
+
+/// One line of the node's `add` answer.
+#[derive(Deserialize)]
+struct AddedEntry {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// Adds (and pins) `dir` with everything below it to the IPFS node at `api_url`,
+/// returning the directory's CID (v1).
+pub async fn add_directory(client: &Client, api_url: &str, dir: &Path) -> Result<String> {
+    let listed = dir.to_path_buf();
+    let entries = tokio::task::spawn_blocking(move || collect_entries(&listed)).await.context("Listing task failed")??;
+    let Some(Entry::Dir(root)) = entries.first().cloned() else { bail!("Nothing to add from '{}'", dir.display()) };
+    let (segments, length) = body_segments(&entries);
+    let response = client
+        .post(format!("{}/api/v0/add", api_url.trim_end_matches('/')))
+        .query(&[("recursive", "true"), ("cid-version", "1"), ("pin", "true"), ("progress", "false")])
+        .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", BOUNDARY))
+        .header(header::CONTENT_LENGTH, length)
+        .body(stream_body(segments))
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the IPFS node at {}", api_url))?;
+    let status = response.status();
+    let text = response.text().await.context("Failed to read the IPFS node's answer")?;
+    if !status.is_success() {
+        let message = serde_json::from_str::<serde_json::Value>(&text).ok().and_then(|v| v["Message"].as_str().map(str::to_string));
+        bail!("IPFS node refused '{}' with {}: {}", dir.display(), status, message.unwrap_or(text));
+    }
+    let cid = text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<AddedEntry>(line).ok())
+        .find(|added| added.name == root)
+        .map(|added| added.hash)
+        .ok_or_else(|| anyhow!("The IPFS node didn't report a CID for '{}'", root))?;
+    info!("Added '{}' to IPFS as {}", dir.display(), cid);
+    Ok(cid)
+}
+
+/// The identifier-to-CID manifest that federating peers fetch.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Manifest {
+    pub generator: String,
+    /// RFC 3339 time the manifest was written.
+    pub generated: String,
+    /// Item identifier to the CID of its directory.
+    pub items: BTreeMap<String, String>,
+}
+
+/// Writes [`MANIFEST_FILE`] into `base_dir` from the CIDs recorded in `library`,
+/// returning its path and the number of items listed.
+pub fn write_manifest(base_dir: &Path, library: &LibraryIndex) -> Result<(PathBuf, usize)> {
+    let manifest = Manifest {
+        generator: format!("archiver {}", env!("CARGO_PKG_VERSION")),
+        generated: chrono::Local::now().to_rfc3339(),
+        items: library.cids(),
+    };
+    let path = base_dir.join(MANIFEST_FILE);
+    let json = serde_json::to_string_pretty(&manifest).context("Failed to serialize IPFS manifest")?;
+    fs_util::atomic_write(&path, json).context(format!("Failed to write IPFS manifest {}", path.display()))?;
+    Ok((path, manifest.items.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::ItemStatus;
+
+    #[test]
+    fn test_entries_and_body_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = dir.path().join("gd77 live");
+        fs::create_dir_all(item.join("disc 1")).unwrap();
+        fs::write(item.join("disc 1/01.flac"), b"track").unwrap();
+        fs::write(item.join("notes+info.txt"), b"notes").unwrap();
+        fs::write(item.join("02.flac.part"), b"unfinished").unwrap();
+
+        let entries = collect_entries(&item).unwrap();
+        let names: Vec<&str> = entries
+            .iter()
+            .map(|e| match e {
+                Entry::Dir(name) | Entry::File { name, .. } => name.as_str(),
+            })
+            .collect();
+        assert_eq!(names, ["gd77 live", "gd77 live/disc 1", "gd77 live/disc 1/01.flac", "gd77 live/notes+info.txt"]);
+        assert!(part_header(&entries[3]).contains("filename=\"gd77%20live%2Fnotes%2Binfo.txt\""));
+
+        let (segments, length) = body_segments(&entries);
+        let streamed: u64 = segments
+            .iter()
+            .map(|s| match s {
+                Segment::Bytes(bytes) => bytes.len() as u64,
+                Segment::File(path) => fs::metadata(path).unwrap().len(),
+            })
+            .sum();
+        assert_eq!(length, streamed);
+    }
+
+    #[test]
+    fn test_manifest_lists_recorded_cids() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut library = LibraryIndex::load(dir.path());
+        library.record("gd1977", ItemStatus::Downloaded);
+        library.record_cid("gd1977", "bafyitem");
+        library.record("not_added", ItemStatus::Downloaded);
+        let (path, count) = write_manifest(dir.path(), &library).unwrap();
+        assert_eq!(count, 1);
+        let manifest: Manifest = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(manifest.items, BTreeMap::from([("gd1977".to_string(), "bafyitem".to_string())]));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_add_directory_to_mock_node() {
+        use crate::mock_server;
+        let dir = tempfile::tempdir().unwrap();
+        let item = dir.path().join("mock_item_one");
+        fs::create_dir_all(item.join("sub")).unwrap();
+        fs::write(item.join("a.txt"), b"first").unwrap();
+        fs::write(item.join("sub/b.bin"), vec![7u8; 200_000]).unwrap();
+        let api = format!("{}/ipfs", mock_server::shared_base_url());
+
+        let cid = add_directory(&Client::new(), &api, &item).await.unwrap();
+        assert!(cid.starts_with("bafy"), "{}", cid);
+        let added = mock_server::ipfs_added(&cid);
+        assert_eq!(added, [("mock_item_one/a.txt".to_string(), 5), ("mock_item_one/sub/b.bin".to_string(), 200_000)]);
+        assert!(add_directory(&Client::new(), "http://127.0.0.1:1", &item).await.is_err(), "No node listening");
+    }
+}
@@ -0,0 +1,480 @@
+use crate::settings::Settings;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::{info, warn};
+use serde::{Deserialize, Deserializer};
+use std::{collections::BTreeMap, fmt};
+
+/// Where an action's key is looked up. Navigation keys (see [`KeyBinding::is_reserved`])
+/// and text prompts aren't remappable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyContext {
+    /// Browsing and the item view (quitting works anywhere outside text prompts).
+    Global,
+    /// Both browsing panes.
+    Browsing,
+    Collections,
+    Items,
+    /// The item view.
+    Item,
+    /// The Downloads view.
+    Downloads,
+    /// The log view.
+    Log,
+    /// The failures view.
+    Failures,
+}
+
+impl KeyContext {
+    /// Heading of the context on the help screen.
+    pub fn title(self) -> &'static str {
+        match self {
+            KeyContext::Global => "General",
+            KeyContext::Browsing => "Browsing",
+            KeyContext::Collections => "Collections pane",
+            KeyContext::Items => "Items pane",
+            KeyContext::Item => "Item view",
+            KeyContext::Downloads => "Downloads view",
+            KeyContext::Log => "Log view",
+            KeyContext::Failures => "Failures view",
+        }
+    }
+
+    /// Whether a key can reach actions of both contexts (so they can't share it).
+    fn overlaps(self, other: KeyContext) -> bool {
+        use KeyContext::*;
+        self == other
+            || self == Global
+            || other == Global
+            || matches!((self, other), (Browsing, Collections | Items) | (Collections | Items, Browsing))
+    }
+}
+
+/// Contexts in help screen order.
+pub const CONTEXTS: [KeyContext; 8] = [
+    KeyContext::Global,
+    KeyContext::Browsing,
+    KeyContext::Collections,
+    KeyContext::Items,
+    KeyContext::Item,
+    KeyContext::Downloads,
+    KeyContext::Log,
+    KeyContext::Failures,
+];
+
+/// Something a remappable key does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Help,
+    CommandPalette,
+    Settings,
+    Back,
+    Forward,
+    SwitchPane,
+    CyclePreset,
+    Search,
+    OpenReport,
+    Upload,
+    Wayback,
+    ToggleDownloads,
+    ShowUpdate,
+    SyncNow,
+    AddCollection,
+    RemoveCollection,
+    ImportFavorites,
+    CycleLayout,
+    FetchFilter,
+    SetGroup,
+    GroupFilter,
+    RenameCollection,
+    MoveUp,
+    MoveDown,
+    CollectionOrder,
+    Estimate,
+    Download,
+    DownloadAll,
+    Mark,
+    MarkAll,
+    ClearMarks,
+    SaveSearch,
+    Export,
+    Open,
+    OpenPage,
+    Play,
+    Preview,
+    StopPreview,
+    ToggleOffline,
+    Copy,
+    CopyUrl,
+    Filter,
+    StatusFilter,
+    MediatypeFilter,
+    FormatFilter,
+    GroupDuplicates,
+    Exclude,
+    Verify,
+    NextTab,
+    RefreshDetails,
+    Pause,
+    PauseAll,
+    Cancel,
+    CancelAll,
+    ToggleLog,
+    LogLevel,
+    ToggleFailures,
+    RetryFailed,
+}
+
+/// An action with its `[keys]` name, default keys, contexts (the first one lists it on the
+/// help screen) and help text.
+type ActionEntry = (Action, &'static str, &'static [&'static str], &'static [KeyContext], &'static str);
+
+const ACTIONS: [ActionEntry; 59] = {
+    use Action::*;
+    use KeyContext::*;
+    [
+        (Quit, "quit", &["q"], &[Global], "Quit"),
+        (Help, "help", &["?"], &[Global], "Show or close this help"),
+        (CommandPalette, "command_palette", &[":"], &[Global], "Run any of these by name"),
+        (Settings, "settings", &["s"], &[Global], "Open the settings"),
+        (Back, "back", &["[", "Alt+Left"], &[Global], "Back to the previous collection, search or item"),
+        (Forward, "forward", &["]", "Alt+Right"], &[Global], "Forward again"),
+        (SwitchPane, "switch_pane", &["Tab"], &[Browsing], "Switch between the collections and items panes"),
+        (CyclePreset, "cycle_preset", &["p"], &[Browsing, Item], "Cycle the file preset of the next download"),
+        (Search, "search", &["/"], &[Browsing], "Search archive.org"),
+        (OpenReport, "open_report", &["R"], &[Browsing], "Open the last download report"),
+        (Upload, "upload", &["u"], &[Browsing], "Upload a directory to archive.org"),
+        (Wayback, "wayback", &["W"], &[Browsing], "Look up a URL in the Wayback Machine"),
+        (ToggleDownloads, "downloads", &["D"], &[Browsing, Downloads], "Show or close the Downloads view"),
+        (ShowUpdate, "show_update", &["U"], &[Browsing], "Show the available update"),
+        (SyncNow, "sync_now", &[], &[Browsing], "Start a sync of the favorite collections now"),
+        (AddCollection, "add_collection", &["a"], &[Collections], "Add a collection"),
+        (RemoveCollection, "remove_collection", &["Delete", "Backspace"], &[Collections], "Remove the selected collection"),
+        (ImportFavorites, "import_favorites", &["F"], &[Collections], "Import archive.org favorites"),
+        (CycleLayout, "cycle_layout", &["L"], &[Collections], "Cycle the collection's library layout"),
+        (FetchFilter, "fetch_filter", &["Q"], &[Collections], "Set the date range and query the collection is listed with"),
+        (SetGroup, "set_group", &["t"], &[Collections], "Put the collection into a group"),
+        (RenameCollection, "rename_collection", &["n"], &[Collections], "Give the collection a readable name"),
+        (GroupFilter, "group_filter", &["T"], &[Collections], "Show one group of collections at a time"),
+        (MoveUp, "move_up", &["K", "Alt+Up"], &[Collections], "Move the collection up the list"),
+        (MoveDown, "move_down", &["J", "Alt+Down"], &[Collections], "Move the collection down the list"),
+        (CollectionOrder, "collection_order", &["o"], &[Collections], "Cycle the collection order: manual, alphabetical, recently used"),
+        (Estimate, "estimate", &["B"], &[Collections, Items], "Estimate the size of a collection download"),
+        (Download, "download", &["d"], &[Collections, Items, Item], "Download the selection (collection, item or marked items, file)"),
+        (DownloadAll, "download_all", &["b"], &[Collections, Items, Item], "Download the whole collection or item"),
+        (Mark, "mark", &["Space"], &[Items], "Mark or unmark the item for a batch download"),
+        (MarkAll, "mark_all", &["*"], &[Items], "Mark every listed item"),
+        (ClearMarks, "clear_marks", &["-"], &[Items], "Clear the marks"),
+        (SaveSearch, "save_search", &["+"], &[Items], "Save the search to the collections pane, to download and sync its results like a collection"),
+        (Export, "export", &["e"], &[Items, Item], "Export metadata to CSV or JSON"),
+        (Open, "open", &["o"], &[Items, Item], "Open the downloaded item folder or file"),
+        (OpenPage, "open_page", &["O"], &[Items, Item], "Open the item's archive.org page in the browser"),
+        (Play, "play", &["P"], &[Item], "Stream the selected file in the media player"),
+        (Preview, "preview", &["Space"], &[Item], "Play the selected MP3 or FLAC file here, or pause and resume it"),
+        (StopPreview, "stop_preview", &["S"], &[Global], "Stop the audio preview"),
+        (ToggleOffline, "offline", &["N"], &[Global], "Switch offline mode: browse the cached collections and items without the network"),
+        (Copy, "copy", &["y"], &[Items, Item], "Copy the identifier, or the selected file's URL in the item view"),
+        (CopyUrl, "copy_url", &["Y"], &[Items, Item], "Copy the URL of the item's archive.org page"),
+        (Filter, "filter", &["f"], &[Items], "Find in the listed items"),
+        (StatusFilter, "status_filter", &["v"], &[Items], "Cycle the download status filter"),
+        (MediatypeFilter, "mediatype_filter", &["m"], &[Items], "Cycle the mediatype filter"),
+        (FormatFilter, "format_filter", &["F"], &[Items], "Cycle the file format filter"),
+        (GroupDuplicates, "group_duplicates", &["z"], &[Items], "Group near-identical titles"),
+        (Exclude, "exclude", &["x"], &[Items], "Exclude the item from collection downloads, or include it again"),
+        (Verify, "verify", &[], &[Items, Item], "Verify the downloaded files of the item"),
+        (NextTab, "next_tab", &["Tab"], &[Item], "Switch between files, reviews and related items"),
+        (RefreshDetails, "refresh", &["r"], &[Item], "Fetch the item's details again instead of using the cache"),
+        (Pause, "pause", &["p"], &[Downloads], "Pause or resume the selected item"),
+        (PauseAll, "pause_all", &["P"], &[Downloads], "Pause or resume every download"),
+        (Cancel, "cancel", &["c"], &[Downloads], "Cancel the selected item"),
+        (CancelAll, "cancel_all", &["C"], &[Downloads], "Cancel every download"),
+        (ToggleLog, "log", &["!"], &[Global], "Show or close the recent log lines"),
+        (LogLevel, "log_level", &["v"], &[Log], "Cycle the levels shown: everything, warnings, errors"),
+        (ToggleFailures, "failures", &["E"], &[Browsing, Failures], "Show or close the failures of the last download run"),
+        (RetryFailed, "retry_failed", &["r"], &[Failures], "Download the failed items again"),
+    ]
+};
+
+fn entry(action: Action) -> &'static ActionEntry {
+    ACTIONS.iter().find(|(a, ..)| *a == action).expect("every action is listed")
+}
+
+impl Action {
+    /// Every action, in help screen order.
+    pub fn all() -> impl Iterator<Item = Action> {
+        ACTIONS.iter().map(|(action, ..)| *action)
+    }
+
+    /// Name of the action in the `[keys]` section of settings.toml.
+    pub fn name(self) -> &'static str {
+        entry(self).1
+    }
+
+    pub fn description(self) -> &'static str {
+        entry(self).4
+    }
+
+    pub fn contexts(self) -> &'static [KeyContext] {
+        entry(self).3
+    }
+}
+
+/// A key with its modifiers, e.g. `Ctrl+d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Parses `q`, `Q`, `?`, `Space`, `Tab`, `Enter`, `Delete`, `F5`, `Ctrl+d`, `Alt+Left`...
+    /// (modifier and key names are case-insensitive; single characters aren't).
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text.trim();
+        // A lone "+" is a key, so only split while something follows the separator
+        while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                _ => return None,
+            };
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "tab" => KeyCode::Tab,
+                "enter" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                name => KeyCode::F(name.strip_prefix('f')?.parse().ok().filter(|n| (1..=24).contains(n))?),
+            },
+        };
+        Some(Self { code, modifiers })
+    }
+
+    /// Whether the key moves through lists, opens or closes, so it can't be bound.
+    pub fn is_reserved(&self) -> bool {
+        self.modifiers.is_empty() && matches!(self.code, KeyCode::Enter | KeyCode::Esc | KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right)
+    }
+
+    /// Whether `event` is this key. Shift is ignored, since it is part of the character
+    /// ('R' arrives as Shift+R).
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        event.code == self.code && event.modifiers.difference(KeyModifiers::SHIFT) == self.modifiers
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if self.modifiers.is_empty() => write!(f, "'{}'", c),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Delete => write!(f, "Del"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Reads the keys of one `[keys]` entry, given as a string or a list of strings.
+pub fn deserialize_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Keys {
+        One(String),
+        Several(Vec<String>),
+    }
+    let keys = BTreeMap::<String, Keys>::deserialize(deserializer)?;
+    Ok(keys
+        .into_iter()
+        .map(|(action, keys)| match keys {
+            Keys::One(key) => (action, vec![key]),
+            Keys::Several(keys) => (action, keys),
+        })
+        .collect())
+}
+
+/// The active key of every remappable action: the defaults, with the actions named in
+/// the `[keys]` section of settings.toml rebound.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<KeyBinding>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_settings(&Settings::default())
+    }
+}
+
+impl Keymap {
+    /// Builds the keymap from the `[keys]` section (action name -> keys). Unknown actions
+    /// and keys are logged and ignored. A configured key takes over from an action that
+    /// only has it by default; two configured actions that can't share a key keep the first.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let keys = &settings.keys;
+        for name in keys.keys().filter(|name| !ACTIONS.iter().any(|(_, n, ..)| n == name)) {
+            warn!("Ignoring [keys] entry '{}': no such action", name);
+        }
+        let mut bindings: Vec<(Action, Vec<KeyBinding>, bool)> = ACTIONS
+            .iter()
+            .map(|(action, name, defaults, ..)| match keys.get(*name) {
+                Some(configured) => {
+                    let parsed = configured
+                        .iter()
+                        .filter_map(|key| match KeyBinding::parse(key) {
+                            Some(binding) if binding.is_reserved() => {
+                                warn!("Ignoring key '{}' for '{}': it is used for navigation", key, name);
+                                None
+                            }
+                            Some(binding) => Some(binding),
+                            None => {
+                                warn!("Ignoring key '{}' for '{}': not a key name", key, name);
+                                None
+                            }
+                        })
+                        .collect();
+                    (*action, parsed, true)
+                }
+                None => (*action, defaults.iter().filter_map(|key| KeyBinding::parse(key)).collect(), false),
+            })
+            .collect();
+
+        // Configured actions first, so they win conflicts against defaults
+        let mut order: Vec<usize> = (0..bindings.len()).collect();
+        order.sort_by_key(|i| !bindings[*i].2);
+        let mut taken: Vec<(KeyBinding, Action)> = Vec::new();
+        for i in order {
+            let (action, keys, configured) = &mut bindings[i];
+            keys.retain(|key| {
+                let clash = taken.iter().find(|(other_key, other)| {
+                    other_key == key && action.contexts().iter().any(|c| other.contexts().iter().any(|o| c.overlaps(*o)))
+                });
+                match clash {
+                    Some((_, other)) if *configured => {
+                        warn!("Key {} of '{}' is already bound to '{}'; ignoring it", key, action.name(), other.name());
+                        false
+                    }
+                    Some((_, other)) => {
+                        info!("Key {} now belongs to '{}' instead of '{}'", key, other.name(), action.name());
+                        false
+                    }
+                    None => true,
+                }
+            });
+            taken.extend(keys.iter().map(|key| (*key, *action)));
+        }
+        Self { bindings: bindings.into_iter().map(|(action, keys, _)| (action, keys)).collect() }
+    }
+
+    /// The action `event` triggers in `context`.
+    pub fn action(&self, context: KeyContext, event: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(action, keys)| action.contexts().contains(&context) && keys.iter().any(|key| key.matches(event)))
+            .map(|(action, _)| *action)
+    }
+
+    /// Keys bound to `action`.
+    pub fn keys(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.iter().find(|(a, _)| *a == action).map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    /// Key of `action` for hints, e.g. `'d'`; "unbound" if it has none.
+    pub fn hint(&self, action: Action) -> String {
+        self.keys(action).first().map_or("unbound".to_string(), ToString::to_string)
+    }
+
+    /// The help screen's rows: each context with its actions and their keys.
+    pub fn help(&self) -> Vec<(KeyContext, Vec<(String, &'static str)>)> {
+        CONTEXTS
+            .iter()
+            .map(|context| {
+                let rows = self
+                    .bindings
+                    .iter()
+                    .filter(|(action, _)| action.contexts().first() == Some(context))
+                    .map(|(action, keys)| {
+                        let keys: Vec<String> = keys.iter().map(ToString::to_string).collect();
+                        (if keys.is_empty() { "unbound".to_string() } else { keys.join(", ") }, action.description())
+                    })
+                    .collect();
+                (*context, rows)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_parse_and_display_keys() {
+        let cases = [("q", "'q'"), ("Space", "Space"), ("ctrl+d", "Ctrl+d"), ("Alt+Left", "Alt+Left"), ("del", "Del"), ("F5", "F5"), ("+", "'+'")];
+        for (text, shown) in cases {
+            assert_eq!(KeyBinding::parse(text).map(|k| k.to_string()).as_deref(), Some(shown), "{}", text);
+        }
+        assert_eq!(KeyBinding::parse("Hyper+x"), None);
+        assert_eq!(KeyBinding::parse("F99"), None);
+        assert!(KeyBinding::parse("R").unwrap().matches(&key(KeyCode::Char('R'), KeyModifiers::SHIFT)));
+        assert!(!KeyBinding::parse("d").unwrap().matches(&key(KeyCode::Char('d'), KeyModifiers::CONTROL)));
+    }
+
+    #[test]
+    fn test_configured_keys_replace_defaults() {
+        let keys = BTreeMap::from([
+            ("download".to_string(), vec!["Enter".to_string(), "x".to_string(), "Ctrl+d".to_string()]),
+            ("quit".to_string(), vec!["Ctrl+q".to_string()]),
+            ("not_an_action".to_string(), vec!["z".to_string()]),
+        ]);
+        let keymap = Keymap::from_settings(&Settings { keys, ..Settings::default() });
+        let plain = |c| key(KeyCode::Char(c), KeyModifiers::NONE);
+        assert_eq!(keymap.action(KeyContext::Items, &plain('d')), None, "Default replaced");
+        assert_eq!(keymap.action(KeyContext::Items, &plain('x')), Some(Action::Download));
+        assert_eq!(keymap.keys(Action::Exclude), &[], "'x' was taken by a configured action");
+        assert_eq!(keymap.action(KeyContext::Global, &plain('q')), None);
+        assert_eq!(keymap.action(KeyContext::Global, &key(KeyCode::Char('q'), KeyModifiers::CONTROL)), Some(Action::Quit));
+        // The same key does different things in separate contexts
+        assert_eq!(keymap.action(KeyContext::Downloads, &plain('p')), Some(Action::Pause));
+        assert_eq!(keymap.action(KeyContext::Browsing, &plain('p')), Some(Action::CyclePreset));
+        assert_eq!(keymap.hint(Action::Download), "'x'", "Enter is reserved for navigation");
+        assert_eq!(keymap.action(KeyContext::Item, &key(KeyCode::Char('d'), KeyModifiers::CONTROL)), Some(Action::Download));
+        let help = keymap.help();
+        assert_eq!(help.len(), CONTEXTS.len());
+        assert!(help[0].1.contains(&("Ctrl+q".to_string(), "Quit")));
+    }
+
+    #[test]
+    fn test_default_keys_do_not_clash() {
+        let keymap = Keymap::default();
+        for (action, _, defaults, ..) in ACTIONS {
+            assert_eq!(keymap.keys(action).len(), defaults.len(), "'{}' lost a default key", action.name());
+        }
+    }
+}
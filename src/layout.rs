@@ -0,0 +1,117 @@
+use crate::archive_api::ItemDetails;
+use crate::paths::{self, PathPolicy};
+use crate::saved_search;
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// Placeholder for the file's own path inside the item; every template ends with it.
+pub const FILENAME: &str = "{filename}";
+/// Placeholders filled from the item's metadata.
+const FIELDS: [&str; 7] = ["identifier", "title", "creator", "year", "date", "collection", "mediatype"];
+
+/// Layouts offered in the TUI, named after what they suit. Both follow the folder names
+/// Jellyfin and Plex expect (artist/album for music, `Title (Year)` for films).
+pub const PRESETS: [(&str, &str); 2] = [
+    ("Music", "{creator}/{year} - {title}/{filename}"),
+    ("Movies", "{title} ({year})/{filename}"),
+];
+
+/// Name of the preset `template` is, if it is one.
+pub fn preset_name(template: &str) -> Option<&'static str> {
+    PRESETS.iter().find(|(_, preset)| *preset == template).map(|(name, _)| *name)
+}
+
+/// Layout after `current` in the TUI's cycle: default layout -> each preset -> default.
+/// A custom template set in settings.toml moves on to the default layout.
+pub fn cycle(current: Option<&str>) -> Option<&'static str> {
+    match current.and_then(|t| PRESETS.iter().position(|(_, preset)| *preset == t)) {
+        None if current.is_none() => Some(PRESETS[0].1),
+        Some(i) if i + 1 < PRESETS.len() => Some(PRESETS[i + 1].1),
+        _ => None,
+    }
+}
+
+/// Checks that `template` ends with `/{filename}` and only uses known placeholders.
+pub fn validate(template: &str) -> Result<()> {
+    let Some(dir) = template.strip_suffix(FILENAME).and_then(|t| t.strip_suffix('/')) else {
+        bail!("Layout '{}' must end with /{}", template, FILENAME);
+    };
+    let mut rest = dir;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else { bail!("Unclosed '{{' in layout '{}'", template) };
+        let name = &rest[start + 1..start + len];
+        if !FIELDS.contains(&name) {
+            bail!("Unknown placeholder {{{}}} in layout '{}' (known: {})", name, template, FIELDS.join(", "));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(())
+}
+
+/// Value of a placeholder for `details`; "Unknown" when the metadata lacks it.
+fn field(name: &str, details: &ItemDetails, collection_id: Option<&str>) -> String {
+    let value = match name {
+        "identifier" => Some(details.identifier.clone()),
+        "title" => details.title.clone().or_else(|| Some(details.identifier.clone())),
+        "creator" => details.creator.clone(),
+        "year" => details.date.as_deref().map(|d| d.chars().take(4).collect::<String>()).filter(|y| y.len() == 4 && y.chars().all(|c| c.is_ascii_digit())),
+        "date" => details.date.clone(),
+        "collection" => collection_id.map(|c| saved_search::local_name(c).into_owned()).or_else(|| details.collections.first().cloned()),
+        "mediatype" => details.mediatype.clone(),
+        _ => None,
+    };
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// The folder `template` puts the files of `details` in, below `base_dir`. Each level is
+/// sanitized like any other path component, so metadata can't escape the download
+/// directory.
+pub fn item_dir(template: &str, base_dir: &str, details: &ItemDetails, collection_id: Option<&str>, policy: &PathPolicy) -> Result<PathBuf> {
+    validate(template)?;
+    let dir = &template[..template.len() - FILENAME.len() - 1];
+    let mut path = paths::collection_dir(base_dir, None, policy);
+    for segment in dir.split('/').filter(|s| !s.is_empty()) {
+        let rendered = FIELDS.iter().fold(segment.to_string(), |text, name| {
+            let placeholder = format!("{{{}}}", name);
+            if text.contains(&placeholder) { text.replace(&placeholder, &field(name, details, collection_id)) } else { text }
+        });
+        path.push(paths::sanitize_component(rendered.trim(), policy));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_render_media_server_folders() {
+        let details = ItemDetails {
+            identifier: "gd1977-05-08".to_string(),
+            title: Some("Live at Barton Hall".to_string()),
+            creator: Some("Grateful Dead".to_string()),
+            date: Some("1977-05-08".to_string()),
+            ..Default::default()
+        };
+        let policy = PathPolicy { ntfs_safe: true, max_path_len: None };
+        let music = item_dir(PRESETS[0].1, "/srv", &details, Some("etree"), &policy).unwrap();
+        assert_eq!(music, PathBuf::from("/srv/Grateful Dead/1977 - Live at Barton Hall"));
+        let movie = ItemDetails { identifier: "night_of_the_living_dead".to_string(), title: Some("Night: Of the Living Dead".to_string()), ..Default::default() };
+        assert_eq!(item_dir(PRESETS[1].1, "/srv", &movie, None, &policy).unwrap(), PathBuf::from("/srv/Night_ Of the Living Dead (Unknown)"));
+        let sneaky = ItemDetails { identifier: "x".to_string(), creator: Some("..".to_string()), ..Default::default() };
+        assert_eq!(item_dir("{creator}/{filename}", "/srv", &sneaky, None, &policy).unwrap(), PathBuf::from("/srv/_"));
+    }
+
+    #[test]
+    fn test_validate_and_cycle() {
+        assert!(validate("{collection}/{identifier}/{filename}").is_ok());
+        assert!(validate("{creator}/{title}").is_err(), "Must end with {{filename}}");
+        assert!(validate("{artist}/{filename}").is_err(), "Unknown placeholder");
+        assert!(validate("{title/{filename}").is_err());
+        assert_eq!(cycle(None), Some(PRESETS[0].1));
+        assert_eq!(cycle(Some(PRESETS[0].1)), Some(PRESETS[1].1));
+        assert_eq!(cycle(Some(PRESETS[1].1)), None);
+        assert_eq!(cycle(Some("{identifier}/{filename}")), None);
+        assert_eq!(preset_name(PRESETS[1].1), Some("Movies"));
+    }
+}
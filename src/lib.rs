@@ -1,8 +1,80 @@
 /// Application modules
+pub mod accessibility;
 pub mod app;
 pub mod archive_api;
+pub mod auth;
+pub mod claims;
+pub mod cli;
+pub mod clipboard;
+pub mod collection_status;
+pub mod control;
+pub mod dedupe;
+pub mod dns;
+pub mod download;
+pub mod estimate;
 pub mod event;
+pub mod exclusions;
+pub mod export;
+pub mod failures;
+pub mod favorites;
+pub mod feed;
+pub mod fetch_filter;
+pub mod file_history;
+pub mod filters;
+pub mod forecast;
+pub mod fs_util;
+pub mod grouping;
+pub mod headless;
+pub mod history;
+pub mod ipfs;
+pub mod ia_upload;
+pub mod keymap;
+pub mod layout;
+pub mod library;
+pub mod list_nav;
+pub mod log_buffer;
+#[cfg(feature = "mock")]
+pub mod mock_server;
+pub mod musicbrainz;
+pub mod offline;
+pub mod opener;
+pub mod palette;
+pub mod paths;
+pub mod perf;
+pub mod playlist;
+pub mod politeness;
+pub mod portable;
+pub mod presets;
+pub mod preview;
+pub mod progress;
+pub mod queue;
+pub mod rate_control;
+pub mod rate_limit;
+pub mod replicate;
+pub mod report;
+pub mod retry;
+pub mod saved_search;
+pub mod scheduler;
+pub mod secrets;
+pub mod seed;
+pub mod segments;
+pub mod session;
 pub mod settings;
+pub mod sidecar;
+pub mod site;
+pub mod storage;
+pub mod sync;
+pub mod tagging;
+pub mod theme;
+pub mod throttle;
+pub mod thumbnail;
+pub mod torrent;
+pub mod torrent_backend;
+pub mod transfers;
+pub mod transport;
 pub mod tui;
 pub mod ui;
 pub mod update;
+pub mod updater;
+pub mod verify;
+pub mod wayback;
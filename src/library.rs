@@ -0,0 +1,241 @@
+use crate::fs_util;
+use anyhow::{Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// File (inside the download directory) recording the outcome of item downloads.
+pub const LIBRARY_FILE: &str = ".archiver-library.json";
+/// Minimum interval between index writes while a download is running.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Outcome of the last download of an item.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemStatus {
+    /// All files were downloaded (or skipped as already complete).
+    Downloaded,
+    /// At least one file failed.
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct LibraryEntry {
+    status: ItemStatus,
+    /// RFC 3339 time of the last download attempt.
+    updated: String,
+    /// CID of the item directory, once added to IPFS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cid: Option<String>,
+    /// Folder of the item when a library layout placed it outside `[collection]/item`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dir: Option<PathBuf>,
+    /// MusicBrainz release (MBID) the item matched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    musicbrainz_release: Option<String>,
+}
+
+/// Which items the items pane shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ItemStatusFilter {
+    #[default]
+    All,
+    NotDownloaded,
+    Downloaded,
+    Failed,
+}
+
+impl fmt::Display for ItemStatusFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemStatusFilter::All => write!(f, "All"),
+            ItemStatusFilter::NotDownloaded => write!(f, "Not downloaded"),
+            ItemStatusFilter::Downloaded => write!(f, "Downloaded"),
+            ItemStatusFilter::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+impl ItemStatusFilter {
+    /// Next filter in All -> Not downloaded -> Downloaded -> Failed order.
+    pub fn cycle(self) -> Self {
+        match self {
+            ItemStatusFilter::All => ItemStatusFilter::NotDownloaded,
+            ItemStatusFilter::NotDownloaded => ItemStatusFilter::Downloaded,
+            ItemStatusFilter::Downloaded => ItemStatusFilter::Failed,
+            ItemStatusFilter::Failed => ItemStatusFilter::All,
+        }
+    }
+
+    /// Whether an item with `status` passes the filter (`None` = never downloaded).
+    pub fn matches(self, status: Option<ItemStatus>) -> bool {
+        match self {
+            ItemStatusFilter::All => true,
+            ItemStatusFilter::NotDownloaded => status.is_none(),
+            ItemStatusFilter::Downloaded => status == Some(ItemStatus::Downloaded),
+            ItemStatusFilter::Failed => status == Some(ItemStatus::Failed),
+        }
+    }
+}
+
+/// Download status of the items in one download directory.
+///
+/// Identifiers are unique across archive.org, so items are keyed by identifier alone
+/// regardless of the collection they were downloaded through.
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    /// Download directory the index belongs to (`None` until one is set).
+    base_dir: Option<PathBuf>,
+    items: HashMap<String, LibraryEntry>,
+    dirty: bool,
+    last_save: Option<Instant>,
+}
+
+impl LibraryIndex {
+    /// Loads the index of `base_dir`. A missing file is an empty index; a corrupt one is
+    /// backed up and replaced.
+    pub fn load(base_dir: &Path) -> Self {
+        let path = base_dir.join(LIBRARY_FILE);
+        let items = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Library index {} is corrupt ({}); starting fresh", path.display(), e);
+                if let Err(backup_err) = fs_util::backup_corrupt_file(&path) {
+                    error!("{:#}", backup_err);
+                }
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(), // Nothing downloaded into this directory yet
+        };
+        Self { base_dir: Some(base_dir.to_path_buf()), items, dirty: false, last_save: None }
+    }
+
+    /// Download directory this index belongs to.
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.base_dir.as_deref()
+    }
+
+    pub fn status(&self, identifier: &str) -> Option<ItemStatus> {
+        self.items.get(identifier).map(|entry| entry.status)
+    }
+
+    /// Identifiers of the items downloaded completely, sorted.
+    pub fn downloaded(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.items.iter().filter(|(_, entry)| entry.status == ItemStatus::Downloaded).map(|(id, _)| id.as_str()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Records the outcome of an item download; written by [`LibraryIndex::save_if_due`]
+    /// or [`LibraryIndex::flush`].
+    pub fn record(&mut self, identifier: &str, status: ItemStatus) {
+        let entry = self.entry_mut(identifier);
+        entry.status = status;
+        entry.updated = chrono::Local::now().to_rfc3339();
+    }
+
+    /// Records the IPFS CID of a downloaded item's directory.
+    pub fn record_cid(&mut self, identifier: &str, cid: &str) {
+        self.entry_mut(identifier).cid = Some(cid.to_string());
+    }
+
+    /// Records the folder a library layout put the item in.
+    pub fn record_dir(&mut self, identifier: &str, dir: &Path) {
+        self.entry_mut(identifier).dir = Some(dir.to_path_buf());
+    }
+
+    /// Folder of the item, if a library layout placed it.
+    pub fn dir(&self, identifier: &str) -> Option<&Path> {
+        self.items.get(identifier).and_then(|entry| entry.dir.as_deref())
+    }
+
+    /// Records the MusicBrainz release a downloaded item matched.
+    pub fn record_musicbrainz_release(&mut self, identifier: &str, release_id: &str) {
+        self.entry_mut(identifier).musicbrainz_release = Some(release_id.to_string());
+    }
+
+    /// MusicBrainz release of the item, if a lookup matched one.
+    pub fn musicbrainz_release(&self, identifier: &str) -> Option<&str> {
+        self.items.get(identifier).and_then(|entry| entry.musicbrainz_release.as_deref())
+    }
+
+    /// The item's entry, created as downloaded (the download's outcome follows right after).
+    fn entry_mut(&mut self, identifier: &str) -> &mut LibraryEntry {
+        self.dirty = true;
+        self.items.entry(identifier.to_string()).or_insert_with(|| LibraryEntry {
+            status: ItemStatus::Downloaded,
+            updated: chrono::Local::now().to_rfc3339(),
+            cid: None,
+            dir: None,
+            musicbrainz_release: None,
+        })
+    }
+
+    /// Identifiers of the items added to IPFS, with their CIDs.
+    pub fn cids(&self) -> BTreeMap<String, String> {
+        self.items.iter().filter_map(|(id, entry)| entry.cid.clone().map(|cid| (id.clone(), cid))).collect()
+    }
+
+    /// Writes pending changes if the last write was a while ago (called every tick).
+    pub fn save_if_due(&mut self) {
+        if self.dirty && self.last_save.is_none_or(|t| t.elapsed() >= SAVE_INTERVAL) {
+            self.flush();
+        }
+    }
+
+    /// Writes pending changes now.
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        match self.save() {
+            Ok(()) => self.dirty = false,
+            Err(e) => error!("{:#}", e),
+        }
+        self.last_save = Some(Instant::now());
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(base_dir) = &self.base_dir else { return Ok(()) };
+        fs::create_dir_all(base_dir).context(format!("Failed to create download directory {}", base_dir.display()))?;
+        let path = base_dir.join(LIBRARY_FILE);
+        let json = serde_json::to_string(&self.items).context("Failed to serialize library index")?;
+        fs_util::atomic_write(&path, json).context(format!("Failed to write library index {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statuses_persist_and_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = LibraryIndex::load(dir.path());
+        index.record("good", ItemStatus::Downloaded);
+        index.record("bad", ItemStatus::Failed);
+        index.record_dir("good", Path::new("/srv/Artist/1977 - Album"));
+        index.record_musicbrainz_release("good", "release-mbid");
+        index.record("good", ItemStatus::Downloaded);
+        index.flush();
+
+        let reloaded = LibraryIndex::load(dir.path());
+        assert_eq!(reloaded.status("good"), Some(ItemStatus::Downloaded));
+        assert_eq!(reloaded.status("bad"), Some(ItemStatus::Failed));
+        assert_eq!(reloaded.status("new"), None);
+        assert_eq!(reloaded.dir("good"), Some(Path::new("/srv/Artist/1977 - Album")), "Kept across records");
+        assert_eq!(reloaded.dir("bad"), None);
+        assert_eq!(reloaded.musicbrainz_release("good"), Some("release-mbid"));
+
+        let shown = |filter: ItemStatusFilter| -> Vec<&str> {
+            ["good", "bad", "new"].into_iter().filter(|id| filter.matches(reloaded.status(id))).collect()
+        };
+        assert_eq!(shown(ItemStatusFilter::All), vec!["good", "bad", "new"]);
+        assert_eq!(shown(ItemStatusFilter::NotDownloaded), vec!["new"]);
+        assert_eq!(shown(ItemStatusFilter::Downloaded), vec!["good"]);
+        assert_eq!(shown(ItemStatusFilter::Failed), vec!["bad"]);
+    }
+}
@@ -0,0 +1,195 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::ops::Range;
+
+/// A move in a list, from the arrow keys or their vim equivalents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Down(usize),
+    Up(usize),
+    /// The first row (`gg`, Home).
+    First,
+    /// The last row (`G`, End).
+    Last,
+    /// A row by index (`5G`, `5gg`).
+    Row(usize),
+    /// Pages down (PgDn, `3` PgDn); a page is the rows the list shows at once.
+    PageDown(usize),
+    PageUp(usize),
+    /// Left or `h`; what it does depends on the list.
+    Left,
+    /// Right or `l`.
+    Right,
+}
+
+impl Motion {
+    /// Selection in a list of `len` rows, `page` of them in view, after the move. Single
+    /// steps wrap around at the ends like the arrow keys always have; counted ones (`25j`)
+    /// and pages stop there. With nothing selected, a step selects the first row. Left and
+    /// Right keep the selection.
+    pub fn apply(self, selected: Option<usize>, len: usize, page: usize) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        let last = len - 1;
+        let Some(i) = selected else {
+            return match self {
+                Motion::Last => Some(last),
+                Motion::Row(row) => Some(row.min(last)),
+                Motion::Left | Motion::Right => None,
+                _ => Some(0),
+            };
+        };
+        Some(match self {
+            Motion::Down(1) if i >= last => 0,
+            Motion::Up(1) if i == 0 => last,
+            Motion::Down(n) => (i + n).min(last),
+            Motion::Up(n) => i.saturating_sub(n),
+            Motion::PageDown(n) => i.saturating_add(n.saturating_mul(page.max(1))).min(last),
+            Motion::PageUp(n) => i.saturating_sub(n.saturating_mul(page.max(1))),
+            Motion::First => 0,
+            Motion::Last => last,
+            Motion::Row(row) => row.min(last),
+            Motion::Left | Motion::Right => i.min(last),
+        })
+    }
+}
+
+/// Turns keys into [`Motion`]s, remembering a count (`25`) or `g` typed before the key
+/// that completes the move.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListNav {
+    count: Option<usize>,
+    g: bool,
+    /// Whether the current key extended the prefix (see [`ListNav::end_key`]).
+    extended: bool,
+}
+
+impl ListNav {
+    /// The move `key` completes, if any. Digits and a first `g` are kept as a prefix.
+    pub fn motion(&mut self, key: &KeyEvent) -> Option<Motion> {
+        if !(key.modifiers - KeyModifiers::SHIFT).is_empty() {
+            return None;
+        }
+        let count = self.count.unwrap_or(1);
+        let motion = match key.code {
+            KeyCode::Char(digit @ '0'..='9') if digit != '0' || self.count.is_some() => {
+                let value = self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize - '0' as usize);
+                self.count = Some(value);
+                self.extended = true;
+                return None;
+            }
+            KeyCode::Char('g') if !self.g => {
+                self.g = true;
+                self.extended = true;
+                return None;
+            }
+            KeyCode::Char('g') | KeyCode::Char('G') => match self.count {
+                Some(line) => Motion::Row(line - 1),
+                None if key.code == KeyCode::Char('G') => Motion::Last,
+                None => Motion::First,
+            },
+            KeyCode::Down | KeyCode::Char('j') => Motion::Down(count),
+            KeyCode::Up | KeyCode::Char('k') => Motion::Up(count),
+            KeyCode::Left | KeyCode::Char('h') => Motion::Left,
+            KeyCode::Right | KeyCode::Char('l') => Motion::Right,
+            KeyCode::Home => Motion::First,
+            KeyCode::End => Motion::Last,
+            KeyCode::PageDown => Motion::PageDown(count),
+            KeyCode::PageUp => Motion::PageUp(count),
+            _ => return None,
+        };
+        *self = Self::default();
+        Some(motion)
+    }
+
+    /// Called once a key has been handled: drops a prefix the key didn't extend, so `5`
+    /// followed by any other key doesn't linger.
+    pub fn end_key(&mut self) {
+        if !std::mem::take(&mut self.extended) {
+            *self = Self::default();
+        }
+    }
+}
+
+/// Rows each list showed when it was last drawn, which is how far a page moves.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageRows {
+    pub collections: usize,
+    pub items: usize,
+    /// The file and related lists of the item view.
+    pub item_view: usize,
+    pub log: usize,
+    pub failures: usize,
+}
+
+/// Rows of a `len`-row list to draw in `height` lines: those from `offset` on, scrolled just
+/// enough to show `selected`. Only these need to be built, however long the list is.
+pub fn window(offset: usize, selected: Option<usize>, height: usize, len: usize) -> Range<usize> {
+    let height = height.max(1);
+    let mut start = offset.min(len.saturating_sub(height));
+    if let Some(selected) = selected.filter(|&i| i < len) {
+        if selected < start {
+            start = selected;
+        } else if selected >= start + height {
+            start = selected + 1 - height;
+        }
+    }
+    start..(start + height).min(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(nav: &mut ListNav, text: &str) -> Option<Motion> {
+        let mut motion = None;
+        for c in text.chars() {
+            motion = nav.motion(&KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            nav.end_key();
+        }
+        motion
+    }
+
+    #[test]
+    fn test_counts_and_jumps() {
+        let mut nav = ListNav::default();
+        assert_eq!(keys(&mut nav, "j"), Some(Motion::Down(1)));
+        assert_eq!(keys(&mut nav, "25j"), Some(Motion::Down(25)));
+        assert_eq!(keys(&mut nav, "3k"), Some(Motion::Up(3)));
+        assert_eq!(keys(&mut nav, "gg"), Some(Motion::First));
+        assert_eq!(keys(&mut nav, "G"), Some(Motion::Last));
+        assert_eq!(keys(&mut nav, "10G"), Some(Motion::Row(9)));
+        assert_eq!(keys(&mut nav, "0"), None, "0 doesn't start a count");
+        assert_eq!(keys(&mut nav, "j"), Some(Motion::Down(1)));
+
+        // A prefix only applies to the very next key
+        assert_eq!(keys(&mut nav, "5"), None);
+        nav.end_key();
+        assert_eq!(keys(&mut nav, "j"), Some(Motion::Down(1)));
+        assert_eq!(nav.motion(&KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL)), None);
+
+        assert_eq!(Motion::Down(1).apply(Some(4), 5, 3), Some(0), "Single steps wrap");
+        assert_eq!(Motion::Up(1).apply(Some(0), 5, 3), Some(4));
+        assert_eq!(Motion::Down(25).apply(Some(1), 5, 3), Some(4), "Counts stop at the end");
+        assert_eq!(Motion::Up(25).apply(Some(3), 5, 3), Some(0));
+        assert_eq!(Motion::Row(9).apply(Some(0), 5, 3), Some(4));
+        assert_eq!(Motion::Down(1).apply(None, 5, 3), Some(0), "Nothing selected yet");
+        assert_eq!(Motion::Last.apply(None, 5, 3), Some(4));
+        assert_eq!(Motion::First.apply(Some(2), 0, 3), None);
+        assert_eq!(Motion::PageDown(1).apply(Some(0), 5, 3), Some(3));
+        assert_eq!(Motion::PageDown(2).apply(Some(0), 5, 3), Some(4), "Pages stop at the end");
+        assert_eq!(Motion::PageUp(1).apply(Some(4), 5, 3), Some(1));
+        assert_eq!(Motion::PageUp(1).apply(Some(1), 5, 0), Some(0), "A list not drawn yet pages by one row");
+    }
+
+    #[test]
+    fn test_window_follows_the_selection() {
+        assert_eq!(window(0, None, 10, 500_000), 0..10);
+        assert_eq!(window(0, Some(9), 10, 500_000), 0..10);
+        assert_eq!(window(0, Some(25), 10, 500_000), 16..26, "Scrolls just enough to show it");
+        assert_eq!(window(16, Some(20), 10, 500_000), 16..26, "Keeps its place while the selection is in view");
+        assert_eq!(window(16, Some(3), 10, 500_000), 3..13);
+        assert_eq!(window(490, Some(3), 10, 5), 0..5, "The list shrank");
+        assert_eq!(window(0, Some(0), 0, 5), 0..1);
+    }
+}
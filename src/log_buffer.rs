@@ -0,0 +1,132 @@
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Lines kept for the log view; older ones are only in the log file.
+pub const CAPACITY: usize = 2000;
+
+/// A logged message.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub message: String,
+}
+
+/// The most recent log lines, shared between the logger and the log view.
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+}
+
+impl LogBuffer {
+    pub fn push(&self, line: LogLine) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Lines at `level` or more severe, oldest first.
+    pub fn lines(&self, level: Level) -> Vec<LogLine> {
+        self.lines.lock().unwrap().iter().filter(|line| line.level <= level).cloned().collect()
+    }
+
+    /// How many lines are errors.
+    pub fn error_count(&self) -> usize {
+        self.lines.lock().unwrap().iter().filter(|line| line.level == Level::Error).count()
+    }
+}
+
+/// The next level the log view filters at: info, then warnings, then errors only.
+pub fn next_level(level: Level) -> Level {
+    match level {
+        Level::Error => Level::Info,
+        Level::Warn => Level::Error,
+        _ => Level::Warn,
+    }
+}
+
+/// What the log view shows at `level`.
+pub fn level_label(level: Level) -> &'static str {
+    match level {
+        Level::Error => "errors",
+        Level::Warn => "warnings and errors",
+        _ => "everything",
+    }
+}
+
+/// Logs into a [`LogBuffer`], next to the log file (see `simplelog::CombinedLogger`).
+pub struct BufferLogger {
+    level: LevelFilter,
+    config: Config,
+    buffer: LogBuffer,
+}
+
+impl BufferLogger {
+    pub fn new(level: LevelFilter, buffer: LogBuffer) -> Box<Self> {
+        Box::new(Self { level, config: Config::default(), buffer })
+    }
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.buffer.push(LogLine { time: Local::now(), level: record.level(), message: record.args().to_string() });
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for BufferLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        Some(&self.config)
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(level: Level, message: &str) -> LogLine {
+        LogLine { time: Local::now(), level, message: message.to_string() }
+    }
+
+    #[test]
+    fn test_buffer_filters_by_level_and_drops_the_oldest() {
+        let buffer = LogBuffer::default();
+        buffer.push(line(Level::Info, "Listing gd1977"));
+        buffer.push(line(Level::Warn, "Retrying d1t01.flac"));
+        buffer.push(line(Level::Error, "Failed to download d1t02.flac"));
+        assert_eq!(buffer.lines(Level::Info).len(), 3);
+        let warnings: Vec<_> = buffer.lines(Level::Warn).into_iter().map(|line| line.message).collect();
+        assert_eq!(warnings, ["Retrying d1t01.flac", "Failed to download d1t02.flac"]);
+        assert_eq!(buffer.error_count(), 1);
+
+        for i in 0..CAPACITY {
+            buffer.push(line(Level::Info, &i.to_string()));
+        }
+        let lines = buffer.lines(Level::Info);
+        assert_eq!(lines.len(), CAPACITY);
+        assert_eq!(lines[0].message, "0", "The first lines were dropped");
+        assert_eq!(next_level(next_level(next_level(Level::Info))), Level::Info);
+    }
+}
@@ -1,36 +1,264 @@
 use anyhow::{anyhow, Context, Result};
-use log::{debug, error, info, warn}; // Import log macros (removed LevelFilter)
+use log::{error, info, warn}; // Import log macros (removed LevelFilter)
 use rust_tui_app::{
-    app::{App, AppRateLimiter, DownloadAction, DownloadProgress, UpdateAction}, // Import AppRateLimiter
-    archive_api::{self, ArchiveDoc, ItemDetails}, // Removed FetchAllResult
+    accessibility::Announcer,
+    app::{App, AppRateLimiter, AppState, DownloadProgress, ItemPage, UpdateAction}, // Import AppRateLimiter
+    archive_api::{self, ArchiveDoc, FetchDetailsErrorKind, ItemDetails}, // Removed FetchAllResult
+    auth::{self, LoginResult},
+    dedupe::{DedupeIndex, DedupeMode},
+    failures::ErrorKind,
+    file_history::FileHistory,
+    musicbrainz::MusicBrainz,
+    cli,
+    clipboard,
+    favorites,
+    fetch_filter,
+    log_buffer::{BufferLogger, LogBuffer},
+    offline::{self, NetworkMode},
+    opener,
+    paths::PathPolicy,
+    perf,
+    playlist,
+    politeness::HostPacer,
+    segments::SegmentPolicy,
+    preview::Preview,
+    queue::{self, DownloadQueue},
+    rate_limit::{self, SharedRateLimiter},
+    replicate::Replication,
+    retry::{self, RetryQueue},
+    scheduler::{DownloadScheduler, Priority, Ticket},
+    session::{self, DownloadJob, SessionJournal},
+    download::{offer_retry, run_download_action, DownloadContext},
+    estimate::{self, EstimateItems, EstimateUpdate},
     event::{Event, EventHandler},
-    settings::{self, DownloadMode},
+    headless,
+    ia_upload::{self, UploadProgress},
+    settings,
+    storage::Storage,
+    thumbnail::{self, Thumbnail},
+    torrent_backend,
     tui::Tui,
-    update::update,
+    update::{self, update, update_repeated},
+    updater::{self, ReleaseInfo},
+    verify::{self, FileCheck},
+    wayback::{self, WaybackProgress},
 }; // Removed extra closing brace
 use ratatui::{backend::CrosstermBackend, Terminal};
-// Use SystemClock here to match the AppRateLimiter definition
-use governor::{Quota, RateLimiter, clock::SystemClock}; // Removed unused NotKeyed
+use chrono::Utc;
+use governor::Quota;
 // Removed unused NoOpMiddleware import
 // Removed unused nonzero_ext import
-use reqwest::Client;
-use simplelog::{Config, WriteLogger, LevelFilter}; // Import necessary simplelog items
-use std::{fs::File, io, num::NonZeroU32, path::Path, sync::Arc, time::Instant}; // Add NonZeroU32, File, Path
+use simplelog::{CombinedLogger, Config, SharedLogger, WriteLogger, LevelFilter}; // Import necessary simplelog items
+use std::{fs::File, io, num::NonZeroU32, path::Path, sync::Arc, time::{Duration, Instant}}; // Add NonZeroU32, File, Path
 use tokio::sync::{mpsc, Semaphore};
-use tokio::time::Duration; // Import tokio Duration
+
+/// Interval between UI ticks (ms). Download progress is applied and rendered once per tick.
+const TICK_RATE_MS: u64 = 250;
+/// Capacity of the download progress channel (messages buffered between ticks).
+const DOWNLOAD_PROGRESS_CHANNEL_CAPACITY: usize = 8192;
+/// API requests per minute allowed in `--mock` mode (the local server has no rate limits).
+const MOCK_REQUESTS_PER_MINUTE: u32 = 600;
+/// API requests per minute allowed against the real archive.org.
+const LIVE_REQUESTS_PER_MINUTE: u32 = 15;
+/// Log file of headless commands, in the cache directory.
+const HEADLESS_LOG_FILE: &str = "headless.log";
+
+/// Long-lived handles shared by every download task.
+struct DownloadHandles {
+    progress_tx: mpsc::Sender<DownloadProgress>,
+    scheduler: Arc<DownloadScheduler>,
+    collection_item_semaphore: Arc<Semaphore>,
+    rate_limiter: AppRateLimiter,
+    host_pacer: Arc<HostPacer>,
+    /// Receives the session journal id of each job when its task ends.
+    finished_tx: mpsc::Sender<u64>,
+}
+
+/// Marks the app as downloading and resets the progress counters.
+fn reset_download_progress(app: &mut App) {
+    // Note: is_downloading is true if *any* download task is running.
+    app.is_downloading = true;
+    app.error_message = None;
+    app.items_downloaded_count = 0;
+    app.restricted_files_count = 0;
+    app.skipped_files_count = 0;
+    app.total_files_to_download = None; // Reset, will be updated by tasks
+    app.files_downloaded_count = 0;
+    app.total_bytes_downloaded = 0;
+    app.bytes_to_download = 0;
+    app.items_sized = 0;
+    app.download_start_time = Some(Instant::now());
+    app.total_items_to_download = None; // Reset, set by Collection task if needed
+}
+
+/// Records `job` in the session journal and runs it on a background task.
+fn spawn_download(app: &mut App, handles: &DownloadHandles, job: DownloadJob) {
+    let journal_id = app.session.record_start(job.clone());
+    let ticket = Ticket::new(journal_id, &job.action);
+    app.begin_report_job(job.describe(), &job.base_dir);
+    let dedupe = (app.settings.dedupe_mode != DedupeMode::Off).then(|| Arc::new(DedupeIndex::new(app.settings.dedupe_mode, &job.base_dir)));
+    let history = FileHistory::open(Path::new(&job.base_dir));
+    let ctx = DownloadContext {
+        client: app.client.clone(),
+        base_dir: job.base_dir,
+        progress_tx: handles.progress_tx.clone(),
+        scheduler: Arc::clone(&handles.scheduler),
+        ticket,
+        collection_item_semaphore: Arc::clone(&handles.collection_item_semaphore),
+        rate_limiter: Arc::clone(&handles.rate_limiter),
+        path_policy: PathPolicy::from_settings(&app.settings),
+        sidecar: app.settings.metadata_sidecar,
+        write_playlist: app.settings.write_playlists,
+        write_checksums: app.settings.write_checksums,
+        library_layouts: Arc::new(app.settings.library_layouts.clone()),
+        fetch_filters: Arc::new(app.settings.fetch_filters.clone()),
+        dedupe,
+        history,
+        force: app.force_downloads,
+        tag_audio: app.settings.tag_audio.then_some(app.settings.tag_policy),
+        musicbrainz: app.settings.musicbrainz_lookup.then(|| Arc::new(MusicBrainz::new(app.client.clone()))),
+        ipfs_api: app.settings.ipfs_export.then(|| app.settings.ipfs_api_url.clone()),
+        replication: Replication::from_settings(&app.settings),
+        storage: Storage::from_settings(&app.settings),
+        authenticated: app.credentials.is_some(),
+        host_pacer: Arc::clone(&handles.host_pacer),
+        exclusions: Arc::clone(&app.exclusions),
+        file_preset: job.preset,
+        source_policy: app.settings.file_source_policy,
+        subtitle_languages: app.settings.include_subtitles.then(|| Arc::new(app.settings.subtitle_languages.clone())),
+        file_filter: Arc::new(app.settings.download_file_filter.clone()),
+        torrent_backend: torrent_backend::from_settings(&app.settings, &app.client),
+        segments: SegmentPolicy::from_settings(&app.settings),
+        throttle: Arc::clone(&app.throttle),
+        control: Arc::clone(&app.download_control),
+        retry: job.retry,
+    };
+    let finished_tx = handles.finished_tx.clone();
+    tokio::spawn(async move {
+        let result = run_download_action(&ctx, job.action.clone(), job.mode, job.collection.as_deref()).await;
+        // Report top-level task errors (e.g., failed to get identifiers)
+        if let Err(e) = result {
+            offer_retry(&ctx, job.action, job.collection.as_deref(), job.mode, &format!("{:#}", e)).await;
+            let _ = ctx.progress_tx.send(DownloadProgress::Error(format!("Download Task Error: {}", e))).await;
+        }
+        // Note: is_downloading flag is reset when CollectionCompleted or Error is received
+        let _ = finished_tx.send(journal_id).await;
+    });
+}
+
+/// A favorite collection's listing (docs and total count) from a sync round.
+type SyncListing = (String, Result<(Vec<ArchiveDoc>, usize)>);
+
+/// Lists every favorite collection for a sync round; the listings come back on `sync_tx`.
+fn spawn_sync_round(app: &App, sync_tx: &mpsc::Sender<SyncListing>, rate_limiter: &AppRateLimiter) {
+    info!("Syncing {} favorite collection(s)", app.settings.favorite_collections.len());
+    for collection in app.settings.favorite_collections.clone() {
+        let client = app.client.clone();
+        let tx = sync_tx.clone();
+        let limiter_clone = Arc::clone(rate_limiter);
+        let filter = fetch_filter::for_collection(&app.settings.fetch_filters, &collection);
+        tokio::spawn(async move {
+            let result = match filter {
+                Ok(filter) => archive_api::fetch_collection_items_bulk(&client, &collection, filter.as_deref(), limiter_clone).await,
+                Err(e) => Err(e),
+            };
+            let _ = tx.send((collection, result)).await;
+        });
+    }
+}
+
+/// Loads the cover art of the item being viewed (see [`thumbnail::load`]); it comes back on
+/// `thumbnail_tx`. Nothing is fetched when the terminal can't draw it.
+fn fetch_thumbnail(app: &App, rate_limiter: &AppRateLimiter, thumbnail_tx: &mpsc::Sender<(String, Result<Vec<u8>>)>) {
+    if app.screen_reader || app.settings.cover_art.protocol().is_none() {
+        return;
+    }
+    let Some(identifier) = app.current_item_details.as_ref().map(|d| d.identifier.clone()) else { return };
+    let client = app.client.clone();
+    let base_dir = app.settings.download_directory.clone();
+    let tx = thumbnail_tx.clone();
+    let limiter_clone = Arc::clone(rate_limiter);
+    tokio::spawn(async move {
+        let result = thumbnail::load(&client, base_dir.as_deref(), &identifier, limiter_clone).await;
+        let _ = tx.send((identifier, result)).await;
+    });
+}
+
+/// Fetches the archive.org titles of the favorite collections that have none yet into
+/// `titles_tx`.
+fn fetch_collection_titles(app: &mut App, rate_limiter: &AppRateLimiter, titles_tx: &mpsc::Sender<Result<Vec<(String, String)>>>) {
+    let untitled = app.take_untitled_collections();
+    if untitled.is_empty() {
+        return;
+    }
+    let client = app.client.clone();
+    let tx = titles_tx.clone();
+    let limiter_clone = Arc::clone(rate_limiter);
+    tokio::spawn(async move {
+        let _ = tx.send(archive_api::fetch_titles(&client, &untitled, limiter_clone).await).await;
+    });
+}
+
+/// Starts the queued jobs that may run now (see [`App::next_queued_job`]).
+fn start_next_download(app: &mut App, handles: &DownloadHandles) {
+    while let Some(job) = app.next_queued_job() {
+        start_download(app, handles, job);
+    }
+}
+
+/// Starts `job` now, or queues it behind the running collection if it is one too.
+fn run_or_queue(app: &mut App, handles: &DownloadHandles, job: DownloadJob) {
+    if Priority::of(&job.action) != Priority::Bulk {
+        // Files and items don't wait for a collection; they get its next free slots
+        start_download(app, handles, job);
+    } else {
+        if app.is_bulk_download_running() {
+            app.download_status = Some(format!("Queued {} (position {}).", job.describe(), app.queue.len() + 1));
+        }
+        app.queue.push(job);
+        start_next_download(app, handles);
+    }
+}
+
+/// Runs `job`, resetting the progress counters unless other jobs are running.
+fn start_download(app: &mut App, handles: &DownloadHandles, job: DownloadJob) {
+    if app.session.entries().is_empty() {
+        reset_download_progress(app);
+    }
+    spawn_download(app, handles, job);
+}
+
+/// Starts the local mock archive.org and points the API client at it.
+#[cfg(feature = "mock")]
+fn start_mock_server() -> Result<()> {
+    let base_url = rust_tui_app::mock_server::start_background()?;
+    archive_api::set_base_url(&base_url);
+    info!("Mock mode enabled; API requests go to {}", base_url);
+    Ok(())
+}
+
+#[cfg(not(feature = "mock"))]
+fn start_mock_server() -> Result<()> {
+    Err(anyhow!("--mock requires a build with the `mock` feature (cargo run --features mock -- --mock)"))
+}
+
+/// Logs to the log file and to the buffer the log view shows (returned).
 /// Fails if the log file cannot be created or written to.
-fn initialize_logging() -> Result<()> {
+fn initialize_logging() -> Result<LogBuffer> {
     let log_path = Path::new("/var/log/riffarchiver.log");
 
     // Attempt to create/open the log file
     match File::create(log_path) {
         Ok(log_file) => {
-            // Initialize ONLY the file logger. Use LevelFilter::Info or adjust as needed.
-            WriteLogger::init(LevelFilter::Info, Config::default(), log_file)
-                .context(format!("Failed to initialize file logger at {}", log_path.display()))?;
+            let log_buffer = LogBuffer::default();
+            CombinedLogger::init(vec![
+                WriteLogger::new(LevelFilter::Info, Config::default(), log_file),
+                BufferLogger::new(LevelFilter::Info, log_buffer.clone()),
+            ])
+            .context(format!("Failed to initialize file logger at {}", log_path.display()))?;
             // Log initialization success *after* successful initialization
             info!("File logging initialized successfully to: {}", log_path.display());
-            Ok(())
+            Ok(log_buffer)
         }
         Err(e) => {
             // If file creation fails, return an error immediately.
@@ -45,13 +273,51 @@ fn initialize_logging() -> Result<()> {
     }
 }
 
+/// Sets up logging for a headless command: warnings and errors go to stderr (cron mails
+/// them), and everything to [`HEADLESS_LOG_FILE`] in the cache directory if it can be
+/// written. Unlike the TUI's log, this never needs /var/log.
+fn initialize_headless_logging() {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![WriteLogger::new(LevelFilter::Warn, Config::default(), io::stderr())];
+    let log_file = settings::cache_dir().and_then(|dir| {
+        std::fs::create_dir_all(&dir).context(format!("Failed to create cache directory {}", dir.display()))?;
+        let path = dir.join(HEADLESS_LOG_FILE);
+        File::options().create(true).append(true).open(&path).context(format!("Failed to open log file {}", path.display()))
+    });
+    match log_file {
+        Ok(file) => loggers.push(WriteLogger::new(LevelFilter::Info, Config::default(), file)),
+        Err(e) => eprintln!("Logging warnings and errors only: {:#}", e),
+    }
+    if let Err(e) = CombinedLogger::init(loggers) {
+        eprintln!("Failed to initialize logging: {}", e);
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging first.
-    initialize_logging().context("Failed to initialize logging")?;
+    let options = cli::parse_args(std::env::args().skip(1))?;
+    if options.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    // Initialize logging first. Headless commands (cron jobs, unprivileged users) can't
+    // count on a writable /var/log.
+    let log_buffer = if options.command.is_some() {
+        initialize_headless_logging();
+        LogBuffer::default()
+    } else {
+        initialize_logging().context("Failed to initialize logging")?
+    };
     info!("Application starting up.");
 
+    if options.profile_perf {
+        perf::enable();
+        info!("Performance profiling enabled.");
+    }
+    if options.mock {
+        start_mock_server().context("Failed to start mock mode")?;
+    }
+
 
     // Load settings first.
     let settings = match settings::load_settings() {
@@ -65,29 +331,83 @@ async fn main() -> Result<()> {
     };
 
     // --- Rate Limiter Setup ---
-    // Allow 15 requests per minute. Use Arc for sharing.
-    // Using SystemClock to match AppRateLimiter type alias.
-    let quota = Quota::per_minute(NonZeroU32::new(15).unwrap());
-    // Explicitly type with AppRateLimiter alias and use SystemClock
-    let rate_limiter: AppRateLimiter = Arc::new(RateLimiter::direct_with_clock(quota, &SystemClock::default()));
+    // Allow 15 requests per minute (more against the local mock). Use Arc for sharing.
+    let requests_per_minute = if options.mock { MOCK_REQUESTS_PER_MINUTE } else { LIVE_REQUESTS_PER_MINUTE };
+    let quota = Quota::per_minute(NonZeroU32::new(requests_per_minute).unwrap());
+    let mut limiter = SharedRateLimiter::new(quota);
+    // Against the real archive.org, the per-minute budget is shared by every running instance.
+    if !options.mock {
+        match settings::cache_dir() {
+            Ok(dir) => {
+                let state_path = dir.join(rate_limit::SHARED_STATE_FILE);
+                info!("Sharing rate-limit budget via {}", state_path.display());
+                limiter = limiter.with_shared_budget(state_path, LIVE_REQUESTS_PER_MINUTE, Duration::from_secs(60));
+            }
+            Err(e) => warn!("No cache directory for the shared rate-limit budget: {}", e),
+        }
+    }
+    let rate_limiter: AppRateLimiter = Arc::new(limiter);
 
+    // Headless commands (cron jobs, CI) run to completion without the TUI
+    if let Some(command) = options.command.clone() {
+        return headless::run(command, settings, options.dir.clone(), options.force, rate_limiter).await;
+    }
 
     // Create an application, load settings, and pass the rate limiter.
     let mut app = App::new(Arc::clone(&rate_limiter));
     app.load_settings(settings);
-
-    // Create a channel for bulk item fetch results (replaces incremental channel)
-    let (bulk_fetch_tx, mut bulk_fetch_rx) = mpsc::channel::<Result<(Vec<ArchiveDoc>, usize)>>(1); // Buffer size 1
+    app.log_buffer = log_buffer;
+
+    // Pages of collection listings, streamed into the items pane as they arrive
+    let (item_page_tx, mut item_page_rx) = mpsc::channel::<(String, Result<ItemPage>)>(4);
+    // The running listing task, aborted when another collection is opened
+    let mut item_fetch_task: Option<tokio::task::JoinHandle<()>> = None;
+    // Search results, tagged with their query so superseded searches can be dropped
+    let (search_tx, mut search_rx) = mpsc::channel::<(String, Result<(Vec<ArchiveDoc>, usize)>)>(4);
+    // Listings of favorite collections from a sync round, tagged with the collection
+    let (sync_tx, mut sync_rx) = mpsc::channel::<SyncListing>(8);
+    // Dry-run estimates of collection downloads, tagged with the collection
+    let (estimate_tx, mut estimate_rx) = mpsc::channel::<(String, EstimateUpdate)>(16);
+    // The running estimate, aborted when its popup is closed or another one starts
+    let mut estimate_task: Option<tokio::task::JoinHandle<()>> = None;
     // Create a channel for item details API results
     let (item_details_tx, mut item_details_rx) = mpsc::channel::<Result<ItemDetails, archive_api::FetchDetailsError>>(1);
-    // Create a channel for download progress updates
-    let (download_progress_tx, mut download_progress_rx) = mpsc::channel::<DownloadProgress>(50); // Increased buffer
+    // Cover art of viewed items, with the identifier
+    let (thumbnail_tx, mut thumbnail_rx) = mpsc::channel::<(String, Result<Vec<u8>>)>(2);
+    // Create a channel for download progress updates. The App drains it on every tick,
+    // so the buffer must hold roughly one tick's worth of chunk updates.
+    let (download_progress_tx, download_progress_rx) = mpsc::channel::<DownloadProgress>(DOWNLOAD_PROGRESS_CHANNEL_CAPACITY);
+    app.attach_progress_receiver(download_progress_rx);
+    // Channels for the opt-in release check and a user-requested self-update
+    let (update_check_tx, mut update_check_rx) = mpsc::channel::<Result<Option<ReleaseInfo>>>(1);
+    let (update_install_tx, mut update_install_rx) = mpsc::channel::<Result<String>>(1);
+    // Progress of a user-started upload ('u')
+    let (upload_tx, mut upload_rx) = mpsc::channel::<UploadProgress>(64);
+    // Result of logging in from the settings
+    let (login_tx, mut login_rx) = mpsc::channel::<Result<LoginResult>>(1);
+    // Collections found among a user's favorites ('F'), with the screen name
+    let (favorites_tx, mut favorites_rx) = mpsc::channel::<(String, Result<Vec<String>>)>(1);
+    // Wayback Machine lookups and capture downloads ('W')
+    let (wayback_tx, mut wayback_rx) = mpsc::channel::<WaybackProgress>(64);
+    // archive.org titles of favorite collections, shown instead of their identifiers
+    let (titles_tx, mut titles_rx) = mpsc::channel::<Result<Vec<(String, String)>>>(4);
+    // Results of verifying an item's downloaded files, with its identifier
+    let (verify_tx, mut verify_rx) = mpsc::channel::<(String, Result<Vec<(String, FileCheck)>>)>(4);
+    match settings::config_dir().and_then(|dir| Ok((settings::secret_store()?, dir))) {
+        Ok((store, dir)) => app.s3_keys = ia_upload::load_keys(store, &dir),
+        Err(e) => warn!("No config directory for the S3 keys: {}", e),
+    }
+    if app.settings.check_for_updates && !options.mock {
+        let client = app.client.clone();
+        tokio::spawn(async move {
+            let _ = update_check_tx.send(updater::check_for_update(&client).await).await;
+        });
+    }
 
-    // --- Concurrency Limiter ---
     // --- Concurrency Limiters ---
-    // Semaphore for limiting concurrent *file* downloads within items/collections
+    // Scheduler handing out the concurrent *file* download slots to all jobs, by priority
     let max_file_downloads = app.settings.max_concurrent_downloads.unwrap_or(4).max(1); // Default 4, min 1
-    let file_semaphore = Arc::new(Semaphore::new(max_file_downloads));
+    let scheduler = DownloadScheduler::new(max_file_downloads);
     info!("File download concurrency limit: {}", max_file_downloads);
 
     // Semaphore for limiting concurrent *item processing* tasks within a collection download
@@ -96,11 +416,44 @@ async fn main() -> Result<()> {
     let collection_item_semaphore = Arc::new(Semaphore::new(max_item_tasks));
      info!("Collection item processing concurrency limit: {}", max_item_tasks);
 
+    // Per-datanode politeness delay, shared by all downloads
+    let host_pacer = Arc::new(HostPacer::new(Duration::from_millis(app.settings.host_delay_ms)));
+    info!("Per-host request delay: {} ms", app.settings.host_delay_ms);
+
+    let (download_finished_tx, mut download_finished_rx) = mpsc::channel::<u64>(16);
+    let download_handles = DownloadHandles {
+        progress_tx: download_progress_tx.clone(),
+        scheduler: Arc::clone(&scheduler),
+        collection_item_semaphore: Arc::clone(&collection_item_semaphore),
+        rate_limiter: Arc::clone(&rate_limiter),
+        host_pacer: Arc::clone(&host_pacer),
+        finished_tx: download_finished_tx,
+    };
+
+    // Downloads interrupted or still queued last time are offered for resumption (not in mock mode,
+    // whose downloads are throwaway).
+    if !options.mock {
+        match settings::cache_dir() {
+            Ok(dir) => app.session = SessionJournal::load(dir.join(session::SESSION_FILE)),
+            Err(e) => warn!("No cache directory for the session journal: {}", e),
+        }
+        match settings::config_dir() {
+            Ok(dir) => {
+                app.queue = DownloadQueue::load(dir.join(queue::QUEUE_FILE));
+                app.retries = RetryQueue::load(dir.join(retry::RETRIES_FILE));
+            }
+            Err(e) => warn!("No config directory for the download queue: {}", e),
+        }
+        if app.has_unfinished_session() {
+            app.current_state = AppState::ResumePrompt;
+        }
+    }
+
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
     let terminal = Terminal::new(backend)?;
-    let events = EventHandler::new(250); // Tick rate 250ms
+    let events = EventHandler::new(TICK_RATE_MS);
     let mut tui = Tui::new(terminal, events);
     if let Err(e) = tui.init() {
         error!("Failed to initialize TUI: {}", e);
@@ -110,8 +463,18 @@ async fn main() -> Result<()> {
     }
     info!("TUI initialized successfully.");
 
+    if options.screen_reader {
+        app.screen_reader = true;
+    }
+    app.force_downloads = options.force;
+    // Announcements only reach stdout with --mirror-stdout; otherwise they just feed the event line.
+    let mut announcer = Announcer::new(options.mirror_stdout);
+
     // Start the main loop.
     while app.running {
+        if app.screen_reader {
+            announcer.observe(&mut app);
+        }
         // Render the user interface.
         tui.draw(&mut app)?;
         // Handle events using tokio::select!
@@ -119,12 +482,39 @@ async fn main() -> Result<()> {
             // Handle terminal events
             event = tui.events.next() => {
                 match event? {
-                    Event::Tick => app.tick(),
+                    Event::Tick => {
+                        app.tick();
+                        // Retries wait for the resume decision like the rest of the previous run, and
+                        // for offline mode to end
+                        if app.current_state != AppState::ResumePrompt && app.network != NetworkMode::Offline {
+                            for job in app.retries.take_due(Utc::now()) {
+                                info!("Retrying {}", job.describe());
+                                run_or_queue(&mut app, &download_handles, job);
+                            }
+                        }
+                        if app.network != NetworkMode::Offline {
+                            fetch_collection_titles(&mut app, &rate_limiter, &titles_tx);
+                        }
+                        let favorites = app.settings.favorite_collections.len();
+                        if app.settings.download_directory.is_some()
+                            && app.network != NetworkMode::Offline
+                            && app.sync.start_if_due(app.settings.sync_interval_minutes, favorites, Instant::now())
+                        {
+                            spawn_sync_round(&app, &sync_tx, &rate_limiter);
+                        }
+                    }
                     Event::Key(key_event) => {
+                        announcer.key(&key_event);
                         // Handle input and check if an action is requested
                         if let Some(action) = update(&mut app, key_event) {
+                            if app.network == NetworkMode::Offline && action.needs_network() {
+                                app.is_loading = false;
+                                app.is_loading_details = false;
+                                app.error_message = Some(offline::NEEDS_NETWORK.to_string());
+                                continue;
+                            }
                             match action {
-                                UpdateAction::StartBulkItemFetch(collection_name) => { // Renamed action
+                                UpdateAction::StartIncrementalItemFetch(collection_name) => {
                                     // Triggered when selecting a collection in update() and cache misses
                                     // State (is_loading, items cleared, etc.) should be set by update()
                                     app.error_message = None; // Clear previous errors
@@ -139,21 +529,49 @@ async fn main() -> Result<()> {
                                         continue; // Skip spawning task
                                     }
 
+                                    if let Some(previous) = item_fetch_task.take() {
+                                        previous.abort(); // Its collection is no longer open
+                                    }
+                                    let filter = match fetch_filter::for_collection(&app.settings.fetch_filters, &collection_name) {
+                                        Ok(filter) => filter,
+                                        Err(e) => {
+                                            app.is_loading = false;
+                                            app.error_message = Some(format!("{:#}", e));
+                                            continue;
+                                        }
+                                    };
                                     let client = app.client.clone();
-                                    let tx = bulk_fetch_tx.clone(); // Use the bulk channel sender
+                                    let tx = item_page_tx.clone();
                                     let limiter_clone = Arc::clone(&rate_limiter);
-                                    // Spawn the bulk fetch task
-                                    tokio::spawn(async move {
-                                        let result = archive_api::fetch_collection_items_bulk(
-                                            &client,
-                                            &collection_name,
-                                            limiter_clone,
-                                        )
-                                        .await;
-                                        // Send the single result (Ok or Err) back
-                                        if tx.send(result).await.is_err() {
-                                            warn!("Bulk fetch receiver dropped for collection '{}'.", collection_name);
+                                    // Spawn the listing task; each page is sent as soon as it arrives
+                                    item_fetch_task = Some(tokio::spawn(async move {
+                                        let mut scrape = archive_api::CollectionScrape::new(&collection_name, filter.as_deref());
+                                        loop {
+                                            let page = match scrape.next_page(&client, &limiter_clone).await {
+                                                Ok(Some(docs)) => Ok(ItemPage {
+                                                    docs,
+                                                    total: scrape.total(),
+                                                    last: scrape.is_finished(),
+                                                }),
+                                                Ok(None) => break,
+                                                Err(e) => Err(e.context(format!("Failed to list collection '{}'", collection_name))),
+                                            };
+                                            let stop = !matches!(page, Ok(ItemPage { last: false, .. }));
+                                            if tx.send((collection_name.clone(), page)).await.is_err() || stop {
+                                                break;
+                                            }
                                         }
+                                    }));
+                                }
+                                UpdateAction::StartSearch(query) => {
+                                    app.error_message = None;
+                                    app.download_status = None;
+                                    let client = app.client.clone();
+                                    let tx = search_tx.clone();
+                                    let limiter_clone = Arc::clone(&rate_limiter);
+                                    tokio::spawn(async move {
+                                        let result = archive_api::search_items(&client, &query, limiter_clone).await;
+                                        let _ = tx.send((query, result)).await;
                                     });
                                 }
                                 UpdateAction::FetchItemDetails => {
@@ -177,62 +595,81 @@ async fn main() -> Result<()> {
                                 }
                                 UpdateAction::StartDownload(download_action) => {
                                     // Triggered by 'd' or 'b' in various contexts
-                                    // Removed check: if app.is_downloading { ... }
                                     if let Some(base_dir) = app.settings.download_directory.clone() {
-                                        // Set downloading flag and reset progress
-                                        // Note: is_downloading is now slightly less accurate, as it's true
-                                        // if *any* download task is running. We might need more granular tracking later.
-                                        app.is_downloading = true;
-                                        app.error_message = None;
-                                        app.items_downloaded_count = 0;
-                                        app.total_files_to_download = None; // Reset, will be updated by tasks
-                                        app.files_downloaded_count = 0;
-                                        app.total_bytes_downloaded = 0;
-                                        app.download_start_time = Some(Instant::now());
-                                        app.total_items_to_download = None; // Reset, set by Collection task if needed
-
-                                        // Clone data needed for the download task
-                                        let client_clone = app.client.clone();
-                                        let base_dir_clone = base_dir.clone();
-                                        let progress_tx_clone = download_progress_tx.clone();
-                                        let file_semaphore_clone = Arc::clone(&file_semaphore); // Use renamed semaphore
-                                        let collection_item_semaphore_clone = Arc::clone(&collection_item_semaphore); // Clone new semaphore
-                                        let limiter_clone = Arc::clone(&rate_limiter); // Clone rate limiter
-                                        let download_mode = app.settings.download_mode; // Get current download mode
-                                        // Clone the current collection name *before* spawning the task
-                                        let current_collection_name_clone = app.current_collection_name.clone();
-
-                                        // Spawn the download task
-                                        tokio::spawn(async move {
-                                            let result = match download_action {
-                                                DownloadAction::ItemAllFiles(item_id) => {
-                                                    // Pass file_semaphore, mode, AND limiter down
-                                                    // Pass the captured collection name
-                                                    download_item(&client_clone, &base_dir_clone, current_collection_name_clone.as_deref(), &item_id, download_mode, progress_tx_clone.clone(), file_semaphore_clone, limiter_clone).await
-                                                }
-                                                DownloadAction::File(item_id, file) => {
-                                                    // Pass file_semaphore AND limiter down
-                                                    // Mode doesn't apply here, always download the specific file
-                                                    // Pass the captured collection name
-                                                    download_single_file(&client_clone, &base_dir_clone, current_collection_name_clone.as_deref(), &item_id, &file, progress_tx_clone.clone(), file_semaphore_clone, limiter_clone).await
-                                                }
-                                                DownloadAction::Collection(collection_id) => {
-                                                     // Pass both semaphores, mode, AND limiter down
-                                                     download_collection(&client_clone, &base_dir_clone, &collection_id, download_mode, progress_tx_clone.clone(), file_semaphore_clone, collection_item_semaphore_clone, limiter_clone).await
-                                                }
-                                            };
-
-                                            // Report top-level task errors (e.g., failed to get identifiers)
-                                            if let Err(e) = result {
-                                                let _ = progress_tx_clone.send(DownloadProgress::Error(format!("Download Task Error: {}", e))).await;
-                                            }
-                                            // Note: is_downloading flag is reset when CollectionCompleted or Error is received
-                                        });
+                                        let job = DownloadJob {
+                                            action: download_action,
+                                            collection: app.current_collection_name.clone(),
+                                            mode: app.settings.download_mode,
+                                            base_dir,
+                                            preset: app.download_preset,
+                                            retry: None,
+                                        };
+                                        run_or_queue(&mut app, &download_handles, job);
                                     } else {
                                         // This case should be handled by update() sending to AskingDownloadDir state
                                         app.error_message = Some("Error: Download directory not set.".to_string());
                                     }
                                 }
+                                UpdateAction::ResumeSession => {
+                                    // Jobs restart with the directory and mode they were started with
+                                    let entries = app.session.take_entries();
+                                    app.queue.release_held();
+                                    if !entries.is_empty() {
+                                        reset_download_progress(&mut app);
+                                        app.download_status = Some(format!("Resuming {} interrupted download(s)...", entries.len()));
+                                        for entry in entries {
+                                            spawn_download(&mut app, &download_handles, entry.job);
+                                        }
+                                    }
+                                    // Queued jobs follow once the interrupted ones are done
+                                    start_next_download(&mut app, &download_handles);
+                                }
+                                UpdateAction::OpenPath(path) => {
+                                    match opener::open_path(&path) {
+                                        Ok(()) => app.download_status = Some(format!("Opened {}", path.display())),
+                                        Err(e) => {
+                                            error!("{:#}", e);
+                                            app.error_message = Some(format!("{:#}", e));
+                                        }
+                                    }
+                                }
+                                UpdateAction::OpenUrl(url) => match opener::open_url(&url) {
+                                    Ok(()) => app.download_status = Some(format!("Opened {}", url)),
+                                    Err(e) => {
+                                        error!("{:#}", e);
+                                        app.error_message = Some(format!("{:#}", e));
+                                    }
+                                },
+                                UpdateAction::PlayUrl(url) => {
+                                    if let Err(e) = opener::play_url(&app.settings.player_command, &url) {
+                                        error!("{:#}", e);
+                                        app.error_message = Some(format!("{:#}", e));
+                                    }
+                                }
+                                UpdateAction::PreviewFile(item_id, file) => {
+                                    let url = archive_api::download_url(&item_id, &file.name);
+                                    let length = file.length.as_deref().and_then(playlist::length_seconds).map(Duration::from_secs_f64);
+                                    // Replacing a playing preview stops it
+                                    app.preview = None;
+                                    match Preview::start(&url, &item_id, &file.name, length) {
+                                        Ok(preview) => app.preview = Some(preview),
+                                        Err(e) => {
+                                            error!("{:#}", e);
+                                            app.error_message = Some(format!("{:#}", e));
+                                        }
+                                    }
+                                }
+                                UpdateAction::CopyToClipboard(text) => match clipboard::copy(&text) {
+                                    Ok(()) => app.download_status = Some(format!("Copied {}", text)),
+                                    Err(e) => {
+                                        error!("{:#}", e);
+                                        app.error_message = Some(format!("{:#}", e));
+                                    }
+                                },
+                                UpdateAction::PasteClipboard => match clipboard::paste() {
+                                    Ok(text) => update::paste(&mut app, &text),
+                                    Err(e) => app.error_message = Some(format!("{:#}", e)),
+                                },
                                 UpdateAction::SaveSettings => {
                                     // Triggered after adding/removing collection or exiting settings
                                     if let Err(e) = settings::save_settings(&app.settings) {
@@ -245,823 +682,441 @@ async fn main() -> Result<()> {
                                         // app.download_status = Some("Settings saved.".to_string());
                                     }
                                 }
+                                UpdateAction::EstimateCollection(collection) => {
+                                    if let Some(previous) = estimate_task.take() {
+                                        previous.abort();
+                                    }
+                                    let (identifiers, plan) = app.estimate_inputs(&collection);
+                                    let items = match identifiers {
+                                        Some(identifiers) => EstimateItems::Listed(identifiers),
+                                        None => match fetch_filter::for_collection(&app.settings.fetch_filters, &collection) {
+                                            Ok(filter) => EstimateItems::Fetch(filter),
+                                            Err(e) => {
+                                                app.size_estimate = None;
+                                                app.current_state = AppState::Browsing;
+                                                app.error_message = Some(format!("{:#}", e));
+                                                continue;
+                                            }
+                                        },
+                                    };
+                                    estimate_task = Some(tokio::spawn(estimate::run(
+                                        app.client.clone(),
+                                        Arc::clone(&rate_limiter),
+                                        collection,
+                                        items,
+                                        Arc::clone(&app.exclusions),
+                                        plan,
+                                        estimate_tx.clone(),
+                                    )));
+                                }
+                                UpdateAction::StartUpload(request) => {
+                                    if let Some(keys) = app.s3_keys.clone() {
+                                        let client = app.client.clone();
+                                        let tx = upload_tx.clone();
+                                        tokio::spawn(async move {
+                                            let result = ia_upload::upload_directory(&client, &ia_upload::endpoint(), &keys, &request, &tx).await;
+                                            let _ = tx.send(match result {
+                                                Ok(_) => UploadProgress::Finished,
+                                                Err(e) => {
+                                                    error!("Upload to '{}' failed: {:#}", request.identifier, e);
+                                                    UploadProgress::Failed(format!("{:#}", e))
+                                                }
+                                            }).await;
+                                        });
+                                    }
+                                }
+                                UpdateAction::SaveUploadKeys => {
+                                    let saved = settings::secret_store().and_then(|store| match &app.s3_keys {
+                                        Some(keys) => ia_upload::save_keys(store, keys),
+                                        None => Ok(()),
+                                    });
+                                    if let Err(e) = saved {
+                                        let err_msg = format!("Failed to save S3 keys: {:#}", e);
+                                        error!("{}", err_msg);
+                                        app.error_message = Some(err_msg);
+                                    }
+                                }
+                                UpdateAction::Login(request) => {
+                                    let client = app.client.clone();
+                                    let tx = login_tx.clone();
+                                    tokio::spawn(async move {
+                                        let result = auth::login(&client, &archive_api::base_url(), &request.email, &request.password).await;
+                                        let _ = tx.send(result).await;
+                                    });
+                                }
+                                UpdateAction::ImportFavorites(user) => {
+                                    let client = app.client.clone();
+                                    let limiter = Arc::clone(&app.rate_limiter);
+                                    let tx = favorites_tx.clone();
+                                    tokio::spawn(async move {
+                                        let result = favorites::fetch_favorite_collections(&client, &user, limiter).await;
+                                        let _ = tx.send((user, result)).await;
+                                    });
+                                }
+                                UpdateAction::SyncNow => {
+                                    let favorites = app.settings.favorite_collections.len();
+                                    if app.settings.download_directory.is_none() {
+                                        app.error_message = Some("Set a download directory first.".to_string());
+                                    } else if favorites == 0 {
+                                        app.error_message = Some("No favorite collections to sync.".to_string());
+                                    } else if app.sync.start_now(favorites, Instant::now()) {
+                                        spawn_sync_round(&app, &sync_tx, &rate_limiter);
+                                        app.download_status = Some(format!("Syncing {} favorite collection(s)...", favorites));
+                                    } else {
+                                        app.download_status = Some("A sync is already running.".to_string());
+                                    }
+                                }
+                                UpdateAction::VerifyItem(identifier, dir) => {
+                                    let client = app.client.clone();
+                                    let limiter = Arc::clone(&app.rate_limiter);
+                                    let policy = PathPolicy::from_settings(&app.settings);
+                                    let tx = verify_tx.clone();
+                                    tokio::spawn(async move {
+                                        // The checksum manifest is checked offline; archive.org is only asked without one
+                                        let result = match verify::verify_manifest(dir.clone()).await {
+                                            Ok(Some(checks)) => Ok(checks),
+                                            Ok(None) => match archive_api::fetch_item_details(&client, &identifier, limiter).await {
+                                                Ok(details) => verify::verify_item(dir, details, policy).await,
+                                                Err(e) => Err(anyhow!("{}", e)),
+                                            },
+                                            Err(e) => Err(e),
+                                        };
+                                        let _ = tx.send((identifier, result)).await;
+                                    });
+                                }
+                                UpdateAction::ListWayback(query) => {
+                                    let client = app.client.clone();
+                                    let limiter = Arc::clone(&app.rate_limiter);
+                                    let tx = wayback_tx.clone();
+                                    tokio::spawn(async move {
+                                        let result = wayback::list_snapshots(&client, &wayback::endpoint(), &query, &limiter).await;
+                                        let _ = tx.send(WaybackProgress::Listed(query, result.map_err(|e| format!("{:#}", e)))).await;
+                                    });
+                                }
+                                UpdateAction::DownloadWayback(snapshots) => {
+                                    // The view only asks for a download with a directory set
+                                    if let Some(base_dir) = app.settings.download_directory.clone() {
+                                        let client = app.client.clone();
+                                        let limiter = Arc::clone(&app.rate_limiter);
+                                        let policy = PathPolicy::from_settings(&app.settings);
+                                        let tx = wayback_tx.clone();
+                                        tokio::spawn(async move {
+                                            wayback::download_all(&client, &wayback::endpoint(), &snapshots, &base_dir, &policy, &limiter, &tx).await;
+                                        });
+                                    }
+                                }
+                                UpdateAction::Logout => {
+                                    match settings::config_dir().and_then(|dir| auth::remove_login(settings::secret_store()?, &dir)) {
+                                        Ok(()) => {
+                                            // A login from settings.toml or `ia configure` still applies
+                                            app.set_credentials(auth::credentials(&app.settings));
+                                            app.download_status = Some(match &app.credentials {
+                                                Some(creds) => format!("Saved login removed; still logged in as '{}' (settings.toml or ia configure)", creds.display_user()),
+                                                None => "Logged out of archive.org".to_string(),
+                                            });
+                                        }
+                                        Err(e) => {
+                                            let err_msg = format!("Failed to log out: {:#}", e);
+                                            error!("{}", err_msg);
+                                            app.error_message = Some(err_msg);
+                                        }
+                                    }
+                                }
+                                UpdateAction::InstallUpdate => {
+                                    // Triggered from the release notes popup
+                                    if let Some(release) = app.available_update.clone() {
+                                        let tx = update_install_tx.clone();
+                                        tokio::spawn(async move {
+                                            let _ = tx.send(updater::install_update(&release).await).await;
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                        if app.size_estimate.is_none() {
+                            if let Some(task) = estimate_task.take() {
+                                task.abort(); // Its popup was closed
                             }
                         }
                     },
+                    Event::KeyRepeat(key_event, steps) => {
+                        // Coalesced navigation keys never produce actions, only selection changes
+                        announcer.key(&key_event);
+                        let _ = update_repeated(&mut app, key_event, steps);
+                    }
+                    Event::Paste(text) => update::paste(&mut app, &text),
                     Event::Mouse(_) => {} // Ignore mouse events
                     Event::Resize(_, _) => {} // Terminal handles resize redraw automatically
                 }
             }
-            // Handle bulk item fetch results
-            Some(result) = bulk_fetch_rx.recv() => {
-                app.is_loading = false; // Fetch finished (successfully or not)
-                match result {
-                    Ok((items, total_found)) => {
-                        info!("Received {} items (total reported: {}) from bulk fetch.", items.len(), total_found);
-                        app.total_items_found = Some(total_found); // Store reported total
-                        let was_empty = app.items.is_empty(); // Check before setting new items
-
-                        // Set items and save to cache
-                        if let Err(e) = app.set_and_save_items(items) {
-                             let err_msg = format!("Error saving item cache after bulk fetch: {}", e);
-                             error!("{}", err_msg);
-                             app.error_message = Some(err_msg);
-                        } else {
-                            // Select first item only if the list *was* empty before this fetch
-                            // and the new list is not empty.
-                            if was_empty && !app.items.is_empty() {
-                                app.item_list_state.select(Some(0));
-                            } else if app.items.is_empty() {
-                                // Ensure selection is cleared if fetch returned no items
-                                app.item_list_state.select(None);
-                            }
-                            // Clear error on successful fetch and save
-                            app.error_message = None;
+            // Handle items checked by the size estimate
+            Some((collection, estimate_update)) = estimate_rx.recv() => {
+                match &mut app.size_estimate {
+                    Some(estimate) if estimate.collection == collection => estimate.apply(estimate_update),
+                    _ => {} // The popup was closed or another estimate started
+                }
+            }
+            // Handle pages of collection listings
+            Some((collection_name, page)) = item_page_rx.recv() => {
+                if app.search_query.is_some() || app.current_collection_name.as_ref() != Some(&collection_name) {
+                    continue; // The user switched to another collection or search results meanwhile
+                }
+                match page.and_then(|page| app.apply_item_page(&collection_name, page)) {
+                    Ok(()) => {
+                        app.error_message = None;
+                        if app.network == NetworkMode::Unreachable {
+                            app.network = NetworkMode::Online; // Answered, so the network is back
                         }
                     }
                     Err(e) => {
-                        let err_msg = format!("Bulk item fetch failed: {}", e);
+                        if app.network == NetworkMode::Online && ErrorKind::classify(&format!("{:#}", e)) == ErrorKind::Network {
+                            app.network = NetworkMode::Unreachable;
+                        }
+                        let err_msg = format!("Item listing failed: {:#}", e);
                         error!("{}", err_msg);
+                        app.is_loading = false;
                         app.error_message = Some(err_msg);
-                        app.items.clear(); // Clear items on fetch error
+                        app.items.clear(); // A partial listing isn't cached or shown
                         app.item_list_state.select(None);
                         app.total_items_found = None;
                     }
                 }
             }
-            // Handle item details API results
-            Some(result) = item_details_rx.recv() => {
-                app.is_loading_details = false; // Reset details loading state
+            // Handle search results (not cached: queries are ad hoc)
+            Some((query, result)) = search_rx.recv() => {
+                if app.search_query.as_deref() != Some(query.as_str()) {
+                    continue; // Superseded by another search or a collection
+                }
+                app.is_loading = false;
                 match result {
-                    // Update match arm to handle FetchDetailsError
-                    Ok(details) => {
-                        app.current_item_details = Some(details);
-                        // Select first file if available
-                        if app.current_item_details.as_ref().map_or(false, |d| !d.files.is_empty()) {
-                            app.file_list_state.select(Some(0));
-                        } else {
-                            app.file_list_state.select(None);
-                        }
-                        app.error_message = None; // Clear error on success
+                    Ok((items, total_found)) => {
+                        info!("Search '{}' returned {} items (total reported: {})", query, items.len(), total_found);
+                        app.total_items_found = Some(total_found);
+                        app.items = items;
+                        app.item_list_state.select((!app.items.is_empty()).then_some(0));
+                        app.error_message = None;
                     }
-                    // Update match arm to handle FetchDetailsError
                     Err(e) => {
-                        // Use the Display impl of FetchDetailsError directly
-                        let err_msg = format!("Error fetching item details: {}", e);
-                        error!("{}", err_msg); // Log the error
-                        app.current_item_details = None; // Clear details on error
-                        app.file_list_state.select(None); // Reset file selection
+                        let err_msg = format!("Search failed: {:#}", e);
+                        error!("{}", err_msg);
                         app.error_message = Some(err_msg);
+                        app.total_items_found = None;
                     }
                 }
             }
-            // Handle download progress updates
-            Some(status) = download_progress_rx.recv() => { // Use correct receiver name
-                 // Check for a final status message to reset the flag
-                 // Note: CollectionCompleted is now the primary signal for bulk completion
-                 // Individual ItemCompleted or Error messages might not reset is_downloading
-                 // if it's part of a larger bulk download. Resetting only on CollectionCompleted or Error.
-
-                 // Update App state based on progress message
-                 match status {
-                     DownloadProgress::CollectionInfo(total) => { // Add handler for CollectionInfo
-                        app.total_items_to_download = Some(total);
-                        // Keep existing status message or update if desired
+            // Handle collection listings from a sync round
+            Some((collection, result)) = sync_rx.recv() => {
+                app.sync.finish_one();
+                match result.and_then(|(items, _)| app.apply_sync_listing(&collection, items)) {
+                    Ok(0) => {}
+                    Ok(queued) => {
+                        app.download_status = Some(format!("Sync: queued {} new item(s) from {}", queued, collection));
+                        start_next_download(&mut app, &download_handles);
                     }
-                     DownloadProgress::ItemStarted(id) => {
-                         app.download_status = Some(format!("Starting: {}", id));
-                     }
-                     DownloadProgress::ItemFileCount(count) => {
-                         app.total_files_to_download = Some(app.total_files_to_download.unwrap_or(0) + count);
-                         app.download_status = Some(format!("Found {} files...", count));
-                     }
-                     DownloadProgress::BytesDownloaded(bytes) => {
-                         app.total_bytes_downloaded += bytes;
-                         // Don't update status string for every chunk, too noisy
-                     }
-                     DownloadProgress::FileCompleted(filename) => {
-                         app.files_downloaded_count += 1;
-                         app.download_status = Some(format!("Done: {}", filename));
-                     }
-                     DownloadProgress::ItemCompleted(id, success) => {
-                         app.items_downloaded_count += 1;
-                         let status_prefix = if success { "Completed item" } else { "Finished item (with errors)" };
-                         app.download_status = Some(format!("{}: {}", status_prefix, id));
-                     }
-                     DownloadProgress::ItemSkippedWasCollection(id) => {
-                        // Increment downloaded count as we 'processed' it by skipping
-                        app.items_downloaded_count += 1;
-                        app.download_status = Some(format!("Skipped (is collection): {}", id));
-                        // Optional: Add logic here to queue a download for the discovered collection 'id'
-                        // E.g., app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Collection(id)));
-                        // For now, just log and update status.
-                        info!("Identified '{}' as a collection during TorrentOnly download attempt.", id);
-                     }
-                     DownloadProgress::CollectionCompleted(total, failed) => {
-                         app.is_downloading = false; // Collection finished
-                         app.download_start_time = None; // Clear start time
-                         app.download_status = Some(format!("Collection download finished. Items: {} attempted, {} failed.", total, failed));
-                     }
-                     DownloadProgress::Error(msg) => {
-                         error!("Download Progress Error: {}", msg); // Log the error
-                         app.is_downloading = false; // Stop on major error
-                         app.download_start_time = None; // Clear start time
-                         app.error_message = Some(msg.clone()); // Show as main error
-                         app.download_status = Some(format!("Error: {}", msg));
-                     }
-                     DownloadProgress::Status(msg) => {
-                         // General status update
-                         app.download_status = Some(msg);
-                     }
-                 }
-            }
-        }
-    }
-
-    // Exit the user interface.
-    if let Err(e) = tui.exit() {
-        error!("Failed to exit TUI cleanly: {}", e);
-        // Continue shutdown despite TUI exit error
-    } else {
-        info!("TUI exited successfully.");
-    }
-
-    info!("Application shutting down.");
-    Ok(())
-}
-
-
-// --- Download Helper Functions ---
-
-// Removed redundant imports: use std::path::Path; and use tokio::fs::{self, File};
-// The necessary items (std::path::Path, tokio::fs::File) are imported at the top.
-// We still need `tokio::fs` itself for functions like `metadata` and `create_dir_all`.
-use tokio::fs::{self, File as TokioFile}; // Alias tokio::fs::File to avoid clash with std::fs::File
-use tokio::io::{AsyncReadExt, AsyncWriteExt}; // Add AsyncReadExt for reading cache file
-use futures_util::StreamExt;
-use serde_json; // Add serde_json for caching
-// Removed redundant log macro import: use log::{debug, error, info, warn};
-// Macros are already imported at the top of the file.
-
-
-/// Downloads a single file.
-/// Path: base_dir / [collection_id] / item_id / filename
-async fn download_single_file(
-    client: &Client,
-    base_dir: &str,
-    collection_id: Option<&str>, // Added: Optional collection context
-    item_id: &str,
-    file_details: &archive_api::FileDetails,
-    progress_tx: mpsc::Sender<DownloadProgress>,
-    file_semaphore: Arc<Semaphore>, // Renamed
-    rate_limiter: AppRateLimiter, // Use the type alias
-) -> Result<()> {
-    let collection_str = collection_id.unwrap_or("<none>");
-    info!("Starting download_single_file: collection='{}', item='{}', file='{}'",
-          collection_str, item_id, file_details.name);
-
-    // --- Idempotency Check & Path Construction ---
-    // Construct path based on whether collection_id is present and if it's a torrent file
-    let file_path = if file_details.name.ends_with("_archive.torrent") { // Check for the specific suffix
-        // Special path for torrent files: base_dir / collection_id / item_id_archive.torrent
-        match collection_id {
-            Some(c) => Path::new(base_dir).join(c).join(format!("{}_archive.torrent", item_id)), // Use _archive.torrent format
-            // If no collection context, maybe place in base_dir? Or error?
-            // For now, assume torrent downloads happen within a collection context.
-            // If collection_id is None, this will likely cause issues later if triggered.
-            None => {
-                warn!("Attempting to download torrent file '{}' for item '{}' without collection context. Placing in base directory.", file_details.name, item_id);
-                Path::new(base_dir).join(format!("{}_archive.torrent", item_id)) // Use _archive.torrent format
-                // Alternatively, return an error:
-                // return Err(anyhow!("Cannot determine torrent download path without collection context for item '{}'", item_id));
-            }
-        }
-    } else {
-        // Standard path for other files: base_dir / [collection_id] / item_id / filename
-        match collection_id {
-            Some(c) => Path::new(base_dir).join(c).join(item_id).join(&file_details.name),
-            None => Path::new(base_dir).join(item_id).join(&file_details.name),
-        }
-    };
-
-    let expected_size_str = file_details.size.as_deref();
-    let expected_size: Option<u64> = expected_size_str.and_then(|s| s.parse().ok());
-
-    if let Some(expected) = expected_size {
-        // Use tokio::fs::metadata here
-        match fs::metadata(&file_path).await {
-            Ok(metadata) => {
-                if metadata.is_file() && metadata.len() == expected {
-                    info!("Skipping existing file with matching size: '{}'", file_path.display());
-                    // Send FileCompleted immediately if skipped
-                    let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
-                    // Also send a status message for clarity
-                    let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping (exists): {}", file_details.name))).await;
-                    return Ok(()); // File exists and size matches, skip download - NO PERMIT USED
-                } else {
-                     debug!("Existing file found but size mismatch or not a file: '{}'. Proceeding with download.", file_path.display());
-                }
-            }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                 debug!("File not found: '{}'. Proceeding with download.", file_path.display());
-            }
-            Err(e) => {
-                 warn!("Failed to get metadata for '{}': {}. Proceeding with download.", file_path.display(), e);
-            }
-        }
-        // If metadata check fails or size mismatch, continue to acquire permit and download
-    } else {
-         // If expected size is unknown, we still need to acquire permit before checking/downloading
-         // Log warning later if needed after acquiring permit
-         debug!("File size unknown for '{}'. Will acquire permit and download.", file_details.name);
-    }
-    // --- End Idempotency Check ---
-
-    // --- Acquire Semaphore Permit ---
-    // Acquire permit *before* making network request or creating file.
-    // The permit is stored in `_permit` and will be dropped automatically
-    // when this function returns (success or error).
-    debug!("Attempting to acquire file download permit for file: {}", file_details.name);
-    let _permit = file_semaphore.acquire_owned().await.context("Failed to acquire file download semaphore permit")?;
-    debug!("Acquired file download permit for file: {}", file_details.name);
-    // --- File Permit Acquired ---
-
-
-    // --- Wait for Rate Limiter ---
-    debug!("Waiting for rate limit permit for file: {}", file_details.name);
-    rate_limiter.until_ready().await;
-    debug!("Acquired rate limit permit for file: {}", file_details.name);
-    // --- Rate Limit Permit Acquired ---
-
-
-    // Log unknown size warning if necessary
-    if expected_size.is_none() {
-        warn!("File size is unknown for '{}'. Downloading anyway.", file_details.name);
-        let _ = progress_tx.send(DownloadProgress::Status(format!("Warning: Unknown size for {}, downloading anyway", file_details.name))).await;
-    }
-
-
-    let download_url = format!(
-        "https://archive.org/download/{}/{}",
-        item_id,
-        // URL encode the filename part? Archive.org seems tolerant but might be safer.
-        // Using raw name for now.
-        file_details.name
-    );
-
-    // Send status via progress channel
-    // let _ = progress_tx.send(DownloadProgress::Status(format!("Checking: {}", file_details.name))).await;
-
-    // Ensure target directory exists
-    if let Some(parent_dir) = file_path.parent() {
-        debug!("Ensuring download directory exists: {}", parent_dir.display());
-        fs::create_dir_all(parent_dir).await.context(format!("Failed to create download directory '{}'", parent_dir.display()))?;
-    } else {
-        error!("Could not determine parent directory for path: {}", file_path.display());
-        return Err(anyhow!("Invalid download file path: {}", file_path.display()));
-    }
-
-    info!("Downloading '{}' from {}", file_details.name, download_url);
-    let _ = progress_tx.send(DownloadProgress::Status(format!("Downloading: {}", file_details.name))).await;
-
-    // Make the request
-    let response = client.get(&download_url).send().await.context(format!("Failed to send download request for {}", file_details.name))?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let err_msg = format!("Download request failed for '{}': Status {}", file_details.name, status);
-        error!("{}", err_msg);
-        let _ = progress_tx.send(DownloadProgress::Error(err_msg.clone())).await; // Send error via progress channel
-        return Err(anyhow!(err_msg));
-    }
-
-    // Stream the response body to the file
-    // Explicitly use tokio::fs::File::create for async operation
-    debug!("Creating target file: {}", file_path.display());
-    let mut dest = tokio::fs::File::create(&file_path).await.context(format!("Failed to create target file '{}'", file_path.display()))?;
-    let mut stream = response.bytes_stream();
-    let mut bytes_written: u64 = 0;
-
-    while let Some(chunk_result) = stream.next().await {
-        match chunk_result {
-            Ok(chunk) => {
-                let chunk_len = chunk.len() as u64;
-                if let Err(e) = dest.write_all(&chunk).await {
-                    error!("Failed to write chunk to file '{}': {}", file_path.display(), e);
-                    return Err(e).context(format!("Failed to write chunk to file '{}'", file_path.display()));
+                    Err(e) => warn!("Sync of collection '{}' failed: {:#}", collection, e),
                 }
-                bytes_written += chunk_len;
-                // Send byte count update
-                let _ = progress_tx.send(DownloadProgress::BytesDownloaded(chunk_len)).await;
-            }
-            Err(e) => {
-                 error!("Failed to read download chunk for '{}': {}", file_details.name, e);
-                 return Err(e).context(format!("Failed to read download chunk for '{}'", file_details.name));
-            }
-        }
-    }
-
-    info!("Successfully downloaded file '{}' ({} bytes)", file_details.name, bytes_written);
-    // Send completion via progress channel
-    let _ = progress_tx.send(DownloadProgress::FileCompleted(file_details.name.clone())).await;
-
-    debug!("Releasing download permit for file: {}", file_details.name); // Log before permit is dropped
-    Ok(())
-}
-
-/// Downloads all files for a given item.
-/// Path: base_dir / [collection_id] / item_id / ...
-async fn download_item(
-    client: &Client,
-    base_dir: &str,
-    collection_id: Option<&str>, // Added: Optional collection context
-    item_id: &str,
-    mode: DownloadMode, // Added: Download mode
-    progress_tx: mpsc::Sender<DownloadProgress>,
-    file_semaphore: Arc<Semaphore>, // Renamed
-    rate_limiter: AppRateLimiter, // Use the type alias
-) -> Result<()> {
-    let collection_str = collection_id.unwrap_or("<none>");
-    info!("Starting download_item: collection='{}', item='{}', mode='{:?}'", collection_str, item_id, mode);
-    let _ = progress_tx.send(DownloadProgress::ItemStarted(item_id.to_string())).await;
-
-    // --- Mode-Specific Logic ---
-    if mode == DownloadMode::TorrentOnly {
-        info!("TorrentOnly mode: Attempting direct download of {}.torrent", item_id);
-        let _ = progress_tx.send(DownloadProgress::Status(format!("Queueing torrent file for item: {}", item_id))).await;
-        let _ = progress_tx.send(DownloadProgress::ItemFileCount(1)).await; // Only 1 file to download
-
-        // Construct the expected torrent file details using the new format
-        let torrent_file_details = archive_api::FileDetails {
-            name: format!("{}_archive.torrent", item_id), // Use _archive.torrent format
-            source: None,
-            format: Some("Torrent".to_string()), // Indicate format if known
-            size: None, // Size is unknown without fetching metadata
-            md5: None,
-        };
-
-        // Ensure the parent directory for the torrent file exists
-        // Path: base_dir / collection_id / item_id.torrent -> Parent: base_dir / collection_id
-        let torrent_parent_dir = match collection_id {
-            Some(c) => Path::new(base_dir).join(c),
-            None => Path::new(base_dir).to_path_buf(), // Place directly in base if no collection?
-        };
-        debug!("Ensuring torrent parent directory exists: {}", torrent_parent_dir.display());
-        fs::create_dir_all(&torrent_parent_dir).await.context(format!("Failed to create torrent parent directory '{}'", torrent_parent_dir.display()))?;
-
-        // --- Idempotency Check for Torrent File ---
-        let torrent_file_path = torrent_parent_dir.join(&torrent_file_details.name);
-        match fs::metadata(&torrent_file_path).await {
-            Ok(metadata) if metadata.is_file() => {
-                info!("Skipping existing torrent file: '{}'", torrent_file_path.display());
-                let _ = progress_tx.send(DownloadProgress::Status(format!("Skipping (exists): {}", torrent_file_details.name))).await;
-                // Send ItemCompleted as we successfully handled this item (by skipping)
-                let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), true)).await;
-                return Ok(()); // File exists, skip download attempt
-            }
-            Ok(_) => {
-                // Path exists but is not a file (e.g., a directory) - proceed to download/overwrite?
-                // Or log a warning? For now, proceed.
-                warn!("Path exists but is not a file: '{}'. Proceeding with download.", torrent_file_path.display());
-            }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                // File does not exist, proceed with download
-                debug!("Torrent file not found: '{}'. Proceeding with download.", torrent_file_path.display());
-            }
-            Err(e) => {
-                // Other error checking metadata, log warning and proceed
-                warn!("Failed to get metadata for torrent file '{}': {}. Proceeding with download.", torrent_file_path.display(), e);
-            }
-        }
-        // --- End Idempotency Check ---
-
-        // Spawn a single task to download the assumed torrent file
-        let client_clone = client.clone();
-        let base_dir_clone = base_dir.to_string();
-        let item_id_clone = item_id.to_string();
-        let progress_tx_clone = progress_tx.clone();
-        let file_semaphore_clone = Arc::clone(&file_semaphore);
-        let limiter_clone_torrent = Arc::clone(&rate_limiter);
-        let collection_id_task_clone = collection_id.map(|s| s.to_string());
-
-        let handle = tokio::spawn(async move {
-            download_single_file(
-                &client_clone,
-                &base_dir_clone,
-                collection_id_task_clone.as_deref(),
-                &item_id_clone,
-                &torrent_file_details, // Pass the constructed details
-                progress_tx_clone,
-                file_semaphore_clone,
-                limiter_clone_torrent,
-            )
-            .await
-        });
-
-        // Wait for the single torrent download task
-        let torrent_result = handle.await;
-        let item_success = match torrent_result {
-            Ok(Ok(_)) => {
-                debug!("Assumed torrent download task completed successfully for item '{}'.", item_id);
-                true
             }
-            Ok(Err(e)) => { // Torrent download task completed but reported an error (e.g., 404)
-                warn!("Assumed torrent download failed for item '{}': {}. Fetching metadata to check if it's a collection.", item_id, e);
-                let _ = progress_tx.send(DownloadProgress::Status(format!("Torrent download failed for {}, checking metadata...", item_id))).await;
-
-                // Fetch metadata to check if it's a collection
-                let limiter_clone_details = Arc::clone(&rate_limiter);
-                match archive_api::fetch_item_details(client, item_id, limiter_clone_details).await {
+            // Handle item details API results
+            Some(result) = item_details_rx.recv() => {
+                app.is_loading_details = false; // Reset details loading state
+                match result {
                     Ok(details) => {
-                        // Check mediatype directly on the processed ItemDetails struct
-                        if details.mediatype == Some("collection".to_string()) {
-                             info!("Item '{}' is actually a collection. Skipping torrent download.", item_id);
-                             let _ = progress_tx.send(DownloadProgress::ItemSkippedWasCollection(item_id.to_string())).await;
-                             // Mark item processing as 'successful' in the sense that we handled it (by skipping)
-                                // The CollectionCompleted count won't increment 'failed' for this.
-                                return Ok(()); // Exit download_item successfully after skipping
-                        } else {
-                            // If mediatype is None or not "collection", proceed to mark as failed download
-                            warn!("Item '{}' is not a collection (mediatype: {:?}). Torrent download failed.", item_id, details.mediatype);
+                        if let Some(base_dir) = app.settings.download_directory.as_deref() {
+                            if let Err(e) = offline::save_details(base_dir, &details) {
+                                warn!("{:#}", e); // Only offline browsing misses it
+                            }
+                        }
+                        if app.network == NetworkMode::Unreachable {
+                            app.network = NetworkMode::Online; // Answered, so the network is back
                         }
+                        app.details_cached_at = None;
+                        app.show_item_details(details);
+                        fetch_thumbnail(&app, &rate_limiter, &thumbnail_tx);
                     }
-                    Err(fetch_err) => {
-                        // Metadata fetch failed after torrent download failed
-                        error!("Failed to fetch metadata for item '{}' after torrent download failed: {}", item_id, fetch_err);
-                        // Proceed to mark item as failed below
+                    Err(e) => {
+                        let cached = match (&e.kind, app.settings.download_directory.as_deref()) {
+                            (FetchDetailsErrorKind::NetworkError, Some(base_dir)) => offline::load_details(base_dir, &e.identifier).ok(),
+                            _ => None,
+                        };
+                        if app.network == NetworkMode::Online && matches!(e.kind, FetchDetailsErrorKind::NetworkError) {
+                            app.network = NetworkMode::Unreachable;
+                        }
+                        match cached {
+                            Some((details, cached_at)) => {
+                                warn!("Network unreachable, showing cached details of '{}': {}", e.identifier, e);
+                                app.details_cached_at = Some(cached_at);
+                                app.show_item_details(details);
+                                fetch_thumbnail(&app, &rate_limiter, &thumbnail_tx);
+                            }
+                            None => {
+                                // Use the Display impl of FetchDetailsError directly
+                                let err_msg = format!("Error fetching item details: {}", e);
+                                error!("{}", err_msg); // Log the error
+                                app.current_item_details = None; // Clear details on error
+                                app.file_list_state.select(None); // Reset file selection
+                                app.error_message = Some(err_msg);
+                            }
+                        }
                     }
                 }
-                // If we reach here, it means torrent download failed AND (metadata fetch failed OR it wasn't a collection)
-                // Mark the item as failed.
-                let _ = progress_tx.send(DownloadProgress::Error(format!("Torrent download failed for {}: {}", item_id, e))).await;
-                false // item_success = false
             }
-            Err(e) => { // Torrent download task panicked
-                error!("Assumed torrent download task panicked for item {}: {}", item_id, e);
-                let _ = progress_tx.send(DownloadProgress::Error(format!("Torrent download task panicked for item {}: {}", item_id, e))).await;
-                false
+            Some((identifier, result)) = thumbnail_rx.recv() => {
+                match result {
+                    Ok(image) if app.current_item_details.as_ref().is_some_and(|d| d.identifier == identifier) => {
+                        app.thumbnail = Some(Thumbnail { identifier, image: image.into() });
+                    }
+                    Ok(_) => {} // Another item is shown by now
+                    Err(e) => warn!("No cover art for '{}': {:#}", identifier, e), // The metadata shows without it
+                }
             }
-        };
-
-        // Only send ItemCompleted if we didn't already send ItemSkippedWasCollection
-        if item_success {
-            info!("Finished processing item '{}' (TorrentOnly mode - direct attempt). Success: {}", item_id, item_success);
-            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), item_success)).await;
-        } else {
-             // Failure case (torrent download failed and it wasn't identified as a collection, or task panicked)
-             info!("Finished processing item '{}' (TorrentOnly mode - direct attempt). Success: false", item_id);
-             let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await;
-        }
-        return Ok(()); // Finished processing this item in TorrentOnly mode
-
-    } else { // Direct Mode
-        // --- Fetch item details with retry logic (Only for Direct mode) ---
-        let details: ItemDetails; // Declare details, assign on success
-        let mut attempt = 0;
-        let mut backoff_secs = 1; // Initial backoff delay
-        const MAX_BACKOFF_SECS: u64 = 60 * 10; // Cap backoff at 10 minutes
-
-        loop {
-            attempt += 1;
-            let limiter_clone_details = Arc::clone(&rate_limiter);
-            let details_result = archive_api::fetch_item_details(client, item_id, limiter_clone_details).await;
-
-            match details_result {
-                Ok(fetched_details) => {
-                    info!("Successfully fetched details for item '{}' on attempt {}", item_id, attempt);
-                    details = fetched_details; // Assign directly on success
-                    break; // Exit loop on success
+            // Download jobs ending (successfully or not) leave the session journal
+            Some(journal_id) = download_finished_rx.recv() => {
+                app.session.record_finish(journal_id);
+                start_next_download(&mut app, &download_handles);
+                if app.session.entries().is_empty() {
+                    app.finish_run_report();
                 }
-                Err(e) => {
-                    // Check if the error is permanent
-                    match e.kind {
-                        archive_api::FetchDetailsErrorKind::NotFound |
-                        archive_api::FetchDetailsErrorKind::ParseError |
-                        archive_api::FetchDetailsErrorKind::ClientError(_) => {
-                            error!("Permanent error fetching details for item '{}': {}. Skipping item.", item_id, e);
-                            let _ = progress_tx.send(DownloadProgress::Error(format!("Permanent error for {}: {:?}", item_id, e.kind))).await;
-                            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), false)).await; // Mark as failed
-                            return Ok(()); // Handled permanent item error
-                        }
-                        // Otherwise, it's a transient error, proceed with retry logic
-                        _ => {
-                            warn!("Transient error fetching details for item '{}' (Attempt {}): {}. Retrying in {}s...", item_id, attempt, e, backoff_secs);
-                            let _ = progress_tx.send(DownloadProgress::Status(format!("Retrying {} (Attempt {}, Wait {}s): {:?}", item_id, attempt, backoff_secs, e.kind))).await;
-                            tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
-                            backoff_secs = (backoff_secs * 2).min(MAX_BACKOFF_SECS);
-                        }
+            }
+            // Handle the startup release check
+            Some(result) = update_check_rx.recv() => {
+                match result {
+                    Ok(Some(release)) => {
+                        info!("Update available: v{}", release.version);
+                        app.download_status = Some(format!("Update v{} available. Press 'U' for release notes.", release.version));
+                        app.available_update = Some(release);
                     }
+                    Ok(None) => info!("No update available."),
+                    Err(e) => warn!("Update check failed: {:#}", e), // Not worth interrupting the user for
                 }
             }
-        } // --- End fetch details retry loop ---
-
-        let total_files = details.files.len();
-        info!("Direct mode: Found {} files for item '{}'", total_files, item_id);
-        let _ = progress_tx.send(DownloadProgress::ItemFileCount(total_files)).await;
-
-        if details.files.is_empty() {
-            info!("No files found for item: {}. Marking as complete.", item_id);
-            let _ = progress_tx.send(DownloadProgress::Status(format!("No files found for item: {}", item_id))).await;
-            let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), true)).await;
-            return Ok(());
-        }
-
-        info!("Direct mode: Queueing {} files for item: {}", total_files, item_id);
-        let _ = progress_tx.send(DownloadProgress::Status(format!("Queueing {} files for item: {}", total_files, item_id))).await;
-
-        // For Direct mode, ensure the item-specific directory exists, as files (other than torrents) go there.
-        // Path: base_dir / [collection_id] / item_id
-        let item_dir = match collection_id {
-            Some(c) => Path::new(base_dir).join(c).join(item_id),
-            None => Path::new(base_dir).join(item_id),
-        };
-        debug!("Ensuring item directory exists for non-torrent files: {}", item_dir.display());
-        fs::create_dir_all(&item_dir).await.context(format!("Failed to create item directory '{}'", item_dir.display()))?;
-
-
-        let mut file_join_handles = vec![];
-        let mut item_failed = false; // Track if any file task fails
-
-        // Spawn a download task for each file concurrently
-        for file in details.files { // Iterate by value to move into tasks
-            // Clone necessary data for the file download task
-            let client_clone = client.clone();
-            let base_dir_clone = base_dir.to_string();
-         let item_id_clone = item_id.to_string();
-         let progress_tx_clone = progress_tx.clone();
-         let file_semaphore_clone = Arc::clone(&file_semaphore); // Use renamed semaphore
-         let limiter_clone_file = Arc::clone(&rate_limiter); // Clone limiter for file download
-         let file_clone = file.clone();
-         // Clone collection_id for the task (as Option<String>)
-         let collection_id_task_clone = collection_id.map(|s| s.to_string());
-
-
-         let handle = tokio::spawn(async move {
-             // Call download_single_file, passing the optional collection ID
-             download_single_file(
-                 &client_clone,
-                 &base_dir_clone,
-                 collection_id_task_clone.as_deref(), // Pass optional collection ID as &str
-                 &item_id_clone,
-                 &file_clone,
-                 progress_tx_clone,
-                 file_semaphore_clone, // Pass renamed semaphore
-                 limiter_clone_file, // Pass limiter
-             )
-             .await
-         });
-         file_join_handles.push(handle);
-     }
-
-     // Wait for all file download tasks for this item to complete (Direct Mode)
-     for handle in file_join_handles {
-         match handle.await {
-             Ok(Ok(_)) => {
-                 debug!("File download task completed successfully for item '{}'.", item_id);
-             }
-             Ok(Err(e)) => {
-                 item_failed = true;
-                 // Error already logged and sent by download_single_file, just log context here.
-                 error!("File download task failed within item {}: {}", item_id, e);
-                 // Optionally send another status update if needed, but Error should have been sent.
-                 // let _ = progress_tx.send(DownloadProgress::Status(format!("File download failed within item {}: {}", item_id, e))).await;
-             }
-             Err(e) => { // Task panicked or was cancelled
-                 item_failed = true;
-                 error!("File download task panicked or was cancelled for item {}: {}", item_id, e);
-                 let _ = progress_tx.send(DownloadProgress::Error(format!("File download task panicked for item {}: {}", item_id, e))).await;
-             }
-         }
-     }
-
-     // Send item completion status based on whether any file task failed (Direct Mode)
-     let success_status = !item_failed;
-     info!("Finished processing item '{}' (Direct mode). Success: {}", item_id, success_status);
-     let _ = progress_tx.send(DownloadProgress::ItemCompleted(item_id.to_string(), success_status)).await;
-
-     // Return Ok even if some files failed, ItemCompleted indicates success/failure of the item overall
-     Ok(())
-    } // End else block for Direct Mode
-} // End download_item function
-
-/// Downloads all items for a specific collection identifier.
-async fn download_collection(
-    client: &Client,
-    base_dir: &str,
-    collection_id: &str, // Now takes specific collection ID
-    mode: DownloadMode, // Added: Download mode
-    progress_tx: mpsc::Sender<DownloadProgress>,
-    file_semaphore: Arc<Semaphore>, // Renamed file download semaphore
-    collection_item_semaphore: Arc<Semaphore>, // Added item processing semaphore
-    rate_limiter: AppRateLimiter, // Use the type alias
-) -> Result<()> {
-    info!("Starting download_collection for '{}', mode: {:?}", collection_id, mode);
-
-    // --- Identifier Caching Logic ---
-    let cache_file_name = format!("{}.identifiers.json", collection_id);
-    let cache_path = Path::new(base_dir).join(&cache_file_name);
-    let mut all_identifiers: Vec<String> = Vec::new();
-    let mut use_cache = false;
-
-    // 1. Check if cache file exists
-    if cache_path.exists() {
-        info!("Found identifier cache file: {}", cache_path.display());
-        let _ = progress_tx.send(DownloadProgress::Status(format!("Loading identifiers from cache: {}", cache_file_name))).await;
-        match TokioFile::open(&cache_path).await {
-            Ok(mut file) => {
-                let mut contents = String::new();
-                if file.read_to_string(&mut contents).await.is_ok() {
-                    match serde_json::from_str::<Vec<String>>(&contents) {
-                        Ok(cached_ids) => {
-                            if !cached_ids.is_empty() {
-                                info!("Successfully loaded {} identifiers from cache: {}", cached_ids.len(), cache_path.display());
-                                all_identifiers = cached_ids;
-                                use_cache = true;
-                            } else {
-                                warn!("Cache file is empty or invalid: {}. Re-fetching.", cache_path.display());
+            // Handle upload progress
+            Some(progress) = upload_rx.recv() => {
+                app.apply_upload_progress(progress);
+            }
+            // Handle the result of logging in
+            Some(result) = login_rx.recv() => {
+                match result {
+                    Ok(login) => {
+                        let saved = settings::secret_store().and_then(|store| {
+                            auth::save_login(store, &login.credentials)?;
+                            // The account's S3 keys come with the login; keep any set by hand
+                            match login.s3_keys {
+                                Some(keys) if app.s3_keys.is_none() => {
+                                    ia_upload::save_keys(store, &keys)?;
+                                    app.s3_keys = Some(keys);
+                                }
+                                _ => {}
                             }
+                            Ok(())
+                        });
+                        if let Err(e) = saved {
+                            let err_msg = format!("Logged in, but failed to save the login: {:#}", e);
+                            error!("{}", err_msg);
+                            app.error_message = Some(err_msg);
                         }
-                        Err(e) => {
-                            warn!("Failed to parse cache file JSON '{}': {}. Re-fetching.", cache_path.display(), e);
-                            // Attempt to delete the invalid cache file? Or just overwrite later.
-                            let _ = fs::remove_file(&cache_path).await; // Try removing invalid cache
+                        if app.settings.archive_screenname.is_none() && login.screenname.is_some() {
+                            // Lets 'F' import this account's favorites without asking
+                            app.settings.archive_screenname = login.screenname.clone();
+                            if let Err(e) = settings::save_settings(&app.settings) {
+                                error!("Failed to save settings: {}", e);
+                            }
                         }
+                        app.download_status = Some(format!("Logged in to archive.org as '{}'", login.credentials.display_user()));
+                        app.set_credentials(Some(login.credentials));
+                    }
+                    Err(e) => {
+                        warn!("archive.org login failed: {:#}", e);
+                        app.download_status = None;
+                        app.error_message = Some(format!("{:#}", e));
                     }
-                } else {
-                    warn!("Failed to read cache file '{}'. Re-fetching.", cache_path.display());
                 }
             }
-            Err(e) => {
-                warn!("Failed to open cache file '{}': {}. Re-fetching.", cache_path.display(), e);
+            // Handle Wayback Machine lookups and downloads
+            Some(progress) = wayback_rx.recv() => {
+                app.apply_wayback_progress(progress);
             }
-        }
-    }
-
-    // 2. Fetch from API if cache wasn't used
-    if !use_cache {
-        info!("Fetching identifiers from API for collection: {}", collection_id);
-        let _ = progress_tx.send(DownloadProgress::Status(format!("Fetching identifiers from API: {}", collection_id))).await;
-
-        // --- Use bulk fetch to get identifiers ---
-        // No temporary channel needed here, call directly
-        let client_clone_ids = client.clone();
-        let collection_id_clone_ids = collection_id.to_string(); // Keep clone for error messages
-        let limiter_clone_ids = Arc::clone(&rate_limiter);
-
-        // Call the bulk fetch function directly
-        match archive_api::fetch_collection_items_bulk(&client_clone_ids, collection_id, limiter_clone_ids).await {
-            Ok((fetched_items, _total_found)) => {
-                 // Extract identifiers from fetched items
-                 all_identifiers = fetched_items.into_iter().map(|doc| doc.identifier).collect();
-                 info!("Fetched {} identifiers via bulk API for collection '{}'", all_identifiers.len(), collection_id);
+            // Handle the verification of an item ('verify' in the command palette)
+            Some((identifier, result)) = verify_rx.recv() => {
+                app.apply_verify_result(&identifier, result);
             }
-            Err(e) => {
-                 // Propagate error if fetch failed
-                 let err_msg = format!("Failed to get identifiers for {}: {}", collection_id_clone_ids, e);
-                 error!("{}", err_msg);
-                 let _ = progress_tx.send(DownloadProgress::Error(err_msg.clone())).await;
-                 let _ = progress_tx.send(DownloadProgress::CollectionCompleted(0, 0)).await;
-                 return Err(anyhow!(err_msg)); // Return the error
+            // Handle archive.org titles for favorite collections
+            Some(result) = titles_rx.recv() => {
+                match result {
+                    Ok(titles) => app.apply_collection_titles(titles),
+                    Err(e) => warn!("Failed to fetch collection titles: {:#}", e), // The identifiers show instead
+                }
             }
-        }
-        // --- End bulk fetch ---
-
-        // 3. Save fetched identifiers to cache (only if fetch was successful)
-        if !all_identifiers.is_empty() {
-            if !all_identifiers.is_empty() {
-                    match serde_json::to_string_pretty(&all_identifiers) {
-                        Ok(json_data) => {
-                            // Ensure parent directory exists (should already from download setup, but good practice)
-                            if let Some(parent) = cache_path.parent() {
-                                if let Err(e) = fs::create_dir_all(parent).await {
-                                     warn!("Failed to ensure cache directory exists '{}': {}", parent.display(), e);
-                                     // Proceed without saving cache if dir creation fails
-                                } else {
-                                    // Write to cache file
-                                    match TokioFile::create(&cache_path).await {
-                                        Ok(mut file) => {
-                                            if let Err(e) = file.write_all(json_data.as_bytes()).await {
-                                                warn!("Failed to write to cache file '{}': {}", cache_path.display(), e);
-                                            } else {
-                                                info!("Successfully saved {} identifiers to cache: {}", all_identifiers.len(), cache_path.display());
-                                            }
-                                        }
-                                        Err(e) => {
-                                            warn!("Failed to create cache file '{}': {}", cache_path.display(), e);
-                                        }
-                                    }
-                                }
-                            } else {
-                                warn!("Could not determine parent directory for cache file: {}", cache_path.display());
-                            }
+            // Handle the favorites import
+            Some((user, result)) = favorites_rx.recv() => {
+                match result {
+                    Ok(found) => {
+                        let added = favorites::merge(&mut app.settings.favorite_collections, &found);
+                        app.settings.archive_screenname = Some(user.clone());
+                        if app.collection_list_state.selected().is_none() && !app.settings.favorite_collections.is_empty() {
+                            app.collection_list_state.select(Some(0));
                         }
-                        Err(e) => {
-                            warn!("Failed to serialize identifiers to JSON for caching: {}", e);
+                        if let Err(e) = settings::save_settings(&app.settings) {
+                            let err_msg = format!("Failed to save settings: {}", e);
+                            error!("{}", err_msg);
+                            app.error_message = Some(err_msg);
                         }
+                        info!("Imported {} of {} collections from the favorites of '{}'", added, found.len(), user);
+                        app.download_status = Some(format!(
+                            "Imported {} collections from the favorites of '{}' ({} already listed)",
+                            added,
+                            user,
+                            found.len() - added
+                        ));
+                    }
+                    Err(e) => {
+                        warn!("Favorites import failed: {:#}", e);
+                        app.download_status = None;
+                        app.error_message = Some(format!("{:#}", e));
                     }
-                } // End of if !all_identifiers.is_empty()
-                else { // Add the else block here
-                    info!("No identifiers fetched from API, cache file not created/updated.");
                 }
-            } // End of else block (fetch_error is None)
-        } // End of if !use_cache block
-    // --- End Identifier Caching Logic --- // Removed extra brace before this comment
-
-
-    if all_identifiers.is_empty() {
-        info!("No items found in collection (or cache): {}. Download complete.", collection_id);
-        let _ = progress_tx.send(DownloadProgress::Status(format!("No items found in collection: {}", collection_id))).await;
-        let _ = progress_tx.send(DownloadProgress::CollectionCompleted(0, 0)).await;
-        return Ok(());
-    }
-
-    let total_items = all_identifiers.len();
-    info!("Found {} items to download for collection '{}'", total_items, collection_id);
-    // Send total item count for this collection download
-    let _ = progress_tx.send(DownloadProgress::CollectionInfo(total_items)).await;
-    let _ = progress_tx.send(DownloadProgress::Status(format!("Queueing {} items for collection: {}", total_items, collection_id))).await;
-
-    let mut join_handles = vec![];
-    let mut total_failed_items = 0; // Count items where download_item itself returned Err or panicked
-
-    // Iterate through identifiers and spawn item download tasks
-    for item_id in all_identifiers.into_iter() {
-        // Acquire item processing permit *before* spawning
-        debug!("Attempting to acquire item processing permit for item: {}", item_id);
-        let item_permit = match collection_item_semaphore.clone().acquire_owned().await {
-            Ok(permit) => {
-                debug!("Acquired item processing permit for item: {}", item_id);
-                permit
-            },
-            Err(e) => {
-                error!("Failed to acquire item processing permit for item {}: {}", item_id, e);
-                // Skip this item if permit acquisition fails
-                total_failed_items += 1;
-                continue;
             }
-        };
-        debug!("Acquired item processing permit for item: {}", item_id);
+            // Handle the self-update result
+            Some(result) = update_install_rx.recv() => {
+                match result {
+                    Ok(version) => {
+                        info!("Binary updated to {}", version);
+                        app.available_update = None;
+                        app.error_message = None;
+                        app.download_status = Some(format!("Updated to v{}. Restart archiver to use it.", version));
+                    }
+                    Err(e) => {
+                        let err_msg = format!("Self-update failed: {:#}", e);
+                        error!("{}", err_msg);
+                        app.download_status = None;
+                        app.error_message = Some(err_msg);
+                    }
+                }
+            }
+        }
+    }
 
-        // Clone data needed for the item download task
-        let client_clone = client.clone();
-        let base_dir_clone = base_dir.to_string();
-        let progress_tx_clone = progress_tx.clone();
-        let file_semaphore_clone = Arc::clone(&file_semaphore); // Pass file semaphore down
-        let limiter_clone_item = Arc::clone(&rate_limiter); // Clone limiter for item download
-        let item_id_clone = item_id.clone(); // Keep clone for task
-        let collection_id_clone = collection_id.to_string(); // Clone collection ID for task
+    app.library.flush(); // Statuses recorded since the last periodic write
 
-        let handle = tokio::spawn(async move {
-            // download_item handles fetching details and spawning file downloads based on mode
-            // It uses the file_semaphore passed down for individual file permits
-            let item_result = download_item(
-                &client_clone,
-                &base_dir_clone,
-                Some(&collection_id_clone), // Pass collection ID context (now cloned)
-                &item_id_clone,
-                mode, // Pass the download mode down
-                progress_tx_clone.clone(),
-                file_semaphore_clone, // Pass file semaphore
-                limiter_clone_item, // Pass limiter
-            )
-            .await;
-            // Drop the item permit when the task finishes
-            drop(item_permit);
-            debug!("Released item processing permit for item: {}", item_id_clone);
-            item_result // Return result (Ok or Err)
-        });
-        join_handles.push(handle);
+    // Exit the user interface.
+    if let Err(e) = tui.exit() {
+        error!("Failed to exit TUI cleanly: {}", e);
+        // Continue shutdown despite TUI exit error
+    } else {
+        info!("TUI exited successfully.");
     }
 
-    // Wait for all item download tasks for this collection to complete
-    info!("Waiting for {} item download tasks for collection '{}'...", join_handles.len(), collection_id);
-    for handle in join_handles {
-        match handle.await {
-            Ok(Ok(_)) => {
-                debug!("Item download task completed successfully for collection '{}'.", collection_id);
-            }
-            Ok(Err(e)) => {
-                // Error should have been logged within download_item (e.g., failed details fetch)
-                error!("Item download task failed for collection '{}': {}", collection_id, e);
-                total_failed_items += 1;
-            }
-            Err(e) => { // Task panicked or was cancelled
-                error!("Item download task panicked or was cancelled for collection '{}': {}", collection_id, e);
-                total_failed_items += 1;
+    // Write the performance report after the terminal is restored so the path is visible.
+    if options.profile_perf {
+        let report_path = perf::default_report_path();
+        match perf::write_report(&report_path) {
+            Ok(_) => {
+                info!("Performance report written to {}", report_path.display());
+                println!("Performance report written to {}", report_path.display());
             }
+            Err(e) => error!("{:#}", e),
         }
-        // Note: Individual file errors within an item are handled by download_item
-        // and reflected in the ItemCompleted message's success flag.
-        // total_failed_items here counts items where the top-level download_item task failed.
     }
 
-    info!("Finished collection download for '{}'. Total items: {}, Failed items: {}",
-          collection_id, total_items, total_failed_items);
-    // Send final completion status for this specific collection download
-    let _ = progress_tx.send(DownloadProgress::CollectionCompleted(total_items, total_failed_items)).await;
-
+    info!("Application shutting down.");
     Ok(())
-} // Re-adding the closing brace for download_collection
-
-// TODO: Implement multi-collection download logic using max_concurrent_collections semaphore.
-// This would likely involve another layer of task spawning in main.rs or a dedicated function.
+}
@@ -0,0 +1,842 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use reqwest::Url;
+use serde_json::json;
+use std::{collections::{BTreeMap, HashMap}, sync::{Mutex, OnceLock}, thread};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+};
+
+/// Thumbnail of every mock item: a 1x1 PNG.
+pub const MOCK_THUMBNAIL: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0\x1f\x15\xc4\x89\0\0\0\nIDATx\x9cc\0\x01\0\0\x05\0\x01\r\n-\xb4\0\0\0\0IEND\xaeB`\x82";
+/// Identifier of the canned collection served by the mock.
+pub const MOCK_COLLECTION: &str = "mock_collection";
+/// Identifiers of the items inside [`MOCK_COLLECTION`].
+pub const MOCK_ITEMS: [&str; 2] = ["mock_item_one", "mock_item_two"];
+/// Items per scrape API page; smaller than [`MOCK_ITEMS`] so listings span several pages.
+pub const MOCK_SCRAPE_PAGE_SIZE: usize = 1;
+/// Files present in every mock item: (name, format, source, size in bytes).
+pub const MOCK_FILES: [(&str, &str, &str, usize); 2] = [
+    ("track01.mp3", "VBR MP3", "derivative", 4096),
+    ("cover.jpg", "JPEG", "original", 1024),
+];
+
+/// Length archive.org lists for the mock `.mp3` files, in seconds.
+pub const MOCK_TRACK_LENGTH: &str = "245.30";
+/// MusicBrainz release the mock (`/musicbrainz/ws/2/`) finds for items by "Mock Artist".
+pub const MOCK_MUSICBRAINZ_RELEASE: &str = "0b5c3e64-6d3b-4c4e-9a0f-000000000001";
+
+/// Lending-only item (not part of [`MOCK_COLLECTION`]) whose `.epub` requires a login.
+pub const MOCK_RESTRICTED_ITEM: &str = "mock_lending_item";
+/// Files of [`MOCK_RESTRICTED_ITEM`]: (name, private, size in bytes).
+pub const MOCK_RESTRICTED_FILES: [(&str, bool, usize); 2] = [
+    ("book.epub", true, 2048),
+    ("book_meta.xml", false, 512),
+];
+/// `logged-in-sig` cookie value the mock accepts as a session entitled to the lending item.
+pub const MOCK_LOGIN_SIG: &str = "mock-entitled-sig";
+/// Account the mock login service (`/services/xauthn/`) accepts; logging in hands out [`MOCK_LOGIN_SIG`].
+pub const MOCK_LOGIN_EMAIL: &str = "mock@example.com";
+/// Password of [`MOCK_LOGIN_EMAIL`].
+pub const MOCK_LOGIN_PASSWORD: &str = "mock-password";
+/// Screen name of the mock account; its favorites collection (`fav-mockuser`) holds
+/// [`MOCK_COLLECTION`] and the first of [`MOCK_ITEMS`].
+pub const MOCK_FAVORITES_USER: &str = "mockuser";
+/// Session id the mock Transmission RPC endpoint (`/transmission/rpc`) hands out.
+pub const MOCK_TRANSMISSION_SESSION: &str = "mock-transmission-session";
+
+/// S3 access key the mock upload endpoint (`/s3/<identifier>/<file>`) accepts.
+pub const MOCK_S3_ACCESS: &str = "mock-access";
+/// S3 secret key the mock upload endpoint accepts.
+pub const MOCK_S3_SECRET: &str = "mock-secret";
+
+/// URL with Wayback Machine captures in the mock (`/wayback/cdx/search/cdx`).
+pub const MOCK_WAYBACK_URL: &str = "http://example.com/";
+/// Captures of [`MOCK_WAYBACK_URL`], oldest first: (timestamp, status, digest).
+pub const MOCK_WAYBACK_CAPTURES: [(&str, &str, &str); 3] = [
+    ("20050101000000", "200", "DIGESTONE"),
+    ("20080615120000", "404", "DIGESTMISSING"),
+    ("20120301083000", "200", "DIGESTTWO"),
+];
+
+/// A file received by the mock upload endpoint.
+#[derive(Debug, Clone)]
+pub struct MockUpload {
+    pub identifier: String,
+    pub name: String,
+    pub body: Vec<u8>,
+    /// `x-archive-*` / `x-amz-*` request headers (lowercased names).
+    pub headers: Vec<(String, String)>,
+}
+
+/// Files uploaded to the mock, in arrival order.
+static UPLOADS: Mutex<Vec<MockUpload>> = Mutex::new(Vec::new());
+
+/// Files of a directory added through the mock IPFS API: (name, size).
+type IpfsFiles = Vec<(String, usize)>;
+/// Directories added through the mock IPFS API, by root CID.
+static IPFS_ADDS: Mutex<Vec<(String, IpfsFiles)>> = Mutex::new(Vec::new());
+
+/// Files (name below the added directory's parent, size) of the directory added as `cid`.
+pub fn ipfs_added(cid: &str) -> IpfsFiles {
+    IPFS_ADDS.lock().ok().and_then(|adds| adds.iter().find(|(root, _)| root == cid).map(|(_, files)| files.clone())).unwrap_or_default()
+}
+
+/// Files uploaded to `identifier` so far.
+pub fn uploads(identifier: &str) -> Vec<MockUpload> {
+    UPLOADS.lock().map(|uploads| uploads.iter().filter(|u| u.identifier == identifier).cloned().collect()).unwrap_or_default()
+}
+
+/// Objects in the mock bucket (served below `/bucket/`), by key.
+static BUCKET: Mutex<BTreeMap<String, Vec<u8>>> = Mutex::new(BTreeMap::new());
+/// Parts of the bucket's unfinished multipart uploads: upload id, then part number.
+static BUCKET_UPLOADS: Mutex<BTreeMap<String, BTreeMap<u32, Vec<u8>>>> = Mutex::new(BTreeMap::new());
+
+/// The object `key` of the mock bucket, once its upload finished.
+pub fn bucket_object(key: &str) -> Option<Vec<u8>> {
+    BUCKET.lock().ok()?.get(key).cloned()
+}
+
+/// Multipart uploads of the mock bucket neither completed nor aborted.
+pub fn bucket_uploads_pending() -> usize {
+    BUCKET_UPLOADS.lock().map_or(0, |uploads| uploads.len())
+}
+
+/// Shared mock instance used by tests, started on first use.
+static SHARED_BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Starts the mock server on a dedicated thread with its own runtime and returns its base URL
+/// (e.g. `http://127.0.0.1:41234`). The server lives until the process exits, so it survives
+/// the per-test runtimes created by `#[tokio::test]`.
+pub fn start_background() -> Result<String> {
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<String>>();
+    thread::Builder::new()
+        .name("mock-archive-org".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(anyhow!(e).context("Failed to build mock server runtime")));
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let (addr_tx, addr_rx) = oneshot::channel();
+                let server = tokio::spawn(serve("127.0.0.1:0", addr_tx));
+                match addr_rx.await {
+                    Ok(Ok(base_url)) => {
+                        let _ = ready_tx.send(Ok(base_url));
+                    }
+                    Ok(Err(e)) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                    Err(_) => {
+                        let _ = ready_tx.send(Err(anyhow!("Mock server exited before binding")));
+                        return;
+                    }
+                }
+                let _ = server.await;
+            });
+        })
+        .context("Failed to spawn mock server thread")?;
+    ready_rx.recv().context("Mock server thread exited unexpectedly")?
+}
+
+/// Returns the base URL of a process-wide mock server, starting it if needed.
+///
+/// # Panics
+/// Panics if the server cannot be started (only used from tests and dev mode).
+pub fn shared_base_url() -> &'static str {
+    SHARED_BASE_URL.get_or_init(|| start_background().expect("Failed to start mock archive.org server"))
+}
+
+/// Accepts connections forever, answering each request from the canned fixtures.
+async fn serve(bind_addr: &str, addr_tx: oneshot::Sender<Result<String>>) {
+    let listener = match TcpListener::bind(bind_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            let _ = addr_tx.send(Err(anyhow!(e).context("Failed to bind mock server")));
+            return;
+        }
+    };
+    let base_url = match listener.local_addr() {
+        Ok(addr) => format!("http://{}", addr),
+        Err(e) => {
+            let _ = addr_tx.send(Err(anyhow!(e).context("Failed to read mock server address")));
+            return;
+        }
+    };
+    info!("Mock archive.org server listening on {}", base_url);
+    let _ = addr_tx.send(Ok(base_url));
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream).await {
+                        warn!("Mock server connection error: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Mock server accept failed: {}", e),
+        }
+    }
+}
+
+/// A canned HTTP response.
+struct MockResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+    /// Extra response headers (`Content-Range` of a 206, Transmission's session id).
+    headers: Vec<(&'static str, String)>,
+}
+
+impl MockResponse {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self { status: 200, content_type, body, headers: Vec::new() }
+    }
+
+    fn not_found() -> Self {
+        Self { status: 404, content_type: "text/plain", body: b"Not Found".to_vec(), headers: Vec::new() }
+    }
+
+    fn forbidden() -> Self {
+        Self { status: 403, content_type: "text/plain", body: b"Forbidden".to_vec(), headers: Vec::new() }
+    }
+
+    /// Applies a `Range: bytes=<start>-[<end>]` request header to a successful response.
+    fn with_range(self, range: &str) -> Self {
+        let Some((start, end)) = range.strip_prefix("bytes=").and_then(|r| r.split_once('-')) else {
+            return self; // Unsupported forms are ignored, as servers may do
+        };
+        let len = self.body.len();
+        let (Ok(start), Ok(end)) = (start.parse::<usize>(), if end.is_empty() { Ok(len.saturating_sub(1)) } else { end.parse::<usize>() }) else {
+            return self;
+        };
+        let end = end.min(len.saturating_sub(1));
+        if self.status != 200 {
+            self
+        } else if start >= len || start > end {
+            Self { status: 416, content_type: "text/plain", body: Vec::new(), headers: vec![("Content-Range", format!("bytes */{}", len))] }
+        } else {
+            let headers = vec![("Content-Range", format!("bytes {}-{}/{}", start, end, len))];
+            Self { status: 206, body: self.body[start..=end].to_vec(), headers, ..self }
+        }
+    }
+}
+
+/// Reads one request from the stream and writes the matching fixture back.
+async fn handle_connection(stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Skip headers until the blank line, keeping the cookies for the lending item
+    let mut cookie = String::new();
+    let mut range = None;
+    let mut session_id = None;
+    let mut content_length = 0;
+    let mut authorization = String::new();
+    let mut archive_headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("cookie") {
+                cookie = value.trim().to_string();
+            } else if name.eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("x-transmission-session-id") {
+                session_id = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = value.trim().to_string();
+            } else if name.to_lowercase().starts_with("x-archive-") || name.to_lowercase().starts_with("x-amz-") {
+                archive_headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    debug!("Mock server request: {}", request_line.trim());
+    let mut response = if target == "/transmission/rpc" {
+        transmission_response(session_id.as_deref(), &body)
+    } else if request_line.starts_with("POST ") && target.starts_with("/services/xauthn/") {
+        login_response(&body)
+    } else if request_line.starts_with("POST ") && target.starts_with("/ipfs/api/v0/add") {
+        ipfs_add_response(&body)
+    } else if request_line.starts_with("PUT ") && target.starts_with("/s3/") {
+        s3_put_response(target, &authorization, archive_headers, body)
+    } else if target.starts_with("/bucket/") {
+        bucket_response(request_line.split_whitespace().next().unwrap_or("GET"), target, &authorization, body)
+    } else {
+        route(target, &cookie)
+    };
+    if let Some(range) = range {
+        response = response.with_range(&range);
+    }
+
+    let reason = match response.status {
+        200 => "OK",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        409 => "Conflict",
+        416 => "Range Not Satisfiable",
+        _ => "Not Found",
+    };
+    let extra_headers: String = response.headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len(),
+        extra_headers
+    );
+    let mut stream = reader.into_inner();
+    stream.write_all(head.as_bytes()).await?;
+    if !request_line.starts_with("HEAD ") {
+        stream.write_all(&response.body).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Maps a request target (path + query) onto a fixture. `cookie` is the request's
+/// `Cookie` header (empty if none).
+fn route(target: &str, cookie: &str) -> MockResponse {
+    let Ok(url) = Url::parse(&format!("http://mock{}", target)) else {
+        return MockResponse::not_found();
+    };
+    let path = url.path().to_string();
+
+    if path == "/advancedsearch.php" {
+        let query = url
+            .query_pairs()
+            .find(|(k, _)| k == "q")
+            .map(|(_, v)| v.into_owned())
+            .unwrap_or_default();
+        let callback = url.query_pairs().find(|(k, _)| k == "callback").map(|(_, v)| v.into_owned());
+        let body = search_response(&query).to_string();
+        let body = match callback {
+            Some(cb) => format!("{}({})", cb, body),
+            None => body,
+        };
+        return MockResponse::ok("application/javascript", body.into_bytes());
+    }
+
+    if path == "/services/search/v1/scrape" {
+        let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+        let body = scrape_response(&param("q").unwrap_or_default(), param("cursor").as_deref()).to_string();
+        return MockResponse::ok("application/json", body.into_bytes());
+    }
+
+    if let Some(identifier) = path.strip_prefix("/metadata/") {
+        return match metadata_response(identifier) {
+            Some(json) => MockResponse::ok("application/json", json.to_string().into_bytes()),
+            // archive.org answers unknown identifiers with 200 and an empty object
+            None => MockResponse::ok("application/json", b"{}".to_vec()),
+        };
+    }
+
+    if path == "/wayback/cdx/search/cdx" {
+        let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+        let body = cdx_response(&param("url").unwrap_or_default(), param("from").as_deref(), param("to").as_deref());
+        return MockResponse::ok("application/json", body.into_bytes());
+    }
+
+    if path == "/musicbrainz/ws/2/release" {
+        let query = url.query_pairs().find(|(k, _)| k == "query").map(|(_, v)| v.into_owned()).unwrap_or_default();
+        return MockResponse::ok("application/json", musicbrainz_search_response(&query).to_string().into_bytes());
+    }
+
+    if path == format!("/musicbrainz/ws/2/release/{}", MOCK_MUSICBRAINZ_RELEASE) {
+        return MockResponse::ok("application/json", musicbrainz_release().to_string().into_bytes());
+    }
+
+    if let Some(capture) = path.strip_prefix("/wayback/web/") {
+        if let Some(bytes) = capture.split_once("id_/").and_then(|(timestamp, original)| wayback_capture(timestamp, original)) {
+            return MockResponse::ok("text/html", bytes);
+        }
+    }
+
+    if let Some(identifier) = path.strip_prefix("/services/img/") {
+        if MOCK_ITEMS.contains(&identifier) {
+            return MockResponse::ok("image/png", MOCK_THUMBNAIL.to_vec());
+        }
+    }
+
+    if let Some(rest) = path.strip_prefix("/download/") {
+        if let Some((identifier, file_name)) = rest.split_once('/') {
+            let entitled = cookie.split(';').any(|c| c.trim() == format!("logged-in-sig={}", MOCK_LOGIN_SIG));
+            if is_private_file(identifier, file_name) && !entitled {
+                return MockResponse::forbidden();
+            }
+            if let Some(bytes) = file_bytes(identifier, file_name) {
+                return MockResponse::ok("application/octet-stream", bytes);
+            }
+        }
+    }
+
+    MockResponse::not_found()
+}
+
+/// Builds an advancedsearch response for `query`.
+fn search_response(query: &str) -> serde_json::Value {
+    let docs = matching_docs(query);
+    json!({
+        "responseHeader": { "status": 0 },
+        "response": { "numFound": docs.len(), "start": 0, "docs": docs }
+    })
+}
+
+/// Builds a scrape API page for `query`, serving [`MOCK_SCRAPE_PAGE_SIZE`] items per
+/// page so clients have to follow the cursor (the item index to continue from).
+fn scrape_response(query: &str, cursor: Option<&str>) -> serde_json::Value {
+    let docs = matching_docs(query);
+    let start = cursor.and_then(|c| c.parse::<usize>().ok()).unwrap_or(0).min(docs.len());
+    let end = (start + MOCK_SCRAPE_PAGE_SIZE).min(docs.len());
+    let mut page = json!({ "items": docs[start..end], "count": end - start, "total": docs.len() });
+    if end < docs.len() {
+        page["cursor"] = json!(end.to_string());
+    }
+    page
+}
+
+/// Search index entries of the mock items matching `query`.
+fn matching_docs(query: &str) -> Vec<serde_json::Value> {
+    // Collection queries list the fixture collection; anything else is a keyword search
+    // matching identifiers and titles.
+    if query == format!("collection:\"fav-{}\"", MOCK_FAVORITES_USER) {
+        return vec![
+            json!({ "identifier": MOCK_COLLECTION, "title": "Mock Collection", "mediatype": "collection" }),
+            json!({ "identifier": MOCK_ITEMS[0], "title": format!("Mock Item {}", MOCK_ITEMS[0]), "mediatype": "audio" }),
+        ];
+    }
+    // Title lookups name the identifiers they want
+    if let Some(list) = query.strip_prefix("identifier:(").and_then(|q| q.strip_suffix(')')) {
+        let wanted: Vec<&str> = list.split(" OR ").map(|id| id.trim_matches('"')).collect();
+        let known = std::iter::once((MOCK_COLLECTION.to_string(), "Mock Collection".to_string()))
+            .chain(MOCK_ITEMS.iter().map(|id| (id.to_string(), format!("Mock Item {}", id))));
+        return known
+            .filter(|(id, _)| wanted.contains(&id.as_str()))
+            .map(|(identifier, title)| json!({ "identifier": identifier, "title": title }))
+            .collect();
+    }
+    let needle = query.to_lowercase();
+    let matches = |id: &str, title: &str| {
+        if query.starts_with("collection:") {
+            query == format!("collection:\"{}\"", MOCK_COLLECTION)
+        } else {
+            id.contains(&needle) || title.to_lowercase().contains(&needle)
+        }
+    };
+    MOCK_ITEMS
+        .iter()
+        .map(|id| (id, format!("Mock Item {}", id)))
+        .filter(|(id, title)| matches(id, title))
+        .map(|(id, title)| {
+            json!({
+                "identifier": id,
+                "title": title,
+                "mediatype": "audio",
+                "format": MOCK_FILES.iter().map(|(_, format, _, _)| *format).collect::<Vec<_>>(),
+                "item_size": item_size(),
+            })
+        })
+        .collect()
+}
+
+/// CDX API answer listing the captures of `url` between `from` and `to` (timestamp
+/// prefixes); an empty body when there are none, like the real API.
+fn cdx_response(url: &str, from: Option<&str>, to: Option<&str>) -> String {
+    let wanted = |timestamp: &str| {
+        from.is_none_or(|from| timestamp[..from.len().min(14)] >= *from) && to.is_none_or(|to| timestamp[..to.len().min(14)] <= *to)
+    };
+    let known = url.trim_start_matches("http://").trim_end_matches('/') == MOCK_WAYBACK_URL.trim_start_matches("http://").trim_end_matches('/');
+    let rows: Vec<serde_json::Value> = MOCK_WAYBACK_CAPTURES
+        .iter()
+        .filter(|(timestamp, _, _)| known && wanted(timestamp))
+        .map(|(timestamp, status, digest)| json!([timestamp, MOCK_WAYBACK_URL, "text/html", status, digest, "512"]))
+        .collect();
+    if rows.is_empty() {
+        return String::new();
+    }
+    let header = json!(["timestamp", "original", "mimetype", "statuscode", "digest", "length"]);
+    json!(std::iter::once(header).chain(rows).collect::<Vec<_>>()).to_string()
+}
+
+/// Page served for a capture of [`MOCK_WAYBACK_URL`]; `None` for unknown captures.
+fn wayback_capture(timestamp: &str, original: &str) -> Option<Vec<u8>> {
+    let known = original == MOCK_WAYBACK_URL && MOCK_WAYBACK_CAPTURES.iter().any(|(t, status, _)| *t == timestamp && *status == "200");
+    known.then(|| format!("<html><body>Example Domain, captured {}</body></html>", timestamp).into_bytes())
+}
+
+/// Answers a Transmission RPC call: 409 until the request carries
+/// [`MOCK_TRANSMISSION_SESSION`], then `torrent-add` accepts metainfo that looks like a
+/// bencoded dictionary (base64 of `d...` starts with `Z`).
+fn transmission_response(session_id: Option<&str>, body: &[u8]) -> MockResponse {
+    if session_id != Some(MOCK_TRANSMISSION_SESSION) {
+        return MockResponse {
+            status: 409,
+            content_type: "text/html",
+            body: b"Conflict".to_vec(),
+            headers: vec![("X-Transmission-Session-Id", MOCK_TRANSMISSION_SESSION.to_string())],
+        };
+    }
+    let request: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    let metainfo = request["arguments"]["metainfo"].as_str().unwrap_or_default();
+    let result = if request["method"] != "torrent-add" {
+        "method name not recognized"
+    } else if metainfo.starts_with('Z') {
+        "success"
+    } else {
+        "invalid or corrupt torrent file"
+    };
+    MockResponse::ok("application/json", json!({ "result": result, "arguments": {} }).to_string().into_bytes())
+}
+
+/// Answers a login form post like archive.org's xauthn service: cookies and S3 keys for
+/// [`MOCK_LOGIN_EMAIL`]/[`MOCK_LOGIN_PASSWORD`], a failure reason otherwise.
+fn login_response(body: &[u8]) -> MockResponse {
+    let form = String::from_utf8_lossy(body);
+    let fields: HashMap<String, String> = Url::parse(&format!("http://mock/?{}", form))
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default();
+    let field = |name: &str| fields.get(name).map(String::as_str).unwrap_or("");
+    if field("email") != MOCK_LOGIN_EMAIL {
+        let body = json!({ "success": false, "values": { "reason": "account_not_found" } });
+        return MockResponse { status: 401, content_type: "application/json", body: body.to_string().into_bytes(), headers: Vec::new() };
+    }
+    if field("password") != MOCK_LOGIN_PASSWORD {
+        let body = json!({ "success": false, "values": { "reason": "account_bad_password" } });
+        return MockResponse { status: 401, content_type: "application/json", body: body.to_string().into_bytes(), headers: Vec::new() };
+    }
+    let body = json!({
+        "success": true,
+        "values": {
+            "cookies": { "logged-in-user": "mock%40example.com", "logged-in-sig": MOCK_LOGIN_SIG },
+            "s3": { "access": MOCK_S3_ACCESS, "secret": MOCK_S3_SECRET },
+            "screenname": MOCK_FAVORITES_USER,
+        },
+    });
+    MockResponse::ok("application/json", body.to_string().into_bytes())
+}
+
+/// Accepts an S3 upload signed with [`MOCK_S3_ACCESS`]/[`MOCK_S3_SECRET`] and records it.
+fn s3_put_response(target: &str, authorization: &str, headers: Vec<(String, String)>, body: Vec<u8>) -> MockResponse {
+    if authorization != format!("LOW {}:{}", MOCK_S3_ACCESS, MOCK_S3_SECRET) {
+        let error = "<Error><Code>InvalidAccessKeyId</Code><Message>The S3 access key is not valid.</Message></Error>";
+        return MockResponse { status: 403, content_type: "application/xml", body: error.as_bytes().to_vec(), headers: Vec::new() };
+    }
+    let Ok(url) = Url::parse(&format!("http://mock{}", target)) else {
+        return MockResponse::not_found();
+    };
+    let Some((identifier, name)) = url.path().trim_start_matches("/s3/").split_once('/') else {
+        return MockResponse::not_found();
+    };
+    let upload = MockUpload {
+        identifier: identifier.to_string(),
+        name: percent_decode(name),
+        body,
+        headers,
+    };
+    if let Ok(mut uploads) = UPLOADS.lock() {
+        uploads.push(upload);
+    }
+    MockResponse::ok("text/plain", Vec::new())
+}
+
+/// An S3-compatible bucket for downloads into object storage: single-request and
+/// multipart uploads, size checks and aborts, signed (Signature Version 4) with
+/// [`MOCK_S3_ACCESS`]. Signatures themselves aren't checked.
+fn bucket_response(method: &str, target: &str, authorization: &str, body: Vec<u8>) -> MockResponse {
+    let xml = |status, body: String| MockResponse { status, content_type: "application/xml", body: body.into_bytes(), headers: Vec::new() };
+    if !authorization.starts_with(&format!("AWS4-HMAC-SHA256 Credential={}/", MOCK_S3_ACCESS)) {
+        return xml(403, "<Error><Code>InvalidAccessKeyId</Code><Message>The access key is not valid.</Message></Error>".to_string());
+    }
+    let Ok(url) = Url::parse(&format!("http://mock{}", target)) else {
+        return MockResponse::not_found();
+    };
+    let key = percent_decode(url.path().trim_start_matches("/bucket/"));
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    let (Ok(mut objects), Ok(mut uploads)) = (BUCKET.lock(), BUCKET_UPLOADS.lock()) else {
+        return MockResponse::not_found();
+    };
+    match (method, query.get("uploadId")) {
+        ("POST", None) if query.contains_key("uploads") => {
+            let upload_id = format!("mock-upload-{}", uploads.len() + objects.len());
+            uploads.insert(upload_id.clone(), BTreeMap::new());
+            xml(200, format!("<InitiateMultipartUploadResult><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>", key, upload_id))
+        }
+        ("PUT", Some(upload_id)) => {
+            let (Some(parts), Some(number)) = (uploads.get_mut(upload_id), query.get("partNumber").and_then(|n| n.parse().ok())) else {
+                return xml(404, "<Error><Code>NoSuchUpload</Code><Message>No such upload.</Message></Error>".to_string());
+            };
+            let etag = format!("\"part-{}-{}\"", number, body.len());
+            parts.insert(number, body);
+            MockResponse { headers: vec![("ETag", etag)], ..MockResponse::ok("text/plain", Vec::new()) }
+        }
+        ("POST", Some(upload_id)) => {
+            let Some(parts) = uploads.remove(upload_id) else {
+                return xml(404, "<Error><Code>NoSuchUpload</Code><Message>No such upload.</Message></Error>".to_string());
+            };
+            let listed = String::from_utf8_lossy(&body).matches("<Part>").count();
+            if listed != parts.len() {
+                return xml(200, "<Error><Code>InvalidPart</Code><Message>Parts are missing.</Message></Error>".to_string());
+            }
+            objects.insert(key.clone(), parts.into_values().flatten().collect());
+            xml(200, format!("<CompleteMultipartUploadResult><Key>{}</Key></CompleteMultipartUploadResult>", key))
+        }
+        ("DELETE", Some(upload_id)) => {
+            uploads.remove(upload_id);
+            MockResponse::ok("text/plain", Vec::new())
+        }
+        ("PUT", None) => {
+            let etag = format!("\"{}\"", crate::verify::md5_hex(&body));
+            objects.insert(key, body);
+            MockResponse { headers: vec![("ETag", etag)], ..MockResponse::ok("text/plain", Vec::new()) }
+        }
+        ("HEAD", None) => match objects.get(&key) {
+            // Like S3, the ETag of a single PUT is the object's MD5
+            Some(object) => MockResponse { headers: vec![("ETag", format!("\"{}\"", crate::verify::md5_hex(object)))], ..MockResponse::ok("application/octet-stream", object.clone()) },
+            None => MockResponse::not_found(),
+        },
+        _ => MockResponse::not_found(),
+    }
+}
+
+/// Answers an IPFS `add` of a multipart body the way kubo does: one JSON line per entry,
+/// the added directory last. CIDs are fake but stable for the same content.
+fn ipfs_add_response(body: &[u8]) -> MockResponse {
+    let Some(delimiter) = body.split(|&b| b == b'\r').next().filter(|line| line.starts_with(b"--")) else {
+        return MockResponse { status: 400, content_type: "text/plain", body: b"Not multipart".to_vec(), headers: Vec::new() };
+    };
+    let mut separator = b"\r\n".to_vec();
+    separator.extend_from_slice(delimiter);
+    let mut parts = Vec::new(); // (name, is directory, content)
+    let mut rest = &body[delimiter.len()..];
+    while rest.starts_with(b"\r\n") {
+        let Some(head_end) = find_bytes(rest, b"\r\n\r\n") else { break };
+        let head = String::from_utf8_lossy(&rest[2..head_end]).into_owned();
+        let content_start = head_end + 4;
+        let Some(content_len) = find_bytes(&rest[content_start..], &separator) else { break };
+        let name = head.split("filename=\"").nth(1).and_then(|n| n.split('"').next()).map(percent_decode).unwrap_or_default();
+        parts.push((name, head.contains("application/x-directory"), rest[content_start..content_start + content_len].to_vec()));
+        rest = &rest[content_start + content_len + separator.len()..];
+    }
+    let fake_cid = |data: &[u8]| format!("bafymock{}", &crate::verify::md5_hex(data)[..24]);
+    let mut lines: Vec<String> = parts
+        .iter()
+        .filter(|(_, dir, _)| !dir)
+        .map(|(name, _, content)| json!({ "Name": name, "Hash": fake_cid(content), "Size": content.len().to_string() }).to_string())
+        .collect();
+    let Some((root, _, _)) = parts.first().filter(|(_, dir, _)| *dir) else {
+        return MockResponse { status: 400, content_type: "text/plain", body: b"No directory".to_vec(), headers: Vec::new() };
+    };
+    let root_cid = fake_cid(body);
+    lines.push(json!({ "Name": root, "Hash": root_cid, "Size": body.len().to_string() }).to_string());
+    let files = parts.iter().filter(|(_, dir, _)| !dir).map(|(name, _, content)| (name.clone(), content.len())).collect();
+    if let Ok(mut adds) = IPFS_ADDS.lock() {
+        adds.push((root_cid, files));
+    }
+    MockResponse::ok("application/json", (lines.join("\n") + "\n").into_bytes())
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// MusicBrainz release search: [`MOCK_MUSICBRAINZ_RELEASE`] for queries by the mock
+/// items' creator, nothing otherwise.
+fn musicbrainz_search_response(query: &str) -> serde_json::Value {
+    if !query.contains("artist:\"Mock Artist\"") {
+        return json!({ "releases": [] });
+    }
+    let audio_files = MOCK_FILES.iter().filter(|(name, _, _, _)| name.ends_with(".mp3")).count();
+    json!({ "releases": [{ "id": MOCK_MUSICBRAINZ_RELEASE, "score": 100, "title": "Mock Release", "track-count": audio_files }] })
+}
+
+/// MusicBrainz release lookup of [`MOCK_MUSICBRAINZ_RELEASE`]: one track per mock `.mp3`,
+/// about [`MOCK_TRACK_LENGTH`] long.
+fn musicbrainz_release() -> serde_json::Value {
+    let tracks: Vec<serde_json::Value> = MOCK_FILES
+        .iter()
+        .filter(|(name, _, _, _)| name.ends_with(".mp3"))
+        .enumerate()
+        .map(|(i, _)| json!({ "id": format!("mock-track-{}", i + 1), "number": (i + 1).to_string(), "length": 246000, "recording": { "id": format!("mock-recording-{}", i + 1) } }))
+        .collect();
+    json!({
+        "id": MOCK_MUSICBRAINZ_RELEASE,
+        "title": "Mock Release",
+        "artist-credit": [{ "name": "Mock Artist", "artist": { "id": "mock-artist" } }],
+        "media": [{ "position": 1, "tracks": tracks }]
+    })
+}
+
+/// Builds the metadata endpoint response for a fixture identifier.
+fn metadata_response(identifier: &str) -> Option<serde_json::Value> {
+    if identifier == MOCK_COLLECTION {
+        return Some(json!({
+            "metadata": {
+                "identifier": MOCK_COLLECTION,
+                "title": "Mock Collection",
+                "mediatype": "collection",
+            },
+            "files": []
+        }));
+    }
+    if identifier == MOCK_RESTRICTED_ITEM {
+        let files: Vec<serde_json::Value> = MOCK_RESTRICTED_FILES
+            .iter()
+            .map(|(name, private, size)| {
+                let mut file = json!({ "name": name, "format": "EPUB", "source": "original", "size": size.to_string() });
+                if *private {
+                    file["private"] = json!("true");
+                }
+                file
+            })
+            .collect();
+        return Some(json!({
+            "metadata": {
+                "identifier": MOCK_RESTRICTED_ITEM,
+                "title": "Mock Lending Item",
+                "mediatype": "texts",
+                "access-restricted-item": "true",
+            },
+            "files": files
+        }));
+    }
+    if !MOCK_ITEMS.contains(&identifier) {
+        return None;
+    }
+    let other = MOCK_ITEMS.iter().find(|item| **item != identifier).copied().unwrap_or(identifier);
+    let files: Vec<serde_json::Value> = MOCK_FILES
+        .iter()
+        .map(|(name, format, source, size)| {
+            let md5 = file_bytes(identifier, name).map(|bytes| crate::verify::md5_hex(&bytes));
+            let mut file = json!({ "name": name, "format": format, "source": source, "size": size.to_string(), "md5": md5 });
+            if name.ends_with(".mp3") {
+                file["length"] = json!(MOCK_TRACK_LENGTH);
+            }
+            file
+        })
+        .collect();
+    Some(json!({
+        "metadata": {
+            "identifier": identifier,
+            "title": format!("Mock Item {}", identifier),
+            "creator": "Mock Artist",
+            "date": "2020-01-01",
+            "mediatype": "audio",
+            "collection": [MOCK_COLLECTION],
+            "description": format!("Canned item served by the mock archive.org server. See also https://archive.org/details/{}", other)
+        },
+        "files": files,
+        "reviews": [{
+            "reviewtitle": "Mock review",
+            "reviewbody": "Canned review of the mock item.",
+            "reviewer": "Mock Listener",
+            "reviewdate": "2021-01-01 00:00:00",
+            "stars": "5"
+        }]
+    }))
+}
+
+/// Total size of all files in a mock item.
+fn item_size() -> usize {
+    MOCK_FILES.iter().map(|(_, _, _, size)| size).sum()
+}
+
+/// Whether a fixture file is only served to an entitled session.
+fn is_private_file(identifier: &str, file_name: &str) -> bool {
+    identifier == MOCK_RESTRICTED_ITEM
+        && MOCK_RESTRICTED_FILES.iter().any(|(name, private, _)| *name == file_name && *private)
+}
+
+/// Deterministic content for a fixture file, or `None` if it doesn't exist.
+pub fn file_bytes(identifier: &str, file_name: &str) -> Option<Vec<u8>> {
+    let size = if identifier == MOCK_RESTRICTED_ITEM {
+        MOCK_RESTRICTED_FILES.iter().find(|(name, _, _)| *name == file_name)?.2
+    } else if !MOCK_ITEMS.contains(&identifier) {
+        return None;
+    } else if file_name == format!("{}_archive.torrent", identifier) {
+        return Some(item_torrent(identifier));
+    } else {
+        MOCK_FILES.iter().find(|(name, _, _, _)| *name == file_name)?.3
+    };
+    let pattern = format!("{}/{}\n", identifier, file_name);
+    Some(pattern.bytes().cycle().take(size).collect())
+}
+
+/// A valid torrent of a mock item's files (no trackers, so clients fall back to HTTP).
+fn item_torrent(identifier: &str) -> Vec<u8> {
+    let files: Vec<(&str, Vec<u8>)> =
+        MOCK_FILES.iter().map(|(name, _, _, _)| (*name, file_bytes(identifier, name).unwrap_or_default())).collect();
+    crate::torrent::build_torrent(identifier, &files, 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_unknown_path_is_404() {
+        assert_eq!(route("/nope", "").status, 404);
+        assert_eq!(route("/download/unknown_item/file.mp3", "").status, 404);
+    }
+
+    #[test]
+    fn test_route_search_wraps_jsonp() {
+        let response = route("/advancedsearch.php?q=collection%3A%22mock_collection%22&callback=callback", "");
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.starts_with("callback("));
+        assert!(body.contains("mock_item_one"));
+    }
+
+    #[test]
+    fn test_file_bytes_have_declared_size() {
+        let bytes = file_bytes("mock_item_one", "track01.mp3").unwrap();
+        assert_eq!(bytes.len(), 4096);
+        assert!(file_bytes("mock_item_one", "missing.bin").is_none());
+    }
+
+    #[test]
+    fn test_route_private_file_requires_entitled_cookie() {
+        let target = format!("/download/{}/book.epub", MOCK_RESTRICTED_ITEM);
+        assert_eq!(route(&target, "").status, 403);
+        assert_eq!(route(&target, "logged-in-user=me; logged-in-sig=wrong").status, 403);
+        let cookie = format!("logged-in-user=me; logged-in-sig={}", MOCK_LOGIN_SIG);
+        assert_eq!(route(&target, &cookie).status, 200);
+        assert_eq!(route(&format!("/download/{}/book_meta.xml", MOCK_RESTRICTED_ITEM), "").status, 200);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::archive_api::{self, FileDetails, ItemDetails};
+use crate::tagging;
+use crate::updater::{BIN_NAME, CURRENT_VERSION, REPO_NAME, REPO_OWNER};
+use anyhow::{Context, Result};
+use log::{debug, info};
+use reqwest::{header::USER_AGENT, Client};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The public MusicBrainz web service.
+const MUSICBRAINZ_BASE_URL: &str = "https://musicbrainz.org/ws/2";
+/// MusicBrainz allows one request per second per client.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Lowest search score (0-100) a release needs to be considered.
+const MIN_SCORE: u32 = 90;
+/// Best-scored releases whose track lists are compared with an item.
+const MAX_CANDIDATES: usize = 3;
+/// How far a file's length may be from its track's, in seconds.
+const LENGTH_TOLERANCE_SECS: f64 = 5.0;
+
+/// The MusicBrainz web service, or `<base>/musicbrainz/ws/2` when the archive.org base
+/// URL is overridden (the mock server).
+pub fn endpoint() -> String {
+    let base = archive_api::base_url();
+    if base == archive_api::DEFAULT_BASE_URL {
+        MUSICBRAINZ_BASE_URL.to_string()
+    } else {
+        format!("{}/musicbrainz/ws/2", base)
+    }
+}
+
+/// MusicBrainz identifiers (MBIDs) of one track of a matched release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackIds {
+    pub release_id: String,
+    /// First credited artist of the release.
+    pub artist_id: Option<String>,
+    pub release_track_id: String,
+    pub recording_id: String,
+}
+
+/// The release an item is a copy of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseMatch {
+    pub release_id: String,
+    pub title: String,
+    /// Identifiers of each matched file, by file name inside the item.
+    pub tracks: HashMap<String, TrackIds>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    #[serde(default)]
+    releases: Vec<SearchRelease>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchRelease {
+    id: String,
+    #[serde(default)]
+    score: u32,
+    #[serde(rename = "track-count", default)]
+    track_count: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    id: String,
+    title: String,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArtistCredit {
+    artist: Artist,
+}
+
+#[derive(Deserialize, Debug)]
+struct Artist {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Medium {
+    #[serde(default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Track {
+    id: String,
+    /// Milliseconds.
+    length: Option<u64>,
+    recording: Recording,
+}
+
+#[derive(Deserialize, Debug)]
+struct Recording {
+    id: String,
+}
+
+/// Client for the MusicBrainz web service, paced to its rate limit.
+pub struct MusicBrainz {
+    client: Client,
+    base_url: String,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl MusicBrainz {
+    pub fn new(client: Client) -> Self {
+        Self { client, base_url: endpoint(), last_request: Mutex::new(None) }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<T> {
+        {
+            // Held while waiting, so concurrent lookups queue up behind each other
+            let mut last_request = self.last_request.lock().await;
+            if let Some(last) = *last_request {
+                tokio::time::sleep_until(last + REQUEST_INTERVAL).await;
+            }
+            *last_request = Some(Instant::now());
+        }
+        let url = format!("{}{}", self.base_url, path);
+        debug!("MusicBrainz request: {} {:?}", url, query);
+        self.client
+            .get(&url)
+            .query(query)
+            .query(&[("fmt", "json")])
+            // MusicBrainz asks clients to identify themselves with a contact URL
+            .header(USER_AGENT, format!("{}/{} ( https://github.com/{}/{} )", BIN_NAME, CURRENT_VERSION, REPO_OWNER, REPO_NAME))
+            .send()
+            .await
+            .context(format!("MusicBrainz request to {} failed", url))?
+            .error_for_status()
+            .context(format!("MusicBrainz request to {} failed", url))?
+            .json()
+            .await
+            .context("Failed to parse MusicBrainz response")
+    }
+
+    /// Finds the release `details` is a copy of: one found by the item's title and creator
+    /// whose track list has as many tracks as the item has files of one audio format, each
+    /// as long as the file in the same position. `Ok(None)` if nothing matches.
+    pub async fn match_item(&self, details: &ItemDetails, files: &[FileDetails]) -> Result<Option<ReleaseMatch>> {
+        let (Some(album), Some(artist)) = (details.title.as_deref(), details.creator.as_deref()) else { return Ok(None) };
+        let groups = audio_groups(files);
+        if groups.is_empty() {
+            return Ok(None);
+        }
+        let query = format!("release:{} AND artist:{}", quoted(album), quoted(artist));
+        let found: SearchResponse = self.get("/release", &[("query", query.as_str()), ("limit", "10")]).await?;
+        let candidates = found
+            .releases
+            .into_iter()
+            .filter(|release| release.score >= MIN_SCORE && groups.iter().any(|group| group.len() == release.track_count))
+            .take(MAX_CANDIDATES);
+        for candidate in candidates {
+            let release: Release = self.get(&format!("/release/{}", candidate.id), &[("inc", "recordings artist-credits")]).await?;
+            let tracks = match_tracks(&release, &groups);
+            if !tracks.is_empty() {
+                info!("Item '{}' matches MusicBrainz release {} ({})", details.identifier, release.id, release.title);
+                return Ok(Some(ReleaseMatch { release_id: release.id, title: release.title, tracks }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A Lucene phrase for the search API.
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The taggable audio files, by format (extension), each in track order.
+fn audio_groups(files: &[FileDetails]) -> Vec<Vec<&FileDetails>> {
+    let mut groups: BTreeMap<String, Vec<&FileDetails>> = BTreeMap::new();
+    for file in files.iter().filter(|f| tagging::is_taggable(&f.name)) {
+        let extension = file.name.rsplit_once('.').map_or("", |(_, ext)| ext).to_ascii_lowercase();
+        groups.entry(extension).or_default().push(file);
+    }
+    let track_number = |file: &FileDetails| -> Option<u32> {
+        let track = file.track.as_deref()?.trim();
+        track.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()
+    };
+    groups
+        .into_values()
+        .map(|mut group| {
+            group.sort_by(|a, b| track_number(a).cmp(&track_number(b)).then_with(|| a.name.cmp(&b.name)));
+            group
+        })
+        .collect()
+}
+
+/// Seconds of a file's `length`, which archive.org gives as seconds ("245.32") or as
+/// "m:ss" / "h:mm:ss".
+fn parse_length(length: &str) -> Option<f64> {
+    length.trim().split(':').try_fold(0.0, |total, part| Some(total * 60.0 + part.parse::<f64>().ok()?))
+}
+
+/// Identifiers of the files of every group whose lengths match the release's tracks.
+fn match_tracks(release: &Release, groups: &[Vec<&FileDetails>]) -> HashMap<String, TrackIds> {
+    let tracks: Vec<&Track> = release.media.iter().flat_map(|medium| &medium.tracks).collect();
+    let artist_id = release.artist_credit.first().map(|credit| credit.artist.id.clone());
+    let mut matched = HashMap::new();
+    for group in groups.iter().filter(|group| group.len() == tracks.len()) {
+        let lengths_match = group.iter().zip(&tracks).all(|(file, track)| {
+            match (file.length.as_deref().and_then(parse_length), track.length) {
+                (Some(file_secs), Some(track_ms)) => (file_secs - track_ms as f64 / 1000.0).abs() <= LENGTH_TOLERANCE_SECS,
+                _ => false,
+            }
+        });
+        if !lengths_match {
+            continue;
+        }
+        for (file, track) in group.iter().zip(&tracks) {
+            let ids = TrackIds {
+                release_id: release.id.clone(),
+                artist_id: artist_id.clone(),
+                release_track_id: track.id.clone(),
+                recording_id: track.recording.id.clone(),
+            };
+            matched.insert(file.name.clone(), ids);
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, track: &str, length: &str) -> FileDetails {
+        FileDetails { name: name.to_string(), track: Some(track.to_string()), length: Some(length.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!(parse_length("245.5"), Some(245.5));
+        assert_eq!(parse_length("4:05"), Some(245.0));
+        assert_eq!(parse_length("1:00:01"), Some(3601.0));
+        assert_eq!(parse_length("n/a"), None);
+    }
+
+    #[test]
+    fn test_tracks_match_by_order_and_length() {
+        let release: Release = serde_json::from_value(serde_json::json!({
+            "id": "rel",
+            "title": "Live",
+            "artist-credit": [{ "artist": { "id": "art" } }],
+            "media": [
+                { "tracks": [{ "id": "t1", "length": 120000, "recording": { "id": "r1" } }] },
+                { "tracks": [{ "id": "t2", "length": 301500, "recording": { "id": "r2" } }] }
+            ]
+        }))
+        .unwrap();
+        let files = vec![
+            file("d2t01.flac", "2", "5:00"),
+            file("d1t01.flac", "1", "121.2"),
+            file("d1t01.mp3", "1", "121.2"),
+            file("d2t01.mp3", "2", "200.0"), // Different recording
+            file("cover.jpg", "", ""),
+        ];
+        let matched = match_tracks(&release, &audio_groups(&files));
+        assert_eq!(matched.len(), 2, "Only the FLAC files match: {:?}", matched);
+        assert_eq!(matched["d1t01.flac"].recording_id, "r1");
+        assert_eq!(matched["d2t01.flac"].release_track_id, "t2");
+        assert_eq!(matched["d2t01.flac"].artist_id.as_deref(), Some("art"));
+        assert_eq!(quoted("Say \"hi\""), "\"Say \\\"hi\\\"\"");
+    }
+}
@@ -0,0 +1,170 @@
+use crate::archive_api::ItemDetails;
+use crate::forecast;
+use crate::fs_util;
+use anyhow::{anyhow, Context, Result};
+use log::{debug, error};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Directory (below the download directory) holding the item list of each collection; the
+/// details of viewed items are kept in its `details` subdirectory.
+pub const CACHE_DIR: &str = ".item_cache";
+/// Lifetimes "Item Details Cache" steps through, in hours; 0 fetches details every time.
+const TTL_STEPS_HOURS: [u64; 6] = [0, 1, 6, 24, 72, 168];
+/// Shown when an action that needs the network is refused in offline mode.
+pub const NEEDS_NETWORK: &str = "Offline mode: this needs the network. Press 'N' to go online.";
+
+/// Where item listings and details come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NetworkMode {
+    #[default]
+    Online,
+    /// Offline mode is on: only the caches are read and no requests are made.
+    Offline,
+    /// A request failed because the network is down. Cached data is shown where there is
+    /// some; uncached data is still requested, and the first answer switches back online.
+    Unreachable,
+}
+
+impl NetworkMode {
+    /// The mode after the `offline_mode` setting was (re)loaded.
+    pub fn with_setting(self, offline_mode: bool) -> Self {
+        match (offline_mode, self) {
+            (true, _) => NetworkMode::Offline,
+            (false, NetworkMode::Offline) => NetworkMode::Online,
+            (false, mode) => mode,
+        }
+    }
+
+    /// Whether listings and details are read from the caches first.
+    pub fn is_offline(self) -> bool {
+        self != NetworkMode::Online
+    }
+
+    /// Whether data missing from the caches may be requested.
+    pub fn allows_requests(self) -> bool {
+        self != NetworkMode::Offline
+    }
+
+    /// Status bar marker, `None` online.
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            NetworkMode::Online => None,
+            NetworkMode::Offline => Some("Offline"),
+            NetworkMode::Unreachable => Some("Network down"),
+        }
+    }
+
+    /// Pane title marker saying the data shown may be stale: ` [Offline: cached 3h 12m ago]`,
+    /// or ` [cached 5m ago]` online. Empty for data fresh from the network.
+    pub fn stale_marker(self, cached_at: Option<SystemTime>, now: SystemTime) -> String {
+        let age = cached_at.map(|at| forecast::format_duration(now.duration_since(at).unwrap_or_default()));
+        match (self.label(), age) {
+            (Some(label), Some(age)) => format!(" [{}: cached {} ago]", label, age),
+            (Some(label), None) => format!(" [{}]", label),
+            (None, Some(age)) => format!(" [cached {} ago]", age),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Cache file of the details of item `identifier`.
+pub fn details_path(base_dir: &str, identifier: &str) -> PathBuf {
+    Path::new(base_dir).join(CACHE_DIR).join("details").join(format!("{}.json", identifier.replace(['/', '\\'], "_")))
+}
+
+/// Saves the details of a viewed item for offline browsing.
+pub fn save_details(base_dir: &str, details: &ItemDetails) -> Result<()> {
+    let path = details_path(base_dir, &details.identifier);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("Failed to create cache directory: {}", dir.display()))?;
+    }
+    let json = serde_json::to_string(details).context("Failed to serialize item details")?;
+    fs_util::atomic_write(&path, json).context(format!("Failed to write item details cache: {}", path.display()))
+}
+
+/// Loads the cached details of item `identifier` and when they were saved. A corrupt
+/// cache file is moved aside.
+pub fn load_details(base_dir: &str, identifier: &str) -> Result<(ItemDetails, SystemTime)> {
+    let path = details_path(base_dir, identifier);
+    let json = fs::read_to_string(&path).map_err(|_| anyhow!("No cached details for '{}'", identifier))?;
+    let details = serde_json::from_str(&json).map_err(|e| {
+        if let Err(backup_err) = fs_util::backup_corrupt_file(&path) {
+            error!("{:#}", backup_err);
+        }
+        anyhow!(e).context(format!("Failed to parse item details cache: {}", path.display()))
+    })?;
+    debug!("Loaded details of '{}' from {}", identifier, path.display());
+    Ok((details, modified(&path).unwrap_or_else(SystemTime::now)))
+}
+
+/// Whether details cached at `cached_at` are recent enough to show without fetching them
+/// again (`Settings::details_cache_hours`; 0 = never).
+pub fn is_fresh(cached_at: SystemTime, ttl_hours: u64, now: SystemTime) -> bool {
+    now.duration_since(cached_at).is_ok_and(|age| age < Duration::from_secs(ttl_hours * 60 * 60))
+}
+
+/// Next longer (or shorter) details cache lifetime.
+pub fn step_ttl(hours: u64, forward: bool) -> u64 {
+    if forward {
+        TTL_STEPS_HOURS.iter().copied().find(|&h| h > hours).unwrap_or(hours)
+    } else {
+        TTL_STEPS_HOURS.iter().copied().rev().find(|&h| h < hours).unwrap_or(0)
+    }
+}
+
+/// The settings row: "Off", "6 h" or "3 days".
+pub fn format_ttl(hours: u64) -> String {
+    match hours {
+        0 => "Off".to_string(),
+        h if h >= 48 && h % 24 == 0 => format!("{} days", h / 24),
+        h => format!("{} h", h),
+    }
+}
+
+/// When the cache file at `path` was last written.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_details_cache_round_trip_freshness_and_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_str().unwrap();
+        assert!(load_details(base, "gd1977").is_err(), "Nothing cached yet");
+        let details = ItemDetails { identifier: "gd1977".to_string(), title: Some("Cornell".to_string()), ..Default::default() };
+        save_details(base, &details).unwrap();
+        let (cached, at) = load_details(base, "gd1977").unwrap();
+        assert_eq!(cached.title.as_deref(), Some("Cornell"));
+
+        fs::write(details_path(base, "broken"), "{").unwrap();
+        assert!(load_details(base, "broken").is_err());
+        assert!(!details_path(base, "broken").exists(), "The corrupt file was moved aside");
+
+        let later = at + Duration::from_secs(3 * 3600 + 12 * 60);
+        assert_eq!(NetworkMode::Online.stale_marker(Some(at), later), " [cached 3h 12m ago]");
+        assert_eq!(NetworkMode::Online.stale_marker(None, later), "");
+        assert!(is_fresh(at, 4, later) && !is_fresh(at, 3, later) && !is_fresh(at, 0, at));
+        assert_eq!(NetworkMode::Offline.stale_marker(Some(at), later), " [Offline: cached 3h 12m ago]");
+        assert_eq!(NetworkMode::Unreachable.stale_marker(None, later), " [Network down]");
+
+        assert_eq!(NetworkMode::Unreachable.with_setting(false), NetworkMode::Unreachable);
+        assert_eq!(NetworkMode::Unreachable.with_setting(true), NetworkMode::Offline);
+        assert_eq!(NetworkMode::Offline.with_setting(false), NetworkMode::Online);
+        assert!(!NetworkMode::Offline.allows_requests() && NetworkMode::Unreachable.allows_requests());
+
+        assert_eq!(step_ttl(24, true), 72);
+        assert_eq!(step_ttl(5, false), 1);
+        assert_eq!(step_ttl(168, true), 168);
+        assert_eq!(format_ttl(step_ttl(0, true)), "1 h");
+        assert_eq!(format_ttl(72), "3 days");
+    }
+}
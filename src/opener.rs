@@ -0,0 +1,92 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::{
+    ffi::OsStr,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Opens `path` with the desktop's default application (`xdg-open`, `open` or `start`).
+///
+/// Returns once the launcher has started; it is reaped on a background thread so the
+/// TUI never waits for the viewer to close.
+pub fn open_path(path: &Path) -> Result<()> {
+    spawn_detached(open_command(path.as_os_str()), &format!("'{}'", path.display()))
+}
+
+/// Opens `url` in the default browser, the same way as [`open_path`].
+pub fn open_url(url: &str) -> Result<()> {
+    spawn_detached(open_command(OsStr::new(url)), url)
+}
+
+/// Streams `url` in the media player `player` (a command with optional arguments, like
+/// `vlc --play-and-exit`).
+pub fn play_url(player: &str, url: &str) -> Result<()> {
+    match play_command(player, url) {
+        Some(command) => spawn_detached(command, url),
+        None => bail!("Set a media player in the settings first"),
+    }
+}
+
+fn spawn_detached(mut command: Command, target: &str) -> Result<()> {
+    // The launcher must not draw over or read from the TUI's terminal
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    let mut child = command
+        .spawn()
+        .context(format!("Failed to run {:?} to open {}", command.get_program(), target))?;
+    debug!("Opening {} with {:?}", target, command.get_program());
+    std::thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => warn!("Opener exited with {}", status),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to wait for opener: {}", e),
+    });
+    Ok(())
+}
+
+fn open_command(target: &OsStr) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg(target);
+        command
+    } else if cfg!(windows) {
+        // `start` is a cmd builtin; its first quoted argument is the window title
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]).arg(target);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(target);
+        command
+    }
+}
+
+/// The player's command split on whitespace, with `url` as the last argument.
+fn play_command(player: &str, url: &str) -> Option<Command> {
+    let mut words = player.split_whitespace();
+    let mut command = Command::new(words.next()?);
+    command.args(words).arg(url);
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_command_passes_path_as_single_argument() {
+        let path = Path::new("/downloads/coll/item/My Book.epub");
+        let command = open_command(path.as_os_str());
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args.last().copied(), Some(path.as_os_str()));
+    }
+
+    #[test]
+    fn test_play_command_appends_url_to_player_arguments() {
+        let url = "https://archive.org/download/gd1977/d1t01.flac";
+        let command = play_command("vlc  --play-and-exit", url).unwrap();
+        assert_eq!(command.get_program(), "vlc");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["--play-and-exit", url]);
+        assert!(play_command("  ", url).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::app::{ActivePane, AppState};
+use crate::filters;
+use crate::keymap::{Action, KeyContext};
+
+/// Contexts whose actions apply in `state` (with `pane` focused while browsing).
+pub fn contexts(state: &AppState, pane: &ActivePane) -> Vec<KeyContext> {
+    match (state, pane) {
+        (AppState::Browsing, ActivePane::Collections) => vec![KeyContext::Global, KeyContext::Browsing, KeyContext::Collections],
+        (AppState::Browsing, ActivePane::Items) => vec![KeyContext::Global, KeyContext::Browsing, KeyContext::Items],
+        (AppState::ViewingItem, _) => vec![KeyContext::Global, KeyContext::Item],
+        (AppState::ViewingDownloads, _) => vec![KeyContext::Global, KeyContext::Downloads],
+        (AppState::ViewingFailures, _) => vec![KeyContext::Global, KeyContext::Failures],
+        _ => vec![KeyContext::Global],
+    }
+}
+
+/// What a query is matched against: the action's name (with spaces) and description.
+fn search_text(action: Action) -> String {
+    format!("{} {}", action.name().replace('_', " "), action.description())
+}
+
+/// Actions of `contexts` matching `query` (see [`filters::fuzzy_positions`]), those whose
+/// match starts earliest and has the fewest gaps first. An empty query lists them all.
+pub fn matching(query: &str, contexts: &[KeyContext]) -> Vec<Action> {
+    let mut found: Vec<(usize, Action)> = Action::all()
+        .filter(|action| *action != Action::CommandPalette && action.contexts().iter().any(|c| contexts.contains(c)))
+        .filter_map(|action| {
+            let positions = filters::fuzzy_positions(query, &search_text(action))?;
+            // Index of the last matched character minus the matched ones: leading chars plus gaps
+            Some((positions.last().map_or(0, |last| last + 1 - positions.len()), action))
+        })
+        .collect();
+    found.sort_by_key(|(score, _)| *score);
+    found.into_iter().map(|(_, action)| action).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_match_by_name_and_description() {
+        let items = contexts(&AppState::Browsing, &ActivePane::Items);
+        assert_eq!(matching("export", &items).first(), Some(&Action::Export));
+        assert_eq!(matching("sync", &items).first(), Some(&Action::SyncNow));
+        assert_eq!(matching("open settings", &items).first(), Some(&Action::Settings));
+        assert!(!matching("add coll", &items).contains(&Action::AddCollection), "A collections pane action");
+        let collections = contexts(&AppState::Browsing, &ActivePane::Collections);
+        assert_eq!(matching("add coll", &collections).first(), Some(&Action::AddCollection));
+        assert!(matching("zz", &collections).is_empty());
+        assert!(!matching("", &items).contains(&Action::CommandPalette));
+        assert!(!matching("", &items).contains(&Action::Pause), "Downloads view only");
+    }
+}
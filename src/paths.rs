@@ -0,0 +1,320 @@
+use crate::saved_search;
+use crate::settings::Settings;
+use std::path::{Path, PathBuf};
+
+/// NTFS limit for a single path component, in UTF-16 code units.
+pub const MAX_COMPONENT_LEN: usize = 255;
+/// Shortest stem we truncate a file name to when fitting a max path length.
+const MIN_TRUNCATED_STEM_LEN: usize = 8;
+/// Characters NTFS/Win32 refuse in file names.
+const NTFS_INVALID_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+/// Device names Windows reserves regardless of extension (`aux.mp3` is still AUX).
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// How download paths are constructed from archive.org identifiers and file names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathPolicy {
+    /// Escape characters and names that are invalid on NTFS/Windows.
+    pub ntfs_safe: bool,
+    /// Truncate file names so the whole path stays within this many characters.
+    pub max_path_len: Option<usize>,
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        Self::from_settings(&Settings::default())
+    }
+}
+
+impl PathPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self { ntfs_safe: settings.windows_safe_filenames, max_path_len: settings.max_path_length }
+    }
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Stable (across runs and Rust versions) short hash, so truncated names are reproducible
+/// and an existing download is recognised on the next run.
+fn short_hash(s: &str) -> String {
+    let mut hash: u32 = 0x811c9dc5; // FNV-1a
+    for byte in s.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    format!("{:06x}", hash & 0xff_ffff)
+}
+
+/// Splits `name` into (stem, extension-with-dot). Leading dots don't start an extension.
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 && name.len() - i <= 16 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    }
+}
+
+/// Shortens `name` to at most `max_units` UTF-16 units, keeping its extension and adding a
+/// hash of the original so distinct long names stay distinct.
+fn truncate_name(name: &str, max_units: usize) -> String {
+    if utf16_len(name) <= max_units {
+        return name.to_string();
+    }
+    let (stem, ext) = split_extension(name);
+    let suffix = format!("~{}{}", short_hash(name), ext);
+    let budget = max_units.saturating_sub(utf16_len(&suffix));
+    let mut kept = String::new();
+    let mut used = 0;
+    for c in stem.chars() {
+        if used + c.len_utf16() > budget {
+            break;
+        }
+        used += c.len_utf16();
+        kept.push(c);
+    }
+    format!("{}{}", kept, suffix)
+}
+
+/// Makes a single path component safe to create.
+///
+/// Always neutralises empty, `.` and `..` components (path traversal). With
+/// `policy.ntfs_safe`, also replaces NTFS-invalid and control characters with `_`, strips
+/// trailing dots/spaces and prefixes reserved device names (`aux.mp3` -> `_aux.mp3`).
+/// The result never exceeds [`MAX_COMPONENT_LEN`].
+pub fn sanitize_component(name: &str, policy: &PathPolicy) -> String {
+    let mut out: String = if policy.ntfs_safe {
+        name.chars()
+            .map(|c| if NTFS_INVALID_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+            .collect()
+    } else {
+        name.replace(['/', '\0'], "_")
+    };
+
+    if policy.ntfs_safe {
+        // Windows silently drops trailing dots and spaces, which would alias other names
+        let trimmed_len = out.trim_end_matches(['.', ' ']).len();
+        if trimmed_len < out.len() {
+            out.truncate(trimmed_len);
+            out.push('_');
+        }
+        let base = out.split('.').next().unwrap_or("").trim_end();
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(base)) {
+            out.insert(0, '_');
+        }
+    }
+
+    if out.is_empty() || out == "." || out == ".." {
+        out = "_".repeat(out.len().max(1));
+    }
+    truncate_name(&out, MAX_COMPONENT_LEN)
+}
+
+/// `base / [collection]`
+pub fn collection_dir(base_dir: &str, collection_id: Option<&str>, policy: &PathPolicy) -> PathBuf {
+    let mut path = PathBuf::from(base_dir);
+    if let Some(c) = collection_id {
+        path.push(sanitize_component(&saved_search::local_name(c), policy));
+    }
+    long_path(path)
+}
+
+/// `base / [collection] / item`
+pub fn item_dir(base_dir: &str, collection_id: Option<&str>, item_id: &str, policy: &PathPolicy) -> PathBuf {
+    collection_dir(base_dir, collection_id, policy).join(sanitize_component(item_id, policy))
+}
+
+/// `base / [collection] / item_archive.torrent`
+pub fn torrent_path(base_dir: &str, collection_id: Option<&str>, item_id: &str, policy: &PathPolicy) -> PathBuf {
+    let name = format!("{}_archive.torrent", item_id);
+    fit_file_name(collection_dir(base_dir, collection_id, policy), &name, policy)
+}
+
+/// `base / [collection] / item / file`. Archive file names may contain `/` for
+/// subdirectories; each segment is sanitized separately.
+pub fn file_path(
+    base_dir: &str,
+    collection_id: Option<&str>,
+    item_id: &str,
+    file_name: &str,
+    policy: &PathPolicy,
+) -> PathBuf {
+    item_file_path(item_dir(base_dir, collection_id, item_id, policy), file_name, policy)
+}
+
+/// `item_dir / file`, for an item folder that doesn't follow the default layout (see
+/// [`crate::layout`]).
+pub fn item_file_path(mut dir: PathBuf, file_name: &str, policy: &PathPolicy) -> PathBuf {
+    let segments: Vec<&str> = file_name.split('/').filter(|s| !s.is_empty()).collect();
+    let (leaf, parents) = segments.split_last().map_or(("", &[][..]), |(l, p)| (*l, p));
+    for segment in parents {
+        dir.push(sanitize_component(segment, policy));
+    }
+    fit_file_name(dir, leaf, policy)
+}
+
+/// Where `path` is written while downloading: `<path>.part`.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Where a file of an item is saved: the item's `_archive.torrent` goes next to the item
+/// directory (see [`torrent_path`]), everything else inside it (see [`file_path`]).
+pub fn download_path(
+    base_dir: &str,
+    collection_id: Option<&str>,
+    item_id: &str,
+    file_name: &str,
+    policy: &PathPolicy,
+) -> PathBuf {
+    if file_name.ends_with("_archive.torrent") {
+        torrent_path(base_dir, collection_id, item_id, policy)
+    } else {
+        file_path(base_dir, collection_id, item_id, file_name, policy)
+    }
+}
+
+/// Joins a sanitized `name` onto `dir`, shortening it to respect `policy.max_path_len`.
+fn fit_file_name(dir: PathBuf, name: &str, policy: &PathPolicy) -> PathBuf {
+    let name = sanitize_component(name, policy);
+    let Some(max_len) = policy.max_path_len else {
+        return dir.join(name);
+    };
+    let dir_len = utf16_len(&strip_extended_prefix(&dir.to_string_lossy())) + 1; // + separator
+    let available = max_len.saturating_sub(dir_len);
+    let (stem, ext) = split_extension(&name);
+    // Never shrink below a recognisable stem; beyond that only the long-path prefix helps
+    let floor = MIN_TRUNCATED_STEM_LEN.min(utf16_len(stem)) + utf16_len(ext) + 7;
+    dir.join(truncate_name(&name, available.max(floor)))
+}
+
+fn strip_extended_prefix(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else {
+        path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+    }
+}
+
+/// Converts an absolute Windows path to its extended-length (`\\?\`) form, which lifts the
+/// 260 character MAX_PATH limit. Relative and already-prefixed paths are returned unchanged.
+pub fn extended_length(path: &str) -> String {
+    if path.starts_with(r"\\?\") {
+        return path.to_string();
+    }
+    // The prefix disables Win32 path normalisation, so separators must already be backslashes
+    let normalized = path.replace('/', r"\");
+    if let Some(unc) = normalized.strip_prefix(r"\\") {
+        return format!(r"\\?\UNC\{}", unc);
+    }
+    let bytes = normalized.as_bytes();
+    if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\' {
+        return format!(r"\\?\{}", normalized);
+    }
+    path.to_string()
+}
+
+/// Applies [`extended_length`] on Windows (after making the path absolute); no-op elsewhere.
+pub fn long_path(path: PathBuf) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let absolute = std::path::absolute(&path).unwrap_or(path);
+        PathBuf::from(extended_length(&absolute.to_string_lossy()))
+    }
+    #[cfg(not(windows))]
+    {
+        path
+    }
+}
+
+/// Returns true if `path` lies inside `root` after sanitization (no component escapes it).
+pub fn is_within(root: &Path, path: &Path) -> bool {
+    path.starts_with(root) && !path.components().any(|c| matches!(c, std::path::Component::ParentDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NTFS: PathPolicy = PathPolicy { ntfs_safe: true, max_path_len: None };
+    const POSIX: PathPolicy = PathPolicy { ntfs_safe: false, max_path_len: None };
+
+    #[test]
+    fn test_reserved_names_are_escaped_case_insensitively() {
+        assert_eq!(sanitize_component("aux.mp3", &NTFS), "_aux.mp3");
+        assert_eq!(sanitize_component("CON", &NTFS), "_CON");
+        assert_eq!(sanitize_component("Lpt9.tar.gz", &NTFS), "_Lpt9.tar.gz");
+        assert_eq!(sanitize_component("com10.txt", &NTFS), "com10.txt");
+        assert_eq!(sanitize_component("auxiliary.mp3", &NTFS), "auxiliary.mp3");
+        // Only escaped when NTFS safety is on
+        assert_eq!(sanitize_component("aux.mp3", &POSIX), "aux.mp3");
+    }
+
+    #[test]
+    fn test_invalid_characters_and_trailing_dots() {
+        assert_eq!(sanitize_component("a<b>c:d\"e|f?g*h\\i", &NTFS), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(sanitize_component("tab\there", &NTFS), "tab_here");
+        assert_eq!(sanitize_component("name. .", &NTFS), "name_");
+        assert_eq!(sanitize_component("what?.mp3", &POSIX), "what?.mp3");
+    }
+
+    #[test]
+    fn test_traversal_components_are_neutralised() {
+        assert_eq!(sanitize_component("..", &POSIX), "__");
+        assert_eq!(sanitize_component(".", &POSIX), "_");
+        assert_eq!(sanitize_component("", &NTFS), "_");
+        let path = file_path("/dl", Some("coll"), "item", "../../etc/passwd", &POSIX);
+        assert!(is_within(Path::new("/dl/coll/item"), &path), "{}", path.display());
+    }
+
+    #[test]
+    fn test_long_components_are_truncated_deterministically() {
+        let long = format!("{}.flac", "x".repeat(300));
+        let short = sanitize_component(&long, &NTFS);
+        assert!(utf16_len(&short) <= MAX_COMPONENT_LEN);
+        assert!(short.ends_with(".flac"));
+        assert_eq!(short, sanitize_component(&long, &NTFS));
+        // Different long names don't collide after truncation
+        let other = format!("{}y.flac", "x".repeat(299));
+        assert_ne!(short, sanitize_component(&other, &NTFS));
+    }
+
+    #[test]
+    fn test_truncation_counts_utf16_units() {
+        let long = "\u{1F3B5}".repeat(200); // each is 2 UTF-16 units
+        let short = sanitize_component(&long, &NTFS);
+        assert!(utf16_len(&short) <= MAX_COMPONENT_LEN);
+    }
+
+    #[test]
+    fn test_max_path_len_shortens_file_name() {
+        let policy = PathPolicy { ntfs_safe: true, max_path_len: Some(60) };
+        let name = format!("{}.mp3", "track".repeat(20));
+        let path = file_path("/downloads", Some("coll"), "item", &name, &policy);
+        assert!(utf16_len(&path.to_string_lossy()) <= 60, "{}", path.display());
+        assert!(path.to_string_lossy().ends_with(".mp3"));
+    }
+
+    #[test]
+    fn test_nested_file_names_create_subdirectories() {
+        let path = file_path("/dl", None, "item", "disc 1/aux.flac", &NTFS);
+        assert_eq!(path, Path::new("/dl").join("item").join("disc 1").join("_aux.flac"));
+        let torrent = torrent_path("/dl", Some("coll"), "item", &NTFS);
+        assert_eq!(torrent, Path::new("/dl").join("coll").join("item_archive.torrent"));
+    }
+
+    #[test]
+    fn test_extended_length_prefix() {
+        assert_eq!(extended_length(r"C:\archive\item"), r"\\?\C:\archive\item");
+        assert_eq!(extended_length("D:/archive/item"), r"\\?\D:\archive\item");
+        assert_eq!(extended_length(r"\\nas\share\item"), r"\\?\UNC\nas\share\item");
+        assert_eq!(extended_length(r"\\?\C:\already"), r"\\?\C:\already");
+        assert_eq!(extended_length("relative/path"), "relative/path");
+        assert_eq!(strip_extended_prefix(r"\\?\UNC\nas\share"), r"\\nas\share");
+    }
+}
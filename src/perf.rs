@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Whether `--profile-perf` is active. Recording is a no-op while this is false.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Collected samples, shared by the UI loop and download tasks.
+static RECORDER: Mutex<Option<PerfRecorder>> = Mutex::new(None);
+
+/// A measured phase of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Network round trip of the advancedsearch request (including body read).
+    SearchFetch,
+    /// Deserializing the advancedsearch response.
+    SearchParse,
+    /// Serializing and writing the per-collection item cache.
+    CacheWrite,
+    /// Rendering one UI frame.
+    RenderFrame,
+    /// Transferring one file body to disk.
+    Download,
+}
+
+impl Phase {
+    fn label(self) -> &'static str {
+        match self {
+            Phase::SearchFetch => "search fetch",
+            Phase::SearchParse => "search parse",
+            Phase::CacheWrite => "cache write",
+            Phase::RenderFrame => "render frame",
+            Phase::Download => "file download",
+        }
+    }
+}
+
+/// Aggregated timings for a single [`Phase`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhaseStats {
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl PhaseStats {
+    fn add(&mut self, elapsed: Duration) {
+        if self.count == 0 || elapsed < self.min {
+            self.min = elapsed;
+        }
+        self.max = self.max.max(elapsed);
+        self.total += elapsed;
+        self.count += 1;
+    }
+
+    /// Mean duration per sample.
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.count as u32
+        }
+    }
+}
+
+/// Collected samples for one profiling session.
+#[derive(Debug)]
+pub struct PerfRecorder {
+    started: Instant,
+    phases: BTreeMap<Phase, PhaseStats>,
+    bytes_downloaded: u64,
+}
+
+impl Default for PerfRecorder {
+    fn default() -> Self {
+        Self { started: Instant::now(), phases: BTreeMap::new(), bytes_downloaded: 0 }
+    }
+}
+
+impl PerfRecorder {
+    /// Adds one timing sample for `phase`.
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        self.phases.entry(phase).or_default().add(elapsed);
+    }
+
+    /// Adds a completed file transfer of `bytes` that took `elapsed`.
+    pub fn record_transfer(&mut self, bytes: u64, elapsed: Duration) {
+        self.bytes_downloaded += bytes;
+        self.record(Phase::Download, elapsed);
+    }
+
+    /// Returns the stats collected for `phase`, if any.
+    pub fn stats(&self, phase: Phase) -> Option<&PhaseStats> {
+        self.phases.get(&phase)
+    }
+
+    /// Renders a plain-text report of everything recorded so far.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let session = self.started.elapsed();
+        let _ = writeln!(out, "archiver performance report");
+        let _ = writeln!(out, "session length: {:.1}s", session.as_secs_f64());
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{:<14} {:>8} {:>12} {:>12} {:>12} {:>12}", "phase", "count", "total", "mean", "min", "max");
+        for (phase, stats) in &self.phases {
+            let _ = writeln!(
+                out,
+                "{:<14} {:>8} {:>12} {:>12} {:>12} {:>12}",
+                phase.label(),
+                stats.count,
+                format_duration(stats.total),
+                format_duration(stats.mean()),
+                format_duration(stats.min),
+                format_duration(stats.max)
+            );
+        }
+        if let Some(frames) = self.stats(Phase::RenderFrame) {
+            let mean_secs = frames.mean().as_secs_f64();
+            if mean_secs > 0.0 {
+                let _ = writeln!(out, "\nrender: {:.0} fps achievable at mean frame time", 1.0 / mean_secs);
+            }
+        }
+        if let Some(downloads) = self.stats(Phase::Download) {
+            let _ = writeln!(out, "\ndownloaded: {} bytes in {} files", self.bytes_downloaded, downloads.count);
+            if session.as_secs_f64() > 0.0 {
+                let _ = writeln!(
+                    out,
+                    "throughput: {:.2} MiB/s over the session",
+                    self.bytes_downloaded as f64 / (1024.0 * 1024.0) / session.as_secs_f64()
+                );
+            }
+        }
+        out
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let ms = d.as_secs_f64() * 1000.0;
+    if ms >= 1000.0 {
+        format!("{:.2}s", ms / 1000.0)
+    } else {
+        format!("{:.3}ms", ms)
+    }
+}
+
+/// Turns on recording for the rest of the process.
+pub fn enable() {
+    if let Ok(mut guard) = RECORDER.lock() {
+        guard.get_or_insert_with(PerfRecorder::default);
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Returns true if `--profile-perf` recording is active.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records a timing sample. Cheap no-op when profiling is disabled.
+pub fn record(phase: Phase, elapsed: Duration) {
+    if !is_enabled() {
+        return;
+    }
+    if let Ok(mut guard) = RECORDER.lock() {
+        if let Some(recorder) = guard.as_mut() {
+            recorder.record(phase, elapsed);
+        }
+    }
+}
+
+/// Records a completed file transfer for throughput reporting.
+pub fn record_transfer(bytes: u64, elapsed: Duration) {
+    if !is_enabled() {
+        return;
+    }
+    if let Ok(mut guard) = RECORDER.lock() {
+        if let Some(recorder) = guard.as_mut() {
+            recorder.record_transfer(bytes, elapsed);
+        }
+    }
+}
+
+/// Runs `f` and records how long it took under `phase`.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(phase, start.elapsed());
+    result
+}
+
+/// Default report location: `archiver-perf-<timestamp>.txt` in the working directory.
+pub fn default_report_path() -> PathBuf {
+    PathBuf::from(format!("archiver-perf-{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+}
+
+/// Writes the current report to `path`. Returns `Ok(false)` if profiling was never enabled.
+pub fn write_report(path: &Path) -> Result<bool> {
+    let report = match RECORDER.lock() {
+        Ok(guard) => match guard.as_ref() {
+            Some(recorder) => recorder.report(),
+            None => return Ok(false),
+        },
+        Err(_) => return Ok(false),
+    };
+    fs::write(path, report).context(format!("Failed to write performance report to {}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_stats_track_min_max_mean() {
+        let mut recorder = PerfRecorder::default();
+        recorder.record(Phase::RenderFrame, Duration::from_millis(10));
+        recorder.record(Phase::RenderFrame, Duration::from_millis(30));
+
+        let stats = recorder.stats(Phase::RenderFrame).unwrap();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.max, Duration::from_millis(30));
+        assert_eq!(stats.mean(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_report_lists_recorded_phases_and_bytes() {
+        let mut recorder = PerfRecorder::default();
+        recorder.record(Phase::SearchFetch, Duration::from_millis(250));
+        recorder.record_transfer(2048, Duration::from_millis(5));
+
+        let report = recorder.report();
+        assert!(report.contains("search fetch"));
+        assert!(report.contains("file download"));
+        assert!(report.contains("2048 bytes in 1 files"));
+        assert!(!report.contains("cache write"));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::archive_api::{FileDetails, ItemDetails};
+use crate::presets::FilePreset;
+use std::fmt::Write as _;
+
+/// Name of the playlist written into an audio item's directory.
+pub fn file_name(item_id: &str) -> String {
+    format!("{}.m3u8", item_id)
+}
+
+/// Renders an extended M3U playlist of the item's audio tracks.
+///
+/// `downloaded` holds the files on disk with their paths relative to the item
+/// directory. Only the best audio format among them is listed (an item with FLAC
+/// originals and MP3 derivatives gets one entry per track), ordered by the `track`
+/// metadata and then by file name. Returns `None` if no audio file was downloaded.
+pub fn render(details: &ItemDetails, downloaded: &[(FileDetails, String)]) -> Option<String> {
+    let tracks = tracks(downloaded);
+    if tracks.is_empty() {
+        return None;
+    }
+
+    let mut out = String::from("#EXTM3U\n");
+    if let Some(title) = &details.title {
+        let _ = writeln!(out, "#PLAYLIST:{}", title);
+    }
+    for (file, path) in tracks {
+        let seconds = file.length.as_deref().and_then(length_seconds).map_or(-1, |s| s.round() as i64);
+        let title = file.title.clone().unwrap_or_else(|| file.name.rsplit('/').next().unwrap_or(&file.name).to_string());
+        let display = match &details.creator {
+            Some(creator) => format!("{} - {}", creator, title),
+            None => title,
+        };
+        let _ = writeln!(out, "#EXTINF:{},{}", seconds, display);
+        let _ = writeln!(out, "{}", path.replace('\\', "/"));
+    }
+    Some(out)
+}
+
+/// The downloaded files of the best audio format, in track order (see [`render`]).
+pub fn tracks(downloaded: &[(FileDetails, String)]) -> Vec<&(FileDetails, String)> {
+    let files: Vec<FileDetails> = downloaded.iter().map(|(file, _)| file.clone()).collect();
+    let best = FilePreset::BestAudio.select(&files);
+    let mut tracks: Vec<&(FileDetails, String)> = downloaded.iter().filter(|(file, _)| best.contains(file)).collect();
+    tracks.sort_by(|(a, _), (b, _)| {
+        let (a_track, b_track) = (track_number(a), track_number(b));
+        (a_track.is_none(), a_track, &a.name).cmp(&(b_track.is_none(), b_track, &b.name))
+    });
+    tracks
+}
+
+/// Leading number of the `track` field (`"03/12"` -> 3).
+fn track_number(file: &FileDetails) -> Option<u32> {
+    let track = file.track.as_deref()?.trim();
+    let digits: String = track.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+/// Seconds of a `length` given as seconds (`"215.43"`) or `[hh:]mm:ss`.
+pub fn length_seconds(length: &str) -> Option<f64> {
+    length.split(':').try_fold(0.0, |total, part| Some(total * 60.0 + part.trim().parse::<f64>().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(name: &str, format: &str, track: Option<&str>, title: Option<&str>, length: Option<&str>) -> (FileDetails, String) {
+        let file = FileDetails {
+            name: name.to_string(),
+            format: Some(format.to_string()),
+            track: track.map(str::to_string),
+            title: title.map(str::to_string),
+            length: length.map(str::to_string),
+            ..Default::default()
+        };
+        (file, name.to_string())
+    }
+
+    #[test]
+    fn test_playlist_lists_best_format_in_track_order() {
+        let details = ItemDetails { title: Some("Live 1977".to_string()), creator: Some("Grateful Dead".to_string()), ..Default::default() };
+        let downloaded = vec![
+            track("d1t02.flac", "Flac", Some("2"), Some("Jack Straw"), Some("05:12")),
+            track("d1t02.mp3", "VBR MP3", Some("2"), Some("Jack Straw"), Some("312.1")),
+            track("d1t10.flac", "Flac", Some("10/12"), None, None),
+            track("d1t01.flac", "Flac", Some("01"), Some("Promised Land"), Some("200.6")),
+            track("cover.jpg", "JPEG", None, None, None),
+        ];
+        assert_eq!(
+            render(&details, &downloaded).unwrap(),
+            "#EXTM3U\n#PLAYLIST:Live 1977\n\
+             #EXTINF:201,Grateful Dead - Promised Land\nd1t01.flac\n\
+             #EXTINF:312,Grateful Dead - Jack Straw\nd1t02.flac\n\
+             #EXTINF:-1,Grateful Dead - d1t10.flac\nd1t10.flac\n"
+        );
+        assert_eq!(render(&details, &downloaded[4..]), None, "No audio, no playlist");
+    }
+}
@@ -0,0 +1,98 @@
+use log::debug;
+use reqwest::Url;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio::time::{sleep_until, Instant};
+
+/// Enforces a minimum delay between consecutive requests to the same host.
+///
+/// This is separate from the global rate limiter: the quota caps requests overall,
+/// while the pacer stops many parallel file requests from landing on one datanode
+/// (`ia8xxxxx.us.archive.org`) at the same moment. File downloads go through
+/// `archive.org/download/...` and are redirected to the item's datanode, so the pacer
+/// remembers which host serves each item (from the metadata `server` field or the
+/// final URL of an earlier response) and paces on that host.
+#[derive(Debug)]
+pub struct HostPacer {
+    min_delay: Duration,
+    state: Mutex<PacerState>,
+}
+
+#[derive(Debug, Default)]
+struct PacerState {
+    /// Earliest start of the next request per host (reserved slots).
+    next_slot: HashMap<String, Instant>,
+    /// Host known to serve each item's files.
+    item_hosts: HashMap<String, String>,
+}
+
+impl HostPacer {
+    pub fn new(min_delay: Duration) -> Self {
+        Self { min_delay, state: Mutex::new(PacerState::default()) }
+    }
+
+    /// Records the host serving `item_id` (ignored if `url` has no host).
+    pub fn learn_item_host(&self, item_id: &str, url: &str) {
+        let Some(host) = host_of(url) else { return };
+        if let Ok(mut state) = self.state.lock() {
+            if state.item_hosts.get(item_id) != Some(&host) {
+                debug!("Item '{}' is served by {}", item_id, host);
+                state.item_hosts.insert(item_id.to_string(), host);
+            }
+        }
+    }
+
+    /// Waits until a request for `item_id` to `url` may start.
+    ///
+    /// Slots are reserved under the lock and slept on outside it, so concurrent callers
+    /// for the same host are spaced `min_delay` apart in arrival order.
+    pub async fn wait_turn(&self, item_id: &str, url: &str) {
+        if self.min_delay.is_zero() {
+            return;
+        }
+        let slot = {
+            let Ok(mut state) = self.state.lock() else { return };
+            let Some(host) = state.item_hosts.get(item_id).cloned().or_else(|| host_of(url)) else { return };
+            let now = Instant::now();
+            let slot = state.next_slot.get(&host).copied().filter(|s| *s > now).unwrap_or(now);
+            state.next_slot.insert(host, slot + self.min_delay);
+            slot
+        };
+        sleep_until(slot).await;
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_to_same_host_are_spaced() {
+        let pacer = HostPacer::new(Duration::from_millis(500));
+        let start = Instant::now();
+        pacer.wait_turn("a", "https://ia800100.us.archive.org/x").await;
+        pacer.wait_turn("a", "https://ia800100.us.archive.org/y").await;
+        pacer.wait_turn("a", "https://ia800100.us.archive.org/z").await;
+        assert_eq!(start.elapsed(), Duration::from_millis(1000));
+
+        // A different host isn't held up by the first one
+        let other = Instant::now();
+        pacer.wait_turn("b", "https://ia600200.us.archive.org/x").await;
+        assert_eq!(other.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_learned_item_host_is_used_for_redirected_requests() {
+        let pacer = HostPacer::new(Duration::from_millis(500));
+        pacer.learn_item_host("item", "https://ia800100.us.archive.org/1/items/item");
+        pacer.wait_turn("other", "https://ia800100.us.archive.org/x").await;
+
+        // The download URL points at archive.org, but the item lives on the busy node
+        let start = Instant::now();
+        pacer.wait_turn("item", "https://archive.org/download/item/file.mp3").await;
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::file_history::{FileHistory, FileRecord};
+use crate::fs_util;
+use crate::secrets;
+use crate::settings::Settings;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Version of the [`StateBundle`] format written by this build.
+const FORMAT_VERSION: u32 = 1;
+
+/// Everything needed to set archiver up on another machine, in one JSON file: the
+/// settings (with the favorite collections and saved searches) and the download history.
+/// Credentials are left out; they stay in the keyring of the machine they were set on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateBundle {
+    pub version: u32,
+    /// RFC 3339 time of the export.
+    pub exported: String,
+    pub settings: Settings,
+    /// Download directory the history's paths are in, if there was one.
+    pub download_directory: Option<String>,
+    /// Files downloaded into it.
+    #[serde(default)]
+    pub download_history: Vec<FileRecord>,
+}
+
+/// The bundle of `settings` and the download history of `base_dir`.
+pub fn export(settings: &Settings, base_dir: Option<&Path>) -> StateBundle {
+    let mut settings = settings.clone();
+    secrets::take_secrets(&mut settings);
+    StateBundle {
+        version: FORMAT_VERSION,
+        exported: chrono::Local::now().to_rfc3339(),
+        settings,
+        download_directory: base_dir.map(|dir| dir.to_string_lossy().into_owned()),
+        download_history: base_dir.map(|dir| FileHistory::load(dir).records()).unwrap_or_default(),
+    }
+}
+
+/// Writes `bundle` to `path` as JSON.
+pub fn write(path: &Path, bundle: &StateBundle) -> Result<()> {
+    let json = serde_json::to_string_pretty(bundle).context("Failed to serialize the state")?;
+    fs_util::atomic_write(path, json).with_context(|| format!("Failed to write '{}'", path.display()))
+}
+
+/// Reads a bundle written by [`write`].
+pub fn read(path: &Path) -> Result<StateBundle> {
+    let json = fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let bundle: StateBundle = serde_json::from_str(&json).with_context(|| format!("'{}' is not an archiver state export", path.display()))?;
+    if bundle.version > FORMAT_VERSION {
+        bail!("'{}' was exported by a newer archiver (format {}); update to import it", path.display(), bundle.version);
+    }
+    Ok(bundle)
+}
+
+/// The settings `bundle` gives this machine, where `current` are its settings now:
+/// the bundle's, with the favorites of both, this machine's credentials, and its download
+/// directory (`base_dir`, else the current one, else the bundle's). A credential is only
+/// kept for the account it was set for: where the bundle names another user, bucket or
+/// server, it is left out. Also returns how many favorites (collections and saved
+/// searches) are new to this machine.
+pub fn merged_settings(bundle: &StateBundle, current: &Settings, base_dir: Option<&str>) -> (Settings, usize) {
+    let mut settings = bundle.settings.clone();
+    let new_favorites = settings.favorite_collections.iter().filter(|f| !current.favorite_collections.contains(f)).count();
+    for favorite in &current.favorite_collections {
+        if !settings.favorite_collections.contains(favorite) {
+            settings.favorite_collections.push(favorite.clone());
+        }
+    }
+    settings.download_directory = base_dir
+        .map(str::to_string)
+        .or_else(|| current.download_directory.clone())
+        .or_else(|| bundle.settings.download_directory.clone());
+    let local: HashMap<String, Option<String>> =
+        secrets::take_secrets(&mut current.clone()).into_iter().filter(|(key, _)| secrets::same_account(key, &settings, current)).collect();
+    secrets::fill_secrets(&mut settings, |key| local.get(key).cloned().flatten());
+    (settings, new_favorites)
+}
+
+/// Adds the bundle's download history to the history of `base_dir`, moving its paths
+/// from the exporting machine's download directory into `base_dir`. Records of paths
+/// outside that directory are kept as they are. Returns the number of records.
+pub fn import_history(bundle: &StateBundle, base_dir: &Path) -> usize {
+    let history = FileHistory::load(base_dir);
+    let old_base = bundle.download_directory.as_deref().map(Path::new);
+    for record in &bundle.download_history {
+        let mut record = record.clone();
+        if let Some(relative) = old_base.and_then(|old| record.path.strip_prefix(old).ok()) {
+            record.path = base_dir.join(relative);
+        }
+        history.record(record);
+    }
+    history.flush();
+    bundle.download_history.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::saved_search;
+
+    #[test]
+    fn test_state_moves_to_another_machine() {
+        let laptop = tempfile::tempdir().unwrap();
+        let seedbox = tempfile::tempdir().unwrap();
+        let history = FileHistory::load(laptop.path());
+        history.record(FileRecord {
+            identifier: "gd1977".to_string(),
+            name: "a.flac".to_string(),
+            md5: None,
+            size: 3,
+            path: laptop.path().join("etree/gd1977/a.flac"),
+            downloaded: "2026-10-01T12:00:00+00:00".to_string(),
+            local_sha256: None,
+        });
+        history.flush();
+        let settings = Settings {
+            favorite_collections: vec!["etree".to_string(), saved_search::entry("year:1977")],
+            transmission_username: Some("admin".to_string()),
+            transmission_password: Some("laptop".to_string()),
+            segments_per_file: 4,
+            ..Settings::default()
+        };
+
+        let path = laptop.path().join("state.json");
+        write(&path, &export(&settings, Some(laptop.path()))).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("\"laptop\""), "Credentials stay behind");
+        let bundle = read(&path).unwrap();
+        assert_eq!(bundle.download_history.len(), 1);
+
+        let current = Settings {
+            favorite_collections: vec!["etree".to_string(), "netlabels".to_string()],
+            transmission_username: Some("admin".to_string()),
+            transmission_password: Some("seedbox".to_string()),
+            download_directory: Some("/srv/old".to_string()),
+            ..Settings::default()
+        };
+        let seedbox_dir = seedbox.path().to_str().unwrap();
+        let (merged, new_favorites) = merged_settings(&bundle, &current, Some(seedbox_dir));
+        assert_eq!(new_favorites, 1);
+        assert_eq!(merged.favorite_collections, vec!["etree".to_string(), saved_search::entry("year:1977"), "netlabels".to_string()]);
+        assert_eq!(merged.segments_per_file, 4);
+        assert_eq!(merged.download_directory.as_deref(), Some(seedbox_dir));
+        assert_eq!(merged.transmission_password.as_deref(), Some("seedbox"));
+
+        // Another account in the bundle doesn't get this machine's secret
+        let current = Settings { transmission_username: Some("root".to_string()), ..current };
+        assert_eq!(merged_settings(&bundle, &current, Some(seedbox_dir)).0.transmission_password, None);
+
+        assert_eq!(import_history(&bundle, seedbox.path()), 1);
+        assert!(FileHistory::load(seedbox.path()).is_recorded(&seedbox.path().join("etree/gd1977/a.flac"), 3, None));
+    }
+
+    #[test]
+    fn test_newer_exports_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let mut bundle = export(&Settings::default(), None);
+        bundle.version = FORMAT_VERSION + 1;
+        write(&path, &bundle).unwrap();
+        assert!(read(&path).unwrap_err().to_string().contains("newer archiver"));
+        fs::write(&path, "{}").unwrap();
+        assert!(read(&path).is_err());
+    }
+}
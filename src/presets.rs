@@ -0,0 +1,328 @@
+use crate::archive_api::{FileDetails, ItemDetails};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Audio formats in order of preference for [`FilePreset::BestAudio`] (archive.org
+/// `format` values, compared case-insensitively).
+const AUDIO_FORMAT_RANK: [&str; 9] = [
+    "24bit flac",
+    "flac",
+    "apple lossless audio",
+    "wave",
+    "vbr mp3",
+    "ogg vorbis",
+    "128kbps mp3",
+    "64kbps mp3",
+    "mp3",
+];
+const EBOOK_EXTENSIONS: [&str; 6] = ["epub", "pdf", "mobi", "azw3", "djvu", "fb2"];
+const SUBTITLE_EXTENSIONS: [&str; 2] = ["srt", "vtt"];
+/// Language names accepted as subtitle tags besides 2/3-letter codes.
+const LANGUAGE_NAMES: [&str; 12] = [
+    "english", "french", "german", "spanish", "italian", "portuguese", "dutch", "russian", "japanese", "chinese", "korean", "arabic",
+];
+/// Item-level metadata files archive.org generates for every item.
+const METADATA_SUFFIXES: [&str; 4] = ["_meta.xml", "_files.xml", "_reviews.xml", "_meta.sqlite"];
+
+/// Named file selection applied to item and collection downloads in Direct mode.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilePreset {
+    /// Every file of the item.
+    #[default]
+    AllFiles,
+    /// Files uploaded by the submitter (`source = original`), no derivatives.
+    OriginalsOnly,
+    /// Only the audio files of the best audio format the item offers.
+    BestAudio,
+    /// E-book formats (EPUB, PDF, MOBI, DjVu, ...).
+    EbooksOnly,
+    /// The item's metadata XML files and its torrent.
+    MetadataAndTorrent,
+}
+
+/// Which files item downloads keep by their `source`: archive.org generates
+/// `derivative` files (e.g. VBR MP3s of uploaded FLACs) from the `original` uploads.
+/// Applied on top of the preset; files of any other source (`metadata`) are always kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourcePolicy {
+    #[default]
+    All,
+    OriginalsOnly,
+    DerivativesOnly,
+}
+
+impl fmt::Display for SourcePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourcePolicy::All => write!(f, "Originals and derivatives"),
+            SourcePolicy::OriginalsOnly => write!(f, "Originals only"),
+            SourcePolicy::DerivativesOnly => write!(f, "Derivatives only"),
+        }
+    }
+}
+
+impl SourcePolicy {
+    /// Next policy, wrapping around.
+    pub fn cycle(self) -> Self {
+        match self {
+            SourcePolicy::All => SourcePolicy::OriginalsOnly,
+            SourcePolicy::OriginalsOnly => SourcePolicy::DerivativesOnly,
+            SourcePolicy::DerivativesOnly => SourcePolicy::All,
+        }
+    }
+
+    pub fn keeps(self, file: &FileDetails) -> bool {
+        !matches!(
+            (self, file.source.as_deref()),
+            (SourcePolicy::OriginalsOnly, Some("derivative")) | (SourcePolicy::DerivativesOnly, Some("original"))
+        )
+    }
+}
+
+impl fmt::Display for FilePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilePreset::AllFiles => write!(f, "All files"),
+            FilePreset::OriginalsOnly => write!(f, "Originals only"),
+            FilePreset::BestAudio => write!(f, "Best audio"),
+            FilePreset::EbooksOnly => write!(f, "Ebooks only"),
+            FilePreset::MetadataAndTorrent => write!(f, "Metadata + torrent only"),
+        }
+    }
+}
+
+impl FilePreset {
+    /// Next preset, wrapping around.
+    pub fn cycle(self) -> Self {
+        match self {
+            FilePreset::AllFiles => FilePreset::OriginalsOnly,
+            FilePreset::OriginalsOnly => FilePreset::BestAudio,
+            FilePreset::BestAudio => FilePreset::EbooksOnly,
+            FilePreset::EbooksOnly => FilePreset::MetadataAndTorrent,
+            FilePreset::MetadataAndTorrent => FilePreset::AllFiles,
+        }
+    }
+
+    /// Returns the files of an item this preset downloads, in their original order.
+    pub fn select(self, files: &[FileDetails]) -> Vec<FileDetails> {
+        match self {
+            FilePreset::AllFiles => files.to_vec(),
+            FilePreset::OriginalsOnly => files.iter().filter(|f| f.source.as_deref() == Some("original")).cloned().collect(),
+            FilePreset::BestAudio => {
+                let Some(best) = files.iter().filter_map(audio_rank).min() else { return Vec::new() };
+                files.iter().filter(|f| audio_rank(f) == Some(best)).cloned().collect()
+            }
+            FilePreset::EbooksOnly => files.iter().filter(|f| EBOOK_EXTENSIONS.contains(&extension(&f.name).as_str())).cloned().collect(),
+            FilePreset::MetadataAndTorrent => files.iter().filter(|f| is_metadata_or_torrent(f)).cloned().collect(),
+        }
+    }
+}
+
+/// The files a Direct-mode download of `details` fetches: the preset's selection that
+/// the source policy keeps and that passes the download `filter`, plus the wanted
+/// subtitles of movie items.
+pub fn plan_files(
+    details: &ItemDetails,
+    preset: FilePreset,
+    sources: SourcePolicy,
+    filter: &[String],
+    subtitle_languages: Option<&[String]>,
+) -> Vec<FileDetails> {
+    let mut files = preset.select(&details.files);
+    files.retain(|file| sources.keeps(file) && passes_file_filter(file, filter));
+    if let Some(languages) = subtitle_languages {
+        if details.mediatype.as_deref() == Some("movies") {
+            add_subtitles(&mut files, &details.files, languages);
+        }
+    }
+    files
+}
+
+/// Adds the subtitle files of `files` missing from `selected` whose language tag is one
+/// of `languages` (case-insensitive). An empty list accepts every language; subtitles
+/// without a language tag are always included.
+pub fn add_subtitles(selected: &mut Vec<FileDetails>, files: &[FileDetails], languages: &[String]) {
+    for file in files {
+        if !SUBTITLE_EXTENSIONS.contains(&extension(&file.name).as_str()) || selected.iter().any(|f| f.name == file.name) {
+            continue;
+        }
+        let wanted = match subtitle_language(&file.name) {
+            Some(tag) => languages.is_empty() || languages.iter().any(|lang| lang.eq_ignore_ascii_case(tag)),
+            None => true,
+        };
+        if wanted {
+            selected.push(file.clone());
+        }
+    }
+}
+
+/// Whether `file` passes the download filter (the `download_file_filter` setting). An
+/// entry containing `*` or `?` is a glob matched against the file name; any other entry
+/// names a file, a format (`Flac`, `Text PDF`) or an extension (`pdf` or `.pdf`).
+/// Matching ignores case, and an empty filter passes every file.
+pub fn passes_file_filter(file: &FileDetails, filter: &[String]) -> bool {
+    if filter.is_empty() {
+        return true;
+    }
+    let name = file.name.to_lowercase();
+    let ext = extension(&file.name);
+    filter.iter().any(|entry| {
+        let entry = entry.trim().to_lowercase();
+        if entry.contains(['*', '?']) {
+            glob_match(&entry, &name)
+        } else {
+            entry == name || entry.trim_start_matches('.') == ext || file.format.as_deref().is_some_and(|format| format.eq_ignore_ascii_case(&entry))
+        }
+    })
+}
+
+/// Matches `text` against a glob where `*` is any run of characters and `?` one character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None; // Position after the last '*', text position it matched up to
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last '*' swallow one more character and retry
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Language tag of a subtitle file name: `movie.en.srt` -> `en`, `movie_eng.vtt` -> `eng`.
+/// Tags are 2-3 letter codes or a language name; a plain `movie.srt` has none.
+fn subtitle_language(name: &str) -> Option<&str> {
+    let stem = name.rsplit_once('.')?.0;
+    let (rest, tag) = stem.rsplit_once(['.', '_', '-'])?;
+    let is_tag = !rest.is_empty() && tag.len() >= 2 && tag.chars().all(|c| c.is_ascii_alphabetic());
+    // Long alphabetic suffixes are more likely part of the title than a language name
+    (is_tag && (tag.len() <= 3 || LANGUAGE_NAMES.contains(&tag.to_lowercase().as_str()))).then_some(tag)
+}
+
+/// Position of the file's format in [`AUDIO_FORMAT_RANK`] (lower is better).
+fn audio_rank(file: &FileDetails) -> Option<usize> {
+    let format = file.format.as_deref()?.to_lowercase();
+    AUDIO_FORMAT_RANK.iter().position(|candidate| *candidate == format)
+}
+
+fn extension(name: &str) -> String {
+    name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default()
+}
+
+fn is_metadata_or_torrent(file: &FileDetails) -> bool {
+    file.name.ends_with(".torrent")
+        || METADATA_SUFFIXES.iter().any(|suffix| file.name.ends_with(suffix))
+        || file.format.as_deref() == Some("Metadata")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, source: &str, format: &str) -> FileDetails {
+        FileDetails { name: name.to_string(), source: Some(source.to_string()), format: Some(format.to_string()), ..Default::default() }
+    }
+
+    fn names(files: Vec<FileDetails>) -> Vec<String> {
+        files.into_iter().map(|f| f.name).collect()
+    }
+
+    #[test]
+    fn test_presets_select_expected_files() {
+        let files = vec![
+            file("t01.flac", "original", "Flac"),
+            file("t01.mp3", "derivative", "VBR MP3"),
+            file("t01.ogg", "derivative", "Ogg Vorbis"),
+            file("cover.jpg", "original", "JPEG"),
+            file("book.epub", "derivative", "EPUB"),
+            file("item_meta.xml", "original", "Metadata"),
+            file("item_archive.torrent", "metadata", "Archive BitTorrent"),
+        ];
+
+        assert_eq!(FilePreset::AllFiles.select(&files).len(), files.len());
+        assert_eq!(names(FilePreset::OriginalsOnly.select(&files)), vec!["t01.flac", "cover.jpg", "item_meta.xml"]);
+        assert_eq!(names(FilePreset::BestAudio.select(&files)), vec!["t01.flac"]);
+        assert_eq!(names(FilePreset::BestAudio.select(&files[1..])), vec!["t01.mp3"]);
+        assert_eq!(names(FilePreset::EbooksOnly.select(&files)), vec!["book.epub"]);
+        assert_eq!(names(FilePreset::MetadataAndTorrent.select(&files)), vec!["item_meta.xml", "item_archive.torrent"]);
+    }
+
+    #[test]
+    fn test_source_policy_applies_on_top_of_the_preset() {
+        let details = ItemDetails {
+            files: vec![
+                file("t01.flac", "original", "Flac"),
+                file("t01.mp3", "derivative", "VBR MP3"),
+                file("item_archive.torrent", "metadata", "Archive BitTorrent"),
+                FileDetails { name: "unknown.bin".to_string(), ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let plan = |sources| names(plan_files(&details, FilePreset::AllFiles, sources, &[], None));
+
+        assert_eq!(plan(SourcePolicy::All).len(), 4);
+        assert_eq!(plan(SourcePolicy::OriginalsOnly), vec!["t01.flac", "item_archive.torrent", "unknown.bin"]);
+        assert_eq!(plan(SourcePolicy::DerivativesOnly), vec!["t01.mp3", "item_archive.torrent", "unknown.bin"]);
+        assert!(plan_files(&details, FilePreset::BestAudio, SourcePolicy::DerivativesOnly, &[], None).is_empty());
+        assert_eq!(SourcePolicy::DerivativesOnly.cycle(), SourcePolicy::All);
+    }
+
+    #[test]
+    fn test_subtitles_added_for_preferred_languages() {
+        let files = vec![
+            file("film.mp4", "derivative", "MPEG4"),
+            file("film.en.srt", "original", "SubRip"),
+            file("film_fre.srt", "original", "SubRip"),
+            file("film.English.vtt", "derivative", "Web Video Text Tracks"),
+            file("film.srt", "original", "SubRip"),
+        ];
+        let mut selected = vec![files[0].clone()];
+        add_subtitles(&mut selected, &files, &["en".to_string(), "english".to_string()]);
+        assert_eq!(names(selected), vec!["film.mp4", "film.en.srt", "film.English.vtt", "film.srt"]);
+
+        let mut any_language = Vec::new();
+        add_subtitles(&mut any_language, &files, &[]);
+        assert_eq!(any_language.len(), 4);
+        assert_eq!(subtitle_language("night_of_the_living_dead.srt"), None);
+    }
+
+    #[test]
+    fn test_file_filter_matches_globs_formats_and_extensions() {
+        let files = [
+            file("t01.flac", "original", "Flac"),
+            file("t01.mp3", "derivative", "VBR MP3"),
+            file("Booklet.PDF", "original", "Text PDF"),
+            file("disc1/t02.flac", "original", "Flac"),
+            file("cover.jpg", "original", "JPEG"),
+        ];
+        let filter = |entries: &[&str]| -> Vec<String> {
+            let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+            files.iter().filter(|f| passes_file_filter(f, &entries)).map(|f| f.name.clone()).collect()
+        };
+
+        assert_eq!(filter(&[]).len(), files.len());
+        assert_eq!(filter(&["flac", ".pdf"]), vec!["t01.flac", "Booklet.PDF", "disc1/t02.flac"]);
+        assert_eq!(filter(&["VBR MP3"]), vec!["t01.mp3"]);
+        assert_eq!(filter(&["*.flac"]), vec!["t01.flac", "disc1/t02.flac"]);
+        assert_eq!(filter(&["disc?/*"]), vec!["disc1/t02.flac"]);
+        assert_eq!(filter(&["t01.*", "cover.jpg"]), vec!["t01.flac", "t01.mp3", "cover.jpg"]);
+        assert!(filter(&["*.ogg"]).is_empty());
+    }
+}
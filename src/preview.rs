@@ -0,0 +1,210 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::{
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// Headless players tried in turn to stream a preview; the first one installed plays it.
+const PLAYERS: [(&str, &[&str]); 2] = [
+    ("mpv", &["--no-video", "--no-terminal", "--really-quiet"]),
+    ("ffplay", &["-nodisp", "-autoexit", "-loglevel", "quiet"]),
+];
+
+/// Whether `name` is a file the preview player handles (MP3 or FLAC).
+pub fn is_playable(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    lower.ends_with(".mp3") || lower.ends_with(".flac")
+}
+
+/// Playback time of a preview: how long it has played, not counting pauses.
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    started: Instant,
+    paused_at: Option<Instant>,
+    paused_for: Duration,
+}
+
+impl Clock {
+    fn new(now: Instant) -> Self {
+        Self { started: now, paused_at: None, paused_for: Duration::ZERO }
+    }
+
+    fn elapsed(&self, now: Instant) -> Duration {
+        let until = self.paused_at.unwrap_or(now);
+        until.saturating_duration_since(self.started).saturating_sub(self.paused_for)
+    }
+
+    fn pause(&mut self, now: Instant) {
+        self.paused_at.get_or_insert(now);
+    }
+
+    fn resume(&mut self, now: Instant) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_for += now.saturating_duration_since(paused_at);
+        }
+    }
+}
+
+/// An audio file streaming from archive.org in a headless player, shown as the now-playing
+/// line. Dropping it stops the player.
+pub struct Preview {
+    /// Item and file playing, to tell a repeated request (pause) from a new one.
+    pub item_id: String,
+    pub file_name: String,
+    /// The file's length from its metadata, if it has one.
+    pub length: Option<Duration>,
+    child: Child,
+    clock: Clock,
+}
+
+impl Preview {
+    /// Starts streaming `url` with the first of mpv and ffplay that is installed.
+    pub fn start(url: &str, item_id: &str, file_name: &str, length: Option<Duration>) -> Result<Self> {
+        for (program, args) in PLAYERS {
+            // The player must not draw over or read from the TUI's terminal
+            let spawned = Command::new(program)
+                .args(args)
+                .arg(url)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            match spawned {
+                Ok(child) => {
+                    debug!("Previewing {} with {}", url, program);
+                    return Ok(Self {
+                        item_id: item_id.to_string(),
+                        file_name: file_name.to_string(),
+                        length,
+                        child,
+                        clock: Clock::new(Instant::now()),
+                    });
+                }
+                Err(e) => debug!("Failed to run {}: {}", program, e),
+            }
+        }
+        bail!("Previews need mpv or ffplay: install one, or stream with the media player (P)")
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.clock.paused_at.is_some()
+    }
+
+    /// Pauses the player, or resumes it when paused.
+    pub fn toggle_pause(&mut self) -> Result<()> {
+        let paused = self.is_paused();
+        signal(&self.child, if paused { "-CONT" } else { "-STOP" })?;
+        if paused {
+            self.clock.resume(Instant::now());
+        } else {
+            self.clock.pause(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Whether the player has exited: the file played to the end, or it couldn't be streamed.
+    pub fn has_finished(&mut self) -> bool {
+        match self.child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    warn!("Preview player exited with {}", status);
+                }
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                warn!("Failed to check on the preview player: {}", e);
+                true
+            }
+        }
+    }
+
+    /// The now-playing line: `♪ d1t01.flac  1:05 / 4:30`, with `(paused)` when paused.
+    pub fn status_line(&self) -> String {
+        let elapsed = self.clock.elapsed(Instant::now());
+        now_playing(&self.file_name, elapsed, self.length, self.is_paused())
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        // A stopped (paused) player only dies once it is continued
+        if self.is_paused() {
+            let _ = signal(&self.child, "-CONT");
+        }
+        if let Err(e) = self.child.kill() {
+            debug!("Failed to stop the preview player: {}", e);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(unix)]
+fn signal(child: &Child, signal: &str) -> Result<()> {
+    let status = Command::new("kill")
+        .arg(signal)
+        .arg(child.id().to_string())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run kill")?;
+    if !status.success() {
+        bail!("kill {} exited with {}", signal, status);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn signal(_child: &Child, _signal: &str) -> Result<()> {
+    bail!("Pausing previews isn't supported on this platform; stop it instead")
+}
+
+fn now_playing(file_name: &str, elapsed: Duration, length: Option<Duration>, paused: bool) -> String {
+    let mut line = format!("♪ {}  {}", file_name, format_time(elapsed));
+    if let Some(length) = length {
+        line.push_str(&format!(" / {}", format_time(length)));
+    }
+    if paused {
+        line.push_str("  (paused)");
+    }
+    line
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour on.
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_stops_while_paused() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let mut clock = Clock::new(start);
+        assert_eq!(clock.elapsed(at(10)), Duration::from_secs(10));
+        clock.pause(at(10));
+        clock.pause(at(12));
+        assert_eq!(clock.elapsed(at(30)), Duration::from_secs(10), "Paused at 10s");
+        clock.resume(at(30));
+        assert_eq!(clock.elapsed(at(35)), Duration::from_secs(15));
+        clock.resume(at(40));
+        assert_eq!(clock.elapsed(at(40)), Duration::from_secs(20), "Resuming twice changes nothing");
+    }
+
+    #[test]
+    fn test_now_playing_line() {
+        let length = Some(Duration::from_secs(270));
+        assert_eq!(now_playing("d1t01.flac", Duration::from_secs(65), length, false), "♪ d1t01.flac  1:05 / 4:30");
+        assert_eq!(now_playing("d1t01.flac", Duration::from_secs(5), None, true), "♪ d1t01.flac  0:05  (paused)");
+        assert_eq!(format_time(Duration::from_secs(3725)), "1:02:05");
+        assert!(is_playable("Track 01.FLAC"));
+        assert!(!is_playable("d1t01.ogg"));
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+/// What the progress of a run is measured in: bytes once the sizes of its files are
+/// known, otherwise files, otherwise items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Bytes,
+    Files,
+    Items,
+}
+
+/// The counters of the running downloads, as far as they are known.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunProgress {
+    /// Items the run covers (collections send it up front).
+    pub items_total: Option<usize>,
+    pub items_done: usize,
+    /// Items whose file list (and with it their size) is known.
+    pub items_sized: usize,
+    /// Bytes the sized items still needed when they started.
+    pub bytes_planned: u64,
+    pub bytes_done: u64,
+    pub files_total: Option<usize>,
+    pub files_done: usize,
+    pub elapsed: Duration,
+}
+
+impl RunProgress {
+    /// Bytes the whole run is expected to download. Items of a collection that haven't
+    /// started yet are assumed to be as large as the average of those that have.
+    pub fn bytes_expected(&self) -> Option<u64> {
+        if self.bytes_planned == 0 || self.items_sized == 0 {
+            return None;
+        }
+        let expected = match self.items_total {
+            Some(total) if total > self.items_sized => self.bytes_planned as u128 * total as u128 / self.items_sized as u128,
+            _ => self.bytes_planned as u128,
+        };
+        Some((expected as u64).max(self.bytes_done))
+    }
+
+    /// The stage the run's progress is measured in, if anything is known.
+    pub fn stage(&self) -> Option<Stage> {
+        if self.bytes_expected().is_some() {
+            Some(Stage::Bytes)
+        } else if self.files_total.is_some_and(|total| total > 0) {
+            Some(Stage::Files)
+        } else if self.items_total.is_some_and(|total| total > 0) {
+            Some(Stage::Items)
+        } else {
+            None
+        }
+    }
+
+    /// How much of the run is done, from 0 to 1.
+    pub fn ratio(&self) -> Option<f64> {
+        let (done, total) = self.done_and_total()?;
+        Some((done / total).clamp(0.0, 1.0))
+    }
+
+    /// Time left at the pace so far: bytes left at the average speed, or the time the
+    /// files (items) done so far took per file (item).
+    pub fn eta(&self) -> Option<Duration> {
+        let (done, total) = self.done_and_total()?;
+        if done <= 0.0 || self.elapsed.is_zero() {
+            return None;
+        }
+        let left = (total - done).max(0.0);
+        Some(Duration::from_secs_f64(self.elapsed.as_secs_f64() * left / done))
+    }
+
+    fn done_and_total(&self) -> Option<(f64, f64)> {
+        match self.stage()? {
+            Stage::Bytes => Some((self.bytes_done as f64, self.bytes_expected()? as f64)),
+            Stage::Files => Some((self.files_done as f64, self.files_total? as f64)),
+            Stage::Items => Some((self.items_done as f64, self.items_total? as f64)),
+        }
+    }
+}
+
+/// The gauge label: `42% · 3h 12m left`, or just the percentage until there's a pace.
+pub fn gauge_label(progress: &RunProgress) -> Option<String> {
+    let percent = (progress.ratio()? * 100.0).floor();
+    Some(match progress.eta() {
+        Some(eta) => format!("{}% · {} left", percent, crate::forecast::format_duration(eta)),
+        None => format!("{}%", percent),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_prefers_bytes_and_extrapolates_unsized_items() {
+        let mut progress = RunProgress { items_total: Some(10), elapsed: Duration::from_secs(60), ..Default::default() };
+        assert_eq!(progress.stage(), Some(Stage::Items));
+        assert_eq!(progress.eta(), None, "Nothing done yet");
+        progress.items_done = 2;
+        assert_eq!(progress.ratio(), Some(0.2));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(240)));
+
+        progress.files_total = Some(40);
+        progress.files_done = 30;
+        assert_eq!(progress.stage(), Some(Stage::Files));
+        assert_eq!(progress.ratio(), Some(0.75));
+
+        // 2 of 10 items sized at 100 MB: about 500 MB in all
+        progress.items_sized = 2;
+        progress.bytes_planned = 100_000_000;
+        progress.bytes_done = 50_000_000;
+        assert_eq!(progress.stage(), Some(Stage::Bytes));
+        assert_eq!(progress.bytes_expected(), Some(500_000_000));
+        assert_eq!(progress.ratio(), Some(0.1));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(540)));
+        assert_eq!(gauge_label(&progress).as_deref(), Some("10% · 9m left"));
+
+        progress.items_total = None;
+        assert_eq!(progress.bytes_expected(), Some(100_000_000), "An item or file run knows its size");
+        assert_eq!(gauge_label(&RunProgress::default()), None);
+    }
+}
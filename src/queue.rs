@@ -0,0 +1,154 @@
+use crate::fs_util;
+use crate::session::DownloadJob;
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use std::{fs, path::PathBuf};
+
+/// File (in the config directory) holding the downloads waiting to start.
+pub const QUEUE_FILE: &str = "queue.json";
+
+/// Persistent FIFO of download jobs that haven't started yet.
+///
+/// Jobs run one after another: the next one starts when nothing is in flight (see the
+/// session journal for jobs already running). Jobs found in the file at startup are
+/// *held* until the user decides to resume or discard them; jobs queued meanwhile start
+/// without waiting for that decision.
+#[derive(Debug, Default)]
+pub struct DownloadQueue {
+    /// `None` keeps the queue in memory only (tests, mock mode).
+    path: Option<PathBuf>,
+    jobs: Vec<DownloadJob>,
+    /// Number of jobs at the front carried over from the previous run.
+    held: usize,
+}
+
+impl DownloadQueue {
+    /// Loads the queue at `path`; its jobs start out held. A missing file is an empty
+    /// queue; a corrupt one is backed up and replaced.
+    pub fn load(path: PathBuf) -> Self {
+        let jobs: Vec<DownloadJob> = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Download queue {} is corrupt ({}); starting fresh", path.display(), e);
+                if let Err(backup_err) = fs_util::backup_corrupt_file(&path) {
+                    error!("{:#}", backup_err);
+                }
+                Vec::new()
+            }),
+            Err(_) => Vec::new(), // Nothing was queued
+        };
+        if !jobs.is_empty() {
+            info!("Found {} queued download job(s) in {}", jobs.len(), path.display());
+        }
+        Self { path: Some(path), held: jobs.len(), jobs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Jobs carried over from the previous run, awaiting a decision.
+    pub fn held(&self) -> &[DownloadJob] {
+        &self.jobs[..self.held]
+    }
+
+    pub fn push(&mut self, job: DownloadJob) {
+        self.jobs.push(job);
+        self.save_logged();
+    }
+
+    /// Removes and returns the next job that may start (held jobs are skipped).
+    pub fn pop_ready(&mut self) -> Option<DownloadJob> {
+        if self.held >= self.jobs.len() {
+            return None;
+        }
+        let job = self.jobs.remove(self.held);
+        self.save_logged();
+        Some(job)
+    }
+
+    /// Lets the held jobs run, ahead of the ones queued since startup.
+    pub fn release_held(&mut self) {
+        self.held = 0;
+    }
+
+    /// Drops the held jobs and returns how many there were.
+    pub fn discard_held(&mut self) -> usize {
+        let discarded = self.jobs.drain(..self.held).count();
+        self.held = 0;
+        self.save_logged();
+        discarded
+    }
+
+    fn save_logged(&self) {
+        if let Err(e) = self.save() {
+            error!("{:#}", e);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if self.jobs.is_empty() {
+            return match fs::remove_file(path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e).context(format!("Failed to remove download queue {}", path.display())),
+            };
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("Failed to create config directory {}", dir.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.jobs).context("Failed to serialize download queue")?;
+        fs_util::atomic_write(path, json).context(format!("Failed to write download queue {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::DownloadAction;
+    use crate::presets::FilePreset;
+    use crate::settings::DownloadMode;
+
+    fn job(item: &str) -> DownloadJob {
+        DownloadJob {
+            action: DownloadAction::ItemAllFiles(item.to_string()),
+            collection: None,
+            mode: DownloadMode::Direct,
+            base_dir: "/downloads".to_string(),
+            preset: FilePreset::AllFiles,
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_survives_restart_and_holds_old_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(QUEUE_FILE);
+
+        let mut queue = DownloadQueue::load(path.clone());
+        queue.push(job("one"));
+        queue.push(job("two"));
+        assert_eq!(queue.pop_ready(), Some(job("one")));
+
+        let mut reloaded = DownloadQueue::load(path.clone());
+        assert_eq!(reloaded.held(), &[job("two")]);
+        // Held jobs wait for the resume decision; new ones don't
+        assert_eq!(reloaded.pop_ready(), None);
+        reloaded.push(job("three"));
+        assert_eq!(reloaded.pop_ready(), Some(job("three")));
+        reloaded.release_held();
+        assert_eq!(reloaded.pop_ready(), Some(job("two")));
+        assert!(reloaded.is_empty());
+        assert!(!path.exists());
+
+        let mut queue = DownloadQueue::load(path.clone());
+        queue.push(job("four"));
+        let mut reloaded = DownloadQueue::load(path.clone());
+        assert_eq!(reloaded.discard_held(), 1);
+        assert!(!path.exists());
+    }
+}
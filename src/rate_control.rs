@@ -0,0 +1,151 @@
+use log::{info, warn};
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use std::{sync::Mutex, time::Duration};
+use tokio::time::{sleep_until, Instant};
+
+/// Spacing between requests right after the first slow-down signal; doubles with each
+/// further signal.
+const MIN_SPACING: Duration = Duration::from_millis(250);
+/// Slow-down levels; at the top, requests are spaced `MIN_SPACING * 2^(MAX_LEVEL - 1)` apart.
+const MAX_LEVEL: u32 = 5;
+/// Quiet time (no further 429/503) after which one slow-down level is dropped again.
+const RAMP_STEP: Duration = Duration::from_secs(30);
+/// Longest pause honored, whatever `Retry-After` asks for.
+const MAX_PAUSE: Duration = Duration::from_secs(300);
+
+/// Global back-off for archive.org requests, driven by the server's own signals.
+///
+/// A 429 or 503 response pauses *every* request until its `Retry-After` (or, without
+/// one, a delay growing with each signal) and raises the slow-down level, which spaces
+/// requests out on top of the fixed quota. Each [`RAMP_STEP`] without another signal
+/// drops a level, so the request rate climbs back to the quota gradually.
+#[derive(Debug, Default)]
+pub struct AdaptiveBackoff {
+    state: Mutex<BackoffState>,
+}
+
+#[derive(Debug, Default)]
+struct BackoffState {
+    /// No request starts before this.
+    paused_until: Option<Instant>,
+    /// Slow-down level at the last signal.
+    level: u32,
+    last_signal: Option<Instant>,
+    /// Earliest start of the next request while slowed down (reserved slots).
+    next_slot: Option<Instant>,
+}
+
+impl BackoffState {
+    /// Level after the quiet time since the last signal has been credited.
+    fn level_at(&self, now: Instant) -> u32 {
+        let Some(last) = self.last_signal else { return 0 };
+        let steps = now.saturating_duration_since(last).as_secs_f64() / RAMP_STEP.as_secs_f64();
+        self.level.saturating_sub(steps as u32)
+    }
+}
+
+impl AdaptiveBackoff {
+    /// Records a 429/503 answer. `retry_after` is the server's requested pause, if it sent one.
+    pub fn throttled(&self, retry_after: Option<Duration>) {
+        let now = Instant::now();
+        let Ok(mut state) = self.state.lock() else { return };
+        let level = (state.level_at(now) + 1).min(MAX_LEVEL);
+        let pause = retry_after.unwrap_or(Duration::from_secs(1 << level)).min(MAX_PAUSE);
+        let until = now + pause;
+        state.paused_until = Some(state.paused_until.map_or(until, |current| current.max(until)));
+        state.level = level;
+        state.last_signal = Some(now);
+        warn!("archive.org asked to slow down: pausing requests for {:?} (slow-down level {})", pause, level);
+    }
+
+    /// Current slow-down level (0 = requests only limited by the quota).
+    pub fn level(&self) -> u32 {
+        self.state.lock().map_or(0, |state| state.level_at(Instant::now()))
+    }
+
+    /// Waits out a pause and, while slowed down, for this request's slot.
+    pub async fn wait(&self) {
+        let slot = {
+            let Ok(mut state) = self.state.lock() else { return };
+            let now = Instant::now();
+            let start = state.paused_until.filter(|until| *until > now).unwrap_or(now);
+            match state.level_at(now) {
+                0 => {
+                    if state.level > 0 {
+                        info!("archive.org request rate back to the normal quota");
+                        state.level = 0;
+                    }
+                    start
+                }
+                level => {
+                    let slot = state.next_slot.filter(|slot| *slot > start).unwrap_or(start);
+                    state.next_slot = Some(slot + MIN_SPACING * (1 << (level - 1)));
+                    slot
+                }
+            }
+        };
+        sleep_until(slot).await;
+    }
+}
+
+/// Whether `status` tells us to slow down rather than that the request failed.
+pub fn is_throttling(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// The pause a response's `Retry-After` header asks for: delay-seconds or an HTTP date.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    parse_retry_after(response.headers().get(RETRY_AFTER)?.to_str().ok()?, chrono::Utc::now())
+}
+
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after_seconds_and_dates() {
+        let now = chrono::DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_pauses_then_ramps_back_up() {
+        let backoff = AdaptiveBackoff::default();
+        let start = Instant::now();
+        backoff.wait().await;
+        assert_eq!(Instant::now(), start, "No waiting before any signal");
+
+        backoff.throttled(Some(Duration::from_secs(10)));
+        backoff.wait().await;
+        assert_eq!(Instant::now(), start + Duration::from_secs(10), "Retry-After is honored");
+        backoff.wait().await;
+        assert_eq!(Instant::now(), start + Duration::from_secs(10) + MIN_SPACING, "Requests are spaced while slowed down");
+
+        backoff.throttled(None);
+        assert_eq!(backoff.level(), 2);
+        let signalled = Instant::now();
+        backoff.wait().await;
+        assert_eq!(Instant::now(), signalled + Duration::from_secs(4), "Without Retry-After the pause grows with the level");
+
+        tokio::time::advance(RAMP_STEP).await;
+        assert_eq!(backoff.level(), 1);
+        tokio::time::advance(RAMP_STEP).await;
+        assert_eq!(backoff.level(), 0);
+        let ramped = Instant::now();
+        backoff.wait().await;
+        backoff.wait().await;
+        assert_eq!(Instant::now(), ramped, "Back to the plain quota");
+    }
+}
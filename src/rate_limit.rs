@@ -0,0 +1,195 @@
+use crate::rate_control::AdaptiveBackoff;
+use anyhow::{Context, Result};
+use governor::{
+    clock::SystemClock,
+    middleware::NoOpMiddleware,
+    state::{direct::NotKeyed, InMemoryState},
+    Quota, RateLimiter,
+};
+use log::{debug, warn};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// File (inside the cache directory) holding the request timestamps shared by all instances.
+pub const SHARED_STATE_FILE: &str = "rate_limit.state";
+
+type DirectLimiter = RateLimiter<NotKeyed, InMemoryState, SystemClock, NoOpMiddleware<SystemTime>>;
+
+/// Rate limiter used for all archive.org API requests.
+///
+/// Always applies an in-process governor quota. When a shared budget is attached, every
+/// request also has to claim a slot in a sliding window stored in a lock-protected file,
+/// so concurrently running instances of the app stay within the limit *together*.
+/// On top of both, [`AdaptiveBackoff`] slows everything down when archive.org answers
+/// 429/503 (see [`SharedRateLimiter::throttled`]).
+pub struct SharedRateLimiter {
+    local: DirectLimiter,
+    backoff: AdaptiveBackoff,
+    shared: Option<SharedBudget>,
+    /// Set once the shared file proved unusable; we then fall back to the local quota only.
+    shared_disabled: AtomicBool,
+}
+
+impl SharedRateLimiter {
+    /// Creates a limiter that only enforces `quota` within this process.
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            local: RateLimiter::direct_with_clock(quota, &SystemClock),
+            backoff: AdaptiveBackoff::default(),
+            shared: None,
+            shared_disabled: AtomicBool::new(false),
+        }
+    }
+
+    /// Additionally enforces at most `max_requests` per `window` across all processes
+    /// sharing `state_path`.
+    pub fn with_shared_budget(mut self, state_path: PathBuf, max_requests: u32, window: Duration) -> Self {
+        self.shared = Some(SharedBudget { state_path, max_requests: max_requests.max(1), window });
+        self
+    }
+
+    /// Records that archive.org asked us to slow down (HTTP 429/503), pausing every
+    /// request for `retry_after` if the response said how long.
+    pub fn throttled(&self, retry_after: Option<Duration>) {
+        self.backoff.throttled(retry_after);
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn until_ready(&self) {
+        self.backoff.wait().await;
+        self.local.until_ready().await;
+
+        let Some(shared) = &self.shared else { return };
+        while !self.shared_disabled.load(Ordering::Relaxed) {
+            let budget = shared.clone();
+            let claim = tokio::task::spawn_blocking(move || budget.try_claim(unix_millis()))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|r| r);
+            match claim {
+                Ok(None) => return,
+                Ok(Some(wait)) => {
+                    debug!("Shared rate-limit budget exhausted, waiting {:?}", wait);
+                    tokio::time::sleep(wait).await;
+                }
+                Err(e) => {
+                    warn!("Shared rate-limit state unusable, falling back to per-process limits: {:#}", e);
+                    self.shared_disabled.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+/// Sliding-window request budget persisted in a file, guarded by an exclusive file lock.
+#[derive(Debug, Clone)]
+struct SharedBudget {
+    state_path: PathBuf,
+    max_requests: u32,
+    window: Duration,
+}
+
+impl SharedBudget {
+    /// Tries to record a request at `now_ms`. Returns `None` if it was recorded, or how long
+    /// to wait before the oldest request in the window expires.
+    fn try_claim(&self, now_ms: u64) -> Result<Option<Duration>> {
+        if let Some(parent) = self.state_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.state_path)
+            .context(format!("Failed to open shared rate-limit state '{}'", self.state_path.display()))?;
+        file.lock().context("Failed to lock shared rate-limit state")?;
+        let result = self.claim_locked(&mut file, now_ms);
+        let _ = file.unlock(); // Also released when the file is closed
+        result
+    }
+
+    fn claim_locked(&self, file: &mut File, now_ms: u64) -> Result<Option<Duration>> {
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let window_ms = self.window.as_millis() as u64;
+        // Unparseable lines (e.g. a torn write from a crashed instance) are simply dropped
+        let mut stamps: Vec<u64> = contents
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .filter(|&stamp: &u64| stamp + window_ms > now_ms && stamp <= now_ms)
+            .collect();
+        stamps.sort_unstable();
+
+        let outcome = if stamps.len() < self.max_requests as usize {
+            stamps.push(now_ms);
+            None
+        } else {
+            Some(Duration::from_millis(stamps[0] + window_ms - now_ms))
+        };
+
+        let serialized: String = stamps.iter().map(|s| format!("{}\n", s)).collect();
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        file.write_all(serialized.as_bytes())?;
+        Ok(outcome)
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+    use tempfile::tempdir;
+
+    fn budget(path: PathBuf) -> SharedBudget {
+        SharedBudget { state_path: path, max_requests: 2, window: Duration::from_secs(60) }
+    }
+
+    #[test]
+    fn test_budget_is_shared_between_instances() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(SHARED_STATE_FILE);
+        let first = budget(path.clone());
+        let second = budget(path);
+
+        assert_eq!(first.try_claim(1_000).unwrap(), None);
+        assert_eq!(second.try_claim(2_000).unwrap(), None);
+        // Third request within the window has to wait for the oldest to expire
+        assert_eq!(first.try_claim(3_000).unwrap(), Some(Duration::from_millis(58_000)));
+        // Once it has expired a slot frees up again
+        assert_eq!(second.try_claim(61_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_budget_ignores_garbage_in_state_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(SHARED_STATE_FILE);
+        std::fs::write(&path, "not a number\n12").unwrap();
+        assert_eq!(budget(path).try_claim(100_000).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_until_ready_with_shared_budget_records_request() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("nested").join(SHARED_STATE_FILE);
+        let quota = Quota::per_minute(NonZeroU32::new(60).unwrap());
+        let limiter = SharedRateLimiter::new(quota).with_shared_budget(path.clone(), 5, Duration::from_secs(60));
+
+        limiter.until_ready().await;
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+    }
+}
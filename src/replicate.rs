@@ -0,0 +1,439 @@
+use crate::settings::Settings;
+use crate::storage;
+use crate::verify::{self, Md5};
+use anyhow::{bail, Context, Result};
+use futures_util::StreamExt;
+use log::{info, warn};
+use reqwest::{header, Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Left in the folder of an item whose local copy was deleted after replication, so
+/// downloads skip it instead of fetching it again.
+pub const MARKER_FILE: &str = ".archiver-replicated.json";
+/// Attempts at each upload (and at each rsync run) before the target counts as failed.
+const ATTEMPTS: u32 = 3;
+/// Wait before the second attempt; each further one waits twice as long.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How a target is reached.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    /// `rsync` over ssh (or to a local path).
+    Rsync,
+    /// HTTP `MKCOL` and `PUT` on a WebDAV share.
+    Webdav,
+    /// `PUT` on an S3-compatible bucket, signed with AWS Signature Version 4.
+    S3,
+}
+
+impl fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetKind::Rsync => write!(f, "rsync"),
+            TargetKind::Webdav => write!(f, "WebDAV"),
+            TargetKind::S3 => write!(f, "S3"),
+        }
+    }
+}
+
+/// Remote storage completed items are pushed to, from `[[replication_targets]]` in
+/// settings.toml. Items keep their path below the download directory there.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ReplicationTarget {
+    /// Shown in messages, e.g. `nas`.
+    pub name: String,
+    pub kind: TargetKind,
+    /// rsync: `[user@]host:/path` (or a local path); WebDAV: URL of the folder items go
+    /// into; S3: endpoint URL with the bucket (and an optional prefix), path style, e.g.
+    /// `https://s3.eu-central-1.amazonaws.com/my-bucket/archive`.
+    pub url: String,
+    /// WebDAV user, or S3 access key.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// WebDAV password, or S3 secret key.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// S3 region (default `us-east-1`).
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Options for `ssh`, e.g. `-p 2222 -i ~/.ssh/backup`.
+    #[serde(default)]
+    pub ssh_options: Option<String>,
+}
+
+impl fmt::Debug for ReplicationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplicationTarget")
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("region", &self.region)
+            .field("ssh_options", &self.ssh_options)
+            .finish()
+    }
+}
+
+/// The replication stage of downloads: where completed items go, and whether the local
+/// copy is deleted once every target holds a verified one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replication {
+    pub targets: Vec<ReplicationTarget>,
+    pub delete_local: bool,
+}
+
+impl Replication {
+    /// `None` without targets.
+    pub fn from_settings(settings: &Settings) -> Option<Arc<Self>> {
+        (!settings.replication_targets.is_empty())
+            .then(|| Arc::new(Self { targets: settings.replication_targets.clone(), delete_local: settings.replicate_delete_local }))
+    }
+}
+
+/// Recorded in [`MARKER_FILE`].
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Marker {
+    /// Names of the targets holding the item.
+    pub targets: Vec<String>,
+    /// RFC 3339 time the local copy was deleted.
+    pub removed: String,
+}
+
+/// Targets holding the item whose folder is `item_dir`, if its local copy was deleted
+/// after replication.
+pub fn replicated_to(item_dir: &Path) -> Option<Vec<String>> {
+    let json = fs::read_to_string(item_dir.join(MARKER_FILE)).ok()?;
+    serde_json::from_str::<Marker>(&json).ok().map(|marker| marker.targets)
+}
+
+/// Pushes `item_dir` (below `base_dir`) to `target` and checks the copy: rsync compares
+/// checksums in a dry run, WebDAV and S3 the size of every file (see [`verify_content`]
+/// for their content). Uploads are retried.
+pub async fn push(client: &Client, target: &ReplicationTarget, base_dir: &Path, item_dir: &Path) -> Result<()> {
+    let relative = item_path(base_dir, item_dir)?;
+    match target.kind {
+        TargetKind::Rsync => {
+            let args = rsync_args(target, base_dir, &relative.join("/"), false);
+            with_retries(target, "rsync", || run_rsync(args.clone())).await?;
+            let changes = run_rsync(rsync_args(target, base_dir, &relative.join("/"), true)).await?;
+            if !changes.trim().is_empty() {
+                bail!("rsync still finds differences after the upload:\n{}", changes.trim());
+            }
+        }
+        TargetKind::Webdav | TargetKind::S3 => {
+            let files = list_files(item_dir)?;
+            if target.kind == TargetKind::Webdav {
+                make_collections(client, target, &relative, &files).await?;
+            }
+            for (name, path, size) in &files {
+                let key = format!("{}/{}", relative.join("/"), name);
+                with_retries(target, &key, || upload(client, target, &key, path, *size)).await?;
+            }
+            for (name, _, size) in &files {
+                let key = format!("{}/{}", relative.join("/"), name);
+                let remote = remote_size(client, target, &key).await?;
+                if remote != Some(*size) {
+                    bail!("'{}' on {} has {} instead of {} bytes", key, target.name, remote.map_or("no size".to_string(), |s| s.to_string()), size);
+                }
+            }
+        }
+    }
+    info!("Replicated '{}' to {}", relative.join("/"), target.name);
+    Ok(())
+}
+
+/// Checks that the copy of `item_dir` on `target` has the content of every local file,
+/// before the local copy is deleted. [`push`] compared rsync copies by checksum already;
+/// S3 objects are compared by ETag (the MD5 of a single `PUT`), WebDAV files by
+/// downloading and hashing them. Fails for files that can't be checked this way.
+pub async fn verify_content(client: &Client, target: &ReplicationTarget, base_dir: &Path, item_dir: &Path) -> Result<()> {
+    if target.kind == TargetKind::Rsync {
+        return Ok(());
+    }
+    let relative = item_path(base_dir, item_dir)?;
+    for (name, path, _) in list_files(item_dir)? {
+        let key = format!("{}/{}", relative.join("/"), name);
+        let local = tokio::task::spawn_blocking(move || verify::md5_file(&path)).await.context("Hashing task failed")??;
+        let remote = match target.kind {
+            TargetKind::S3 => remote_etag_md5(client, target, &key).await?,
+            _ => Some(remote_md5(client, target, &key).await?),
+        };
+        match remote {
+            Some(md5) if md5.eq_ignore_ascii_case(&local) => {}
+            Some(md5) => bail!("'{}' on {} has MD5 {} instead of {}", key, target.name, md5, local),
+            None => bail!("'{}' on {} can't be checked: its ETag is not an MD5", key, target.name),
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the local copy of a replicated item, leaving [`MARKER_FILE`] naming `targets`.
+pub fn remove_local(item_dir: &Path, targets: &[String]) -> Result<()> {
+    fs::remove_dir_all(item_dir).context(format!("Failed to delete '{}'", item_dir.display()))?;
+    fs::create_dir_all(item_dir).context(format!("Failed to recreate '{}'", item_dir.display()))?;
+    let marker = Marker { targets: targets.to_vec(), removed: chrono::Local::now().to_rfc3339() };
+    let json = serde_json::to_string_pretty(&marker).context("Failed to serialize replication marker")?;
+    fs::write(item_dir.join(MARKER_FILE), json).context(format!("Failed to write {}", MARKER_FILE))
+}
+
+async fn with_retries<F, Fut, T>(target: &ReplicationTarget, what: &str, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut delay = RETRY_DELAY;
+    for number in 1.. {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if number < ATTEMPTS => {
+                warn!("Replicating {} to {} failed (attempt {} of {}): {:#}", what, target.name, number, ATTEMPTS, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(e) => return Err(e.context(format!("Failed to replicate {} to {} after {} attempts", what, target.name, ATTEMPTS))),
+        }
+    }
+    unreachable!()
+}
+
+/// Arguments of the rsync run copying `relative` below `base_dir` to the target, keeping
+/// the path (`--relative` from the `/./` marker). `check` makes it a dry run listing the
+/// files whose checksum differs.
+fn rsync_args(target: &ReplicationTarget, base_dir: &Path, relative: &str, check: bool) -> Vec<String> {
+    let mut args = vec!["--archive".to_string(), "--relative".to_string(), "--partial".to_string(), "--exclude=*.part".to_string()];
+    if check {
+        args.extend(["--checksum".to_string(), "--dry-run".to_string(), "--itemize-changes".to_string()]);
+    }
+    if let Some(options) = target.ssh_options.as_deref().filter(|o| !o.trim().is_empty()) {
+        args.push("-e".to_string());
+        args.push(format!("ssh {}", options.trim()));
+    }
+    args.push(format!("{}/./{}", base_dir.display(), relative));
+    args.push(target.url.clone());
+    args
+}
+
+/// The components of `item_dir`'s path below `base_dir`, which it keeps on the targets.
+fn item_path(base_dir: &Path, item_dir: &Path) -> Result<Vec<String>> {
+    let relative = item_dir.strip_prefix(base_dir).context(format!("'{}' is outside the download directory", item_dir.display()))?;
+    let relative: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    if relative.is_empty() {
+        bail!("Refusing to replicate the whole download directory");
+    }
+    Ok(relative)
+}
+
+/// Runs rsync, returning its output.
+async fn run_rsync(args: Vec<String>) -> Result<String> {
+    let output = tokio::task::spawn_blocking(move || Command::new("rsync").args(&args).output())
+        .await
+        .context("rsync task failed")?
+        .context("Failed to run rsync (is it installed?)")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("rsync failed ({}): {}", output.status, stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Files below `item_dir` as (`/`-separated name, path, size), unfinished downloads left
+/// out.
+fn list_files(item_dir: &Path) -> Result<Vec<(String, PathBuf, u64)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![(item_dir.to_path_buf(), String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        for entry in fs::read_dir(&dir).context(format!("Failed to read directory '{}'", dir.display()))? {
+            let entry = entry?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push((entry.path(), format!("{}/", name)));
+            } else if metadata.is_file() && entry.path().extension().is_none_or(|ext| ext != "part") {
+                files.push((name, entry.path(), metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Creates the WebDAV folders of the item and of its files' subfolders, parents first.
+async fn make_collections(client: &Client, target: &ReplicationTarget, relative: &[String], files: &[(String, PathBuf, u64)]) -> Result<()> {
+    let mut dirs: Vec<String> = (1..=relative.len()).map(|depth| relative[..depth].join("/")).collect();
+    let item = relative.join("/");
+    for (name, _, _) in files {
+        let parts: Vec<&str> = name.split('/').collect();
+        for depth in 1..parts.len() {
+            let dir = format!("{}/{}", item, parts[..depth].join("/"));
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    for dir in dirs {
+        let mkcol = Method::from_bytes(b"MKCOL").expect("valid method");
+        let url = format!("{}/", object_url(target, &dir));
+        let status = with_retries(target, &dir, || async {
+            let response = authorize(client.request(mkcol.clone(), &url), target).send().await.context(format!("Failed to create folder '{}'", dir))?;
+            Ok(response.status())
+        })
+        .await?;
+        // 405: the folder exists already
+        if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+            bail!("Creating folder '{}' on {} failed with {}", dir, target.name, status);
+        }
+    }
+    Ok(())
+}
+
+async fn upload(client: &Client, target: &ReplicationTarget, key: &str, path: &Path, size: u64) -> Result<()> {
+    let file = tokio::fs::File::open(path).await.context(format!("Failed to open '{}'", path.display()))?;
+    let request = signed(client, target, Method::PUT, key)?.header(header::CONTENT_LENGTH, size).body(file);
+    let response = request.send().await.context(format!("Failed to upload '{}'", key))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("Upload of '{}' failed with {}: {}", key, status, body.chars().take(200).collect::<String>().trim());
+    }
+    Ok(())
+}
+
+/// Size of the uploaded copy, `None` if the target doesn't have it.
+async fn remote_size(client: &Client, target: &ReplicationTarget, key: &str) -> Result<Option<u64>> {
+    let response = signed(client, target, Method::HEAD, key)?.send().await.context(format!("Failed to check '{}'", key))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        bail!("Checking '{}' on {} failed with {}", key, target.name, response.status());
+    }
+    Ok(response.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse().ok()))
+}
+
+/// MD5 of an S3 object from its ETag; `None` if the ETag is something else (multipart
+/// uploads, some kinds of server-side encryption).
+async fn remote_etag_md5(client: &Client, target: &ReplicationTarget, key: &str) -> Result<Option<String>> {
+    let response = signed(client, target, Method::HEAD, key)?.send().await.context(format!("Failed to check '{}'", key))?;
+    if !response.status().is_success() {
+        bail!("Checking '{}' on {} failed with {}", key, target.name, response.status());
+    }
+    let etag = response.headers().get(header::ETAG).and_then(|v| v.to_str().ok()).unwrap_or_default().trim_matches('"');
+    Ok((etag.len() == 32 && etag.chars().all(|c| c.is_ascii_hexdigit())).then(|| etag.to_string()))
+}
+
+/// MD5 of the uploaded copy, downloaded in full.
+async fn remote_md5(client: &Client, target: &ReplicationTarget, key: &str) -> Result<String> {
+    let response = signed(client, target, Method::GET, key)?.send().await.context(format!("Failed to download '{}'", key))?;
+    if !response.status().is_success() {
+        bail!("Downloading '{}' from {} failed with {}", key, target.name, response.status());
+    }
+    let mut hasher = Md5::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        hasher.update(&chunk.context(format!("Failed to download '{}'", key))?);
+    }
+    Ok(hasher.finish_hex())
+}
+
+/// A request for `key`, with Basic auth (WebDAV) or a Signature Version 4 (S3).
+fn signed(client: &Client, target: &ReplicationTarget, method: Method, key: &str) -> Result<RequestBuilder> {
+    let url = object_url(target, key);
+    if target.kind != TargetKind::S3 {
+        return Ok(authorize(client.request(method, &url), target));
+    }
+    let (Some(access), Some(secret)) = (&target.username, &target.password) else {
+        bail!("S3 target {} needs `username` (access key) and `password` (secret key)", target.name);
+    };
+    storage::signed_request(client, method, &url, access, secret, target.region.as_deref().unwrap_or(storage::DEFAULT_REGION))
+}
+
+fn authorize(builder: RequestBuilder, target: &ReplicationTarget) -> RequestBuilder {
+    match &target.username {
+        Some(user) => builder.basic_auth(user, target.password.as_deref()),
+        None => builder,
+    }
+}
+
+/// URL of `key` (a `/`-separated path) on the target.
+fn object_url(target: &ReplicationTarget, key: &str) -> String {
+    storage::object_url(&target.url, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(kind: TargetKind, url: &str) -> ReplicationTarget {
+        ReplicationTarget { name: "nas".to_string(), kind, url: url.to_string(), username: None, password: None, region: None, ssh_options: None }
+    }
+
+    #[test]
+    fn test_targets_keep_the_item_path() {
+        let rsync = ReplicationTarget { ssh_options: Some("-p 2222".to_string()), ..target(TargetKind::Rsync, "backup@nas:/srv/archive") };
+        let args = rsync_args(&rsync, Path::new("/data/archive"), "etree/gd1977", false);
+        assert_eq!(args[args.len() - 2..], ["/data/archive/./etree/gd1977".to_string(), "backup@nas:/srv/archive".to_string()]);
+        assert!(args.contains(&"ssh -p 2222".to_string()));
+        assert!(rsync_args(&rsync, Path::new("/data/archive"), "etree/gd1977", true).contains(&"--dry-run".to_string()));
+
+        let dav = target(TargetKind::Webdav, "https://dav.example.com/archive/");
+        assert_eq!(object_url(&dav, "etree/gd1977/d1 t01.flac"), "https://dav.example.com/archive/etree/gd1977/d1%20t01.flac");
+
+        let settings: Settings = toml::from_str(
+            "replicate_delete_local = true\n[[replication_targets]]\nname = \"bucket\"\nkind = \"s3\"\nurl = \"https://s3.example.com/b\"\nusername = \"AKID\"\npassword = \"secret\"\n",
+        )
+        .unwrap();
+        let replication = Replication::from_settings(&settings).unwrap();
+        assert_eq!(replication.targets[0].kind, TargetKind::S3);
+        assert!(replication.delete_local);
+        assert!(!format!("{:?}", replication).contains("secret"));
+        assert!(Replication::from_settings(&Settings::default()).is_none());
+    }
+
+    #[test]
+    fn test_removed_items_leave_a_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = dir.path().join("etree/gd1977");
+        fs::create_dir_all(item.join("disc 1")).unwrap();
+        fs::write(item.join("disc 1/t01.flac"), b"flac").unwrap();
+        fs::write(item.join("t02.flac.part"), b"fl").unwrap();
+        let files = list_files(&item).unwrap();
+        assert_eq!(files.iter().map(|(name, _, size)| (name.as_str(), *size)).collect::<Vec<_>>(), [("disc 1/t01.flac", 4)]);
+
+        assert_eq!(replicated_to(&item), None);
+        remove_local(&item, &["nas".to_string()]).unwrap();
+        assert!(!item.join("disc 1").exists());
+        assert_eq!(replicated_to(&item), Some(vec!["nas".to_string()]));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_content_is_checked_before_deleting() {
+        use crate::mock_server;
+        let dir = tempfile::tempdir().unwrap();
+        let item = dir.path().join("etree/gd1977-replica");
+        fs::create_dir_all(&item).unwrap();
+        fs::write(item.join("t01.flac"), b"flac").unwrap();
+        let bucket = ReplicationTarget {
+            username: Some(mock_server::MOCK_S3_ACCESS.to_string()),
+            password: Some(mock_server::MOCK_S3_SECRET.to_string()),
+            ..target(TargetKind::S3, &format!("{}/bucket/replica", mock_server::shared_base_url()))
+        };
+        let client = Client::new();
+        push(&client, &bucket, dir.path(), &item).await.unwrap();
+        verify_content(&client, &bucket, dir.path(), &item).await.unwrap();
+
+        // Same size, other content: only the MD5 tells them apart
+        fs::write(item.join("t01.flac"), b"FLAC").unwrap();
+        let err = verify_content(&client, &bucket, dir.path(), &item).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("instead of"), "{:#}", err);
+    }
+}
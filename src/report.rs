@@ -0,0 +1,470 @@
+use crate::forecast;
+use crate::fs_util;
+use crate::saved_search;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Directory (inside the download directory) the end-of-run reports are written to.
+pub const REPORTS_DIR: &str = ".archiver-reports";
+
+/// What happened to one file of a download.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FileOutcome {
+    Downloaded,
+    /// Already on disk with the expected size.
+    AlreadyPresent,
+    /// Linked to an identical file already on disk (see [`crate::dedupe`]).
+    Linked,
+    /// Needs a login that can borrow/stream the item.
+    Restricted,
+    /// The download failed; the reason is the error chain.
+    Failed(String),
+}
+
+/// Result of one file download, reported by [`crate::download::download_single_file`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileResult {
+    pub item_id: String,
+    pub name: String,
+    pub outcome: FileOutcome,
+    /// Bytes transferred (or found on disk for [`FileOutcome::AlreadyPresent`] and
+    /// [`FileOutcome::Linked`]).
+    pub bytes: u64,
+    /// Whether the size on disk matches the size archive.org lists (`None` if unknown).
+    pub size_verified: Option<bool>,
+}
+
+/// How an item download ended.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemOutcome {
+    Downloaded,
+    Failed,
+    /// The identifier turned out to be a collection (torrent mode).
+    SkippedCollection,
+    /// Another archiver instance was downloading it.
+    SkippedClaimed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ItemReport {
+    pub identifier: String,
+    /// `None` while running, or for single-file downloads (see [`RunReport::finish`]).
+    pub outcome: Option<ItemOutcome>,
+    pub duration_secs: Option<f64>,
+    /// RFC 3339 time the item ended (`None` like `outcome`).
+    pub finished: Option<String>,
+    pub files: Vec<FileResult>,
+}
+
+impl ItemReport {
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes).sum()
+    }
+
+    /// Bytes of the files linked instead of downloaded.
+    pub fn bytes_linked(&self) -> u64 {
+        self.files.iter().filter(|f| f.outcome == FileOutcome::Linked).map(|f| f.bytes).sum()
+    }
+
+    fn count(&self, matches: impl Fn(&FileOutcome) -> bool) -> usize {
+        self.files.iter().filter(|f| matches(&f.outcome)).count()
+    }
+}
+
+/// Everything that happened during one download run, i.e. from the first job starting
+/// until no job is left in flight.
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    /// Download directory of the run; the report is written inside it.
+    pub download_dir: PathBuf,
+    /// RFC 3339 start and end times.
+    pub started: String,
+    pub finished: Option<String>,
+    pub duration_secs: f64,
+    /// Descriptions of the jobs of the run (see [`crate::session::DownloadJob::describe`]).
+    pub jobs: Vec<String>,
+    pub items: Vec<ItemReport>,
+    /// Errors not tied to a file (e.g. failing to list a collection).
+    pub errors: Vec<String>,
+    #[serde(skip)]
+    started_at: Instant,
+    #[serde(skip)]
+    item_started: HashMap<String, Instant>,
+}
+
+impl RunReport {
+    pub fn new(download_dir: PathBuf) -> Self {
+        Self {
+            download_dir,
+            started: chrono::Local::now().to_rfc3339(),
+            finished: None,
+            duration_secs: 0.0,
+            jobs: Vec::new(),
+            items: Vec::new(),
+            errors: Vec::new(),
+            started_at: Instant::now(),
+            item_started: HashMap::new(),
+        }
+    }
+
+    fn item_mut(&mut self, identifier: &str) -> &mut ItemReport {
+        let index = match self.items.iter().position(|item| item.identifier == identifier) {
+            Some(index) => index,
+            None => {
+                self.items.push(ItemReport { identifier: identifier.to_string(), outcome: None, duration_secs: None, finished: None, files: Vec::new() });
+                self.items.len() - 1
+            }
+        };
+        &mut self.items[index]
+    }
+
+    pub fn item_started(&mut self, identifier: &str) {
+        self.item_mut(identifier);
+        self.item_started.insert(identifier.to_string(), Instant::now());
+    }
+
+    pub fn file_finished(&mut self, result: FileResult) {
+        self.item_mut(&result.item_id).files.push(result);
+    }
+
+    pub fn item_finished(&mut self, identifier: &str, outcome: ItemOutcome) {
+        let duration = self.item_started.remove(identifier).map(|t| t.elapsed().as_secs_f64());
+        let item = self.item_mut(identifier);
+        item.outcome = Some(outcome);
+        item.duration_secs = duration;
+        item.finished = Some(chrono::Local::now().to_rfc3339());
+    }
+
+    pub fn error(&mut self, message: String) {
+        self.errors.push(message);
+    }
+
+    /// Closes the run. Items without a reported outcome (single-file downloads) are
+    /// judged by their files.
+    pub fn finish(&mut self) {
+        self.finished = Some(chrono::Local::now().to_rfc3339());
+        self.duration_secs = self.started_at.elapsed().as_secs_f64();
+        for item in &mut self.items {
+            if item.outcome.is_none() {
+                let failed = item.files.iter().any(|f| matches!(f.outcome, FileOutcome::Failed(_)));
+                item.outcome = Some(if failed { ItemOutcome::Failed } else { ItemOutcome::Downloaded });
+            }
+        }
+        // Failed requests are also sent as errors; list them once, with their file
+        let file_reasons: Vec<&str> = self
+            .items
+            .iter()
+            .flat_map(|item| &item.files)
+            .filter_map(|f| match &f.outcome {
+                FileOutcome::Failed(reason) => Some(reason.as_str()),
+                _ => None,
+            })
+            .collect();
+        let errors = std::mem::take(&mut self.errors);
+        self.errors = errors.into_iter().filter(|e| !file_reasons.contains(&e.as_str())).collect();
+    }
+
+    /// Writes the report as `report-<time>.json` and `.html` into
+    /// `<download_dir>/.archiver-reports` and returns the HTML path.
+    pub fn write(&self) -> Result<PathBuf> {
+        let dir = self.download_dir.join(REPORTS_DIR);
+        fs::create_dir_all(&dir).context(format!("Failed to create report directory {}", dir.display()))?;
+        let stem = format!("report-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let json_path = dir.join(format!("{}.json", stem));
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize download report")?;
+        fs_util::atomic_write(&json_path, json).context(format!("Failed to write report {}", json_path.display()))?;
+        let html_path = dir.join(format!("{}.html", stem));
+        fs_util::atomic_write(&html_path, self.to_html()).context(format!("Failed to write report {}", html_path.display()))?;
+        Ok(html_path)
+    }
+
+    /// One-line totals, e.g. for the status bar.
+    pub fn summary(&self) -> String {
+        let count = |outcome: ItemOutcome| self.items.iter().filter(|item| item.outcome == Some(outcome)).count();
+        let skipped = count(ItemOutcome::SkippedCollection) + count(ItemOutcome::SkippedClaimed);
+        let bytes: u64 = self.items.iter().map(ItemReport::bytes).sum();
+        let linked: u64 = self.items.iter().map(ItemReport::bytes_linked).sum();
+        let mut summary = format!(
+            "{} item(s) downloaded, {} failed, {} skipped, {} in {}",
+            count(ItemOutcome::Downloaded),
+            count(ItemOutcome::Failed),
+            skipped,
+            forecast::format_bytes(bytes),
+            forecast::format_duration(Duration::from_secs_f64(self.duration_secs))
+        );
+        if linked > 0 {
+            let _ = write!(summary, "; {} saved by linking duplicates", forecast::format_bytes(linked));
+        }
+        summary
+    }
+
+    fn to_html(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>archiver report {}</title>\n\
+             <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}td,th{{border:1px solid #999;padding:2px 6px;text-align:left}}.failed{{color:#b00}}</style>\n\
+             </head><body>\n<h1>Download report</h1>\n<p>{} &ndash; {}</p>\n<p>{}</p>\n<ul>",
+            escape(&self.started),
+            escape(&self.started),
+            escape(self.finished.as_deref().unwrap_or("running")),
+            escape(&self.summary())
+        );
+        for job in &self.jobs {
+            let _ = write!(html, "<li>{}</li>", escape(job));
+        }
+        html.push_str("</ul>\n");
+
+        let failures: Vec<(&str, &str, &str)> = self
+            .items
+            .iter()
+            .flat_map(|item| item.files.iter().filter_map(|f| match &f.outcome {
+                FileOutcome::Failed(reason) => Some((item.identifier.as_str(), f.name.as_str(), reason.as_str())),
+                _ => None,
+            }))
+            .collect();
+        if !failures.is_empty() || !self.errors.is_empty() {
+            html.push_str("<h2>Failures</h2>\n<table><tr><th>Item</th><th>File</th><th>Reason</th></tr>\n");
+            for (item, file, reason) in failures {
+                let _ = writeln!(html, "<tr class=\"failed\"><td>{}</td><td>{}</td><td>{}</td></tr>", escape(item), escape(file), escape(reason));
+            }
+            for error in &self.errors {
+                let _ = writeln!(html, "<tr class=\"failed\"><td colspan=\"2\"></td><td>{}</td></tr>", escape(error));
+            }
+            html.push_str("</table>\n");
+        }
+
+        let mismatches: Vec<(&str, &str)> = self
+            .items
+            .iter()
+            .flat_map(|item| item.files.iter().filter(|f| f.size_verified == Some(false)).map(|f| (item.identifier.as_str(), f.name.as_str())))
+            .collect();
+        if !mismatches.is_empty() {
+            html.push_str("<h2>Size mismatches</h2>\n<ul>\n");
+            for (item, file) in mismatches {
+                let _ = writeln!(html, "<li>{}/{}</li>", escape(item), escape(file));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str(
+            "<h2>Items</h2>\n<table><tr><th>Item</th><th>Result</th><th>Downloaded</th><th>Already present</th>\
+             <th>Linked</th><th>Restricted</th><th>Failed</th><th>Size verified</th><th>Size</th><th>Duration</th></tr>\n",
+        );
+        for item in &self.items {
+            let outcome = item.outcome.map_or("Running".to_string(), |o| format!("{:?}", o));
+            let verified = item.files.iter().filter(|f| f.size_verified == Some(true)).count();
+            let duration = item.duration_secs.map_or(String::new(), |s| forecast::format_duration(Duration::from_secs_f64(s)));
+            let _ = writeln!(
+                html,
+                "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}/{}</td><td>{}</td><td>{}</td></tr>",
+                if item.outcome == Some(ItemOutcome::Failed) { " class=\"failed\"" } else { "" },
+                escape(&item.identifier),
+                outcome,
+                item.count(|o| *o == FileOutcome::Downloaded),
+                item.count(|o| *o == FileOutcome::AlreadyPresent),
+                item.count(|o| *o == FileOutcome::Linked),
+                item.count(|o| *o == FileOutcome::Restricted),
+                item.count(|o| matches!(o, FileOutcome::Failed(_))),
+                verified,
+                item.files.len(),
+                forecast::format_bytes(item.bytes()),
+                duration
+            );
+        }
+        html.push_str("</table>\n</body></html>\n");
+        html
+    }
+}
+
+/// A collection download that ended, sent by [`crate::download::download_items`]; what
+/// happened to its items is in the [`RunReport`].
+#[derive(Debug, Clone)]
+pub struct CollectionRun {
+    pub collection: String,
+    pub items: Vec<String>,
+    /// RFC 3339 start time.
+    pub started: String,
+    pub duration: Duration,
+}
+
+/// Totals of one collection download, written to `collection-<identifier>-<time>.json` and
+/// `.txt` next to the run reports and shown when the download ends.
+#[derive(Serialize, Debug, Clone)]
+pub struct CollectionReport {
+    pub collection: String,
+    /// RFC 3339 start and end times.
+    pub started: String,
+    pub finished: String,
+    pub duration_secs: f64,
+    pub attempted: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Items that were collections themselves or claimed by another instance.
+    pub skipped: usize,
+    /// Bytes of the files downloaded (not those already on disk or linked).
+    pub bytes_transferred: u64,
+    /// Bytes per second over the whole download.
+    pub average_speed: f64,
+    pub failed_items: Vec<String>,
+}
+
+impl CollectionReport {
+    /// Totals of `run` from what `report` recorded for its items. Items without an outcome
+    /// never finished, so they count as failed.
+    pub fn new(run: &CollectionRun, report: &RunReport) -> Self {
+        let (mut succeeded, mut skipped, mut bytes_transferred) = (0, 0, 0);
+        let mut failed_items = Vec::new();
+        for identifier in &run.items {
+            let item = report.items.iter().find(|item| item.identifier == *identifier);
+            match item.and_then(|item| item.outcome) {
+                Some(ItemOutcome::Downloaded) => succeeded += 1,
+                Some(ItemOutcome::SkippedCollection | ItemOutcome::SkippedClaimed) => skipped += 1,
+                _ => failed_items.push(identifier.clone()),
+            }
+            bytes_transferred += item.map_or(0, |item| item.files.iter().filter(|f| f.outcome == FileOutcome::Downloaded).map(|f| f.bytes).sum());
+        }
+        let duration_secs = run.duration.as_secs_f64();
+        Self {
+            collection: run.collection.clone(),
+            started: run.started.clone(),
+            finished: chrono::Local::now().to_rfc3339(),
+            duration_secs,
+            attempted: run.items.len(),
+            succeeded,
+            failed: failed_items.len(),
+            skipped,
+            bytes_transferred,
+            average_speed: if duration_secs > 0.0 { bytes_transferred as f64 / duration_secs } else { 0.0 },
+            failed_items,
+        }
+    }
+
+    /// `Items: 10 attempted, ...` and `Transferred: ...` lines, as in the popup.
+    pub fn summary_lines(&self) -> Vec<String> {
+        vec![
+            format!("Items: {} attempted, {} succeeded, {} failed, {} skipped", self.attempted, self.succeeded, self.failed, self.skipped),
+            format!(
+                "Transferred: {} in {} ({}/s)",
+                forecast::format_bytes(self.bytes_transferred),
+                forecast::format_duration(Duration::from_secs_f64(self.duration_secs)),
+                forecast::format_bytes(self.average_speed as u64)
+            ),
+        ]
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("Collection download report: {}\n{} - {}\n", self.collection, self.started, self.finished);
+        for line in self.summary_lines() {
+            let _ = writeln!(text, "{}", line);
+        }
+        if !self.failed_items.is_empty() {
+            text.push_str("\nFailed items:\n");
+            for identifier in &self.failed_items {
+                let _ = writeln!(text, "  {}", identifier);
+            }
+        }
+        text
+    }
+
+    /// Writes the report as `collection-<identifier>-<time>.json` and `.txt` into
+    /// `<download_dir>/.archiver-reports` and returns the text file's path.
+    pub fn write(&self, download_dir: &Path) -> Result<PathBuf> {
+        let dir = download_dir.join(REPORTS_DIR);
+        fs::create_dir_all(&dir).context(format!("Failed to create report directory {}", dir.display()))?;
+        let stem = format!("collection-{}-{}", saved_search::local_name(&self.collection), chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let json_path = dir.join(format!("{}.json", stem));
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize collection report")?;
+        fs_util::atomic_write(&json_path, json).context(format!("Failed to write report {}", json_path.display()))?;
+        let text_path = dir.join(format!("{}.txt", stem));
+        fs_util::atomic_write(&text_path, self.to_text()).context(format!("Failed to write report {}", text_path.display()))?;
+        Ok(text_path)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(item: &str, name: &str, outcome: FileOutcome, bytes: u64) -> FileResult {
+        FileResult { item_id: item.to_string(), name: name.to_string(), outcome, bytes, size_verified: Some(true) }
+    }
+
+    #[test]
+    fn test_report_written_as_json_and_html() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut report = RunReport::new(dir.path().to_path_buf());
+        report.jobs.push("collection coll".to_string());
+        report.item_started("good");
+        report.file_finished(file("good", "a.mp3", FileOutcome::Downloaded, 1000));
+        report.file_finished(file("good", "b.mp3", FileOutcome::AlreadyPresent, 500));
+        report.file_finished(file("good", "c.mp3", FileOutcome::Linked, 524));
+        report.item_finished("good", ItemOutcome::Downloaded);
+        report.item_started("bad");
+        report.error("Status 404 Not Found".to_string());
+        report.file_finished(file("bad", "<c>.mp3", FileOutcome::Failed("Status 404 Not Found".to_string()), 0));
+        report.error("Download Task Error: disk full".to_string());
+        report.item_finished("bad", ItemOutcome::Failed);
+        report.item_finished("coll2", ItemOutcome::SkippedCollection);
+        // Single-file download: no item events
+        report.file_finished(file("single", "d.pdf", FileOutcome::Downloaded, 10));
+        report.finish();
+
+        assert!(report.summary().starts_with("2 item(s) downloaded, 1 failed, 1 skipped, 2.0 KB"), "{}", report.summary());
+        assert!(report.summary().ends_with("; 524 B saved by linking duplicates"), "{}", report.summary());
+
+        assert_eq!(report.errors, vec!["Download Task Error: disk full"]);
+
+        let html_path = report.write().unwrap();
+        let html = fs::read_to_string(&html_path).unwrap();
+        assert!(html.contains("&lt;c&gt;.mp3") && html.contains("Status 404 Not Found"));
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(html_path.with_extension("json")).unwrap()).unwrap();
+        assert_eq!(json["items"].as_array().unwrap().len(), 4);
+        assert_eq!(json["items"][1]["files"][0]["outcome"]["Failed"], "Status 404 Not Found");
+        assert_eq!(json["items"][3]["outcome"], "Downloaded");
+    }
+
+    #[test]
+    fn test_collection_report_totals_its_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut report = RunReport::new(dir.path().to_path_buf());
+        report.file_finished(file("a", "a.mp3", FileOutcome::Downloaded, 3000));
+        report.file_finished(file("a", "b.mp3", FileOutcome::AlreadyPresent, 9000));
+        report.item_finished("a", ItemOutcome::Downloaded);
+        report.item_finished("b", ItemOutcome::Failed);
+        report.item_finished("c", ItemOutcome::SkippedClaimed);
+        report.file_finished(file("other", "x.mp3", FileOutcome::Downloaded, 500));
+        report.item_finished("other", ItemOutcome::Downloaded);
+        let run = CollectionRun {
+            collection: "coll".to_string(),
+            items: ["a", "b", "c", "d"].map(str::to_string).to_vec(),
+            started: chrono::Local::now().to_rfc3339(),
+            duration: Duration::from_secs(2),
+        };
+
+        let collection = CollectionReport::new(&run, &report);
+        assert_eq!((collection.attempted, collection.succeeded, collection.failed, collection.skipped), (4, 1, 2, 1));
+        assert_eq!(collection.failed_items, ["b", "d"], "d never finished");
+        assert_eq!(collection.bytes_transferred, 3000, "Files already present aren't transferred");
+        assert_eq!(collection.average_speed, 1500.0);
+        assert_eq!(collection.summary_lines()[0], "Items: 4 attempted, 1 succeeded, 2 failed, 1 skipped");
+
+        let text_path = collection.write(dir.path()).unwrap();
+        let text = fs::read_to_string(&text_path).unwrap();
+        assert!(text.starts_with("Collection download report: coll\n") && text.ends_with("Failed items:\n  b\n  d\n"), "{}", text);
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(text_path.with_extension("json")).unwrap()).unwrap();
+        assert_eq!(json["bytes_transferred"], 3000);
+    }
+}
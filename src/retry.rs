@@ -0,0 +1,240 @@
+use crate::fs_util;
+use crate::session::DownloadJob;
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, time::Duration};
+
+/// Most attempts the settings allow.
+pub const MAX_ATTEMPTS: u32 = 10;
+/// File (in the config directory) holding the downloads waiting to be retried.
+pub const RETRIES_FILE: &str = "retries.json";
+/// Wait before the first retry; each further one waits twice as long.
+const BASE_DELAY: Duration = Duration::from_secs(30);
+/// Longest wait between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How often a download that failed for a passing reason (network or server trouble) is
+/// tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first one included; 1 or less never retries.
+    pub max_attempts: u32,
+    /// Time after the first failure from which no more retries are made; `None` = no limit.
+    pub give_up_after: Option<Duration>,
+}
+
+impl RetryPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            max_attempts: settings.retry_max_attempts,
+            give_up_after: (settings.retry_give_up_hours > 0).then(|| Duration::from_secs(settings.retry_give_up_hours * 60 * 60)),
+        }
+    }
+
+    /// Wait after the `attempts`th failed attempt: 30s, 1m, 2m, ... up to an hour.
+    pub fn delay(attempts: u32) -> Duration {
+        BASE_DELAY.saturating_mul(1 << attempts.saturating_sub(1).min(16)).min(MAX_DELAY)
+    }
+}
+
+/// Failed attempts of a retried job, carried by the job so a retry that fails again
+/// (even after a restart) counts on from there.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryState {
+    pub attempts: u32,
+    pub first_failed: DateTime<Utc>,
+}
+
+/// A job waiting for its next attempt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetryEntry {
+    pub job: DownloadJob,
+    pub due: DateTime<Utc>,
+    /// Why the last attempt failed.
+    pub reason: String,
+}
+
+/// What [`RetryQueue::schedule`] decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// The job runs again in this long; `attempt` is the number of the next attempt.
+    After { delay: Duration, attempt: u32 },
+    /// The policy's attempts or time ran out after this many attempts.
+    GaveUp { attempts: u32 },
+}
+
+/// Persistent list of download jobs to try again once their backoff has passed.
+///
+/// Jobs come from downloads that failed for a reason likely to pass (see
+/// [`crate::failures::ErrorKind::is_transient`]): a single file, or an item whose
+/// metadata couldn't be fetched or some of whose files failed. Retrying an item skips
+/// the files already on disk. Due jobs are started by the main loop; the list is saved
+/// like the download queue, so retries survive a restart.
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    /// `None` keeps the list in memory only (tests, mock mode).
+    path: Option<PathBuf>,
+    entries: Vec<RetryEntry>,
+}
+
+impl RetryQueue {
+    /// Loads the list at `path`. A missing file is an empty list; a corrupt one is backed
+    /// up and replaced.
+    pub fn load(path: PathBuf) -> Self {
+        let entries: Vec<RetryEntry> = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Retry list {} is corrupt ({}); starting fresh", path.display(), e);
+                if let Err(backup_err) = fs_util::backup_corrupt_file(&path) {
+                    error!("{:#}", backup_err);
+                }
+                Vec::new()
+            }),
+            Err(_) => Vec::new(), // Nothing to retry
+        };
+        if !entries.is_empty() {
+            info!("Found {} download(s) to retry in {}", entries.len(), path.display());
+        }
+        Self { path: Some(path), entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records that an attempt of `job` failed with `reason` and schedules the next one,
+    /// unless `policy` says to give up. A job already waiting is replaced.
+    pub fn schedule(&mut self, mut job: DownloadJob, reason: String, policy: &RetryPolicy, now: DateTime<Utc>) -> Retry {
+        self.entries.retain(|entry| !same_work(&entry.job, &job));
+        let state = match job.retry {
+            Some(state) => RetryState { attempts: state.attempts + 1, ..state },
+            None => RetryState { attempts: 1, first_failed: now },
+        };
+        let delay = RetryPolicy::delay(state.attempts);
+        let out_of_time = policy.give_up_after.is_some_and(|limit| {
+            let retry_at = now.signed_duration_since(state.first_failed).to_std().unwrap_or_default() + delay;
+            retry_at > limit
+        });
+        if state.attempts >= policy.max_attempts || out_of_time {
+            self.save_logged();
+            return Retry::GaveUp { attempts: state.attempts };
+        }
+        job.retry = Some(state);
+        let due = now + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX);
+        self.entries.push(RetryEntry { job, due, reason });
+        self.save_logged();
+        Retry::After { delay, attempt: state.attempts + 1 }
+    }
+
+    /// Removes and returns the jobs whose time has come.
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<DownloadJob> {
+        if !self.entries.iter().any(|entry| entry.due <= now) {
+            return Vec::new();
+        }
+        let (due, waiting) = std::mem::take(&mut self.entries).into_iter().partition(|entry| entry.due <= now);
+        self.entries = waiting;
+        self.save_logged();
+        due.into_iter().map(|entry: RetryEntry| entry.job).collect()
+    }
+
+    /// When the next job is due.
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.entries.iter().map(|entry| entry.due).min()
+    }
+
+    fn save_logged(&self) {
+        if let Err(e) = self.save() {
+            error!("{:#}", e);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if self.entries.is_empty() {
+            return match fs::remove_file(path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e).context(format!("Failed to remove retry list {}", path.display())),
+            };
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("Failed to create config directory {}", dir.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries).context("Failed to serialize retry list")?;
+        fs_util::atomic_write(path, json).context(format!("Failed to write retry list {}", path.display()))
+    }
+}
+
+/// Whether two jobs download the same thing (whatever their attempts so far).
+fn same_work(a: &DownloadJob, b: &DownloadJob) -> bool {
+    a.action == b.action && a.base_dir == b.base_dir && a.collection == b.collection
+}
+
+/// The settings row: "Off" or "5 attempts, give up after 24h".
+pub fn describe(settings: &Settings) -> String {
+    match (settings.retry_max_attempts, settings.retry_give_up_hours) {
+        (0 | 1, _) => "Off".to_string(),
+        (attempts, 0) => format!("{} attempts", attempts),
+        (attempts, hours) => format!("{} attempts, give up after {}h", attempts, hours),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::DownloadAction;
+    use crate::presets::FilePreset;
+    use crate::settings::DownloadMode;
+
+    fn job(item: &str) -> DownloadJob {
+        DownloadJob {
+            action: DownloadAction::ItemAllFiles(item.to_string()),
+            collection: None,
+            mode: DownloadMode::Direct,
+            base_dir: "/downloads".to_string(),
+            preset: FilePreset::AllFiles,
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn test_retries_back_off_survive_restart_and_give_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(RETRIES_FILE);
+        let policy = RetryPolicy { max_attempts: 3, give_up_after: None };
+        let start = Utc::now();
+
+        let mut retries = RetryQueue::load(path.clone());
+        let scheduled = retries.schedule(job("one"), "connection reset".to_string(), &policy, start);
+        assert_eq!(scheduled, Retry::After { delay: Duration::from_secs(30), attempt: 2 });
+        assert!(retries.take_due(start).is_empty(), "Not due yet");
+
+        let mut reloaded = RetryQueue::load(path.clone());
+        assert_eq!(reloaded.next_due(), Some(start + chrono::Duration::seconds(30)));
+        let due = reloaded.take_due(start + chrono::Duration::seconds(30));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].retry.map(|state| state.attempts), Some(1));
+        assert!(!path.exists(), "Nothing left to retry");
+
+        // The retry fails too: twice the wait, then the third attempt is the last
+        let later = start + chrono::Duration::seconds(40);
+        let scheduled = reloaded.schedule(due[0].clone(), "status 503".to_string(), &policy, later);
+        assert_eq!(scheduled, Retry::After { delay: Duration::from_secs(60), attempt: 3 });
+        let third = reloaded.take_due(later + chrono::Duration::seconds(60)).remove(0);
+        assert_eq!(reloaded.schedule(third, "status 503".to_string(), &policy, later), Retry::GaveUp { attempts: 3 });
+        assert!(reloaded.is_empty());
+
+        let patient = RetryPolicy { max_attempts: 10, give_up_after: Some(Duration::from_secs(60)) };
+        assert_eq!(RetryQueue::default().schedule(job("two"), String::new(), &patient, start), Retry::After { delay: Duration::from_secs(30), attempt: 2 });
+        let mut failed_before = job("two");
+        failed_before.retry = Some(RetryState { attempts: 1, first_failed: start - chrono::Duration::seconds(50) });
+        assert_eq!(RetryQueue::default().schedule(failed_before, String::new(), &patient, start), Retry::GaveUp { attempts: 2 }, "Past the time limit");
+        assert_eq!(RetryPolicy::delay(40), MAX_DELAY);
+    }
+}
@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+
+/// Prefix of a favorite collection entry that is a saved search, e.g.
+/// `search:creator:"Grateful Dead" AND year:1977`. Collection identifiers can't contain
+/// `:`, so the two never clash.
+///
+/// A saved search behaves like a collection whose items are the search's results: it is
+/// listed (in full, through the scrape API), downloaded, estimated and synced the same way.
+/// Its folder and caches use [`local_name`] instead of the query.
+pub const PREFIX: &str = "search:";
+
+/// Longest part of [`local_name`] taken from the query.
+const MAX_SLUG_LEN: usize = 48;
+
+/// The favorites entry saving `query`.
+pub fn entry(query: &str) -> String {
+    format!("{}{}", PREFIX, query.trim())
+}
+
+/// The query of a saved search entry, `None` for a real collection.
+pub fn query(collection: &str) -> Option<&str> {
+    collection.strip_prefix(PREFIX)
+}
+
+/// The search clause listing `collection`: its members, or a saved search's results.
+pub fn listing_query(collection: &str) -> String {
+    match query(collection) {
+        Some(query) => format!("({})", query),
+        None => format!("collection:\"{}\"", collection), // Ensure collection name is quoted
+    }
+}
+
+/// Name of the folder and cache files of `collection`: the identifier itself, or for a
+/// saved search `search-` with a slug of the query and a hash telling similar ones apart,
+/// e.g. `search-creator-grateful-dead-and-year-1977-6a1f5c85`.
+pub fn local_name(collection: &str) -> Cow<'_, str> {
+    let Some(query) = query(collection) else { return Cow::Borrowed(collection) };
+    let mut slug = String::new();
+    for c in query.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    Cow::Owned(format!("search-{}-{:08x}", slug, fnv1a(query)))
+}
+
+/// How the collections pane shows `collection`.
+pub fn label(collection: &str) -> Cow<'_, str> {
+    match query(collection) {
+        Some(query) => Cow::Owned(format!("[Search] {}", query)),
+        None => Cow::Borrowed(collection),
+    }
+}
+
+/// 32-bit FNV-1a, stable across builds (unlike the std hasher).
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_searches_are_told_apart_from_collections() {
+        let saved = entry("  creator:\"Grateful Dead\" AND year:1977 ");
+        assert_eq!(saved, "search:creator:\"Grateful Dead\" AND year:1977");
+        assert_eq!(listing_query(&saved), "(creator:\"Grateful Dead\" AND year:1977)");
+        assert_eq!(listing_query("etree"), "collection:\"etree\"");
+        assert_eq!(label(&saved), "[Search] creator:\"Grateful Dead\" AND year:1977");
+        assert_eq!(label("etree"), "etree");
+
+        let name = local_name(&saved);
+        assert_eq!(name, "search-creator-grateful-dead-and-year-1977-6a1f5c85");
+        assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'), "Safe as a file name: {}", name);
+        assert_ne!(name, local_name("search:creator:\"Grateful Dead\" AND year:1978"));
+        assert_eq!(local_name("etree"), "etree");
+        assert!(local_name(&entry(&"x".repeat(500))).len() < 70);
+    }
+}
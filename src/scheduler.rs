@@ -0,0 +1,233 @@
+use crate::app::DownloadAction;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+/// How urgently a download job's files are wanted; a free download slot goes to the most
+/// urgent waiting file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// A single file picked by hand: usually small, and someone is waiting for it.
+    Interactive,
+    /// One item, or a few picked in the Items pane.
+    Item,
+    /// A whole collection.
+    Bulk,
+}
+
+impl Priority {
+    pub fn of(action: &DownloadAction) -> Self {
+        match action {
+            DownloadAction::File(..) => Priority::Interactive,
+            DownloadAction::ItemAllFiles(_) | DownloadAction::Items(_) => Priority::Item,
+            DownloadAction::Collection(_) => Priority::Bulk,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::Interactive => "file",
+            Priority::Item => "item",
+            Priority::Bulk => "collection",
+        }
+    }
+}
+
+/// A download job as the scheduler sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ticket {
+    /// Tells jobs of the same priority apart (the session journal id).
+    pub job: u64,
+    pub priority: Priority,
+}
+
+impl Ticket {
+    pub fn new(job: u64, action: &DownloadAction) -> Self {
+        Self { job, priority: Priority::of(action) }
+    }
+}
+
+/// Hands out the download slots (concurrent file transfers) shared by every running job.
+///
+/// A free slot goes to a waiting file of the highest priority class, so a file grabbed by
+/// hand or an item download doesn't wait behind the queue of a collection download. Within
+/// a class, jobs take turns: the slot goes to the job served longest ago, so two
+/// collections downloading at once progress at the same pace.
+#[derive(Debug)]
+pub struct DownloadScheduler {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    slots: usize,
+    in_use: usize,
+    waiting: VecDeque<(Ticket, oneshot::Sender<Slot>)>,
+    /// When each job was last given a slot (a counter, not a time).
+    last_served: HashMap<u64, u64>,
+    served: u64,
+}
+
+impl DownloadScheduler {
+    pub fn new(slots: usize) -> Arc<Self> {
+        let state = State { slots: slots.max(1), in_use: 0, waiting: VecDeque::new(), last_served: HashMap::new(), served: 0 };
+        Arc::new(Self { state: Mutex::new(state) })
+    }
+
+    /// Waits for a slot for a file of the job `ticket`. The slot is given back when the
+    /// returned [`Slot`] is dropped.
+    pub async fn acquire(self: &Arc<Self>, ticket: Ticket) -> Slot {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_use < state.slots && state.waiting.is_empty() {
+                state.in_use += 1;
+                state.serve(ticket.job);
+                return Slot { scheduler: Some(Arc::clone(self)) };
+            }
+            let (tx, rx) = oneshot::channel();
+            state.waiting.push_back((ticket, tx));
+            rx
+        };
+        match waiter.await {
+            Ok(slot) => slot,
+            Err(_) => unreachable!("Waiting files are only dropped after being handed a slot"),
+        }
+    }
+
+    /// Files waiting for a slot, by priority class.
+    pub fn waiting(&self, priority: Priority) -> usize {
+        self.state.lock().unwrap().waiting.iter().filter(|(ticket, _)| ticket.priority == priority).count()
+    }
+
+    /// Passes a slot given back to the next waiting file, or frees it.
+    fn release(self: &Arc<Self>) {
+        loop {
+            let tx = {
+                let mut state = self.state.lock().unwrap();
+                match state.next_waiting() {
+                    Some((ticket, tx)) => {
+                        state.serve(ticket.job);
+                        tx
+                    }
+                    None => {
+                        state.in_use -= 1;
+                        return;
+                    }
+                }
+            };
+            // A waiter whose download was cancelled is gone; try the next one
+            match tx.send(Slot { scheduler: Some(Arc::clone(self)) }) {
+                Ok(()) => return,
+                Err(slot) => slot.disarm(),
+            }
+        }
+    }
+}
+
+impl State {
+    fn serve(&mut self, job: u64) {
+        self.served += 1;
+        self.last_served.insert(job, self.served);
+    }
+
+    /// The first waiting file of the most urgent class whose job was served longest ago.
+    fn next_waiting(&mut self) -> Option<(Ticket, oneshot::Sender<Slot>)> {
+        let priority = self.waiting.iter().map(|(ticket, _)| ticket.priority).min()?;
+        let index = self
+            .waiting
+            .iter()
+            .enumerate()
+            .filter(|(_, (ticket, _))| ticket.priority == priority)
+            .min_by_key(|(index, (ticket, _))| (self.last_served.get(&ticket.job).copied().unwrap_or(0), *index))
+            .map(|(index, _)| index)?;
+        self.waiting.remove(index)
+    }
+}
+
+/// A download slot held by one file transfer; dropping it hands the slot on.
+#[derive(Debug)]
+pub struct Slot {
+    scheduler: Option<Arc<DownloadScheduler>>,
+}
+
+impl Slot {
+    /// Drops the slot without handing it on (the caller already did).
+    fn disarm(mut self) {
+        self.scheduler = None;
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn ticket(job: u64, priority: Priority) -> Ticket {
+        Ticket { job, priority }
+    }
+
+    /// Queues a waiter for `ticket` that reports its name on `order` once it has a slot,
+    /// then holds the slot until the test ends.
+    fn wait(scheduler: &Arc<DownloadScheduler>, ticket: Ticket, name: &'static str, order: &tokio::sync::mpsc::UnboundedSender<(&'static str, Slot)>) {
+        let (scheduler, order) = (Arc::clone(scheduler), order.clone());
+        tokio::spawn(async move {
+            let slot = scheduler.acquire(ticket).await;
+            let _ = order.send((name, slot));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_free_slots_go_to_urgent_classes_then_alternate_between_jobs() {
+        let scheduler = DownloadScheduler::new(1);
+        let held = scheduler.acquire(ticket(1, Priority::Bulk)).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for (job, name) in [(1, "bulk 1a"), (1, "bulk 1b"), (2, "bulk 2a"), (2, "bulk 2b")] {
+            wait(&scheduler, ticket(job, Priority::Bulk), name, &tx);
+            tokio::time::sleep(Duration::from_millis(10)).await; // Keeps the queue in this order
+        }
+        wait(&scheduler, ticket(3, Priority::Item), "item", &tx);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        wait(&scheduler, ticket(4, Priority::Interactive), "file", &tx);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(scheduler.waiting(Priority::Bulk), 4);
+
+        drop(held);
+        let mut order = Vec::new();
+        while let Some((name, slot)) = rx.recv().await {
+            order.push(name);
+            drop(slot);
+            if order.len() == 6 {
+                break;
+            }
+        }
+        assert_eq!(order, ["file", "item", "bulk 2a", "bulk 1a", "bulk 2b", "bulk 1b"]);
+        assert_eq!(Priority::of(&DownloadAction::Collection("etree".to_string())), Priority::Bulk);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiters_pass_the_slot_on() {
+        let scheduler = DownloadScheduler::new(1);
+        let held = scheduler.acquire(ticket(1, Priority::Bulk)).await;
+        let cancelled = {
+            let scheduler = Arc::clone(&scheduler);
+            tokio::spawn(async move { scheduler.acquire(ticket(2, Priority::Interactive)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancelled.abort();
+        let _ = cancelled.await;
+
+        drop(held);
+        let slot = tokio::time::timeout(Duration::from_secs(1), scheduler.acquire(ticket(1, Priority::Bulk))).await;
+        assert!(slot.is_ok(), "The slot came back after the cancelled waiter");
+    }
+}
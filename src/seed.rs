@@ -0,0 +1,389 @@
+use crate::archive_api;
+use crate::torrent::{sha1, sha256, Bencode};
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Size of the blocks hashed into a file's v2 merkle tree.
+const BLOCK_SIZE: u64 = 16 * 1024;
+/// Piece count the automatic piece length stays under, where the size allows.
+const TARGET_PIECES: u64 = 2000;
+/// Largest automatic piece length.
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// What goes into a generated torrent besides the files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedOptions {
+    /// Tracker announce URLs, each in its own tier.
+    pub trackers: Vec<String>,
+    /// BEP 19 web seed URLs (`url-list`).
+    pub web_seeds: Vec<String>,
+    /// Also list archive.org as a web seed (see [`archive_web_seed`]).
+    pub archive_web_seed: bool,
+    /// Piece length in bytes (a power of two of at least 16 KiB); chosen from the total
+    /// size when unset.
+    pub piece_length: Option<u64>,
+}
+
+/// A generated hybrid (v1 + v2) torrent.
+#[derive(Debug, Clone)]
+pub struct SeedTorrent {
+    /// The bencoded `.torrent`.
+    pub data: Vec<u8>,
+    pub info_hash_v1: [u8; 20],
+    pub info_hash_v2: [u8; 32],
+    pub file_count: usize,
+    pub total_size: u64,
+    pub piece_length: u64,
+}
+
+/// archive.org's download URL as a web seed. Clients fetch `<seed><name>/<path>`, which
+/// resolves for an item directory (named after its identifier) but not for a collection.
+pub fn archive_web_seed() -> String {
+    format!("{}/download/", archive_api::base_url())
+}
+
+/// Where the torrent of `dir` is written: `<dir>.torrent` next to the directory.
+pub fn torrent_path(dir: &Path) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".torrent");
+    dir.with_file_name(name)
+}
+
+/// A file of the directory, with its path segments below it.
+struct LocalFile {
+    segments: Vec<String>,
+    path: PathBuf,
+    length: u64,
+}
+
+/// Builds a hybrid BitTorrent v1/v2 torrent of every file below `dir`, named after the
+/// directory. Downloads still in progress (`.part`) and symlinks are left out. Files are
+/// padded to piece boundaries (BEP 47) so v1 and v2 clients share one swarm. Runs
+/// synchronously: call it on a blocking thread.
+pub fn create_torrent(dir: &Path, options: &SeedOptions) -> Result<SeedTorrent> {
+    let name = dir.file_name().and_then(|n| n.to_str()).context(format!("'{}' has no usable directory name", dir.display()))?.to_string();
+    let mut files = Vec::new();
+    collect_files(dir, &mut Vec::new(), &mut files)?;
+    if files.is_empty() {
+        bail!("'{}' contains no files to share", dir.display());
+    }
+    // File order must match the v2 file tree, which is sorted like bencode dictionaries
+    files.sort_by(|a, b| a.segments.cmp(&b.segments));
+    let total_size: u64 = files.iter().map(|f| f.length).sum();
+    let piece_length = match options.piece_length {
+        Some(length) if length < BLOCK_SIZE || !length.is_power_of_two() => bail!("Piece length must be a power of two of at least 16 KiB"),
+        Some(length) => length,
+        None => auto_piece_length(total_size),
+    };
+    info!("Hashing {} files ({} bytes) of '{}' in {} byte pieces", files.len(), total_size, dir.display(), piece_length);
+
+    let mut hasher = PieceHasher::new(piece_length);
+    let mut v1_files = Vec::new();
+    let mut file_tree = BTreeMap::new();
+    let mut piece_layers = BTreeMap::new();
+    for (index, file) in files.iter().enumerate() {
+        let (root, layer) = hasher.add_file(file)?;
+        let mut leaf = BTreeMap::from([(b"length".to_vec(), Bencode::Int(file.length as i64))]);
+        if let Some(root) = root {
+            leaf.insert(b"pieces root".to_vec(), Bencode::Bytes(root.to_vec()));
+            if file.length > piece_length {
+                piece_layers.insert(root.to_vec(), Bencode::Bytes(layer));
+            }
+        }
+        insert_file(&mut file_tree, &file.segments, Bencode::Dict(leaf));
+        v1_files.push(file_entry(&file.segments, file.length, false));
+        if index + 1 < files.len() {
+            let padding = hasher.pad_to_piece();
+            if padding > 0 {
+                v1_files.push(file_entry(&[".pad".to_string(), padding.to_string()], padding, true));
+            }
+        }
+    }
+
+    let info = Bencode::Dict(BTreeMap::from([
+        (b"file tree".to_vec(), Bencode::Dict(file_tree)),
+        (b"files".to_vec(), Bencode::List(v1_files)),
+        (b"meta version".to_vec(), Bencode::Int(2)),
+        (b"name".to_vec(), Bencode::Bytes(name.into_bytes())),
+        (b"piece length".to_vec(), Bencode::Int(piece_length as i64)),
+        (b"pieces".to_vec(), Bencode::Bytes(hasher.finish())),
+    ]));
+    let info_bytes = info.encode();
+    let mut root = BTreeMap::from([
+        (b"created by".to_vec(), Bencode::Bytes(format!("archiver {}", env!("CARGO_PKG_VERSION")).into_bytes())),
+        (b"creation date".to_vec(), Bencode::Int(chrono::Utc::now().timestamp())),
+        (b"info".to_vec(), info),
+        (b"piece layers".to_vec(), Bencode::Dict(piece_layers)),
+    ]);
+    if let Some(first) = options.trackers.first() {
+        root.insert(b"announce".to_vec(), Bencode::Bytes(first.as_bytes().to_vec()));
+        let tiers = options.trackers.iter().map(|url| Bencode::List(vec![Bencode::Bytes(url.as_bytes().to_vec())])).collect();
+        root.insert(b"announce-list".to_vec(), Bencode::List(tiers));
+    }
+    let mut web_seeds = options.web_seeds.clone();
+    if options.archive_web_seed {
+        web_seeds.push(archive_web_seed());
+    }
+    if !web_seeds.is_empty() {
+        root.insert(b"url-list".to_vec(), Bencode::List(web_seeds.into_iter().map(|url| Bencode::Bytes(url.into_bytes())).collect()));
+    }
+
+    Ok(SeedTorrent {
+        data: Bencode::Dict(root).encode(),
+        info_hash_v1: sha1(&info_bytes),
+        info_hash_v2: sha256(&info_bytes),
+        file_count: files.len(),
+        total_size,
+        piece_length,
+    })
+}
+
+/// Smallest power of two (at least 16 KiB, at most 16 MiB) giving at most
+/// [`TARGET_PIECES`] pieces.
+fn auto_piece_length(total_size: u64) -> u64 {
+    let mut length = BLOCK_SIZE;
+    while length < MAX_PIECE_LENGTH && total_size.div_ceil(length) > TARGET_PIECES {
+        length *= 2;
+    }
+    length
+}
+
+fn collect_files(dir: &Path, segments: &mut Vec<String>, files: &mut Vec<LocalFile>) -> Result<()> {
+    let entries = fs::read_dir(dir).context(format!("Failed to list '{}'", dir.display()))?;
+    for entry in entries {
+        let entry = entry.context(format!("Failed to list '{}'", dir.display()))?;
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            bail!("'{}' is not valid UTF-8, which torrents require", path.display());
+        };
+        let file_type = entry.file_type().context(format!("Failed to read '{}'", path.display()))?;
+        segments.push(name);
+        if file_type.is_dir() {
+            collect_files(&path, segments, files)?;
+        } else if file_type.is_file() && path.extension().is_none_or(|ext| ext != "part") {
+            let length = entry.metadata().context(format!("Failed to read '{}'", path.display()))?.len();
+            files.push(LocalFile { segments: segments.clone(), path, length });
+        }
+        segments.pop();
+    }
+    Ok(())
+}
+
+/// Entry of the v1 `files` list; padding files carry the `p` attribute.
+fn file_entry(segments: &[String], length: u64, padding: bool) -> Bencode {
+    let path = segments.iter().map(|s| Bencode::Bytes(s.as_bytes().to_vec())).collect();
+    let mut entry = BTreeMap::from([(b"length".to_vec(), Bencode::Int(length as i64)), (b"path".to_vec(), Bencode::List(path))]);
+    if padding {
+        entry.insert(b"attr".to_vec(), Bencode::Bytes(b"p".to_vec()));
+    }
+    Bencode::Dict(entry)
+}
+
+/// Adds a file to the v2 file tree: one nested dictionary per directory, with the file's
+/// properties under an empty key.
+fn insert_file(tree: &mut BTreeMap<Vec<u8>, Bencode>, segments: &[String], leaf: Bencode) {
+    let Some((first, rest)) = segments.split_first() else { return };
+    if rest.is_empty() {
+        tree.insert(first.as_bytes().to_vec(), Bencode::Dict(BTreeMap::from([(Vec::new(), leaf)])));
+    } else if let Bencode::Dict(children) = tree.entry(first.as_bytes().to_vec()).or_insert_with(|| Bencode::Dict(BTreeMap::new())) {
+        insert_file(children, rest, leaf);
+    }
+}
+
+/// Root of a merkle tree over `hashes`, padded to `width` leaves with `padding`.
+fn merkle_root(hashes: &[[u8; 32]], width: usize, padding: [u8; 32]) -> [u8; 32] {
+    let mut layer = hashes.to_vec();
+    layer.resize(width.max(1), padding);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| {
+                let mut joined = pair[0].to_vec();
+                joined.extend_from_slice(&pair[1]);
+                sha256(&joined)
+            })
+            .collect();
+    }
+    layer[0]
+}
+
+/// Hashes files in torrent order: SHA-1 pieces of the padded v1 byte stream, and each
+/// file's v2 merkle tree of 16 KiB blocks.
+struct PieceHasher {
+    piece_length: u64,
+    /// v1 piece being filled.
+    piece: Vec<u8>,
+    /// Concatenated v1 piece hashes.
+    pieces: Vec<u8>,
+}
+
+impl PieceHasher {
+    fn new(piece_length: u64) -> Self {
+        Self { piece_length, piece: Vec::with_capacity(piece_length as usize), pieces: Vec::new() }
+    }
+
+    fn push_v1(&mut self, data: &[u8]) {
+        self.piece.extend_from_slice(data);
+        if self.piece.len() as u64 == self.piece_length {
+            self.pieces.extend_from_slice(&sha1(&self.piece));
+            self.piece.clear();
+        }
+    }
+
+    /// Hashes `file`, returning its v2 pieces root (`None` for an empty file) and its
+    /// piece layer.
+    fn add_file(&mut self, file: &LocalFile) -> Result<(Option<[u8; 32]>, Vec<u8>)> {
+        let blocks_per_piece = (self.piece_length / BLOCK_SIZE) as usize;
+        let mut reader = File::open(&file.path).context(format!("Failed to open '{}'", file.path.display()))?;
+        let mut block = vec![0u8; BLOCK_SIZE as usize];
+        let (mut leaves, mut piece_hashes, mut read_total) = (Vec::new(), Vec::new(), 0u64);
+        loop {
+            let read = read_block(&mut reader, &mut block).context(format!("Failed to read '{}'", file.path.display()))?;
+            if read == 0 {
+                break;
+            }
+            read_total += read as u64;
+            self.push_v1(&block[..read]);
+            leaves.push(sha256(&block[..read]));
+            if leaves.len() == blocks_per_piece {
+                piece_hashes.push(merkle_root(&leaves, blocks_per_piece, [0; 32]));
+                leaves.clear();
+            }
+        }
+        if read_total != file.length {
+            bail!("'{}' changed while it was being hashed", file.path.display());
+        }
+        if piece_hashes.is_empty() {
+            // Smaller than a piece: the tree only spans the file's own blocks
+            let root = (!leaves.is_empty()).then(|| merkle_root(&leaves, leaves.len().next_power_of_two(), [0; 32]));
+            return Ok((root, Vec::new()));
+        }
+        if !leaves.is_empty() {
+            piece_hashes.push(merkle_root(&leaves, blocks_per_piece, [0; 32]));
+        }
+        let empty_piece = merkle_root(&[], blocks_per_piece, [0; 32]);
+        let root = merkle_root(&piece_hashes, piece_hashes.len().next_power_of_two(), empty_piece);
+        Ok((Some(root), piece_hashes.concat()))
+    }
+
+    /// Fills the current v1 piece with zeros, returning how many were added.
+    fn pad_to_piece(&mut self) -> u64 {
+        if self.piece.is_empty() {
+            return 0;
+        }
+        let padding = self.piece_length - self.piece.len() as u64;
+        self.push_v1(&vec![0; padding as usize]);
+        padding
+    }
+
+    /// Concatenated v1 piece hashes, including the last (short) piece.
+    fn finish(mut self) -> Vec<u8> {
+        if !self.piece.is_empty() {
+            self.pieces.extend_from_slice(&sha1(&self.piece));
+        }
+        self.pieces
+    }
+}
+
+/// Reads until `block` is full or the file ends, returning the bytes read.
+fn read_block(reader: &mut File, block: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent::Metainfo;
+
+    #[test]
+    fn test_auto_piece_length() {
+        assert_eq!(auto_piece_length(0), BLOCK_SIZE);
+        assert_eq!(auto_piece_length(2000 * BLOCK_SIZE), BLOCK_SIZE);
+        assert_eq!(auto_piece_length(2000 * BLOCK_SIZE + 1), 2 * BLOCK_SIZE);
+        assert_eq!(auto_piece_length(u64::MAX / 2), MAX_PIECE_LENGTH);
+    }
+
+    #[test]
+    fn test_hybrid_torrent_of_collection_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let collection = dir.path().join("etree");
+        fs::create_dir_all(collection.join("item_one/disc1")).unwrap();
+        let big: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(collection.join("item_one/disc1/track.flac"), &big).unwrap();
+        fs::write(collection.join("item_one/notes.txt"), b"liner notes").unwrap();
+        fs::write(collection.join("item_one/empty.txt"), b"").unwrap();
+        fs::write(collection.join("item_one/unfinished.flac.part"), b"partial").unwrap();
+
+        let options = SeedOptions {
+            trackers: vec!["http://tracker.example/announce".to_string(), "http://backup.example/announce".to_string()],
+            web_seeds: vec!["http://mirror.example/".to_string()],
+            archive_web_seed: false,
+            piece_length: Some(2 * BLOCK_SIZE),
+        };
+        let torrent = create_torrent(&collection, &options).unwrap();
+        assert_eq!((torrent.file_count, torrent.total_size), (3, 40_011), "The .part file is left out");
+        assert_eq!(torrent_path(&collection), dir.path().join("etree.torrent"));
+
+        // v1 view: files padded to piece boundaries, pieces hash the padded stream
+        let meta = Metainfo::parse(&torrent.data).unwrap();
+        assert_eq!(meta.name, "etree");
+        assert_eq!(meta.info_hash, torrent.info_hash_v1);
+        assert_eq!(meta.trackers.len(), 2);
+        let paths: Vec<&str> = meta.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["item_one/disc1/track.flac", ".pad/25536", "item_one/empty.txt", "item_one/notes.txt"]);
+        assert!(meta.files[1].padding);
+        let mut stream = big.clone();
+        stream.resize(2 * 32768, 0);
+        stream.extend_from_slice(b"liner notes");
+        let expected: Vec<[u8; 20]> = stream.chunks(32768).map(sha1).collect();
+        assert_eq!(meta.pieces, expected);
+
+        // v2 view: per-file merkle roots and the piece layer of the file spanning two pieces
+        let root = Bencode::decode(&torrent.data).unwrap();
+        let info = root.get("info").unwrap();
+        assert_eq!(sha256(&info.encode()), torrent.info_hash_v2);
+        assert_eq!(info.get("meta version").and_then(Bencode::as_int), Some(2));
+        let item = info.get("file tree").and_then(|tree| tree.get("item_one")).unwrap();
+        let leaf = |node: &Bencode| node.get("").unwrap().clone();
+        let notes = leaf(item.get("notes.txt").unwrap());
+        assert_eq!(notes.get("pieces root").and_then(Bencode::as_bytes), Some(&sha256(b"liner notes")[..]));
+        assert!(leaf(item.get("empty.txt").unwrap()).get("pieces root").is_none());
+
+        let blocks: Vec<[u8; 32]> = big.chunks(BLOCK_SIZE as usize).map(sha256).collect();
+        let join = |a: &[u8; 32], b: &[u8; 32]| sha256(&[&a[..], &b[..]].concat());
+        let pieces = [join(&blocks[0], &blocks[1]), join(&blocks[2], &[0; 32])];
+        let track = leaf(item.get("disc1").unwrap().get("track.flac").unwrap());
+        let track_root = join(&pieces[0], &pieces[1]);
+        assert_eq!(track.get("pieces root").and_then(Bencode::as_bytes), Some(&track_root[..]));
+        let Some(Bencode::Dict(layers)) = root.get("piece layers") else { panic!("No piece layers") };
+        assert_eq!(layers.len(), 1, "Only files longer than a piece have a layer");
+        assert_eq!(layers.get(&track_root[..]).and_then(Bencode::as_bytes), Some(&pieces.concat()[..]));
+        assert_eq!(root.get("url-list").and_then(Bencode::as_list).map(|seeds| seeds.len()), Some(1));
+    }
+
+    #[test]
+    fn test_rejects_bad_piece_length_and_empty_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(create_torrent(dir.path(), &SeedOptions::default()).is_err(), "Nothing to share");
+        fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        let options = SeedOptions { piece_length: Some(20_000), ..Default::default() };
+        assert!(create_torrent(dir.path(), &options).is_err());
+        let options = SeedOptions { archive_web_seed: true, ..Default::default() };
+        let torrent = create_torrent(dir.path(), &options).unwrap();
+        let seeds = Bencode::decode(&torrent.data).unwrap().get("url-list").and_then(|l| l.as_list().map(|l| l.to_vec())).unwrap();
+        assert_eq!(seeds[0].as_str(), Some(archive_web_seed().as_str()));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::settings::Settings;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Most connections one file is split over.
+pub const MAX_SEGMENTS: usize = 16;
+
+/// When a file is fetched as several byte ranges over parallel connections.
+///
+/// archive.org often caps the speed of a single connection, so large files are split into
+/// ranged requests that download at the same time and are stitched together once all are
+/// complete. Each range is saved to its own `.part` file (see [`segment_path`]), so an
+/// interrupted file resumes every range where it stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPolicy {
+    /// Connections per file; 1 downloads every file over a single connection.
+    pub segments: usize,
+    /// Files from this many bytes on are split.
+    pub threshold: u64,
+}
+
+impl Default for SegmentPolicy {
+    fn default() -> Self {
+        Self { segments: 1, threshold: u64::MAX }
+    }
+}
+
+impl SegmentPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self { segments: settings.segments_per_file.clamp(1, MAX_SEGMENTS), threshold: settings.segment_threshold_mb.saturating_mul(1024 * 1024) }
+    }
+
+    /// The byte ranges a file of `size` bytes is fetched in, or `None` to fetch it over a
+    /// single connection (segments off, file too small, or size unknown).
+    pub fn plan(&self, size: Option<u64>) -> Option<Vec<Range<u64>>> {
+        let size = size?;
+        if self.segments < 2 || size < self.threshold.max(self.segments as u64) {
+            return None;
+        }
+        let length = size.div_ceil(self.segments as u64);
+        Some((0..size).step_by(length as usize).map(|start| start..(start + length).min(size)).collect())
+    }
+}
+
+/// Where the range starting at `start` is saved: the file's `.part` itself for the first
+/// one (so a single-connection download can resume it), `<file>.part.<start>` for the rest.
+pub fn segment_path(part_path: &Path, start: u64) -> PathBuf {
+    if start == 0 {
+        return part_path.to_path_buf();
+    }
+    let mut name = part_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", start));
+    part_path.with_file_name(name)
+}
+
+/// The settings row: "Off" or "4 per file above 256 MB".
+pub fn describe(settings: &Settings) -> String {
+    match settings.segments_per_file {
+        0 | 1 => "Off".to_string(),
+        segments => format!("{} per file above {} MB", segments.min(MAX_SEGMENTS), settings.segment_threshold_mb),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_splits_large_files_into_covering_ranges() {
+        let policy = SegmentPolicy { segments: 4, threshold: 1000 };
+        assert_eq!(policy.plan(Some(999)), None, "Below the threshold");
+        assert_eq!(policy.plan(None), None, "Unknown size");
+        assert_eq!(SegmentPolicy { segments: 1, threshold: 0 }.plan(Some(10_000)), None);
+        assert_eq!(policy.plan(Some(1001)).unwrap(), [0..251, 251..502, 502..753, 753..1001]);
+        assert_eq!(SegmentPolicy { segments: 4, threshold: 0 }.plan(Some(2)), None, "Fewer bytes than segments");
+
+        let part = Path::new("/dl/item/a.flac.part");
+        assert_eq!(segment_path(part, 0), part);
+        assert_eq!(segment_path(part, 251), Path::new("/dl/item/a.flac.part.251"));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::app::DownloadAction;
+use crate::forecast;
+use crate::fs_util;
+use crate::presets::FilePreset;
+use crate::retry::RetryState;
+use crate::settings::DownloadMode;
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// File (in the cache directory) recording the downloads in flight.
+pub const SESSION_FILE: &str = "session.json";
+/// Minimum interval between progress-only journal writes.
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Everything needed to (re)start a download action.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DownloadJob {
+    pub action: DownloadAction,
+    /// Collection context used for item/file paths.
+    pub collection: Option<String>,
+    pub mode: DownloadMode,
+    pub base_dir: String,
+    /// Files selected within each item (missing in journals from older versions).
+    #[serde(default)]
+    pub preset: FilePreset,
+    /// Earlier failed attempts, when the job is a retry (see [`crate::retry`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryState>,
+}
+
+impl DownloadJob {
+    /// One-line description, e.g. `collection foo` or `file bar/track01.mp3`.
+    pub fn describe(&self) -> String {
+        match &self.action {
+            DownloadAction::Collection(id) => format!("collection {}", id),
+            DownloadAction::ItemAllFiles(id) => format!("item {}", id),
+            DownloadAction::File(id, file) => format!("file {}/{}", id, file.name),
+            DownloadAction::Items(ids) => format!("{} items", ids.len()),
+        }
+    }
+}
+
+/// A job recorded in the journal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub id: u64,
+    /// RFC 3339 start time.
+    pub started: String,
+    pub job: DownloadJob,
+}
+
+/// Last known progress counters of the jobs in flight.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProgressSnapshot {
+    pub items_done: usize,
+    pub items_total: Option<usize>,
+    pub files_done: usize,
+    pub files_total: Option<usize>,
+    pub bytes_done: u64,
+}
+
+impl ProgressSnapshot {
+    /// Files known to be left, if the total is known.
+    pub fn files_remaining(&self) -> Option<usize> {
+        self.files_total.map(|total| total.saturating_sub(self.files_done))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct JournalData {
+    next_id: u64,
+    entries: Vec<JournalEntry>,
+    progress: ProgressSnapshot,
+    /// Interrupted jobs the user put off deciding about; not in flight.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    held: Vec<JournalEntry>,
+}
+
+/// Persistent record of the download jobs in flight.
+///
+/// Jobs are added when they start and removed when they finish, so anything left in
+/// the file at startup was interrupted (quit mid-download, crash, power loss). Jobs held
+/// at the resume prompt stay in the file for the next startup to offer again. Since
+/// downloads skip files that are already complete, resuming is just starting the jobs
+/// again.
+#[derive(Debug, Default)]
+pub struct SessionJournal {
+    /// `None` keeps the journal in memory only (tests, mock mode).
+    path: Option<PathBuf>,
+    data: JournalData,
+    last_progress_save: Option<Instant>,
+}
+
+impl SessionJournal {
+    /// Loads the journal at `path`. A missing file is an empty journal; a corrupt one is
+    /// backed up and replaced.
+    pub fn load(path: PathBuf) -> Self {
+        let mut data: JournalData = match fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("Session journal {} is corrupt ({}); starting fresh", path.display(), e);
+                    if let Err(backup_err) = fs_util::backup_corrupt_file(&path) {
+                        error!("{:#}", backup_err);
+                    }
+                    JournalData::default()
+                }
+            },
+            Err(_) => JournalData::default(), // Missing (first run or clean shutdown)
+        };
+        // Jobs held last time were interrupted just the same
+        let held = std::mem::take(&mut data.held);
+        data.entries.splice(0..0, held);
+        if !data.entries.is_empty() {
+            info!("Found {} interrupted download job(s) in {}", data.entries.len(), path.display());
+        }
+        Self { path: Some(path), data, last_progress_save: None }
+    }
+
+    /// Jobs currently recorded (at startup: the interrupted ones).
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.data.entries
+    }
+
+    /// Interrupted jobs held at the resume prompt (see [`SessionJournal::hold_entries`]).
+    pub fn held(&self) -> &[JournalEntry] {
+        &self.data.held
+    }
+
+    /// Progress counters saved with the jobs.
+    pub fn progress(&self) -> &ProgressSnapshot {
+        &self.data.progress
+    }
+
+    /// Short description of what was in flight, e.g. for the resume prompt.
+    pub fn summary(&self) -> String {
+        let progress = &self.data.progress;
+        let mut parts = vec![format!("{} download(s) interrupted", self.data.entries.len())];
+        if let Some(total) = progress.items_total {
+            parts.push(format!("items {}/{} done", progress.items_done, total));
+        }
+        match progress.files_remaining() {
+            Some(remaining) => parts.push(format!("{} file(s) remaining", remaining)),
+            None => parts.push(format!("{} file(s) done", progress.files_done)),
+        }
+        parts.push(format!("{} downloaded", forecast::format_bytes(progress.bytes_done)));
+        parts.join(", ")
+    }
+
+    /// Records a job as started and returns its id.
+    pub fn record_start(&mut self, job: DownloadJob) -> u64 {
+        let id = self.data.next_id;
+        self.data.next_id += 1;
+        self.data.entries.push(JournalEntry { id, started: chrono::Local::now().to_rfc3339(), job });
+        self.save_logged();
+        id
+    }
+
+    /// Removes a finished job. Progress is reset once nothing is in flight.
+    pub fn record_finish(&mut self, id: u64) {
+        self.data.entries.retain(|entry| entry.id != id);
+        if self.data.entries.is_empty() {
+            self.data.progress = ProgressSnapshot::default();
+        }
+        self.save_logged();
+    }
+
+    /// Updates the progress counters, writing them at most every few seconds.
+    pub fn update_progress(&mut self, progress: ProgressSnapshot) {
+        if self.data.entries.is_empty() || progress == self.data.progress {
+            return;
+        }
+        self.data.progress = progress;
+        if self.last_progress_save.is_none_or(|t| t.elapsed() >= PROGRESS_SAVE_INTERVAL) {
+            self.save_logged();
+        }
+    }
+
+    /// Removes and returns all recorded jobs, held ones first (to restart them).
+    pub fn take_entries(&mut self) -> Vec<JournalEntry> {
+        let mut entries = std::mem::take(&mut self.data.held);
+        entries.append(&mut self.data.entries);
+        self.data.progress = ProgressSnapshot::default();
+        self.save_logged();
+        entries
+    }
+
+    /// Moves the recorded jobs out of the ones in flight, keeping them in the file until
+    /// they are taken or the next startup offers them again.
+    pub fn hold_entries(&mut self) {
+        let mut entries = std::mem::take(&mut self.data.entries);
+        self.data.held.append(&mut entries);
+        self.data.progress = ProgressSnapshot::default();
+        self.save_logged();
+    }
+
+    fn save_logged(&mut self) {
+        if let Err(e) = self.save() {
+            error!("{:#}", e);
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        self.last_progress_save = Some(Instant::now());
+        if self.data.entries.is_empty() && self.data.held.is_empty() {
+            // Nothing in flight or held: no file means a clean state
+            return match fs::remove_file(path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e).context(format!("Failed to remove session journal {}", path.display())),
+            };
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("Failed to create cache directory {}", dir.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.data).context("Failed to serialize session journal")?;
+        fs_util::atomic_write(path, json).context(format!("Failed to write session journal {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(item: &str) -> DownloadJob {
+        DownloadJob {
+            action: DownloadAction::ItemAllFiles(item.to_string()),
+            collection: Some("coll".to_string()),
+            mode: DownloadMode::Direct,
+            base_dir: "/downloads".to_string(),
+            preset: FilePreset::AllFiles,
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn test_unfinished_jobs_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_FILE);
+
+        let mut journal = SessionJournal::load(path.clone());
+        let first = journal.record_start(job("one"));
+        journal.record_start(job("two"));
+        journal.update_progress(ProgressSnapshot { files_done: 3, files_total: Some(10), ..Default::default() });
+        journal.record_finish(first);
+
+        let reloaded = SessionJournal::load(path.clone());
+        assert_eq!(reloaded.entries().len(), 1);
+        assert_eq!(reloaded.entries()[0].job, job("two"));
+        assert_eq!(reloaded.progress().files_remaining(), Some(7));
+        assert_eq!(reloaded.summary(), "1 download(s) interrupted, 7 file(s) remaining, 0 B downloaded");
+    }
+
+    #[test]
+    fn test_journal_file_removed_when_idle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_FILE);
+
+        let mut journal = SessionJournal::load(path.clone());
+        let id = journal.record_start(job("one"));
+        assert!(path.exists());
+        journal.record_finish(id);
+        assert!(!path.exists());
+
+        journal.record_start(job("two"));
+        assert_eq!(journal.take_entries().len(), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_held_jobs_are_offered_again_at_startup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_FILE);
+
+        let mut journal = SessionJournal::load(path.clone());
+        journal.record_start(job("one"));
+        journal.hold_entries();
+        assert!(journal.entries().is_empty());
+        let id = journal.record_start(job("two"));
+        journal.record_finish(id);
+        assert!(path.exists(), "Held jobs keep the file");
+
+        let mut reloaded = SessionJournal::load(path.clone());
+        assert_eq!(reloaded.entries().len(), 1);
+        assert_eq!(reloaded.entries()[0].job, job("one"));
+        reloaded.hold_entries();
+        assert_eq!(reloaded.take_entries().len(), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_corrupt_journal_is_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_FILE);
+        fs::write(&path, "{ not json").unwrap();
+
+        let journal = SessionJournal::load(path.clone());
+        assert!(journal.entries().is_empty());
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1, "Corrupt journal should be kept as a backup");
+    }
+}
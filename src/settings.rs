@@ -1,7 +1,23 @@
+use crate::auth::Credentials;
+use crate::dedupe::DedupeMode;
+use crate::dns::DnsConfig;
+use crate::fs_util;
+use crate::ipfs;
+use crate::keymap;
+use crate::presets::{FilePreset, SourcePolicy};
+use crate::replicate::ReplicationTarget;
+use crate::secrets::{self, SecretStore};
+use crate::sidecar::SidecarFormat;
+use crate::storage::S3Bucket;
+use crate::tagging::TagPolicy;
+use crate::theme::ThemeKind;
+use crate::thumbnail::CoverArt;
+use crate::transport::TransportConfig;
+use crate::torrent_backend::{self, TorrentBackendKind};
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::{fmt, fs, path::PathBuf}; // Add fmt
+use std::{collections::BTreeMap, fmt, fs, path::{Path, PathBuf}, sync::OnceLock}; // Add fmt
 
 const QUALIFIER: &str = "com";
 const ORGANIZATION: &str = "riffcc"; // Updated organization
@@ -14,6 +30,9 @@ pub enum DownloadMode {
     Direct,
     /// Download only the .torrent file.
     TorrentOnly,
+    /// Download the .torrent, then the item's files over BitTorrent with the built-in
+    /// client (HTTP for pieces the swarm doesn't provide).
+    TorrentEmbedded,
 }
 
 // Implement Display for showing the mode in the UI
@@ -22,6 +41,40 @@ impl fmt::Display for DownloadMode {
         match self {
             DownloadMode::Direct => write!(f, "Direct (All Files)"),
             DownloadMode::TorrentOnly => write!(f, "Torrent Only (.torrent)"),
+            DownloadMode::TorrentEmbedded => write!(f, "BitTorrent (built-in client)"),
+        }
+    }
+}
+
+/// Order of the favorite collections in the collections pane.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionOrder {
+    /// The order of `favorite_collections`, rearranged with the move keys.
+    #[default]
+    Manual,
+    Alphabetical,
+    /// Most recently opened first.
+    Recent,
+}
+
+impl fmt::Display for CollectionOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectionOrder::Manual => write!(f, "manual"),
+            CollectionOrder::Alphabetical => write!(f, "alphabetical"),
+            CollectionOrder::Recent => write!(f, "recently used"),
+        }
+    }
+}
+
+impl CollectionOrder {
+    /// Next order (manual -> alphabetical -> recently used -> manual).
+    pub fn cycle(self) -> Self {
+        match self {
+            CollectionOrder::Manual => CollectionOrder::Alphabetical,
+            CollectionOrder::Alphabetical => CollectionOrder::Recent,
+            CollectionOrder::Recent => CollectionOrder::Manual,
         }
     }
 }
@@ -29,7 +82,7 @@ impl fmt::Display for DownloadMode {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)] // Added PartialEq
 pub struct Settings {
     pub download_directory: Option<String>,
-    /// Download mode (Direct or TorrentOnly).
+    /// Download mode (Direct, TorrentOnly or TorrentEmbedded).
     #[serde(default = "default_download_mode")]
     pub download_mode: DownloadMode,
     /// Max concurrent file downloads *within* a single item/collection download task.
@@ -39,6 +92,176 @@ pub struct Settings {
     pub favorite_collections: Vec<String>,
     /// Max concurrent collection downloads (when downloading multiple collections).
     pub max_concurrent_collections: Option<usize>,
+    /// DNS nameservers (`ip` or `ip:port`) used instead of the system configuration.
+    #[serde(default)]
+    pub dns_nameservers: Vec<String>,
+    /// Resolve hostnames to IPv4 addresses only (for broken IPv6 setups).
+    #[serde(default)]
+    pub dns_ipv4_only: bool,
+    /// Proxy for all requests (`http://[user:pass@]host:port`); unset uses the `HTTP(S)_PROXY` environment.
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// PEM file of extra root certificates to trust (e.g. a corporate proxy's CA).
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
+    /// `User-Agent` sent with requests; unset sends none.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Browse from the local caches only, without network requests (see [`crate::offline`]).
+    #[serde(default)]
+    pub offline_mode: bool,
+    /// Hours viewed item details are shown from the cache before being fetched again; 0 = always fetch.
+    #[serde(default = "default_details_cache_hours")]
+    pub details_cache_hours: u64,
+    /// Color theme for the TUI (NO_COLOR in the environment overrides colors regardless).
+    #[serde(default)]
+    pub theme: ThemeKind,
+    /// Simplified single-pane layout with a textual event line (also enabled by `--screen-reader`).
+    #[serde(default)]
+    pub screen_reader: bool,
+    /// Escape file names that are invalid on NTFS (reserved names like `aux`, `:` etc.).
+    #[serde(default = "default_windows_safe_filenames")]
+    pub windows_safe_filenames: bool,
+    /// Truncate file names so full download paths stay within this many characters.
+    #[serde(default)]
+    pub max_path_length: Option<usize>,
+    /// Check GitHub for a newer release on startup (opt-in).
+    #[serde(default)]
+    pub check_for_updates: bool,
+    /// Write a human-readable README.txt / metadata.md into each downloaded item directory.
+    #[serde(default)]
+    pub metadata_sidecar: SidecarFormat,
+    /// Write an `<identifier>.m3u8` playlist of the tracks into each downloaded audio item.
+    #[serde(default)]
+    pub write_playlists: bool,
+    /// archive.org login cookies. When unset, the cookies saved by `ia configure` are used.
+    #[serde(default)]
+    pub archive_login: Option<Credentials>,
+    /// archive.org screen name whose favorites 'F' imports; set by logging in or the first import.
+    #[serde(default)]
+    pub archive_screenname: Option<String>,
+    /// Collection downloads larger than this (in GB) ask for confirmation first; 0 always asks.
+    #[serde(default = "default_confirm_collection_above_gb")]
+    pub confirm_collection_above_gb: u64,
+    /// Minimum delay in milliseconds between requests to the same datanode host (0 disables).
+    #[serde(default = "default_host_delay_ms")]
+    pub host_delay_ms: u64,
+    /// Identifiers never downloaded as part of a collection.
+    #[serde(default)]
+    pub excluded_identifiers: Vec<String>,
+    /// Regular expressions; collection items whose identifier matches are skipped.
+    #[serde(default)]
+    pub excluded_patterns: Vec<String>,
+    /// File selection preset pre-selected for item and collection downloads.
+    #[serde(default)]
+    pub default_file_preset: FilePreset,
+    /// Whether item downloads keep original uploads, archive.org's derivatives, or both.
+    #[serde(default)]
+    pub file_source_policy: SourcePolicy,
+    /// Always download subtitles (srt/vtt) of movie items, whatever the file preset.
+    #[serde(default)]
+    pub include_subtitles: bool,
+    /// Subtitle language tags to include (`en`, `eng`, `english`, ...); empty = all.
+    #[serde(default)]
+    pub subtitle_languages: Vec<String>,
+    /// Only download item files matching one of these entries (globs like `*.flac`,
+    /// formats like `Flac`, or extensions like `pdf`); empty = no filter.
+    #[serde(default)]
+    pub download_file_filter: Vec<String>,
+    /// Torrent client TorrentOnly downloads are handed off to.
+    #[serde(default)]
+    pub torrent_backend: TorrentBackendKind,
+    /// Transmission RPC endpoint, e.g. `http://nas:9091/transmission/rpc`.
+    #[serde(default = "default_transmission_url")]
+    pub transmission_url: String,
+    /// Transmission RPC username, if authentication is enabled on the daemon.
+    #[serde(default)]
+    pub transmission_username: Option<String>,
+    #[serde(default)]
+    pub transmission_password: Option<String>,
+    /// Global download speed cap in KB/s, shared by all concurrent downloads; 0 = unlimited.
+    #[serde(default)]
+    pub download_speed_limit_kb: u64,
+    /// Parallel ranged connections large files are split over (see [`crate::segments`]); 1 = off.
+    #[serde(default = "default_segments_per_file")]
+    pub segments_per_file: usize,
+    /// Files from this size (in MB) on are split when `segments_per_file` is above 1.
+    #[serde(default = "default_segment_threshold_mb")]
+    pub segment_threshold_mb: u64,
+    /// Attempts in all for a download that fails for a passing reason (see [`crate::retry`]); 1 = no retries.
+    #[serde(default = "default_retry_max_attempts")]
+    pub retry_max_attempts: u32,
+    /// Hours after the first failure from which a download is no longer retried; 0 = no limit.
+    #[serde(default = "default_retry_give_up_hours")]
+    pub retry_give_up_hours: u64,
+    /// Minutes between re-listing favorite collections to queue newly added items; 0 = off.
+    #[serde(default)]
+    pub sync_interval_minutes: u64,
+    /// Write a checksum manifest (size, MD5, SHA-256) into each downloaded item.
+    #[serde(default = "default_write_checksums")]
+    pub write_checksums: bool,
+    /// Library layout template (see [`crate::layout`]) by collection identifier, e.g.
+    /// `etree = "{creator}/{year} - {title}/{filename}"`.
+    #[serde(default)]
+    pub library_layouts: BTreeMap<String, String>,
+    /// Filter (see [`crate::fetch_filter`]) narrowing what is listed of a collection, by
+    /// collection identifier, e.g. `etree = "2020.. mediatype:audio"`.
+    #[serde(default)]
+    pub fetch_filters: BTreeMap<String, String>,
+    /// Group a favorite collection is listed under in the collections pane, by collection
+    /// identifier, e.g. `etree = "live music"`.
+    #[serde(default)]
+    pub collection_groups: BTreeMap<String, String>,
+    /// Name the collections pane shows instead of the identifier (or title), by collection
+    /// identifier, e.g. `enrmp270_litmus_-_perception_of_light = "Litmus - Perception of Light"`.
+    #[serde(default)]
+    pub collection_aliases: BTreeMap<String, String>,
+    /// Order of the collections pane; moving a collection switches it to manual.
+    #[serde(default)]
+    pub collection_order: CollectionOrder,
+    /// Link files whose content is already in the download directory instead of downloading them.
+    #[serde(default)]
+    pub dedupe_mode: DedupeMode,
+    /// Write artist/album/title/track/date tags from archive.org metadata into downloaded MP3 and FLAC files.
+    #[serde(default)]
+    pub tag_audio: bool,
+    /// What happens to tags the files already have when `tag_audio` is on.
+    #[serde(default)]
+    pub tag_policy: TagPolicy,
+    /// Look up downloaded audio items on MusicBrainz and record the matching release's MBIDs.
+    #[serde(default)]
+    pub musicbrainz_lookup: bool,
+    /// Add each completed item directory to the IPFS node at `ipfs_api_url`.
+    #[serde(default)]
+    pub ipfs_export: bool,
+    /// HTTP API of the IPFS node items are added to.
+    #[serde(default = "default_ipfs_api_url")]
+    pub ipfs_api_url: String,
+    /// Remote storage every completed item is pushed to (see [`crate::replicate`]).
+    #[serde(default)]
+    pub replication_targets: Vec<ReplicationTarget>,
+    /// Delete an item's local copy once every replication target holds a verified one.
+    #[serde(default)]
+    pub replicate_delete_local: bool,
+    /// Bucket downloaded files are streamed into instead of the download directory (see
+    /// [`crate::storage`]).
+    #[serde(default)]
+    pub download_bucket: Option<S3Bucket>,
+    /// URL the download directory is served at (e.g. `http://nas.local/archive`), for the
+    /// links of the library feeds (see [`crate::feed`]); relative links without it.
+    #[serde(default)]
+    pub feed_base_url: Option<String>,
+    /// How the item view shows cover art (see [`crate::thumbnail`]).
+    #[serde(default)]
+    pub cover_art: CoverArt,
+    /// Media player files are streamed in, with any arguments before the URL, e.g.
+    /// `vlc --play-and-exit`.
+    #[serde(default = "default_player_command")]
+    pub player_command: String,
+    /// Key bindings by action name (see [`crate::keymap`]), e.g. `download = ["d", "Enter"]`;
+    /// actions not listed keep their default keys.
+    #[serde(default, deserialize_with = "keymap::deserialize_keys")]
+    pub keys: BTreeMap<String, Vec<String>>,
 }
 
 // Implement Default manually to set defaults
@@ -50,66 +273,234 @@ impl Default for Settings {
             max_concurrent_downloads: Some(4), // Default to 4 concurrent file downloads
             favorite_collections: Vec::new(),  // Default to empty list
             max_concurrent_collections: Some(1), // Default to downloading 1 collection at a time
+            dns_nameservers: Vec::new(), // Use the system resolver configuration
+            dns_ipv4_only: false,
+            proxy: None,
+            ca_bundle: None,
+            user_agent: None,
+            offline_mode: false,
+            details_cache_hours: default_details_cache_hours(),
+            theme: ThemeKind::Default,
+            screen_reader: false,
+            windows_safe_filenames: default_windows_safe_filenames(),
+            max_path_length: None, // Long paths are supported via \\?\ on Windows
+            check_for_updates: false, // Never phone home unless asked to
+            metadata_sidecar: SidecarFormat::Off,
+            write_playlists: false,
+            archive_login: None,
+            archive_screenname: None,
+            confirm_collection_above_gb: default_confirm_collection_above_gb(),
+            host_delay_ms: default_host_delay_ms(),
+            excluded_identifiers: Vec::new(),
+            excluded_patterns: Vec::new(),
+            default_file_preset: FilePreset::AllFiles,
+            file_source_policy: SourcePolicy::All,
+            include_subtitles: false,
+            subtitle_languages: Vec::new(),
+            download_file_filter: Vec::new(),
+            torrent_backend: TorrentBackendKind::SaveFile,
+            transmission_url: default_transmission_url(),
+            transmission_username: None,
+            transmission_password: None,
+            download_speed_limit_kb: 0,
+            segments_per_file: default_segments_per_file(),
+            segment_threshold_mb: default_segment_threshold_mb(),
+            retry_max_attempts: default_retry_max_attempts(),
+            retry_give_up_hours: default_retry_give_up_hours(),
+            sync_interval_minutes: 0,
+            write_checksums: default_write_checksums(),
+            library_layouts: BTreeMap::new(),
+            fetch_filters: BTreeMap::new(),
+            collection_groups: BTreeMap::new(),
+            collection_aliases: BTreeMap::new(),
+            collection_order: CollectionOrder::Manual,
+            dedupe_mode: DedupeMode::Off,
+            tag_audio: false,
+            tag_policy: TagPolicy::FillMissing,
+            musicbrainz_lookup: false,
+            ipfs_export: false,
+            ipfs_api_url: default_ipfs_api_url(),
+            replication_targets: Vec::new(),
+            replicate_delete_local: false,
+            download_bucket: None,
+            feed_base_url: None,
+            cover_art: CoverArt::default(),
+            player_command: default_player_command(),
+            keys: BTreeMap::new(),
         }
     }
 }
 
+impl Settings {
+    /// Resolver options for the HTTP client.
+    pub fn dns_config(&self) -> DnsConfig {
+        DnsConfig { nameservers: self.dns_nameservers.clone(), ipv4_only: self.dns_ipv4_only }
+    }
+
+    /// Proxy, TLS and user agent options for the HTTP client.
+    pub fn transport_config(&self) -> TransportConfig {
+        TransportConfig { proxy: self.proxy.clone(), ca_bundle: self.ca_bundle.clone(), user_agent: self.user_agent.clone() }
+    }
+}
+
+fn default_transmission_url() -> String {
+    torrent_backend::DEFAULT_TRANSMISSION_URL.to_string()
+}
+
+fn default_write_checksums() -> bool {
+    true
+}
+
+fn default_player_command() -> String {
+    "mpv".to_string()
+}
+
+fn default_ipfs_api_url() -> String {
+    ipfs::DEFAULT_API_URL.to_string()
+}
+
 // Helper function for serde default
 fn default_download_mode() -> DownloadMode {
     DownloadMode::Direct // Default download mode
 }
 
+fn default_confirm_collection_above_gb() -> u64 {
+    10
+}
 
-/// Returns the path to the configuration file.
-fn get_config_path() -> Result<PathBuf> {
+fn default_segments_per_file() -> usize {
+    1
+}
+
+fn default_segment_threshold_mb() -> u64 {
+    256
+}
+
+fn default_retry_max_attempts() -> u32 {
+    5
+}
+
+fn default_details_cache_hours() -> u64 {
+    24
+}
+
+fn default_retry_give_up_hours() -> u64 {
+    24
+}
+
+fn default_host_delay_ms() -> u64 {
+    250 // Spreads out bursts of parallel file requests to one node
+}
+
+fn default_windows_safe_filenames() -> bool {
+    cfg!(windows) // Mirrors on other platforms keep archive.org's names unless asked
+}
+
+
+/// Returns the per-user configuration directory, creating it if needed.
+pub fn config_dir() -> Result<PathBuf> {
     let proj_dirs = ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION)
         .context("Could not find project directories")?;
     let config_dir = proj_dirs.config_dir();
     fs::create_dir_all(config_dir)?; // Ensure the config directory exists
-    Ok(config_dir.join("settings.toml"))
+    Ok(config_dir.to_path_buf())
+}
+
+/// Returns the path to the configuration file.
+fn get_config_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("settings.toml"))
+}
+
+/// Returns the per-user cache directory for state shared between app instances.
+pub fn cache_dir() -> Result<PathBuf> {
+    let proj_dirs = ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION)
+        .context("Could not find project directories")?;
+    Ok(proj_dirs.cache_dir().to_path_buf())
 }
 
-/// Loads settings from the default configuration file path.
-/// If the file doesn't exist, returns default settings.
+/// The keyring (or secrets file) of the config directory, shared so known secrets aren't
+/// looked up again on every save.
+pub fn secret_store() -> Result<&'static SecretStore> {
+    static STORE: OnceLock<SecretStore> = OnceLock::new();
+    let config_dir = config_dir()?;
+    Ok(STORE.get_or_init(|| SecretStore::open(&config_dir)))
+}
+
+/// Loads settings from the default configuration file path, with their credentials from
+/// the keyring. If the file doesn't exist, returns default settings.
 pub fn load_settings() -> Result<Settings> {
     let config_path = get_config_path()?;
-    load_settings_from_path(&config_path)
+    load_settings_with_secrets(&config_path, secret_store()?)
 }
 
-/// Saves the given settings to the default configuration file path.
+/// Saves the given settings to the default configuration file path, and their
+/// credentials to the keyring.
 pub fn save_settings(settings: &Settings) -> Result<()> {
     let config_path = get_config_path()?;
-    save_settings_to_path(settings, &config_path)
+    save_settings_with_secrets(settings, &config_path, secret_store()?)
+}
+
+/// Loads settings and fills in the credentials kept in `store`. Credentials still in the
+/// file (written by hand, or by an older version) are moved to `store` and the file is
+/// rewritten without them.
+fn load_settings_with_secrets(config_path: &Path, store: &SecretStore) -> Result<Settings> {
+    let mut settings = load_settings_from_path(config_path)?;
+    match secrets::migrate(&settings, store) {
+        Ok(true) => save_settings_with_secrets(&settings, config_path, store)?,
+        Ok(false) => {}
+        Err(e) => log::warn!("Leaving credentials in '{}': {:#}", config_path.display(), e),
+    }
+    secrets::fill_secrets(&mut settings, |key| store.get(key));
+    Ok(settings)
+}
+
+/// Saves the settings without their credentials, which go to `store`.
+fn save_settings_with_secrets(settings: &Settings, config_path: &Path, store: &SecretStore) -> Result<()> {
+    let mut stripped = settings.clone();
+    for (key, value) in secrets::take_secrets(&mut stripped) {
+        store.set(&key, value.as_deref())?;
+    }
+    save_settings_to_path(&stripped, config_path)
 }
 
 
 /// Loads settings from the specified configuration file path.
-/// If the file doesn't exist, returns default settings.
-fn load_settings_from_path(config_path: &PathBuf) -> Result<Settings> {
+/// If the file doesn't exist, returns default settings. If it exists but can't be parsed,
+/// it is backed up next to the original and replaced with defaults.
+fn load_settings_from_path(config_path: &Path) -> Result<Settings> {
     if !config_path.exists() {
         return Ok(Settings::default()); // Return default if no config file
     }
 
-    let settings = config::Config::builder()
+    let parsed = config::Config::builder()
         // Make the file source optional for the builder.
         // If the file exists (as expected in the test), it will be loaded.
         // If not, build() won't error, and try_deserialize will likely use defaults.
-        .add_source(config::File::from(config_path.clone()).required(false))
-        .build()?
-        .try_deserialize::<Settings>()?;
+        .add_source(config::File::from(config_path.to_path_buf()).required(false))
+        .build()
+        .and_then(|c| c.try_deserialize::<Settings>());
 
-    Ok(settings)
+    match parsed {
+        Ok(settings) => Ok(settings),
+        Err(e) => {
+            log::error!("Settings file '{}' is corrupt ({}); regenerating defaults", config_path.display(), e);
+            fs_util::backup_corrupt_file(config_path)?;
+            let settings = Settings::default();
+            save_settings_to_path(&settings, config_path)?;
+            Ok(settings)
+        }
+    }
 }
 
 /// Saves the given settings to the specified configuration file path.
 /// Ensures the parent directory exists.
-fn save_settings_to_path(settings: &Settings, config_path: &PathBuf) -> Result<()> {
+fn save_settings_to_path(settings: &Settings, config_path: &Path) -> Result<()> {
     // Ensure the parent directory exists before writing
     if let Some(parent_dir) = config_path.parent() {
         fs::create_dir_all(parent_dir)?;
     }
     let toml_string = toml::to_string_pretty(settings)?;
-    fs::write(config_path, toml_string)?;
+    fs_util::atomic_write(config_path, toml_string)?;
     Ok(())
 }
 
@@ -153,11 +544,15 @@ mod tests {
     fn test_save_and_load_settings() {
         let (_temp_dir, config_path) = setup_test_env(); // Keep temp_dir alive
 
-        let mut settings_to_save = Settings::default();
-        settings_to_save.download_directory = Some("/tmp/downloads".to_string());
-        settings_to_save.download_mode = DownloadMode::TorrentOnly; // Test non-default mode
-        settings_to_save.max_concurrent_downloads = Some(10);
-        settings_to_save.favorite_collections = vec!["test_coll".to_string()];
+        let settings_to_save = Settings {
+            download_directory: Some("/tmp/downloads".to_string()),
+            download_mode: DownloadMode::TorrentOnly, // Test non-default mode
+            max_concurrent_downloads: Some(10),
+            favorite_collections: vec!["test_coll".to_string()],
+            library_layouts: BTreeMap::from([("test_coll".to_string(), "{creator}/{title}/{filename}".to_string())]),
+            keys: BTreeMap::from([("quit".to_string(), vec!["Ctrl+q".to_string(), "Q".to_string()])]),
+            ..Settings::default()
+        };
 
         // Save to the specific path
         save_settings_to_path(&settings_to_save, &config_path).unwrap();
@@ -169,6 +564,37 @@ mod tests {
         assert_eq!(loaded_settings.download_mode, DownloadMode::TorrentOnly); // Verify loaded mode
         assert_eq!(loaded_settings.max_concurrent_downloads, Some(10));
         assert_eq!(loaded_settings.favorite_collections, vec!["test_coll".to_string()]);
+        assert_eq!(loaded_settings.library_layouts, settings_to_save.library_layouts);
+        assert_eq!(loaded_settings.keys, settings_to_save.keys);
+    }
+
+    #[test]
+    fn test_load_corrupt_settings_backs_up_and_regenerates() {
+        let (_temp_dir, config_path) = setup_test_env();
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "download_mode = [[[").unwrap();
+
+        let settings = load_settings_from_path(&config_path).unwrap();
+        assert_eq!(settings, Settings::default());
+        // Regenerated file parses, and the corrupt original is kept alongside it
+        assert_eq!(load_settings_from_path(&config_path).unwrap(), Settings::default());
+        let backups = fs::read_dir(config_path.parent().unwrap()).unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with("settings.toml.corrupt-"))
+            .count();
+        assert_eq!(backups, 1);
+    }
+
+    #[test]
+    fn test_load_settings_without_dns_fields_uses_defaults() {
+        let (_temp_dir, config_path) = setup_test_env();
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "download_mode = \"Direct\"\nmax_concurrent_downloads = 2\n").unwrap();
+
+        let settings = load_settings_from_path(&config_path).unwrap();
+        assert!(settings.dns_nameservers.is_empty());
+        assert!(!settings.dns_ipv4_only);
+        assert_eq!(settings.max_concurrent_downloads, Some(2));
     }
 
      #[test]
@@ -182,6 +608,27 @@ mod tests {
          assert_eq!(settings.max_concurrent_downloads, Some(4)); // Check another default
      }
 
+    #[test]
+    fn test_credentials_in_the_file_move_to_the_secret_store() {
+        let (_temp_dir, config_path) = setup_test_env();
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "transmission_username = \"admin\"\ntransmission_password = \"hunter2\"\n").unwrap();
+        let store = SecretStore::new(None, config_path.with_file_name(secrets::SECRETS_FILE));
+
+        let settings = load_settings_with_secrets(&config_path, &store).unwrap();
+        assert_eq!(settings.transmission_password.as_deref(), Some("hunter2"));
+        let written = fs::read_to_string(&config_path).unwrap();
+        assert!(written.contains("transmission_username") && !written.contains("hunter2"), "{}", written);
+
+        // A fresh store finds it again, and saving without it forgets it
+        let store = SecretStore::new(None, config_path.with_file_name(secrets::SECRETS_FILE));
+        let mut settings = load_settings_with_secrets(&config_path, &store).unwrap();
+        assert_eq!(settings.transmission_password.as_deref(), Some("hunter2"));
+        settings.transmission_password = None;
+        save_settings_with_secrets(&settings, &config_path, &store).unwrap();
+        assert_eq!(store.get("transmission_password"), None);
+    }
+
      #[test]
      fn test_save_settings_creates_directory() {
          let temp_dir = tempdir().unwrap();
@@ -203,6 +650,7 @@ mod tests {
              max_concurrent_downloads: Some(5),
              favorite_collections: vec!["coll1".to_string(), "coll2".to_string()],
              max_concurrent_collections: Some(2),
+             ..Settings::default()
          };
          // This call should create the directory and write the file to the specific path
          save_settings_to_path(&settings_to_save, &expected_config_path).unwrap();
@@ -0,0 +1,195 @@
+use crate::archive_api::{self, ItemDetails};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Optional human-readable description written into each downloaded item directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SidecarFormat {
+    /// Don't write a sidecar.
+    #[default]
+    Off,
+    /// Plain text `README.txt`.
+    Text,
+    /// Markdown `metadata.md`.
+    Markdown,
+}
+
+impl fmt::Display for SidecarFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidecarFormat::Off => write!(f, "Off"),
+            SidecarFormat::Text => write!(f, "README.txt"),
+            SidecarFormat::Markdown => write!(f, "metadata.md"),
+        }
+    }
+}
+
+impl SidecarFormat {
+    /// Next format in settings order (Off -> Text -> Markdown -> Off).
+    pub fn cycle(self) -> Self {
+        match self {
+            SidecarFormat::Off => SidecarFormat::Text,
+            SidecarFormat::Text => SidecarFormat::Markdown,
+            SidecarFormat::Markdown => SidecarFormat::Off,
+        }
+    }
+
+    /// File name of the sidecar, or `None` when disabled.
+    pub fn file_name(self) -> Option<&'static str> {
+        match self {
+            SidecarFormat::Off => None,
+            SidecarFormat::Text => Some("README.txt"),
+            SidecarFormat::Markdown => Some("metadata.md"),
+        }
+    }
+}
+
+/// Page on archive.org (or the configured base URL) describing the item.
+pub fn source_url(identifier: &str) -> String {
+    format!("{}/details/{}", archive_api::base_url(), identifier)
+}
+
+/// Renders the sidecar contents for `details`. Returns `None` for [`SidecarFormat::Off`].
+///
+/// `downloaded_on` is written verbatim (callers pass a local date like `2024-05-01`).
+pub fn render(format: SidecarFormat, details: &ItemDetails, downloaded_on: &str) -> Option<String> {
+    let title = details.title.as_deref().unwrap_or(&details.identifier);
+    let description = details.description.as_deref().map(plain_text).filter(|d| !d.is_empty());
+    let fields = [
+        ("Identifier", Some(details.identifier.clone())),
+        ("Creator", details.creator.clone()),
+        ("Date", details.date.clone()),
+        ("Media type", details.mediatype.clone()),
+        ("Collections", (!details.collections.is_empty()).then(|| details.collections.join(", "))),
+        ("Source", Some(source_url(&details.identifier))),
+        ("Downloaded", Some(downloaded_on.to_string())),
+    ];
+
+    let mut out = String::new();
+    match format {
+        SidecarFormat::Off => return None,
+        SidecarFormat::Text => {
+            out.push_str(title);
+            out.push('\n');
+            out.push_str(&"=".repeat(title.chars().count()));
+            out.push_str("\n\n");
+            for (label, value) in fields.iter() {
+                if let Some(value) = value {
+                    out.push_str(&format!("{}: {}\n", label, value));
+                }
+            }
+            if let Some(description) = description {
+                out.push_str("\nDescription\n-----------\n");
+                out.push_str(&description);
+                out.push('\n');
+            }
+        }
+        SidecarFormat::Markdown => {
+            out.push_str(&format!("# {}\n\n", title));
+            for (label, value) in fields.iter() {
+                if let Some(value) = value {
+                    out.push_str(&format!("- **{}:** {}\n", label, value));
+                }
+            }
+            if let Some(description) = description {
+                out.push_str("\n## Description\n\n");
+                out.push_str(&description);
+                out.push('\n');
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Reduces archive.org's HTML descriptions to plain text: line-breaking tags become
+/// newlines, other tags are dropped and the common entities are decoded.
+pub fn plain_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        match rest[start..].find('>') {
+            Some(end) => {
+                let tag = rest[start + 1..start + end].trim().trim_start_matches('/').to_ascii_lowercase();
+                let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+                if matches!(name, "br" | "p" | "div" | "li") {
+                    out.push('\n');
+                }
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                // Unclosed '<' is just text
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+
+    let decoded = out
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    // Collapse the blank-line runs left behind by nested block tags
+    let mut lines: Vec<&str> = Vec::new();
+    for line in decoded.lines().map(str::trim_end) {
+        if line.trim().is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(if line.trim().is_empty() { "" } else { line });
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details() -> ItemDetails {
+        ItemDetails {
+            identifier: "test_item".to_string(),
+            title: Some("A Test Item".to_string()),
+            creator: Some("Jane Doe".to_string()),
+            description: Some("First line<br/>Second &amp; last<p>Para</p>".to_string()),
+            date: Some("1999".to_string()),
+            uploader: None,
+            collections: vec!["opensource".to_string(), "community".to_string()],
+            mediatype: Some("texts".to_string()),
+            files: Vec::new(),
+            download_base_url: None,
+            mirror_base_urls: Vec::new(),
+            reviews: Vec::new(),
+            related_identifiers: Vec::new(),
+            access_restricted: false,
+        }
+    }
+
+    #[test]
+    fn test_render_markdown_contains_metadata() {
+        let md = render(SidecarFormat::Markdown, &details(), "2024-05-01").unwrap();
+        assert!(md.starts_with("# A Test Item\n"));
+        assert!(md.contains("- **Creator:** Jane Doe\n"));
+        assert!(md.contains("- **Collections:** opensource, community\n"));
+        assert!(md.contains("- **Downloaded:** 2024-05-01\n"));
+        assert!(md.contains(&format!("- **Source:** {}\n", source_url("test_item"))));
+        assert!(md.contains("## Description\n\nFirst line\nSecond & last\nPara\n"));
+    }
+
+    #[test]
+    fn test_render_text_skips_missing_fields() {
+        let mut d = details();
+        d.creator = None;
+        d.description = None;
+        let txt = render(SidecarFormat::Text, &d, "2024-05-01").unwrap();
+        assert!(txt.starts_with("A Test Item\n===========\n\n"));
+        assert!(!txt.contains("Creator"));
+        assert!(!txt.contains("Description"));
+        assert!(render(SidecarFormat::Off, &d, "2024-05-01").is_none());
+    }
+}
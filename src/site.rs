@@ -0,0 +1,264 @@
+use crate::feed::{self, LocalItem};
+use crate::forecast;
+use crate::fs_util;
+use crate::library::LibraryIndex;
+use crate::paths::PathPolicy;
+use crate::sidecar;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use log::warn;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory (below the download directory) holding the collection and item pages.
+/// archive.org identifiers start with a letter or digit, so it can't clash with a
+/// collection folder.
+pub const SITE_DIR: &str = "_site";
+/// Front page, written into the download directory.
+pub const INDEX_FILE: &str = "index.html";
+/// Marks the pages as generated, so an `index.html` of the user's is never overwritten.
+const GENERATOR: &str = "<meta name=\"generator\" content=\"archiver\">";
+/// Page name of the items downloaded outside a collection folder.
+const SINGLE_ITEMS: &str = "_items";
+const STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:auto;padding:1em;line-height:1.4}\
+table{border-collapse:collapse;width:100%}th,td{text-align:left;padding:.2em .6em;border-bottom:1px solid #ddd}\
+td.size{text-align:right;white-space:nowrap}dt{font-weight:bold}nav{margin-bottom:1em}";
+
+/// What [`write`] wrote.
+#[derive(Debug)]
+pub struct SiteSummary {
+    /// The front page, or `None` if an `index.html` not written by archiver is in the way.
+    pub index: Option<PathBuf>,
+    pub collections: usize,
+    pub items: usize,
+    /// Downloaded items left out because their metadata isn't cached.
+    pub missing_metadata: usize,
+}
+
+/// Writes a static site of the downloaded items into `base_dir`, for serving the mirror
+/// read-only with any web server: `index.html` lists the collection folders,
+/// `_site/collections/<folder>.html` their items and `_site/items/<identifier>.html` an
+/// item's metadata and files. Items come from [`feed::collect`]; links to the files are
+/// relative, so the site works from any URL. Pages of items no longer downloaded are
+/// removed.
+pub fn write(base_dir: &Path, library: &LibraryIndex, policy: &PathPolicy) -> Result<SiteSummary> {
+    let (items, missing_metadata) = feed::collect(base_dir, library, policy);
+    let mut groups: BTreeMap<&str, Vec<&LocalItem>> = BTreeMap::new();
+    for item in &items {
+        let folder = item.dir.split_once('/').map_or(SINGLE_ITEMS, |(top, _)| top);
+        groups.entry(folder).or_default().push(item);
+    }
+    let site_dir = base_dir.join(SITE_DIR);
+    let generated = Local::now().format("%Y-%m-%d %H:%M").to_string();
+
+    let mut written = HashSet::new();
+    for item in &items {
+        let path = site_dir.join("items").join(page_name(&item.details.identifier));
+        write_page(&path, &item_page(item))?;
+        written.insert(path);
+    }
+    for (folder, group) in groups.iter_mut() {
+        group.sort_by_cached_key(|item| (item.details.title.as_deref().unwrap_or("").to_lowercase(), item.details.identifier.clone()));
+        let path = site_dir.join("collections").join(page_name(folder));
+        write_page(&path, &collection_page(folder, group))?;
+        written.insert(path);
+    }
+    for subdir in ["items", "collections"] {
+        remove_stale_pages(&site_dir.join(subdir), &written);
+    }
+
+    let index_path = base_dir.join(INDEX_FILE);
+    let ours = fs::read_to_string(&index_path).map_or(true, |html| html.contains(GENERATOR));
+    let index = if ours {
+        write_page(&index_path, &index_page(&groups, &generated))?;
+        Some(index_path)
+    } else {
+        warn!("Not overwriting {}, which archiver didn't write", index_path.display());
+        None
+    };
+    Ok(SiteSummary { index, collections: groups.len(), items: items.len(), missing_metadata })
+}
+
+fn write_page(path: &Path, html: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("Failed to create site directory {}", dir.display()))?;
+    }
+    fs_util::atomic_write(path, html).context(format!("Failed to write page {}", path.display()))
+}
+
+/// Deletes the pages in `dir` that weren't just written.
+fn remove_stale_pages(dir: &Path, written: &HashSet<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_some_and(|ext| ext == "html") && !written.contains(&path) {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove stale page {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn page_name(name: &str) -> String {
+    format!("{}.html", name)
+}
+
+/// Link to a page in `_site/<kind>` from another page there.
+fn page_link(kind: &str, name: &str) -> String {
+    format!("../{}/{}", kind, feed::link(None, &page_name(name)))
+}
+
+fn collection_title(folder: &str) -> &str {
+    if folder == SINGLE_ITEMS { "Single items" } else { folder }
+}
+
+fn total_size(item: &LocalItem) -> u64 {
+    item.files.iter().filter_map(|(file, _)| file.size.as_deref()?.parse::<u64>().ok()).sum()
+}
+
+fn index_page(groups: &BTreeMap<&str, Vec<&LocalItem>>, generated: &str) -> String {
+    let mut body = String::from("<table>\n<tr><th>Collection</th><th>Items</th><th>Size</th></tr>\n");
+    for (folder, items) in groups {
+        let size: u64 = items.iter().map(|item| total_size(item)).sum();
+        let href = format!("{}/collections/{}", SITE_DIR, feed::link(None, &page_name(folder)));
+        let _ = writeln!(
+            body,
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td class=\"size\">{}</td></tr>",
+            feed::escape(&href),
+            feed::escape(collection_title(folder)),
+            items.len(),
+            forecast::format_bytes(size)
+        );
+    }
+    body.push_str("</table>\n");
+    let _ = writeln!(body, "<p>Generated by archiver on {}.</p>", generated);
+    page("Mirror", None, &body)
+}
+
+fn collection_page(folder: &str, items: &[&LocalItem]) -> String {
+    let mut body = String::from("<table>\n<tr><th>Title</th><th>Creator</th><th>Date</th><th>Files</th><th>Size</th></tr>\n");
+    for item in items {
+        let details = &item.details;
+        let _ = writeln!(
+            body,
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td class=\"size\">{}</td></tr>",
+            feed::escape(&page_link("items", &details.identifier)),
+            feed::escape(details.title.as_deref().unwrap_or(&details.identifier)),
+            feed::escape(details.creator.as_deref().unwrap_or("")),
+            feed::escape(details.date.as_deref().unwrap_or("")),
+            item.files.len(),
+            forecast::format_bytes(total_size(item))
+        );
+    }
+    body.push_str("</table>\n");
+    page(collection_title(folder), Some(("../../index.html", "Mirror")), &body)
+}
+
+fn item_page(item: &LocalItem) -> String {
+    let details = &item.details;
+    let folder = item.dir.split_once('/').map_or(SINGLE_ITEMS, |(top, _)| top);
+    let source = sidecar::source_url(&details.identifier);
+    let downloaded = DateTime::<Local>::from(item.downloaded).format("%Y-%m-%d").to_string();
+    let fields = [
+        ("Identifier", Some(feed::escape(&details.identifier))),
+        ("Creator", details.creator.as_deref().map(feed::escape)),
+        ("Date", details.date.as_deref().map(feed::escape)),
+        ("Media type", details.mediatype.as_deref().map(feed::escape)),
+        ("Collections", (!details.collections.is_empty()).then(|| feed::escape(&details.collections.join(", ")))),
+        ("Source", Some(format!("<a href=\"{0}\">{0}</a>", feed::escape(&source)))),
+        ("Downloaded", Some(downloaded)),
+    ];
+    let mut body = String::from("<dl>\n");
+    for (label, value) in fields {
+        if let Some(value) = value {
+            let _ = writeln!(body, "<dt>{}</dt><dd>{}</dd>", label, value);
+        }
+    }
+    body.push_str("</dl>\n");
+    if let Some(description) = feed::description(details) {
+        for paragraph in description.split("\n\n") {
+            let _ = writeln!(body, "<p>{}</p>", feed::escape(paragraph).replace('\n', "<br>"));
+        }
+    }
+    body.push_str("<h2>Files</h2>\n<table>\n<tr><th>Name</th><th>Format</th><th>Size</th></tr>\n");
+    for (file, path) in &item.files {
+        let size = file.size.as_deref().and_then(|size| size.parse().ok()).map_or(String::new(), forecast::format_bytes);
+        let _ = writeln!(
+            body,
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td class=\"size\">{}</td></tr>",
+            feed::escape(&feed::link(Some("../.."), path)),
+            feed::escape(&file.name),
+            feed::escape(file.format.as_deref().unwrap_or("")),
+            size
+        );
+    }
+    body.push_str("</table>\n");
+    let up = page_link("collections", folder);
+    page(details.title.as_deref().unwrap_or(&details.identifier), Some((&up, collection_title(folder))), &body)
+}
+
+/// Wraps `body` in a page titled `title`, linking back `up` (href, label) if given.
+fn page(title: &str, up: Option<(&str, &str)>, body: &str) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(out, "{}", GENERATOR);
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    let _ = writeln!(out, "<title>{}</title>\n<style>{}</style>\n</head>\n<body>", feed::escape(title), STYLE);
+    if let Some((href, label)) = up {
+        let _ = writeln!(out, "<nav><a href=\"{}\">&larr; {}</a></nav>", feed::escape(href), feed::escape(label));
+    }
+    let _ = writeln!(out, "<h1>{}</h1>", feed::escape(title));
+    out.push_str(body);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive_api::{FileDetails, ItemDetails};
+    use crate::library::ItemStatus;
+    use crate::offline;
+
+    #[test]
+    fn test_site_pages_link_collections_items_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        let details = ItemDetails {
+            identifier: "gd1977".to_string(),
+            title: Some("Live <1977>".to_string()),
+            description: Some("First set<br/>Second set".to_string()),
+            mediatype: Some("etree".to_string()),
+            files: vec![
+                FileDetails { name: "d1 t01.flac".to_string(), format: Some("Flac".to_string()), ..Default::default() },
+                FileDetails { name: "missing.flac".to_string(), ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        fs::create_dir_all(base.join("etree/gd1977")).unwrap();
+        fs::write(base.join("etree/gd1977/d1 t01.flac"), b"flac").unwrap();
+        offline::save_details(base.to_str().unwrap(), &details).unwrap();
+        let mut library = LibraryIndex::load(base);
+        library.record("gd1977", ItemStatus::Downloaded);
+        fs::create_dir_all(base.join("_site/items")).unwrap();
+        fs::write(base.join("_site/items/gone.html"), "old").unwrap();
+
+        let summary = write(base, &library, &PathPolicy::default()).unwrap();
+        assert_eq!((summary.collections, summary.items, summary.missing_metadata), (1, 1, 0));
+        let index = fs::read_to_string(summary.index.unwrap()).unwrap();
+        assert!(index.contains("<a href=\"_site/collections/etree.html\">etree</a>"), "{}", index);
+        let collection = fs::read_to_string(base.join("_site/collections/etree.html")).unwrap();
+        assert!(collection.contains("<a href=\"../items/gd1977.html\">Live &lt;1977&gt;</a>"), "{}", collection);
+        let item = fs::read_to_string(base.join("_site/items/gd1977.html")).unwrap();
+        assert!(item.contains("<a href=\"../../etree/gd1977/d1%20t01.flac\">d1 t01.flac</a>"), "{}", item);
+        assert!(item.contains("<p>First set<br>Second set</p>"), "{}", item);
+        assert!(!item.contains("missing.flac"), "Only files on disk are listed");
+        assert!(!base.join("_site/items/gone.html").exists(), "Stale pages are removed");
+
+        fs::write(base.join(INDEX_FILE), "<html>my own page</html>").unwrap();
+        assert!(write(base, &library, &PathPolicy::default()).unwrap().index.is_none());
+        assert_eq!(fs::read_to_string(base.join(INDEX_FILE)).unwrap(), "<html>my own page</html>");
+    }
+}
@@ -0,0 +1,433 @@
+use crate::settings::Settings;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use reqwest::{header, Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt, path::Path, sync::Arc};
+
+/// Region signed for when a bucket doesn't name one.
+pub const DEFAULT_REGION: &str = "us-east-1";
+/// Smallest part of a multipart upload. S3 wants at least 5 MiB for every part but the
+/// last; larger parts mean fewer requests but more memory per transfer.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+/// Most parts S3 accepts for one object.
+const MAX_PARTS: u64 = 10_000;
+
+/// Where downloaded files are written.
+#[derive(Debug, Clone, Default)]
+pub enum Storage {
+    /// Below the download directory.
+    #[default]
+    LocalFs,
+    /// Streamed into an S3-compatible bucket, each file under its path below the download
+    /// directory. Caches, claims and the download history stay in the download directory,
+    /// and the stages working on local files (sidecars, tags, playlists, checksums, IPFS,
+    /// replication) are skipped.
+    S3(Arc<S3Bucket>),
+}
+
+impl Storage {
+    /// The bucket from `[download_bucket]` in settings.toml, or the local disk.
+    pub fn from_settings(settings: &Settings) -> Self {
+        match &settings.download_bucket {
+            Some(bucket) => Storage::S3(Arc::new(bucket.clone())),
+            None => Storage::LocalFs,
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, Storage::LocalFs)
+    }
+}
+
+/// An S3-compatible bucket, addressed path style.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct S3Bucket {
+    /// Endpoint URL with the bucket and an optional prefix, e.g.
+    /// `https://s3.eu-central-1.amazonaws.com/my-bucket/archive`.
+    pub url: String,
+    /// Region signed for (default `us-east-1`).
+    #[serde(default)]
+    pub region: Option<String>,
+    pub access_key: String,
+    /// Kept in the keyring rather than `settings.toml` (see [`crate::secrets`]).
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub secret_key: String,
+}
+
+impl fmt::Debug for S3Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Bucket")
+            .field("url", &self.url)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .field("secret_key", &"***")
+            .finish()
+    }
+}
+
+impl S3Bucket {
+    /// Size of the object `key`, `None` if the bucket doesn't have it.
+    pub async fn size(&self, client: &Client, key: &str) -> Result<Option<u64>> {
+        let response = self.request(client, Method::HEAD, key, &[])?.send().await.context(format!("Failed to check '{}'", key))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            bail!("Checking '{}' in the bucket failed with {}", key, response.status());
+        }
+        Ok(response.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse().ok()))
+    }
+
+    /// Stores `data` as the object `key` in a single request.
+    pub async fn put(&self, client: &Client, key: &str, data: Vec<u8>) -> Result<()> {
+        let request = self.request(client, Method::PUT, key, &[])?.header(header::CONTENT_LENGTH, data.len()).body(data);
+        let response = request.send().await.context(format!("Failed to upload '{}'", key))?;
+        checked(response, key).await.map(|_| ())
+    }
+
+    async fn create_upload(&self, client: &Client, key: &str) -> Result<String> {
+        let response = self.request(client, Method::POST, key, &[("uploads", "")])?.send().await.context(format!("Failed to start uploading '{}'", key))?;
+        let body = checked(response, key).await?.text().await.context(format!("Failed to read the upload of '{}'", key))?;
+        xml_value(&body, "UploadId").map(str::to_string).ok_or_else(|| anyhow!("The bucket started no upload for '{}'", key))
+    }
+
+    /// Uploads part `number` (from 1), returning its ETag.
+    async fn upload_part(&self, client: &Client, key: &str, upload_id: &str, number: usize, data: Vec<u8>) -> Result<String> {
+        let number = number.to_string();
+        let request = self
+            .request(client, Method::PUT, key, &[("partNumber", &number), ("uploadId", upload_id)])?
+            .header(header::CONTENT_LENGTH, data.len())
+            .body(data);
+        let response = request.send().await.context(format!("Failed to upload part {} of '{}'", number, key))?;
+        let response = checked(response, key).await?;
+        let etag = response.headers().get(header::ETAG).and_then(|v| v.to_str().ok());
+        etag.map(str::to_string).ok_or_else(|| anyhow!("The bucket returned no ETag for part {} of '{}'", number, key))
+    }
+
+    async fn complete_upload(&self, client: &Client, key: &str, upload_id: &str, etags: &[String]) -> Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in etags.iter().enumerate() {
+            body.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", index + 1, etag));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let request = self.request(client, Method::POST, key, &[("uploadId", upload_id)])?.body(body);
+        let response = request.send().await.context(format!("Failed to finish uploading '{}'", key))?;
+        // Completing can fail after the 200 is sent; the body tells
+        let body = checked(response, key).await?.text().await.unwrap_or_default();
+        if let Some(message) = xml_value(&body, "Message").filter(|_| body.contains("<Error>")) {
+            bail!("Finishing the upload of '{}' failed: {}", key, message);
+        }
+        Ok(())
+    }
+
+    async fn abort_upload(&self, client: &Client, key: &str, upload_id: &str) -> Result<()> {
+        let response = self.request(client, Method::DELETE, key, &[("uploadId", upload_id)])?.send().await.context(format!("Failed to abort uploading '{}'", key))?;
+        checked(response, key).await.map(|_| ())
+    }
+
+    /// A signed request for the object `key`, with the `query` parameters.
+    fn request(&self, client: &Client, method: Method, key: &str, query: &[(&str, &str)]) -> Result<RequestBuilder> {
+        let mut url = object_url(&self.url, key);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, canonical_query(query));
+        }
+        signed_request(client, method, &url, &self.access_key, &self.secret_key, self.region.as_deref().unwrap_or(DEFAULT_REGION))
+    }
+}
+
+/// Streams one file into the bucket: kept in memory up to the part size, then sent as a
+/// multipart upload, so large files never need the whole file in memory (or on disk).
+/// Files smaller than one part are sent in a single request.
+///
+/// Data received so far survives failed requests, so a transfer can continue where
+/// [`received`](Self::received) says with a Range request.
+pub struct ObjectUpload {
+    key: String,
+    part_size: usize,
+    upload_id: Option<String>,
+    /// ETags of the uploaded parts, in order.
+    etags: Vec<String>,
+    buffer: Vec<u8>,
+    uploaded: u64,
+}
+
+impl ObjectUpload {
+    /// An upload of the object `key`, with parts sized so `expected_size` bytes fit.
+    pub fn new(key: String, expected_size: Option<u64>) -> Self {
+        Self { key, part_size: part_size(expected_size), upload_id: None, etags: Vec::new(), buffer: Vec::new(), uploaded: 0 }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Bytes received so far, uploaded or not.
+    pub fn received(&self) -> u64 {
+        self.uploaded + self.buffer.len() as u64
+    }
+
+    /// Appends `chunk`, uploading a part once enough data is buffered. If that fails the
+    /// data is kept, and the part is tried again with the next chunk.
+    pub async fn write(&mut self, client: &Client, bucket: &S3Bucket, chunk: &[u8]) -> Result<()> {
+        self.buffer.extend_from_slice(chunk);
+        if self.buffer.len() >= self.part_size {
+            self.upload_part(client, bucket).await?;
+        }
+        Ok(())
+    }
+
+    /// Uploads what is left and completes the object, returning its size.
+    pub async fn finish(mut self, client: &Client, bucket: &S3Bucket) -> Result<u64> {
+        let size = self.received();
+        if self.upload_id.is_none() {
+            bucket.put(client, &self.key, std::mem::take(&mut self.buffer)).await?;
+            return Ok(size);
+        }
+        if !self.buffer.is_empty() {
+            self.upload_part(client, bucket).await?;
+        }
+        let upload_id = self.upload_id.as_deref().expect("upload started");
+        if let Err(e) = bucket.complete_upload(client, &self.key, upload_id, &self.etags).await {
+            self.abort(client, bucket).await;
+            return Err(e);
+        }
+        Ok(size)
+    }
+
+    /// Drops everything received, aborting the multipart upload so the bucket frees its
+    /// parts. Failures are only logged: the bucket's lifecycle rules clean up after them.
+    pub async fn abort(&mut self, client: &Client, bucket: &S3Bucket) {
+        if let Some(upload_id) = self.upload_id.take() {
+            if let Err(e) = bucket.abort_upload(client, &self.key, &upload_id).await {
+                warn!("Failed to abort the upload of '{}': {:#}", self.key, e);
+            }
+        }
+        self.etags.clear();
+        self.buffer.clear();
+        self.uploaded = 0;
+    }
+
+    async fn upload_part(&mut self, client: &Client, bucket: &S3Bucket) -> Result<()> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let upload_id = bucket.create_upload(client, &self.key).await?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+        let number = self.etags.len() + 1;
+        debug!("Uploading part {} of '{}' ({} bytes)", number, self.key, self.buffer.len());
+        let etag = bucket.upload_part(client, &self.key, &upload_id, number, self.buffer.clone()).await?;
+        self.etags.push(etag);
+        self.uploaded += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Object key of `path`: its `/`-separated path below `base_dir`.
+pub fn object_key(base_dir: &Path, path: &Path) -> Result<String> {
+    let relative = path.strip_prefix(base_dir).context(format!("'{}' is outside the download directory", path.display()))?;
+    let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    if parts.is_empty() {
+        bail!("'{}' names no file", path.display());
+    }
+    Ok(parts.join("/"))
+}
+
+/// URL of `key` (a `/`-separated path) below `base_url`.
+pub fn object_url(base_url: &str, key: &str) -> String {
+    let encoded = key.split('/').map(percent_encode).collect::<Vec<_>>().join("/");
+    format!("{}/{}", base_url.trim_end_matches('/'), encoded)
+}
+
+/// URI-encodes `text` the way Signature Version 4 expects: everything but unreserved
+/// characters.
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// A request for `url` signed with AWS Signature Version 4 and an unsigned payload. The
+/// path and query of `url` must already be encoded with [`percent_encode`], the query
+/// sorted (see [`canonical_query`]).
+pub fn signed_request(client: &Client, method: Method, url: &str, access: &str, secret: &str, region: &str) -> Result<RequestBuilder> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid S3 URL '{}': {}", url, e))?;
+    if parsed.host_str().is_none() {
+        bail!("Invalid S3 URL '{}'", url);
+    }
+    let (authorization, amz_date) = sigv4(method.as_str(), &parsed, access, secret, region, Utc::now());
+    Ok(client
+        .request(method, parsed)
+        .header(header::AUTHORIZATION, authorization)
+        .header("x-amz-date", amz_date)
+        .header("x-amz-content-sha256", "UNSIGNED-PAYLOAD"))
+}
+
+/// Query string with the parameters encoded and sorted, as Signature Version 4 signs it.
+fn canonical_query(params: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<String> = params.iter().map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value))).collect();
+    pairs.sort();
+    pairs.join("&")
+}
+
+/// Part size for a file of `expected_size` bytes: [`PART_SIZE`], or more if the file
+/// wouldn't fit in [`MAX_PARTS`] of them.
+fn part_size(expected_size: Option<u64>) -> usize {
+    let needed = expected_size.map_or(0, |size| size.div_ceil(MAX_PARTS)) as usize;
+    needed.max(PART_SIZE)
+}
+
+/// The response, or its status and error message as an error.
+async fn checked(response: Response, key: &str) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = xml_value(&body, "Message").unwrap_or(body.trim());
+    bail!("Request for '{}' failed with {}: {}", key, status, message.chars().take(200).collect::<String>())
+}
+
+/// Text of the first `<tag>` element of an S3 XML response.
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
+
+/// `Authorization` and `x-amz-date` headers signing a request with an unsigned payload
+/// (AWS Signature Version 4). The path and query of `url` must already be URI-encoded.
+fn sigv4(method: &str, url: &reqwest::Url, access: &str, secret: &str, region: &str, now: DateTime<Utc>) -> (String, String) {
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical = format!(
+        "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:UNSIGNED-PAYLOAD\nx-amz-date:{}\n\n{}\nUNSIGNED-PAYLOAD",
+        method,
+        url.path(),
+        url.query().unwrap_or(""),
+        host,
+        amz_date,
+        signed_headers
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical.as_bytes())));
+    let key = signing_key(secret, &date, region, "s3");
+    let signature = hex(&hmac_sha256(&key, to_sign.as_bytes()));
+    (format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", access, scope, signed_headers, signature), amz_date)
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    let key = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// HMAC-SHA256 (RFC 2104).
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_signing_matches_reference_vectors() {
+        // RFC 4231 test case 2
+        assert_eq!(hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        // AWS's signing key example
+        assert_eq!(
+            hex(&signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam")),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let url = reqwest::Url::parse("https://s3.example.com/bucket/etree/gd1977/a%20b.flac?partNumber=1&uploadId=x").unwrap();
+        let (authorization, amz_date) = sigv4("PUT", &url, "AKID", "secret", "eu-central-1", now);
+        assert_eq!(amz_date, "20240501T120000Z");
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKID/20240501/eu-central-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="));
+        assert_eq!(canonical_query(&[("uploadId", "a/b c"), ("partNumber", "2")]), "partNumber=2&uploadId=a%2Fb%20c");
+    }
+
+    #[test]
+    fn test_files_map_onto_objects() {
+        let base = Path::new("/data/archive");
+        assert_eq!(object_key(base, &base.join("etree/gd1977/d1 t01.flac")).unwrap(), "etree/gd1977/d1 t01.flac");
+        assert!(object_key(base, Path::new("/elsewhere/x.flac")).is_err());
+        assert_eq!(object_url("https://s3.example.com/b/archive/", "etree/d1 t01.flac"), "https://s3.example.com/b/archive/etree/d1%20t01.flac");
+
+        assert_eq!(part_size(None), PART_SIZE);
+        assert_eq!(part_size(Some(1_000)), PART_SIZE);
+        let huge = 200 * 1024 * 1024 * 1024;
+        assert!(part_size(Some(huge)) as u64 * MAX_PARTS >= huge);
+
+        assert_eq!(xml_value("<InitiateMultipartUploadResult><UploadId>abc-1</UploadId></InitiateMultipartUploadResult>", "UploadId"), Some("abc-1"));
+        assert_eq!(xml_value("<Error><Code>NoSuchUpload</Code></Error>", "UploadId"), None);
+
+        let settings: Settings = toml::from_str("[download_bucket]\nurl = \"https://s3.example.com/b\"\naccess_key = \"AKID\"\nsecret_key = \"hunter2\"\n").unwrap();
+        let storage = Storage::from_settings(&settings);
+        assert!(!storage.is_local());
+        assert!(!format!("{:?}", storage).contains("hunter2"));
+        assert!(Storage::from_settings(&Settings::default()).is_local());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_large_files_go_up_in_parts() {
+        use crate::mock_server;
+        let bucket = S3Bucket {
+            url: format!("{}/bucket", mock_server::shared_base_url()),
+            region: Some("eu-central-1".to_string()),
+            access_key: mock_server::MOCK_S3_ACCESS.to_string(),
+            secret_key: mock_server::MOCK_S3_SECRET.to_string(),
+        };
+        let client = Client::new();
+        let data: Vec<u8> = (0..PART_SIZE + PART_SIZE / 2).map(|i| (i % 251) as u8).collect();
+        let mut upload = ObjectUpload::new("parts/big file.bin".to_string(), Some(data.len() as u64));
+        for chunk in data.chunks(64 * 1024) {
+            upload.write(&client, &bucket, chunk).await.unwrap();
+        }
+        assert_eq!(upload.etags.len(), 1, "A full part went up while the rest streamed in");
+        assert_eq!(upload.finish(&client, &bucket).await.unwrap(), data.len() as u64);
+        assert_eq!(mock_server::bucket_object("parts/big file.bin"), Some(data));
+        assert_eq!(bucket.size(&client, "parts/big file.bin").await.unwrap(), Some(PART_SIZE as u64 + PART_SIZE as u64 / 2));
+
+        let mut aborted = ObjectUpload::new("parts/aborted.bin".to_string(), None);
+        aborted.write(&client, &bucket, &vec![1; PART_SIZE]).await.unwrap();
+        aborted.abort(&client, &bucket).await;
+        assert_eq!(aborted.received(), 0);
+        assert_eq!(mock_server::bucket_uploads_pending(), 0);
+        assert_eq!(bucket.size(&client, "parts/aborted.bin").await.unwrap(), None);
+    }
+}
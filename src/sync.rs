@@ -0,0 +1,129 @@
+use crate::archive_api::ArchiveDoc;
+use std::{collections::HashSet, time::{Duration, Instant}};
+
+/// Sync intervals offered in the settings, in minutes (0 = sync off).
+const INTERVALS_MINUTES: [u64; 8] = [0, 15, 30, 60, 180, 360, 720, 1440];
+
+/// Keeps favorite collections mirrored: every `sync_interval_minutes` their listing is
+/// fetched again, compared with the item cache, and new items are queued for download.
+///
+/// The schedule only decides *when* a sync round starts and tracks the collections
+/// still being listed; fetching and queueing happen in the main loop and [`crate::app::App`].
+#[derive(Debug, Default)]
+pub struct SyncSchedule {
+    last_started: Option<Instant>,
+    /// Collections of the current round whose listing hasn't come back yet.
+    pending: usize,
+}
+
+impl SyncSchedule {
+    /// Starts a round over `collections` favorites if syncing is on, no round is running
+    /// and the interval has passed (the first round starts right away). Returns whether
+    /// a round was started.
+    pub fn start_if_due(&mut self, interval_minutes: u64, collections: usize, now: Instant) -> bool {
+        if interval_minutes == 0 || collections == 0 || self.pending > 0 {
+            return false;
+        }
+        let interval = Duration::from_secs(interval_minutes * 60);
+        if self.last_started.is_some_and(|last| now.duration_since(last) < interval) {
+            return false;
+        }
+        self.last_started = Some(now);
+        self.pending = collections;
+        true
+    }
+
+    /// Starts a round over `collections` favorites right away, whatever the interval,
+    /// unless one is running. The next scheduled round counts from now.
+    pub fn start_now(&mut self, collections: usize, now: Instant) -> bool {
+        if collections == 0 || self.pending > 0 {
+            return false;
+        }
+        self.last_started = Some(now);
+        self.pending = collections;
+        true
+    }
+
+    /// Records that one collection of the current round has been listed (or failed).
+    pub fn finish_one(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.pending > 0
+    }
+}
+
+/// Identifiers in `fresh` that aren't in `cached`, in listing order.
+pub fn new_identifiers(cached: &[ArchiveDoc], fresh: &[ArchiveDoc]) -> Vec<String> {
+    let known: HashSet<&str> = cached.iter().map(|doc| doc.identifier.as_str()).collect();
+    fresh.iter().filter(|doc| !known.contains(doc.identifier.as_str())).map(|doc| doc.identifier.clone()).collect()
+}
+
+/// Next interval when adjusting the setting with Left/Right (stops at both ends).
+pub fn step_interval(minutes: u64, forward: bool) -> u64 {
+    if forward {
+        INTERVALS_MINUTES.iter().copied().find(|&m| m > minutes).unwrap_or(minutes)
+    } else {
+        INTERVALS_MINUTES.iter().copied().rev().find(|&m| m < minutes).unwrap_or(0)
+    }
+}
+
+/// Human-readable interval, e.g. "Off", "Every 30 min" or "Every 6 h".
+pub fn format_interval(minutes: u64) -> String {
+    match minutes {
+        0 => "Off".to_string(),
+        m if m % 60 == 0 => format!("Every {} h", m / 60),
+        m => format!("Every {} min", m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str) -> ArchiveDoc {
+        ArchiveDoc { identifier: id.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_new_identifiers_and_schedule() {
+        let cached = [doc("a"), doc("b")];
+        let fresh = [doc("c"), doc("a"), doc("b"), doc("d")];
+        assert_eq!(new_identifiers(&cached, &fresh), vec!["c", "d"]);
+        assert!(new_identifiers(&fresh, &cached).is_empty());
+
+        let start = Instant::now();
+        let mut schedule = SyncSchedule::default();
+        assert!(!schedule.start_if_due(0, 2, start), "Sync is off");
+        assert!(schedule.start_if_due(15, 2, start), "First round starts right away");
+        assert!(!schedule.start_if_due(15, 2, start + Duration::from_secs(3600)), "Round still running");
+        schedule.finish_one();
+        schedule.finish_one();
+        assert!(!schedule.is_running());
+        assert!(!schedule.start_if_due(15, 2, start + Duration::from_secs(600)));
+        assert!(schedule.start_if_due(15, 2, start + Duration::from_secs(900)));
+
+        // A round started by hand doesn't wait for the interval and postpones the next one
+        schedule.finish_one();
+        schedule.finish_one();
+        let manual = start + Duration::from_secs(1000);
+        assert!(schedule.start_now(1, manual));
+        assert!(!schedule.start_now(1, manual), "Round still running");
+        schedule.finish_one();
+        assert!(!schedule.start_if_due(15, 2, manual + Duration::from_secs(600)));
+        assert!(!schedule.start_now(0, manual), "No favorites");
+    }
+
+    #[test]
+    fn test_interval_steps() {
+        assert_eq!(step_interval(0, true), 15);
+        assert_eq!(step_interval(15, false), 0);
+        assert_eq!(step_interval(0, false), 0);
+        assert_eq!(step_interval(1440, true), 1440);
+        assert_eq!(step_interval(45, true), 60); // Hand-edited values snap to the neighbouring step
+        assert_eq!(step_interval(45, false), 30);
+        assert_eq!(format_interval(30), "Every 30 min");
+        assert_eq!(format_interval(360), "Every 6 h");
+    }
+}
@@ -0,0 +1,546 @@
+use crate::archive_api::{FileDetails, ItemDetails};
+use crate::musicbrainz::TrackIds;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// What happens to tags a downloaded audio file already carries.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagPolicy {
+    /// Only add the tags the file lacks.
+    #[default]
+    FillMissing,
+    /// Replace artist/album/title/track/date with archive.org's; other tags are kept.
+    Overwrite,
+    /// Leave files that have any of those tags alone.
+    SkipTagged,
+}
+
+impl fmt::Display for TagPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagPolicy::FillMissing => write!(f, "fill in missing tags"),
+            TagPolicy::Overwrite => write!(f, "overwrite tags"),
+            TagPolicy::SkipTagged => write!(f, "skip tagged files"),
+        }
+    }
+}
+
+impl TagPolicy {
+    /// Next policy in settings order (FillMissing -> Overwrite -> SkipTagged -> FillMissing).
+    pub fn cycle(self) -> Self {
+        match self {
+            TagPolicy::FillMissing => TagPolicy::Overwrite,
+            TagPolicy::Overwrite => TagPolicy::SkipTagged,
+            TagPolicy::SkipTagged => TagPolicy::FillMissing,
+        }
+    }
+}
+
+/// Where a tag lives in an ID3v2 tag.
+#[derive(Debug, Clone, Copy)]
+enum Id3Key {
+    /// A text frame, e.g. `TPE1`.
+    Text(&'static str),
+    /// A `TXXX` frame with this description.
+    UserText(&'static str),
+    /// A `UFID` frame with this owner.
+    UniqueId(&'static str),
+}
+
+/// ID3v2 location and Vorbis comment name of each tag, in [`Tags::values`] order. The
+/// MusicBrainz ones use MusicBrainz Picard's names, which media servers read.
+const KEYS: [(Id3Key, &str); 9] = [
+    (Id3Key::Text("TPE1"), "ARTIST"),
+    (Id3Key::Text("TALB"), "ALBUM"),
+    (Id3Key::Text("TIT2"), "TITLE"),
+    (Id3Key::Text("TRCK"), "TRACKNUMBER"),
+    (Id3Key::Text("TDRC"), "DATE"),
+    (Id3Key::UserText("MusicBrainz Album Id"), "MUSICBRAINZ_ALBUMID"),
+    (Id3Key::UserText("MusicBrainz Artist Id"), "MUSICBRAINZ_ARTISTID"),
+    (Id3Key::UserText("MusicBrainz Release Track Id"), "MUSICBRAINZ_RELEASETRACKID"),
+    (Id3Key::UniqueId("http://musicbrainz.org"), "MUSICBRAINZ_TRACKID"),
+];
+/// Free space left in a rewritten tag, so later edits by other taggers fit in place.
+const PADDING: usize = 1024;
+
+/// The tags written into an audio file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<String>,
+    pub date: Option<String>,
+    /// Identifiers of the track, when the item matched a MusicBrainz release.
+    pub musicbrainz: Option<TrackIds>,
+}
+
+impl Tags {
+    /// Tags of `file` from its own metadata, falling back to the item's: the item's
+    /// creator is the artist and its title the album.
+    pub fn for_file(details: &ItemDetails, file: &FileDetails) -> Self {
+        let stem = file.name.rsplit('/').next().unwrap_or(&file.name);
+        let stem = stem.rsplit_once('.').map_or(stem, |(stem, _)| stem);
+        let clean = |value: Option<&String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        Self {
+            artist: clean(file.artist.as_ref()).or_else(|| clean(details.creator.as_ref())),
+            album: clean(file.album.as_ref()).or_else(|| clean(details.title.as_ref())),
+            title: clean(file.title.as_ref()).or_else(|| Some(stem.to_string())),
+            track: clean(file.track.as_ref()),
+            date: clean(details.date.as_ref()),
+            musicbrainz: None,
+        }
+    }
+
+    fn values(&self) -> [Option<&str>; KEYS.len()] {
+        let ids = self.musicbrainz.as_ref();
+        [
+            self.artist.as_deref(),
+            self.album.as_deref(),
+            self.title.as_deref(),
+            self.track.as_deref(),
+            self.date.as_deref(),
+            ids.map(|ids| ids.release_id.as_str()),
+            ids.and_then(|ids| ids.artist_id.as_deref()),
+            ids.map(|ids| ids.release_track_id.as_str()),
+            ids.map(|ids| ids.recording_id.as_str()),
+        ]
+    }
+
+    /// The tags to write, as `(index into KEYS, value)`, given the file's current ones.
+    /// Values the file already has are never rewritten, so tagging twice changes nothing.
+    fn to_write(&self, existing: &[Option<String>; KEYS.len()], policy: TagPolicy) -> Vec<(usize, &str)> {
+        if policy == TagPolicy::SkipTagged && existing.iter().any(Option::is_some) {
+            return Vec::new();
+        }
+        self.values()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, value)| Some((i, value?)))
+            .filter(|(i, value)| match &existing[*i] {
+                None => true,
+                Some(current) => policy == TagPolicy::Overwrite && current != value,
+            })
+            .collect()
+    }
+}
+
+/// How tagging a file ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagOutcome {
+    Written,
+    /// The policy left nothing to change.
+    Unchanged,
+    /// Not an MP3 or FLAC file, or a tag layout this module doesn't rewrite.
+    Unsupported,
+}
+
+/// Whether `name` is a file [`tag_file`] can tag.
+pub fn is_taggable(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower.ends_with(".mp3") || lower.ends_with(".flac")
+}
+
+/// Writes `tags` into the MP3 (ID3v2.4) or FLAC (Vorbis comment) file at `path`
+/// following `policy`. The file is rewritten through a temporary copy, so an error
+/// leaves it as it was. Blocking.
+pub fn tag_file(path: &Path, tags: &Tags, policy: TagPolicy) -> Result<TagOutcome> {
+    let mut file = File::open(path).context(format!("Failed to open '{}'", path.display()))?;
+    let mut magic = [0u8; 4];
+    let read = file.read(&mut magic)?;
+    let lower = path.to_string_lossy().to_ascii_lowercase();
+    let rewrite = if read == 4 && &magic == b"fLaC" {
+        flac::retag(&mut file, tags, policy)?
+    } else if lower.ends_with(".mp3") {
+        id3::retag(&mut file, tags, policy)?
+    } else {
+        return Ok(TagOutcome::Unsupported);
+    };
+    match rewrite {
+        Rewrite::Unsupported => Ok(TagOutcome::Unsupported),
+        Rewrite::Unchanged => Ok(TagOutcome::Unchanged),
+        Rewrite::Replace { head, audio_offset } => {
+            replace_head(path, &mut file, &head, audio_offset)?;
+            Ok(TagOutcome::Written)
+        }
+    }
+}
+
+/// What the format-specific code wants done to the file.
+enum Rewrite {
+    Unsupported,
+    Unchanged,
+    /// Replace everything before `audio_offset` with `head`.
+    Replace { head: Vec<u8>, audio_offset: u64 },
+}
+
+fn replace_head(path: &Path, original: &mut File, head: &[u8], audio_offset: u64) -> Result<()> {
+    let name = path.file_name().context("Path has no file name")?.to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{}.tagging", name));
+    let result = (|| -> Result<()> {
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+        out.write_all(head)?;
+        original.seek(SeekFrom::Start(audio_offset))?;
+        io::copy(original, &mut out)?;
+        out.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.context(format!("Failed to rewrite '{}'", path.display()))
+}
+
+fn read_exact_at(file: &mut File, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf).context("File ends inside its tags")?;
+    Ok(buf)
+}
+
+mod id3 {
+    use super::*;
+
+    fn syncsafe(bytes: &[u8]) -> usize {
+        bytes.iter().fold(0, |n, b| (n << 7) | usize::from(b & 0x7f))
+    }
+
+    fn to_syncsafe(n: usize) -> [u8; 4] {
+        [(n >> 21 & 0x7f) as u8, (n >> 14 & 0x7f) as u8, (n >> 7 & 0x7f) as u8, (n & 0x7f) as u8]
+    }
+
+    /// A frame kept as it was (v2.4 layout).
+    struct Frame {
+        id: [u8; 4],
+        flags: [u8; 2],
+        data: Vec<u8>,
+    }
+
+    /// Frames of the ID3v2.3/2.4 tag at the start of `file` and the offset of the audio
+    /// after it; `None` for layouts that aren't rewritten (v2.2, unsynchronised tags).
+    fn read_tag(file: &mut File) -> Result<Option<(Vec<Frame>, u64)>> {
+        let len = file.metadata()?.len();
+        if len < 10 {
+            return Ok(Some((Vec::new(), 0)));
+        }
+        let header = read_exact_at(file, 0, 10)?;
+        if &header[..3] != b"ID3" {
+            return Ok(Some((Vec::new(), 0)));
+        }
+        let (major, flags) = (header[3], header[5]);
+        if !(3..=4).contains(&major) || flags & 0x80 != 0 {
+            return Ok(None);
+        }
+        let size = syncsafe(&header[6..10]);
+        let footer = if major == 4 && flags & 0x10 != 0 { 10 } else { 0 };
+        let body = read_exact_at(file, 10, size)?;
+        let mut pos = 0;
+        if flags & 0x40 != 0 {
+            let Some(ext) = body.get(..4) else { bail!("Truncated ID3 extended header") };
+            pos = if major == 4 { syncsafe(ext) } else { u32::from_be_bytes([ext[0], ext[1], ext[2], ext[3]]) as usize + 4 };
+        }
+        let mut frames = Vec::new();
+        while pos + 10 <= body.len() && body[pos] != 0 {
+            let id: [u8; 4] = body[pos..pos + 4].try_into()?;
+            let size = if major == 4 { syncsafe(&body[pos + 4..pos + 8]) } else { u32::from_be_bytes(body[pos + 4..pos + 8].try_into()?) as usize };
+            let flags = [body[pos + 8], body[pos + 9]];
+            let Some(data) = body.get(pos + 10..pos + 10 + size) else { bail!("Truncated ID3 frame") };
+            pos += 10 + size;
+            if major == 3 {
+                // v2.3 compression/encryption/grouping don't map onto v2.4; drop such frames
+                if flags[1] != 0 {
+                    continue;
+                }
+                let id = if &id == b"TYER" { *b"TDRC" } else { id };
+                frames.push(Frame { id, flags: [0, 0], data: data.to_vec() });
+            } else {
+                frames.push(Frame { id, flags, data: data.to_vec() });
+            }
+        }
+        Ok(Some((frames, (10 + size + footer) as u64)))
+    }
+
+    /// Strings of a text-carrying frame (after its encoding byte), split at terminators.
+    fn strings(data: &[u8]) -> Vec<String> {
+        let Some((encoding, bytes)) = data.split_first() else { return Vec::new() };
+        match encoding {
+            0 => bytes.split(|b| *b == 0).map(|s| s.iter().map(|b| char::from(*b)).collect()).collect(),
+            1 | 2 => {
+                let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                units
+                    .split(|unit| *unit == 0)
+                    .map(|s| match s {
+                        [0xfffe, rest @ ..] => String::from_utf16_lossy(&rest.iter().map(|u| u.swap_bytes()).collect::<Vec<_>>()),
+                        [0xfeff, rest @ ..] => String::from_utf16_lossy(rest),
+                        s => String::from_utf16_lossy(s),
+                    })
+                    .collect()
+            }
+            _ => bytes.split(|b| *b == 0).map(|s| String::from_utf8_lossy(s).into_owned()).collect(),
+        }
+    }
+
+    /// Value `frame` holds for `key` (possibly empty), or `None` if it holds another tag.
+    fn value_of(key: Id3Key, frame: &Frame) -> Option<String> {
+        match key {
+            Id3Key::Text(id) if frame.id == id.as_bytes() => Some(strings(&frame.data).into_iter().next().unwrap_or_default()),
+            Id3Key::UserText(description) if &frame.id == b"TXXX" => {
+                let mut parts = strings(&frame.data).into_iter();
+                (parts.next()? == description).then(|| parts.next().unwrap_or_default())
+            }
+            Id3Key::UniqueId(owner) if &frame.id == b"UFID" => {
+                let end = frame.data.iter().position(|b| *b == 0)?;
+                (&frame.data[..end] == owner.as_bytes()).then(|| String::from_utf8_lossy(&frame.data[end + 1..]).into_owned())
+            }
+            _ => None,
+        }
+    }
+
+    fn frame_for(key: Id3Key, value: &str) -> Frame {
+        let (id, data) = match key {
+            Id3Key::Text(id) => (id.as_bytes(), [&[3], value.as_bytes()].concat()), // 3 = UTF-8
+            Id3Key::UserText(description) => (&b"TXXX"[..], [&[3], description.as_bytes(), &[0], value.as_bytes()].concat()),
+            Id3Key::UniqueId(owner) => (&b"UFID"[..], [owner.as_bytes(), &[0], value.as_bytes()].concat()),
+        };
+        Frame { id: [id[0], id[1], id[2], id[3]], flags: [0, 0], data }
+    }
+
+    pub(super) fn retag(file: &mut File, tags: &Tags, policy: TagPolicy) -> Result<Rewrite> {
+        let Some((mut frames, audio_offset)) = read_tag(file)? else { return Ok(Rewrite::Unsupported) };
+        let existing = KEYS.map(|(key, _)| frames.iter().filter_map(|f| value_of(key, f)).find(|v| !v.trim().is_empty()));
+        let changes = tags.to_write(&existing, policy);
+        if changes.is_empty() {
+            return Ok(Rewrite::Unchanged);
+        }
+        for (i, value) in changes {
+            let key = KEYS[i].0;
+            frames.retain(|f| value_of(key, f).is_none());
+            frames.push(frame_for(key, value));
+        }
+        let mut body = Vec::new();
+        for frame in &frames {
+            body.extend_from_slice(&frame.id);
+            body.extend_from_slice(&to_syncsafe(frame.data.len()));
+            body.extend_from_slice(&frame.flags);
+            body.extend_from_slice(&frame.data);
+        }
+        body.resize(body.len() + PADDING, 0);
+        let mut head = b"ID3\x04\x00\x00".to_vec();
+        head.extend_from_slice(&to_syncsafe(body.len()));
+        head.extend_from_slice(&body);
+        Ok(Rewrite::Replace { head, audio_offset })
+    }
+}
+
+mod flac {
+    use super::*;
+
+    const VORBIS_COMMENT: u8 = 4;
+    const PADDING_BLOCK: u8 = 1;
+
+    fn le_u32(data: &[u8], pos: usize) -> Result<u32> {
+        let Some(bytes) = data.get(pos..pos + 4) else { bail!("Truncated Vorbis comment") };
+        Ok(u32::from_le_bytes(bytes.try_into()?))
+    }
+
+    /// Vendor string and `KEY=value` comments of a VORBIS_COMMENT block.
+    pub(super) fn parse_comments(data: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        let vendor_len = le_u32(data, 0)? as usize;
+        let Some(vendor) = data.get(4..4 + vendor_len) else { bail!("Truncated Vorbis comment") };
+        let mut pos = 4 + vendor_len;
+        let count = le_u32(data, pos)?;
+        pos += 4;
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let len = le_u32(data, pos)? as usize;
+            let Some(comment) = data.get(pos + 4..pos + 4 + len) else { bail!("Truncated Vorbis comment") };
+            comments.push(String::from_utf8_lossy(comment).into_owned());
+            pos += 4 + len;
+        }
+        Ok((vendor.to_vec(), comments))
+    }
+
+    fn key_of(comment: &str) -> &str {
+        comment.split_once('=').map_or(comment, |(key, _)| key)
+    }
+
+    pub(super) fn retag(file: &mut File, tags: &Tags, policy: TagPolicy) -> Result<Rewrite> {
+        let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+        let mut pos = 4u64;
+        loop {
+            let header = read_exact_at(file, pos, 4)?;
+            let (last, kind) = (header[0] & 0x80 != 0, header[0] & 0x7f);
+            let len = usize::from(header[1]) << 16 | usize::from(header[2]) << 8 | usize::from(header[3]);
+            blocks.push((kind, read_exact_at(file, pos + 4, len)?));
+            pos += 4 + len as u64;
+            if last {
+                break;
+            }
+        }
+        let (vendor, mut comments) = match blocks.iter().find(|(kind, _)| *kind == VORBIS_COMMENT) {
+            Some((_, data)) => parse_comments(data)?,
+            None => (b"archiver".to_vec(), Vec::new()),
+        };
+        let existing = KEYS.map(|(_, key)| {
+            comments
+                .iter()
+                .filter(|c| key_of(c).eq_ignore_ascii_case(key))
+                .find_map(|c| c.split_once('=').map(|(_, value)| value.to_string()).filter(|v| !v.trim().is_empty()))
+        });
+        let changes = tags.to_write(&existing, policy);
+        if changes.is_empty() {
+            return Ok(Rewrite::Unchanged);
+        }
+        for (i, value) in changes {
+            let key = KEYS[i].1;
+            comments.retain(|c| !key_of(c).eq_ignore_ascii_case(key));
+            comments.push(format!("{}={}", key, value));
+        }
+        let mut data = Vec::new();
+        data.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        data.extend_from_slice(&vendor);
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in &comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+
+        // STREAMINFO stays first; the new comment replaces the old one (or follows
+        // STREAMINFO) and old padding is replaced by fresh padding at the end
+        let mut kept: Vec<(u8, Vec<u8>)> = Vec::new();
+        for (kind, block) in blocks {
+            match kind {
+                VORBIS_COMMENT | PADDING_BLOCK => {}
+                _ => kept.push((kind, block)),
+            }
+        }
+        kept.insert(1.min(kept.len()), (VORBIS_COMMENT, data));
+        kept.push((PADDING_BLOCK, vec![0; PADDING]));
+        let mut head = b"fLaC".to_vec();
+        let count = kept.len();
+        for (i, (kind, block)) in kept.into_iter().enumerate() {
+            if block.len() >= 1 << 24 {
+                bail!("FLAC metadata block too large");
+            }
+            let last = if i + 1 == count { 0x80 } else { 0 };
+            head.push(last | kind);
+            head.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+            head.extend_from_slice(&block);
+        }
+        Ok(Rewrite::Replace { head, audio_offset: pos })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> Tags {
+        Tags {
+            artist: Some("Grateful Dead".to_string()),
+            album: Some("Live at Barton Hall".to_string()),
+            title: Some("Scarlet Begonias".to_string()),
+            track: Some("03".to_string()),
+            date: Some("1977-05-08".to_string()),
+            musicbrainz: None,
+        }
+    }
+
+    #[test]
+    fn test_tags_for_file_fall_back_to_item() {
+        let details = ItemDetails { creator: Some("Grateful Dead".to_string()), title: Some("Live".to_string()), ..Default::default() };
+        let file = FileDetails { name: "d1/gd77t03.mp3".to_string(), track: Some("03".to_string()), album: Some("Cornell".to_string()), ..Default::default() };
+        let tags = Tags::for_file(&details, &file);
+        assert_eq!(tags.artist.as_deref(), Some("Grateful Dead"));
+        assert_eq!(tags.album.as_deref(), Some("Cornell"));
+        assert_eq!(tags.title.as_deref(), Some("gd77t03"));
+        assert_eq!(tags.date, None);
+        assert!(is_taggable("A.FLAC") && !is_taggable("cover.jpg"));
+    }
+
+    #[test]
+    fn test_mp3_gets_id3_tag_and_keeps_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.mp3");
+        // An existing v2.3 tag with a title and a comment frame, then "audio"
+        let mut old = b"ID3\x03\x00\x00".to_vec();
+        let mut frames = Vec::new();
+        for (id, text) in [("TIT2", "Old title"), ("COMM", "xxxkeep me")] {
+            frames.extend_from_slice(id.as_bytes());
+            frames.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+            frames.extend_from_slice(&[0, 0, 0]);
+            frames.extend_from_slice(text.as_bytes());
+        }
+        old.extend_from_slice(&[0, 0, 0, frames.len() as u8]);
+        old.extend_from_slice(&frames);
+        old.extend_from_slice(b"AUDIO");
+        fs::write(&path, &old).unwrap();
+
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::SkipTagged).unwrap(), TagOutcome::Unchanged);
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::FillMissing).unwrap(), TagOutcome::Written);
+        let tagged = fs::read(&path).unwrap();
+        assert_eq!(&tagged[..4], b"ID3\x04");
+        assert!(tagged.ends_with(b"AUDIO"));
+        let text = String::from_utf8_lossy(&tagged);
+        assert!(text.contains("Old title") && !text.contains("Scarlet Begonias"), "FillMissing keeps the title");
+        assert!(text.contains("keep me") && text.contains("Grateful Dead") && text.contains("1977-05-08"));
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::FillMissing).unwrap(), TagOutcome::Unchanged);
+
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::Overwrite).unwrap(), TagOutcome::Written);
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::Overwrite).unwrap(), TagOutcome::Unchanged, "Same tags aren't rewritten");
+        let text = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+        assert!(text.contains("Scarlet Begonias") && !text.contains("Old title"));
+        assert!(text.ends_with("AUDIO"));
+
+        let ids = TrackIds {
+            release_id: "release-id".to_string(),
+            artist_id: Some("artist-id".to_string()),
+            release_track_id: "track-id".to_string(),
+            recording_id: "recording-id".to_string(),
+        };
+        let with_ids = Tags { musicbrainz: Some(ids), ..tags() };
+        assert_eq!(tag_file(&path, &with_ids, TagPolicy::FillMissing).unwrap(), TagOutcome::Written);
+        assert_eq!(tag_file(&path, &with_ids, TagPolicy::FillMissing).unwrap(), TagOutcome::Unchanged, "TXXX and UFID frames are read back");
+        let text = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+        assert!(text.contains("MusicBrainz Album Id\0release-id"));
+        assert!(text.contains("UFID\0\0\0#\0\0http://musicbrainz.org\0recording-id"));
+    }
+
+    #[test]
+    fn test_flac_gets_vorbis_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.flac");
+        let mut flac = b"fLaC".to_vec();
+        flac.push(0); // STREAMINFO, not last
+        flac.extend_from_slice(&[0, 0, 34]);
+        flac.extend_from_slice(&[7; 34]);
+        let comment: &[u8] = b"\x06\x00\x00\x00vendor\x01\x00\x00\x00\x0b\x00\x00\x00ARTIST=Band";
+        flac.push(0x80 | 4);
+        flac.extend_from_slice(&(comment.len() as u32).to_be_bytes()[1..]);
+        flac.extend_from_slice(comment);
+        flac.extend_from_slice(b"FRAMES");
+        fs::write(&path, &flac).unwrap();
+
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::FillMissing).unwrap(), TagOutcome::Written);
+        let tagged = fs::read(&path).unwrap();
+        assert_eq!(&tagged[..4 + 4 + 34], &flac[..4 + 4 + 34], "STREAMINFO stays first");
+        assert!(tagged.ends_with(b"FRAMES"));
+        let mut file = File::open(&path).unwrap();
+        let block = read_exact_at(&mut file, 42, 4).unwrap();
+        assert_eq!(block[0], 4, "Vorbis comment follows STREAMINFO");
+        let len = usize::from(block[1]) << 16 | usize::from(block[2]) << 8 | usize::from(block[3]);
+        let (vendor, comments) = flac::parse_comments(&read_exact_at(&mut file, 46, len).unwrap()).unwrap();
+        assert_eq!(vendor, b"vendor");
+        assert!(comments.contains(&"ARTIST=Band".to_string()), "Existing artist kept: {:?}", comments);
+        assert!(comments.contains(&"TRACKNUMBER=03".to_string()));
+        assert_eq!(tag_file(&path, &tags(), TagPolicy::FillMissing).unwrap(), TagOutcome::Unchanged);
+        assert_eq!(tag_file(&dir.path().join("t.flac"), &Tags::default(), TagPolicy::Overwrite).unwrap(), TagOutcome::Unchanged);
+    }
+}
@@ -0,0 +1,197 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Color scheme selectable in the settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemeKind {
+    /// The original palette.
+    #[default]
+    Default,
+    /// Bright foregrounds and bold/reversed highlights for low-vision users.
+    HighContrast,
+}
+
+impl fmt::Display for ThemeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeKind::Default => write!(f, "Default"),
+            ThemeKind::HighContrast => write!(f, "High Contrast"),
+        }
+    }
+}
+
+impl ThemeKind {
+    /// Returns the other theme (there are only two, so Left and Right both toggle).
+    pub fn cycle(self) -> Self {
+        match self {
+            ThemeKind::Default => ThemeKind::HighContrast,
+            ThemeKind::HighContrast => ThemeKind::Default,
+        }
+    }
+}
+
+/// Prefix for error messages, so errors are recognisable without color.
+pub const ERROR_MARKER: &str = "[!] ";
+/// Prefix for completed/success messages.
+pub const SUCCESS_MARKER: &str = "[ok] ";
+/// Prefix for in-progress messages.
+pub const BUSY_MARKER: &str = "[..] ";
+/// Prefix for files that need an entitled login (lending/stream-only).
+pub const RESTRICTED_MARKER: &str = "[locked] ";
+/// Prefix for items skipped by collection downloads because of an exclusion rule.
+pub const EXCLUDED_MARKER: &str = "[excluded] ";
+/// Prefix of a collapsed group of duplicate items (Right expands it).
+pub const GROUP_COLLAPSED_MARKER: &str = "[+] ";
+/// Prefix of an expanded group of duplicate items (Left collapses it).
+pub const GROUP_EXPANDED_MARKER: &str = "[-] ";
+/// Prefix of items marked for a batch download (Space in the items pane).
+pub const MARKED_MARKER: &str = "[x] ";
+/// Prefix of items that are collections themselves (Enter lists them).
+pub const COLLECTION_MARKER: &str = "[collection] ";
+/// Prefix for the title of the pane that has focus.
+pub const ACTIVE_PANE_MARKER: &str = "* ";
+
+/// Returns true if the `NO_COLOR` convention (<https://no-color.org>) asks us not to emit color.
+pub fn no_color_requested() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+/// Resolved styles used by the UI.
+///
+/// State is never conveyed by color alone: focus, selection and errors also get a
+/// modifier or a text marker, so the UI stays usable in monochrome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub kind: ThemeKind,
+    /// False when NO_COLOR is set; all styles then use modifiers only.
+    pub color: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self { kind: ThemeKind::Default, color: true }
+    }
+}
+
+impl Theme {
+    /// Builds the theme for `kind`, honouring NO_COLOR from the environment.
+    pub fn from_env(kind: ThemeKind) -> Self {
+        Self { kind, color: !no_color_requested() }
+    }
+
+    fn fg(&self, default: Color, high_contrast: Color) -> Style {
+        if !self.color {
+            return Style::default();
+        }
+        match self.kind {
+            ThemeKind::Default => Style::default().fg(default),
+            ThemeKind::HighContrast => Style::default().fg(high_contrast).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// Border of the focused pane.
+    pub fn active_border(&self) -> Style {
+        self.fg(Color::Yellow, Color::White).add_modifier(Modifier::BOLD)
+    }
+
+    /// Border of the item detail view.
+    pub fn item_view_border(&self) -> Style {
+        self.fg(Color::Cyan, Color::White)
+    }
+
+    /// Border of the settings view.
+    pub fn settings_border(&self) -> Style {
+        self.fg(Color::Magenta, Color::White)
+    }
+
+    /// Border of input popups.
+    pub fn input_border(&self) -> Style {
+        self.fg(Color::Yellow, Color::White)
+    }
+
+    /// Highlighted (selected) list row.
+    pub fn highlight(&self) -> Style {
+        if !self.color || self.kind == ThemeKind::HighContrast {
+            return Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        Style::default().bg(Color::Blue).add_modifier(Modifier::BOLD)
+    }
+
+    /// Highlighted row in the settings list.
+    pub fn settings_highlight(&self) -> Style {
+        if !self.color || self.kind == ThemeKind::HighContrast {
+            return Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD)
+    }
+
+    /// Error text.
+    pub fn error(&self) -> Style {
+        self.fg(Color::Red, Color::LightRed).add_modifier(Modifier::BOLD)
+    }
+
+    /// Loading / in-progress text.
+    pub fn busy(&self) -> Style {
+        self.fg(Color::Yellow, Color::LightYellow)
+    }
+
+    /// Completed / success text.
+    pub fn success(&self) -> Style {
+        self.fg(Color::Green, Color::LightGreen)
+    }
+
+    /// Placeholder and hint text.
+    pub fn muted(&self) -> Style {
+        if !self.color {
+            return Style::default().add_modifier(Modifier::ITALIC);
+        }
+        match self.kind {
+            ThemeKind::Default => Style::default().fg(Color::DarkGray),
+            // DarkGray is unreadable on many dark backgrounds
+            ThemeKind::HighContrast => Style::default().fg(Color::Gray),
+        }
+    }
+
+    /// Title for a pane, marking it if it has focus.
+    pub fn pane_title(&self, title: &str, active: bool) -> String {
+        if active {
+            format!("{}{}", ACTIVE_PANE_MARKER, title)
+        } else {
+            title.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_color_theme_has_no_colors() {
+        let theme = Theme { kind: ThemeKind::HighContrast, color: false };
+        for style in [theme.active_border(), theme.highlight(), theme.error(), theme.busy(), theme.success(), theme.muted()] {
+            assert_eq!(style.fg, None);
+            assert_eq!(style.bg, None);
+        }
+        // Selection and errors still stand out without color
+        assert!(theme.highlight().add_modifier.contains(Modifier::REVERSED));
+        assert!(theme.error().add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_high_contrast_uses_reversed_selection() {
+        let theme = Theme { kind: ThemeKind::HighContrast, color: true };
+        assert!(theme.highlight().add_modifier.contains(Modifier::REVERSED));
+        assert_eq!(theme.muted().fg, Some(Color::Gray));
+        assert_eq!(Theme::default().highlight().bg, Some(Color::Blue));
+    }
+
+    #[test]
+    fn test_pane_title_marks_active_pane() {
+        let theme = Theme::default();
+        assert_eq!(theme.pane_title("Items", true), "* Items");
+        assert_eq!(theme.pane_title("Items", false), "Items");
+        assert_eq!(ThemeKind::Default.cycle(), ThemeKind::HighContrast);
+    }
+}
@@ -0,0 +1,128 @@
+use std::{sync::Mutex, time::Duration};
+use tokio::time::{sleep, Instant};
+
+/// Smallest non-zero speed cap offered in the settings, in KB/s.
+const MIN_LIMIT_KB: u64 = 64;
+
+/// Global download speed cap shared by every concurrent file download.
+///
+/// A token bucket holding up to one second's worth of bytes: each received chunk takes
+/// its size from the bucket, and a chunk that overdraws it makes the caller sleep until
+/// the debt is paid back. The limit can be changed while downloads are running.
+#[derive(Debug)]
+pub struct Throttle {
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes per second; 0 = unlimited.
+    rate: u64,
+    /// Bytes that may be received without waiting (negative = debt).
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Throttle {
+    /// A throttle capped at `limit_kb` KB/s (0 = unlimited).
+    pub fn new(limit_kb: u64) -> Self {
+        let rate = limit_kb * 1024;
+        Self { state: Mutex::new(Bucket { rate, tokens: rate as f64, last_refill: Instant::now() }) }
+    }
+
+    /// Changes the cap; downloads already running pick it up on their next chunk.
+    pub fn set_limit(&self, limit_kb: u64) {
+        let mut bucket = self.state.lock().unwrap();
+        bucket.rate = limit_kb * 1024;
+        bucket.tokens = bucket.tokens.min(bucket.rate as f64);
+        bucket.last_refill = Instant::now();
+    }
+
+    /// Current cap in KB/s (0 = unlimited).
+    pub fn limit(&self) -> u64 {
+        self.state.lock().unwrap().rate / 1024
+    }
+
+    /// Accounts for `bytes` just received, waiting as long as the cap requires.
+    pub async fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            if bucket.rate == 0 {
+                return;
+            }
+            let now = Instant::now();
+            let rate = bucket.rate as f64;
+            let refill = now.duration_since(bucket.last_refill).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate) - bytes as f64;
+            bucket.last_refill = now;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        };
+        sleep(wait).await;
+    }
+}
+
+/// Next cap when pressing Right in the settings: Unlimited is left for the lowest cap,
+/// after which the cap doubles.
+pub fn raise_limit(limit_kb: u64) -> u64 {
+    if limit_kb == 0 {
+        MIN_LIMIT_KB
+    } else {
+        limit_kb.saturating_mul(2)
+    }
+}
+
+/// Next cap when pressing Left: the cap halves, and below the lowest cap it goes back to Unlimited.
+pub fn lower_limit(limit_kb: u64) -> u64 {
+    if limit_kb / 2 < MIN_LIMIT_KB {
+        0
+    } else {
+        limit_kb / 2
+    }
+}
+
+/// Human-readable cap, e.g. "Unlimited", "512 KB/s" or "2.0 MB/s".
+pub fn format_limit(limit_kb: u64) -> String {
+    match limit_kb {
+        0 => "Unlimited".to_string(),
+        kb if kb < 1024 => format!("{} KB/s", kb),
+        kb => format!("{:.1} MB/s", kb as f64 / 1024.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_caps_throughput() {
+        let throttle = Throttle::new(64);
+        let start = Instant::now();
+        // The first second's worth passes straight through, the next 128 KiB take two seconds
+        for _ in 0..12 {
+            throttle.consume(16 * 1024).await;
+        }
+        assert_eq!(start.elapsed().as_secs(), 2);
+
+        // Lifting the cap stops the waiting
+        throttle.set_limit(0);
+        let lifted = Instant::now();
+        throttle.consume(10 * 1024 * 1024).await;
+        assert_eq!(lifted.elapsed(), Duration::ZERO);
+        assert_eq!(throttle.limit(), 0);
+    }
+
+    #[test]
+    fn test_limit_steps_and_format() {
+        assert_eq!(raise_limit(0), 64);
+        assert_eq!(raise_limit(64), 128);
+        assert_eq!(lower_limit(128), 64);
+        assert_eq!(lower_limit(64), 0);
+        assert_eq!(lower_limit(0), 0);
+        assert_eq!(format_limit(0), "Unlimited");
+        assert_eq!(format_limit(512), "512 KB/s");
+        assert_eq!(format_limit(2048), "2.0 MB/s");
+    }
+}
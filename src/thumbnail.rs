@@ -0,0 +1,255 @@
+use crate::app::AppRateLimiter;
+use crate::archive_api;
+use crate::fs_util;
+use crate::offline;
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+/// Largest thumbnail kept; archive.org's are a few KB, anything much bigger isn't one.
+const MAX_THUMBNAIL_BYTES: usize = 2 * 1024 * 1024;
+/// Base64 bytes per kitty graphics escape; the protocol caps chunks at 4096.
+const KITTY_CHUNK: usize = 4096;
+/// Deletes every image kitty placed, so a cover doesn't outlive its pane.
+pub const KITTY_DELETE_ALL: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+/// How the item view shows cover art, selectable in the settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverArt {
+    /// With the graphics protocol the terminal is known to support, if any.
+    #[default]
+    Auto,
+    Kitty,
+    Sixel,
+    Iterm2,
+    Off,
+}
+
+impl fmt::Display for CoverArt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoverArt::Auto => write!(f, "Auto"),
+            CoverArt::Kitty => write!(f, "Kitty"),
+            CoverArt::Sixel => write!(f, "Sixel"),
+            CoverArt::Iterm2 => write!(f, "iTerm2"),
+            CoverArt::Off => write!(f, "Off"),
+        }
+    }
+}
+
+impl CoverArt {
+    /// Next mode in settings order (Auto -> Kitty -> Sixel -> iTerm2 -> Off -> Auto).
+    pub fn cycle(self) -> Self {
+        match self {
+            CoverArt::Auto => CoverArt::Kitty,
+            CoverArt::Kitty => CoverArt::Sixel,
+            CoverArt::Sixel => CoverArt::Iterm2,
+            CoverArt::Iterm2 => CoverArt::Off,
+            CoverArt::Off => CoverArt::Auto,
+        }
+    }
+
+    /// The protocol cover art is drawn with in this terminal, `None` for none.
+    pub fn protocol(self) -> Option<Protocol> {
+        match self {
+            CoverArt::Auto => {
+                let var = |name| std::env::var(name).unwrap_or_default();
+                detect(&var("TERM"), &var("TERM_PROGRAM"), std::env::var_os("KITTY_WINDOW_ID").is_some(), std::env::var_os("TMUX").is_some())
+            }
+            CoverArt::Kitty => Some(Protocol::Kitty),
+            CoverArt::Sixel => Some(Protocol::Sixel),
+            CoverArt::Iterm2 => Some(Protocol::Iterm2),
+            CoverArt::Off => None,
+        }
+    }
+}
+
+/// A terminal graphics protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    Iterm2,
+}
+
+/// The protocol of a terminal known to draw images, from its `TERM` and `TERM_PROGRAM`.
+/// None inside tmux, which would need every escape wrapped for passthrough.
+fn detect(term: &str, term_program: &str, kitty_window: bool, tmux: bool) -> Option<Protocol> {
+    if tmux {
+        return None;
+    }
+    match term_program {
+        "iTerm.app" | "WezTerm" => return Some(Protocol::Iterm2),
+        "ghostty" => return Some(Protocol::Kitty),
+        _ => {}
+    }
+    if kitty_window || term == "xterm-kitty" || term == "xterm-ghostty" {
+        Some(Protocol::Kitty)
+    } else if term.starts_with("foot") || term.starts_with("mlterm") || term.contains("sixel") {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+/// Cover art of the item being viewed, once fetched.
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub identifier: String,
+    /// The image file as archive.org serves it (usually JPEG).
+    pub image: Arc<[u8]>,
+}
+
+/// Where the thumbnail of `identifier` is cached, next to its details.
+pub fn cache_path(base_dir: &str, identifier: &str) -> PathBuf {
+    Path::new(base_dir).join(offline::CACHE_DIR).join("thumbs").join(identifier.replace(['/', '\\'], "_"))
+}
+
+/// The thumbnail of `identifier`: from the cache below `base_dir` if it's there, otherwise
+/// fetched (and cached, if there is a download directory).
+pub async fn load(client: &Client, base_dir: Option<&str>, identifier: &str, rate_limiter: AppRateLimiter) -> Result<Vec<u8>> {
+    let cached = base_dir.map(|dir| cache_path(dir, identifier));
+    if let Some(image) = cached.as_ref().and_then(|path| fs::read(path).ok()) {
+        debug!("Thumbnail of '{}' from the cache", identifier);
+        return Ok(image);
+    }
+    rate_limiter.until_ready().await;
+    let url = archive_api::thumbnail_url(identifier);
+    let response = client.get(&url).send().await.context(format!("Failed to fetch the thumbnail of '{}'", identifier))?;
+    if !response.status().is_success() {
+        bail!("Thumbnail request for '{}' failed: Status {}", identifier, response.status());
+    }
+    let image = response.bytes().await.context(format!("Failed to read the thumbnail of '{}'", identifier))?.to_vec();
+    if image.len() > MAX_THUMBNAIL_BYTES {
+        bail!("Thumbnail of '{}' is too large ({} bytes)", identifier, image.len());
+    }
+    if let Some(path) = cached {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("Failed to create cache directory: {}", dir.display()))?;
+        }
+        fs_util::atomic_write(&path, &image).context(format!("Failed to write thumbnail cache: {}", path.display()))?;
+    }
+    Ok(image)
+}
+
+/// The escape sequence drawing `image` into a `cols` x `rows` cell area from the cursor.
+/// iTerm2 takes any image format and kitty PNGs as they are; other images (for kitty)
+/// and every sixel image are converted by `chafa`, which must be installed.
+pub fn encode(protocol: Protocol, image: &[u8], cols: u16, rows: u16) -> Result<String> {
+    match protocol {
+        Protocol::Iterm2 => Ok(format!(
+            "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+            image.len(),
+            cols,
+            rows,
+            STANDARD.encode(image)
+        )),
+        Protocol::Kitty if is_png(image) => Ok(kitty_png(image, cols, rows)),
+        Protocol::Kitty => chafa("kitty", image, cols, rows),
+        Protocol::Sixel => chafa("sixels", image, cols, rows),
+    }
+}
+
+fn is_png(image: &[u8]) -> bool {
+    image.starts_with(b"\x89PNG\r\n\x1a\n")
+}
+
+/// A PNG sent with kitty's graphics protocol, in chunks, scaled to the cell area.
+fn kitty_png(image: &[u8], cols: u16, rows: u16) -> String {
+    let data = STANDARD.encode(image);
+    let chunks: Vec<&str> = data.as_bytes().chunks(KITTY_CHUNK).map(|c| std::str::from_utf8(c).unwrap_or_default()).collect();
+    let mut escape = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        if index == 0 {
+            escape.push_str(&format!("\x1b_Ga=T,f=100,c={},r={},q=2,m={};{}\x1b\\", cols, rows, more, chunk));
+        } else {
+            escape.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    escape
+}
+
+/// Converts `image` with `chafa --format <format>`, fitting it into the cell area.
+fn chafa(format: &str, image: &[u8], cols: u16, rows: u16) -> Result<String> {
+    let mut child = Command::new("chafa")
+        .args(["--format", format, "--size", &format!("{}x{}", cols, rows), "--animate", "off", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run chafa (is it installed?)")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(image).context("Failed to write to chafa")?;
+    }
+    let output = child.wait_with_output().context("Failed to wait for chafa")?;
+    if !output.status.success() {
+        bail!("chafa exited with {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminals_map_onto_protocols() {
+        assert_eq!(detect("xterm-kitty", "", false, false), Some(Protocol::Kitty));
+        assert_eq!(detect("xterm-256color", "", true, false), Some(Protocol::Kitty));
+        assert_eq!(detect("xterm-256color", "iTerm.app", false, false), Some(Protocol::Iterm2));
+        assert_eq!(detect("foot", "", false, false), Some(Protocol::Sixel));
+        assert_eq!(detect("xterm-256color", "", false, false), None);
+        assert_eq!(detect("xterm-kitty", "", true, true), None, "tmux would swallow the escapes");
+        assert_eq!(CoverArt::Off.protocol(), None);
+        assert_eq!(CoverArt::Sixel.protocol(), Some(Protocol::Sixel));
+        assert_eq!(CoverArt::Off.cycle(), CoverArt::Auto);
+    }
+
+    #[test]
+    fn test_images_are_encoded_for_the_protocol() {
+        let jpeg = b"\xff\xd8\xff\xe0fake jpeg";
+        let iterm = encode(Protocol::Iterm2, jpeg, 20, 10).unwrap();
+        assert!(iterm.starts_with("\x1b]1337;File=inline=1;size=13;width=20;height=10;preserveAspectRatio=1:"));
+        assert!(iterm.ends_with(&format!("{}\x07", STANDARD.encode(jpeg))));
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.resize(4000, 7); // More than one chunk once encoded
+        let kitty = encode(Protocol::Kitty, &png, 20, 10).unwrap();
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,c=20,r=10,q=2,m=1;"));
+        assert_eq!(kitty.matches("\x1b_G").count(), 2);
+        assert!(kitty.contains("\x1b_Gm=0;"));
+
+        assert_eq!(cache_path("/data", "a/b"), Path::new("/data/.item_cache/thumbs/a_b"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_thumbnails_are_fetched_once_then_cached() {
+        use crate::mock_server;
+        use crate::rate_limit::SharedRateLimiter;
+        use governor::Quota;
+        use std::num::NonZeroU32;
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let limiter: AppRateLimiter = Arc::new(SharedRateLimiter::new(Quota::per_minute(NonZeroU32::new(600).unwrap())));
+        let dir = tempfile::tempdir().unwrap();
+        let base_dir = dir.path().to_str().unwrap();
+
+        let image = load(&Client::new(), Some(base_dir), "mock_item_one", Arc::clone(&limiter)).await.unwrap();
+        assert_eq!(image, mock_server::MOCK_THUMBNAIL);
+        assert!(is_png(&image));
+        fs::write(cache_path(base_dir, "mock_item_one"), b"cached").unwrap();
+        assert_eq!(load(&Client::new(), Some(base_dir), "mock_item_one", Arc::clone(&limiter)).await.unwrap(), b"cached");
+        assert!(load(&Client::new(), Some(base_dir), "no_such_item", limiter).await.is_err());
+    }
+}
@@ -0,0 +1,920 @@
+use crate::{
+    app::DownloadProgress,
+    archive_api,
+    download::DownloadContext,
+    paths,
+    report::{FileOutcome, FileResult},
+    throttle::Throttle,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use reqwest::{header::RANGE, Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc,
+    task::JoinSet,
+    time::{timeout, Duration, Instant},
+};
+
+/// Size of the blocks requested from peers (the de facto standard).
+const BLOCK_SIZE: u64 = 16 * 1024;
+/// Block requests kept in flight per peer.
+const PIPELINE_DEPTH: usize = 5;
+/// Peers downloaded from at the same time.
+const MAX_ACTIVE_PEERS: usize = 8;
+/// Peers taken from the trackers in total.
+const MAX_KNOWN_PEERS: usize = 50;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// A peer that sends no block (or keeps us choked) this long is dropped.
+const PEER_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Pieces failing their hash check before a peer is dropped.
+const MAX_BAD_PIECES: usize = 3;
+/// Largest peer message accepted (a block plus header, or a large bitfield).
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+/// Port announced to trackers. Nothing listens on it: the client only downloads.
+const ANNOUNCE_PORT: u16 = 6881;
+
+// --- Bencode ---
+
+/// A decoded bencode value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    /// Decodes a complete value; trailing bytes are ignored.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        Ok(decode_at(data, 0, 0)?.0)
+    }
+
+    /// Canonical encoding (dictionary keys sorted).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(n) => out.extend_from_slice(format!("i{}e", n).as_bytes()),
+            Bencode::Bytes(bytes) => {
+                out.extend_from_slice(format!("{}:", bytes.len()).as_bytes());
+                out.extend_from_slice(bytes);
+            }
+            Bencode::List(items) => {
+                out.push(b'l');
+                items.iter().for_each(|item| item.encode_into(out));
+                out.push(b'e');
+            }
+            Bencode::Dict(entries) => {
+                out.push(b'd');
+                for (key, value) in entries {
+                    Bencode::Bytes(key.clone()).encode_into(out);
+                    value.encode_into(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Bencode> {
+        match self {
+            Bencode::Dict(entries) => entries.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Bencode::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+
+    pub fn as_list(&self) -> Option<&[Bencode]> {
+        match self {
+            Bencode::List(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the value starting at `pos`, returning it and the position after it.
+fn decode_at(data: &[u8], pos: usize, depth: usize) -> Result<(Bencode, usize)> {
+    if depth > 64 {
+        bail!("Bencode nested too deeply");
+    }
+    match data.get(pos) {
+        Some(b'i') => {
+            let end = find(data, pos + 1, b'e')?;
+            let n = std::str::from_utf8(&data[pos + 1..end])?.parse().context("Invalid bencode integer")?;
+            Ok((Bencode::Int(n), end + 1))
+        }
+        Some(b'l') => {
+            let (mut items, mut pos) = (Vec::new(), pos + 1);
+            while data.get(pos) != Some(&b'e') {
+                let (item, next) = decode_at(data, pos, depth + 1)?;
+                items.push(item);
+                pos = next;
+            }
+            Ok((Bencode::List(items), pos + 1))
+        }
+        Some(b'd') => {
+            let (mut entries, mut pos) = (BTreeMap::new(), pos + 1);
+            while data.get(pos) != Some(&b'e') {
+                let (Bencode::Bytes(key), next) = decode_at(data, pos, depth + 1)? else { bail!("Bencode dictionary key is not a string") };
+                let (value, next) = decode_at(data, next, depth + 1)?;
+                entries.insert(key, value);
+                pos = next;
+            }
+            Ok((Bencode::Dict(entries), pos + 1))
+        }
+        Some(b'0'..=b'9') => {
+            let colon = find(data, pos, b':')?;
+            let len: usize = std::str::from_utf8(&data[pos..colon])?.parse().context("Invalid bencode string length")?;
+            let end = (colon + 1).checked_add(len).filter(|&end| end <= data.len()).context("Bencode string runs past the end")?;
+            Ok((Bencode::Bytes(data[colon + 1..end].to_vec()), end))
+        }
+        Some(c) => bail!("Unexpected byte {:?} in bencode", *c as char),
+        None => bail!("Bencode ends unexpectedly"),
+    }
+}
+
+fn find(data: &[u8], from: usize, byte: u8) -> Result<usize> {
+    data.get(from..).and_then(|rest| rest.iter().position(|&b| b == byte)).map(|i| from + i).context("Bencode ends unexpectedly")
+}
+
+/// Byte range of the top-level dictionary's `key` value, as it appears in `data`.
+fn raw_value_span(data: &[u8], key: &[u8]) -> Result<(usize, usize)> {
+    if data.first() != Some(&b'd') {
+        bail!("Torrent is not a bencode dictionary");
+    }
+    let mut pos = 1;
+    while data.get(pos) != Some(&b'e') {
+        let (entry_key, value_start) = decode_at(data, pos, 1)?;
+        let (_, value_end) = decode_at(data, value_start, 1)?;
+        if entry_key.as_bytes() == Some(key) {
+            return Ok((value_start, value_end));
+        }
+        pos = value_end;
+    }
+    bail!("Torrent has no '{}' entry", String::from_utf8_lossy(key))
+}
+
+// --- SHA-1 (piece and info hashes) ---
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(chunk.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (out, word) in digest.chunks_mut(4).zip(h) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+// --- SHA-256 (v2 block and merkle hashes) ---
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+// --- Metainfo ---
+
+/// A file inside a torrent, in torrent order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentFile {
+    /// Path relative to the item directory, `/`-separated.
+    pub path: String,
+    pub length: u64,
+    /// Position of the file's first byte in the torrent's byte stream.
+    pub offset: u64,
+    /// BEP 47 padding file: all zeros, never written to disk.
+    pub padding: bool,
+}
+
+/// The parts of a `.torrent` file the downloader uses.
+#[derive(Debug, Clone)]
+pub struct Metainfo {
+    pub info_hash: [u8; 20],
+    pub name: String,
+    pub piece_length: u64,
+    pub pieces: Vec<[u8; 20]>,
+    pub files: Vec<TorrentFile>,
+    /// HTTP(S) tracker announce URLs (UDP trackers aren't supported).
+    pub trackers: Vec<String>,
+}
+
+impl Metainfo {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let root = Bencode::decode(data).context("Invalid torrent file")?;
+        let (start, end) = raw_value_span(data, b"info")?;
+        let info = root.get("info").context("Torrent has no info dictionary")?;
+
+        let name = info.get("name").and_then(Bencode::as_str).context("Torrent has no name")?.to_string();
+        let piece_length = info.get("piece length").and_then(Bencode::as_int).filter(|&n| n > 0).context("Torrent has no piece length")? as u64;
+        let piece_hashes = info.get("pieces").and_then(Bencode::as_bytes).context("Torrent has no piece hashes")?;
+        if piece_hashes.len() % 20 != 0 {
+            bail!("Torrent piece hashes are truncated");
+        }
+        let pieces = piece_hashes.chunks(20).map(|hash| hash.try_into().expect("chunks of 20")).collect();
+
+        let mut files = Vec::new();
+        match info.get("files").and_then(Bencode::as_list) {
+            Some(entries) => {
+                let mut offset = 0;
+                for entry in entries {
+                    let length = entry.get("length").and_then(Bencode::as_int).filter(|&n| n >= 0).context("Torrent file without a length")? as u64;
+                    let segments: Vec<&str> = entry
+                        .get("path")
+                        .and_then(Bencode::as_list)
+                        .context("Torrent file without a path")?
+                        .iter()
+                        .map(|segment| segment.as_str().context("Torrent file path is not UTF-8"))
+                        .collect::<Result<_>>()?;
+                    let padding = entry.get("attr").and_then(Bencode::as_str).is_some_and(|attr| attr.contains('p'));
+                    files.push(TorrentFile { path: segments.join("/"), length, offset, padding });
+                    offset += length;
+                }
+            }
+            None => {
+                let length = info.get("length").and_then(Bencode::as_int).filter(|&n| n >= 0).context("Torrent has no length")? as u64;
+                files.push(TorrentFile { path: name.clone(), length, offset: 0, padding: false });
+            }
+        }
+        let meta = Self { info_hash: sha1(&data[start..end]), name, piece_length, pieces, files, trackers: trackers(&root) };
+        if meta.total_length().div_ceil(piece_length) != meta.pieces.len() as u64 {
+            bail!("Torrent piece count doesn't match its length");
+        }
+        Ok(meta)
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.files.iter().map(|f| f.length).sum()
+    }
+
+    /// Length of piece `index` (the last one may be short).
+    pub fn piece_size(&self, index: usize) -> u64 {
+        let start = index as u64 * self.piece_length;
+        self.piece_length.min(self.total_length() - start)
+    }
+
+    /// Parts of files covering piece `index`: (file index, offset in file, length).
+    fn piece_segments(&self, index: usize) -> Vec<(usize, u64, u64)> {
+        let start = index as u64 * self.piece_length;
+        let end = start + self.piece_size(index);
+        self.files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.length > 0 && f.offset < end && f.offset + f.length > start)
+            .map(|(i, f)| {
+                let from = start.max(f.offset);
+                let to = end.min(f.offset + f.length);
+                (i, from - f.offset, to - from)
+            })
+            .collect()
+    }
+}
+
+/// HTTP(S) trackers from `announce` and `announce-list`, without duplicates.
+fn trackers(root: &Bencode) -> Vec<String> {
+    let mut urls: Vec<String> = root.get("announce").and_then(Bencode::as_str).map(str::to_string).into_iter().collect();
+    for tier in root.get("announce-list").and_then(Bencode::as_list).unwrap_or_default() {
+        urls.extend(tier.as_list().unwrap_or_default().iter().filter_map(Bencode::as_str).map(str::to_string));
+    }
+    let mut unique = Vec::new();
+    for url in urls {
+        if url.starts_with("http") && !unique.contains(&url) {
+            unique.push(url);
+        }
+    }
+    unique
+}
+
+// --- Trackers ---
+
+/// Asks each tracker for peers. Tracker failures are logged and skipped.
+async fn discover_peers(client: &Client, meta: &Metainfo, peer_id: &[u8; 20]) -> Vec<SocketAddr> {
+    let mut peers = Vec::new();
+    for tracker in &meta.trackers {
+        match announce(client, tracker, meta, peer_id).await {
+            Ok(found) => {
+                debug!("Tracker {} returned {} peer(s)", tracker, found.len());
+                peers.extend(found.into_iter().filter(|p| !peers.contains(p)).collect::<Vec<_>>());
+            }
+            Err(e) => warn!("Tracker {} failed: {:#}", tracker, e),
+        }
+        if peers.len() >= MAX_KNOWN_PEERS {
+            break;
+        }
+    }
+    peers.truncate(MAX_KNOWN_PEERS);
+    peers
+}
+
+async fn announce(client: &Client, tracker: &str, meta: &Metainfo, peer_id: &[u8; 20]) -> Result<Vec<SocketAddr>> {
+    let url = format!(
+        "{}{}info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&compact=1&event=started",
+        tracker,
+        if tracker.contains('?') { '&' } else { '?' },
+        percent_encode(&meta.info_hash),
+        percent_encode(peer_id),
+        ANNOUNCE_PORT,
+        meta.total_length()
+    );
+    let body = client.get(&url).timeout(Duration::from_secs(15)).send().await?.error_for_status()?.bytes().await?;
+    let reply = Bencode::decode(&body).context("Invalid tracker response")?;
+    if let Some(reason) = reply.get("failure reason").and_then(Bencode::as_str) {
+        bail!("Tracker refused the announce: {}", reason);
+    }
+    Ok(match reply.get("peers") {
+        Some(Bencode::Bytes(compact)) => compact
+            .chunks_exact(6)
+            .map(|p| SocketAddr::from((Ipv4Addr::new(p[0], p[1], p[2], p[3]), u16::from_be_bytes([p[4], p[5]]))))
+            .collect(),
+        Some(Bencode::List(entries)) => entries
+            .iter()
+            .filter_map(|entry| {
+                let ip = entry.get("ip")?.as_str()?.parse().ok()?;
+                let port = u16::try_from(entry.get("port")?.as_int()?).ok()?;
+                Some(SocketAddr::new(ip, port))
+            })
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+fn percent_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) { (b as char).to_string() } else { format!("%{:02X}", b) })
+        .collect()
+}
+
+/// Random peer id in Azureus style (`-AV0100-` + 12 random bytes).
+fn new_peer_id() -> [u8; 20] {
+    let mut id = *b"-AV0100-000000000000";
+    for chunk in id[8..].chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish().to_be_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+    id
+}
+
+// --- Download state ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PieceState {
+    Missing,
+    InProgress,
+    Done,
+}
+
+/// A torrent being downloaded into an item directory.
+///
+/// Files are written as `<name>.part` (preallocated, written out of order) and renamed
+/// into place once every piece has been verified. Files already complete on disk are
+/// used as they are, and their pieces are hash-checked instead of fetched again.
+pub struct Torrent {
+    pub meta: Metainfo,
+    /// Final location of each file.
+    targets: Vec<PathBuf>,
+    /// Where each file is read and written during the download.
+    storage: Vec<PathBuf>,
+    pieces: Mutex<Vec<PieceState>>,
+    progress_tx: mpsc::Sender<DownloadProgress>,
+    /// Download speed cap, charged for every verified piece.
+    throttle: Arc<Throttle>,
+}
+
+impl Torrent {
+    /// Prepares the files at `targets` (one per torrent file) and checks which pieces
+    /// they already hold.
+    pub async fn open(meta: Metainfo, targets: Vec<PathBuf>, progress_tx: mpsc::Sender<DownloadProgress>, throttle: Arc<Throttle>) -> Result<Self> {
+        let mut storage = Vec::with_capacity(targets.len());
+        let mut fresh = Vec::with_capacity(targets.len()); // Created empty just now: nothing to verify
+        for (file, target) in meta.files.iter().zip(&targets) {
+            let complete = fs::metadata(target).await.is_ok_and(|m| m.is_file() && m.len() == file.length);
+            if complete || file.padding {
+                storage.push(target.clone());
+                fresh.push(file.padding);
+                continue;
+            }
+            let part = paths::part_path(target);
+            if let Some(dir) = part.parent() {
+                fs::create_dir_all(dir).await.context(format!("Failed to create directory '{}'", dir.display()))?;
+            }
+            let existed = fs::metadata(&part).await.is_ok();
+            let handle = OpenOptions::new().create(true).write(true).truncate(false).open(&part).await.context(format!("Failed to open '{}'", part.display()))?;
+            handle.set_len(file.length).await.context(format!("Failed to allocate '{}'", part.display()))?;
+            storage.push(part);
+            fresh.push(!existed);
+        }
+
+        let torrent = Self { pieces: Mutex::new(vec![PieceState::Missing; meta.pieces.len()]), meta, targets, storage, progress_tx, throttle };
+        let mut present = 0;
+        for index in 0..torrent.meta.pieces.len() {
+            let segments = torrent.meta.piece_segments(index);
+            if segments.iter().all(|(file, _, _)| fresh[*file]) {
+                continue;
+            }
+            if torrent.read_piece(index).await.is_ok_and(|data| sha1(&data) == torrent.meta.pieces[index]) {
+                torrent.pieces.lock().unwrap()[index] = PieceState::Done;
+                present += 1;
+            }
+        }
+        if present > 0 {
+            info!("{} of {} pieces of '{}' are already on disk", present, torrent.meta.pieces.len(), torrent.meta.name);
+        }
+        Ok(torrent)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pieces.lock().unwrap().iter().all(|&state| state == PieceState::Done)
+    }
+
+    fn missing_pieces(&self) -> Vec<usize> {
+        let pieces = self.pieces.lock().unwrap();
+        (0..pieces.len()).filter(|&i| pieces[i] != PieceState::Done).collect()
+    }
+
+    /// Claims the first missing piece `has` reports available.
+    fn claim_piece(&self, has: &[bool]) -> Option<usize> {
+        let mut pieces = self.pieces.lock().unwrap();
+        let index = (0..pieces.len()).find(|&i| pieces[i] == PieceState::Missing && has.get(i).copied().unwrap_or(false))?;
+        pieces[index] = PieceState::InProgress;
+        Some(index)
+    }
+
+    fn release_piece(&self, index: usize) {
+        let mut pieces = self.pieces.lock().unwrap();
+        if pieces[index] == PieceState::InProgress {
+            pieces[index] = PieceState::Missing;
+        }
+    }
+
+    async fn read_piece(&self, index: usize) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.meta.piece_size(index) as usize);
+        for (file, offset, length) in self.meta.piece_segments(index) {
+            let start = data.len();
+            data.resize(start + length as usize, 0);
+            if self.meta.files[file].padding {
+                continue;
+            }
+            let mut handle = fs::File::open(&self.storage[file]).await?;
+            handle.seek(std::io::SeekFrom::Start(offset)).await?;
+            handle.read_exact(&mut data[start..]).await?;
+        }
+        Ok(data)
+    }
+
+    /// Verifies `data` against the piece hash and writes it. Returns `false` (writing
+    /// nothing) if the hash doesn't match.
+    async fn store_piece(&self, index: usize, data: &[u8]) -> Result<bool> {
+        if sha1(data) != self.meta.pieces[index] {
+            self.release_piece(index);
+            return Ok(false);
+        }
+        let mut written = 0;
+        for (file, offset, length) in self.meta.piece_segments(index) {
+            let chunk = &data[written..written + length as usize];
+            written += length as usize;
+            if self.meta.files[file].padding {
+                continue;
+            }
+            let mut handle = OpenOptions::new().write(true).open(&self.storage[file]).await?;
+            handle.seek(std::io::SeekFrom::Start(offset)).await?;
+            handle.write_all(chunk).await?;
+        }
+        self.pieces.lock().unwrap()[index] = PieceState::Done;
+        self.throttle.consume(data.len() as u64).await;
+        let _ = self.progress_tx.send(DownloadProgress::BytesDownloaded(data.len() as u64)).await;
+        Ok(true)
+    }
+
+    /// Downloads from `peers` (up to [`MAX_ACTIVE_PEERS`] at once) until every piece is
+    /// done or no peer is left. Returns the number of pieces still missing.
+    pub async fn fetch_from_peers(self: &Arc<Self>, peers: Vec<SocketAddr>) -> usize {
+        let peer_id = new_peer_id();
+        let mut waiting = peers.into_iter();
+        let mut sessions = JoinSet::new();
+        loop {
+            while sessions.len() < MAX_ACTIVE_PEERS && !self.is_complete() {
+                let Some(addr) = waiting.next() else { break };
+                let torrent = Arc::clone(self);
+                sessions.spawn(async move {
+                    if let Err(e) = torrent.peer_session(addr, peer_id).await {
+                        debug!("Peer {} dropped: {:#}", addr, e);
+                    }
+                });
+            }
+            if sessions.join_next().await.is_none() {
+                break;
+            }
+        }
+        self.missing_pieces().len()
+    }
+
+    /// Downloads pieces from one peer until it has nothing more we need.
+    async fn peer_session(&self, addr: SocketAddr, peer_id: [u8; 20]) -> Result<()> {
+        let mut stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await.context("Connect timed out")??;
+        let mut handshake = Vec::with_capacity(68);
+        handshake.push(19);
+        handshake.extend_from_slice(b"BitTorrent protocol");
+        handshake.extend_from_slice(&[0; 8]);
+        handshake.extend_from_slice(&self.meta.info_hash);
+        handshake.extend_from_slice(&peer_id);
+        stream.write_all(&handshake).await?;
+        let mut reply = [0u8; 68];
+        timeout(PEER_STALL_TIMEOUT, stream.read_exact(&mut reply)).await.context("Handshake timed out")??;
+        if reply[..20] != handshake[..20] || reply[28..48] != self.meta.info_hash {
+            bail!("Peer answered with a different protocol or torrent");
+        }
+        send_message(&mut stream, 2, &[]).await?; // interested
+
+        let mut session = PeerSession { has: vec![false; self.meta.pieces.len()], choked: true, piece: None, bad_pieces: 0 };
+        let result = self.run_peer(&mut stream, &mut session).await;
+        if let Some(current) = session.piece {
+            self.release_piece(current.index);
+        }
+        result
+    }
+
+    async fn run_peer(&self, stream: &mut TcpStream, session: &mut PeerSession) -> Result<()> {
+        let mut last_progress = Instant::now();
+        loop {
+            if !session.choked && session.piece.is_none() {
+                match self.claim_piece(&session.has) {
+                    Some(index) => session.piece = Some(PieceBuffer::new(index, self.meta.piece_size(index))),
+                    None => return Ok(()), // Nothing we still need; HTTP covers pieces other peers drop
+                }
+            }
+            if let (false, Some(piece)) = (session.choked, session.piece.as_mut()) {
+                while piece.in_flight < PIPELINE_DEPTH && piece.next_request < piece.data.len() as u64 {
+                    let length = BLOCK_SIZE.min(piece.data.len() as u64 - piece.next_request);
+                    let mut payload = Vec::with_capacity(12);
+                    payload.extend_from_slice(&(piece.index as u32).to_be_bytes());
+                    payload.extend_from_slice(&(piece.next_request as u32).to_be_bytes());
+                    payload.extend_from_slice(&(length as u32).to_be_bytes());
+                    send_message(stream, 6, &payload).await?; // request
+                    piece.next_request += length;
+                    piece.in_flight += 1;
+                }
+            }
+
+            let remaining = PEER_STALL_TIMEOUT.checked_sub(last_progress.elapsed()).context("Peer stalled")?;
+            let (id, payload) = timeout(remaining, read_message(stream)).await.context("Peer stalled")??;
+            match id {
+                None => {} // keep-alive
+                Some(0) => {
+                    // choke: outstanding requests are dropped, so start the piece over later
+                    session.choked = true;
+                    if let Some(piece) = session.piece.take() {
+                        self.release_piece(piece.index);
+                    }
+                }
+                Some(1) => {
+                    session.choked = false;
+                    last_progress = Instant::now();
+                }
+                Some(4) if payload.len() == 4 => {
+                    let index = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+                    if let Some(has) = session.has.get_mut(index) {
+                        *has = true;
+                    }
+                }
+                Some(5) => {
+                    for (index, has) in session.has.iter_mut().enumerate() {
+                        *has = payload.get(index / 8).is_some_and(|byte| byte & (0x80 >> (index % 8)) != 0);
+                    }
+                }
+                Some(7) if payload.len() >= 8 => {
+                    let index = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+                    let begin = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]) as usize;
+                    let Some(piece) = session.piece.as_mut().filter(|p| p.index == index) else { continue };
+                    let block = &payload[8..];
+                    let end = begin.checked_add(block.len()).filter(|&end| end <= piece.data.len()).context("Peer sent a block outside the piece")?;
+                    piece.data[begin..end].copy_from_slice(block);
+                    piece.received += block.len() as u64;
+                    piece.in_flight = piece.in_flight.saturating_sub(1);
+                    last_progress = Instant::now();
+                    if piece.received >= piece.data.len() as u64 {
+                        let piece = session.piece.take().expect("piece is set");
+                        if !self.store_piece(piece.index, &piece.data).await? {
+                            session.bad_pieces += 1;
+                            warn!("Piece {} of '{}' failed its hash check", piece.index, self.meta.name);
+                            if session.bad_pieces >= MAX_BAD_PIECES {
+                                bail!("Too many corrupt pieces");
+                            }
+                        }
+                    }
+                }
+                Some(_) => {} // Messages a download-only client doesn't act on
+            }
+        }
+    }
+
+    /// Fetches the pieces no peer delivered from the archive.org download URLs of the
+    /// item's files (HTTP Range requests), verifying each against its hash.
+    pub async fn fetch_missing_over_http(&self, ctx: &DownloadContext, item_id: &str) -> Result<()> {
+        let missing = self.missing_pieces();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        info!("Fetching {} piece(s) of '{}' over HTTP", missing.len(), item_id);
+        let _ = self.progress_tx.send(DownloadProgress::Status(format!("Fetching {} missing piece(s) of {} over HTTP", missing.len(), item_id))).await;
+        let _slot = ctx.scheduler.acquire(ctx.ticket).await;
+        for index in missing {
+            let mut data = Vec::with_capacity(self.meta.piece_size(index) as usize);
+            for (file, offset, length) in self.meta.piece_segments(index) {
+                let file = &self.meta.files[file];
+                if file.padding {
+                    data.resize(data.len() + length as usize, 0);
+                    continue;
+                }
+                ctx.rate_limiter.until_ready().await;
+                data.extend_from_slice(&fetch_range(&ctx.client, &archive_api::download_url(item_id, &file.path), offset, length).await?);
+            }
+            if !self.store_piece(index, &data).await? {
+                bail!("Piece {} of '{}' from archive.org doesn't match the torrent (the item changed since the torrent was made?)", index, item_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves completed `.part` files into place and reports every file. Call once all
+    /// pieces are done.
+    pub async fn finish(&self, item_id: &str) -> Result<()> {
+        for ((file, storage), target) in self.meta.files.iter().zip(&self.storage).zip(&self.targets) {
+            if file.padding {
+                continue;
+            }
+            let outcome = if storage != target {
+                fs::rename(storage, target).await.context(format!("Failed to move '{}' into place", storage.display()))?;
+                FileOutcome::Downloaded
+            } else {
+                FileOutcome::AlreadyPresent
+            };
+            let _ = self.progress_tx.send(DownloadProgress::FileCompleted(file.path.clone())).await;
+            let result = FileResult { item_id: item_id.to_string(), name: file.path.clone(), outcome, bytes: file.length, size_verified: Some(true) };
+            let _ = self.progress_tx.send(DownloadProgress::FileFinished(result)).await;
+        }
+        Ok(())
+    }
+}
+
+struct PeerSession {
+    /// Pieces the peer has announced.
+    has: Vec<bool>,
+    choked: bool,
+    piece: Option<PieceBuffer>,
+    bad_pieces: usize,
+}
+
+/// A piece being assembled from blocks.
+struct PieceBuffer {
+    index: usize,
+    data: Vec<u8>,
+    next_request: u64,
+    received: u64,
+    in_flight: usize,
+}
+
+impl PieceBuffer {
+    fn new(index: usize, size: u64) -> Self {
+        Self { index, data: vec![0; size as usize], next_request: 0, received: 0, in_flight: 0 }
+    }
+}
+
+async fn send_message(stream: &mut TcpStream, id: u8, payload: &[u8]) -> Result<()> {
+    let mut message = Vec::with_capacity(5 + payload.len());
+    message.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+    message.push(id);
+    message.extend_from_slice(payload);
+    stream.write_all(&message).await?;
+    Ok(())
+}
+
+/// Reads one peer message: `(None, _)` for a keep-alive, otherwise its id and payload.
+async fn read_message(stream: &mut TcpStream) -> Result<(Option<u8>, Vec<u8>)> {
+    let len = stream.read_u32().await? as usize;
+    if len == 0 {
+        return Ok((None, Vec::new()));
+    }
+    if len > MAX_MESSAGE_LEN {
+        bail!("Peer message of {} bytes is too large", len);
+    }
+    let mut message = vec![0; len];
+    stream.read_exact(&mut message).await?;
+    Ok((Some(message[0]), message.split_off(1)))
+}
+
+async fn fetch_range(client: &Client, url: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let response = client.get(url).header(RANGE, format!("bytes={}-{}", offset, offset + length - 1)).send().await?;
+    let body = match response.status() {
+        StatusCode::PARTIAL_CONTENT => response.bytes().await?.to_vec(),
+        // Server ignored the range: cut the part out of the whole file
+        StatusCode::OK => response.bytes().await?.get(offset as usize..(offset + length) as usize).map(<[u8]>::to_vec).unwrap_or_default(),
+        status => bail!("HTTP {} for {}", status, url),
+    };
+    if body.len() as u64 != length {
+        return Err(anyhow!("Expected {} bytes from {}, got {}", length, url, body.len()));
+    }
+    Ok(body)
+}
+
+/// Downloads the content of the torrent at `torrent_path` into the item directory, from
+/// the swarm first and over HTTP for whatever the swarm didn't provide. Files land where
+/// Direct mode would put them.
+pub async fn download_item_content(ctx: &DownloadContext, collection_id: Option<&str>, item_id: &str, torrent_path: &Path) -> Result<()> {
+    let data = fs::read(torrent_path).await.context(format!("Failed to read '{}'", torrent_path.display()))?;
+    let meta = Metainfo::parse(&data).context(format!("Failed to parse '{}'", torrent_path.display()))?;
+    let targets = meta.files.iter().map(|f| paths::file_path(&ctx.base_dir, collection_id, item_id, &f.path, &ctx.path_policy)).collect();
+    let _ = ctx.progress_tx.send(DownloadProgress::ItemFileCount(meta.files.iter().filter(|f| !f.padding).count())).await;
+
+    let torrent = Arc::new(Torrent::open(meta, targets, ctx.progress_tx.clone(), Arc::clone(&ctx.throttle)).await?);
+    if !torrent.is_complete() {
+        let peers = discover_peers(&ctx.client, &torrent.meta, &new_peer_id()).await;
+        info!("Downloading '{}' over BitTorrent from {} peer(s)", item_id, peers.len());
+        let _ = ctx.progress_tx.send(DownloadProgress::Status(format!("BitTorrent: {} peer(s) for {}", peers.len(), item_id))).await;
+        let missing = torrent.fetch_from_peers(peers).await;
+        if missing > 0 {
+            torrent.fetch_missing_over_http(ctx, item_id).await?;
+        }
+    }
+    torrent.finish(item_id).await
+}
+
+/// Builds a multi-file torrent of `files` (path, content) with `piece_length`, for the
+/// mock server and tests.
+#[cfg(any(test, feature = "mock"))]
+pub fn build_torrent(name: &str, files: &[(&str, Vec<u8>)], piece_length: usize) -> Vec<u8> {
+    let stream: Vec<u8> = files.iter().flat_map(|(_, content)| content.clone()).collect();
+    let pieces: Vec<u8> = stream.chunks(piece_length).flat_map(sha1).collect();
+    let file_list = files
+        .iter()
+        .map(|(path, content)| {
+            let path = path.split('/').map(|s| Bencode::Bytes(s.as_bytes().to_vec())).collect();
+            Bencode::Dict(BTreeMap::from([
+                (b"length".to_vec(), Bencode::Int(content.len() as i64)),
+                (b"path".to_vec(), Bencode::List(path)),
+            ]))
+        })
+        .collect();
+    let info = Bencode::Dict(BTreeMap::from([
+        (b"files".to_vec(), Bencode::List(file_list)),
+        (b"name".to_vec(), Bencode::Bytes(name.as_bytes().to_vec())),
+        (b"piece length".to_vec(), Bencode::Int(piece_length as i64)),
+        (b"pieces".to_vec(), Bencode::Bytes(pieces)),
+    ]));
+    Bencode::Dict(BTreeMap::from([(b"info".to_vec(), info)])).encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_sha1_known_digests() {
+        let hex = |d: [u8; 20]| d.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hex(sha1(&[b'a'; 1000])), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+    }
+
+    #[test]
+    fn test_sha256_known_digests() {
+        let hex = |d: [u8; 32]| d.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(hex(sha256(long)), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+    }
+
+    #[test]
+    fn test_metainfo_parse_maps_pieces_to_files() {
+        let data = build_torrent("item", &[("a.bin", vec![1; 5]), ("sub/b.bin", vec![2; 7])], 4);
+        let meta = Metainfo::parse(&data).unwrap();
+        assert_eq!(meta.name, "item");
+        assert_eq!(meta.pieces.len(), 3);
+        assert_eq!(meta.files[1], TorrentFile { path: "sub/b.bin".to_string(), length: 7, offset: 5, padding: false });
+        assert_eq!(meta.piece_segments(1), vec![(0, 4, 1), (1, 0, 3)]);
+        assert_eq!(meta.piece_size(2), 4);
+        let (start, end) = raw_value_span(&data, b"info").unwrap();
+        assert_eq!(Bencode::decode(&data[start..end]).unwrap().encode(), data[start..end]);
+        assert!(Metainfo::parse(b"d4:infod4:name1:xee").is_err());
+    }
+
+    /// Serves `content` (the torrent's byte stream) to one downloader as a seeding peer.
+    async fn seed(listener: TcpListener, meta: Metainfo, content: Vec<u8>) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut handshake = [0u8; 68];
+        stream.read_exact(&mut handshake).await.unwrap();
+        stream.write_all(&handshake).await.unwrap(); // Same protocol and info hash back
+        let bitfield = vec![0xFF; meta.pieces.len().div_ceil(8)];
+        send_message(&mut stream, 5, &bitfield).await.unwrap();
+        send_message(&mut stream, 1, &[]).await.unwrap();
+        while let Ok((id, payload)) = read_message(&mut stream).await {
+            if id != Some(6) {
+                continue;
+            }
+            let field = |i: usize| u32::from_be_bytes(payload[i..i + 4].try_into().unwrap()) as usize;
+            let (index, begin, length) = (field(0), field(4), field(8));
+            let start = index * meta.piece_length as usize + begin;
+            let mut reply = payload[..8].to_vec();
+            reply.extend_from_slice(&content[start..start + length]);
+            send_message(&mut stream, 7, &reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_from_peer_and_resume_from_disk() {
+        let files = [("track.flac", (0..40_000u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>()), ("notes.txt", b"liner notes".to_vec())];
+        let content: Vec<u8> = files.iter().flat_map(|(_, c)| c.clone()).collect();
+        let meta = Metainfo::parse(&build_torrent("item", &files, 32 * 1024)).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let targets: Vec<PathBuf> = files.iter().map(|(name, _)| dir.path().join(name)).collect();
+        let (progress_tx, mut progress_rx) = mpsc::channel(100);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let seeder = tokio::spawn(seed(listener, meta.clone(), content.clone()));
+
+        let torrent = Arc::new(Torrent::open(meta.clone(), targets.clone(), progress_tx.clone(), Arc::new(Throttle::new(0))).await.unwrap());
+        assert!(paths::part_path(&targets[0]).exists());
+        assert_eq!(torrent.fetch_from_peers(vec![addr]).await, 0);
+        torrent.finish("item").await.unwrap();
+        seeder.abort();
+        for ((_, expected), target) in files.iter().zip(&targets) {
+            assert_eq!(&std::fs::read(target).unwrap(), expected);
+        }
+        assert!(!paths::part_path(&targets[0]).exists());
+        let mut downloaded = 0;
+        while let Ok(progress) = progress_rx.try_recv() {
+            if let DownloadProgress::BytesDownloaded(n) = progress {
+                downloaded += n;
+            }
+        }
+        assert_eq!(downloaded, content.len() as u64);
+
+        // Complete files are recognised, nothing is fetched again
+        let reopened = Torrent::open(meta, targets, progress_tx, Arc::new(Throttle::new(0))).await.unwrap();
+        assert!(reopened.is_complete());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::settings::Settings;
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::future::BoxFuture;
+use log::{debug, info};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{fmt, path::Path, sync::Arc, sync::Mutex};
+
+/// Default Transmission RPC endpoint (transmission-daemon on this machine).
+pub const DEFAULT_TRANSMISSION_URL: &str = "http://localhost:9091/transmission/rpc";
+/// Header carrying Transmission's CSRF token; a 409 response hands out a fresh one.
+const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+
+/// What happens to `.torrent` files downloaded in TorrentOnly mode.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TorrentBackendKind {
+    /// Only save the `.torrent` file.
+    #[default]
+    SaveFile,
+    /// Also add it to a Transmission daemon over RPC.
+    Transmission,
+}
+
+impl fmt::Display for TorrentBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorrentBackendKind::SaveFile => write!(f, "None (save .torrent)"),
+            TorrentBackendKind::Transmission => write!(f, "Transmission"),
+        }
+    }
+}
+
+impl TorrentBackendKind {
+    /// Next backend, wrapping around.
+    pub fn cycle(self) -> Self {
+        match self {
+            TorrentBackendKind::SaveFile => TorrentBackendKind::Transmission,
+            TorrentBackendKind::Transmission => TorrentBackendKind::SaveFile,
+        }
+    }
+}
+
+/// A torrent client daemon that TorrentOnly downloads are handed off to.
+pub trait TorrentBackend: Send + Sync {
+    /// Name shown in status messages.
+    fn name(&self) -> &'static str;
+
+    /// Adds the torrent at `torrent_path`, downloading its content into `download_dir`.
+    /// Torrents the daemon already has count as added.
+    fn add_torrent<'a>(&'a self, torrent_path: &'a Path, download_dir: &'a Path) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Builds the backend selected in the settings, or `None` when torrents are only saved.
+pub fn from_settings(settings: &Settings, client: &Client) -> Option<Arc<dyn TorrentBackend>> {
+    match settings.torrent_backend {
+        TorrentBackendKind::SaveFile => None,
+        TorrentBackendKind::Transmission => {
+            let credentials = settings.transmission_username.clone().map(|user| (user, settings.transmission_password.clone()));
+            Some(Arc::new(TransmissionBackend::new(client.clone(), settings.transmission_url.clone(), credentials)))
+        }
+    }
+}
+
+/// Transmission's JSON-RPC interface (`torrent-add`).
+pub struct TransmissionBackend {
+    client: Client,
+    url: String,
+    /// Username and password for RPC authentication, if enabled on the daemon.
+    credentials: Option<(String, Option<String>)>,
+    /// Session id from the last 409 response, reused until the daemon rotates it.
+    session_id: Mutex<Option<String>>,
+}
+
+impl TransmissionBackend {
+    pub fn new(client: Client, url: String, credentials: Option<(String, Option<String>)>) -> Self {
+        Self { client, url, credentials, session_id: Mutex::new(None) }
+    }
+
+    /// Sends one RPC call, fetching a session id first if the daemon asks for one.
+    async fn call(&self, body: &serde_json::Value) -> Result<serde_json::Value> {
+        for _ in 0..2 {
+            let mut request = self.client.post(&self.url).json(body);
+            if let Some(id) = self.session_id.lock().unwrap().clone() {
+                request = request.header(SESSION_ID_HEADER, id);
+            }
+            if let Some((user, password)) = &self.credentials {
+                request = request.basic_auth(user, password.as_deref());
+            }
+            let response = request.send().await.context(format!("Failed to reach Transmission at {}", self.url))?;
+            match response.status() {
+                StatusCode::CONFLICT => {
+                    let id = response.headers().get(SESSION_ID_HEADER).and_then(|v| v.to_str().ok()).map(str::to_string);
+                    debug!("Transmission handed out a new session id");
+                    *self.session_id.lock().unwrap() = Some(id.context("Transmission sent 409 without a session id")?);
+                }
+                StatusCode::UNAUTHORIZED => bail!("Transmission rejected the RPC username/password"),
+                status if !status.is_success() => bail!("Transmission RPC returned HTTP {}", status),
+                _ => return response.json().await.context("Failed to parse Transmission RPC response"),
+            }
+        }
+        Err(anyhow!("Transmission kept rejecting the session id"))
+    }
+}
+
+/// Reply of the `torrent-add` method.
+#[derive(Deserialize)]
+struct RpcReply {
+    result: String,
+}
+
+impl TorrentBackend for TransmissionBackend {
+    fn name(&self) -> &'static str {
+        "Transmission"
+    }
+
+    fn add_torrent<'a>(&'a self, torrent_path: &'a Path, download_dir: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let metainfo = tokio::fs::read(torrent_path).await.context(format!("Failed to read {}", torrent_path.display()))?;
+            // The file content is sent rather than its path, so the daemon may run elsewhere
+            let body = json!({
+                "method": "torrent-add",
+                "arguments": {
+                    "metainfo": base64_encode(&metainfo),
+                    "download-dir": download_dir.to_string_lossy(),
+                },
+            });
+            let reply: RpcReply = serde_json::from_value(self.call(&body).await?).context("Unexpected Transmission RPC response")?;
+            if reply.result != "success" {
+                bail!("Transmission refused {}: {}", torrent_path.display(), reply.result);
+            }
+            info!("Added {} to Transmission (download dir {})", torrent_path.display(), download_dir.display());
+            Ok(())
+        })
+    }
+}
+
+/// Standard base64 with padding, as Transmission expects for `metainfo`.
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"d8:announce"), "ZDg6YW5ub3VuY2U=");
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_transmission_backend_adds_torrent_mock() {
+        let base = crate::mock_server::shared_base_url();
+        let dir = tempfile::tempdir().unwrap();
+        let torrent = dir.path().join("item_archive.torrent");
+        std::fs::write(&torrent, b"d8:announce0:e").unwrap();
+
+        let backend = TransmissionBackend::new(Client::new(), format!("{}/transmission/rpc", base), None);
+        // The first call gets a 409 and retries with the session id it hands out
+        backend.add_torrent(&torrent, dir.path()).await.expect("Torrent should be added");
+        assert_eq!(backend.session_id.lock().unwrap().as_deref(), Some(crate::mock_server::MOCK_TRANSMISSION_SESSION));
+
+        std::fs::write(&torrent, b"not a torrent").unwrap();
+        let err = backend.add_torrent(&torrent, dir.path()).await.expect_err("Invalid torrents are refused");
+        assert!(err.to_string().contains("invalid or corrupt torrent file"), "{}", err);
+    }
+}
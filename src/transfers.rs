@@ -0,0 +1,128 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Files currently being transferred over HTTP, in the order they started; feeds the
+/// Downloads view ('D'). Torrent downloads only report aggregate bytes and aren't listed.
+#[derive(Debug, Default)]
+pub struct ActiveTransfers {
+    files: Vec<Transfer>,
+}
+
+#[derive(Debug)]
+struct Transfer {
+    /// `item_id/file name`, as sent with [`crate::app::DownloadProgress::FileBytes`].
+    key: Arc<str>,
+    total: Option<u64>,
+    done: u64,
+    /// Bytes already on disk when this attempt started (resumed `.part` file); not
+    /// counted towards the speed.
+    resumed: u64,
+    started: Instant,
+}
+
+/// One row of the Downloads table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferRow {
+    /// `item_id/file name`.
+    pub name: String,
+    pub item_id: String,
+    pub done: u64,
+    pub total: Option<u64>,
+    /// Bytes per second over this attempt.
+    pub speed: f64,
+    /// Time left at the current speed, if the size is known and bytes are flowing.
+    pub eta: Option<Duration>,
+}
+
+impl TransferRow {
+    /// Completed fraction in `0.0..=1.0`, if the size is known.
+    pub fn ratio(&self) -> Option<f64> {
+        self.total.filter(|&t| t > 0).map(|t| (self.done as f64 / t as f64).min(1.0))
+    }
+}
+
+/// Key identifying a file transfer in progress messages.
+pub fn transfer_key(item_id: &str, name: &str) -> Arc<str> {
+    format!("{}/{}", item_id, name).into()
+}
+
+impl ActiveTransfers {
+    /// Registers a transfer (or a retry of one) that already has `resumed` bytes on disk.
+    pub fn start(&mut self, key: Arc<str>, total: Option<u64>, resumed: u64, now: Instant) {
+        let transfer = Transfer { key, total, done: resumed, resumed, started: now };
+        match self.files.iter_mut().find(|t| t.key == transfer.key) {
+            Some(existing) => *existing = transfer,
+            None => self.files.push(transfer),
+        }
+    }
+
+    pub fn add(&mut self, key: &str, bytes: u64) {
+        if let Some(transfer) = self.files.iter_mut().find(|t| &*t.key == key) {
+            transfer.done += bytes;
+        }
+    }
+
+    /// Drops a transfer that ended, whatever the outcome.
+    pub fn finish(&mut self, key: &str) {
+        self.files.retain(|t| &*t.key != key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn rows(&self, now: Instant) -> Vec<TransferRow> {
+        self.files
+            .iter()
+            .map(|t| {
+                let elapsed = now.saturating_duration_since(t.started).as_secs_f64();
+                let speed = if elapsed > 0.0 { (t.done - t.resumed) as f64 / elapsed } else { 0.0 };
+                let eta = match t.total {
+                    Some(total) if speed > 0.0 => Some(Duration::from_secs_f64(total.saturating_sub(t.done) as f64 / speed)),
+                    _ => None,
+                };
+                let item_id = t.key.split_once('/').map_or(&*t.key, |(item, _)| item).to_string();
+                TransferRow { name: t.key.to_string(), item_id, done: t.done, total: t.total, speed, eta }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_report_progress_speed_and_eta() {
+        let start = Instant::now();
+        let mut transfers = ActiveTransfers::default();
+        transfers.start(transfer_key("item", "a.flac"), Some(1000), 200, start);
+        transfers.start(transfer_key("item", "b.txt"), None, 0, start);
+        transfers.add("item/a.flac", 400);
+        transfers.add("item/b.txt", 50);
+        transfers.add("item/unknown", 50); // Ignored
+
+        let rows = transfers.rows(start + Duration::from_secs(2));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name, "item/a.flac");
+        assert_eq!(rows[0].item_id, "item");
+        assert_eq!(rows[0].done, 600);
+        assert_eq!(rows[0].speed, 200.0, "Resumed bytes don't count towards the speed");
+        assert_eq!(rows[0].eta, Some(Duration::from_secs(2)));
+        assert_eq!(rows[0].ratio(), Some(0.6));
+        assert_eq!(rows[1].eta, None);
+        assert_eq!(rows[1].ratio(), None);
+
+        // A retry restarts the row in place
+        transfers.start(transfer_key("item", "a.flac"), Some(1000), 600, start + Duration::from_secs(2));
+        assert_eq!(transfers.rows(start + Duration::from_secs(2))[0].done, 600);
+        transfers.finish("item/a.flac");
+        assert_eq!(transfers.len(), 1);
+    }
+}
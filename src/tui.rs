@@ -1,11 +1,13 @@
-use crate::{app::App, event::EventHandler, ui};
+use crate::{app::App, event::EventHandler, perf::{self, Phase}, thumbnail::{self, Protocol}, ui};
 use anyhow::Result;
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    cursor::MoveTo,
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use ratatui::{backend::Backend, Terminal};
-use std::io;
+use log::warn;
+use ratatui::{backend::Backend, layout::Rect, Terminal};
+use std::io::{self, Write};
 
 /// Representation of a terminal user interface.
 ///
@@ -17,12 +19,17 @@ pub struct Tui<B: Backend> {
     terminal: Terminal<B>,
     /// Terminal event handler.
     pub events: EventHandler,
+    /// Cover art on screen: the item, where, and with which protocol.
+    cover_art: Option<(String, Rect, Protocol)>,
+    /// The escape drawing the last cover art (for an item and size), so images are only
+    /// converted once.
+    cover_art_escape: Option<((String, Rect), String)>,
 }
 
-impl<B: Backend> Tui<B> {
+impl<B: Backend + Write> Tui<B> {
     /// Constructs a new instance of [`Tui`].
     pub fn new(terminal: Terminal<B>, events: EventHandler) -> Self {
-        Self { terminal, events }
+        Self { terminal, events, cover_art: None, cover_art_escape: None }
     }
 
     /// Initializes the terminal interface.
@@ -30,7 +37,7 @@ impl<B: Backend> Tui<B> {
     /// It enables the raw mode and sets terminal properties.
     pub fn init(&mut self) -> Result<()> {
         terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
         self.terminal.hide_cursor()?;
         self.terminal.clear()?;
         Ok(())
@@ -41,7 +48,39 @@ impl<B: Backend> Tui<B> {
     /// [`Draw`]: tui::Terminal::draw
     /// [`rendering`]: crate::ui:render
     pub fn draw(&mut self, app: &mut App) -> Result<()> {
-        self.terminal.draw(|frame| ui::render(app, frame))?;
+        perf::time(Phase::RenderFrame, || self.terminal.draw(|frame| ui::render(app, frame)))?;
+        let wanted = match (&app.thumbnail, app.thumbnail_area, app.settings.cover_art.protocol()) {
+            (Some(thumbnail), Some(area), Some(protocol)) => Some((thumbnail.identifier.clone(), area, protocol)),
+            _ => None,
+        };
+        if wanted == self.cover_art {
+            return Ok(());
+        }
+        if let Some((_, _, shown_with)) = self.cover_art.take() {
+            // Images stay until the cells under them are drawn again (kitty's until deleted)
+            if shown_with == Protocol::Kitty {
+                self.terminal.backend_mut().write_all(thumbnail::KITTY_DELETE_ALL.as_bytes())?;
+            }
+            self.terminal.clear()?;
+            perf::time(Phase::RenderFrame, || self.terminal.draw(|frame| ui::render(app, frame)))?;
+        }
+        let (Some((identifier, area, protocol)), Some(thumbnail)) = (wanted, &app.thumbnail) else {
+            return Ok(());
+        };
+        let key = (identifier.clone(), area);
+        if self.cover_art_escape.as_ref().is_none_or(|(shown, _)| *shown != key) {
+            let escape = thumbnail::encode(protocol, &thumbnail.image, area.width, area.height).unwrap_or_else(|e| {
+                warn!("Can't show the cover art of '{}': {:#}", identifier, e);
+                String::new()
+            });
+            self.cover_art_escape = Some((key, escape));
+        }
+        let escape = self.cover_art_escape.as_ref().map_or("", |(_, escape)| escape.as_str());
+        let backend = self.terminal.backend_mut();
+        crossterm::queue!(backend, MoveTo(area.x, area.y))?;
+        backend.write_all(escape.as_bytes())?;
+        Write::flush(backend)?;
+        self.cover_art = Some((identifier, area, protocol));
         Ok(())
     }
 
@@ -50,7 +89,7 @@ impl<B: Backend> Tui<B> {
     /// It disables the raw mode and reverts back the terminal properties.
     pub fn exit(&mut self) -> Result<()> {
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste)?;
         self.terminal.show_cursor()?;
         Ok(())
     }
@@ -1,23 +1,69 @@
-use crate::app::{ActivePane, App, AppState}; // Add ActivePane
+use crate::app::{ActivePane, App, AppState, CollectionRow, ItemTab, UPLOAD_FIELDS}; // Add ActivePane
+use crate::theme::{
+    BUSY_MARKER, COLLECTION_MARKER, ERROR_MARKER, EXCLUDED_MARKER, GROUP_COLLAPSED_MARKER, GROUP_EXPANDED_MARKER, MARKED_MARKER, RESTRICTED_MARKER, SUCCESS_MARKER,
+};
+use crate::exclusions::{self, ExclusionKind};
+use crate::filters;
+use crate::forecast;
+use crate::keymap::Action;
+use crate::layout;
+use crate::offline;
+use crate::palette;
+use crate::progress;
+use crate::retry;
+use crate::segments;
+use crate::settings::CollectionOrder;
+use crate::library::ItemStatus;
+use crate::list_nav;
+use crate::log_buffer;
+use crate::sync;
+use crate::throttle;
+use crate::torrent_backend::TorrentBackendKind;
+use crate::transfers::TransferRow;
+use crate::updater;
 use ratatui::{
     prelude::{Alignment, Constraint, Direction, Frame, Layout, Line, Rect, Span},
-    style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap}, // Add Clear
+    style::{Modifier, Style},
+    widgets::{Block, Borders, Cell, Clear, LineGauge, List, ListItem, ListState, Paragraph, Row, Table, Tabs, Wrap}, // Add Clear
 };
+use log::Level;
+use std::time::{Duration, Instant, SystemTime};
 
 /// Renders the user interface widgets.
 pub fn render(app: &mut App, frame: &mut Frame) {
-    // Main layout: Status bar at the bottom, rest is the main content area
+    app.thumbnail_area = None; // Set again by the item view if it shows cover art
+    // Main layout: Status bar at the bottom, rest is the main content area.
+    // Screen-reader mode adds an event line describing the screen in plain text.
+    // A playing preview adds a now-playing line.
+    let event_line_height = if app.screen_reader { 1 } else { 0 };
+    let now_playing_height = if app.preview.is_some() { 1 } else { 0 };
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(0),    // Main content area
+            Constraint::Length(now_playing_height), // Audio preview
+            Constraint::Length(event_line_height), // Screen-reader event line
             Constraint::Length(1), // Status/Error message
         ])
         .split(frame.area());
 
     let content_area = main_layout[0];
-    let status_area = main_layout[1];
+    let status_area = main_layout[3];
+
+    if let Some(preview) = &app.preview {
+        let hint = if preview.is_paused() { "Space: Resume" } else { "Space: Pause" };
+        let now_playing = Paragraph::new(Line::from(vec![
+            Span::styled(preview.status_line(), app.theme.success()),
+            Span::styled(format!("  {}, S: Stop", hint), app.theme.muted()),
+        ]));
+        frame.render_widget(now_playing, main_layout[1]);
+    }
+
+    if app.screen_reader {
+        let event_line = Paragraph::new(app.last_announcement.clone().unwrap_or_default())
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        frame.render_widget(event_line, main_layout[2]);
+    }
 
     // Render content based on state
     match app.current_state {
@@ -40,6 +86,49 @@ pub fn render(app: &mut App, frame: &mut Frame) {
             // Render the add collection input overlay
             render_add_collection_input(app, frame); // Needs frame ref
         }
+        AppState::EnteringSearch => {
+            render_browsing_panes(app, frame, content_area);
+            render_search_input(app, frame);
+        }
+        AppState::FilteringItems => {
+            // The query is typed in the status bar; the items pane narrows as it changes
+            render_browsing_panes(app, frame, content_area);
+        }
+        AppState::EditingUpload => {
+            render_browsing_panes(app, frame, content_area);
+            render_upload_form(app, frame);
+        }
+        AppState::EnteringFavoritesUser => {
+            render_browsing_panes(app, frame, content_area);
+            render_favorites_user_input(app, frame);
+        }
+        AppState::EnteringWaybackUrl => {
+            render_browsing_panes(app, frame, content_area);
+            render_wayback_url_input(app, frame);
+        }
+        AppState::EnteringFetchFilter => {
+            render_browsing_panes(app, frame, content_area);
+            render_fetch_filter_input(app, frame);
+        }
+        AppState::EnteringCollectionGroup => {
+            render_browsing_panes(app, frame, content_area);
+            render_collection_group_input(app, frame);
+        }
+        AppState::EnteringCollectionAlias => {
+            render_browsing_panes(app, frame, content_area);
+            render_collection_alias_input(app, frame);
+        }
+        AppState::ViewingWayback => {
+            render_wayback_view(app, frame, content_area);
+        }
+        AppState::EnteringExportPath => {
+            if app.exporting_item {
+                render_item_view(app, frame, content_area);
+            } else {
+                render_browsing_panes(app, frame, content_area);
+            }
+            render_export_path_input(app, frame);
+        }
         AppState::AskingDownloadDir => {
             // Render browsing panes underneath (or maybe just grey out?)
             render_browsing_panes(app, frame, content_area);
@@ -50,13 +139,143 @@ pub fn render(app: &mut App, frame: &mut Frame) {
              // Render browsing panes underneath, status bar shows progress
              render_browsing_panes(app, frame, content_area);
         }
+        AppState::UpdateAvailable => {
+            render_browsing_panes(app, frame, content_area);
+            render_update_popup(app, frame);
+        }
+        AppState::ConfirmingDownload => {
+            render_browsing_panes(app, frame, content_area);
+            render_download_forecast_popup(app, frame);
+        }
+        AppState::ResumePrompt => {
+            render_browsing_panes(app, frame, content_area);
+            render_resume_prompt(app, frame);
+        }
+        AppState::ViewingEstimate => {
+            render_browsing_panes(app, frame, content_area);
+            render_estimate_popup(app, frame);
+        }
+        AppState::ViewingCollectionReport => {
+            render_browsing_panes(app, frame, content_area);
+            render_collection_report_popup(app, frame);
+        }
+        AppState::ViewingDownloads => {
+            render_downloads_view(app, frame, content_area);
+        }
+        AppState::ManagingExclusions | AppState::AddingExclusion => {
+            render_exclusions_view(app, frame, content_area);
+            if app.current_state == AppState::AddingExclusion {
+                render_add_exclusion_input(app, frame);
+            }
+        }
+        AppState::ViewingHelp => {
+            render_help_view(app, frame, content_area);
+        }
+        AppState::ViewingLog => {
+            render_log_view(app, frame, content_area);
+        }
+        AppState::ViewingFailures => {
+            render_failures_view(app, frame, content_area);
+        }
+        AppState::EnteringCommand => {
+            match app.command_return_state {
+                AppState::ViewingItem => render_item_view(app, frame, content_area),
+                AppState::ViewingDownloads => render_downloads_view(app, frame, content_area),
+                AppState::ViewingFailures => render_failures_view(app, frame, content_area),
+                _ => render_browsing_panes(app, frame, content_area),
+            }
+            render_command_palette(app, frame);
+        }
     }
 
     render_status_bar(app, frame, status_area);
 }
 
+/// `'a': Add, Del: Remove`-style hints naming the active key of each action.
+fn key_hints(app: &App, hints: &[(Action, &str)]) -> String {
+    hints.iter().map(|(action, label)| format!("{}: {}", app.keymap.hint(*action), label)).collect::<Vec<_>>().join(", ")
+}
+
+/// Renders the help screen: every remappable action with its active keys, by where it applies.
+fn render_help_view(app: &mut App, frame: &mut Frame, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            "Keys (Esc/{}, ↑/↓: Scroll; rebind in the [keys] section of settings.toml)",
+            key_hints(app, &[(Action::Help, "Back")])
+        ))
+        .border_style(app.theme.item_view_border());
+    let heading = Style::default().add_modifier(Modifier::BOLD);
+    let rows: Vec<Row> = app
+        .keymap
+        .help()
+        .into_iter()
+        .flat_map(|(context, actions)| {
+            std::iter::once(Row::new(vec![Cell::from(context.title()), Cell::from("")]).style(heading))
+                .chain(actions.into_iter().map(|(keys, description)| Row::new(vec![Cell::from(format!("  {}", keys)), Cell::from(description)])))
+        })
+        .collect();
+    let table = Table::new(rows, [Constraint::Length(24), Constraint::Min(20)])
+        .block(block)
+        .row_highlight_style(app.theme.highlight())
+        .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.help_table_state);
+}
+
+/// Renders the recent log lines at the chosen levels, following the newest until one is
+/// selected.
+fn render_log_view(app: &mut App, frame: &mut Frame, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            "Log: {} (Esc/{}, ↑/↓/PgUp/PgDn: Scroll, {})",
+            log_buffer::level_label(app.log_level),
+            key_hints(app, &[(Action::ToggleLog, "Back")]),
+            key_hints(app, &[(Action::LogLevel, "Levels")])
+        ))
+        .border_style(app.theme.item_view_border());
+    let lines = app.log_buffer.lines(app.log_level);
+    if lines.is_empty() {
+        frame.render_widget(Paragraph::new("Nothing logged at these levels yet.").block(block).style(app.theme.muted()), area);
+        return;
+    }
+
+    let height = area.height.saturating_sub(2) as usize;
+    app.page_rows.log = height;
+    let selected = app.log_list_state.selected();
+    let window = list_nav::window(app.log_list_state.offset(), selected.or(Some(lines.len() - 1)), height, lines.len());
+    let items: Vec<ListItem> = lines[window.clone()]
+        .iter()
+        .map(|line| {
+            let style = match line.level {
+                Level::Error => app.theme.error(),
+                Level::Warn => app.theme.busy(),
+                _ => Style::default(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(line.time.format("%H:%M:%S ").to_string(), app.theme.muted()),
+                Span::styled(format!("{:<5} ", line.level), style),
+                Span::raw(line.message.clone()),
+            ]))
+        })
+        .collect();
+    let list = List::new(items).block(block).highlight_style(app.theme.highlight());
+    let mut state = ListState::default().with_selected(selected.map(|i| i - window.start));
+    frame.render_stateful_widget(list, area, &mut state);
+    *app.log_list_state.offset_mut() = window.start;
+}
+
 /// Renders the two-pane view for Collections and Items.
 fn render_browsing_panes(app: &mut App, frame: &mut Frame, area: Rect) {
+    // Screen readers cope badly with side-by-side panes; show only the focused one.
+    if app.screen_reader {
+        match app.active_pane {
+            ActivePane::Collections => render_collection_list_pane(app, frame, area),
+            ActivePane::Items => render_item_list_pane(app, frame, area),
+        }
+        return;
+    }
+
     let browser_layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
@@ -71,42 +290,78 @@ fn render_browsing_panes(app: &mut App, frame: &mut Frame, area: Rect) {
 
 /// Renders the list of favorite collections.
 fn render_collection_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
-    let border_style = if app.active_pane == ActivePane::Collections {
-        Style::default().fg(Color::Yellow) // Highlight active pane
+    let theme = app.theme;
+    let is_active = app.active_pane == ActivePane::Collections;
+    let border_style = if is_active {
+        theme.active_border() // Highlight active pane
     } else {
         Style::default()
     };
 
+    let group_label = app.collection_group_filter.as_ref().map(|group| format!(" [Group: {}]", group)).unwrap_or_default();
+    let order_label = match app.settings.collection_order {
+        CollectionOrder::Manual => String::new(),
+        order => format!(" [{}]", order),
+    };
+    let title = format!(
+        "Collections{}{} ({}, Enter: Load, {})",
+        group_label,
+        order_label,
+        key_hints(app, &[(Action::AddCollection, "Add"), (Action::RemoveCollection, "Remove")]),
+        key_hints(app, &[(Action::SwitchPane, "Switch")])
+    );
     let list_block = Block::default()
         .borders(Borders::ALL)
-        .title("Collections ('a': Add, Del: Remove, Enter: Load, Tab: Switch)")
+        .title(theme.pane_title(&title, is_active))
         .border_style(border_style);
 
-    let _inner_area = list_block.inner(area); // Prefix with underscore
+    app.page_rows.collections = list_block.inner(area).height as usize;
 
     if app.settings.favorite_collections.is_empty() {
-        let empty_msg = Paragraph::new("No collections saved.\nPress 'a' to add one.")
+        let empty_msg = Paragraph::new(format!("No collections saved.\nPress {} to add one.", app.keymap.hint(Action::AddCollection)))
             .block(list_block) // Render block border anyway
-            .style(Style::default().fg(Color::DarkGray))
+            .style(theme.muted())
             .alignment(Alignment::Center);
         frame.render_widget(empty_msg, area);
         return;
     }
 
+    let now = chrono::Utc::now();
+    let grouped = app.collection_group_filter.is_none() && !app.settings.collection_groups.is_empty();
     let list_items: Vec<ListItem> = app
-        .settings
-        .favorite_collections
-        .iter()
-        .map(|collection_name| ListItem::new(collection_name.clone()))
+        .collection_rows()
+        .into_iter()
+        .map(|row| {
+            let collection_name = match row {
+                CollectionRow::Group { name, count, collapsed } => {
+                    let marker = if collapsed { "▸" } else { "▾" };
+                    return ListItem::new(Span::styled(format!("{} {} ({})", marker, name, count), Style::default().add_modifier(Modifier::BOLD)));
+                }
+                CollectionRow::Collection(name) => name,
+            };
+            // Grouped favorites are indented under their header
+            let indent = if grouped && app.settings.collection_groups.contains_key(collection_name) { "  " } else { "" };
+            let mut label = format!("{}{}", indent, app.collection_display_name(collection_name));
+            if let Some(template) = app.settings.library_layouts.get(collection_name) {
+                label.push_str(&format!(" [{}]", layout::preset_name(template).unwrap_or("custom layout")));
+            }
+            if let Some(filter) = app.settings.fetch_filters.get(collection_name) {
+                label.push_str(&format!(" [Filter: {}]", filter));
+            }
+            let mut spans = vec![Span::raw(label)];
+            if let Some(status) = app.collection_status.get(collection_name) {
+                if status.new_items > 0 {
+                    spans.push(Span::styled(format!(" [{} new]", status.new_items), theme.success()));
+                }
+                spans.push(Span::styled(status.summary(now), theme.muted()));
+            }
+            ListItem::new(Line::from(spans))
+        })
         .collect();
 
     let list = List::new(list_items)
         .block(list_block) // Attach the block here
-        .highlight_style(
-            Style::default()
-                .bg(Color::Blue)
-                .add_modifier(Modifier::BOLD),
-        )
+        .highlight_style(theme.highlight())
         .highlight_symbol("> ");
 
     frame.render_stateful_widget(list, area, &mut app.collection_list_state);
@@ -115,35 +370,80 @@ fn render_collection_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
 
 /// Renders the list of items for the selected collection. (Renamed from render_item_list)
 fn render_item_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
-     let border_style = if app.active_pane == ActivePane::Items {
-        Style::default().fg(Color::Yellow) // Highlight active pane
+    let theme = app.theme;
+    let is_active = app.active_pane == ActivePane::Items;
+    app.page_rows.items = area.height.saturating_sub(2) as usize; // Inside the borders
+    let rows = app.visible_rows();
+     let border_style = if is_active {
+        theme.active_border() // Highlight active pane
     } else {
         Style::default()
     };
 
-    let list_title = if let Some(collection_name) = app.current_collection_name.as_deref() {
+    // Status filter, plus the mediatype/format filter when one is set
+    let mut filter_label = if app.item_filter.is_active() {
+        format!("{} | {}", app.item_status_filter, app.item_filter)
+    } else {
+        app.item_status_filter.to_string()
+    };
+    if !app.item_query.trim().is_empty() {
+        filter_label.push_str(&format!(" | Matching '{}'", app.item_query.trim()));
+    }
+    let marked_label = match app.marked_items.len() {
+        0 => String::new(),
+        count => format!(" [{} marked, {}]", count, key_hints(app, &[(Action::Download, "Download"), (Action::ClearMarks, "Clear")])),
+    };
+    let mark_hint = format!("{}/{}: Mark", app.keymap.hint(Action::Mark), app.keymap.hint(Action::MarkAll));
+    let filter_hint = format!(
+        "{}, {}/{}/{}: Filter, {}, {}",
+        key_hints(app, &[(Action::Filter, "Find")]),
+        app.keymap.hint(Action::StatusFilter),
+        app.keymap.hint(Action::MediatypeFilter),
+        app.keymap.hint(Action::FormatFilter),
+        key_hints(app, &[(Action::GroupDuplicates, "Group")]),
+        key_hints(app, &[(Action::SwitchPane, "Switch")])
+    );
+    let list_title = if let Some(query) = app.search_query.as_deref() {
+        let count_str = app.total_items_found.map_or("?".to_string(), |t| t.to_string());
+        format!(
+            "Search '{}' ({} / {}) [Filter: {}]{}{} (Enter: View, {}, {}, {}, {})",
+            query,
+            rows.len(),
+            count_str,
+            filter_label,
+            if app.group_duplicates { " [Grouped]" } else { "" },
+            marked_label,
+            key_hints(app, &[(Action::Download, "Item")]),
+            mark_hint,
+            key_hints(app, &[(Action::Search, "New Search")]),
+            filter_hint
+        )
+    } else if app.current_collection_name.is_some() {
         let count_str = app.total_items_found.map_or("?".to_string(), |t| t.to_string());
-        let shown_count = app.items.len();
+        let shown_count = rows.len();
+        let up_hint = if app.collection_trail.is_empty() { "" } else { "Backspace: Up, " };
         format!(
-            "Items for '{}' ({} / {}) (Enter: View, 'd': Item, 'b': All, Tab: Switch)",
-            collection_name, shown_count, count_str
+            "Items for '{}' ({} / {}){} [Filter: {}]{}{} ({}Enter: View, {}, {}, {}, {})",
+            app.breadcrumb_path(), shown_count, count_str, app.network.stale_marker(app.items_cached_at, SystemTime::now()), filter_label,
+            if app.group_duplicates { " [Grouped]" } else { "" }, marked_label, up_hint,
+            key_hints(app, &[(Action::Download, "Item")]), mark_hint, key_hints(app, &[(Action::DownloadAll, "All")]), filter_hint
         )
     } else {
-        "Items (Select a collection) (Tab: Switch)".to_string()
+        format!("Items (Select a collection) ({})", key_hints(app, &[(Action::SwitchPane, "Switch")]))
     };
 
 
     let list_block = Block::default()
         .borders(Borders::ALL)
-        .title(list_title)
+        .title(theme.pane_title(&list_title, is_active))
         .border_style(border_style);
 
-    let _inner_area = list_block.inner(area); // Prefix with underscore
+    let height = list_block.inner(area).height as usize;
 
-    if app.is_loading {
-        let loading_paragraph = Paragraph::new("Loading items...")
+    if app.is_loading && app.items.is_empty() {
+        let loading_paragraph = Paragraph::new(format!("{}Loading items...", BUSY_MARKER))
             .block(list_block) // Render block border anyway
-            .style(Style::default().fg(Color::Yellow))
+            .style(theme.busy())
             .alignment(Alignment::Center);
         frame.render_widget(loading_paragraph, area);
         return;
@@ -153,10 +453,10 @@ fn render_item_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
     if let Some(err) = &app.error_message {
         // Only show error if it's relevant to the item list (e.g., fetch failed)
         // We might need more specific error types later.
-        if app.current_collection_name.is_some() { // Only show if we tried loading a collection
-            let error_paragraph = Paragraph::new(format!("Error: {}", err))
+        if app.current_collection_name.is_some() || app.search_query.is_some() { // Only show if we tried loading items
+            let error_paragraph = Paragraph::new(format!("{}Error: {}", ERROR_MARKER, err))
                 .block(list_block)
-                .style(Style::default().fg(Color::Red))
+                .style(theme.error())
                 .alignment(Alignment::Center);
             frame.render_widget(error_paragraph, area);
             return;
@@ -164,37 +464,72 @@ fn render_item_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
     }
 
     // Handle empty list or no collection selected
-    if app.current_collection_name.is_none() || (app.items.is_empty() && !app.is_loading) {
-        let empty_msg = if app.current_collection_name.is_none() {
-            "<- Select a collection"
-        } else {
+    let has_source = app.current_collection_name.is_some() || app.search_query.is_some();
+    if !has_source || (rows.is_empty() && !app.is_loading) {
+        let empty_msg = if !has_source {
+            "<- Select a collection, or press '/' to search"
+        } else if app.items.is_empty() && app.search_query.is_some() {
+            "No items match the search."
+        } else if app.items.is_empty() {
             "No items found for this collection."
+        } else {
+            "No items match the filters ('f', 'v', 'm', 'F' to change)."
         };
         let empty_paragraph = Paragraph::new(empty_msg)
             .block(list_block) // Render block border anyway
-            .style(Style::default().fg(Color::DarkGray))
+            .style(theme.muted())
             .alignment(Alignment::Center);
         frame.render_widget(empty_paragraph, area);
         return;
     }
 
-    // Render the actual item list
-    let list_items: Vec<ListItem> = app
-        .items
+    // Render the actual item list. Only the rows in view are built, so a listing of
+    // 500k items draws as fast as a short one.
+    let query = app.item_query.trim();
+    let window = list_nav::window(app.item_list_state.offset(), app.item_list_state.selected(), height, rows.len());
+    let list_items: Vec<ListItem> = rows[window.clone()]
         .iter()
-        .map(|item| ListItem::new(item.identifier.clone()))
+        .map(|row| {
+            let item = row.doc;
+            // Grouped view: variants are indented under their group, which shows its size
+            let (indent, label) = match (row.is_variant, row.group_size) {
+                (true, _) => ("    ", item.identifier.clone()),
+                (false, 1) => ("", item.identifier.clone()),
+                (false, size) => {
+                    let toggle = if row.expanded { GROUP_EXPANDED_MARKER } else { GROUP_COLLAPSED_MARKER };
+                    (toggle, format!("{} ({} variants)", item.identifier, size))
+                }
+            };
+            // Titles are shown when grouping and when filtering, which also matches them
+            let label = match item.title.as_deref().filter(|_| app.group_duplicates || !query.is_empty()) {
+                Some(title) => format!("{} - {}", label, title),
+                None => label,
+            };
+            let (marker, style) = if app.exclusions.is_excluded(&item.identifier) {
+                (EXCLUDED_MARKER, theme.muted())
+            } else {
+                match app.library.status(&item.identifier) {
+                    Some(ItemStatus::Downloaded) => (SUCCESS_MARKER, theme.success()),
+                    Some(ItemStatus::Failed) => (ERROR_MARKER, theme.error()),
+                    None => ("", Style::default()),
+                }
+            };
+            let mark = if app.marked_items.contains(&item.identifier) { MARKED_MARKER } else { "" };
+            let kind = if item.mediatype.as_deref() == Some("collection") { COLLECTION_MARKER } else { "" };
+            let mut spans = vec![Span::raw(format!("{}{}{}{}", indent, mark, marker, kind))];
+            spans.extend(highlight_matches(&label, query));
+            ListItem::new(Line::from(spans)).style(style)
+        })
         .collect();
 
     let list = List::new(list_items)
         .block(list_block) // Attach block here
-        .highlight_style(
-            Style::default()
-                .bg(Color::Blue)
-                .add_modifier(Modifier::BOLD),
-        )
+        .highlight_style(theme.highlight())
         .highlight_symbol("> ");
 
-    frame.render_stateful_widget(list, area, &mut app.item_list_state);
+    let mut window_state = ListState::default().with_selected(app.item_list_state.selected().filter(|i| window.contains(i)).map(|i| i - window.start));
+    frame.render_stateful_widget(list, area, &mut window_state);
+    *app.item_list_state.offset_mut() = window.start;
 }
 
 
@@ -202,55 +537,151 @@ fn render_item_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
 fn render_item_view(app: &mut App, frame: &mut Frame, area: Rect) {
     let item_id = app.viewing_item_id.as_deref().unwrap_or("Unknown"); // Get the ID
 
-    let collection_name = app.current_collection_name.as_deref().unwrap_or("Unknown");
+    let collection_name = app.current_collection_name.as_deref().unwrap_or(if app.search_query.is_some() { "Search" } else { "Unknown" });
     let outer_block = Block::default()
         .borders(Borders::ALL)
         .title(format!(
-            "Item: {} / {} (Esc: Back, ↑/↓: Files, Enter/'d': File, 'b': All Files)",
-            collection_name, item_id
+            "Item: {} / {}{} (Esc: Back, {}/{}: History, {}, ↑/↓: Files, Enter/{}, {} [{}], {})",
+            collection_name,
+            item_id,
+            app.network.stale_marker(app.details_cached_at, SystemTime::now()),
+            app.keymap.hint(Action::Back),
+            app.keymap.hint(Action::Forward),
+            key_hints(app, &[(Action::NextTab, "Reviews/Related")]),
+            key_hints(app, &[(Action::Download, "File")]),
+            key_hints(app, &[(Action::DownloadAll, "Files")]),
+            app.download_preset,
+            key_hints(app, &[(Action::CyclePreset, "Preset"), (Action::Open, "Open"), (Action::Export, "Export"), (Action::RefreshDetails, "Refresh")])
         ))
-        .border_style(Style::default().fg(Color::Cyan)); // Highlight view border
+        .border_style(app.theme.item_view_border()); // Highlight view border
 
     // Create inner area excluding the border
     let inner_area = outer_block.inner(area);
     frame.render_widget(outer_block, area); // Render the outer block first
 
     if app.is_loading_details {
-        let loading_paragraph = Paragraph::new("Loading details...")
-            .style(Style::default().fg(Color::Yellow))
+        let loading_paragraph = Paragraph::new(format!("{}Loading details...", BUSY_MARKER))
+            .style(app.theme.busy())
             .alignment(Alignment::Center);
         frame.render_widget(loading_paragraph, inner_area);
         return;
     }
 
     if let Some(_details) = &app.current_item_details { // Prefix with underscore
-        // Split the inner area for metadata and file list
+        // Split the inner area for metadata and file list (stacked in screen-reader mode)
+        let direction = if app.screen_reader { Direction::Vertical } else { Direction::Horizontal };
         let view_layout = Layout::default()
-            .direction(Direction::Horizontal)
+            .direction(direction)
             .constraints([
                 Constraint::Percentage(40), // Metadata pane
                 Constraint::Percentage(60), // File list pane
             ])
             .split(inner_area);
 
-        render_metadata_pane(app, frame, view_layout[0]);
+        render_item_info_pane(app, frame, view_layout[0]);
         render_file_list_pane(app, frame, view_layout[1]);
 
     } else {
         // Display error if details are None and not loading
         let error_msg = app.error_message.as_deref().unwrap_or("Failed to load item details.");
-         let error_paragraph = Paragraph::new(error_msg)
-             .style(Style::default().fg(Color::Red))
+         let error_paragraph = Paragraph::new(format!("{}{}", ERROR_MARKER, error_msg))
+             .style(app.theme.error())
              .alignment(Alignment::Center);
          frame.render_widget(error_paragraph, inner_area);
     }
 }
 
-/// Renders the metadata pane within the item view.
-fn render_metadata_pane(app: &App, frame: &mut Frame, area: Rect) {
-    let block = Block::default().borders(Borders::RIGHT).title("Metadata"); // Add right border
+/// Renders the left pane of the item view: tabs for the metadata, the reviews and the
+/// related items.
+fn render_item_info_pane(app: &mut App, frame: &mut Frame, area: Rect) {
+    let block = Block::default().borders(Borders::RIGHT); // Add right border
     let inner_area = block.inner(area);
     frame.render_widget(block, area);
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(0)])
+        .split(inner_area);
+
+    let (reviews, related) = app.current_item_details.as_ref().map_or((0, 0), |d| (d.reviews.len(), d.related_identifiers.len()));
+    let selected_tab = match app.item_tab {
+        ItemTab::Metadata => 0,
+        ItemTab::Reviews => 1,
+        ItemTab::Related => 2,
+    };
+    let tabs = Tabs::new(vec!["Metadata".to_string(), format!("Reviews ({})", reviews), format!("Related ({})", related)])
+        .select(selected_tab)
+        .highlight_style(app.theme.highlight())
+        .block(Block::default().borders(Borders::BOTTOM));
+    frame.render_widget(tabs, layout[0]);
+
+    match app.item_tab {
+        ItemTab::Metadata => render_metadata_pane(app, frame, layout[1]),
+        ItemTab::Reviews => render_reviews_tab(app, frame, layout[1]),
+        ItemTab::Related => render_related_tab(app, frame, layout[1]),
+    }
+}
+
+/// Renders one review at a time; Up/Down browse them.
+fn render_reviews_tab(app: &App, frame: &mut Frame, area: Rect) {
+    let reviews = app.current_item_details.as_ref().map_or(&[][..], |d| d.reviews.as_slice());
+    let Some(review) = reviews.get(app.review_index) else {
+        let empty = Paragraph::new("No reviews for this item.").style(app.theme.muted()).alignment(Alignment::Center);
+        frame.render_widget(empty, area);
+        return;
+    };
+    let stars = review.stars.map_or(String::new(), |n| format!("{}{} ", "★".repeat(n as usize), "☆".repeat(5 - n as usize)));
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("Review {} of {} (↑/↓)", app.review_index + 1, reviews.len()),
+            app.theme.muted(),
+        )),
+        Line::from(vec![
+            Span::raw(stars),
+            Span::styled(review.title.as_deref().unwrap_or("Untitled"), Style::default().add_modifier(Modifier::BOLD)),
+        ]),
+        Line::from(format!(
+            "by {}{}",
+            review.reviewer.as_deref().unwrap_or("anonymous"),
+            review.date.as_deref().map_or(String::new(), |date| format!(", {}", date))
+        )),
+        Line::from(""),
+    ];
+    lines.extend(review.body.lines().map(|line| Line::from(line.to_string())));
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), area);
+}
+
+/// Renders the identifiers this item links to; Enter opens the selected one.
+fn render_related_tab(app: &mut App, frame: &mut Frame, area: Rect) {
+    let related = app.current_item_details.as_ref().map_or(&[][..], |d| d.related_identifiers.as_slice());
+    if related.is_empty() {
+        let empty = Paragraph::new("No related items linked from this item.").style(app.theme.muted()).alignment(Alignment::Center);
+        frame.render_widget(empty, area);
+        return;
+    }
+    let items: Vec<ListItem> = related.iter().map(|id| ListItem::new(id.as_str())).collect();
+    let block = Block::default().title("Enter: Open");
+    app.page_rows.item_view = block.inner(area).height as usize;
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(app.theme.highlight())
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list, area, &mut app.related_list_state);
+}
+
+/// Rows of the metadata tab the cover art takes up.
+const COVER_ART_ROWS: u16 = 10;
+
+/// Renders the metadata tab within the item view, with room for the cover art at the top
+/// when the terminal can draw it.
+fn render_metadata_pane(app: &mut App, frame: &mut Frame, inner_area: Rect) {
+    let mut inner_area = inner_area;
+    let shows_cover = app.thumbnail.as_ref().zip(app.current_item_details.as_ref()).is_some_and(|(t, d)| t.identifier == d.identifier);
+    if shows_cover && !app.screen_reader && app.settings.cover_art.protocol().is_some() && inner_area.height >= 2 * COVER_ART_ROWS {
+        // Terminal cells are about twice as tall as wide; covers are mostly square
+        let cover = Rect { height: COVER_ART_ROWS, width: inner_area.width.min(COVER_ART_ROWS * 2), ..inner_area };
+        app.thumbnail_area = Some(cover);
+        inner_area = Rect { y: inner_area.y + COVER_ART_ROWS + 1, height: inner_area.height - COVER_ART_ROWS - 1, ..inner_area };
+    }
 
     // Use the details if available
     if let Some(_details) = &app.current_item_details { // Prefix with underscore
@@ -283,6 +714,26 @@ fn render_metadata_pane(app: &App, frame: &mut Frame, area: Rect) {
         ]));
         lines.push(Line::from(""));
 
+        if let Some(release) = app.library.musicbrainz_release(&details.identifier) {
+            lines.push(Line::from(vec![
+                Span::styled("MusicBrainz Release: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(release),
+            ]));
+            lines.push(Line::from(""));
+        }
+
+        if details.access_restricted {
+            let access = match &app.credentials {
+                Some(creds) => format!("Restricted. Files '{}' is entitled to (e.g. borrowed) will download", creds.display_user()),
+                None => "Restricted. Log in (archive.org Login in settings) to download lending/stream-only files".to_string(),
+            };
+            lines.push(Line::from(vec![
+                Span::styled("Access: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(access, app.theme.error()),
+            ]));
+            lines.push(Line::from(""));
+        }
+
         lines.push(Line::from(Span::styled(
             "Collections: ",
             Style::default().add_modifier(Modifier::BOLD),
@@ -317,7 +768,7 @@ fn render_metadata_pane(app: &App, frame: &mut Frame, area: Rect) {
 fn render_file_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
     // Use a block to potentially add a border later if desired
     let block = Block::default().title("Files");
-    // let inner_area = block.inner(area); // Use area directly if no border
+    app.page_rows.item_view = block.inner(area).height as usize;
     frame.render_widget(block.clone(), area); // Render the block title/borders if any
 
     // Prefix `details` with `_` again to satisfy the compiler warning.
@@ -327,7 +778,7 @@ fn render_file_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
 
         if details.files.is_empty() {
             let empty_msg = Paragraph::new("No files found for this item.")
-                .style(Style::default().fg(Color::DarkGray))
+                .style(app.theme.muted())
                 .alignment(Alignment::Center);
             // Render inside the block's inner area
             frame.render_widget(empty_msg, block.inner(area));
@@ -337,7 +788,8 @@ fn render_file_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
         let list_items: Vec<ListItem> = details.files.iter().map(|file| {
             // Combine relevant file info into one line
             let line = format!(
-                "{} (Format: {}, Size: {})",
+                "{}{} (Format: {}, Size: {})",
+                if file.private { RESTRICTED_MARKER } else { "" },
                 file.name,
                 file.format.as_deref().unwrap_or("N/A"),
                 file.size.as_deref().unwrap_or("N/A")
@@ -346,11 +798,7 @@ fn render_file_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
         }).collect();
 
         let list = List::new(list_items)
-            .highlight_style(
-                Style::default()
-                    .bg(Color::Blue)
-                    .add_modifier(Modifier::BOLD),
-            )
+            .highlight_style(app.theme.highlight())
             .highlight_symbol("> ");
 
         // Render list inside the block's area
@@ -358,6 +806,29 @@ fn render_file_list_pane(app: &mut App, frame: &mut Frame, area: Rect) {
     }
 }
 
+/// Splits `text` into spans with the characters matching the live filter `query` underlined.
+fn highlight_matches<'a>(text: &str, query: &str) -> Vec<Span<'a>> {
+    let positions = match filters::fuzzy_positions(query, text) {
+        Some(positions) if !positions.is_empty() => positions,
+        _ => return vec![Span::raw(text.to_string())],
+    };
+    let matched = Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    let mut spans: Vec<Span> = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in text.chars().enumerate() {
+        let is_match = positions.binary_search(&i).is_ok();
+        if is_match != run_matched && !run.is_empty() {
+            let style = if run_matched { matched } else { Style::default() };
+            spans.push(Span::styled(std::mem::take(&mut run), style));
+        }
+        run_matched = is_match;
+        run.push(c);
+    }
+    spans.push(Span::styled(run, if run_matched { matched } else { Style::default() }));
+    spans
+}
+
 /// Helper function to create a centered rectangle for popups.
 fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -384,14 +855,35 @@ fn render_editing_setting_input(app: &mut App, frame: &mut Frame) {
     let area = centered_rect(60, 3, frame.area()); // Use frame.area()
 
     let input_prompt = "Edit Value: ";
-    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
-
+    let value = if app.selected_setting_index == 16 || app.selected_setting_index == 17 {
+        // Only the access key or email is shown; the secret after it is masked
+        let separators: &[char] = if app.selected_setting_index == 16 { &[' ', ':'] } else { &[' '] };
+        match app.editing_setting_input.split_once(separators) {
+            Some((access, secret)) => format!("{} {}", access, "*".repeat(secret.chars().count())),
+            None => app.editing_setting_input.clone(),
+        }
+    } else {
+        app.editing_setting_input.clone()
+    };
+    let input_text = format!("{}{}", input_prompt, value);
+
+    let title = if app.selected_setting_index == 10 {
+        "Download Filter: comma-separated globs, formats or extensions, e.g. *.flac, pdf (Enter: Save, Esc: Cancel)"
+    } else if app.selected_setting_index == 16 {
+        "Upload Keys: ACCESS SECRET from archive.org/account/s3.php (Enter: Save, Esc: Cancel)"
+    } else if app.selected_setting_index == 17 {
+        "archive.org Login: EMAIL PASSWORD; only the session cookies are saved (Enter: Log in, Esc: Cancel)"
+    } else if app.selected_setting_index == 23 {
+        "Media Player: command streamed files are played with, e.g. mpv or vlc --play-and-exit (Enter: Save, Esc: Cancel)"
+    } else {
+        "Editing Setting (Enter: Save, Esc: Cancel)"
+    };
     let input = Paragraph::new(input_text)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Editing Setting (Enter: Save, Esc: Cancel)")
-                .border_style(Style::default().fg(Color::Yellow)),
+                .title(title)
+                .border_style(app.theme.input_border()),
         );
 
     frame.render_widget(Clear, area); // Clear the area behind the input box
@@ -416,7 +908,7 @@ fn render_add_collection_input(app: &mut App, frame: &mut Frame) {
             Block::default()
                 .borders(Borders::ALL)
                 .title("Add Collection (Enter: Save, Esc: Cancel)")
-                .border_style(Style::default().fg(Color::Yellow)),
+                .border_style(app.theme.input_border()),
         );
 
     frame.render_widget(Clear, area); // Clear the area behind the input box
@@ -429,6 +921,222 @@ fn render_add_collection_input(app: &mut App, frame: &mut Frame) {
     ));
 }
 
+/// Renders the search query input overlay.
+fn render_search_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(80, 3, frame.area());
+
+    let input_prompt = "Search: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Search archive.org (Enter: Search, Esc: Cancel) e.g. grateful dead, title:(moby dick) AND mediatype:texts")
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
+/// Renders the command palette: the typed command above the commands matching it.
+fn render_command_palette(app: &mut App, frame: &mut Frame) {
+    let commands = palette::matching(&app.editing_setting_input, &palette::contexts(&app.command_return_state, &app.active_pane));
+    let list_height = commands.len().clamp(1, 12) as u16 + 2;
+    let area = centered_rect(70, 3 + list_height, frame.area());
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let input_prompt = ": ";
+    let input = Paragraph::new(format!("{}{}", input_prompt, app.editing_setting_input)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Command (type to search, ↑/↓: Select, Enter: Run, Esc: Cancel)")
+            .border_style(app.theme.input_border()),
+    );
+    let list_items: Vec<ListItem> = commands
+        .iter()
+        .map(|action| {
+            let mut spans = highlight_matches(action.description(), &app.editing_setting_input);
+            let key = match app.keymap.keys(*action).first() {
+                Some(key) => format!("  {} [{}]", action.name(), key),
+                None => format!("  {}", action.name()),
+            };
+            spans.push(Span::styled(key, app.theme.muted()));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = if list_items.is_empty() {
+        List::new(vec![ListItem::new("No matching command")]).style(app.theme.muted())
+    } else {
+        List::new(list_items).highlight_style(app.theme.highlight()).highlight_symbol("> ")
+    };
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, layout[0]);
+    frame.render_stateful_widget(list.block(Block::default().borders(Borders::ALL).border_style(app.theme.input_border())), layout[1], &mut app.command_list_state);
+    frame.set_cursor_position((layout[0].x + app.cursor_position as u16 + input_prompt.len() as u16 + 1, layout[0].y + 1));
+}
+
+/// Renders a centered input box overlay for the file an export is written to.
+fn render_export_path_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(80, 3, frame.area());
+
+    let input_prompt = "Export to: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+    let what = if app.exporting_item { "item metadata and files" } else { "listed items" };
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Export {} (Enter: Write, Esc: Cancel) .json for JSON, otherwise CSV", what))
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
+/// Renders the screen name prompt of the favorites import ('F').
+fn render_favorites_user_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(60, 3, frame.area());
+
+    let input_prompt = "Screen name: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Import archive.org favorites (Enter: Import, Esc: Cancel)")
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
+/// Renders the input box for the alias of the selected collection.
+fn render_collection_alias_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(60, 3, frame.area());
+
+    let input_prompt = "Name: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+    let collection = app.get_selected_collection().map(String::as_str).unwrap_or("");
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Name of {} (Enter: Save, empty for its title, Esc: Cancel)", collection))
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
+/// Renders the input box for the group of the selected collection.
+fn render_collection_group_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(60, 3, frame.area());
+
+    let input_prompt = "Group: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+    let collection = app.get_selected_collection().map(String::as_str).unwrap_or("");
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Group of {} (Enter: Save, empty for none, Esc: Cancel)", collection))
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
+/// Renders the input box for the fetch filter of the selected collection.
+fn render_fetch_filter_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(80, 3, frame.area());
+
+    let input_prompt = "[from..to] [query]: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+    let collection = app.get_selected_collection().map(String::as_str).unwrap_or("");
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(
+                    "Fetch filter for {}, e.g. 2020-01.. mediatype:audio (Enter: Save, empty for none, Esc: Cancel)",
+                    collection
+                ))
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
+/// Renders the input box for a Wayback Machine lookup.
+fn render_wayback_url_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(80, 3, frame.area());
+
+    let input_prompt = "URL [from [to]]: ";
+    let input_text = format!("{}{}", input_prompt, app.editing_setting_input);
+
+    let input = Paragraph::new(input_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Wayback Machine lookup, dates as YYYY[-MM[-DD]] (Enter: List Captures, Esc: Cancel)")
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
 /// Renders a centered input box overlay for asking the download directory.
 fn render_ask_download_dir_input(app: &mut App, frame: &mut Frame) {
     let area = centered_rect(80, 3, frame.area()); // Use frame.area()
@@ -442,7 +1150,7 @@ fn render_ask_download_dir_input(app: &mut App, frame: &mut Frame) {
             Block::default()
                 .borders(Borders::ALL)
                 .title("Set Download Directory (Enter: Save, Esc: Cancel)")
-                .border_style(Style::default().fg(Color::Yellow)),
+                .border_style(app.theme.input_border()),
         );
 
     frame.render_widget(Clear, area); // Clear the area behind the input box
@@ -456,12 +1164,255 @@ fn render_ask_download_dir_input(app: &mut App, frame: &mut Frame) {
 }
 
 
+/// Renders the release notes popup for an available update.
+fn render_update_popup(app: &App, frame: &mut Frame) {
+    let Some(release) = &app.available_update else { return };
+    let area = centered_rect(70, 20, frame.area());
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("Version {} is available (running {})", release.version, updater::CURRENT_VERSION),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(release.url.clone()),
+        Line::from(""),
+    ];
+    if release.notes.is_empty() {
+        lines.push(Line::from(Span::styled("No release notes.", app.theme.muted())));
+    } else {
+        lines.extend(release.notes.lines().map(|l| Line::from(l.to_string())));
+    }
+
+    let popup = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Update Available (Enter/'i': Install, Esc: Close)")
+                .border_style(app.theme.input_border()),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Renders the size forecast shown before a collection download starts.
+fn render_download_forecast_popup(app: &App, frame: &mut Frame) {
+    let Some(forecast) = &app.collection_forecast else { return };
+    let area = centered_rect(60, 10, frame.area());
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+
+    let mut lines = Vec::new();
+    match forecast.item_count {
+        Some(count) => {
+            let mut size = forecast::format_bytes(forecast.total_bytes);
+            if forecast.unknown_sizes > 0 {
+                size.push_str(&format!(" (+{} items of unknown size)", forecast.unknown_sizes));
+            }
+            lines.push(Line::from(vec![Span::styled("Items: ", bold), Span::raw(count.to_string())]));
+            lines.push(Line::from(vec![Span::styled("Total size: ", bold), Span::raw(size)]));
+        }
+        None => lines.push(Line::from(Span::styled(
+            "Size unknown: the collection hasn't been listed yet (press Enter on it to load it).",
+            app.theme.muted(),
+        ))),
+    }
+    let free = forecast.free_bytes.map_or("unknown".to_string(), forecast::format_bytes);
+    if forecast.exceeds_free_space() {
+        lines.push(Line::from(vec![
+            Span::styled("Free space: ", bold),
+            Span::styled(format!("{}{} (not enough)", ERROR_MARKER, free), app.theme.error()),
+        ]));
+    } else {
+        lines.push(Line::from(vec![Span::styled("Free space: ", bold), Span::raw(free)]));
+    }
+    let duration = match (forecast.estimated_duration, app.recent_download_speed) {
+        (Some(duration), Some(speed)) => format!("{} at {}", forecast::format_duration(duration), format_speed(speed)),
+        _ => "unknown (no downloads yet this session)".to_string(),
+    };
+    lines.push(Line::from(vec![Span::styled("Estimated time: ", bold), Span::raw(duration)]));
+
+    let popup = Paragraph::new(lines)
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Download collection {}? (Enter/'y': Start, Esc/'n': Cancel)", forecast.collection))
+                .border_style(app.theme.input_border()),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Formats listed in the size estimate popup; the rest are summed up in one line.
+const ESTIMATE_FORMAT_LINES: usize = 8;
+
+/// Renders the dry-run size estimate of a collection download.
+fn render_estimate_popup(app: &App, frame: &mut Frame) {
+    let Some(estimate) = &app.size_estimate else { return };
+    let formats = estimate.formats_by_size();
+    let format_lines = formats.len().min(ESTIMATE_FORMAT_LINES + 1) as u16;
+    let area = centered_rect(70, 8 + format_lines, frame.area());
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+
+    let mut items = match estimate.items_total {
+        Some(total) if estimate.is_complete() => format!("{} (all checked)", total),
+        Some(total) => format!("{} of {} checked...", estimate.items_checked, total),
+        None => "listing the collection...".to_string(),
+    };
+    if estimate.items_failed > 0 {
+        items.push_str(&format!(", {} failed", estimate.items_failed));
+    }
+    let mut size = forecast::format_bytes(estimate.bytes);
+    if estimate.unknown_sizes > 0 {
+        size.push_str(&format!(" (+{} files of unknown size)", estimate.unknown_sizes));
+    }
+    let mut lines = vec![
+        Line::from(vec![Span::styled("Items: ", bold), Span::raw(items)]),
+        Line::from(vec![Span::styled("Files: ", bold), Span::raw(estimate.files.to_string())]),
+        Line::from(vec![Span::styled("Total size: ", bold), Span::raw(size)]),
+        Line::from(Span::styled("By format:", bold)),
+    ];
+    for (format, total) in formats.iter().take(ESTIMATE_FORMAT_LINES) {
+        lines.push(Line::from(format!("  {}: {} in {} file(s)", format, forecast::format_bytes(total.bytes), total.files)));
+    }
+    if formats.len() > ESTIMATE_FORMAT_LINES {
+        let rest = &formats[ESTIMATE_FORMAT_LINES..];
+        let bytes: u64 = rest.iter().map(|(_, total)| total.bytes).sum();
+        lines.push(Line::from(format!("  {} other formats: {}", rest.len(), forecast::format_bytes(bytes))));
+    }
+
+    let popup = Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Estimate for {} with preset '{}' (Enter: Download, Esc: Close)", estimate.collection, estimate.preset))
+            .border_style(app.theme.input_border()),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Failed items listed by name in the collection report popup; the rest are counted.
+const REPORT_FAILED_LINES: usize = 5;
+
+/// Renders the totals of the collection download that just ended.
+fn render_collection_report_popup(app: &App, frame: &mut Frame) {
+    let Some(report) = &app.collection_report else { return };
+    let failed_lines = report.failed_items.len().min(REPORT_FAILED_LINES + 1) as u16;
+    let area = centered_rect(70, 4 + failed_lines + u16::from(failed_lines > 0), frame.area());
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+
+    let mut lines: Vec<Line> = report
+        .summary_lines()
+        .into_iter()
+        .map(|line| match line.split_once(": ") {
+            Some((label, value)) => Line::from(vec![Span::styled(format!("{}: ", label), bold), Span::raw(value.to_string())]),
+            None => Line::from(line),
+        })
+        .collect();
+    if !report.failed_items.is_empty() {
+        lines.push(Line::from(Span::styled("Failed:", bold)));
+        for identifier in report.failed_items.iter().take(REPORT_FAILED_LINES) {
+            lines.push(Line::from(Span::styled(format!("  {}", identifier), app.theme.error())));
+        }
+        if report.failed_items.len() > REPORT_FAILED_LINES {
+            lines.push(Line::from(format!("  and {} more", report.failed_items.len() - REPORT_FAILED_LINES)));
+        }
+    }
+
+    let hint = if report.failed_items.is_empty() { String::new() } else { format!(", {}", key_hints(app, &[(Action::ToggleFailures, "Failures")])) };
+    let popup = Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Downloaded {} (Enter/Esc: Close{})", report.collection, hint))
+            .border_style(app.theme.input_border()),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Renders the startup prompt about downloads interrupted in a previous session.
+fn render_resume_prompt(app: &App, frame: &mut Frame) {
+    let entries = app.session.entries();
+    let queued = app.queue.held();
+    let job_count = (entries.len() + queued.len()) as u16;
+    let height = if app.show_session_details { job_count.saturating_mul(2).saturating_add(5).min(20) } else { 5 };
+    let area = centered_rect(70, height, frame.area());
+
+    let mut lines = vec![Line::from(app.unfinished_session_summary())];
+    if app.show_session_details {
+        lines.push(Line::from(""));
+        for entry in entries {
+            lines.push(Line::from(Span::styled(entry.job.describe(), Style::default().add_modifier(Modifier::BOLD))));
+            lines.push(Line::from(Span::styled(
+                format!("  into {} ({}), started {}", entry.job.base_dir, entry.job.mode, entry.started),
+                app.theme.muted(),
+            )));
+        }
+        for job in queued {
+            lines.push(Line::from(Span::styled(job.describe(), Style::default().add_modifier(Modifier::BOLD))));
+            lines.push(Line::from(Span::styled(format!("  into {} ({}), not started", job.base_dir, job.mode), app.theme.muted())));
+        }
+    }
+
+    let popup = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Resume interrupted downloads? (Enter/'r': Resume, 'd': Discard, 'i': Inspect, Esc: Later)")
+                .border_style(app.theme.input_border()),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Renders the upload form popup: one line per field, the selected one with a cursor.
+fn render_upload_form(app: &App, frame: &mut Frame) {
+    let area = centered_rect(80, UPLOAD_FIELDS.len() as u16 + 4, frame.area());
+    let form = &app.upload_form;
+    let label_width = UPLOAD_FIELDS.iter().map(|label| label.len()).max().unwrap_or(0) + 2;
+
+    let mut lines: Vec<Line> = UPLOAD_FIELDS
+        .iter()
+        .zip(&form.values)
+        .enumerate()
+        .map(|(index, (label, value))| {
+            let label = format!("{:width$}", format!("{}:", label), width = label_width);
+            if index == form.field {
+                Line::from(vec![Span::styled(label, Style::default().add_modifier(Modifier::BOLD)), Span::raw(value.clone())])
+            } else {
+                Line::from(vec![Span::styled(label, app.theme.muted()), Span::raw(value.clone())])
+            }
+        })
+        .collect();
+    let keys = match &app.s3_keys {
+        Some(keys) => format!("Keys: {}", keys.masked()),
+        None => "No S3 keys: set Upload Keys in settings first".to_string(),
+    };
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(keys, app.theme.muted())));
+
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Upload to archive.org (↑/↓/Tab: Field, Enter: Upload, Esc: Cancel)")
+            .border_style(app.theme.input_border()),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+
+    frame.set_cursor_position((
+        area.x + 1 + (label_width + form.values[form.field].chars().count()) as u16,
+        area.y + 1 + form.field as u16,
+    ));
+}
+
 /// Renders the settings view.
 fn render_settings_view(app: &mut App, frame: &mut Frame, area: Rect) {
     let settings_block = Block::default()
         .borders(Borders::ALL)
-        .title("Settings (Esc: Save & Back, ↑/↓: Select, ←/→: Adjust/Cycle)") // Updated hint
-        .border_style(Style::default().fg(Color::Magenta)); // Distinct border color
+        .title("Settings (Esc: Save & Back, ↑/↓: Select, ←/→: Adjust/Cycle, 'x': Exclusions)") // Updated hint
+        .border_style(app.theme.settings_border()); // Distinct border color
 
     let inner_area = settings_block.inner(area);
     frame.render_widget(settings_block.clone(), area); // Render the block itself
@@ -492,25 +1443,439 @@ fn render_settings_view(app: &mut App, frame: &mut Frame, area: Rect) {
     );
 
 
+    let theme_text = format!(
+        "Theme: {}{} {}",
+        app.settings.theme,
+        if app.theme.color { "" } else { " (NO_COLOR set)" },
+        if app.selected_setting_index == 4 { "< >" } else { "" } // Hint for cycling
+    );
+
+
+    let update_check_text = format!(
+        "Check for Updates on Startup: {} {}",
+        if app.settings.check_for_updates { "On" } else { "Off" },
+        if app.selected_setting_index == 5 { "< >" } else { "" } // Hint for toggling
+    );
+
+    let confirm_threshold_text = format!(
+        "Confirm Collection Downloads Above: {} {}",
+        match app.settings.confirm_collection_above_gb {
+            0 => "Always".to_string(),
+            gb => format!("{} GB", gb),
+        },
+        if app.selected_setting_index == 7 { "< >" } else { "" } // Hint for adjustment
+    );
+
+    let sidecar_text = format!(
+        "Metadata Sidecar: {} {}",
+        app.settings.metadata_sidecar,
+        if app.selected_setting_index == 6 { "< >" } else { "" } // Hint for cycling
+    );
+
+    let file_preset_text = format!(
+        "Default File Preset: {} {}",
+        app.settings.default_file_preset,
+        if app.selected_setting_index == 8 { "< >" } else { "" } // Hint for cycling
+    );
+
+    let subtitles_text = format!(
+        "Always Include Subtitles (Movies): {} {}",
+        match (app.settings.include_subtitles, app.settings.subtitle_languages.is_empty()) {
+            (false, _) => "Off".to_string(),
+            (true, true) => "On (any language)".to_string(),
+            (true, false) => format!("On ({})", app.settings.subtitle_languages.join(", ")),
+        },
+        if app.selected_setting_index == 9 { "< >" } else { "" } // Hint for toggling
+    );
+
+    let file_filter_text = format!(
+        "Download Filter: {}",
+        if app.settings.download_file_filter.is_empty() {
+            "All files".to_string()
+        } else {
+            app.settings.download_file_filter.join(", ")
+        }
+    );
+
+    let torrent_backend_text = format!(
+        "Torrent Backend (Torrent Only mode): {}{} {}",
+        app.settings.torrent_backend,
+        match app.settings.torrent_backend {
+            TorrentBackendKind::SaveFile => String::new(),
+            TorrentBackendKind::Transmission => format!(" at {}", app.settings.transmission_url),
+        },
+        if app.selected_setting_index == 11 { "< >" } else { "" } // Hint for cycling
+    );
+
+    let speed_limit_text = format!(
+        "Download Speed Limit: {} {}",
+        throttle::format_limit(app.settings.download_speed_limit_kb),
+        if app.selected_setting_index == 12 { "< >" } else { "" } // Hint for adjusting
+    );
+
+    let sync_text = format!(
+        "Collection Sync (favorites): {}{} {}",
+        sync::format_interval(app.settings.sync_interval_minutes),
+        if app.sync.is_running() { " (syncing...)" } else { "" },
+        if app.selected_setting_index == 13 { "< >" } else { "" } // Hint for adjusting
+    );
+
+    let sources_text = format!(
+        "File Sources: {} {}",
+        app.settings.file_source_policy,
+        if app.selected_setting_index == 14 { "< >" } else { "" } // Hint for cycling
+    );
+
+    let playlists_text = format!(
+        "Write Playlists (audio items): {} {}",
+        if app.settings.write_playlists { "On" } else { "Off" },
+        if app.selected_setting_index == 15 { "< >" } else { "" } // Hint for toggling
+    );
+
+    let upload_keys_text = format!(
+        "Upload Keys (IA S3): {}",
+        app.s3_keys.as_ref().map_or("Not set".to_string(), |keys| keys.masked())
+    );
+
+    let login_text = format!(
+        "archive.org Login: {} {}",
+        app.credentials.as_ref().map_or("Not logged in".to_string(), |creds| creds.display_user()),
+        if app.selected_setting_index == 17 { "(Enter: Log in, Del: Log out)" } else { "" }
+    );
+
+    let ipfs_text = format!(
+        "Add Items to IPFS: {} {}",
+        if app.settings.ipfs_export { format!("On ({})", app.settings.ipfs_api_url) } else { "Off".to_string() },
+        if app.selected_setting_index == 18 { "< >" } else { "" } // Hint for toggling
+    );
+
+    let checksums_text = format!(
+        "Write Checksum Manifests: {} {}",
+        if app.settings.write_checksums { "On" } else { "Off" },
+        if app.selected_setting_index == 19 { "< >" } else { "" } // Hint for toggling
+    );
+
+    let dedupe_text = format!(
+        "Deduplicate Files: {} {}",
+        app.settings.dedupe_mode,
+        if app.selected_setting_index == 20 { "< >" } else { "" } // Hint for cycling
+    );
+
+    let tagging_text = format!(
+        "Tag Audio Files: {} {}",
+        if app.settings.tag_audio { format!("On ({})", app.settings.tag_policy) } else { "Off".to_string() },
+        if app.selected_setting_index == 21 { "< >" } else { "" } // Hint for cycling
+    );
+
+    let musicbrainz_text = format!(
+        "MusicBrainz Lookup: {} {}",
+        if app.settings.musicbrainz_lookup { "On" } else { "Off" },
+        if app.selected_setting_index == 22 { "< >" } else { "" } // Hint for toggling
+    );
+
+    let player_text = format!(
+        "Media Player: {} {}",
+        app.settings.player_command,
+        if app.selected_setting_index == 23 { "(Enter: Edit)" } else { "" }
+    );
+
+    let segments_text = format!(
+        "Parallel Segments (large files): {} {}",
+        segments::describe(&app.settings),
+        if app.selected_setting_index == 24 { "< >" } else { "" } // Hint for adjusting
+    );
+
+    let retry_text = format!(
+        "Retry Failed Downloads: {} {}",
+        retry::describe(&app.settings),
+        if app.selected_setting_index == 25 { "< >" } else { "" } // Hint for adjusting
+    );
+
+    let details_cache_text = format!(
+        "Item Details Cache: {} {}",
+        offline::format_ttl(app.settings.details_cache_hours),
+        if app.selected_setting_index == 26 { "< >" } else { "" } // Hint for adjusting
+    );
+
+    let cover_art_text = format!(
+        "Cover Art: {} {}",
+        app.settings.cover_art,
+        if app.selected_setting_index == 27 { "< >" } else { "" } // Hint for cycling
+    );
+
     let settings_items = vec![
         ListItem::new(download_dir_text),           // Index 0
         ListItem::new(download_mode_text),          // Index 1
         ListItem::new(file_concurrency_text),       // Index 2
         ListItem::new(collection_concurrency_text), // Index 3
+        ListItem::new(theme_text),                  // Index 4
+        ListItem::new(update_check_text),           // Index 5
+        ListItem::new(sidecar_text),                // Index 6
+        ListItem::new(confirm_threshold_text),      // Index 7
+        ListItem::new(file_preset_text),            // Index 8
+        ListItem::new(subtitles_text),              // Index 9
+        ListItem::new(file_filter_text),            // Index 10
+        ListItem::new(torrent_backend_text),        // Index 11
+        ListItem::new(speed_limit_text),            // Index 12
+        ListItem::new(sync_text),                   // Index 13
+        ListItem::new(sources_text),                // Index 14
+        ListItem::new(playlists_text),              // Index 15
+        ListItem::new(upload_keys_text),            // Index 16
+        ListItem::new(login_text),                  // Index 17
+        ListItem::new(ipfs_text),                   // Index 18
+        ListItem::new(checksums_text),              // Index 19
+        ListItem::new(dedupe_text),                 // Index 20
+        ListItem::new(tagging_text),                // Index 21
+        ListItem::new(musicbrainz_text),            // Index 22
+        ListItem::new(player_text),                 // Index 23
+        ListItem::new(segments_text),               // Index 24
+        ListItem::new(retry_text),                  // Index 25
+        ListItem::new(details_cache_text),          // Index 26
+        ListItem::new(cover_art_text),              // Index 27
     ];
 
     let list = List::new(settings_items)
-        .highlight_style(
-            Style::default()
-                .bg(Color::DarkGray) // Different highlight for settings
-                .add_modifier(Modifier::BOLD),
-        )
+        .highlight_style(app.theme.settings_highlight()) // Different highlight for settings
         .highlight_symbol(">> ");
 
     // Render the list inside the block's inner area
     frame.render_stateful_widget(list, inner_area, &mut app.settings_list_state);
 }
 
+/// Renders the exclusion rules screen.
+fn render_exclusions_view(app: &mut App, frame: &mut Frame, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Exclusions (Esc: Save & Back, ↑/↓: Select, 'a': Add Identifier, 'r': Add Regex, Del: Remove)")
+        .border_style(app.theme.settings_border());
+
+    let rules = exclusions::rules(&app.settings);
+    if rules.is_empty() {
+        let empty = Paragraph::new("No exclusions. Collection downloads skip identifiers listed here ('x' on an item excludes it).")
+            .block(block)
+            .style(app.theme.muted())
+            .wrap(Wrap { trim: true });
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = rules
+        .iter()
+        .map(|rule| match rule.kind {
+            ExclusionKind::Identifier => ListItem::new(format!("Identifier: {}", rule.value)),
+            ExclusionKind::Pattern => ListItem::new(format!("Regex:      {}", rule.value)),
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(app.theme.settings_highlight())
+        .highlight_symbol(">> ");
+    frame.render_stateful_widget(list, area, &mut app.exclusion_list_state);
+}
+
+/// Renders the Wayback Machine captures of a URL; redirects and errors are dimmed.
+fn render_wayback_view(app: &mut App, frame: &mut Frame, area: Rect) {
+    let theme = app.theme;
+    let Some(view) = &mut app.wayback else { return };
+    let range = match (&view.query.from, &view.query.to) {
+        (None, None) => String::new(),
+        (from, to) => format!(" {}..{}", from.as_deref().unwrap_or(""), to.as_deref().unwrap_or("")),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            "Wayback Machine: {}{} (Esc: Back, ↑/↓: Select, Enter/'d': Save Capture, 'a': Save All, 'W': New Lookup)",
+            view.query.url, range
+        ))
+        .border_style(theme.item_view_border());
+
+    if view.loading || view.snapshots.is_empty() {
+        let (text, style) = if view.loading {
+            (format!("{}Looking up captures...", BUSY_MARKER), theme.busy())
+        } else {
+            ("No captures of this URL.".to_string(), theme.muted())
+        };
+        frame.render_widget(Paragraph::new(text).block(block).style(style), area);
+        return;
+    }
+
+    let items: Vec<ListItem> = view
+        .snapshots
+        .iter()
+        .map(|snapshot| {
+            let size = snapshot.length.map(forecast::format_bytes).unwrap_or_default();
+            let line = format!("{}  {:>3}  {:<24} {:>10}  {}", snapshot.date(), snapshot.status, snapshot.mimetype, size, snapshot.original);
+            if snapshot.is_ok() {
+                ListItem::new(line)
+            } else {
+                ListItem::new(line).style(theme.muted())
+            }
+        })
+        .collect();
+    let list = List::new(items).block(block).highlight_style(theme.highlight()).highlight_symbol(">> ");
+    frame.render_stateful_widget(list, area, &mut view.list_state);
+}
+
+/// Width of the text progress bar in the Downloads table, in cells.
+const PROGRESS_BAR_WIDTH: usize = 20;
+
+/// Renders the table of files being downloaded with their progress, speed and ETA.
+fn render_downloads_view(app: &mut App, frame: &mut Frame, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            "Downloads: {} active{} (Esc/{}, ↑/↓: Select, {})",
+            app.active_transfers.len(),
+            if app.download_control.is_paused_all() { ", PAUSED" } else { "" },
+            key_hints(app, &[(Action::ToggleDownloads, "Back")]),
+            key_hints(
+                app,
+                &[
+                    (Action::Pause, "Pause/Resume Item"),
+                    (Action::PauseAll, "Pause/Resume All"),
+                    (Action::Cancel, "Cancel Item"),
+                    (Action::CancelAll, "Cancel All"),
+                    (Action::Help, "Keys"),
+                ]
+            )
+        ))
+        .border_style(app.theme.item_view_border());
+
+    if app.active_transfers.is_empty() {
+        let text = if app.is_downloading { "Waiting for file transfers to start..." } else { "Nothing is downloading." };
+        frame.render_widget(Paragraph::new(text).block(block).style(app.theme.muted()), area);
+        return;
+    }
+
+    let rows: Vec<Row> = app
+        .active_transfers
+        .rows(Instant::now())
+        .iter()
+        .map(|row| {
+            let size = match row.total {
+                Some(total) => format!("{} / {}", forecast::format_bytes(row.done), forecast::format_bytes(total)),
+                None => forecast::format_bytes(row.done),
+            };
+            Row::new(vec![
+                Cell::from(row.name.clone()),
+                Cell::from(progress_bar(row)),
+                Cell::from(size),
+                Cell::from(format!("{}/s", forecast::format_bytes(row.speed as u64))),
+                Cell::from(if app.download_control.is_paused(&row.item_id) {
+                    "Paused".to_string()
+                } else {
+                    row.eta.map(format_eta).unwrap_or_else(|| "-".to_string())
+                }),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(20),
+            Constraint::Length(PROGRESS_BAR_WIDTH as u16 + 7),
+            Constraint::Length(21),
+            Constraint::Length(11),
+            Constraint::Length(8),
+        ],
+    )
+    .header(Row::new(vec!["File", "Progress", "Size", "Speed", "ETA"]).style(Style::default().add_modifier(Modifier::BOLD)))
+    .block(block)
+    .row_highlight_style(app.theme.highlight())
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.transfers_table_state);
+}
+
+/// Renders what failed in the last download run: when, which item and file, and why.
+fn render_failures_view(app: &mut App, frame: &mut Frame, area: Rect) {
+    let started = chrono::DateTime::parse_from_rfc3339(&app.failures.started).map_or(String::new(), |t| format!(" of {}", t.format("%Y-%m-%d %H:%M")));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            "Failures of the last run{}: {} (Esc/{}, ↑/↓: Select, {})",
+            started,
+            app.failures.failures.len(),
+            key_hints(app, &[(Action::ToggleFailures, "Back")]),
+            key_hints(app, &[(Action::RetryFailed, "Retry All"), (Action::Help, "Keys")])
+        ))
+        .border_style(app.theme.item_view_border());
+
+    if app.failures.is_empty() {
+        frame.render_widget(Paragraph::new("Nothing failed in the last download run.").block(block).style(app.theme.muted()), area);
+        return;
+    }
+
+    app.page_rows.failures = area.height.saturating_sub(3) as usize;
+    let rows: Vec<Row> = app
+        .failures
+        .failures
+        .iter()
+        .map(|failure| {
+            let time = chrono::DateTime::parse_from_rfc3339(&failure.time).map_or(failure.time.clone(), |t| t.format("%H:%M:%S").to_string());
+            Row::new(vec![
+                Cell::from(time),
+                Cell::from(failure.identifier.clone()),
+                Cell::from(failure.file.clone().unwrap_or_default()),
+                Cell::from(failure.kind.label()).style(app.theme.error()),
+                Cell::from(failure.reason.clone()),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [Constraint::Length(8), Constraint::Percentage(20), Constraint::Percentage(20), Constraint::Length(12), Constraint::Min(20)],
+    )
+    .header(Row::new(vec!["Time", "Item", "File", "Kind", "Reason"]).style(Style::default().add_modifier(Modifier::BOLD)))
+    .block(block)
+    .row_highlight_style(app.theme.highlight())
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.failures_table_state);
+}
+
+/// `█████░░░░░  50%`, or an empty bar with `?` when the size isn't listed.
+fn progress_bar(row: &TransferRow) -> String {
+    match row.ratio() {
+        Some(ratio) => {
+            let filled = (ratio * PROGRESS_BAR_WIDTH as f64).round() as usize;
+            format!("{}{} {:>3}%", "█".repeat(filled), "░".repeat(PROGRESS_BAR_WIDTH - filled), (ratio * 100.0) as u64)
+        }
+        None => format!("{}    ?", "░".repeat(PROGRESS_BAR_WIDTH)),
+    }
+}
+
+/// ETA with second precision under a minute (files finish quickly).
+fn format_eta(eta: Duration) -> String {
+    if eta.as_secs() < 60 {
+        format!("{}s", eta.as_secs())
+    } else {
+        forecast::format_duration(eta)
+    }
+}
+
+/// Renders the input box for a new exclusion rule.
+fn render_add_exclusion_input(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(70, 3, frame.area());
+
+    let input_prompt = match app.new_exclusion_kind {
+        ExclusionKind::Identifier => "Identifier: ",
+        ExclusionKind::Pattern => "Regex: ",
+    };
+    let input = Paragraph::new(format!("{}{}", input_prompt, app.editing_setting_input))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Add Exclusion (Enter: Save, Esc: Cancel)")
+                .border_style(app.theme.input_border()),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(input, area);
+    frame.set_cursor_position((
+        area.x + app.cursor_position as u16 + input_prompt.len() as u16,
+        area.y + 1,
+    ));
+}
+
 /// Formats a download speed in bytes per second into a human-readable string (KB/s, MB/s, etc.).
 fn format_speed(bytes_per_sec: f64) -> String {
     const KB: f64 = 1024.0;
@@ -528,6 +1893,9 @@ fn format_speed(bytes_per_sec: f64) -> String {
     } // <-- Add missing closing brace here
 }
 
+/// Columns of the run gauge in the status bar (label included).
+const STATUS_GAUGE_WIDTH: u16 = 36;
+
 /// Renders the status bar at the bottom of the screen.
 fn render_status_bar(app: &mut App, frame: &mut Frame, area: Rect) {
     let status_text = if app.is_downloading {
@@ -547,19 +1915,58 @@ fn render_status_bar(app: &mut App, frame: &mut Frame, area: Rect) {
         // Format progress string if downloading
         let item_progress = app.total_items_to_download.map_or("?".to_string(), |t| t.to_string());
         let file_progress = app.total_files_to_download.map_or("?".to_string(), |t| t.to_string());
+        let skipped_str = if app.skipped_files_count > 0 { format!(" | Skipped: {}", app.skipped_files_count) } else { String::new() };
+        let restricted_str = if app.restricted_files_count > 0 {
+            format!(" | {}{}", RESTRICTED_MARKER, app.restricted_files_count)
+        } else {
+            "".to_string()
+        };
+        let bytes_str = match app.run_progress().bytes_expected() {
+            Some(expected) => format!(
+                " | {}/{}{}",
+                forecast::format_bytes(app.total_bytes_downloaded),
+                if app.total_items_to_download.is_some_and(|total| total > app.items_sized) { "~" } else { "" },
+                forecast::format_bytes(expected)
+            ),
+            None => String::new(),
+        };
+        let mut queued_str = if app.queue.is_empty() { String::new() } else { format!(" | Queued: {}", app.queue.len()) };
+        if !app.retries.is_empty() {
+            queued_str.push_str(&format!(" | To retry: {}", app.retries.len()));
+        }
         format!(
-            "Downloading [Items: {}/{} | Files: {}/{}{}]: {}", // Added speed, changed Last: to :
+            "Downloading [Items: {}/{} | Files: {}/{}{}{}{}{}]{}: {}", // Added speed, changed Last: to :
             app.items_downloaded_count,
             item_progress,
             app.files_downloaded_count,
             file_progress,
+            bytes_str,
+            skipped_str,
+            restricted_str,
             speed_str, // Include speed string
+            queued_str,
             app.download_status.as_deref().unwrap_or("...") // Show last status message
         )
+    } else if let Some(upload) = &app.upload {
+        format!(
+            "Uploading to {} [Files: {}/{} | {} / {}]: {}",
+            upload.identifier,
+            upload.files_done,
+            upload.files_total,
+            forecast::format_bytes(upload.bytes_sent),
+            forecast::format_bytes(upload.bytes_total),
+            upload.current_file.as_deref().unwrap_or("...")
+        )
     } else if let Some(status) = &app.download_status {
         status.clone() // Clone the String status
     } else if let Some(err) = &app.error_message {
         err.clone() // Clone the String error
+    } else if app.is_loading && !app.items.is_empty() {
+        // Collection pages arrive one by one
+        match app.total_items_found {
+            Some(total) => format!("Fetching collection data... ({} of {} items)", app.items.len(), total),
+            None => format!("Fetching collection data... ({} items)", app.items.len()),
+        }
     } else if app.is_loading {
         "Fetching collection data...".to_string() // Convert literal to String
     } else if app.is_loading_details {
@@ -579,23 +1986,148 @@ fn render_status_bar(app: &mut App, frame: &mut Frame, area: Rect) {
     } else if app.current_state == AppState::AddingCollection {
          // Status handled by the add collection overlay title
          " ".to_string()
+    } else if matches!(app.current_state, AppState::ManagingExclusions | AppState::AddingExclusion) {
+         // Status handled by the exclusions view title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringSearch {
+         // Status handled by the search input title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringExportPath {
+         // Status handled by the export input title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringFavoritesUser {
+         // Status handled by the screen name input title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringWaybackUrl {
+         // Status handled by the URL input title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringFetchFilter {
+         // Status handled by the filter input title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringCollectionGroup {
+         // Status handled by the group input title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringCollectionAlias {
+         // Status handled by the alias input title
+         " ".to_string()
+    } else if app.current_state == AppState::ViewingWayback {
+         // Status handled by the captures view title
+         " ".to_string()
+    } else if app.current_state == AppState::FilteringItems {
+        format!("Find: {}▏ ({} shown; Enter: Keep, Esc: Clear, ↑/↓: Nav)", app.item_query, app.visible_items().len())
+    } else if app.current_state == AppState::ViewingCollectionReport {
+         // Status handled by the report popup title
+         " ".to_string()
+    } else if app.current_state == AppState::ViewingEstimate {
+         // Status handled by the estimate popup title
+         " ".to_string()
+    } else if app.current_state == AppState::ViewingDownloads {
+         // Status handled by the downloads view title
+         " ".to_string()
+    } else if app.current_state == AppState::ViewingHelp {
+         // Status handled by the help view title
+         " ".to_string()
+    } else if app.current_state == AppState::EnteringCommand {
+         // Status handled by the palette title
+         " ".to_string()
+    } else if app.current_state == AppState::ViewingLog {
+         // Status handled by the log view title
+         " ".to_string()
+    } else if app.current_state == AppState::ViewingFailures {
+         // Status handled by the failures view title
+         " ".to_string()
     } else { // Browsing state
-        match app.active_pane {
-            ActivePane::Collections => "Collections Pane. 'q': Quit, 's': Settings, Tab: Switch, ↑/↓: Nav, Enter: Load, 'a': Add, Del: Remove, 'd'/'b': Download Collection".to_string(),
-            ActivePane::Items => "Items Pane. 'q': Quit, 's': Settings, Tab: Switch, ↑/↓: Nav, Enter: View Details, 'd': Download Item, 'b': Download All Items".to_string(),
+        let general = key_hints(
+            app,
+            &[(Action::Quit, "Quit"), (Action::Help, "Keys"), (Action::CommandPalette, "Commands"), (Action::Settings, "Settings"), (Action::SwitchPane, "Switch")],
+        );
+        let hint = match app.active_pane {
+            ActivePane::Collections => format!(
+                "Collections Pane. {}, ↑/↓: Nav, Enter: Load, {}",
+                general,
+                key_hints(
+                    app,
+                    &[
+                        (Action::AddCollection, "Add"),
+                        (Action::RemoveCollection, "Remove"),
+                        (Action::Download, "Download Collection"),
+                        (Action::Search, "Search"),
+                        (Action::Estimate, "Estimate Size"),
+                        (Action::ImportFavorites, "Import Favorites"),
+                        (Action::Upload, "Upload"),
+                        (Action::Wayback, "Wayback Machine"),
+                        (Action::ToggleDownloads, "Downloads"),
+                    ]
+                )
+            ),
+            ActivePane::Items => format!(
+                "Items Pane. {}, ↑/↓/PgUp/PgDn: Nav, Enter: View Details, {}, {}/{}: Back/Forward, {}",
+                general,
+                key_hints(
+                    app,
+                    &[
+                        (Action::Download, "Download Item"),
+                        (Action::DownloadAll, "Download All Items"),
+                        (Action::Estimate, "Estimate Size"),
+                        (Action::Exclude, "Exclude/Include"),
+                        (Action::Filter, "Find"),
+                        (Action::StatusFilter, "Filter by Status"),
+                        (Action::GroupDuplicates, "Group Duplicates"),
+                        (Action::Open, "Open Folder"),
+                        (Action::Export, "Export"),
+                        (Action::Copy, "Copy ID"),
+                    ]
+                ),
+                app.keymap.hint(Action::Back),
+                app.keymap.hint(Action::Forward),
+                key_hints(app, &[(Action::ToggleDownloads, "Downloads")])
+            ),
+        };
+        let mut hint = format!("{}, {} [{}]", hint, key_hints(app, &[(Action::CyclePreset, "Preset")]), app.download_preset);
+        if app.last_report_path.is_some() {
+            hint.push_str(&format!(", {}", key_hints(app, &[(Action::OpenReport, "Last Report")])));
+        }
+        if !app.failures.is_empty() {
+            hint.push_str(&format!(", {}: Failures ({})", app.keymap.hint(Action::ToggleFailures), app.failures.failures.len()));
+        }
+        match app.log_buffer.error_count() {
+            0 => hint.push_str(&format!(", {}", key_hints(app, &[(Action::ToggleLog, "Log")]))),
+            errors => hint.push_str(&format!(", {}: Log ({} errors)", app.keymap.hint(Action::ToggleLog), errors)),
+        }
+        match &app.available_update {
+            Some(release) => format!("{}, {}: Update to v{}", hint, app.keymap.hint(Action::ShowUpdate), release.version),
+            None => hint,
         }
     };
 
-    let status_style = if app.error_message.is_some() || app.download_status.as_deref().unwrap_or("").contains("Error") || app.download_status.as_deref().unwrap_or("").contains("Failed") {
-        Style::default().fg(Color::Red)
-    } else if app.is_downloading {
-         Style::default().fg(Color::Yellow) // Indicate ongoing download
+    // Marker + style per state, so the state is readable without color
+    let theme = app.theme;
+    let (marker, status_style) = if app.error_message.is_some() || app.download_status.as_deref().unwrap_or("").contains("Error") || app.download_status.as_deref().unwrap_or("").contains("Failed") {
+        (ERROR_MARKER, theme.error())
+    } else if app.is_downloading || app.is_loading || app.is_loading_details {
+         (BUSY_MARKER, theme.busy()) // Indicate ongoing work
     } else if app.download_status.is_some() {
-         Style::default().fg(Color::Green) // Indicate completed download (if no error)
+         (SUCCESS_MARKER, theme.success()) // Indicate completed download (if no error)
     } else {
-        Style::default()
+        ("", Style::default())
     };
 
-    let status_paragraph = Paragraph::new(status_text).style(status_style);
-    frame.render_widget(status_paragraph, area);
+    // A gauge of the whole run at the right end while downloading
+    let progress = app.run_progress();
+    let mut text_area = area;
+    if let (true, Some(ratio), Some(label)) = (app.is_downloading, progress.ratio(), progress::gauge_label(&progress)) {
+        let gauge_width = STATUS_GAUGE_WIDTH.min(area.width / 3);
+        let [text, gauge] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(gauge_width)])
+            .areas(area);
+        let gauge_widget = LineGauge::default().ratio(ratio).label(label).filled_style(theme.busy()).unfilled_style(theme.muted());
+        frame.render_widget(gauge_widget, gauge);
+        text_area = text;
+    }
+
+    // Offline mode (or a network outage) stays visible whatever else the bar says
+    let network = app.network.label().map_or(String::new(), |label| format!("[{}] ", label));
+    let status_paragraph = Paragraph::new(format!("{}{}{}", marker, network, status_text)).style(status_style);
+    frame.render_widget(status_paragraph, text_area);
 }
@@ -1,4 +1,28 @@
-use crate::app::{ActivePane, App, AppState, DownloadAction, UpdateAction};
+use crate::app::{ActivePane, App, AppState, Breadcrumb, DownloadAction, Listing, ItemTab, UpdateAction, UploadForm, UploadStatus, WaybackView, UPLOAD_FIELDS};
+use crate::archive_api;
+use crate::auth::LoginRequest;
+use crate::estimate::SizeEstimate;
+use crate::exclusions::{self, ExclusionKind};
+use crate::export;
+use crate::fetch_filter;
+use crate::filters::ItemFilter;
+use crate::history::Place;
+use crate::keymap::{Action, KeyContext};
+use crate::ia_upload;
+use crate::layout;
+use crate::list_nav::Motion;
+use crate::log_buffer;
+use crate::offline::{self, NetworkMode};
+use crate::palette;
+use crate::presets::{self, FilePreset};
+use crate::preview;
+use crate::retry;
+use crate::saved_search;
+use crate::segments;
+use crate::sync;
+use crate::tagging::TagPolicy;
+use crate::throttle;
+use crate::wayback::{self, SnapshotQuery};
 // Removed unused settings import
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::widgets::ListState;
@@ -6,6 +30,12 @@ use ratatui::widgets::ListState;
 /// Handle key events based on the current application state.
 /// Returns an optional `UpdateAction` to be performed by the main loop.
 pub fn update(app: &mut App, key_event: KeyEvent) -> Option<UpdateAction> {
+    let action = handle_key(app, key_event);
+    app.list_nav.end_key();
+    action
+}
+
+fn handle_key(app: &mut App, key_event: KeyEvent) -> Option<UpdateAction> {
     // Clear pending action and non-sticky messages at the start
     app.pending_action = None;
     if !app.is_downloading {
@@ -13,46 +43,46 @@ pub fn update(app: &mut App, key_event: KeyEvent) -> Option<UpdateAction> {
     }
     // Clear general error messages unless in a state that displays specific errors
     match app.current_state {
-        AppState::AskingDownloadDir | AppState::EditingSetting | AppState::AddingCollection => {} // Keep errors in input modes
+        AppState::AskingDownloadDir | AppState::EditingSetting | AppState::AddingCollection | AppState::AddingExclusion | AppState::EnteringSearch | AppState::FilteringItems | AppState::EnteringExportPath | AppState::EditingUpload | AppState::EnteringFavoritesUser | AppState::EnteringWaybackUrl | AppState::EnteringFetchFilter | AppState::EnteringCollectionGroup | AppState::EnteringCollectionAlias | AppState::EnteringCommand => {} // Keep errors in input modes
         _ => app.error_message = None, // Clear errors in other states
     }
 
     // --- Global Keys ---
-    match key_event.code {
-        KeyCode::Char('q') => {
-            app.quit();
-            return None;
+    // Keys are literal characters while typing a setting value, exclusion pattern, search query, item filter, export path, upload field, screen name, URL or command
+    let typing = is_typing(&app.current_state);
+    if let Some(action) = app.keymap.action(KeyContext::Global, &key_event).filter(|_| !typing) {
+        if global_action(app, action) {
+            return app.pending_action.clone();
         }
+    }
+    match key_event.code {
         KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
             app.quit();
             return None;
         }
-        // Global 's' to enter settings (unless in an input mode)
-        KeyCode::Char('s') => {
-             match app.current_state {
-                 AppState::Browsing | AppState::ViewingItem => {
-                     app.current_state = AppState::SettingsView;
-                     app.settings_list_state.select(Some(app.selected_setting_index));
-                     return None;
-                 }
-                 _ => {} // Ignore 's' in other states like input modes
-             }
+        // Pastes the system clipboard into the input (see [`paste`])
+        KeyCode::Char('v') | KeyCode::Char('V') if key_event.modifiers == KeyModifiers::CONTROL && is_text_input(&app.current_state) => {
+            return Some(UpdateAction::PasteClipboard);
         }
         // Global Esc handling (exit input modes or quit)
         KeyCode::Esc => {
             match app.current_state {
-                AppState::AskingDownloadDir | AppState::EditingSetting | AppState::AddingCollection => {
+                AppState::AskingDownloadDir | AppState::EditingSetting | AppState::AddingCollection | AppState::AddingExclusion | AppState::EnteringSearch | AppState::FilteringItems | AppState::EnteringExportPath | AppState::EditingUpload | AppState::EnteringFavoritesUser | AppState::EnteringWaybackUrl | AppState::EnteringFetchFilter | AppState::EnteringCollectionGroup | AppState::EnteringCollectionAlias | AppState::EnteringCommand => {
                     // Handled within the specific state handlers to revert to previous state
                 }
-                AppState::ViewingItem | AppState::SettingsView => {
+                AppState::ViewingItem | AppState::SettingsView | AppState::ManagingExclusions | AppState::ViewingDownloads | AppState::ViewingWayback | AppState::ViewingHelp | AppState::ViewingLog | AppState::ViewingFailures => {
                     // Handled within the specific state handlers to revert to Browsing
                 }
+                AppState::Browsing if app.active_pane == ActivePane::Items && !app.collection_trail.is_empty() => {
+                    // Handled by the items pane (back up out of a sub-collection)
+                }
                 AppState::Browsing => {
                     // Esc in Browsing mode quits the app
                     app.quit();
                     return None;
                 }
                 AppState::Downloading => {} // Ignore Esc during download
+                AppState::UpdateAvailable | AppState::ConfirmingDownload | AppState::ResumePrompt | AppState::ViewingEstimate | AppState::ViewingCollectionReport => {} // Handled by the popup handlers (close them)
             }
         }
         _ => {} // Other keys are handled by state
@@ -67,716 +97,3641 @@ pub fn update(app: &mut App, key_event: KeyEvent) -> Option<UpdateAction> {
         AppState::EditingSetting => handle_editing_setting_input(app, key_event),
         AppState::AddingCollection => handle_adding_collection_input(app, key_event),
         AppState::Downloading => {} // Ignore most input during download
+        AppState::UpdateAvailable => handle_update_popup_input(app, key_event),
+        AppState::ConfirmingDownload => handle_confirm_download_input(app, key_event),
+        AppState::ResumePrompt => handle_resume_prompt_input(app, key_event),
+        AppState::ManagingExclusions => handle_managing_exclusions_input(app, key_event),
+        AppState::AddingExclusion => handle_adding_exclusion_input(app, key_event),
+        AppState::EnteringSearch => handle_entering_search_input(app, key_event),
+        AppState::ViewingDownloads => handle_viewing_downloads_input(app, key_event),
+        AppState::ViewingEstimate => handle_estimate_input(app, key_event),
+        AppState::ViewingCollectionReport => handle_collection_report_input(app, key_event),
+        AppState::FilteringItems => handle_filtering_items_input(app, key_event),
+        AppState::EnteringExportPath => handle_entering_export_path_input(app, key_event),
+        AppState::EditingUpload => handle_editing_upload_input(app, key_event),
+        AppState::EnteringFavoritesUser => handle_entering_favorites_user_input(app, key_event),
+        AppState::EnteringWaybackUrl => handle_entering_wayback_url_input(app, key_event),
+        AppState::EnteringFetchFilter => handle_entering_fetch_filter_input(app, key_event),
+        AppState::EnteringCollectionGroup => handle_entering_collection_group_input(app, key_event),
+        AppState::EnteringCollectionAlias => handle_entering_collection_alias_input(app, key_event),
+        AppState::ViewingWayback => handle_viewing_wayback_input(app, key_event),
+        AppState::ViewingHelp => handle_viewing_help_input(app, key_event),
+        AppState::EnteringCommand => handle_entering_command_input(app, key_event),
+        AppState::ViewingLog => handle_viewing_log_input(app, key_event),
+        AppState::ViewingFailures => handle_viewing_failures_input(app, key_event),
     }
 
     // Return the pending action, if any was set by the handlers
     app.pending_action.clone()
 }
 
-/// Handles input when in the main browsing state (`AppState::Browsing`).
-/// Dispatches to specific handlers based on the active pane.
-fn handle_browsing_input(app: &mut App, key_event: KeyEvent) {
-    // Handle Tab first to switch panes
-    if key_event.code == KeyCode::Tab {
-        app.active_pane = match app.active_pane {
-            ActivePane::Collections => ActivePane::Items,
-            ActivePane::Items => ActivePane::Collections,
-        };
-        return; // Pane switched, no further action needed for this event
-    }
+/// Whether keys are literal characters in `state` rather than actions.
+fn is_typing(state: &AppState) -> bool {
+    matches!(state, AppState::EditingSetting | AppState::AddingExclusion | AppState::EnteringSearch | AppState::FilteringItems | AppState::EnteringExportPath | AppState::EditingUpload | AppState::EnteringFavoritesUser | AppState::EnteringWaybackUrl | AppState::EnteringFetchFilter | AppState::EnteringCollectionGroup | AppState::EnteringCollectionAlias | AppState::EnteringCommand)
+}
+
+/// Whether `state` is a text prompt, whose input pasted text goes into.
+fn is_text_input(state: &AppState) -> bool {
+    matches!(state, AppState::AskingDownloadDir | AppState::AddingCollection) || is_typing(state)
+}
 
-    // Delegate to pane-specific handlers
-    match app.active_pane {
-        ActivePane::Collections => handle_collections_pane_input(app, key_event),
-        ActivePane::Items => handle_items_pane_input(app, key_event),
+/// Types pasted text (bracketed paste or the clipboard) into the current text prompt, as if
+/// each character had been pressed; line breaks are dropped. Outside text prompts pastes
+/// are ignored, so pasted text can't trigger actions.
+pub fn paste(app: &mut App, text: &str) {
+    if !is_text_input(&app.current_state) {
+        return;
+    }
+    for c in text.chars().filter(|c| !c.is_control()) {
+        update(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
     }
 }
 
-/// Handles key events when the Collections pane is active.
-fn handle_collections_pane_input(app: &mut App, key_event: KeyEvent) {
-    match key_event.code {
-        // Navigation
-        KeyCode::Down => app.select_next_collection(),
-        KeyCode::Up => app.select_previous_collection(),
+/// Applies a coalesced navigation key `steps` times (see [`crate::event::Event::KeyRepeat`]).
+/// Only navigation keys are coalesced, so at most the last action is returned.
+pub fn update_repeated(app: &mut App, key_event: KeyEvent, steps: usize) -> Option<UpdateAction> {
+    let mut action = None;
+    for _ in 0..steps {
+        action = update(app, key_event);
+    }
+    action
+}
 
-        // Actions
-        KeyCode::Enter => {
-            if let Some(collection_name) = app.get_selected_collection().cloned() {
-                app.current_collection_name = Some(collection_name.clone());
-                app.items.clear(); // Clear previous items before attempting load/fetch
-                app.item_list_state.select(None);
-                app.total_items_found = None;
+/// Handles input when in the main browsing state (`AppState::Browsing`).
+/// Dispatches to specific handlers based on the active pane.
+fn handle_browsing_input(app: &mut App, key_event: KeyEvent) {
+    match app.keymap.action(KeyContext::Browsing, &key_event) {
+        Some(action) => {
+            browsing_action(app, action);
+        }
+        // Delegate to pane-specific handlers
+        None => match app.active_pane {
+            ActivePane::Collections => handle_collections_pane_input(app, key_event),
+            ActivePane::Items => handle_items_pane_input(app, key_event),
+        },
+    }
+}
 
-                // Attempt to load from cache first
-                match app.load_items_from_cache(&collection_name) {
-                    Ok(cached_items) => {
-                        log::info!("Loaded {} items from cache for collection '{}'", cached_items.len(), collection_name);
-                        app.items = cached_items;
-                        app.total_items_found = Some(app.items.len()); // Set total found from cache
-                        app.is_loading = false; // Not loading from network
-                        // Select first item if cache wasn't empty
-                        if !app.items.is_empty() {
-                            app.item_list_state.select(Some(0));
-                        }
-                        app.pending_action = None; // No network fetch needed
-                    }
-                    Err(e) => {
-                        // Cache miss or error, proceed with network fetch
-                        log::warn!("Failed to load items from cache for '{}' ({}). Fetching from network.", collection_name, e);
-                        // Keep items clear, reset selection/count
-                        app.items.clear();
-                        app.item_list_state.select(None);
-                        app.total_items_found = None;
-                        app.is_loading = true; // Set loading flag for network fetch
-                        // Dispatch the action to start bulk fetching
-                        app.pending_action = Some(UpdateAction::StartBulkItemFetch(collection_name.clone()));
-                    }
-                }
-                // Always switch focus to items pane after attempting load or starting fetch
-                app.active_pane = ActivePane::Items;
-            }
+/// Performs an action of [`KeyContext::Global`]. Returns `false` if it doesn't apply in
+/// the current state.
+fn global_action(app: &mut App, action: Action) -> bool {
+    let browsing = matches!(app.current_state, AppState::Browsing | AppState::ViewingItem);
+    let listing_keys = browsing || matches!(app.current_state, AppState::ViewingDownloads | AppState::ViewingFailures);
+    match action {
+        Action::Quit => app.quit(),
+        // Back/forward through visited collections, searches and items
+        Action::Back | Action::Forward if browsing => navigate_history(app, action == Action::Forward),
+        Action::Settings if browsing => {
+            app.current_state = AppState::SettingsView;
+            app.settings_list_state.select(Some(app.selected_setting_index));
         }
-        KeyCode::Char('a') => {
-            // Enter Add Collection mode
-            app.current_state = AppState::AddingCollection;
-            app.add_collection_input.clear();
-            app.add_collection_cursor_pos = 0;
+        Action::Help if listing_keys => {
+            app.help_return_state = app.current_state.clone();
+            app.help_table_state.select(Some(0));
+            app.current_state = AppState::ViewingHelp;
         }
-        KeyCode::Delete | KeyCode::Backspace => { // Use Delete or Backspace to remove
-            if let Some(selected_collection) = app.get_selected_collection().cloned() {
-                if app.remove_selected_collection() {
-                    // If a collection was removed, trigger save
-                    app.pending_action = Some(UpdateAction::SaveSettings);
-                    // Clear items list if the removed collection was the one being viewed
-                    if app.current_collection_name.as_ref() == Some(&selected_collection) {
-                         app.items.clear();
-                         app.item_list_state.select(None);
-                         app.current_collection_name = None; // No collection selected anymore
-                         app.total_items_found = None;
-                    }
-                }
-            }
+        Action::CommandPalette if listing_keys => open_command_palette(app),
+        Action::ToggleLog if listing_keys => {
+            app.log_return_state = app.current_state.clone();
+            app.log_list_state.select(None);
+            app.current_state = AppState::ViewingLog;
         }
-        KeyCode::Char('d') | KeyCode::Char('b') => { // 'd' or 'b' to download selected collection
-            if let Some(collection_name) = app.get_selected_collection().cloned() {
-                if app.settings.download_directory.is_none() {
-                    app.current_state = AppState::AskingDownloadDir;
-                    // Use editing_setting_input for the path temporarily
-                    app.editing_setting_input.clear();
-                    app.cursor_position = 0;
-                } else {
-                    // Trigger download for the selected collection
-                    app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Collection(collection_name.clone())));
-                    app.download_status = Some(format!("Queueing download for collection: {}", collection_name));
-                }
+        Action::ToggleOffline if listing_keys => {
+            app.settings.offline_mode = !app.settings.offline_mode;
+            // Going online tries the network again, even if it was down
+            app.network = if app.settings.offline_mode { NetworkMode::Offline } else { NetworkMode::Online };
+            app.download_status = Some(if app.settings.offline_mode {
+                "Offline mode: browsing the cached collections and items only".to_string()
             } else {
-                app.error_message = Some("Select a collection to download.".to_string());
+                "Online again".to_string()
+            });
+            app.pending_action = Some(UpdateAction::SaveSettings);
+        }
+        Action::StopPreview if listing_keys && app.preview.is_some() => {
+            if let Some(preview) = app.preview.take() {
+                app.download_status = Some(format!("Stopped {}", preview.file_name));
             }
         }
-
-        _ => {} // Ignore other keys
+        _ => return false,
     }
+    true
 }
 
-/// Handles key events when the Items pane is active.
-fn handle_items_pane_input(app: &mut App, key_event: KeyEvent) {
-    match key_event.code {
-        // Navigation
-        KeyCode::Down => app.select_next_item(),
-        KeyCode::Up => app.select_previous_item(),
-
-        // Actions
-        KeyCode::Enter => {
-            // View item details
-            if let Some(selected_index) = app.item_list_state.selected() {
-                if let Some(item) = app.items.get(selected_index) {
-                    app.viewing_item_id = Some(item.identifier.clone());
-                    app.current_state = AppState::ViewingItem;
-                    app.current_item_details = None; // Clear previous details
-                    app.file_list_state = ListState::default(); // Reset file list selection
-                    app.is_loading_details = true; // Set flag
-                    app.pending_action = Some(UpdateAction::FetchItemDetails);
-                }
-            }
+/// Performs an action of [`KeyContext::Browsing`]. Returns `false` if it doesn't apply.
+fn browsing_action(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::SwitchPane => {
+            app.active_pane = match app.active_pane {
+                ActivePane::Collections => ActivePane::Items,
+                ActivePane::Items => ActivePane::Collections,
+            };
         }
-        KeyCode::Char('d') => { // Download selected item
-            if let Some(selected_index) = app.item_list_state.selected() {
-                if let Some(item) = app.items.get(selected_index) {
-                    if app.settings.download_directory.is_none() {
-                        app.current_state = AppState::AskingDownloadDir;
-                        app.editing_setting_input.clear();
-                        app.cursor_position = 0;
-                    } else {
-                        app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(item.identifier.clone())));
-                        app.download_status = Some(format!("Queueing download for item: {}", item.identifier));
-                    }
-                }
-            } else {
-                app.error_message = Some("Select an item to download.".to_string());
-            }
+        // Picks the file preset for the next item/collection download
+        Action::CyclePreset => cycle_download_preset(app),
+        // Searches archive.org; results replace the items pane contents
+        Action::Search => {
+            app.current_state = AppState::EnteringSearch;
+            app.editing_setting_input = app.search_query.clone().unwrap_or_default();
+            app.cursor_position = app.editing_setting_input.chars().count();
         }
-        KeyCode::Char('b') => { // Bulk download all items in the *current view*
-            if let Some(collection_name) = app.current_collection_name.clone() {
-                 if app.settings.download_directory.is_none() {
-                     app.current_state = AppState::AskingDownloadDir;
-                     app.editing_setting_input.clear();
-                     app.cursor_position = 0;
-                 } else if !app.items.is_empty() {
-                     // Trigger download for the currently loaded collection
-                     // Note: This re-uses the Collection action, which might re-fetch identifiers.
-                     // A future optimization could pass the already loaded identifiers.
-                     app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Collection(collection_name.clone())));
-                     app.download_status = Some(format!("Queueing bulk download for collection: {}", collection_name));
-                 } else {
-                     app.error_message = Some("No items listed to download.".to_string());
-                 }
+        // Opens the report of the last download run
+        Action::OpenReport => match app.last_report_path.clone() {
+            Some(path) if path.exists() => app.pending_action = Some(UpdateAction::OpenPath(path)),
+            Some(path) => app.error_message = Some(format!("Report {} no longer exists.", path.display())),
+            None => app.error_message = Some("No download report yet; one is written when a download finishes.".to_string()),
+        },
+        // Uploads a local directory to archive.org
+        Action::Upload => {
+            if app.upload.is_some() {
+                app.error_message = Some("An upload is already running.".to_string());
             } else {
-                 app.error_message = Some("No collection selected to download items from.".to_string());
+                app.current_state = AppState::EditingUpload;
             }
         }
-
-        _ => {} // Ignore other keys
+        // Looks up a URL in the Wayback Machine
+        Action::Wayback => {
+            app.current_state = AppState::EnteringWaybackUrl;
+            app.editing_setting_input = app.wayback.as_ref().map(|view| view.query.url.clone()).unwrap_or_default();
+            app.cursor_position = app.editing_setting_input.chars().count();
+        }
+        // Shows the files being downloaded with their progress
+        Action::ToggleDownloads => app.current_state = AppState::ViewingDownloads,
+        // Lists what failed in the last download run
+        Action::ToggleFailures => {
+            app.failures_table_state.select(if app.failures.is_empty() { None } else { Some(0) });
+            app.current_state = AppState::ViewingFailures;
+        }
+        // Reopens the release notes of an available update
+        Action::ShowUpdate if app.available_update.is_some() => app.current_state = AppState::UpdateAvailable,
+        // Starts a sync round of the favorite collections without waiting for the interval
+        Action::SyncNow => app.pending_action = Some(UpdateAction::SyncNow),
+        _ => return false,
     }
+    true
 }
 
+/// Opens the command palette over the current screen.
+fn open_command_palette(app: &mut App) {
+    app.command_return_state = app.current_state.clone();
+    app.editing_setting_input.clear();
+    app.cursor_position = 0;
+    app.command_list_state.select(Some(0));
+    app.current_state = AppState::EnteringCommand;
+}
 
-/// Handles input when prompting for the download directory.
-/// Uses the `editing_setting_input` buffer and `cursor_position`.
-fn handle_asking_download_dir_input(app: &mut App, key_event: KeyEvent) {
+/// Handles typing in the command palette (`AppState::EnteringCommand`): the commands
+/// matching the input are listed, and Enter runs the selected one where the palette was
+/// opened.
+fn handle_entering_command_input(app: &mut App, key_event: KeyEvent) {
+    let commands = palette::matching(&app.editing_setting_input, &palette::contexts(&app.command_return_state, &app.active_pane));
     match key_event.code {
         KeyCode::Esc => {
-            // Cancel entering download dir and return to browsing
-            app.current_state = AppState::Browsing;
-            app.editing_setting_input.clear(); // Clear the temp input
-            app.error_message = None;
+            app.current_state = app.command_return_state.clone();
+            app.editing_setting_input.clear();
         }
-        KeyCode::Char(to_insert) => {
-            app.enter_char_edit_setting(to_insert);
+        KeyCode::Enter => {
+            let Some(action) = app.command_list_state.selected().and_then(|i| commands.get(i)).copied() else {
+                app.error_message = Some("No command matches; Esc to cancel.".to_string());
+                return;
+            };
+            app.current_state = app.command_return_state.clone();
+            app.editing_setting_input.clear();
+            run_action(app, action);
+        }
+        KeyCode::Down if !commands.is_empty() => {
+            let next = app.command_list_state.selected().map_or(0, |i| (i + 1) % commands.len());
+            app.command_list_state.select(Some(next));
+        }
+        KeyCode::Up if !commands.is_empty() => {
+            let previous = app.command_list_state.selected().map_or(0, |i| if i == 0 { commands.len() - 1 } else { i - 1 });
+            app.command_list_state.select(Some(previous));
+        }
+        KeyCode::Char(c) => {
+            app.enter_char_edit_setting(c);
+            app.command_list_state.select(Some(0)); // The best match of the new input
         }
         KeyCode::Backspace => {
             app.delete_char_edit_setting();
+            app.command_list_state.select(Some(0));
         }
-        KeyCode::Left => {
-            app.move_cursor_left_edit_setting();
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
+    }
+}
+
+/// Performs `action` as its key would in the current state (used by the command palette).
+fn run_action(app: &mut App, action: Action) {
+    let done = global_action(app, action)
+        || match app.current_state {
+            AppState::Browsing => {
+                browsing_action(app, action)
+                    || match app.active_pane {
+                        ActivePane::Collections => collections_action(app, action),
+                        ActivePane::Items => items_action(app, action),
+                    }
+            }
+            AppState::ViewingItem => item_view_action(app, action),
+            AppState::ViewingDownloads => downloads_action(app, action),
+            AppState::ViewingFailures => failures_action(app, action),
+            _ => false,
+        };
+    if !done {
+        app.error_message = Some(format!("'{}' can't be done here.", action.description()));
+    }
+}
+
+/// Handles input on the help screen (`AppState::ViewingHelp`), which lists the active key
+/// bindings.
+fn handle_viewing_help_input(app: &mut App, key_event: KeyEvent) {
+    let rows = app.keymap.help().iter().map(|(_, actions)| actions.len() + 1).sum::<usize>();
+    match key_event.code {
+        KeyCode::Esc => app.current_state = app.help_return_state.clone(),
+        KeyCode::Down => {
+            let next = app.help_table_state.selected().map_or(0, |i| (i + 1).min(rows - 1));
+            app.help_table_state.select(Some(next));
         }
-        KeyCode::Right => {
-            app.move_cursor_right_edit_setting();
+        KeyCode::Up => {
+            let previous = app.help_table_state.selected().map_or(0, |i| i.saturating_sub(1));
+            app.help_table_state.select(Some(previous));
         }
-        KeyCode::Enter => {
-            let entered_path = app.editing_setting_input.trim().to_string();
-            if !entered_path.is_empty() {
-                app.settings.download_directory = Some(entered_path);
-                // Trigger save settings action
-                app.pending_action = Some(UpdateAction::SaveSettings);
-                app.current_state = AppState::Browsing; // Return to browsing
-                app.editing_setting_input.clear(); // Clear the temp input
-                // Set a confirmation message (will be cleared on next update unless error)
-                app.error_message = Some("Download directory saved. Press 'd'/'b' again to start download.".to_string());
-            } else {
-                app.error_message = Some("Download directory cannot be empty. Press Esc to cancel.".to_string());
+        _ if app.keymap.action(KeyContext::Global, &key_event) == Some(Action::Help) => {
+            app.current_state = app.help_return_state.clone();
+        }
+        _ => {}
+    }
+}
+
+/// Handles input in the log view (`AppState::ViewingLog`): scrolling back through the
+/// recent log lines and choosing the levels shown.
+fn handle_viewing_log_input(app: &mut App, key_event: KeyEvent) {
+    if key_event.code == KeyCode::Esc || app.keymap.action(KeyContext::Global, &key_event) == Some(Action::ToggleLog) {
+        app.current_state = app.log_return_state.clone();
+        return;
+    }
+    match app.keymap.action(KeyContext::Log, &key_event) {
+        Some(Action::LogLevel) => {
+            app.log_level = log_buffer::next_level(app.log_level);
+            app.log_list_state.select(None);
+        }
+        _ => {
+            if let Some(motion) = app.list_nav.motion(&key_event) {
+                // Nothing selected follows the newest line; moving from there starts at it
+                let len = app.log_buffer.lines(app.log_level).len();
+                let selected = app.log_list_state.selected().or(len.checked_sub(1));
+                let moved = motion.apply(selected, len, app.page_rows.log).filter(|&i| i + 1 < len);
+                app.log_list_state.select(moved);
             }
         }
-        _ => {} // Ignore other keys
     }
 }
 
+/// Handles input in the failures view (`AppState::ViewingFailures`), which lists what failed
+/// in the last download run.
+fn handle_viewing_failures_input(app: &mut App, key_event: KeyEvent) {
+    if key_event.code == KeyCode::Esc {
+        app.current_state = AppState::Browsing;
+        return;
+    }
+    match app.keymap.action(KeyContext::Failures, &key_event) {
+        Some(action) => {
+            failures_action(app, action);
+        }
+        None => {
+            if let Some(motion) = app.list_nav.motion(&key_event) {
+                let selected = app.failures_table_state.selected();
+                app.failures_table_state.select(motion.apply(selected, app.failures.failures.len(), app.page_rows.failures));
+            }
+        }
+    }
+}
 
-/// Handles input when viewing item details.
-fn handle_viewing_item_input(app: &mut App, key_event: KeyEvent) {
-    match key_event.code {
-        KeyCode::Esc => {
-            // Go back to browsing
-            app.current_state = AppState::Browsing;
-            app.viewing_item_id = None;
-            app.current_item_details = None;
-            app.file_list_state = ListState::default();
-            // Active pane remains Items (usually where you came from)
-        }
-        KeyCode::Down => app.select_next_file(),
-        KeyCode::Up => app.select_previous_file(),
-        KeyCode::Enter | KeyCode::Char('d') => {
-            // Download selected file
-            if let Some(file_details) = app.get_selected_file().cloned() {
-                if let Some(item_id) = app.viewing_item_id.clone() {
-                    if app.settings.download_directory.is_none() {
-                        app.current_state = AppState::AskingDownloadDir;
-                        app.editing_setting_input.clear();
-                        app.cursor_position = 0;
-                    } else {
-                        app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::File(item_id, file_details.clone())));
-                        app.download_status = Some(format!("Queueing download for file: {}", file_details.name));
-                        // Maybe switch back to Browsing view after queuing? Or stay? Staying for now.
-                    }
-                }
+/// Performs an action of [`KeyContext::Failures`]. Returns `false` if it doesn't apply.
+fn failures_action(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::ToggleFailures => app.current_state = AppState::Browsing,
+        Action::RetryFailed => {
+            let ids = app.failures.identifiers();
+            if ids.is_empty() {
+                app.error_message = Some("Nothing failed in the last download run.".to_string());
+            } else if app.settings.download_directory.is_none() {
+                app.error_message = Some("Set a download directory first.".to_string());
             } else {
-                app.error_message = Some("Select a file to download.".to_string());
+                // Files already on disk are skipped, so only what failed is fetched again
+                app.download_status = Some(format!("Queueing download for {} failed items", ids.len()));
+                app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Items(ids)));
+                app.current_state = AppState::Browsing;
             }
         }
-        KeyCode::Char('b') => { // Download all files for this item
-            if let Some(item_id) = app.viewing_item_id.clone() {
-                if app.settings.download_directory.is_none() {
-                    app.current_state = AppState::AskingDownloadDir;
-                    app.editing_setting_input.clear();
-                    app.cursor_position = 0;
-                } else {
-                    app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(item_id.clone())));
-                    app.download_status = Some(format!("Queueing download for all files in item: {}", item_id));
-                    // Maybe switch back to Browsing view after queuing? Or stay? Staying for now.
-                }
+        _ => return false,
+    }
+    true
+}
+
+/// Handles input in the Downloads view (`AppState::ViewingDownloads`): pausing, resuming
+/// and cancelling the item of the selected file, or every download.
+fn handle_viewing_downloads_input(app: &mut App, key_event: KeyEvent) {
+    let count = app.active_transfers.len();
+    match key_event.code {
+        KeyCode::Esc => app.current_state = AppState::Browsing,
+        KeyCode::Down if count > 0 => {
+            let next = app.transfers_table_state.selected().map_or(0, |i| (i + 1) % count);
+            app.transfers_table_state.select(Some(next));
+        }
+        KeyCode::Up if count > 0 => {
+            let previous = app.transfers_table_state.selected().map_or(0, |i| if i == 0 { count - 1 } else { i - 1 });
+            app.transfers_table_state.select(Some(previous));
+        }
+        _ => {
+            if let Some(action) = app.keymap.action(KeyContext::Downloads, &key_event) {
+                downloads_action(app, action);
             }
         }
-        _ => {} // Ignore other keys
     }
 }
 
+/// Performs an action of [`KeyContext::Downloads`]. Returns `false` if it doesn't apply.
+fn downloads_action(app: &mut App, action: Action) -> bool {
+    let rows = app.active_transfers.rows(std::time::Instant::now());
+    let selected_item = app.transfers_table_state.selected().and_then(|i| rows.get(i)).map(|row| row.item_id.clone());
+    match action {
+        Action::ToggleDownloads => app.current_state = AppState::Browsing,
+        Action::Pause => {
+            if let Some(item) = selected_item {
+                let paused = app.download_control.toggle_pause_item(&item);
+                app.download_status = Some(format!("{} {}", if paused { "Paused" } else { "Resumed" }, item));
+            }
+        }
+        Action::PauseAll => {
+            let paused = app.download_control.toggle_pause_all();
+            app.download_status = Some(if paused { "Paused all downloads" } else { "Resumed all downloads" }.to_string());
+        }
+        Action::Cancel => {
+            if let Some(item) = selected_item {
+                app.download_control.cancel_item(&item);
+                app.download_status = Some(format!("Cancelling {}; partial files are kept for resuming.", item));
+            }
+        }
+        Action::CancelAll => app.cancel_all_downloads(),
+        _ => return false,
+    }
+    true
+}
 
-use crate::settings::DownloadMode; // Import the new enum
+/// Opens the export path prompt, suggesting `<download dir>/<name>.csv`.
+fn start_export(app: &mut App, name: &str, item: bool) {
+    let file_name = format!("{}.csv", name);
+    let path = match app.settings.download_directory.as_deref() {
+        Some(dir) => std::path::Path::new(dir).join(file_name).to_string_lossy().into_owned(),
+        None => file_name,
+    };
+    app.exporting_item = item;
+    app.editing_setting_input = path;
+    app.cursor_position = app.editing_setting_input.chars().count();
+    app.current_state = AppState::EnteringExportPath;
+}
 
-/// Handles input when viewing/editing settings.
-fn handle_settings_view_input(app: &mut App, key_event: KeyEvent) {
-    let num_settings = 4; // Download Dir, Download Mode, File Concurrency, Collection Concurrency
+/// Handles typing the export path (`AppState::EnteringExportPath`). The extension picks
+/// the format: `.json` for JSON, anything else CSV.
+fn handle_entering_export_path_input(app: &mut App, key_event: KeyEvent) {
+    let previous_state = if app.exporting_item { AppState::ViewingItem } else { AppState::Browsing };
     match key_event.code {
         KeyCode::Esc => {
-            // Exit settings view, save, return to browsing
-            app.current_state = AppState::Browsing;
-            // Trigger save settings action
-            app.pending_action = Some(UpdateAction::SaveSettings);
-        }
-        KeyCode::Down => {
-            app.selected_setting_index = (app.selected_setting_index + 1) % num_settings;
-            app.settings_list_state.select(Some(app.selected_setting_index));
-        }
-        KeyCode::Up => {
-            app.selected_setting_index = if app.selected_setting_index == 0 {
-                num_settings - 1
-            } else {
-                app.selected_setting_index - 1
-            };
-            app.settings_list_state.select(Some(app.selected_setting_index));
+            app.current_state = previous_state;
+            app.editing_setting_input.clear();
         }
-        KeyCode::Right | KeyCode::Left => { // Use Left/Right to cycle/adjust
-            match app.selected_setting_index {
-                1 => { // Download Mode (Cycle)
-                    app.settings.download_mode = match app.settings.download_mode {
-                        DownloadMode::Direct => DownloadMode::TorrentOnly,
-                        DownloadMode::TorrentOnly => DownloadMode::Direct,
-                    };
-                }
-                2 => { // File Concurrency (Adjust)
-                    let current = app.settings.max_concurrent_downloads.unwrap_or(1);
-                    let new_val = if key_event.code == KeyCode::Right {
-                        current.saturating_add(1)
-                    } else {
-                        current.saturating_sub(1).max(1) // Min 1
-                    };
-                    app.settings.max_concurrent_downloads = Some(new_val);
+        KeyCode::Enter => {
+            let path = std::path::PathBuf::from(app.editing_setting_input.trim());
+            if path.as_os_str().is_empty() {
+                app.error_message = Some("Type the file to export to first.".to_string());
+                return;
+            }
+            let result = match (app.exporting_item, &app.current_item_details) {
+                (true, Some(details)) => export::write_item(&path, details).map(|_| format!("{} files of {}", details.files.len(), details.identifier)),
+                (true, None) => Err(anyhow::anyhow!("Item details are no longer loaded")),
+                (false, _) => {
+                    let items = app.visible_items();
+                    export::write_items(&path, &items).map(|_| format!("{} items", items.len()))
                 }
-                3 => { // Collection Concurrency (Adjust)
-                    let current = app.settings.max_concurrent_collections.unwrap_or(1);
-                     let new_val = if key_event.code == KeyCode::Right {
-                        current.saturating_add(1)
-                    } else {
-                        current.saturating_sub(1).max(1) // Min 1
-                    };
-                    app.settings.max_concurrent_collections = Some(new_val);
+            };
+            match result {
+                Ok(what) => {
+                    log::info!("Exported {} to {}", what, path.display());
+                    app.download_status = Some(format!("Exported {} to {}", what, path.display()));
+                    app.editing_setting_input.clear();
+                    app.current_state = previous_state;
                 }
-                _ => {} // No Left/Right action for Download Dir (index 0)
-            }
-        }
-        KeyCode::Enter => {
-            // Enter edit mode only for Download Directory (index 0)
-            if app.selected_setting_index == 0 {
-                app.current_state = AppState::EditingSetting;
-                app.editing_setting_input = app.settings.download_directory.clone().unwrap_or_default();
-                app.cursor_position = app.editing_setting_input.len();
+                Err(e) => app.error_message = Some(format!("{:#}", e)),
             }
         }
-        _ => {} // Ignore other keys
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
     }
 }
 
+/// Asks the main loop to fetch `user`'s favorites; the screen name is remembered once the
+/// import succeeds.
+fn start_favorites_import(app: &mut App, user: String) {
+    app.download_status = Some(format!("Importing the favorites of '{}'...", user));
+    app.pending_action = Some(UpdateAction::ImportFavorites(user));
+}
 
-/// Handles input when actively editing a setting value (only Download Dir for now).
-/// Uses `editing_setting_input` and `cursor_position`.
-fn handle_editing_setting_input(app: &mut App, key_event: KeyEvent) {
+/// Handles typing the screen name for the favorites import (`AppState::EnteringFavoritesUser`).
+fn handle_entering_favorites_user_input(app: &mut App, key_event: KeyEvent) {
     match key_event.code {
         KeyCode::Esc => {
-            // Cancel editing, revert to SettingsView
-            app.current_state = AppState::SettingsView;
+            app.current_state = AppState::Browsing;
             app.editing_setting_input.clear();
-            app.error_message = None;
         }
-        KeyCode::Char(to_insert) => {
-            app.enter_char_edit_setting(to_insert);
+        KeyCode::Enter => {
+            let user = app.editing_setting_input.trim().trim_start_matches('@').to_string();
+            if user.is_empty() {
+                app.error_message = Some("Type an archive.org screen name first.".to_string());
+                return;
+            }
+            app.editing_setting_input.clear();
+            app.current_state = AppState::Browsing;
+            start_favorites_import(app, user);
         }
-        KeyCode::Backspace => {
-            app.delete_char_edit_setting();
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
+    }
+}
+
+/// Handles typing the fetch filter of the selected collection (`AppState::EnteringFetchFilter`).
+/// An empty filter lists the whole collection again.
+fn handle_entering_fetch_filter_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::Browsing;
+            app.editing_setting_input.clear();
         }
-        KeyCode::Left => {
-            app.move_cursor_left_edit_setting();
+        KeyCode::Enter => {
+            let Some(collection_name) = app.get_selected_collection().cloned() else {
+                app.current_state = AppState::Browsing;
+                return;
+            };
+            let filter = app.editing_setting_input.trim().to_string();
+            let query = match fetch_filter::to_query(&filter) {
+                Ok(query) => query,
+                Err(e) => {
+                    app.error_message = Some(format!("{:#}", e));
+                    return;
+                }
+            };
+            app.editing_setting_input.clear();
+            app.current_state = AppState::Browsing;
+            if app.settings.fetch_filters.get(&collection_name).map(String::as_str).unwrap_or("") == filter {
+                return; // Unchanged; the cached listing still fits
+            }
+            match query {
+                Some(query) => {
+                    log::info!("Fetch filter for {}: {}", collection_name, query);
+                    app.settings.fetch_filters.insert(collection_name.clone(), filter.clone());
+                    app.download_status = Some(format!("Fetch filter for {}: {}", collection_name, filter));
+                }
+                None => {
+                    app.settings.fetch_filters.remove(&collection_name);
+                    app.download_status = Some(format!("Fetch filter for {} removed; the whole collection is listed", collection_name));
+                }
+            }
+            // The cached listing was made with the old filter
+            if let Err(e) = app.forget_item_cache(&collection_name) {
+                log::error!("{:#}", e);
+            }
+            if app.current_collection_name.as_ref() == Some(&collection_name) && app.search_query.is_none() {
+                app.items.clear();
+                app.item_list_state.select(None);
+                app.total_items_found = None;
+                app.current_collection_name = None; // Opening it again lists it with the new filter
+                app.collection_trail.clear();
+            }
+            app.pending_action = Some(UpdateAction::SaveSettings);
         }
-        KeyCode::Right => {
-            app.move_cursor_right_edit_setting();
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
+    }
+}
+
+/// Handles typing the group of the selected collection (`AppState::EnteringCollectionGroup`).
+/// An empty name takes the collection out of its group.
+fn handle_entering_collection_group_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::Browsing;
+            app.editing_setting_input.clear();
         }
         KeyCode::Enter => {
-            // Save the edited value back to the actual setting
-            let edited_value = app.editing_setting_input.trim().to_string();
-            if app.selected_setting_index == 0 { // Download Directory
-                app.settings.download_directory = if edited_value.is_empty() { None } else { Some(edited_value) };
-            }
-            // No need to trigger save action here, Esc from SettingsView saves.
-            app.current_state = AppState::SettingsView;
+            app.current_state = AppState::Browsing;
+            let group = std::mem::take(&mut app.editing_setting_input);
+            let Some(collection_name) = app.get_selected_collection().cloned() else { return };
+            app.set_collection_group(&collection_name, &group);
+            app.download_status = Some(match app.settings.collection_groups.get(&collection_name) {
+                Some(group) => format!("{} is in group {}", collection_name, group),
+                None => format!("{} is in no group", collection_name),
+            });
+            app.pending_action = Some(UpdateAction::SaveSettings);
+        }
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
+    }
+}
+
+/// Handles typing the alias of the selected collection (`AppState::EnteringCollectionAlias`).
+/// An empty alias shows the collection's title (or identifier) again.
+fn handle_entering_collection_alias_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::Browsing;
             app.editing_setting_input.clear();
-            app.error_message = None; // Clear error from input mode
         }
-        _ => {} // Ignore other keys
+        KeyCode::Enter => {
+            app.current_state = AppState::Browsing;
+            let alias = std::mem::take(&mut app.editing_setting_input);
+            let Some(collection_name) = app.get_selected_collection().cloned() else { return };
+            app.set_collection_alias(&collection_name, &alias);
+            app.download_status = Some(format!("{} is listed as {}", collection_name, app.collection_display_name(&collection_name)));
+            app.pending_action = Some(UpdateAction::SaveSettings);
+        }
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
     }
 }
 
-/// Handles input when adding a new collection identifier.
-/// Uses `add_collection_input` and `add_collection_cursor_pos`.
-fn handle_adding_collection_input(app: &mut App, key_event: KeyEvent) {
-     match key_event.code {
+/// Handles typing `URL [FROM [TO]]` for a Wayback Machine lookup (`AppState::EnteringWaybackUrl`).
+fn handle_entering_wayback_url_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
         KeyCode::Esc => {
-            // Cancel adding, revert to Browsing
             app.current_state = AppState::Browsing;
-            app.add_collection_input.clear();
-            app.error_message = None;
+            app.editing_setting_input.clear();
         }
-        KeyCode::Char(to_insert) => {
-            app.enter_char_add_collection(to_insert);
+        KeyCode::Enter => match SnapshotQuery::parse(&app.editing_setting_input) {
+            Ok(query) => {
+                app.editing_setting_input.clear();
+                app.wayback = Some(WaybackView { query: query.clone(), snapshots: Vec::new(), loading: true, downloading: false, list_state: ListState::default() });
+                app.current_state = AppState::ViewingWayback;
+                app.download_status = Some(format!("Looking up {} in the Wayback Machine...", query.url));
+                app.pending_action = Some(UpdateAction::ListWayback(query));
+            }
+            Err(e) => app.error_message = Some(format!("{:#}", e)),
+        },
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
+    }
+}
+
+/// Handles the list of Wayback Machine captures (`AppState::ViewingWayback`): Enter/'d'
+/// saves the selected capture, 'a' every distinct capture listed.
+fn handle_viewing_wayback_input(app: &mut App, key_event: KeyEvent) {
+    let Some(view) = &mut app.wayback else {
+        app.current_state = AppState::Browsing;
+        return;
+    };
+    let count = view.snapshots.len();
+    let to_save = match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::Browsing;
+            return;
         }
-        KeyCode::Backspace => {
-            app.delete_char_add_collection();
+        KeyCode::Char('W') => {
+            app.current_state = AppState::EnteringWaybackUrl;
+            app.editing_setting_input = view.query.url.clone();
+            app.cursor_position = app.editing_setting_input.chars().count();
+            return;
         }
-        KeyCode::Left => {
-            app.move_cursor_left_add_collection();
+        KeyCode::Down if count > 0 => {
+            let next = view.list_state.selected().map_or(0, |i| (i + 1) % count);
+            view.list_state.select(Some(next));
+            return;
         }
-        KeyCode::Right => {
-            app.move_cursor_right_add_collection();
+        KeyCode::Up if count > 0 => {
+            let previous = view.list_state.selected().map_or(0, |i| if i == 0 { count - 1 } else { i - 1 });
+            view.list_state.select(Some(previous));
+            return;
+        }
+        KeyCode::Enter | KeyCode::Char('d') => match view.list_state.selected().and_then(|i| view.snapshots.get(i)) {
+            Some(snapshot) if snapshot.is_ok() => vec![snapshot.clone()],
+            Some(snapshot) => {
+                app.error_message = Some(format!("The {} capture is a {} response, not the page.", snapshot.date(), snapshot.status));
+                return;
+            }
+            None => return,
+        },
+        KeyCode::Char('a') => wayback::to_download(&view.snapshots, true),
+        _ => return,
+    };
+    if view.downloading {
+        app.error_message = Some("Captures are already being saved.".to_string());
+    } else if app.settings.download_directory.is_none() {
+        app.error_message = Some("Set a download directory first.".to_string());
+    } else if to_save.is_empty() {
+        app.error_message = Some("No successful captures to save.".to_string());
+    } else {
+        view.downloading = true;
+        app.download_status = Some(format!("Saving {} capture(s)...", to_save.len()));
+        app.pending_action = Some(UpdateAction::DownloadWayback(to_save));
+    }
+}
+
+/// Asks the main loop to open `path` if it has been downloaded.
+fn request_open(app: &mut App, path: Option<std::path::PathBuf>, name: &str) {
+    match path {
+        Some(path) if path.exists() => app.pending_action = Some(UpdateAction::OpenPath(path)),
+        Some(_) => app.error_message = Some(format!("'{}' has not been downloaded yet.", name)),
+        None => app.error_message = Some("Set a download directory first.".to_string()),
+    }
+}
+
+/// Asks the main loop to verify the downloaded files of `identifier`.
+fn request_verify(app: &mut App, identifier: String) {
+    match app.get_download_path_for_item(&identifier) {
+        Some(dir) if dir.is_dir() => {
+            app.download_status = Some(format!("Verifying {}...", identifier));
+            app.pending_action = Some(UpdateAction::VerifyItem(identifier, dir));
         }
+        Some(_) => app.error_message = Some(format!("'{}' has not been downloaded yet.", identifier)),
+        None => app.error_message = Some("Set a download directory first.".to_string()),
+    }
+}
+
+/// Listed size of what downloading `item` fetches. Only known without a preset or
+/// filter; otherwise the download task checks the space once it has the file list.
+fn item_size_for_download(app: &App, item: &crate::archive_api::ArchiveDoc) -> Option<u64> {
+    let whole_item = app.download_preset == FilePreset::AllFiles && app.settings.download_file_filter.is_empty();
+    item.item_size.filter(|_| whole_item)
+}
+
+/// Total listed size of the viewed item's files that the preset and filter select.
+fn selected_files_size(app: &App) -> Option<u64> {
+    let details = app.current_item_details.as_ref()?;
+    let files = app.download_preset.select(&details.files);
+    let sizes = files.iter().filter(|f| app.settings.file_source_policy.keeps(f) && presets::passes_file_filter(f, &app.settings.download_file_filter));
+    Some(sizes.filter_map(|f| f.size.as_deref().and_then(|s| s.parse::<u64>().ok())).sum())
+}
+
+/// Switches the file preset used by the next download and reports it.
+fn cycle_download_preset(app: &mut App) {
+    app.download_preset = app.download_preset.cycle();
+    app.download_status = Some(format!("File preset for downloads: {}", app.download_preset));
+}
+
+/// Handles key events when the Collections pane is active.
+fn handle_collections_pane_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
         KeyCode::Enter => {
-            let identifier = app.add_collection_input.trim().to_string();
-            if !identifier.is_empty() {
-                app.add_collection_to_favorites(identifier);
-                // Trigger save settings action
+            if let Some(collection_name) = app.get_selected_collection().cloned() {
+                remember_place(app);
+                app.collection_trail.clear(); // A top-level collection starts a new trail
+                open_collection(app, collection_name);
+            } else {
+                app.toggle_selected_collection_group(); // Fold or unfold the group
+            }
+        }
+        _ => match app.keymap.action(KeyContext::Collections, &key_event) {
+            Some(action) => {
+                collections_action(app, action);
+            }
+            // Navigation (arrows, hjkl, gg/G, counts) unless the key is bound to an action
+            None => match app.list_nav.motion(&key_event) {
+                Some(Motion::Right) => app.active_pane = ActivePane::Items,
+                Some(motion) => app.move_collection_selection(motion),
+                None => {}
+            },
+        },
+    }
+}
+
+/// Performs an action of [`KeyContext::Collections`] on the selected collection. Returns
+/// `false` if it doesn't apply.
+fn collections_action(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::AddCollection => {
+            // Enter Add Collection mode
+            app.current_state = AppState::AddingCollection;
+            app.add_collection_input.clear();
+            app.add_collection_cursor_pos = 0;
+        }
+        Action::RemoveCollection => {
+            if let Some(selected_collection) = app.get_selected_collection().cloned() {
+                if app.remove_selected_collection() {
+                    // If a collection was removed, trigger save
+                    app.pending_action = Some(UpdateAction::SaveSettings);
+                    // Clear items list if the removed collection was the one being viewed
+                    if app.current_collection_name.as_ref() == Some(&selected_collection) {
+                         app.items.clear();
+                         app.item_list_state.select(None);
+                         app.current_collection_name = None; // No collection selected anymore
+                         app.collection_trail.clear();
+                         app.total_items_found = None;
+                    }
+                }
+            }
+        }
+        Action::ImportFavorites => { // Import the collections among the user's archive.org favorites
+            match app.settings.archive_screenname.clone() {
+                Some(user) => start_favorites_import(app, user),
+                None => {
+                    app.current_state = AppState::EnteringFavoritesUser;
+                    app.editing_setting_input.clear();
+                    app.cursor_position = 0;
+                }
+            }
+        }
+        Action::FetchFilter => { // Edit the filter the selected collection is listed with
+            if let Some(collection_name) = app.get_selected_collection() {
+                app.editing_setting_input = app.settings.fetch_filters.get(collection_name).cloned().unwrap_or_default();
+                app.cursor_position = app.editing_setting_input.chars().count();
+                app.current_state = AppState::EnteringFetchFilter;
+            }
+        }
+        Action::SetGroup => { // Put the selected collection into a group
+            if let Some(collection_name) = app.get_selected_collection() {
+                app.editing_setting_input = app.settings.collection_groups.get(collection_name).cloned().unwrap_or_default();
+                app.cursor_position = app.editing_setting_input.chars().count();
+                app.current_state = AppState::EnteringCollectionGroup;
+            }
+        }
+        Action::RenameCollection => { // Give the selected collection a readable name
+            if let Some(collection_name) = app.get_selected_collection() {
+                app.editing_setting_input = app.collection_display_name(collection_name).into_owned();
+                app.cursor_position = app.editing_setting_input.chars().count();
+                app.current_state = AppState::EnteringCollectionAlias;
+            }
+        }
+        Action::GroupFilter => { // Show the next group only
+            if app.collection_group_names().is_empty() {
+                app.error_message = Some(format!("No groups yet. Press {} on a collection to put it into one.", app.keymap.hint(Action::SetGroup)));
+            } else {
+                app.cycle_collection_group_filter();
+                app.download_status = Some(match &app.collection_group_filter {
+                    Some(group) => format!("Showing the collections in {}", group),
+                    None => "Showing all collections".to_string(),
+                });
+            }
+        }
+        Action::MoveUp | Action::MoveDown => { // Rearrange the favorites by hand
+            if app.move_selected_collection(action == Action::MoveUp) {
                 app.pending_action = Some(UpdateAction::SaveSettings);
-                app.current_state = AppState::Browsing;
-                app.add_collection_input.clear();
+            }
+        }
+        Action::CollectionOrder => { // Sort the favorites another way
+            app.cycle_collection_order();
+            app.download_status = Some(format!("Collections in {} order", app.settings.collection_order));
+            app.pending_action = Some(UpdateAction::SaveSettings);
+        }
+        Action::CycleLayout => { // Cycle the library layout new downloads of the selected collection use
+            if let Some(collection_name) = app.get_selected_collection().cloned() {
+                let next = layout::cycle(app.settings.library_layouts.get(&collection_name).map(String::as_str));
+                app.download_status = Some(match next {
+                    Some(template) => {
+                        app.settings.library_layouts.insert(collection_name.clone(), template.to_string());
+                        format!("Library layout for {}: {}", collection_name, template)
+                    }
+                    None => {
+                        app.settings.library_layouts.remove(&collection_name);
+                        format!("Library layout for {}: default ({}/<item>)", collection_name, collection_name)
+                    }
+                });
+                app.pending_action = Some(UpdateAction::SaveSettings);
+            }
+        }
+        Action::Estimate => { // Dry run: estimate the download of the selected collection
+            if let Some(collection_name) = app.get_selected_collection().cloned() {
+                start_estimate(app, collection_name);
+            }
+        }
+        Action::Download | Action::DownloadAll => { // Download the selected collection
+            if let Some(collection_name) = app.get_selected_collection().cloned() {
+                if app.settings.download_directory.is_none() {
+                    app.current_state = AppState::AskingDownloadDir;
+                    // Use editing_setting_input for the path temporarily
+                    app.editing_setting_input.clear();
+                    app.cursor_position = 0;
+                } else {
+                    // Trigger download for the selected collection (after the forecast, if needed)
+                    request_collection_download(app, collection_name);
+                }
             } else {
-                app.error_message = Some("Collection identifier cannot be empty. Press Esc to cancel.".to_string());
+                app.error_message = Some("Select a collection to download.".to_string());
             }
         }
-        _ => {} // Ignore other keys
+        _ => return false,
     }
+    true
 }
 
+/// Lists `collection_name` in the items pane, from the item cache if possible, otherwise by
+/// asking the main loop to fetch it page by page.
+fn open_collection(app: &mut App, collection_name: String) {
+    app.mark_collection_opened(&collection_name);
+    app.current_collection_name = Some(collection_name.clone());
+    app.search_query = None;
+    app.items.clear(); // Clear previous items before attempting load/fetch
+    app.item_list_state.select(None);
+    app.expanded_groups.clear();
+    app.item_filter = ItemFilter::default(); // The new items may not have the filtered types
+    app.item_query.clear();
+    app.marked_items.clear();
+    app.total_items_found = None;
+    app.items_cached_at = None;
+
+    // Attempt to load from cache first
+    match app.load_items_from_cache(&collection_name) {
+        Ok(cached_items) => {
+            log::info!("Loaded {} items from cache for collection '{}'", cached_items.len(), collection_name);
+            app.items_cached_at = app.item_cache_modified(&collection_name);
+            app.mark_collection_seen(&collection_name, cached_items.len());
+            app.items = cached_items;
+            app.total_items_found = Some(app.items.len()); // Set total found from cache
+            app.is_loading = false; // Not loading from network
+            // Select first item if cache wasn't empty
+            if !app.items.is_empty() {
+                app.item_list_state.select(Some(0));
+            }
+            app.pending_action = None; // No network fetch needed
+        }
+        Err(_) if !app.network.allows_requests() => {
+            app.is_loading = false;
+            app.pending_action = None;
+            app.error_message = Some(format!("Offline: '{}' isn't cached. Press 'N' to go online.", collection_name));
+        }
+        Err(e) => {
+            // Cache miss or error, proceed with network fetch
+            log::warn!("Failed to load items from cache for '{}' ({}). Fetching from network.", collection_name, e);
+            app.is_loading = true; // Set loading flag for network fetch
+            // Dispatch the action to start listing the collection page by page
+            app.pending_action = Some(UpdateAction::StartIncrementalItemFetch(collection_name));
+        }
+    }
+    // Always switch focus to items pane after attempting load or starting fetch
+    app.active_pane = ActivePane::Items;
+}
 
-// --- Tests ---
-// Note: Many existing tests related to the old input/filter/navigate modes
-// will need significant updates or removal due to the UI changes.
-// Adding some basic tests for the new pane switching and collection management.
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::app::{ActivePane, App, AppRateLimiter, AppState}; // Add ActivePane, AppRateLimiter
-    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-    // Import necessary items for dummy rate limiter
-    use governor::{Quota, RateLimiter, clock::SystemClock};
-    use std::{num::NonZeroU32, sync::Arc};
+/// Records where the user is before navigating elsewhere, for the back/forward history.
+fn remember_place(app: &mut App) {
+    if let Some(place) = app.current_place() {
+        app.history.visit(place);
+    }
+}
 
-    // Helper function to create a dummy rate limiter for tests (allows all requests)
-    fn test_limiter() -> AppRateLimiter {
-        let quota = Quota::per_hour(NonZeroU32::new(u32::MAX).unwrap());
-        Arc::new(RateLimiter::direct_with_clock(quota, &SystemClock::default()))
+/// Moves back (or forward) through the history of visited places.
+fn navigate_history(app: &mut App, forward: bool) {
+    let Some(current) = app.current_place() else {
+        return; // Nothing visited yet
+    };
+    let target = if forward { app.history.forward(current) } else { app.history.back(current) };
+    match target {
+        Some(place) => go_to(app, place),
+        None => app.download_status = Some(format!("Nothing to go {} to.", if forward { "forward" } else { "back" })),
     }
+}
 
-    // Helper for setting up test environment with mock config
-    // Note: This helper doesn't need to interact with the actual config file system anymore,
-    // as App::load_settings uses the default path logic which is tested separately in settings::tests.
-    // We just need an App instance with some initial settings for UI interaction tests.
-    fn setup_test_app() -> App {
-        let limiter = test_limiter(); // Create dummy limiter
-        let mut app = App::new(limiter); // Pass the limiter
-        // Set some initial settings directly for testing UI logic
-        app.settings.favorite_collections = vec!["coll1".to_string(), "coll2".to_string(), "coll3".to_string()];
-        app.settings.download_directory = Some("/fake/test/dir".to_string()); // Assume a dir is set for some tests
-        app.collection_list_state.select(Some(0)); // Pre-select first collection
-        app
+/// Shows `place` without recording it in the history.
+fn go_to(app: &mut App, place: Place) {
+    match place {
+        Place::Item(identifier) => app.open_item(identifier),
+        Place::Listing { listing, trail } => {
+            let already_listed = app.current_listing().as_ref() == Some(&listing);
+            if app.current_state == AppState::ViewingItem {
+                close_item_view(app);
+            }
+            app.collection_trail = trail;
+            if !already_listed {
+                match listing {
+                    Listing::Collection(name) => open_collection(app, name),
+                    Listing::Search(query) => start_search(app, query),
+                }
+            }
+            app.active_pane = ActivePane::Items;
+        }
+    }
+}
+
+/// Leaves the item view for the listing it was opened from.
+fn close_item_view(app: &mut App) {
+    app.current_state = AppState::Browsing;
+    app.viewing_item_id = None;
+    app.current_item_details = None;
+    app.file_list_state = ListState::default();
+    // Active pane remains Items (usually where you came from)
+}
+
+/// Lists the results of `query` in the items pane.
+fn start_search(app: &mut App, query: String) {
+    app.active_pane = ActivePane::Items;
+    app.current_collection_name = None; // Results aren't downloaded into a collection directory
+    app.search_query = Some(query.clone());
+    app.items.clear();
+    app.item_list_state.select(None);
+    app.expanded_groups.clear();
+    app.item_filter = ItemFilter::default();
+    app.item_query.clear();
+    app.marked_items.clear();
+    app.total_items_found = None;
+    app.is_loading = true;
+    app.pending_action = Some(UpdateAction::StartSearch(query));
+}
+
+/// Drills into the sub-collection `collection_name`, remembering the current listing.
+fn enter_sub_collection(app: &mut App, collection_name: String) {
+    if let Some(listing) = app.current_listing() {
+        app.collection_trail.push(Breadcrumb { listing, selected: Some(collection_name.clone()) });
+    }
+    open_collection(app, collection_name);
+}
+
+/// Goes back up to the listing the current sub-collection was entered from. Returns
+/// `false` at the top of the trail.
+fn leave_sub_collection(app: &mut App) -> bool {
+    if app.collection_trail.is_empty() {
+        return false;
+    }
+    remember_place(app);
+    let Some(crumb) = app.collection_trail.pop() else { return false };
+    match crumb.listing {
+        Listing::Collection(name) => open_collection(app, name),
+        Listing::Search(query) => start_search(app, query),
+    }
+    if let (Some(identifier), false) = (crumb.selected, app.items.is_empty()) {
+        app.select_item(&identifier); // Only listings from the cache are loaded already
+    }
+    true
+}
+
+/// Handles key events when the Items pane is active.
+fn handle_items_pane_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Enter => {
+            // Collections open like directories; anything else shows the item details
+            if let Some(item) = app.selected_item().cloned() {
+                remember_place(app);
+                if item.mediatype.as_deref() == Some("collection") {
+                    enter_sub_collection(app, item.identifier);
+                } else {
+                    app.open_item(item.identifier);
+                }
+            }
+        }
+        KeyCode::Esc => {
+            leave_sub_collection(app);
+        }
+        _ => match app.keymap.action(KeyContext::Items, &key_event) {
+            Some(action) => {
+                items_action(app, action);
+            }
+            // Backspace backs out of a sub-collection unless it is bound to an action here
+            None if key_event.code == KeyCode::Backspace => {
+                leave_sub_collection(app);
+            }
+            None => match app.list_nav.motion(&key_event) {
+                Some(Motion::Right) => {
+                    app.set_selected_group_expanded(true);
+                }
+                // Left folds a duplicate group, or moves to the collections pane
+                Some(Motion::Left) => {
+                    let folded = app.set_selected_group_expanded(false);
+                    if !folded {
+                        app.active_pane = ActivePane::Collections;
+                    }
+                }
+                Some(motion) => app.move_item_selection(motion),
+                None => {}
+            },
+        },
+    }
+}
+
+/// Performs an action of [`KeyContext::Items`] on the selected or marked items. Returns
+/// `false` if it doesn't apply.
+fn items_action(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::Mark => { // Mark/unmark the selected item for a batch download
+            let marked = app.toggle_marked_item();
+            if marked {
+                app.move_item_selection(Motion::Down(1));
+            }
+        }
+        Action::MarkAll => app.mark_visible_items(),
+        Action::ClearMarks => app.marked_items.clear(),
+        Action::SaveSearch => match app.search_query.clone() {
+            Some(query) => {
+                let entry = saved_search::entry(&query);
+                if app.settings.favorite_collections.contains(&entry) {
+                    app.download_status = Some(format!("'{}' is already saved.", query));
+                } else {
+                    app.add_collection_to_favorites(entry);
+                    app.pending_action = Some(UpdateAction::SaveSettings);
+                    app.download_status = Some(format!("Saved search '{}' to the collections.", query));
+                }
+            }
+            None => app.error_message = Some(format!("Search first ({}), then save the search.", app.keymap.hint(Action::Search))),
+        },
+        Action::Download if !app.marked_items.is_empty() => { // Download the marked items as one batch
+            if app.settings.download_directory.is_none() {
+                app.current_state = AppState::AskingDownloadDir;
+                app.editing_setting_input.clear();
+                app.cursor_position = 0;
+                return true;
+            }
+            let docs = app.marked_item_docs();
+            let ids: Vec<String> = docs.iter().map(|doc| doc.identifier.clone()).collect();
+            let size: Option<u64> = docs.iter().map(|doc| item_size_for_download(app, doc)).sum();
+            let what = format!("{} marked items", ids.len());
+            if let Some(shortfall) = size.and_then(|size| app.space_shortfall(&what, size)) {
+                app.error_message = Some(shortfall);
+            } else {
+                app.download_status = Some(format!("Queueing download for {}", what));
+                app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Items(ids)));
+                app.marked_items.clear();
+            }
+        }
+        Action::Download => { // Download selected item
+            if app.item_list_state.selected().is_some() {
+                if let Some(item) = app.selected_item().cloned() {
+                    if app.settings.download_directory.is_none() {
+                        app.current_state = AppState::AskingDownloadDir;
+                        app.editing_setting_input.clear();
+                        app.cursor_position = 0;
+                    } else if let Some(shortfall) = item_size_for_download(app, &item).and_then(|size| app.space_shortfall(&item.identifier, size)) {
+                        app.error_message = Some(shortfall);
+                    } else {
+                        app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(item.identifier.clone())));
+                        app.download_status = Some(format!("Queueing download for item: {}", item.identifier));
+                    }
+                }
+            } else {
+                app.error_message = Some("Select an item to download.".to_string());
+            }
+        }
+        Action::Estimate => { // Dry run of downloading the open collection
+            match (&app.search_query, app.current_collection_name.clone()) {
+                (None, Some(collection_name)) => start_estimate(app, collection_name),
+                _ => app.error_message = Some("Open a collection to estimate its download.".to_string()),
+            }
+        }
+        Action::DownloadAll => { // Bulk download all items in the *current view*
+            if app.search_query.is_some() {
+                app.error_message = Some(format!("Search results can't be downloaded all at once; save the search with {} and download it from the collections pane.", app.keymap.hint(Action::SaveSearch)));
+            } else if let Some(collection_name) = app.current_collection_name.clone() {
+                 if app.settings.download_directory.is_none() {
+                     app.current_state = AppState::AskingDownloadDir;
+                     app.editing_setting_input.clear();
+                     app.cursor_position = 0;
+                 } else if !app.items.is_empty() {
+                     // Trigger download for the currently loaded collection
+                     // Note: This re-uses the Collection action, which might re-fetch identifiers.
+                     // A future optimization could pass the already loaded identifiers.
+                     request_collection_download(app, collection_name);
+                 } else {
+                     app.error_message = Some("No items listed to download.".to_string());
+                 }
+            } else {
+                 app.error_message = Some("No collection selected to download items from.".to_string());
+            }
+        }
+        Action::Export => { // Export the listed items to CSV/JSON
+            if app.visible_items().is_empty() {
+                app.error_message = Some("No items listed to export.".to_string());
+            } else {
+                let name = app.current_collection_name.clone().unwrap_or_else(|| "search-results".to_string());
+                start_export(app, &name, false);
+            }
+        }
+        Action::Open => { // Open the downloaded item directory locally
+            if let Some(item_id) = app.selected_item().map(|item| item.identifier.clone()) {
+                request_open(app, app.get_download_path_for_item(&item_id), &item_id);
+            }
+        }
+        Action::OpenPage => {
+            if let Some(item_id) = app.selected_item().map(|item| item.identifier.clone()) {
+                app.pending_action = Some(UpdateAction::OpenUrl(archive_api::details_url(&item_id)));
+            }
+        }
+        Action::Copy => {
+            if let Some(item_id) = app.selected_item().map(|item| item.identifier.clone()) {
+                app.pending_action = Some(UpdateAction::CopyToClipboard(item_id));
+            }
+        }
+        Action::CopyUrl => {
+            if let Some(item_id) = app.selected_item().map(|item| item.identifier.clone()) {
+                app.pending_action = Some(UpdateAction::CopyToClipboard(archive_api::details_url(&item_id)));
+            }
+        }
+        Action::Filter => { // Type a live filter over identifiers and titles
+            app.current_state = AppState::FilteringItems;
+        }
+        Action::StatusFilter => { // Cycle the download status filter
+            app.cycle_item_status_filter();
+        }
+        Action::MediatypeFilter => { // Cycle the mediatype filter
+            app.cycle_mediatype_filter();
+            app.download_status = Some(format!("Showing: {}", app.item_filter));
+        }
+        Action::FormatFilter => { // Cycle the file format filter
+            app.cycle_format_filter();
+            app.download_status = Some(format!("Showing: {}", app.item_filter));
+        }
+        Action::GroupDuplicates => { // Group items with near-identical titles
+            app.toggle_duplicate_grouping();
+            app.download_status = Some(if app.group_duplicates {
+                "Grouping duplicate titles (Right: expand, Left: collapse).".to_string()
+            } else {
+                "Showing every item.".to_string()
+            });
+        }
+        Action::Verify => {
+            if let Some(identifier) = app.selected_item().map(|item| item.identifier.clone()) {
+                request_verify(app, identifier);
+            }
+        }
+        Action::Exclude => { // Toggle an exact exclusion for the selected item
+            if let Some(identifier) = app.selected_item().map(|item| item.identifier.clone()) {
+                let excluded = app.toggle_identifier_exclusion(&identifier);
+                app.download_status = Some(if excluded {
+                    format!("Excluded '{}' from collection downloads.", identifier)
+                } else {
+                    format!("'{}' is no longer excluded.", identifier)
+                });
+                app.pending_action = Some(UpdateAction::SaveSettings);
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+
+/// Handles input when prompting for the download directory.
+/// Uses the `editing_setting_input` buffer and `cursor_position`.
+fn handle_asking_download_dir_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            // Cancel entering download dir and return to browsing
+            app.current_state = AppState::Browsing;
+            app.editing_setting_input.clear(); // Clear the temp input
+            app.error_message = None;
+        }
+        KeyCode::Char(to_insert) => {
+            app.enter_char_edit_setting(to_insert);
+        }
+        KeyCode::Backspace => {
+            app.delete_char_edit_setting();
+        }
+        KeyCode::Left => {
+            app.move_cursor_left_edit_setting();
+        }
+        KeyCode::Right => {
+            app.move_cursor_right_edit_setting();
+        }
+        KeyCode::Enter => {
+            let entered_path = app.editing_setting_input.trim().to_string();
+            if !entered_path.is_empty() {
+                app.settings.download_directory = Some(entered_path);
+                // Trigger save settings action
+                app.pending_action = Some(UpdateAction::SaveSettings);
+                app.current_state = AppState::Browsing; // Return to browsing
+                app.editing_setting_input.clear(); // Clear the temp input
+                // Set a confirmation message (will be cleared on next update unless error)
+                app.error_message = Some(format!(
+                    "Download directory saved. Press {}/{} again to start download.",
+                    app.keymap.hint(Action::Download),
+                    app.keymap.hint(Action::DownloadAll)
+                ));
+            } else {
+                app.error_message = Some("Download directory cannot be empty. Press Esc to cancel.".to_string());
+            }
+        }
+        _ => {} // Ignore other keys
+    }
+}
+
+
+/// Handles input when viewing item details.
+fn handle_viewing_item_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => close_item_view(app),
+        KeyCode::Enter if app.item_tab == ItemTab::Related => { // Jump to the related item
+            if let Some(identifier) = app.selected_related().cloned() {
+                remember_place(app);
+                app.open_item(identifier);
+            }
+        }
+        KeyCode::Enter => download_selected_file(app),
+        _ => match app.keymap.action(KeyContext::Item, &key_event) {
+            Some(action) => {
+                item_view_action(app, action);
+            }
+            None => match (app.list_nav.motion(&key_event), app.item_tab) {
+                (Some(motion), ItemTab::Metadata) => app.move_file_selection(motion),
+                (Some(motion), ItemTab::Reviews) => app.move_review(motion),
+                (Some(motion), ItemTab::Related) => app.move_related_selection(motion),
+                (None, _) => {}
+            },
+        },
+    }
+}
+
+/// Performs an action of [`KeyContext::Item`] on the viewed item. Returns `false` if it
+/// doesn't apply.
+fn item_view_action(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::NextTab => app.item_tab = app.item_tab.next(),
+        Action::RefreshDetails => app.refresh_item_details(),
+        Action::CyclePreset => cycle_download_preset(app),
+        Action::Export => { // Export the item's metadata and file list to CSV/JSON
+            match app.current_item_details.as_ref().map(|details| details.identifier.clone()) {
+                Some(identifier) => start_export(app, &identifier, true),
+                None => app.error_message = Some("Item details are not loaded yet.".to_string()),
+            }
+        }
+        Action::Open => { // Open the downloaded file locally
+            if let Some(file) = app.get_selected_file().cloned() {
+                request_open(app, app.get_download_path_for_file(&file), &file.name);
+            }
+        }
+        Action::OpenPage => {
+            if let Some(item_id) = app.viewing_item_id.as_deref() {
+                app.pending_action = Some(UpdateAction::OpenUrl(archive_api::details_url(item_id)));
+            }
+        }
+        Action::Play => { // Stream the selected file from archive.org
+            let file = app.get_selected_file().filter(|_| app.item_tab == ItemTab::Metadata).cloned();
+            match (app.viewing_item_id.clone(), file) {
+                (Some(_), Some(file)) if file.private => {
+                    app.error_message = Some(format!("'{}' is access-restricted and can't be streamed.", file.name));
+                }
+                (Some(item_id), Some(file)) => {
+                    app.download_status = Some(format!("Playing {} in {}", file.name, app.settings.player_command));
+                    app.pending_action = Some(UpdateAction::PlayUrl(archive_api::download_url(&item_id, &file.name)));
+                }
+                _ => app.error_message = Some("Select a file to play.".to_string()),
+            }
+        }
+        Action::Preview => { // Plays an MP3/FLAC file in the preview player; again pauses it
+            let file = app.get_selected_file().filter(|_| app.item_tab == ItemTab::Metadata).cloned();
+            match (app.viewing_item_id.clone(), file) {
+                (Some(item_id), Some(file)) if app.preview.as_ref().is_some_and(|p| p.item_id == item_id && p.file_name == file.name) => {
+                    if let Some(Err(e)) = app.preview.as_mut().map(|preview| preview.toggle_pause()) {
+                        app.error_message = Some(format!("{:#}", e));
+                    }
+                }
+                (Some(_), Some(file)) if !preview::is_playable(&file.name) => {
+                    app.error_message = Some(format!("'{}' isn't an MP3 or FLAC file.", file.name));
+                }
+                (Some(_), Some(file)) if file.private => {
+                    app.error_message = Some(format!("'{}' is access-restricted and can't be streamed.", file.name));
+                }
+                (Some(item_id), Some(file)) => app.pending_action = Some(UpdateAction::PreviewFile(item_id, file)),
+                _ => app.error_message = Some("Select a file to play.".to_string()),
+            }
+        }
+        Action::Copy => { // The selected file's download URL, or the identifier on the other tabs
+            let Some(item_id) = app.viewing_item_id.clone() else { return true };
+            let text = match (app.item_tab, app.get_selected_file()) {
+                (ItemTab::Metadata, Some(file)) => archive_api::download_url(&item_id, &file.name),
+                _ => item_id,
+            };
+            app.pending_action = Some(UpdateAction::CopyToClipboard(text));
+        }
+        Action::CopyUrl => {
+            if let Some(item_id) = app.viewing_item_id.as_deref() {
+                app.pending_action = Some(UpdateAction::CopyToClipboard(archive_api::details_url(item_id)));
+            }
+        }
+        Action::Download => download_selected_file(app),
+        Action::Verify => {
+            if let Some(identifier) = app.viewing_item_id.clone() {
+                request_verify(app, identifier);
+            }
+        }
+        Action::DownloadAll => { // Download all files for this item
+            if let Some(item_id) = app.viewing_item_id.clone() {
+                if app.settings.download_directory.is_none() {
+                    app.current_state = AppState::AskingDownloadDir;
+                    app.editing_setting_input.clear();
+                    app.cursor_position = 0;
+                } else if let Some(shortfall) = selected_files_size(app).and_then(|size| app.space_shortfall(&item_id, size)) {
+                    app.error_message = Some(shortfall);
+                } else {
+                    app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(item_id.clone())));
+                    app.download_status = Some(format!("Queueing download for all files in item: {}", item_id));
+                    // Maybe switch back to Browsing view after queuing? Or stay? Staying for now.
+                }
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Downloads the file selected in the item view.
+fn download_selected_file(app: &mut App) {
+    if let Some(file_details) = app.get_selected_file().cloned() {
+        if let Some(item_id) = app.viewing_item_id.clone() {
+            if app.settings.download_directory.is_none() {
+                app.current_state = AppState::AskingDownloadDir;
+                app.editing_setting_input.clear();
+                app.cursor_position = 0;
+            } else if let Some(shortfall) =
+                file_details.size.as_deref().and_then(|s| s.parse().ok()).and_then(|size| app.space_shortfall(&file_details.name, size))
+            {
+                app.error_message = Some(shortfall);
+            } else {
+                app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::File(item_id, Box::new(file_details.clone()))));
+                app.download_status = Some(format!("Queueing download for file: {}", file_details.name));
+                // Maybe switch back to Browsing view after queuing? Or stay? Staying for now.
+            }
+        }
+    } else {
+        app.error_message = Some("Select a file to download.".to_string());
+    }
+}
+
+
+use crate::settings::{DownloadMode, Settings}; // Import the new enum
+
+/// Handles input when viewing/editing settings.
+fn handle_settings_view_input(app: &mut App, key_event: KeyEvent) {
+    let num_settings = 28; // Download Dir, Download Mode, File Concurrency, Collection Concurrency, Theme, Update Check, Metadata Sidecar, Confirm Threshold, File Preset, Subtitles, Download Filter, Torrent Backend, Speed Limit, Collection Sync, File Sources, Playlists, Upload Keys, Login, IPFS Export, Checksums, Dedupe, Tagging, MusicBrainz, Media Player, Parallel Segments, Retries, Details Cache, Cover Art
+    match key_event.code {
+        KeyCode::Esc => {
+            // Exit settings view, save, return to browsing
+            app.current_state = AppState::Browsing;
+            // Trigger save settings action
+            app.pending_action = Some(UpdateAction::SaveSettings);
+        }
+        KeyCode::Down => {
+            app.selected_setting_index = (app.selected_setting_index + 1) % num_settings;
+            app.settings_list_state.select(Some(app.selected_setting_index));
+        }
+        KeyCode::Up => {
+            app.selected_setting_index = if app.selected_setting_index == 0 {
+                num_settings - 1
+            } else {
+                app.selected_setting_index - 1
+            };
+            app.settings_list_state.select(Some(app.selected_setting_index));
+        }
+        KeyCode::Right | KeyCode::Left => { // Use Left/Right to cycle/adjust
+            match app.selected_setting_index {
+                1 => { // Download Mode (Cycle; Left goes backwards)
+                    let modes = [DownloadMode::Direct, DownloadMode::TorrentOnly, DownloadMode::TorrentEmbedded];
+                    let current = modes.iter().position(|m| *m == app.settings.download_mode).unwrap_or(0);
+                    let step = if key_event.code == KeyCode::Right { 1 } else { modes.len() - 1 };
+                    app.settings.download_mode = modes[(current + step) % modes.len()];
+                }
+                2 => { // File Concurrency (Adjust)
+                    let current = app.settings.max_concurrent_downloads.unwrap_or(1);
+                    let new_val = if key_event.code == KeyCode::Right {
+                        current.saturating_add(1)
+                    } else {
+                        current.saturating_sub(1).max(1) // Min 1
+                    };
+                    app.settings.max_concurrent_downloads = Some(new_val);
+                }
+                3 => { // Collection Concurrency (Adjust)
+                    let current = app.settings.max_concurrent_collections.unwrap_or(1);
+                     let new_val = if key_event.code == KeyCode::Right {
+                        current.saturating_add(1)
+                    } else {
+                        current.saturating_sub(1).max(1) // Min 1
+                    };
+                    app.settings.max_concurrent_collections = Some(new_val);
+                }
+                4 => { // Theme (Cycle)
+                    app.settings.theme = app.settings.theme.cycle();
+                    app.refresh_theme();
+                }
+                5 => { // Update Check (Toggle)
+                    app.settings.check_for_updates = !app.settings.check_for_updates;
+                }
+                6 => { // Metadata Sidecar (Cycle)
+                    app.settings.metadata_sidecar = app.settings.metadata_sidecar.cycle();
+                }
+                7 => { // Confirm Collection Downloads Above (Adjust, GB)
+                    let current = app.settings.confirm_collection_above_gb;
+                    app.settings.confirm_collection_above_gb = if key_event.code == KeyCode::Right {
+                        current.saturating_add(1)
+                    } else {
+                        current.saturating_sub(1) // 0 = always confirm
+                    };
+                }
+                8 => { // Default File Preset (Cycle); also becomes the preset for the next download
+                    app.settings.default_file_preset = app.settings.default_file_preset.cycle();
+                    app.download_preset = app.settings.default_file_preset;
+                }
+                9 => { // Always Include Subtitles (Toggle)
+                    app.settings.include_subtitles = !app.settings.include_subtitles;
+                }
+                11 => { // Torrent Backend (Cycle)
+                    app.settings.torrent_backend = app.settings.torrent_backend.cycle();
+                }
+                12 => { // Download Speed Limit (Adjust; applies to running downloads too)
+                    let current = app.settings.download_speed_limit_kb;
+                    app.settings.download_speed_limit_kb =
+                        if key_event.code == KeyCode::Right { throttle::raise_limit(current) } else { throttle::lower_limit(current) };
+                    app.throttle.set_limit(app.settings.download_speed_limit_kb);
+                }
+                13 => { // Collection Sync interval (Adjust)
+                    app.settings.sync_interval_minutes = sync::step_interval(app.settings.sync_interval_minutes, key_event.code == KeyCode::Right);
+                }
+                14 => { // File Sources (Cycle)
+                    app.settings.file_source_policy = app.settings.file_source_policy.cycle();
+                }
+                15 => { // Write Playlists (Toggle)
+                    app.settings.write_playlists = !app.settings.write_playlists;
+                }
+                18 => { // IPFS Export (Toggle)
+                    app.settings.ipfs_export = !app.settings.ipfs_export;
+                }
+                19 => { // Checksum Manifests (Toggle)
+                    app.settings.write_checksums = !app.settings.write_checksums;
+                }
+                20 => { // Deduplicate Files (Cycle)
+                    app.settings.dedupe_mode = app.settings.dedupe_mode.cycle();
+                }
+                21 => { // Tag Audio Files (Cycle: Off, then each tag policy)
+                    match (app.settings.tag_audio, app.settings.tag_policy) {
+                        (false, _) => {
+                            app.settings.tag_audio = true;
+                            app.settings.tag_policy = TagPolicy::default();
+                        }
+                        (true, TagPolicy::SkipTagged) => app.settings.tag_audio = false,
+                        (true, policy) => app.settings.tag_policy = policy.cycle(),
+                    }
+                }
+                22 => { // MusicBrainz Lookup (Toggle)
+                    app.settings.musicbrainz_lookup = !app.settings.musicbrainz_lookup;
+                }
+                24 => { // Parallel Segments (Adjust; 1 = off)
+                    let current = app.settings.segments_per_file.clamp(1, segments::MAX_SEGMENTS);
+                    app.settings.segments_per_file = if key_event.code == KeyCode::Right {
+                        (current + 1).min(segments::MAX_SEGMENTS)
+                    } else {
+                        current.saturating_sub(1).max(1)
+                    };
+                }
+                25 => { // Retry Failed Downloads (Adjust attempts; 1 = off)
+                    let current = app.settings.retry_max_attempts.clamp(1, retry::MAX_ATTEMPTS);
+                    app.settings.retry_max_attempts = if key_event.code == KeyCode::Right {
+                        (current + 1).min(retry::MAX_ATTEMPTS)
+                    } else {
+                        current.saturating_sub(1).max(1)
+                    };
+                }
+                26 => { // Item Details Cache lifetime (Adjust)
+                    app.settings.details_cache_hours = offline::step_ttl(app.settings.details_cache_hours, key_event.code == KeyCode::Right);
+                }
+                27 => { // Cover Art (Cycle)
+                    app.settings.cover_art = app.settings.cover_art.cycle();
+                }
+                _ => {} // No Left/Right action for Download Dir (index 0), Download Filter (index 10), Upload Keys (index 16), Login (index 17) or Media Player (index 23)
+            }
+        }
+        KeyCode::Char('x') => {
+            app.current_state = AppState::ManagingExclusions;
+            let has_rules = !exclusions::rules(&app.settings).is_empty();
+            app.exclusion_list_state.select(has_rules.then_some(0));
+        }
+        // Enter edit mode for the text settings: Download Directory (index 0), Download Filter (index 10) and Media Player (index 23)
+        KeyCode::Enter if app.selected_setting_index == 0 => {
+            app.current_state = AppState::EditingSetting;
+            app.editing_setting_input = app.settings.download_directory.clone().unwrap_or_default();
+            app.cursor_position = app.editing_setting_input.len();
+        }
+        KeyCode::Enter if app.selected_setting_index == 10 => {
+            app.current_state = AppState::EditingSetting;
+            app.editing_setting_input = app.settings.download_file_filter.join(", ");
+            app.cursor_position = app.editing_setting_input.chars().count();
+        }
+        KeyCode::Enter if app.selected_setting_index == 23 => {
+            app.current_state = AppState::EditingSetting;
+            app.editing_setting_input = app.settings.player_command.clone();
+            app.cursor_position = app.editing_setting_input.chars().count();
+        }
+        KeyCode::Enter if app.selected_setting_index == 16 || app.selected_setting_index == 17 => { // Upload Keys / Login: typed fresh, secrets are never shown
+            app.current_state = AppState::EditingSetting;
+            app.editing_setting_input.clear();
+            app.cursor_position = 0;
+        }
+        KeyCode::Delete | KeyCode::Backspace if app.selected_setting_index == 17 => {
+            app.pending_action = Some(UpdateAction::Logout);
+        }
+        _ => {} // Ignore other keys
+    }
+}
+
+
+/// Handles input when actively editing a text setting (Download Dir or Download Filter).
+/// Uses `editing_setting_input` and `cursor_position`.
+fn handle_editing_setting_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            // Cancel editing, revert to SettingsView
+            app.current_state = AppState::SettingsView;
+            app.editing_setting_input.clear();
+            app.error_message = None;
+        }
+        KeyCode::Char(to_insert) => {
+            app.enter_char_edit_setting(to_insert);
+        }
+        KeyCode::Backspace => {
+            app.delete_char_edit_setting();
+        }
+        KeyCode::Left => {
+            app.move_cursor_left_edit_setting();
+        }
+        KeyCode::Right => {
+            app.move_cursor_right_edit_setting();
+        }
+        KeyCode::Enter => {
+            // Save the edited value back to the actual setting
+            let edited_value = app.editing_setting_input.trim().to_string();
+            if app.selected_setting_index == 0 { // Download Directory
+                app.settings.download_directory = if edited_value.is_empty() { None } else { Some(edited_value) };
+            } else if app.selected_setting_index == 10 { // Download Filter, comma-separated
+                app.settings.download_file_filter =
+                    edited_value.split(',').map(str::trim).filter(|entry| !entry.is_empty()).map(str::to_string).collect();
+            } else if app.selected_setting_index == 23 { // Media Player; cleared goes back to the default
+                app.settings.player_command = if edited_value.is_empty() { Settings::default().player_command } else { edited_value };
+            } else if app.selected_setting_index == 16 { // Upload Keys, saved to their own file
+                match ia_upload::S3Keys::parse(&edited_value) {
+                    Some(keys) => {
+                        app.s3_keys = Some(keys);
+                        app.pending_action = Some(UpdateAction::SaveUploadKeys);
+                    }
+                    None if edited_value.is_empty() => {} // Keep the current keys
+                    None => {
+                        app.error_message = Some("Enter the access key and secret separated by a space.".to_string());
+                        return;
+                    }
+                }
+            } else if app.selected_setting_index == 17 { // Login, checked with archive.org in the background
+                match LoginRequest::parse(&edited_value) {
+                    Some(request) => {
+                        app.download_status = Some(format!("Logging in as '{}'...", request.email));
+                        app.pending_action = Some(UpdateAction::Login(request));
+                    }
+                    None if edited_value.is_empty() => {} // Keep the current login
+                    None => {
+                        app.error_message = Some("Enter your archive.org email address and password separated by a space.".to_string());
+                        return;
+                    }
+                }
+            }
+            // No need to trigger save action here, Esc from SettingsView saves.
+            app.current_state = AppState::SettingsView;
+            app.editing_setting_input.clear();
+            app.error_message = None; // Clear error from input mode
+        }
+        _ => {} // Ignore other keys
+    }
+}
+
+/// Handles the upload form (`AppState::EditingUpload`): Up/Down/Tab pick a field, typing
+/// edits it, Enter starts the upload.
+fn handle_editing_upload_input(app: &mut App, key_event: KeyEvent) {
+    let fields = UPLOAD_FIELDS.len();
+    let form = &mut app.upload_form;
+    match key_event.code {
+        KeyCode::Esc => app.current_state = AppState::Browsing,
+        KeyCode::Down | KeyCode::Tab => form.field = (form.field + 1) % fields,
+        KeyCode::Up | KeyCode::BackTab => form.field = (form.field + fields - 1) % fields,
+        KeyCode::Char(c) => form.values[form.field].push(c),
+        KeyCode::Backspace => {
+            form.values[form.field].pop();
+        }
+        KeyCode::Enter => {
+            if app.s3_keys.is_none() {
+                app.error_message = Some(format!("Set your archive.org S3 keys (Upload Keys in settings) first; see {}", ia_upload::KEYS_URL));
+                return;
+            }
+            match app.upload_form.request() {
+                Ok(request) => {
+                    app.upload = Some(UploadStatus { identifier: request.identifier.clone(), ..Default::default() });
+                    app.pending_action = Some(UpdateAction::StartUpload(request));
+                    app.upload_form = UploadForm::default();
+                    app.current_state = AppState::Browsing;
+                }
+                Err(e) => app.error_message = Some(format!("{:#}", e)),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handles input in the update release-notes popup.
+fn handle_update_popup_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => app.current_state = AppState::Browsing,
+        KeyCode::Enter | KeyCode::Char('i') => {
+            app.current_state = AppState::Browsing;
+            if let Some(release) = &app.available_update {
+                app.download_status = Some(format!("Installing update {}...", release.version));
+                app.pending_action = Some(UpdateAction::InstallUpdate);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Queues a download of a whole collection. Large downloads, ones that won't fit on disk
+/// and collections that haven't been listed yet go through the forecast popup first.
+fn request_collection_download(app: &mut App, collection_name: String) {
+    let forecast = app.forecast_collection(&collection_name);
+    if forecast.needs_confirmation(app.settings.confirm_collection_above_gb) {
+        app.collection_forecast = Some(forecast);
+        app.current_state = AppState::ConfirmingDownload;
+    } else {
+        app.download_status = Some(format!("Queueing download for collection: {}", collection_name));
+        app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Collection(collection_name)));
+    }
+}
+
+/// Opens the size estimate of `collection_name` and asks the main loop to fill it in.
+fn start_estimate(app: &mut App, collection_name: String) {
+    app.size_estimate = Some(SizeEstimate::new(&collection_name, app.download_preset));
+    app.current_state = AppState::ViewingEstimate;
+    app.pending_action = Some(UpdateAction::EstimateCollection(collection_name));
+}
+
+/// Handles input in the size estimate popup: Enter goes on to download the collection,
+/// Esc closes it (stopping the estimate if it is still running).
+fn handle_estimate_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Enter => {
+            app.current_state = AppState::Browsing;
+            if let Some(estimate) = app.size_estimate.take() {
+                if app.settings.download_directory.is_none() {
+                    app.current_state = AppState::AskingDownloadDir;
+                    app.editing_setting_input.clear();
+                    app.cursor_position = 0;
+                } else {
+                    request_collection_download(app, estimate.collection);
+                }
+            }
+        }
+        KeyCode::Esc => {
+            app.current_state = AppState::Browsing;
+            app.size_estimate = None;
+        }
+        _ => {}
+    }
+}
+
+/// Handles input in the popup with the totals of a finished collection download; the
+/// failures key opens the failures view from there.
+fn handle_collection_report_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Enter | KeyCode::Esc => app.current_state = AppState::Browsing,
+        _ if app.keymap.action(KeyContext::Browsing, &key_event) == Some(Action::ToggleFailures) => {
+            app.current_state = AppState::Browsing;
+            browsing_action(app, Action::ToggleFailures);
+        }
+        _ => {}
+    }
+}
+
+/// Handles input in the collection download forecast popup.
+fn handle_confirm_download_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Enter | KeyCode::Char('y') => {
+            app.current_state = AppState::Browsing;
+            if let Some(forecast) = app.collection_forecast.take() {
+                app.download_status = Some(format!("Queueing download for collection: {}", forecast.collection));
+                app.pending_action = Some(UpdateAction::StartDownload(DownloadAction::Collection(forecast.collection)));
+            }
+        }
+        KeyCode::Esc | KeyCode::Char('n') => {
+            app.current_state = AppState::Browsing;
+            app.collection_forecast = None;
+            app.download_status = Some("Collection download cancelled.".to_string());
+        }
+        _ => {}
+    }
+}
+
+/// Handles input in the startup prompt about an interrupted session.
+/// Esc postpones the decision: the jobs stay in the journal for the next launch.
+fn handle_resume_prompt_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Enter | KeyCode::Char('r') => {
+            app.current_state = AppState::Browsing;
+            app.show_session_details = false;
+            app.pending_action = Some(UpdateAction::ResumeSession);
+        }
+        KeyCode::Char('d') => {
+            let discarded = app.session.take_entries().len();
+            let queued = app.queue.discard_held();
+            app.current_state = AppState::Browsing;
+            app.show_session_details = false;
+            app.download_status = Some(if queued == 0 {
+                format!("Discarded {} interrupted download(s).", discarded)
+            } else {
+                format!("Discarded {} interrupted and {} queued download(s).", discarded, queued)
+            });
+        }
+        KeyCode::Char('i') => app.show_session_details = !app.show_session_details,
+        KeyCode::Esc => {
+            // Decide later: the interrupted jobs wait for the next startup, out of the way
+            // of the downloads started meanwhile
+            app.session.hold_entries();
+            app.current_state = AppState::Browsing;
+            app.show_session_details = false;
+        }
+        _ => {}
+    }
+}
+
+/// Handles input while typing a search query. Enter runs it, Esc returns to browsing.
+fn handle_entering_search_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::Browsing;
+            app.editing_setting_input.clear();
+        }
+        KeyCode::Enter => {
+            let query = app.editing_setting_input.trim().to_string();
+            if query.is_empty() {
+                app.error_message = Some("Type a search query first.".to_string());
+                return;
+            }
+            app.editing_setting_input.clear();
+            app.current_state = AppState::Browsing;
+            remember_place(app);
+            app.collection_trail.clear();
+            start_search(app, query);
+        }
+        KeyCode::Char(c) => app.enter_char_edit_setting(c),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        _ => {}
+    }
+}
+
+/// Handles input while typing the items pane's live filter: the list narrows with every
+/// key. Enter keeps the filter, Esc clears it.
+fn handle_filtering_items_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            app.set_item_query(String::new());
+            app.current_state = AppState::Browsing;
+        }
+        KeyCode::Enter => app.current_state = AppState::Browsing,
+        KeyCode::Down => app.move_item_selection(Motion::Down(1)),
+        KeyCode::Up => app.move_item_selection(Motion::Up(1)),
+        KeyCode::Char(c) => {
+            let mut query = app.item_query.clone();
+            query.push(c);
+            app.set_item_query(query);
+        }
+        KeyCode::Backspace => {
+            let mut query = app.item_query.clone();
+            query.pop();
+            app.set_item_query(query);
+        }
+        _ => {}
+    }
+}
+
+/// Handles input on the exclusion rules screen (opened from the settings with 'x').
+fn handle_managing_exclusions_input(app: &mut App, key_event: KeyEvent) {
+    let count = exclusions::rules(&app.settings).len();
+    match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::SettingsView;
+            app.pending_action = Some(UpdateAction::SaveSettings);
+        }
+        KeyCode::Down if count > 0 => {
+            let next = app.exclusion_list_state.selected().map_or(0, |i| (i + 1) % count);
+            app.exclusion_list_state.select(Some(next));
+        }
+        KeyCode::Up if count > 0 => {
+            let previous = app.exclusion_list_state.selected().map_or(0, |i| if i == 0 { count - 1 } else { i - 1 });
+            app.exclusion_list_state.select(Some(previous));
+        }
+        KeyCode::Char('a') | KeyCode::Char('r') => {
+            app.new_exclusion_kind = if key_event.code == KeyCode::Char('a') { ExclusionKind::Identifier } else { ExclusionKind::Pattern };
+            app.current_state = AppState::AddingExclusion;
+            app.editing_setting_input.clear();
+            app.cursor_position = 0;
+        }
+        KeyCode::Delete | KeyCode::Backspace => {
+            if let Some(rule) = app.remove_selected_exclusion() {
+                app.download_status = Some(format!("Removed exclusion '{}'.", rule.value));
+                app.pending_action = Some(UpdateAction::SaveSettings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handles input while typing a new exclusion rule.
+/// Reuses `editing_setting_input` and `cursor_position`.
+fn handle_adding_exclusion_input(app: &mut App, key_event: KeyEvent) {
+    match key_event.code {
+        KeyCode::Esc => {
+            app.current_state = AppState::ManagingExclusions;
+            app.editing_setting_input.clear();
+            app.error_message = None;
+        }
+        KeyCode::Char(to_insert) => app.enter_char_edit_setting(to_insert),
+        KeyCode::Backspace => app.delete_char_edit_setting(),
+        KeyCode::Left => app.move_cursor_left_edit_setting(),
+        KeyCode::Right => app.move_cursor_right_edit_setting(),
+        KeyCode::Enter => {
+            let input = app.editing_setting_input.clone();
+            match app.add_exclusion(app.new_exclusion_kind, &input) {
+                Ok(_) => {
+                    app.current_state = AppState::ManagingExclusions;
+                    app.editing_setting_input.clear();
+                    app.error_message = None;
+                    app.pending_action = Some(UpdateAction::SaveSettings);
+                }
+                Err(e) => app.error_message = Some(format!("{:#}. Press Esc to cancel.", e)),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handles input when adding a new collection identifier.
+/// Uses `add_collection_input` and `add_collection_cursor_pos`.
+fn handle_adding_collection_input(app: &mut App, key_event: KeyEvent) {
+     match key_event.code {
+        KeyCode::Esc => {
+            // Cancel adding, revert to Browsing
+            app.current_state = AppState::Browsing;
+            app.add_collection_input.clear();
+            app.error_message = None;
+        }
+        KeyCode::Char(to_insert) => {
+            app.enter_char_add_collection(to_insert);
+        }
+        KeyCode::Backspace => {
+            app.delete_char_add_collection();
+        }
+        KeyCode::Left => {
+            app.move_cursor_left_add_collection();
+        }
+        KeyCode::Right => {
+            app.move_cursor_right_add_collection();
+        }
+        KeyCode::Enter => {
+            let identifier = app.add_collection_input.trim().to_string();
+            if !identifier.is_empty() {
+                app.add_collection_to_favorites(identifier);
+                // Trigger save settings action
+                app.pending_action = Some(UpdateAction::SaveSettings);
+                app.current_state = AppState::Browsing;
+                app.add_collection_input.clear();
+            } else {
+                app.error_message = Some("Collection identifier cannot be empty. Press Esc to cancel.".to_string());
+            }
+        }
+        _ => {} // Ignore other keys
+    }
+}
+
+
+// --- Tests ---
+// Note: Many existing tests related to the old input/filter/navigate modes
+// will need significant updates or removal due to the UI changes.
+// Adding some basic tests for the new pane switching and collection management.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{ActivePane, App, AppRateLimiter, AppState}; // Add ActivePane, AppRateLimiter
+    use crate::dedupe::DedupeMode;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    // Import necessary items for dummy rate limiter
+    use crate::rate_limit::SharedRateLimiter;
+    use governor::Quota;
+    use std::{num::NonZeroU32, sync::Arc};
+
+    // Helper function to create a dummy rate limiter for tests (allows all requests)
+    fn test_limiter() -> AppRateLimiter {
+        let quota = Quota::per_hour(NonZeroU32::new(u32::MAX).unwrap());
+        Arc::new(SharedRateLimiter::new(quota))
+    }
+
+    // Helper for setting up test environment with mock config
+    // Note: This helper doesn't need to interact with the actual config file system anymore,
+    // as App::load_settings uses the default path logic which is tested separately in settings::tests.
+    // We just need an App instance with some initial settings for UI interaction tests.
+    fn setup_test_app() -> App {
+        let limiter = test_limiter(); // Create dummy limiter
+        let mut app = App::new(limiter); // Pass the limiter
+        // Set some initial settings directly for testing UI logic
+        app.settings.favorite_collections = vec!["coll1".to_string(), "coll2".to_string(), "coll3".to_string()];
+        app.settings.download_directory = Some("/fake/test/dir".to_string()); // Assume a dir is set for some tests
+        app.collection_list_state.select(Some(0)); // Pre-select first collection
+        app
+    }
+
+    // Update tests to use the simplified setup helper
+    #[test]
+    fn test_update_quit_keys() {
+        let mut app = setup_test_app();
+        assert!(app.running);
+
+        // Test 'q' in Browsing
+        app.current_state = AppState::Browsing;
+        update(&mut app, KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(!app.running, "App should not be running after 'q'");
+
+        // Reset and test Ctrl+C in Browsing
+        app.running = true;
+        update(&mut app, KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(!app.running, "App should not be running after Ctrl+C");
+
+        // Reset and test Esc in Browsing (should quit)
+        app.running = true;
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(!app.running, "App should not be running after Esc in Browsing");
+    }
+
+    #[test]
+    fn test_update_tab_switches_panes_in_browsing() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+
+        update(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.active_pane, ActivePane::Items);
+
+        update(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.active_pane, ActivePane::Collections);
+    }
+
+    #[test]
+    fn test_update_collection_pane_navigation() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.collection_list_state.select(Some(0)); // Start at first
+
+        // Down
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.collection_list_state.selected(), Some(1));
+
+        // Down again
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.collection_list_state.selected(), Some(2));
+
+        // Down (wraps)
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.collection_list_state.selected(), Some(0));
+
+        // Up (wraps)
+        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(app.collection_list_state.selected(), Some(2));
+
+        // Up
+        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(app.collection_list_state.selected(), Some(1));
+    }
+
+     #[test]
+    fn test_update_item_pane_navigation() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items; // Focus items pane
+        app.items = vec![ // Add some dummy items
+            crate::archive_api::ArchiveDoc { identifier: "itemA".to_string(), ..Default::default() },
+            crate::archive_api::ArchiveDoc { identifier: "itemB".to_string(), ..Default::default() },
+        ];
+        app.item_list_state.select(None); // Start with nothing selected
+
+        // Down
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(0));
+
+        // Down
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(1));
+
+        // Down (wraps)
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(0));
+
+        // Up (wraps)
+        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_vim_keys_move_through_lists() {
+        use crate::archive_api::{ArchiveDoc, FileDetails, ItemDetails};
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.items = (0..40).map(|i| ArchiveDoc { identifier: format!("item{}", i), ..Default::default() }).collect();
+        app.item_list_state.select(Some(0));
+        let press = |app: &mut App, keys: &str| {
+            for c in keys.chars() {
+                update(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            }
+        };
+
+        press(&mut app, "25j");
+        assert_eq!(app.item_list_state.selected(), Some(25));
+        press(&mut app, "k");
+        assert_eq!(app.item_list_state.selected(), Some(24));
+        press(&mut app, "G");
+        assert_eq!(app.item_list_state.selected(), Some(39));
+        press(&mut app, "100j");
+        assert_eq!(app.item_list_state.selected(), Some(39), "Counts stop at the last item");
+        press(&mut app, "gg");
+        assert_eq!(app.item_list_state.selected(), Some(0));
+        press(&mut app, "12G");
+        assert_eq!(app.item_list_state.selected(), Some(11));
+        // A count is dropped by any key that doesn't use it
+        press(&mut app, "5");
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        press(&mut app, "j");
+        assert_eq!(app.item_list_state.selected(), Some(12));
+        // Pages are as long as the list was when last drawn
+        app.page_rows.items = 10;
+        update(&mut app, KeyEvent::new(KeyCode::PageDown, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(22));
+        press(&mut app, "2");
+        update(&mut app, KeyEvent::new(KeyCode::PageUp, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(2));
+        update(&mut app, KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+        assert_eq!(app.item_list_state.selected(), Some(39));
+
+        // h and l move between the panes, where the collections list moves the same way
+        press(&mut app, "h");
+        assert_eq!(app.active_pane, ActivePane::Collections);
+        app.collection_list_state.select(Some(0));
+        press(&mut app, "G");
+        assert_eq!(app.collection_list_state.selected(), Some(2));
+        press(&mut app, "j");
+        assert_eq!(app.collection_list_state.selected(), Some(0), "Single steps wrap around");
+        press(&mut app, "l");
+        assert_eq!(app.active_pane, ActivePane::Items);
+
+        // And so does the file list of the item view
+        app.open_item("item1".to_string());
+        let files = (0..10).map(|i| FileDetails { name: format!("file{}", i), ..Default::default() }).collect();
+        app.current_item_details = Some(ItemDetails { identifier: "item1".to_string(), files, ..Default::default() });
+        press(&mut app, "3j");
+        assert_eq!(app.file_list_state.selected(), Some(0), "The first move selects the first file");
+        press(&mut app, "3jG");
+        assert_eq!(app.file_list_state.selected(), Some(9));
+        press(&mut app, "2gg");
+        assert_eq!(app.file_list_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_update_collection_pane_enter_loads_items() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.collection_list_state.select(Some(1)); // Select "coll2"
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+
+        // Note: This test assumes cache miss. If cache existed, action would be None.
+        // TODO: Add a test case where cache *does* exist.
+        assert!(action.is_some(), "Expected an action when cache misses");
+        assert!(matches!(action, Some(UpdateAction::StartIncrementalItemFetch(ref name)) if name == "coll2"), "Action should be StartIncrementalItemFetch for 'coll2'");
+        assert_eq!(app.current_collection_name, Some("coll2".to_string()));
+        assert!(app.items.is_empty()); // Items cleared before fetch
+        assert!(app.item_list_state.selected().is_none()); // Item selection reset
+        assert!(app.is_loading); // Loading flag set
+        assert_eq!(app.active_pane, ActivePane::Items); // Focus switched to items pane
+    }
+
+     #[test]
+    fn test_update_collection_pane_delete_removes_item_and_saves() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.collection_list_state.select(Some(1)); // Select "coll2"
+        assert_eq!(app.settings.favorite_collections.len(), 3);
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+
+        assert!(action.is_some());
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.favorite_collections.len(), 2);
+        assert_eq!(app.settings.favorite_collections, vec!["coll1".to_string(), "coll3".to_string()]);
+        assert_eq!(app.collection_list_state.selected(), Some(1)); // Selection should move to "coll3"
+    }
+
+     #[test]
+    fn test_update_collection_pane_delete_removes_last_item() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.collection_list_state.select(Some(2)); // Select "coll3" (last item)
+        assert_eq!(app.settings.favorite_collections.len(), 3);
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+
+        assert!(action.is_some());
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.favorite_collections.len(), 2);
+        assert_eq!(app.settings.favorite_collections, vec!["coll1".to_string(), "coll2".to_string()]);
+        assert_eq!(app.collection_list_state.selected(), Some(1)); // Selection should move to new last item "coll2"
+    }
+
+
+    #[test]
+    fn test_update_collection_pane_a_enters_adding_state() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+
+        assert!(action.is_none());
+        assert_eq!(app.current_state, AppState::AddingCollection);
+        assert!(app.add_collection_input.is_empty());
+        assert_eq!(app.add_collection_cursor_pos, 0);
+    }
+
+    #[test]
+    fn test_update_adding_collection_input_and_save() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::AddingCollection;
+        assert_eq!(app.settings.favorite_collections.len(), 3);
+
+        // Simulate typing
+        update(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        assert_eq!(app.add_collection_input, "new");
+
+        // Enter to save
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+
+        assert!(action.is_some());
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert_eq!(app.settings.favorite_collections.len(), 4);
+        assert!(app.settings.favorite_collections.contains(&"new".to_string()));
+        // Check if it's selected (depends on sort order)
+        let expected_sorted = vec!["coll1", "coll2", "coll3", "new"]; // Assuming simple append then sort
+        assert_eq!(app.settings.favorite_collections, expected_sorted);
+        assert_eq!(app.collection_list_state.selected(), Some(3)); // Should select the new item
+    }
+
+     #[test]
+    fn test_update_adding_collection_esc_cancels() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::AddingCollection;
+        app.add_collection_input = "partial".to_string();
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+
+        assert!(action.is_none());
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert_eq!(app.settings.favorite_collections.len(), 3); // No change
+        assert!(app.add_collection_input.is_empty()); // Input cleared
+    }
+
+    #[test]
+    fn test_collection_groups_fold_and_filter() {
+        use crate::app::CollectionRow;
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.collection_list_state.select(Some(1)); // "coll2"
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('t'), KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::EnteringCollectionGroup);
+        for c in "live".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.collection_groups["coll2"], "live");
+        assert_eq!(
+            app.collection_rows(),
+            vec![
+                CollectionRow::Collection("coll1"),
+                CollectionRow::Collection("coll3"),
+                CollectionRow::Group { name: "live", count: 1, collapsed: false },
+                CollectionRow::Collection("coll2"),
+            ]
+        );
+        assert_eq!(app.get_selected_collection().map(String::as_str), Some("coll2"));
+
+        // Enter on the header folds the group
+        app.collection_list_state.select(Some(2));
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.collection_rows().len(), 3);
+        assert_eq!(app.get_selected_collection(), None);
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('T'), KeyModifiers::NONE));
+        assert_eq!(app.collection_group_filter.as_deref(), Some("live"));
+        assert_eq!(app.collection_rows(), vec![CollectionRow::Collection("coll2")]);
+
+        // Removing the group's last collection drops the filter
+        let action = update(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.collection_group_filter, None);
+        assert!(app.settings.collection_groups.is_empty());
+        assert_eq!(app.collection_rows().len(), 2);
+    }
+
+    #[test]
+    fn test_collections_move_by_hand_and_cycle_order() {
+        use crate::settings::CollectionOrder;
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.settings.download_directory = None; // Keep the opened times in memory
+        app.add_collection_to_favorites("a-first".to_string());
+        assert_eq!(app.settings.favorite_collections.last().map(String::as_str), Some("a-first"), "Added at the end");
+
+        app.collection_list_state.select(Some(2)); // "coll3"
+        assert!(matches!(update(&mut app, key('K')), Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.favorite_collections, vec!["coll1", "coll3", "coll2", "a-first"]);
+        assert_eq!(app.collection_list_state.selected(), Some(1), "Follows the collection");
+        app.collection_list_state.select(Some(3));
+        assert!(update(&mut app, key('J')).is_none(), "Already last");
+
+        update(&mut app, key('o'));
+        assert_eq!(app.settings.collection_order, CollectionOrder::Alphabetical);
+        assert_eq!(app.get_selected_collection().map(String::as_str), Some("a-first"));
+        assert_eq!(app.collection_list_state.selected(), Some(0));
+        assert_eq!(app.settings.favorite_collections[0], "coll1", "Sorting keeps the manual order");
+
+        update(&mut app, key('o'));
+        assert_eq!(app.settings.collection_order, CollectionOrder::Recent);
+        app.mark_collection_opened("coll2");
+        assert_eq!(app.collection_rows()[0], crate::app::CollectionRow::Collection("coll2"));
+        assert_eq!(app.get_selected_collection().map(String::as_str), Some("a-first"));
+
+        // Moving adopts the shown order as the manual one
+        app.collection_list_state.select(Some(1)); // "coll1"
+        update(&mut app, key('K'));
+        assert_eq!(app.settings.collection_order, CollectionOrder::Manual);
+        assert_eq!(app.settings.favorite_collections, vec!["coll1", "coll2", "coll3", "a-first"]);
+    }
+
+    #[test]
+    fn test_collections_show_titles_and_aliases() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.settings.download_directory = None; // Keep the titles in memory
+        app.add_collection_to_favorites(crate::saved_search::entry("year:1977"));
+        assert_eq!(app.take_untitled_collections(), vec!["coll1", "coll2", "coll3"], "Saved searches have no title");
+        assert!(app.take_untitled_collections().is_empty(), "Asked for once");
+
+        app.collection_list_state.select(Some(0));
+        app.apply_collection_titles(vec![("coll1".to_string(), "Zappa Live".to_string())]);
+        assert_eq!(app.collection_display_name("coll1"), "Zappa Live");
+        assert_eq!(app.collection_display_name("coll2"), "coll2");
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::EnteringCollectionAlias);
+        assert_eq!(app.editing_setting_input, "Zappa Live");
+        app.editing_setting_input = "Best of Zappa".to_string();
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.collection_aliases["coll1"], "Best of Zappa");
+        assert_eq!(app.collection_display_name("coll1"), "Best of Zappa");
+        assert_eq!(app.get_selected_collection().map(String::as_str), Some("coll1"), "The identifier is kept");
+
+        // Alphabetical order goes by the shown names
+        app.settings.collection_order = crate::settings::CollectionOrder::Alphabetical;
+        assert_eq!(app.collection_rows()[0], crate::app::CollectionRow::Collection(crate::saved_search::entry("year:1977").as_str()));
+        assert_eq!(app.collection_rows()[1], crate::app::CollectionRow::Collection("coll1"));
+
+        // Typing the title again drops the alias
+        app.set_collection_alias("coll1", "Zappa Live");
+        assert!(app.settings.collection_aliases.is_empty());
+    }
+
+    use crate::settings::DownloadMode; // Import for test
+    use crate::sidecar::SidecarFormat;
+    use crate::theme::ThemeKind;
+    use crate::thumbnail::CoverArt;
+
+    #[test]
+    fn test_update_settings_navigation_and_adjustment() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::SettingsView;
+        app.selected_setting_index = 0; // Start at Download Dir
+        app.settings_list_state.select(Some(0));
+        app.settings.download_mode = DownloadMode::Direct; // Start with Direct
+        app.settings.max_concurrent_downloads = Some(4);
+        app.settings.max_concurrent_collections = Some(1);
+
+        // Down to Download Mode
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 1);
+        assert_eq!(app.settings_list_state.selected(), Some(1));
+
+        // Right cycles Download Mode to TorrentOnly
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.download_mode, DownloadMode::TorrentOnly);
+
+        // Left cycles Download Mode back to Direct, and from there to the built-in client
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.download_mode, DownloadMode::Direct);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.download_mode, DownloadMode::TorrentEmbedded);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.download_mode, DownloadMode::Direct);
+
+        // Down to File Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 2);
+        assert_eq!(app.settings_list_state.selected(), Some(2));
+
+        // Right increases File Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.max_concurrent_downloads, Some(5));
+
+        // Left decreases File Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.max_concurrent_downloads, Some(4));
+
+        // Left again (min 1)
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.max_concurrent_downloads, Some(1));
+
+
+        // Down to Collection Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 3);
+        assert_eq!(app.settings_list_state.selected(), Some(3));
+
+         // Right increases Collection Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.max_concurrent_collections, Some(2));
+
+        // Left decreases Collection Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.max_concurrent_collections, Some(1));
+
+        // Left again (min 1)
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.max_concurrent_collections, Some(1));
+
+        // Down to Theme; Right cycles it and re-resolves the styles
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 4);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.theme, ThemeKind::HighContrast);
+        assert_eq!(app.theme.kind, ThemeKind::HighContrast);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.theme, ThemeKind::Default);
+
+        // Down to Update Check; Left/Right toggles it
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 5);
+        assert!(!app.settings.check_for_updates);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert!(app.settings.check_for_updates);
+
+        // Down to Metadata Sidecar; Right cycles Off -> README.txt -> metadata.md
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 6);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.metadata_sidecar, SidecarFormat::Text);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.metadata_sidecar, SidecarFormat::Markdown);
+
+        // Down to Confirm Threshold; Left lowers it but not below 0 (always confirm)
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 7);
+        app.settings.confirm_collection_above_gb = 1;
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.confirm_collection_above_gb, 0);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.confirm_collection_above_gb, 1);
+
+        // Down to Default File Preset; Right cycles it
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 8);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.default_file_preset, crate::presets::FilePreset::OriginalsOnly);
+        assert_eq!(app.download_preset, crate::presets::FilePreset::OriginalsOnly);
+
+        // Down to Subtitles; Left/Right toggles it
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 9);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert!(app.settings.include_subtitles);
+
+        // Down to Download Filter; Enter edits it as a comma-separated list
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 10);
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::EditingSetting);
+        for c in "*.flac, , qt".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert!(app.running);
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::SettingsView);
+        assert_eq!(app.settings.download_file_filter, vec!["*.flac".to_string(), "qt".to_string()]);
+
+        // Down to Torrent Backend; Right cycles it
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 11);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.torrent_backend, crate::torrent_backend::TorrentBackendKind::Transmission);
+
+        // Down to Speed Limit; Right sets the lowest cap and also updates the shared throttle
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 12);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.download_speed_limit_kb, 128);
+        assert_eq!(app.throttle.limit(), 128);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.download_speed_limit_kb, 0); // Back to Unlimited
+        assert_eq!(app.throttle.limit(), 0);
+
+        // Down to Collection Sync; Right turns it on at the shortest interval
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 13);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.sync_interval_minutes, 15);
+
+        // Down to File Sources; Right cycles to originals only
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 14);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.file_source_policy, crate::presets::SourcePolicy::OriginalsOnly);
+
+        // Down to Write Playlists; Right turns it on
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 15);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert!(app.settings.write_playlists);
+
+        // Down to Upload Keys; Enter starts with an empty input, Enter saves parsed keys
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 16);
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::EditingSetting);
+        assert!(app.editing_setting_input.is_empty());
+        for c in "AK SK".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::SaveUploadKeys)));
+        assert_eq!(app.s3_keys.as_ref().map(|k| k.secret.as_str()), Some("SK"));
+        assert_eq!(app.current_state, AppState::SettingsView);
+
+        // Down to Login; Enter logs in with email and password, Backspace logs out
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 17);
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        for c in "nope".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(app.error_message.is_some(), "An email and a password are needed");
+        assert_eq!(app.current_state, AppState::EditingSetting);
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        for c in "me@example.com open sesame".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        let Some(UpdateAction::Login(request)) = action else { panic!("Expected a login, got {:?}", action) };
+        assert_eq!((request.email.as_str(), request.password.as_str()), ("me@example.com", "open sesame"));
+        assert_eq!(app.current_state, AppState::SettingsView);
+        let action = update(&mut app, KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::Logout)));
+
+        // Down to IPFS Export; Right turns it on
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 18);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert!(app.settings.ipfs_export);
+
+        // Down to Checksum Manifests (on by default); Left turns it off
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 19);
+        assert!(app.settings.write_checksums);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert!(!app.settings.write_checksums);
+
+        // Down to Deduplicate Files; Right cycles to hard links
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 20);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.dedupe_mode, DedupeMode::Hardlink);
+
+        // Down to Tag Audio Files; Right turns it on, then cycles the policies back to off
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 21);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert!(app.settings.tag_audio);
+        assert_eq!(app.settings.tag_policy, TagPolicy::FillMissing);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.tag_policy, TagPolicy::Overwrite);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert!(!app.settings.tag_audio);
+
+        // Down to MusicBrainz Lookup; Right turns it on
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 22);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert!(app.settings.musicbrainz_lookup);
+
+        // Down to Media Player; Enter edits the command
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 23);
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.editing_setting_input, "mpv");
+        paste(&mut app, " --no-video");
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.settings.player_command, "mpv --no-video");
+        assert_eq!(app.current_state, AppState::SettingsView);
+
+        // Down to Parallel Segments; Right adds a connection, Left can't go below one
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 24);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.segments_per_file, 2);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.segments_per_file, 1);
+
+        // Down to Retry Failed Downloads; Left takes an attempt away
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 25);
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
+        assert_eq!(app.settings.retry_max_attempts, 4);
+
+        // Down to Item Details Cache; Right keeps details longer
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 26);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.details_cache_hours, 72);
+
+        // Down to Cover Art; Right cycles the protocol
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 27);
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
+        assert_eq!(app.settings.cover_art, CoverArt::Kitty);
+
+        // Down wraps to Download Dir
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.selected_setting_index, 0);
+
+        // Enter on Download Dir enters EditingSetting state
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(action.is_none());
+        assert_eq!(app.current_state, AppState::EditingSetting);
+    }
+
+    #[test]
+    fn test_update_repeated_moves_multiple_steps() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.items = (0..10)
+            .map(|i| crate::archive_api::ArchiveDoc { identifier: format!("item{}", i), ..Default::default() })
+            .collect();
+        app.item_list_state.select(Some(0));
+
+        let action = update_repeated(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE), 4);
+        assert!(action.is_none());
+        assert_eq!(app.item_list_state.selected(), Some(4));
+    }
+
+    #[test]
+    fn test_estimate_popup_opens_and_leads_to_download() {
+        let mut app = setup_test_app();
+        app.active_pane = ActivePane::Collections;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = vec![crate::archive_api::ArchiveDoc { identifier: "a".to_string(), item_size: Some(1), ..Default::default() }];
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        assert!(matches!(action, Some(UpdateAction::EstimateCollection(ref c)) if c == "coll1"));
+        assert_eq!(app.current_state, AppState::ViewingEstimate);
+        let (identifiers, plan) = app.estimate_inputs("coll1");
+        assert_eq!(identifiers, Some(vec!["a".to_string()]));
+        assert_eq!(plan.preset, app.download_preset);
+
+        // Esc closes without downloading; Enter goes on to the download
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.size_estimate.is_none());
+        assert!(app.running);
+        update(&mut app, KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::Collection(ref c))) if c == "coll1"));
+    }
+
+    #[test]
+    fn test_library_layout_cycles_per_collection() {
+        let mut app = setup_test_app();
+        app.active_pane = ActivePane::Collections;
+        let collection = app.get_selected_collection().cloned().unwrap();
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('L'), KeyModifiers::SHIFT));
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.library_layouts.get(&collection).map(String::as_str), Some(layout::PRESETS[0].1));
+        update(&mut app, KeyEvent::new(KeyCode::Char('L'), KeyModifiers::SHIFT));
+        assert_eq!(app.settings.library_layouts.get(&collection).map(String::as_str), Some(layout::PRESETS[1].1));
+        update(&mut app, KeyEvent::new(KeyCode::Char('L'), KeyModifiers::SHIFT));
+        assert!(app.settings.library_layouts.is_empty(), "Back to the default layout");
+        assert!(app.download_status.as_deref().is_some_and(|s| s.contains("default")));
+    }
+
+    #[test]
+    fn test_fetch_filter_is_checked_saved_and_drops_the_cached_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = setup_test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.active_pane = ActivePane::Collections;
+        app.save_items_to_cache("coll1", &[archive_api::ArchiveDoc { identifier: "old".to_string(), ..Default::default() }]).unwrap();
+        let identifiers = crate::download::identifier_cache_path(app.settings.download_directory.as_deref().unwrap(), "coll1");
+        std::fs::write(&identifiers, "[\"old\"]").unwrap();
+        let type_filter = |app: &mut App, filter: &str| {
+            update(app, KeyEvent::new(KeyCode::Char('Q'), KeyModifiers::SHIFT));
+            assert_eq!(app.current_state, AppState::EnteringFetchFilter);
+            app.editing_setting_input.clear();
+            for c in filter.chars() {
+                update(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            }
+            update(app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+        };
+
+        type_filter(&mut app, "2020-13..");
+        assert_eq!(app.current_state, AppState::EnteringFetchFilter, "An invalid filter isn't saved");
+        assert!(app.error_message.as_deref().is_some_and(|e| e.contains("not a date")));
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+
+        let action = type_filter(&mut app, "2020.. mediatype:audio");
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.fetch_filters.get("coll1").map(String::as_str), Some("2020.. mediatype:audio"));
+        assert!(app.load_items_from_cache("coll1").is_err(), "Listed anew with the filter");
+        assert!(!identifiers.exists(), "Collection downloads list it anew too");
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('Q'), KeyModifiers::SHIFT));
+        assert_eq!(app.editing_setting_input, "2020.. mediatype:audio", "The prompt starts from the saved filter");
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        type_filter(&mut app, "");
+        assert!(app.settings.fetch_filters.is_empty());
+    }
+
+    #[test]
+    fn test_collection_download_goes_through_forecast_above_threshold() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Collections;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = vec![
+            crate::archive_api::ArchiveDoc { identifier: "a".to_string(), item_size: Some(2_000_000), ..Default::default() },
+            crate::archive_api::ArchiveDoc { identifier: "b".to_string(), item_size: None, ..Default::default() },
+        ];
+
+        // Small collection under the threshold starts immediately
+        app.settings.confirm_collection_above_gb = 10;
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::Collection(ref c))) if c == "coll1"));
+
+        // Threshold 0 always asks; Esc cancels without quitting
+        app.settings.confirm_collection_above_gb = 0;
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(action.is_none());
+        assert_eq!(app.current_state, AppState::ConfirmingDownload);
+        let forecast = app.collection_forecast.clone().unwrap();
+        assert_eq!(forecast.item_count, Some(2));
+        assert_eq!(forecast.total_bytes, 2_000_000);
+        assert_eq!(forecast.unknown_sizes, 1);
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert!(app.running);
+        assert!(app.collection_forecast.is_none());
+
+        // Enter confirms
+        update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::Collection(ref c))) if c == "coll1"));
+        assert_eq!(app.current_state, AppState::Browsing);
+    }
+
+    #[test]
+    fn test_update_popup_install_and_dismiss() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        // 'U' does nothing until an update is known
+        update(&mut app, KeyEvent::new(KeyCode::Char('U'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::Browsing);
+
+        app.available_update = Some(crate::updater::ReleaseInfo {
+            version: "9.9.9".to_string(),
+            notes: "notes".to_string(),
+            url: String::new(),
+        });
+        update(&mut app, KeyEvent::new(KeyCode::Char('U'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::UpdateAvailable);
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert!(app.running, "Esc in the popup must not quit");
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('U'), KeyModifiers::SHIFT));
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::InstallUpdate)));
+        assert_eq!(app.current_state, AppState::Browsing);
+    }
+
+    #[test]
+    fn test_resume_prompt_resume_inspect_and_discard() {
+        use crate::session::DownloadJob;
+        use crate::settings::DownloadMode;
+        let mut app = setup_test_app();
+        app.session.record_start(DownloadJob {
+            action: DownloadAction::ItemAllFiles("item1".to_string()),
+            collection: None,
+            mode: DownloadMode::Direct,
+            base_dir: "/fake/test/dir".to_string(),
+            preset: crate::presets::FilePreset::AllFiles,
+            retry: None,
+        });
+        app.current_state = AppState::ResumePrompt;
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE));
+        assert!(app.show_session_details);
+        // Esc postpones the decision without quitting, holding the jobs
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert!(app.running);
+        assert!(app.session.entries().is_empty());
+        assert_eq!(app.session.held().len(), 1);
+
+        app.current_state = AppState::ResumePrompt;
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::ResumeSession)));
+        assert_eq!(app.current_state, AppState::Browsing);
+
+        app.current_state = AppState::ResumePrompt;
+        update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(app.session.entries().is_empty());
+        assert_eq!(app.download_status.as_deref(), Some("Discarded 1 interrupted download(s)."));
+    }
+
+    #[test]
+    fn test_resume_prompt_later_lets_new_downloads_start() {
+        use crate::session::DownloadJob;
+        use crate::settings::DownloadMode;
+        let job = |action| DownloadJob {
+            action,
+            collection: Some("coll1".to_string()),
+            mode: DownloadMode::Direct,
+            base_dir: "/fake/test/dir".to_string(),
+            preset: crate::presets::FilePreset::AllFiles,
+            retry: None,
+        };
+        let mut app = setup_test_app();
+        app.session.record_start(job(DownloadAction::Collection("coll1".to_string())));
+        app.current_state = AppState::ResumePrompt;
+        assert!(app.is_bulk_download_running());
+
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(!app.is_bulk_download_running(), "The interrupted collection isn't running");
+
+        // A collection queued now starts, and its end leaves nothing in flight
+        app.queue.push(job(DownloadAction::Collection("coll2".to_string())));
+        let next = app.next_queued_job().expect("The queue isn't blocked");
+        let id = app.session.record_start(next);
+        assert!(app.is_bulk_download_running());
+        app.session.record_finish(id);
+        assert!(app.session.entries().is_empty());
+        assert_eq!(app.session.held().len(), 1);
+    }
+
+    #[test]
+    fn test_resume_prompt_discards_queued_jobs() {
+        use crate::queue::{DownloadQueue, QUEUE_FILE};
+        use crate::session::DownloadJob;
+        use crate::settings::DownloadMode;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(QUEUE_FILE);
+        let mut previous_run = DownloadQueue::load(path.clone());
+        previous_run.push(DownloadJob {
+            action: DownloadAction::Collection("coll1".to_string()),
+            collection: Some("coll1".to_string()),
+            mode: DownloadMode::Direct,
+            base_dir: "/fake/test/dir".to_string(),
+            preset: crate::presets::FilePreset::AllFiles,
+            retry: None,
+        });
+
+        let mut app = setup_test_app();
+        app.queue = DownloadQueue::load(path.clone());
+        assert!(app.has_unfinished_session());
+        assert_eq!(app.unfinished_session_summary(), "1 download(s) queued");
+        assert!(app.next_queued_job().is_none(), "Held jobs wait for the prompt");
+
+        app.current_state = AppState::ResumePrompt;
+        update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert_eq!(app.download_status.as_deref(), Some("Discarded 0 interrupted and 1 queued download(s)."));
+        assert!(!app.has_unfinished_session());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_item_download_refused_without_free_space() {
+        use crate::archive_api::ArchiveDoc;
+        let mut app = setup_test_app();
+        app.active_pane = ActivePane::Items;
+        app.items = vec![ArchiveDoc { identifier: "huge".to_string(), item_size: Some(u64::MAX), ..Default::default() }];
+        app.item_list_state.select(Some(0));
+
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(action.is_none());
+        assert!(app.error_message.as_deref().unwrap().starts_with("Not enough free space for huge: needs"));
+
+        // With a preset the size isn't known up front; the download task checks it instead
+        app.download_preset = FilePreset::BestAudio;
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(_))));
+    }
+
+    #[test]
+    fn test_downloads_view_pauses_and_cancels() {
+        use crate::transfers::transfer_key;
+        let mut app = setup_test_app();
+        update(&mut app, KeyEvent::new(KeyCode::Char('D'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::ViewingDownloads);
+        app.active_transfers.start(transfer_key("item", "a.flac"), Some(10), 0, std::time::Instant::now());
+
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE));
+        assert!(app.download_control.is_paused("item"));
+        assert!(!app.download_control.is_paused("other"));
+        update(&mut app, KeyEvent::new(KeyCode::Char('P'), KeyModifiers::SHIFT));
+        assert!(app.download_control.is_paused("other"));
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE));
+        assert!(app.download_control.is_cancelled("item"));
+        let running = std::sync::Arc::clone(&app.download_control);
+        update(&mut app, KeyEvent::new(KeyCode::Char('C'), KeyModifiers::SHIFT));
+        assert!(running.is_cancelled("other"));
+        assert!(!app.download_control.is_cancelled("other"), "Later downloads get a fresh control");
+
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::Browsing);
+    }
+
+    #[test]
+    fn test_rebound_keys_and_help_screen() {
+        let mut app = setup_test_app();
+        app.settings.keys.insert("downloads".to_string(), vec!["Ctrl+t".to_string()]);
+        app.settings.keys.insert("quit".to_string(), vec!["Q".to_string()]);
+        app.refresh_keymap();
+
+        update(&mut app, KeyEvent::new(KeyCode::Char('D'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::Browsing, "Default key replaced");
+        update(&mut app, KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL));
+        assert_eq!(app.current_state, AppState::ViewingDownloads);
+        update(&mut app, KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(app.running);
+
+        // The help screen lists the active keys and returns to where it was opened
+        update(&mut app, KeyEvent::new(KeyCode::Char('?'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::ViewingHelp);
+        let help = app.keymap.help();
+        assert!(help.iter().flat_map(|(_, rows)| rows).any(|(keys, _)| keys == "Ctrl+t"));
+        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.help_table_state.selected(), Some(1));
+        update(&mut app, KeyEvent::new(KeyCode::Char('?'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::ViewingDownloads);
+        update(&mut app, KeyEvent::new(KeyCode::Char('Q'), KeyModifiers::SHIFT));
+        assert!(!app.running);
+    }
+
+    #[test]
+    fn test_failures_view_retries_failed_items() {
+        use crate::failures::{ErrorKind, Failure};
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let failure = |identifier: &str, file: Option<&str>| Failure {
+            identifier: identifier.to_string(),
+            file: file.map(str::to_string),
+            kind: ErrorKind::Network,
+            reason: "timed out".to_string(),
+            time: String::new(),
+        };
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.settings.download_directory = Some("/tmp/archive".to_string());
+
+        update(&mut app, key(KeyCode::Char('E')));
+        assert_eq!(app.current_state, AppState::ViewingFailures);
+        assert_eq!(app.failures_table_state.selected(), None);
+        assert!(update(&mut app, key(KeyCode::Char('r'))).is_none());
+        assert_eq!(app.error_message.as_deref(), Some("Nothing failed in the last download run."));
+        update(&mut app, key(KeyCode::Esc));
+
+        app.failures.failures = vec![failure("a", Some("1.mp3")), failure("a", Some("2.mp3")), failure("b", None)];
+        update(&mut app, key(KeyCode::Char('E')));
+        update(&mut app, key(KeyCode::Char('j')));
+        assert_eq!(app.failures_table_state.selected(), Some(1));
+        let action = update(&mut app, key(KeyCode::Char('r')));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::Items(ref ids))) if ids == &["a", "b"]));
+        assert_eq!(app.current_state, AppState::Browsing);
+    }
+
+    #[test]
+    fn test_log_view_scrolls_and_filters() {
+        use crate::log_buffer::LogLine;
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut app = setup_test_app();
+        app.current_state = AppState::ViewingItem;
+        for (level, message) in [(log::Level::Info, "Listing gd1977"), (log::Level::Error, "Failed to download d1t01.flac"), (log::Level::Info, "Done")] {
+            app.log_buffer.push(LogLine { time: chrono::Local::now(), level, message: message.to_string() });
+        }
+
+        update(&mut app, key(KeyCode::Char('!')));
+        assert_eq!(app.current_state, AppState::ViewingLog);
+        assert_eq!(app.log_list_state.selected(), None, "Follows the newest line");
+        update(&mut app, key(KeyCode::Char('k')));
+        assert_eq!(app.log_list_state.selected(), Some(1));
+        update(&mut app, key(KeyCode::Char('G')));
+        assert_eq!(app.log_list_state.selected(), None, "Back to following");
+
+        update(&mut app, key(KeyCode::Char('v')));
+        assert_eq!(app.log_level, log::Level::Warn);
+        assert_eq!(app.log_buffer.lines(app.log_level).len(), 1);
+        update(&mut app, key(KeyCode::Esc));
+        assert_eq!(app.current_state, AppState::ViewingItem);
+    }
+
+    #[test]
+    fn test_command_palette_runs_actions() {
+        use crate::archive_api::ArchiveDoc;
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = setup_test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = vec![ArchiveDoc { identifier: "a".to_string(), ..Default::default() }];
+        app.item_list_state.select(Some(0));
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let run = |app: &mut App, command: &str| {
+            update(app, key(KeyCode::Char(':')));
+            assert_eq!(app.current_state, AppState::EnteringCommand);
+            for c in command.chars() {
+                update(app, key(KeyCode::Char(c)));
+            }
+            update(app, key(KeyCode::Enter))
+        };
+
+        // 'q' and 's' are typed into the palette, not handled as keys
+        run(&mut app, "export");
+        assert_eq!(app.current_state, AppState::EnteringExportPath, "Same as pressing 'e'");
+        update(&mut app, key(KeyCode::Esc));
+        assert!(matches!(run(&mut app, "sync"), Some(UpdateAction::SyncNow)));
+        assert!(app.running);
+
+        run(&mut app, "verify");
+        assert_eq!(app.error_message.as_deref(), Some("'a' has not been downloaded yet."));
+        std::fs::create_dir_all(dir.path().join("coll1").join("a")).unwrap();
+        assert!(matches!(run(&mut app, "verify"), Some(UpdateAction::VerifyItem(id, _)) if id == "a"));
+
+        update(&mut app, key(KeyCode::Char(':')));
+        update(&mut app, key(KeyCode::Char('z')));
+        update(&mut app, key(KeyCode::Char('z')));
+        update(&mut app, key(KeyCode::Enter));
+        assert_eq!(app.current_state, AppState::EnteringCommand, "Nothing to run");
+        update(&mut app, key(KeyCode::Esc));
+        assert_eq!(app.current_state, AppState::Browsing);
+    }
+
+    #[test]
+    fn test_clipboard_browser_and_player_actions() {
+        use crate::archive_api::{ArchiveDoc, FileDetails, ItemDetails};
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let copied = |action: Option<UpdateAction>| match action {
+            Some(UpdateAction::CopyToClipboard(text)) => text,
+            other => panic!("Expected a copy, got {:?}", other),
+        };
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.items = vec![ArchiveDoc { identifier: "gd1977".to_string(), ..Default::default() }];
+        app.item_list_state.select(Some(0));
+
+        assert_eq!(copied(update(&mut app, key(KeyCode::Char('y')))), "gd1977");
+        assert_eq!(copied(update(&mut app, key(KeyCode::Char('Y')))), archive_api::details_url("gd1977"));
+        let action = update(&mut app, key(KeyCode::Char('O')));
+        assert!(matches!(action, Some(UpdateAction::OpenUrl(ref url)) if *url == archive_api::details_url("gd1977")));
+        app.open_item("gd1977".to_string());
+        app.current_item_details = Some(ItemDetails {
+            identifier: "gd1977".to_string(),
+            files: vec![FileDetails { name: "d1t01.flac".to_string(), ..Default::default() }],
+            ..Default::default()
+        });
+        assert_eq!(copied(update(&mut app, key(KeyCode::Char('y')))), "gd1977", "No file selected");
+        update(&mut app, key(KeyCode::Down));
+        assert_eq!(copied(update(&mut app, key(KeyCode::Char('y')))), archive_api::download_url("gd1977", "d1t01.flac"));
+        let action = update(&mut app, key(KeyCode::Char('P')));
+        assert!(matches!(action, Some(UpdateAction::PlayUrl(ref url)) if *url == archive_api::download_url("gd1977", "d1t01.flac")));
+        let action = update(&mut app, key(KeyCode::Char(' ')));
+        assert!(matches!(action, Some(UpdateAction::PreviewFile(ref id, ref file)) if id == "gd1977" && file.name == "d1t01.flac"));
+        app.pending_action = None;
+        app.current_item_details.as_mut().unwrap().files[0].name = "d1t01.ogg".to_string();
+        assert!(update(&mut app, key(KeyCode::Char(' '))).is_none());
+        assert_eq!(app.error_message.as_deref(), Some("'d1t01.ogg' isn't an MP3 or FLAC file."));
+        assert!(!global_action(&mut app, Action::StopPreview), "Nothing playing");
+        app.item_tab = ItemTab::Reviews;
+        assert!(update(&mut app, key(KeyCode::Char('P'))).is_none());
+        assert_eq!(app.error_message.as_deref(), Some("Select a file to play."));
+
+        // Pastes only go into text prompts
+        app.current_state = AppState::Browsing;
+        app.pending_action = None;
+        paste(&mut app, "dqx");
+        assert!(app.pending_action.is_none() && app.current_state == AppState::Browsing);
+        app.current_state = AppState::AddingCollection;
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL));
+        assert!(matches!(action, Some(UpdateAction::PasteClipboard)));
+        assert!(app.add_collection_input.is_empty());
+        paste(&mut app, "nasa\n");
+        assert_eq!(app.add_collection_input, "nasa", "Line breaks are dropped");
+        app.current_state = AppState::AskingDownloadDir;
+        app.editing_setting_input.clear();
+        app.cursor_position = 0;
+        paste(&mut app, "/srv/archive");
+        assert_eq!(app.editing_setting_input, "/srv/archive");
+    }
+
+    #[test]
+    fn test_exclusions_screen_add_and_remove() {
+        let mut app = setup_test_app();
+        app.current_state = AppState::SettingsView;
+        update(&mut app, KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::ManagingExclusions);
+
+        // A regex containing 'q' is typed, not treated as quit
+        update(&mut app, KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::AddingExclusion);
+        for c in "^eq_".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert!(app.running);
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        assert_eq!(app.settings.excluded_patterns, vec!["^eq_".to_string()]);
+        assert!(app.exclusions.is_excluded("eq_1977"));
+
+        // Invalid patterns are rejected and the input stays open
+        update(&mut app, KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Char('('), KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::AddingExclusion);
+        assert!(app.error_message.is_some());
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+
+        update(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+        assert!(app.settings.excluded_patterns.is_empty());
+        assert!(!app.exclusions.is_excluded("eq_1977"));
+        update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::SettingsView);
     }
 
-    // Update tests to use the simplified setup helper
     #[test]
-    fn test_update_quit_keys() {
+    fn test_search_query_replaces_collection_items() {
         let mut app = setup_test_app();
-        assert!(app.running);
-
-        // Test 'q' in Browsing
         app.current_state = AppState::Browsing;
-        update(&mut app, KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE));
-        assert!(!app.running, "App should not be running after 'q'");
+        app.current_collection_name = Some("coll1".to_string());
 
-        // Reset and test Ctrl+C in Browsing
-        app.running = true;
-        update(&mut app, KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
-        assert!(!app.running, "App should not be running after Ctrl+C");
+        update(&mut app, KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        assert_eq!(app.current_state, AppState::EnteringSearch);
+        // A 'q' in the query is typed, not treated as quit
+        for c in "quake".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert!(app.running);
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartSearch(ref q)) if q == "quake"));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert_eq!(app.active_pane, ActivePane::Items);
+        assert_eq!(app.search_query.as_deref(), Some("quake"));
+        assert_eq!(app.current_collection_name, None);
+        assert!(app.is_loading);
 
-        // Reset and test Esc in Browsing (should quit)
-        app.running = true;
+        // Reopening the prompt starts from the previous query
+        update(&mut app, KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        assert_eq!(app.editing_setting_input, "quake");
         update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
-        assert!(!app.running, "App should not be running after Esc in Browsing");
+        assert_eq!(app.current_state, AppState::Browsing);
     }
 
     #[test]
-    fn test_update_tab_switches_panes_in_browsing() {
+    fn test_saved_search_lists_like_a_collection() {
         let mut app = setup_test_app();
         app.current_state = AppState::Browsing;
-        app.active_pane = ActivePane::Collections;
+        app.active_pane = ActivePane::Items;
+        update(&mut app, KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE));
+        assert!(app.error_message.is_some(), "Nothing to save without a search");
+        app.error_message = None;
 
-        update(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
-        assert_eq!(app.active_pane, ActivePane::Items);
+        app.search_query = Some("creator:\"Grateful Dead\" AND year:1977".to_string());
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        let entry = "search:creator:\"Grateful Dead\" AND year:1977";
+        let index = app.settings.favorite_collections.iter().position(|c| c == entry).expect("saved");
+        assert_eq!(app.collection_list_state.selected(), Some(index));
+        assert!(update(&mut app, KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE)).is_none(), "Saved once");
+        assert_eq!(app.settings.favorite_collections.len(), 4);
 
-        update(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
-        assert_eq!(app.active_pane, ActivePane::Collections);
+        app.active_pane = ActivePane::Collections;
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartIncrementalItemFetch(ref c)) if c == entry));
+        assert_eq!(app.search_query, None);
+        assert_eq!(app.breadcrumb_path(), "[Search] creator:\"Grateful Dead\" AND year:1977");
+        let dir = crate::paths::collection_dir("/fake/test/dir", Some(entry), &crate::paths::PathPolicy::default());
+        assert!(dir.ends_with(saved_search::local_name(entry).as_ref()), "{}", dir.display());
     }
 
     #[test]
-    fn test_update_collection_pane_navigation() {
+    fn test_items_pane_status_filter() {
+        use crate::app::DownloadProgress;
+        use crate::archive_api::ArchiveDoc;
         let mut app = setup_test_app();
         app.current_state = AppState::Browsing;
-        app.active_pane = ActivePane::Collections;
-        app.collection_list_state.select(Some(0)); // Start at first
-
-        // Down
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.collection_list_state.selected(), Some(1));
-
-        // Down again
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.collection_list_state.selected(), Some(2));
-
-        // Down (wraps)
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.collection_list_state.selected(), Some(0));
-
-        // Up (wraps)
-        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
-        assert_eq!(app.collection_list_state.selected(), Some(2));
-
-        // Up
-        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
-        assert_eq!(app.collection_list_state.selected(), Some(1));
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = ["a", "b", "c"].iter().map(|id| ArchiveDoc { identifier: id.to_string(), ..Default::default() }).collect();
+        app.apply_download_progress(DownloadProgress::ItemCompleted("b".to_string(), true));
+        app.apply_download_progress(DownloadProgress::ItemCompleted("c".to_string(), false));
+
+        let shown = |app: &App| -> Vec<String> { app.visible_items().iter().map(|d| d.identifier.clone()).collect() };
+        let v = KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE);
+        assert_eq!(shown(&app), vec!["a", "b", "c"]);
+        update(&mut app, v);
+        assert_eq!(shown(&app), vec!["a"]);
+        update(&mut app, v);
+        assert_eq!(shown(&app), vec!["b"]);
+        update(&mut app, v);
+        assert_eq!(shown(&app), vec!["c"]);
+        // Actions apply to the selected item of the filtered list
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(ref id))) if id == "c"));
+        update(&mut app, v);
+        assert_eq!(shown(&app).len(), 3);
     }
 
-     #[test]
-    fn test_update_item_pane_navigation() {
+    #[test]
+    fn test_export_listed_items_and_viewed_item() {
+        use crate::archive_api::{ArchiveDoc, FileDetails, ItemDetails};
+        let dir = tempfile::tempdir().unwrap();
         let mut app = setup_test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
         app.current_state = AppState::Browsing;
-        app.active_pane = ActivePane::Items; // Focus items pane
-        app.items = vec![ // Add some dummy items
-            crate::archive_api::ArchiveDoc { identifier: "itemA".to_string() },
-            crate::archive_api::ArchiveDoc { identifier: "itemB".to_string() },
-        ];
-        app.item_list_state.select(None); // Start with nothing selected
-
-        // Down
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.item_list_state.selected(), Some(0));
-
-        // Down
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.item_list_state.selected(), Some(1));
-
-        // Down (wraps)
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.item_list_state.selected(), Some(0));
-
-        // Up (wraps)
-        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
-        assert_eq!(app.item_list_state.selected(), Some(1));
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = ["a", "b"].iter().map(|id| ArchiveDoc { identifier: id.to_string(), ..Default::default() }).collect();
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+
+        update(&mut app, key(KeyCode::Char('e')));
+        assert_eq!(app.current_state, AppState::EnteringExportPath);
+        assert!(app.editing_setting_input.ends_with("coll1.csv"), "Suggests a file in the download directory");
+        update(&mut app, key(KeyCode::Enter));
+        assert_eq!(app.current_state, AppState::Browsing);
+        let csv = std::fs::read_to_string(dir.path().join("coll1.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 3, "Header plus one row per listed item");
+
+        app.current_state = AppState::ViewingItem;
+        app.current_item_details = Some(ItemDetails {
+            identifier: "a".to_string(),
+            files: vec![FileDetails { name: "a.mp3".to_string(), ..Default::default() }],
+            ..Default::default()
+        });
+        update(&mut app, key(KeyCode::Char('e')));
+        for _ in 0..3 {
+            update(&mut app, key(KeyCode::Backspace));
+        }
+        for c in "json".chars() {
+            update(&mut app, key(KeyCode::Char(c)));
+        }
+        update(&mut app, key(KeyCode::Enter));
+        assert_eq!(app.current_state, AppState::ViewingItem);
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.path().join("a.json")).unwrap()).unwrap();
+        assert_eq!(json["files"][0]["name"], "a.mp3");
     }
 
     #[test]
-    fn test_update_collection_pane_enter_loads_items() {
+    fn test_sub_collections_open_like_directories() {
+        let dir = tempfile::tempdir().unwrap();
         let mut app = setup_test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.settings.favorite_collections = vec!["parent".to_string()];
+        let doc = |id: &str, mediatype: &str| crate::archive_api::ArchiveDoc { identifier: id.to_string(), mediatype: Some(mediatype.to_string()), ..Default::default() };
+        app.save_items_to_cache("parent", &[doc("gd77", "audio"), doc("sub", "collection"), doc("deep", "collection")]).unwrap();
+        app.save_items_to_cache("sub", &[doc("tape1", "audio")]).unwrap();
         app.current_state = AppState::Browsing;
-        app.active_pane = ActivePane::Collections;
-        app.collection_list_state.select(Some(1)); // Select "coll2"
-
-        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
-
-        // Note: This test assumes cache miss. If cache existed, action would be None.
-        // TODO: Add a test case where cache *does* exist.
-        assert!(action.is_some(), "Expected an action when cache misses");
-        // Use the correct action variant StartBulkItemFetch
-        assert!(matches!(action, Some(UpdateAction::StartBulkItemFetch(ref name)) if name == "coll2"), "Action should be StartBulkItemFetch for 'coll2'");
-        assert_eq!(app.current_collection_name, Some("coll2".to_string()));
-        assert!(app.items.is_empty()); // Items cleared before fetch
-        assert!(app.item_list_state.selected().is_none()); // Item selection reset
-        assert!(app.is_loading); // Loading flag set
-        assert_eq!(app.active_pane, ActivePane::Items); // Focus switched to items pane
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+
+        update(&mut app, key(KeyCode::Enter));
+        assert_eq!(app.current_collection_name.as_deref(), Some("parent"));
+        update(&mut app, key(KeyCode::Down));
+        update(&mut app, key(KeyCode::Enter));
+        assert_eq!(app.current_state, AppState::Browsing, "A collection is listed, not shown as an item");
+        assert_eq!(app.breadcrumb_path(), "parent > sub");
+        assert_eq!(app.selected_item().map(|d| d.identifier.as_str()), Some("tape1"));
+
+        // Esc goes back up (instead of quitting) and reselects the sub-collection
+        update(&mut app, key(KeyCode::Esc));
+        assert!(app.running);
+        assert_eq!(app.breadcrumb_path(), "parent");
+        assert_eq!(app.selected_item().map(|d| d.identifier.as_str()), Some("sub"));
+
+        // An uncached sub-collection is fetched
+        update(&mut app, key(KeyCode::Down));
+        let action = update(&mut app, key(KeyCode::Enter));
+        assert!(matches!(action, Some(UpdateAction::StartIncrementalItemFetch(ref name)) if name == "deep"));
+        update(&mut app, key(KeyCode::Backspace));
+        assert!(app.collection_trail.is_empty());
+        update(&mut app, key(KeyCode::Backspace));
+        assert_eq!(app.breadcrumb_path(), "parent", "Backspace does nothing at the top");
+        update(&mut app, key(KeyCode::Esc));
+        assert!(!app.running, "Esc at the top still quits");
     }
 
-     #[test]
-    fn test_update_collection_pane_delete_removes_item_and_saves() {
+    #[test]
+    fn test_history_moves_back_and_forward() {
+        let dir = tempfile::tempdir().unwrap();
         let mut app = setup_test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.settings.favorite_collections = vec!["parent".to_string()];
+        let doc = |id: &str, mediatype: &str| crate::archive_api::ArchiveDoc { identifier: id.to_string(), mediatype: Some(mediatype.to_string()), ..Default::default() };
+        app.save_items_to_cache("parent", &[doc("gd77", "audio"), doc("sub", "collection")]).unwrap();
+        app.save_items_to_cache("sub", &[doc("tape1", "audio")]).unwrap();
         app.current_state = AppState::Browsing;
-        app.active_pane = ActivePane::Collections;
-        app.collection_list_state.select(Some(1)); // Select "coll2"
-        assert_eq!(app.settings.favorite_collections.len(), 3);
-
-        let action = update(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
 
-        assert!(action.is_some());
-        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
-        assert_eq!(app.settings.favorite_collections.len(), 2);
-        assert_eq!(app.settings.favorite_collections, vec!["coll1".to_string(), "coll3".to_string()]);
-        assert_eq!(app.collection_list_state.selected(), Some(1)); // Selection should move to "coll3"
+        update(&mut app, key(KeyCode::Enter)); // List "parent"
+        update(&mut app, key(KeyCode::Enter)); // View gd77
+        assert_eq!(app.current_state, AppState::ViewingItem);
+        update(&mut app, key(KeyCode::Char('[')));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert_eq!(app.breadcrumb_path(), "parent");
+        let action = update(&mut app, key(KeyCode::Char(']')));
+        assert_eq!(app.viewing_item_id.as_deref(), Some("gd77"));
+        assert!(matches!(action, Some(UpdateAction::FetchItemDetails)));
+
+        // Alt+Left/Right work too, and restore the sub-collection trail
+        update(&mut app, key(KeyCode::Esc));
+        update(&mut app, key(KeyCode::Down));
+        update(&mut app, key(KeyCode::Enter));
+        assert_eq!(app.breadcrumb_path(), "parent > sub");
+        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::ALT));
+        assert_eq!(app.breadcrumb_path(), "parent");
+        assert!(app.collection_trail.is_empty());
+        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::ALT));
+        assert_eq!(app.breadcrumb_path(), "parent > sub");
+        assert_eq!(app.selected_item().map(|d| d.identifier.as_str()), Some("tape1"));
+        update(&mut app, key(KeyCode::Char(']')));
+        assert!(app.download_status.as_deref().unwrap_or("").contains("Nothing to go forward"));
     }
 
-     #[test]
-    fn test_update_collection_pane_delete_removes_last_item() {
+    #[test]
+    fn test_import_favorites_asks_for_screen_name_once() {
         let mut app = setup_test_app();
         app.current_state = AppState::Browsing;
         app.active_pane = ActivePane::Collections;
-        app.collection_list_state.select(Some(2)); // Select "coll3" (last item)
-        assert_eq!(app.settings.favorite_collections.len(), 3);
 
-        let action = update(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+        update(&mut app, KeyEvent::new(KeyCode::Char('F'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::EnteringFavoritesUser);
+        update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(app.error_message.is_some(), "A screen name is needed");
+        for c in "@quinn".chars() {
+            update(&mut app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::ImportFavorites(ref user)) if user == "quinn"));
+        assert_eq!(app.current_state, AppState::Browsing);
 
-        assert!(action.is_some());
-        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
-        assert_eq!(app.settings.favorite_collections.len(), 2);
-        assert_eq!(app.settings.favorite_collections, vec!["coll1".to_string(), "coll2".to_string()]);
-        assert_eq!(app.collection_list_state.selected(), Some(1)); // Selection should move to new last item "coll2"
+        // Once the screen name is known (saved by the import or a login), 'F' imports right away
+        app.settings.archive_screenname = Some("quinn".to_string());
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('F'), KeyModifiers::SHIFT));
+        assert!(matches!(action, Some(UpdateAction::ImportFavorites(ref user)) if user == "quinn"));
+        assert_eq!(app.current_state, AppState::Browsing);
     }
 
-
     #[test]
-    fn test_update_collection_pane_a_enters_adding_state() {
+    fn test_wayback_lookup_lists_and_saves_captures() {
+        use crate::wayback::{Snapshot, WaybackProgress};
         let mut app = setup_test_app();
         app.current_state = AppState::Browsing;
-        app.active_pane = ActivePane::Collections;
-
-        let action = update(&mut app, KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
 
-        assert!(action.is_none());
-        assert_eq!(app.current_state, AppState::AddingCollection);
-        assert!(app.add_collection_input.is_empty());
-        assert_eq!(app.add_collection_cursor_pos, 0);
+        update(&mut app, KeyEvent::new(KeyCode::Char('W'), KeyModifiers::SHIFT));
+        assert_eq!(app.current_state, AppState::EnteringWaybackUrl);
+        for c in "example.com 2005 2012".chars() {
+            update(&mut app, key(KeyCode::Char(c)));
+        }
+        let action = update(&mut app, key(KeyCode::Enter));
+        let Some(UpdateAction::ListWayback(query)) = action else { panic!("Expected a lookup, got {:?}", action) };
+        assert_eq!((query.url.as_str(), query.from.as_deref(), query.to.as_deref()), ("example.com", Some("2005"), Some("2012")));
+        assert_eq!(app.current_state, AppState::ViewingWayback);
+
+        let capture = |timestamp: &str, status: &str, digest: &str| Snapshot {
+            timestamp: timestamp.to_string(),
+            original: "http://example.com/".to_string(),
+            mimetype: "text/html".to_string(),
+            status: status.to_string(),
+            digest: digest.to_string(),
+            length: None,
+        };
+        let snapshots = vec![capture("20050101000000", "200", "A"), capture("20080101000000", "404", "B"), capture("20120101000000", "200", "C")];
+        app.apply_wayback_progress(WaybackProgress::Listed(query, Ok(snapshots)));
+        assert_eq!(app.wayback.as_ref().unwrap().list_state.selected(), Some(2), "The latest capture is selected");
+
+        app.settings.download_directory = None;
+        update(&mut app, key(KeyCode::Enter));
+        assert!(app.error_message.is_some(), "A download directory is needed");
+        app.settings.download_directory = Some("/tmp/archiver-test".to_string());
+        update(&mut app, key(KeyCode::Up));
+        update(&mut app, key(KeyCode::Char('d')));
+        assert!(app.error_message.as_deref().unwrap().contains("404"), "A 404 capture is not a page");
+
+        let action = update(&mut app, key(KeyCode::Char('a')));
+        let Some(UpdateAction::DownloadWayback(to_save)) = action else { panic!("Expected a download, got {:?}", action) };
+        assert_eq!(to_save.len(), 2);
+        assert!(update(&mut app, key(KeyCode::Char('a'))).is_none(), "One download at a time");
+        app.apply_wayback_progress(WaybackProgress::Finished { saved: 2, failed: 0 });
+        assert!(update(&mut app, key(KeyCode::Char('a'))).is_some());
+
+        update(&mut app, key(KeyCode::Esc));
+        assert_eq!(app.current_state, AppState::Browsing);
+        assert!(app.running, "Esc leaves the captures view without quitting");
     }
 
     #[test]
-    fn test_update_adding_collection_input_and_save() {
+    fn test_upload_form_validates_and_starts_upload() {
+        let dir = tempfile::tempdir().unwrap();
         let mut app = setup_test_app();
-        app.current_state = AppState::AddingCollection;
-        assert_eq!(app.settings.favorite_collections.len(), 3);
-
-        // Simulate typing
-        update(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE));
-        update(&mut app, KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE));
-        update(&mut app, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
-        assert_eq!(app.add_collection_input, "new");
-
-        // Enter to save
-        let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        app.current_state = AppState::Browsing;
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let type_text = |app: &mut App, text: &str| {
+            for c in text.chars() {
+                update(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            }
+        };
 
-        assert!(action.is_some());
-        assert!(matches!(action, Some(UpdateAction::SaveSettings)));
+        update(&mut app, key(KeyCode::Char('u')));
+        assert_eq!(app.current_state, AppState::EditingUpload);
+        type_text(&mut app, &dir.path().to_string_lossy());
+        update(&mut app, key(KeyCode::Tab));
+        type_text(&mut app, "bad id");
+        update(&mut app, key(KeyCode::Enter));
+        assert!(app.error_message.as_deref().unwrap_or("").contains("S3 keys"), "Keys are needed first");
+
+        app.s3_keys = crate::ia_upload::S3Keys::parse("AK SK");
+        update(&mut app, key(KeyCode::Enter));
+        assert!(app.error_message.as_deref().unwrap_or("").contains("not a valid identifier"));
+        assert_eq!(app.current_state, AppState::EditingUpload);
+        for _ in 0..3 {
+            update(&mut app, key(KeyCode::Backspace));
+        }
+        type_text(&mut app, "_q1"); // 'q' is typed, not quit
+        update(&mut app, key(KeyCode::Down));
+        type_text(&mut app, "My Tapes");
+
+        let action = update(&mut app, key(KeyCode::Enter));
+        let Some(UpdateAction::StartUpload(request)) = action else { panic!("Expected an upload, got {:?}", action) };
+        assert_eq!(request.identifier, "bad_q1");
+        assert_eq!(request.directory, dir.path());
+        assert_eq!(
+            request.metadata,
+            vec![("title".to_string(), "My Tapes".to_string()), ("mediatype".to_string(), "data".to_string()), ("collection".to_string(), "opensource".to_string())]
+        );
         assert_eq!(app.current_state, AppState::Browsing);
-        assert_eq!(app.settings.favorite_collections.len(), 4);
-        assert!(app.settings.favorite_collections.contains(&"new".to_string()));
-        // Check if it's selected (depends on sort order)
-        let expected_sorted = vec!["coll1", "coll2", "coll3", "new"]; // Assuming simple append then sort
-        assert_eq!(app.settings.favorite_collections, expected_sorted);
-        assert_eq!(app.collection_list_state.selected(), Some(3)); // Should select the new item
+        assert_eq!(app.upload.as_ref().map(|u| u.identifier.as_str()), Some("bad_q1"));
+
+        // Progress messages update the status until the upload ends
+        app.apply_upload_progress(crate::ia_upload::UploadProgress::Started { files: 2, bytes: 10 });
+        app.apply_upload_progress(crate::ia_upload::UploadProgress::File("a".to_string()));
+        app.apply_upload_progress(crate::ia_upload::UploadProgress::File("b".to_string()));
+        assert_eq!(app.upload.as_ref().map(|u| u.files_done), Some(1));
+        app.apply_upload_progress(crate::ia_upload::UploadProgress::Finished);
+        assert!(app.upload.is_none());
+        assert!(app.download_status.as_deref().unwrap_or("").contains("details/bad_q1"));
     }
 
-     #[test]
-    fn test_update_adding_collection_esc_cancels() {
+    #[test]
+    fn test_items_pane_live_filter_narrows_as_you_type() {
+        use crate::archive_api::ArchiveDoc;
         let mut app = setup_test_app();
-        app.current_state = AppState::AddingCollection;
-        app.add_collection_input = "partial".to_string();
-
-        let action = update(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
-
-        assert!(action.is_none());
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        let doc = |id: &str, title: &str| ArchiveDoc { identifier: id.to_string(), title: Some(title.to_string()), ..Default::default() };
+        app.items = vec![doc("gd77-05-08", "Grateful Dead Live at Barton Hall"), doc("jg1990", "Jerry Garcia Band"), doc("gd1972", "Grateful Dead Europe")];
+        app.item_list_state.select(Some(0));
+        let shown = |app: &App| -> Vec<String> { app.visible_items().iter().map(|d| d.identifier.clone()).collect() };
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+
+        update(&mut app, key(KeyCode::Char('f')));
+        assert_eq!(app.current_state, AppState::FilteringItems);
+        for c in "dead q".chars() {
+            update(&mut app, key(KeyCode::Char(c)));
+        }
+        assert!(shown(&app).is_empty(), "'q' is part of the filter, not quit");
+        assert!(app.running);
+        update(&mut app, key(KeyCode::Backspace));
+        update(&mut app, key(KeyCode::Char('7')));
+        assert_eq!(shown(&app), vec!["gd77-05-08", "gd1972"], "Titles and identifiers both match");
+        update(&mut app, key(KeyCode::Down));
+        update(&mut app, key(KeyCode::Enter));
         assert_eq!(app.current_state, AppState::Browsing);
-        assert_eq!(app.settings.favorite_collections.len(), 3); // No change
-        assert!(app.add_collection_input.is_empty()); // Input cleared
+        assert_eq!(app.selected_item().map(|d| d.identifier.as_str()), Some("gd1972"));
+
+        // Esc while typing clears the filter and keeps the selection
+        update(&mut app, key(KeyCode::Char('f')));
+        update(&mut app, key(KeyCode::Esc));
+        assert_eq!(shown(&app).len(), 3);
+        assert_eq!(app.selected_item().map(|d| d.identifier.as_str()), Some("gd1972"));
     }
 
-    use crate::settings::DownloadMode; // Import for test
+    #[test]
+    fn test_marked_items_download_as_one_batch() {
+        use crate::archive_api::ArchiveDoc;
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = ["a", "b", "c"].iter().map(|id| ArchiveDoc { identifier: id.to_string(), ..Default::default() }).collect();
+        app.item_list_state.select(Some(0));
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+
+        // Space marks and moves on; marking again unmarks
+        update(&mut app, key(KeyCode::Char(' ')));
+        assert_eq!(app.selected_item().map(|d| d.identifier.as_str()), Some("b"));
+        update(&mut app, key(KeyCode::Down));
+        update(&mut app, key(KeyCode::Char(' ')));
+        update(&mut app, key(KeyCode::Up));
+        update(&mut app, key(KeyCode::Char(' ')));
+        update(&mut app, key(KeyCode::Char(' ')));
+        assert_eq!(app.marked_items.len(), 2);
+
+        let action = update(&mut app, key(KeyCode::Char('d')));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::Items(ref ids))) if ids == &["a", "c"]));
+        assert!(app.marked_items.is_empty(), "Queued items are unmarked");
+
+        // '*' marks everything shown, '-' clears; without marks 'd' takes the selected item
+        update(&mut app, key(KeyCode::Char('*')));
+        assert_eq!(app.marked_items.len(), 3);
+        update(&mut app, key(KeyCode::Char('-')));
+        assert!(app.marked_items.is_empty());
+        let action = update(&mut app, key(KeyCode::Char('d')));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(_)))));
+    }
 
     #[test]
-    fn test_update_settings_navigation_and_adjustment() {
+    fn test_items_pane_mediatype_and_format_filters() {
+        use crate::archive_api::ArchiveDoc;
         let mut app = setup_test_app();
-        app.current_state = AppState::SettingsView;
-        app.selected_setting_index = 0; // Start at Download Dir
-        app.settings_list_state.select(Some(0));
-        app.settings.download_mode = DownloadMode::Direct; // Start with Direct
-        app.settings.max_concurrent_downloads = Some(4);
-        app.settings.max_concurrent_collections = Some(1);
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        let doc = |id: &str, mediatype: &str, format: &str| ArchiveDoc {
+            identifier: id.to_string(),
+            mediatype: Some(mediatype.to_string()),
+            format: vec![format.to_string()],
+            ..Default::default()
+        };
+        app.items = vec![doc("show", "audio", "Flac"), doc("film", "movies", "MPEG4"), doc("tape", "audio", "VBR MP3")];
+
+        let shown = |app: &App| -> Vec<String> { app.visible_items().iter().map(|d| d.identifier.clone()).collect() };
+        let m = KeyEvent::new(KeyCode::Char('m'), KeyModifiers::NONE);
+        let format = KeyEvent::new(KeyCode::Char('F'), KeyModifiers::SHIFT);
+        update(&mut app, m);
+        assert_eq!(shown(&app), vec!["show", "tape"]);
+        update(&mut app, format);
+        assert_eq!(shown(&app), vec!["show"]);
+        assert_eq!(app.item_list_state.selected(), Some(0));
+        update(&mut app, format); // MPEG4: no audio item has it
+        assert!(shown(&app).is_empty());
+        assert_eq!(app.item_list_state.selected(), None);
+        update(&mut app, format);
+        update(&mut app, format);
+        assert_eq!(app.item_filter.format, None);
+        update(&mut app, m);
+        assert_eq!(shown(&app), vec!["film"]);
+        update(&mut app, m);
+        assert_eq!(shown(&app).len(), 3);
+    }
 
-        // Down to Download Mode
-        update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.selected_setting_index, 1);
-        assert_eq!(app.settings_list_state.selected(), Some(1));
+    #[test]
+    fn test_duplicate_groups_expand_and_pick_variant() {
+        use crate::archive_api::ArchiveDoc;
+        let mut app = setup_test_app();
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = [("album_flac", "Album (FLAC)"), ("other", "Other"), ("album_mp3", "Album [MP3]")]
+            .iter()
+            .map(|(id, title)| ArchiveDoc { identifier: id.to_string(), title: Some(title.to_string()), ..Default::default() })
+            .collect();
+        app.item_list_state.select(Some(2));
+
+        let shown = |app: &App| -> Vec<String> { app.visible_items().iter().map(|d| d.identifier.clone()).collect() };
+        update(&mut app, KeyEvent::new(KeyCode::Char('z'), KeyModifiers::NONE));
+        assert_eq!(shown(&app), vec!["album_flac", "other"]);
+        // The selected variant was folded into its group
+        assert_eq!(app.item_list_state.selected(), Some(0));
 
-        // Right cycles Download Mode to TorrentOnly
         update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
-        assert_eq!(app.settings.download_mode, DownloadMode::TorrentOnly);
-
-        // Left cycles Download Mode back to Direct
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        assert_eq!(app.settings.download_mode, DownloadMode::Direct);
-
-        // Down to File Concurrency
+        assert_eq!(shown(&app), vec!["album_flac", "album_mp3", "other"]);
         update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.selected_setting_index, 2);
-        assert_eq!(app.settings_list_state.selected(), Some(2));
-
-        // Right increases File Concurrency
-        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
-        assert_eq!(app.settings.max_concurrent_downloads, Some(5));
+        let action = update(&mut app, KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(matches!(action, Some(UpdateAction::StartDownload(DownloadAction::ItemAllFiles(ref id))) if id == "album_mp3"));
 
-        // Left decreases File Concurrency
         update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        assert_eq!(app.settings.max_concurrent_downloads, Some(4));
+        assert_eq!(shown(&app), vec!["album_flac", "other"]);
+        assert_eq!(app.item_list_state.selected(), Some(0));
+        update(&mut app, KeyEvent::new(KeyCode::Char('z'), KeyModifiers::NONE));
+        assert_eq!(shown(&app).len(), 3);
+    }
 
-        // Left again (min 1)
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        assert_eq!(app.settings.max_concurrent_downloads, Some(1));
+    #[test]
+    fn test_item_view_tabs_browse_reviews_and_open_related_items() {
+        use crate::archive_api::{ItemDetails, Review};
+        let mut app = setup_test_app();
+        app.open_item("item1".to_string());
+        app.pending_action = None;
+        let review = |title: &str| Review { title: Some(title.to_string()), body: "Text".to_string(), ..Default::default() };
+        app.current_item_details = Some(ItemDetails {
+            identifier: "item1".to_string(),
+            reviews: vec![review("First"), review("Second")],
+            related_identifiers: vec!["item2".to_string(), "item3".to_string()],
+            ..Default::default()
+        });
+        app.is_loading_details = false;
 
+        update(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.item_tab, ItemTab::Reviews);
+        update(&mut app, KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(app.review_index, 1, "Wraps to the last review");
 
-        // Down to Collection Concurrency
+        update(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.item_tab, ItemTab::Related);
         update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.selected_setting_index, 3);
-        assert_eq!(app.settings_list_state.selected(), Some(3));
-
-         // Right increases Collection Concurrency
-        update(&mut app, KeyEvent::new(KeyCode::Right, KeyModifiers::NONE));
-        assert_eq!(app.settings.max_concurrent_collections, Some(2));
-
-        // Left decreases Collection Concurrency
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        assert_eq!(app.settings.max_concurrent_collections, Some(1));
-
-        // Left again (min 1)
-        update(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::NONE));
-        assert_eq!(app.settings.max_concurrent_collections, Some(1));
-
-        // Down wraps to Download Dir
         update(&mut app, KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
-        assert_eq!(app.selected_setting_index, 0);
-
-        // Enter on Download Dir enters EditingSetting state
+        assert_eq!(app.selected_related().map(String::as_str), Some("item3"));
         let action = update(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
-        assert!(action.is_none());
-        assert_eq!(app.current_state, AppState::EditingSetting);
+        assert!(matches!(action, Some(UpdateAction::FetchItemDetails)));
+        assert_eq!(app.viewing_item_id.as_deref(), Some("item3"));
+        assert_eq!(app.item_tab, ItemTab::Metadata);
+        assert!(app.current_item_details.is_none() && app.is_loading_details);
+    }
+
+    #[test]
+    fn test_open_requires_downloaded_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = setup_test_app();
+        app.settings.download_directory = Some(dir.path().to_string_lossy().into_owned());
+        app.current_state = AppState::Browsing;
+        app.active_pane = ActivePane::Items;
+        app.current_collection_name = Some("coll1".to_string());
+        app.items = vec![crate::archive_api::ArchiveDoc { identifier: "item1".to_string(), ..Default::default() }];
+        app.item_list_state.select(Some(0));
+
+        let o = KeyEvent::new(KeyCode::Char('o'), KeyModifiers::NONE);
+        assert!(update(&mut app, o).is_none());
+        assert_eq!(app.error_message.as_deref(), Some("'item1' has not been downloaded yet."));
+
+        let item_dir = dir.path().join("coll1").join("item1");
+        std::fs::create_dir_all(&item_dir).unwrap();
+        let action = update(&mut app, o);
+        assert!(matches!(action, Some(UpdateAction::OpenPath(ref p)) if *p == item_dir));
     }
 
     // TODO: Add tests for download actions ('d', 'b') in both panes
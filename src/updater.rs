@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+
+/// GitHub repository releases are published to.
+pub const REPO_OWNER: &str = "riffcc";
+pub const REPO_NAME: &str = "archiver";
+/// Name of the executable inside release archives.
+pub const BIN_NAME: &str = "archiver";
+/// Version of the running binary.
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A published release newer than the running binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseInfo {
+    /// Version without the leading `v` (e.g. `0.2.0`).
+    pub version: String,
+    /// Release notes (markdown body of the GitHub release).
+    pub notes: String,
+    /// Web page of the release.
+    pub url: String,
+}
+
+/// Subset of the GitHub "latest release" response we use.
+#[derive(Deserialize, Debug)]
+struct GithubRelease {
+    tag_name: String,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    html_url: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+fn latest_release_url() -> String {
+    format!("https://api.github.com/repos/{}/{}/releases/latest", REPO_OWNER, REPO_NAME)
+}
+
+/// Parses a GitHub release response, returning it if it is newer than `current_version`.
+fn newer_release(json: &str, current_version: &str) -> Result<Option<ReleaseInfo>> {
+    let release: GithubRelease = serde_json::from_str(json).context("Failed to parse GitHub release response")?;
+    if release.draft || release.prerelease {
+        return Ok(None);
+    }
+    let version = release.tag_name.trim_start_matches('v').to_string();
+    let is_newer = self_update::version::bump_is_greater(current_version, &version)
+        .map_err(|e| anyhow!("Unrecognised release version '{}': {}", release.tag_name, e))?;
+    if !is_newer {
+        return Ok(None);
+    }
+    Ok(Some(ReleaseInfo {
+        version,
+        notes: release.body.unwrap_or_default().trim().to_string(),
+        url: release.html_url,
+    }))
+}
+
+/// Asks GitHub for the latest release. Returns `Ok(None)` when already up to date.
+pub async fn check_for_update(client: &Client) -> Result<Option<ReleaseInfo>> {
+    info!("Checking for updates (current version {})", CURRENT_VERSION);
+    let response = client
+        .get(latest_release_url())
+        .header(reqwest::header::USER_AGENT, format!("{}/{}", BIN_NAME, CURRENT_VERSION)) // Required by the GitHub API
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .context("Failed to reach GitHub releases")?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        warn!("No published releases found for {}/{}", REPO_OWNER, REPO_NAME);
+        return Ok(None);
+    }
+    let response = response.error_for_status().context("GitHub releases request failed")?;
+    let body = response.text().await.context("Failed to read GitHub release response")?;
+    let release = newer_release(&body, CURRENT_VERSION)?;
+    match &release {
+        Some(r) => info!("Update available: {} -> {}", CURRENT_VERSION, r.version),
+        None => info!("Already running the latest release"),
+    }
+    Ok(release)
+}
+
+/// Downloads `release` for this platform and replaces the running executable.
+/// Returns the installed version. The new binary is used from the next start.
+pub async fn install_update(release: &ReleaseInfo) -> Result<String> {
+    let tag = format!("v{}", release.version);
+    tokio::task::spawn_blocking(move || -> Result<String> {
+        let status = self_update::backends::github::Update::configure()
+            .repo_owner(REPO_OWNER)
+            .repo_name(REPO_NAME)
+            .bin_name(BIN_NAME)
+            .current_version(CURRENT_VERSION)
+            .target_version_tag(&tag)
+            // The TUI owns the terminal: no prompts or progress bars
+            .show_output(false)
+            .show_download_progress(false)
+            .no_confirm(true)
+            .build()
+            .context("Failed to configure updater")?
+            .update()
+            .context("Failed to download or install the update")?;
+        Ok(status.version().to_string())
+    })
+    .await
+    .context("Updater task panicked")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release_json(tag: &str, prerelease: bool) -> String {
+        serde_json::json!({
+            "tag_name": tag,
+            "body": "## Changes\n- Faster downloads\n",
+            "html_url": format!("https://github.com/riffcc/archiver/releases/tag/{}", tag),
+            "draft": false,
+            "prerelease": prerelease,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_newer_release_is_reported_with_notes() {
+        let release = newer_release(&release_json("v0.2.0", false), "0.1.0").unwrap().unwrap();
+        assert_eq!(release.version, "0.2.0");
+        assert_eq!(release.notes, "## Changes\n- Faster downloads");
+        assert!(release.url.ends_with("/v0.2.0"));
+    }
+
+    #[test]
+    fn test_same_or_older_and_prereleases_are_ignored() {
+        assert_eq!(newer_release(&release_json("v0.1.0", false), "0.1.0").unwrap(), None);
+        assert_eq!(newer_release(&release_json("0.0.9", false), "0.1.0").unwrap(), None);
+        assert_eq!(newer_release(&release_json("v9.0.0", true), "0.1.0").unwrap(), None);
+    }
+
+    #[test]
+    fn test_garbage_version_is_an_error() {
+        assert!(newer_release(&release_json("nightly", false), "0.1.0").is_err());
+    }
+}
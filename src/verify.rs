@@ -0,0 +1,416 @@
+use crate::archive_api::ItemDetails;
+use crate::paths::{self, PathPolicy};
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Suffix of the checksum manifest written into each item directory
+/// (`<identifier>_checksums.tsv`).
+pub const MANIFEST_SUFFIX: &str = "_checksums.tsv";
+/// Column header line of the manifest.
+const MANIFEST_COLUMNS: &str = "# name\tsize\tmd5\tsha256";
+
+/// Result of checking one listed file of an item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileCheck {
+    /// Checksum (or, without one, size) matches archive.org.
+    Ok,
+    /// Not on disk: never downloaded, or left out by the preset or filter.
+    Missing,
+    /// On disk but different from archive.org's copy.
+    Mismatch { expected: String, actual: String },
+}
+
+/// Checks every file archive.org lists for `details` against the copy in `item_dir`.
+/// Access-restricted files are skipped. Runs on a blocking thread (files are hashed).
+pub async fn verify_item(item_dir: PathBuf, details: ItemDetails, policy: PathPolicy) -> Result<Vec<(String, FileCheck)>> {
+    tokio::task::spawn_blocking(move || {
+        let base = item_dir.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+        let item_name = item_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut checks = Vec::new();
+        for file in details.files.iter().filter(|f| !f.private) {
+            let path = paths::file_path(&base, None, &item_name, &file.name, &policy);
+            checks.push((file.name.clone(), check_file(&path, file.md5.as_deref(), file.size.as_deref())?));
+        }
+        Ok(checks)
+    })
+    .await
+    .context("Verification task failed")?
+}
+
+fn check_file(path: &Path, md5: Option<&str>, size: Option<&str>) -> Result<FileCheck> {
+    let Ok(metadata) = path.metadata() else {
+        return Ok(FileCheck::Missing);
+    };
+    if let Some(expected) = md5 {
+        let actual = md5_file(path)?;
+        return Ok(if actual.eq_ignore_ascii_case(expected) {
+            FileCheck::Ok
+        } else {
+            FileCheck::Mismatch { expected: format!("md5 {}", expected), actual: format!("md5 {}", actual) }
+        });
+    }
+    // Files without a checksum (e.g. some metadata files) are compared by size
+    match size.and_then(|s| s.parse::<u64>().ok()) {
+        Some(expected) if expected != metadata.len() => {
+            Ok(FileCheck::Mismatch { expected: format!("{} bytes", expected), actual: format!("{} bytes", metadata.len()) })
+        }
+        _ => Ok(FileCheck::Ok),
+    }
+}
+
+/// Hex MD5 of the file at `path`, read in chunks.
+pub fn md5_file(path: &Path) -> Result<String> {
+    let mut hasher = Md5::new();
+    read_chunks(path, |chunk| hasher.update(chunk))?;
+    Ok(hex(&hasher.finish()))
+}
+
+/// Hex SHA-256 of the file at `path`, read in chunks.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    read_chunks(path, |chunk| hasher.update(chunk))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn read_chunks(path: &Path, mut consume: impl FnMut(&[u8])) -> Result<()> {
+    let mut file = File::open(path).context(format!("Failed to open '{}'", path.display()))?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).context(format!("Failed to read '{}'", path.display()))?;
+        if read == 0 {
+            return Ok(());
+        }
+        consume(&buffer[..read]);
+    }
+}
+
+/// One file listed in an item's checksum manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path relative to the item directory, `/`-separated.
+    pub name: String,
+    pub size: u64,
+    /// MD5 archive.org lists for the file, if any.
+    pub md5: Option<String>,
+    /// SHA-256 computed locally after the download.
+    pub sha256: String,
+}
+
+/// Name of the checksum manifest of `identifier`.
+pub fn manifest_file_name(identifier: &str) -> String {
+    format!("{}{}", identifier, MANIFEST_SUFFIX)
+}
+
+/// The checksum manifest in `item_dir`, if one was written.
+pub fn find_manifest(item_dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(item_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| path.is_file() && path.file_name().is_some_and(|n| n.to_string_lossy().ends_with(MANIFEST_SUFFIX)))
+}
+
+/// Tab-separated manifest: a comment naming the item, the column header, then one line
+/// per file. Tabs, newlines and backslashes in names are escaped.
+pub fn render_manifest(identifier: &str, written: &str, entries: &[ManifestEntry]) -> String {
+    let mut out = format!("# Checksums of archive.org item {}, written {} by archiver\n{}\n", identifier, written, MANIFEST_COLUMNS);
+    for entry in entries {
+        let md5 = entry.md5.as_deref().unwrap_or("-");
+        out.push_str(&format!("{}\t{}\t{}\t{}\n", escape_name(&entry.name), entry.size, md5, entry.sha256));
+    }
+    out
+}
+
+/// Parses a manifest written by [`render_manifest`]; comment lines are skipped.
+pub fn parse_manifest(text: &str) -> Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.starts_with('#') && !line.trim().is_empty()) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [name, size, md5, sha256] = fields[..] else { bail!("Manifest line {} doesn't have 4 columns", number + 1) };
+        entries.push(ManifestEntry {
+            name: unescape_name(name),
+            size: size.parse().context(format!("Manifest line {} has an invalid size", number + 1))?,
+            md5: (md5 != "-").then(|| md5.to_string()),
+            sha256: sha256.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+fn escape_name(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('t') => out.push('\t'),
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            (c, false) => out.push(c),
+        }
+    }
+    out
+}
+
+/// Hashes `files` (path on disk, name in the item directory, archive.org's MD5) and writes
+/// the manifest of `identifier` into `item_dir`. Files not on disk are left out. Runs on a
+/// blocking thread; returns the manifest's path.
+pub async fn write_manifest(item_dir: PathBuf, identifier: String, files: Vec<(PathBuf, String, Option<String>)>) -> Result<PathBuf> {
+    tokio::task::spawn_blocking(move || {
+        let mut entries = Vec::new();
+        for (path, name, md5) in files {
+            let Ok(metadata) = path.metadata() else { continue };
+            entries.push(ManifestEntry { name, size: metadata.len(), md5, sha256: sha256_file(&path)? });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let path = item_dir.join(manifest_file_name(&identifier));
+        let contents = render_manifest(&identifier, &chrono::Local::now().to_rfc3339(), &entries);
+        crate::fs_util::atomic_write(&path, contents)?;
+        Ok(path)
+    })
+    .await
+    .context("Checksum manifest task failed")?
+}
+
+/// Checks the files of `item_dir` against its checksum manifest (size, then SHA-256),
+/// without contacting archive.org. `None` if the directory has no manifest.
+pub async fn verify_manifest(item_dir: PathBuf) -> Result<Option<Vec<(String, FileCheck)>>> {
+    tokio::task::spawn_blocking(move || {
+        let Some(manifest) = find_manifest(&item_dir) else { return Ok(None) };
+        let text = fs::read_to_string(&manifest).context(format!("Failed to read '{}'", manifest.display()))?;
+        let mut checks = Vec::new();
+        for entry in parse_manifest(&text).context(format!("Invalid checksum manifest '{}'", manifest.display()))? {
+            let path = entry.name.split('/').fold(item_dir.clone(), |path, segment| path.join(segment));
+            let check = match path.metadata() {
+                Err(_) => FileCheck::Missing,
+                Ok(metadata) if metadata.len() != entry.size => {
+                    FileCheck::Mismatch { expected: format!("{} bytes", entry.size), actual: format!("{} bytes", metadata.len()) }
+                }
+                Ok(_) => match sha256_file(&path)? {
+                    actual if actual.eq_ignore_ascii_case(&entry.sha256) => FileCheck::Ok,
+                    actual => FileCheck::Mismatch { expected: format!("sha256 {}", entry.sha256), actual: format!("sha256 {}", actual) },
+                },
+            };
+            checks.push((entry.name, check));
+        }
+        Ok(Some(checks))
+    })
+    .await
+    .context("Verification task failed")?
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hex MD5 of `data`.
+pub fn md5_hex(data: &[u8]) -> String {
+    let mut hasher = Md5::new();
+    hasher.update(data);
+    hex(&hasher.finish())
+}
+
+/// Streaming MD5 (RFC 1321), the checksum archive.org lists for every file.
+pub struct Md5 {
+    state: [u32; 4],
+    /// Bytes of an incomplete block.
+    pending: Vec<u8>,
+    length: u64,
+}
+
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4,
+    11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Round constants: the integer part of `abs(sin(i + 1)) * 2^32`.
+const K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Md5 {
+    pub fn new() -> Self {
+        Self { state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476], pending: Vec::with_capacity(64), length: 0 }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if !self.pending.is_empty() {
+            let take = (64 - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            let block: [u8; 64] = self.pending[..].try_into().unwrap();
+            self.compress(&block);
+            self.pending.clear();
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block.try_into().unwrap());
+        }
+        self.pending.extend_from_slice(blocks.remainder());
+    }
+
+    /// Hex digest of everything passed to [`Md5::update`].
+    pub fn finish_hex(self) -> String {
+        hex(&self.finish())
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80u8];
+        padding.resize((55usize.wrapping_sub(self.pending.len()) % 64) + 1, 0);
+        padding.extend_from_slice(&bit_length.to_le_bytes());
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+        let mut digest = [0u8; 16];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let words: Vec<u32> = block.chunks_exact(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for (i, shift) in SHIFTS.iter().enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(K[i]).wrapping_add(words[g]).rotate_left(*shift);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive_api::FileDetails;
+
+    fn md5_in_pieces(data: &[u8]) -> String {
+        let mut hasher = Md5::new();
+        // Odd split sizes exercise the partial-block path
+        for chunk in data.chunks(7) {
+            hasher.update(chunk);
+        }
+        hex(&hasher.finish())
+    }
+
+    #[test]
+    fn test_md5_known_digests() {
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5_hex(b"The quick brown fox jumps over the lazy dog"), "9e107d9d372bb6826bd81d3542a419d6");
+        let long = b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        assert_eq!(md5_hex(long), "57edf4a22be3c955ac49da2e2107b67a");
+        assert_eq!(md5_in_pieces(long), md5_hex(long));
+    }
+
+    #[test]
+    fn test_manifest_round_trips_odd_names() {
+        let entries = vec![
+            ManifestEntry { name: "disc 1/01\ttrack.flac".to_string(), size: 3, md5: Some("900150983cd24fb0d6963f7d28e17f72".to_string()), sha256: "ab".to_string() },
+            ManifestEntry { name: "back\\slash.txt".to_string(), size: 0, md5: None, sha256: "cd".to_string() },
+        ];
+        let text = render_manifest("item", "2026-10-15T12:00:00+00:00", &entries);
+        assert!(text.starts_with("# Checksums of archive.org item item"));
+        assert_eq!(parse_manifest(&text).unwrap(), entries);
+        assert!(parse_manifest("a\t1\t-").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_manifest_detects_changed_and_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let item_dir = dir.path().join("item");
+        std::fs::create_dir_all(item_dir.join("sub")).unwrap();
+        std::fs::write(item_dir.join("a.txt"), b"abc").unwrap();
+        std::fs::write(item_dir.join("sub/b.txt"), b"def").unwrap();
+        std::fs::write(item_dir.join("c.txt"), b"ghi").unwrap();
+        assert_eq!(verify_manifest(item_dir.clone()).await.unwrap(), None, "No manifest yet");
+
+        let files = ["a.txt", "sub/b.txt", "c.txt", "never.txt"]
+            .iter()
+            .map(|name| (item_dir.join(name), name.to_string(), None))
+            .collect();
+        let path = write_manifest(item_dir.clone(), "item".to_string(), files).await.unwrap();
+        assert_eq!(path, item_dir.join("item_checksums.tsv"));
+        let entries = parse_manifest(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(entries.len(), 3, "Files not on disk are left out");
+        assert_eq!(entries[0].sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        std::fs::write(item_dir.join("a.txt"), b"abd").unwrap();
+        std::fs::remove_file(item_dir.join("c.txt")).unwrap();
+        let checks = verify_manifest(item_dir).await.unwrap().unwrap();
+        assert!(matches!(&checks[0], (name, FileCheck::Mismatch { .. }) if name == "a.txt"));
+        assert_eq!(checks[1], ("c.txt".to_string(), FileCheck::Missing));
+        assert_eq!(checks[2], ("sub/b.txt".to_string(), FileCheck::Ok));
+    }
+
+    #[tokio::test]
+    async fn test_verify_item_reports_missing_and_mismatched_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let item_dir = dir.path().join("item");
+        std::fs::create_dir_all(&item_dir).unwrap();
+        std::fs::write(item_dir.join("good.txt"), b"abc").unwrap();
+        std::fs::write(item_dir.join("bad.txt"), b"abd").unwrap();
+        std::fs::write(item_dir.join("meta.xml"), b"<xml/>").unwrap();
+        let file = |name: &str, md5: Option<&str>, size: Option<&str>| FileDetails {
+            name: name.to_string(),
+            md5: md5.map(str::to_string),
+            size: size.map(str::to_string),
+            ..Default::default()
+        };
+        let details = ItemDetails {
+            identifier: "item".to_string(),
+            files: vec![
+                file("good.txt", Some("900150983cd24fb0d6963f7d28e17f72"), None),
+                file("bad.txt", Some("900150983cd24fb0d6963f7d28e17f72"), None),
+                file("meta.xml", None, Some("6")),
+                file("absent.flac", Some("00"), None),
+            ],
+            ..Default::default()
+        };
+
+        let checks = verify_item(item_dir, details, PathPolicy::default()).await.unwrap();
+        assert_eq!(checks[0].1, FileCheck::Ok);
+        assert!(matches!(checks[1].1, FileCheck::Mismatch { .. }));
+        assert_eq!(checks[2].1, FileCheck::Ok);
+        assert_eq!(checks[3].1, FileCheck::Missing);
+    }
+}
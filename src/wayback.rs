@@ -0,0 +1,360 @@
+use crate::app::AppRateLimiter;
+use crate::archive_api;
+use crate::fs_util;
+use crate::paths::{self, PathPolicy};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info};
+use reqwest::{Client, Url};
+use std::{fs, path::PathBuf};
+use tokio::sync::mpsc;
+
+/// Host of the Wayback Machine (CDX API and snapshots).
+const WAYBACK_BASE_URL: &str = "https://web.archive.org";
+/// Fields requested from the CDX API, in this order.
+const CDX_FIELDS: &str = "timestamp,original,mimetype,statuscode,digest,length";
+/// Most recent captures listed per query.
+const MAX_SNAPSHOTS: usize = 1000;
+/// Directory below the download directory that snapshots are saved in.
+pub const WAYBACK_DIR: &str = "wayback";
+
+/// The Wayback Machine host, or `<base>/wayback` when the archive.org base URL is
+/// overridden (the mock server).
+pub fn endpoint() -> String {
+    let base = archive_api::base_url();
+    if base == archive_api::DEFAULT_BASE_URL {
+        WAYBACK_BASE_URL.to_string()
+    } else {
+        format!("{}/wayback", base)
+    }
+}
+
+/// Progress of a Wayback Machine lookup or download started from the TUI.
+#[derive(Debug, Clone)]
+pub enum WaybackProgress {
+    /// The captures matching a query, or why listing them failed.
+    Listed(SnapshotQuery, Result<Vec<Snapshot>, String>),
+    /// A capture was saved at this path.
+    Saved(PathBuf),
+    /// A capture could not be saved.
+    Failed(String),
+    /// A download ended.
+    Finished { saved: usize, failed: usize },
+}
+
+/// Captures of `url` to list, optionally limited to a date range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotQuery {
+    pub url: String,
+    /// Earliest capture time as CDX digits (`2005`, `200506`, `20050601120000`).
+    pub from: Option<String>,
+    /// Latest capture time, like `from`.
+    pub to: Option<String>,
+}
+
+impl SnapshotQuery {
+    /// Builds a query, normalising `from`/`to` (see [`parse_date`]).
+    pub fn new(url: &str, from: Option<&str>, to: Option<&str>) -> Result<Self> {
+        let url = url.trim();
+        if url.is_empty() {
+            bail!("Type a URL to look up in the Wayback Machine");
+        }
+        Ok(SnapshotQuery { url: url.to_string(), from: from.map(parse_date).transpose()?, to: to.map(parse_date).transpose()? })
+    }
+
+    /// Parses `URL [FROM [TO]]` as typed in the TUI prompt.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parts = input.split_whitespace();
+        let url = parts.next().unwrap_or("");
+        let query = Self::new(url, parts.next(), parts.next())?;
+        if let Some(extra) = parts.next() {
+            bail!("Unexpected '{}': type a URL, optionally followed by a start and an end date", extra);
+        }
+        Ok(query)
+    }
+
+    /// Whether a date range was given (downloads then save every capture in it).
+    pub fn has_range(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+}
+
+/// Turns `2005`, `2005-06`, `2005-06-01` or `2005-06-01 12:00` into the digits the CDX
+/// API expects (`2005`, `200506`, ...).
+pub fn parse_date(date: &str) -> Result<String> {
+    let digits: String = date.chars().filter(|c| !matches!(c, '-' | ':' | 'T' | '/' | ' ')).collect();
+    if digits.len() < 4 || digits.len() > 14 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        bail!("'{}' is not a date (use YYYY, YYYY-MM, YYYY-MM-DD or a 14-digit Wayback timestamp)", date);
+    }
+    Ok(digits)
+}
+
+/// One capture of a URL in the Wayback Machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Capture time, `YYYYMMDDhhmmss`.
+    pub timestamp: String,
+    /// The URL as captured.
+    pub original: String,
+    pub mimetype: String,
+    /// HTTP status of the capture (`200`, `301`, `404`, or `-` for some records).
+    pub status: String,
+    /// Content hash; identical captures share it.
+    pub digest: String,
+    /// Compressed size of the capture record in bytes.
+    pub length: Option<u64>,
+}
+
+impl Snapshot {
+    /// Capture time as `YYYY-MM-DD hh:mm:ss`.
+    pub fn date(&self) -> String {
+        let t = &self.timestamp;
+        match (t.get(0..4), t.get(4..6), t.get(6..8), t.get(8..10), t.get(10..12), t.get(12..14)) {
+            (Some(y), Some(mo), Some(d), Some(h), Some(mi), Some(s)) => format!("{}-{}-{} {}:{}:{}", y, mo, d, h, mi, s),
+            _ => t.clone(),
+        }
+    }
+
+    /// Whether the capture holds the page itself rather than a redirect or error.
+    pub fn is_ok(&self) -> bool {
+        self.status == "200"
+    }
+
+    /// URL of the capture as originally served, without the Wayback Machine's toolbar
+    /// and link rewriting (`id_`).
+    pub fn raw_url(&self, base: &str) -> String {
+        format!("{}/web/{}id_/{}", base, self.timestamp, self.original)
+    }
+
+    /// Where the capture is saved: `<dir>/wayback/<host>/<timestamp>/<path>`, with
+    /// `index.html` for directory URLs.
+    pub fn local_path(&self, base_dir: &str, policy: &PathPolicy) -> PathBuf {
+        let with_scheme = if self.original.contains("://") { self.original.clone() } else { format!("http://{}", self.original) };
+        let (host, mut path, query) = match Url::parse(&with_scheme) {
+            Ok(url) => (url.host_str().unwrap_or("unknown-host").to_string(), url.path().to_string(), url.query().map(str::to_string)),
+            Err(_) => ("unknown-host".to_string(), "/".to_string(), None),
+        };
+        if path.ends_with('/') {
+            path.push_str("index.html");
+        }
+        if let Some(query) = query {
+            path.push('?');
+            path.push_str(&query.replace('/', "_"));
+        }
+        paths::file_path(base_dir, Some(WAYBACK_DIR), &host, &format!("{}{}", self.timestamp, path), policy)
+    }
+}
+
+/// Lists the captures matching `query`, oldest first (at most the [`MAX_SNAPSHOTS`] most
+/// recent ones).
+pub async fn list_snapshots(client: &Client, base: &str, query: &SnapshotQuery, rate_limiter: &AppRateLimiter) -> Result<Vec<Snapshot>> {
+    let limit = format!("-{}", MAX_SNAPSHOTS); // Negative limits return the latest captures
+    let mut params = vec![("url", query.url.as_str()), ("output", "json"), ("fl", CDX_FIELDS), ("limit", limit.as_str())];
+    if let Some(from) = &query.from {
+        params.push(("from", from));
+    }
+    if let Some(to) = &query.to {
+        params.push(("to", to));
+    }
+    rate_limiter.until_ready().await;
+    let response = client
+        .get(format!("{}/cdx/search/cdx", base))
+        .query(&params)
+        .send()
+        .await
+        .context("Failed to reach the Wayback Machine")?;
+    if !response.status().is_success() {
+        bail!("Wayback Machine lookup of '{}' failed with status {}", query.url, response.status());
+    }
+    let body = response.text().await.context("Failed to read the Wayback Machine's answer")?;
+    let snapshots = parse_cdx(&body).with_context(|| format!("Unexpected Wayback Machine answer for '{}'", query.url))?;
+    info!("Found {} Wayback Machine captures of '{}'", snapshots.len(), query.url);
+    Ok(snapshots)
+}
+
+/// Parses a CDX `output=json` answer: a header row naming the fields, then one row per
+/// capture. No captures is an empty body.
+fn parse_cdx(body: &str) -> Result<Vec<Snapshot>> {
+    if body.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows: Vec<Vec<String>> = serde_json::from_str(body)?;
+    let Some((header, rows)) = rows.split_first() else { return Ok(Vec::new()) };
+    let column = |name: &str| header.iter().position(|field| field == name).ok_or_else(|| anyhow!("No '{}' column", name));
+    let (timestamp, original) = (column("timestamp")?, column("original")?);
+    let (mimetype, status, digest, length) = (column("mimetype").ok(), column("statuscode").ok(), column("digest").ok(), column("length").ok());
+    let field = |row: &[String], index: Option<usize>| index.and_then(|i| row.get(i)).cloned().unwrap_or_default();
+    Ok(rows
+        .iter()
+        .map(|row| Snapshot {
+            timestamp: field(row, Some(timestamp)),
+            original: field(row, Some(original)),
+            mimetype: field(row, mimetype),
+            status: field(row, status),
+            digest: field(row, digest),
+            length: field(row, length).parse().ok(),
+        })
+        .collect())
+}
+
+/// The captures a download saves: every capture with a 200 status in a date range,
+/// skipping repeats of identical content, otherwise just the latest one.
+pub fn to_download(snapshots: &[Snapshot], all: bool) -> Vec<Snapshot> {
+    let mut ok = snapshots.iter().filter(|s| s.is_ok());
+    if !all {
+        return ok.next_back().cloned().into_iter().collect();
+    }
+    let mut seen = std::collections::HashSet::new();
+    ok.filter(|s| s.digest.is_empty() || seen.insert(s.digest.clone())).cloned().collect()
+}
+
+/// Saves `snapshot` below `base_dir`, returning where. Captures already saved are kept.
+pub async fn download_snapshot(
+    client: &Client,
+    base: &str,
+    snapshot: &Snapshot,
+    base_dir: &str,
+    policy: &PathPolicy,
+    rate_limiter: &AppRateLimiter,
+) -> Result<PathBuf> {
+    let path = snapshot.local_path(base_dir, policy);
+    if path.exists() {
+        debug!("Snapshot already saved at {}", path.display());
+        return Ok(path);
+    }
+    rate_limiter.until_ready().await;
+    let url = snapshot.raw_url(base);
+    let response = client.get(&url).send().await.with_context(|| format!("Failed to fetch {}", url))?;
+    if !response.status().is_success() {
+        bail!("Fetching the {} capture of '{}' failed with status {}", snapshot.date(), snapshot.original, response.status());
+    }
+    let bytes = response.bytes().await.with_context(|| format!("Failed to read {}", url))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create '{}'", parent.display()))?;
+    }
+    fs_util::atomic_write(&path, &bytes)?;
+    info!("Saved the {} capture of '{}' to {}", snapshot.date(), snapshot.original, path.display());
+    Ok(path)
+}
+
+/// Saves `snapshots` one after the other, reporting each on `progress_tx`.
+pub async fn download_all(
+    client: &Client,
+    base: &str,
+    snapshots: &[Snapshot],
+    base_dir: &str,
+    policy: &PathPolicy,
+    rate_limiter: &AppRateLimiter,
+    progress_tx: &mpsc::Sender<WaybackProgress>,
+) {
+    let (mut saved, mut failed) = (0, 0);
+    for snapshot in snapshots {
+        let progress = match download_snapshot(client, base, snapshot, base_dir, policy, rate_limiter).await {
+            Ok(path) => {
+                saved += 1;
+                WaybackProgress::Saved(path)
+            }
+            Err(e) => {
+                log::error!("{:#}", e);
+                failed += 1;
+                WaybackProgress::Failed(format!("{:#}", e))
+            }
+        };
+        let _ = progress_tx.send(progress).await;
+    }
+    let _ = progress_tx.send(WaybackProgress::Finished { saved, failed }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: &str, status: &str, digest: &str) -> Snapshot {
+        Snapshot {
+            timestamp: timestamp.to_string(),
+            original: "http://example.com/".to_string(),
+            mimetype: "text/html".to_string(),
+            status: status.to_string(),
+            digest: digest.to_string(),
+            length: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_cdx_rows() {
+        let body = r#"[["timestamp","original","mimetype","statuscode","digest","length"],
+            ["20010911120000","http://example.com/","text/html","200","AAA","1234"],
+            ["20020101000000","http://example.com/","warc/revisit","-","AAA","-"]]"#;
+        let snapshots = parse_cdx(body).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].date(), "2001-09-11 12:00:00");
+        assert_eq!(snapshots[0].length, Some(1234));
+        assert_eq!(snapshots[1].length, None);
+        assert_eq!(parse_cdx("").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_query_parsing_and_dates() {
+        let query = SnapshotQuery::parse("example.com 2005-06 2010").unwrap();
+        assert_eq!((query.from.as_deref(), query.to.as_deref()), (Some("200506"), Some("2010")));
+        assert!(query.has_range());
+        assert!(!SnapshotQuery::parse("example.com").unwrap().has_range());
+        assert!(SnapshotQuery::parse("example.com june").is_err());
+        assert!(SnapshotQuery::parse("").is_err());
+    }
+
+    #[test]
+    fn test_downloads_skip_errors_and_repeats() {
+        let snapshots = [snapshot("2001", "200", "A"), snapshot("2002", "200", "A"), snapshot("2003", "404", "B"), snapshot("2004", "200", "C")];
+        let timestamps = |list: Vec<Snapshot>| list.into_iter().map(|s| s.timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps(to_download(&snapshots, false)), ["2004"], "Latest good capture");
+        assert_eq!(timestamps(to_download(&snapshots, true)), ["2001", "2004"]);
+    }
+
+    #[test]
+    fn test_local_path_per_host_and_capture() {
+        let policy = PathPolicy::default();
+        let mut capture = snapshot("20010911120000", "200", "A");
+        assert_eq!(
+            capture.local_path("/dl", &policy),
+            PathBuf::from("/dl/wayback/example.com/20010911120000/index.html")
+        );
+        capture.original = "example.com/docs/a.pdf?v=1".to_string();
+        assert_eq!(
+            capture.local_path("/dl", &policy),
+            PathBuf::from("/dl/wayback/example.com/20010911120000/docs/a.pdf?v=1")
+        );
+        assert_eq!(capture.raw_url("https://web.archive.org"), "https://web.archive.org/web/20010911120000id_/example.com/docs/a.pdf?v=1");
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_list_and_download_from_mock() {
+        use crate::mock_server::{self, MOCK_WAYBACK_URL};
+        use crate::rate_limit::SharedRateLimiter;
+        use governor::Quota;
+        use std::{num::NonZeroU32, sync::Arc};
+        archive_api::set_base_url(mock_server::shared_base_url());
+        let client = Client::new();
+        let limiter: AppRateLimiter = Arc::new(SharedRateLimiter::new(Quota::per_minute(NonZeroU32::new(600).unwrap())));
+        let base = endpoint();
+
+        let all = list_snapshots(&client, &base, &SnapshotQuery::new("example.com", None, None).unwrap(), &limiter).await.unwrap();
+        assert_eq!(all.len(), 3);
+        let ranged = SnapshotQuery::new("example.com", Some("2006"), Some("2012")).unwrap();
+        assert_eq!(list_snapshots(&client, &base, &ranged, &limiter).await.unwrap().len(), 2);
+        let unknown = SnapshotQuery::new("nothing.invalid", None, None).unwrap();
+        assert!(list_snapshots(&client, &base, &unknown, &limiter).await.unwrap().is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let base_dir = dir.path().to_str().unwrap();
+        let wanted = to_download(&all, true);
+        assert_eq!(wanted.len(), 2, "The 404 capture is skipped");
+        for snapshot in &wanted {
+            let path = download_snapshot(&client, &base, snapshot, base_dir, &PathPolicy::default(), &limiter).await.unwrap();
+            assert!(fs::read_to_string(&path).unwrap().contains(&snapshot.timestamp));
+            assert!(path.ends_with(format!("{}/index.html", snapshot.timestamp)));
+        }
+        let missing = Snapshot { timestamp: "20080615120000".to_string(), original: MOCK_WAYBACK_URL.to_string(), ..all[0].clone() };
+        assert!(download_snapshot(&client, &base, &missing, base_dir, &PathPolicy::default(), &limiter).await.is_err());
+    }
+}